@@ -6,6 +6,7 @@ use cubeclient::models::{V1Error, V1LoadRequestQuery, V1LoadResponse, V1MetaResp
 use cubesql::{
     di_service,
     sql::AuthContext,
+    telemetry::tracing::SpanContext,
     transport::{MetaContext, TransportService},
     CubeError,
 };
@@ -47,7 +48,11 @@ struct MetaRequest {
 
 #[async_trait]
 impl TransportService for NodeBridgeTransport {
-    async fn meta(&self, ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError> {
+    async fn meta(
+        &self,
+        ctx: Arc<AuthContext>,
+        _span: Option<SpanContext>,
+    ) -> Result<Arc<MetaContext>, CubeError> {
         trace!("[transport] Meta ->");
 
         let request_id = Uuid::new_v4().to_string();
@@ -74,6 +79,7 @@ impl TransportService for NodeBridgeTransport {
         &self,
         query: V1LoadRequestQuery,
         ctx: Arc<AuthContext>,
+        _span: Option<SpanContext>,
     ) -> Result<V1LoadResponse, CubeError> {
         trace!("[transport] Request ->");
 
@@ -10,6 +10,8 @@ pub struct ConfigurationImpl<T> {
     pub oauth_access_token: Option<String>,
     pub bearer_access_token: Option<String>,
     pub api_key: Option<ApiKey>,
+    /// Headers of every request, they replace headers which are set by the client
+    pub headers: reqwest::header::HeaderMap,
     // TODO: take an oauth2 token source, similar to the go one
 }
 
@@ -33,6 +35,7 @@ impl Configuration {
             oauth_access_token: None,
             bearer_access_token: None,
             api_key: None,
+            headers: reqwest::header::HeaderMap::new(),
         }
     }
 }
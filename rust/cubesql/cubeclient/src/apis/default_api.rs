@@ -51,6 +51,7 @@ pub async fn load_v1(
             "x-request-id",
             format!("{}-span-{}", request_id, span_counter),
         );
+        local_var_req_builder = local_var_req_builder.headers(configuration.headers.clone());
 
         let local_var_req = local_var_req_builder.build()?;
         let local_var_resp = local_var_client.execute(local_var_req).await?;
@@ -129,6 +130,7 @@ pub async fn meta_v1(
     if let Some(ref local_var_token) = local_var_configuration.bearer_access_token {
         local_var_req_builder = local_var_req_builder.bearer_auth(local_var_token.to_owned());
     };
+    local_var_req_builder = local_var_req_builder.headers(local_var_configuration.headers.clone());
 
     let local_var_req = local_var_req_builder.build()?;
     let local_var_resp = local_var_client.execute(local_var_req).await?;
@@ -34,6 +34,8 @@ fn main() {
         let services = config.configure().await;
         track_event("Cube SQL Start".to_string(), HashMap::new()).await;
         stop_on_ctrl_c(&services).await;
+        #[cfg(unix)]
+        stop_on_sigterm(&services).await;
         services.wait_processing_loops().await.unwrap();
     });
 }
@@ -58,3 +60,24 @@ async fn stop_on_ctrl_c(s: &CubeServices) {
         }
     });
 }
+
+/// Orchestrators stop containers by SIGTERM, connections are closed gracefully then
+#[cfg(unix)]
+async fn stop_on_sigterm(s: &CubeServices) {
+    let s = s.clone();
+    tokio::spawn(async move {
+        let mut signal =
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(signal) => signal,
+                Err(e) => {
+                    log::error!("Failed to listen for SIGTERM: {}", e);
+                    return;
+                }
+            };
+
+        if signal.recv().await.is_some() {
+            log::info!("Received SIGTERM, shutting down.");
+            s.stop_processing_loops().await.ok();
+        }
+    });
+}
@@ -0,0 +1,450 @@
+use std::convert::TryFrom;
+
+use datafusion::scalar::ScalarValue;
+use sqlparser::ast;
+
+use super::{grouping::GroupingColumn, CompilationResult};
+
+/// Every bucket is a Cube query, it's limited as grouping sets
+const MAX_BUCKETS: usize = 64;
+
+fn negated_operator(op: &ast::BinaryOperator) -> Option<ast::BinaryOperator> {
+    match op {
+        ast::BinaryOperator::Lt => Some(ast::BinaryOperator::GtEq),
+        ast::BinaryOperator::LtEq => Some(ast::BinaryOperator::Gt),
+        ast::BinaryOperator::Gt => Some(ast::BinaryOperator::LtEq),
+        ast::BinaryOperator::GtEq => Some(ast::BinaryOperator::Lt),
+        ast::BinaryOperator::Eq => Some(ast::BinaryOperator::NotEq),
+        ast::BinaryOperator::NotEq => Some(ast::BinaryOperator::Eq),
+        _ => None,
+    }
+}
+
+fn column(expr: &ast::Expr) -> Option<&ast::Expr> {
+    match expr {
+        ast::Expr::Identifier(_) | ast::Expr::CompoundIdentifier(_) => Some(expr),
+        _ => None,
+    }
+}
+
+fn is_literal(expr: &ast::Expr) -> bool {
+    matches!(expr, ast::Expr::Value(value) if value != &ast::Value::Null)
+}
+
+/// Column of a condition which compares only this column with literals (`amount < 100`,
+/// `amount BETWEEN 1 AND 10 OR amount IN (20, 30)`), these conditions are Cube filters
+fn condition_column(expr: &ast::Expr) -> Option<&ast::Expr> {
+    match expr {
+        ast::Expr::Nested(expr) => condition_column(expr),
+        ast::Expr::BinaryOp {
+            left,
+            op: ast::BinaryOperator::And,
+            right,
+        }
+        | ast::Expr::BinaryOp {
+            left,
+            op: ast::BinaryOperator::Or,
+            right,
+        } => {
+            let column = condition_column(left)?;
+            if condition_column(right)?.to_string() == column.to_string() {
+                Some(column)
+            } else {
+                None
+            }
+        }
+        ast::Expr::BinaryOp { left, op, right } if negated_operator(op).is_some() => {
+            if is_literal(right) {
+                column(left)
+            } else {
+                None
+            }
+        }
+        ast::Expr::Between {
+            expr, low, high, ..
+        } if is_literal(low) && is_literal(high) => column(expr),
+        ast::Expr::InList { expr, list, .. } if list.iter().all(is_literal) => column(expr),
+        _ => None,
+    }
+}
+
+fn nested(expr: ast::Expr) -> Box<ast::Expr> {
+    Box::new(match expr {
+        ast::Expr::Nested(_) => expr,
+        expr => ast::Expr::Nested(Box::new(expr)),
+    })
+}
+
+/// Negation of a normalized condition of `condition_column`, rows with NULL in the column don't match
+/// either of them
+fn negate(expr: &ast::Expr) -> ast::Expr {
+    match expr {
+        ast::Expr::Nested(expr) => negate(expr),
+        ast::Expr::BinaryOp {
+            left,
+            op: ast::BinaryOperator::And,
+            right,
+        } => ast::Expr::BinaryOp {
+            left: nested(negate(left)),
+            op: ast::BinaryOperator::Or,
+            right: nested(negate(right)),
+        },
+        ast::Expr::BinaryOp {
+            left,
+            op: ast::BinaryOperator::Or,
+            right,
+        } => ast::Expr::BinaryOp {
+            left: nested(negate(left)),
+            op: ast::BinaryOperator::And,
+            right: nested(negate(right)),
+        },
+        ast::Expr::BinaryOp { left, op, right } => match negated_operator(op) {
+            Some(op) => ast::Expr::BinaryOp {
+                left: left.clone(),
+                op,
+                right: right.clone(),
+            },
+            None => ast::Expr::UnaryOp {
+                op: ast::UnaryOperator::Not,
+                expr: nested(expr.clone()),
+            },
+        },
+        ast::Expr::InList {
+            expr,
+            list,
+            negated,
+        } => ast::Expr::InList {
+            expr: expr.clone(),
+            list: list.clone(),
+            negated: !negated,
+        },
+        expr => ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Not,
+            expr: nested(expr.clone()),
+        },
+    }
+}
+
+fn comparison(expr: &ast::Expr, op: ast::BinaryOperator, value: &ast::Expr) -> Box<ast::Expr> {
+    nested(ast::Expr::BinaryOp {
+        left: Box::new(expr.clone()),
+        op,
+        right: Box::new(value.clone()),
+    })
+}
+
+/// BETWEEN of a number is replaced by comparisons, as only BETWEEN of a time dimension is
+/// a Cube filter
+fn normalize(expr: &ast::Expr) -> ast::Expr {
+    match expr {
+        ast::Expr::Nested(expr) => ast::Expr::Nested(Box::new(normalize(expr))),
+        ast::Expr::BinaryOp { left, op, right } => ast::Expr::BinaryOp {
+            left: Box::new(normalize(left)),
+            op: op.clone(),
+            right: Box::new(normalize(right)),
+        },
+        ast::Expr::Between {
+            expr,
+            negated,
+            low,
+            high,
+        } if matches!(low.as_ref(), ast::Expr::Value(ast::Value::Number(..))) => {
+            if *negated {
+                ast::Expr::BinaryOp {
+                    left: comparison(expr, ast::BinaryOperator::Lt, low),
+                    op: ast::BinaryOperator::Or,
+                    right: comparison(expr, ast::BinaryOperator::Gt, high),
+                }
+            } else {
+                ast::Expr::BinaryOp {
+                    left: comparison(expr, ast::BinaryOperator::GtEq, low),
+                    op: ast::BinaryOperator::And,
+                    right: comparison(expr, ast::BinaryOperator::LtEq, high),
+                }
+            }
+        }
+        expr => expr.clone(),
+    }
+}
+
+fn and(left: ast::Expr, right: ast::Expr) -> ast::Expr {
+    ast::Expr::BinaryOp {
+        left: nested(left),
+        op: ast::BinaryOperator::And,
+        right: nested(right),
+    }
+}
+
+/// Values of results of CASE, which are literals of the same type. ELSE without a result is
+/// NULL of this type.
+fn bucket_values(results: &[Option<&ast::Expr>]) -> Option<Vec<ScalarValue>> {
+    let mut values = vec![];
+    for result in results.iter() {
+        values.push(match result {
+            None | Some(ast::Expr::Value(ast::Value::Null)) => None,
+            Some(ast::Expr::Value(ast::Value::SingleQuotedString(value))) => {
+                Some(ScalarValue::Utf8(Some(value.clone())))
+            }
+            Some(ast::Expr::Value(ast::Value::Boolean(value))) => {
+                Some(ScalarValue::Boolean(Some(*value)))
+            }
+            Some(ast::Expr::Value(ast::Value::Number(value, _))) => match value.parse::<i64>() {
+                Ok(value) => Some(ScalarValue::Int64(Some(value))),
+                Err(_) => Some(ScalarValue::Float64(Some(value.parse::<f64>().ok()?))),
+            },
+            _ => return None,
+        });
+    }
+
+    // Integers are widened to floats, as PostgreSQL resolves the type of CASE
+    let has_floats = values
+        .iter()
+        .any(|value| matches!(value, Some(ScalarValue::Float64(_))));
+    let values = values
+        .into_iter()
+        .map(|value| match value {
+            Some(ScalarValue::Int64(Some(value))) if has_floats => {
+                Some(ScalarValue::Float64(Some(value as f64)))
+            }
+            value => value,
+        })
+        .collect::<Vec<_>>();
+
+    let data_type = values.iter().flatten().next()?.get_datatype();
+    values
+        .into_iter()
+        .map(|value| match value {
+            Some(value) if value.get_datatype() == data_type => Some(value),
+            Some(_) => None,
+            None => ScalarValue::try_from(&data_type).ok(),
+        })
+        .collect()
+}
+
+/// Conditions and values of buckets of `CASE WHEN amount < 100 THEN 'small' ... END`, the
+/// last bucket is ELSE. None if it's not a range bucketing of a column.
+fn case_buckets(expr: &ast::Expr) -> Option<(ast::Expr, Vec<ast::Expr>, Vec<ScalarValue>)> {
+    let (operand, conditions, results, else_result) = match expr {
+        ast::Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => (operand, conditions, results, else_result),
+        _ => return None,
+    };
+    if conditions.len() + 1 > MAX_BUCKETS {
+        return None;
+    }
+
+    // `CASE amount WHEN 1 THEN ...` is `CASE WHEN amount = 1 THEN ...`
+    let conditions = match operand {
+        Some(operand) => conditions
+            .iter()
+            .map(|condition| ast::Expr::BinaryOp {
+                left: operand.clone(),
+                op: ast::BinaryOperator::Eq,
+                right: Box::new(condition.clone()),
+            })
+            .collect(),
+        None => conditions.clone(),
+    };
+
+    let column = condition_column(conditions.first()?)?.clone();
+    let same_column = conditions.iter().all(|condition| {
+        condition_column(condition).map(|c| c.to_string()) == Some(column.to_string())
+    });
+    if !same_column {
+        return None;
+    }
+
+    let mut values = results.iter().map(Some).collect::<Vec<_>>();
+    values.push(else_result.as_deref());
+    let values = bucket_values(&values)?;
+
+    Some((column, conditions.iter().map(normalize).collect(), values))
+}
+
+fn is_alias(expr: &ast::Expr, alias: Option<&ast::Ident>) -> bool {
+    match (expr, alias) {
+        (ast::Expr::Identifier(ident), Some(alias)) => ident.value == alias.value,
+        _ => false,
+    }
+}
+
+/// Query of a bucket of CASE, it's compiled into a Cube query
+pub struct BucketQuery {
+    pub query: ast::Query,
+    pub columns: Vec<GroupingColumn>,
+}
+
+/// CASE of the projection, which buckets values of a column by ranges and is a key of GROUP BY,
+/// is compiled into a Cube query per bucket: it's filtered by the condition of the bucket and
+/// conditions of previous buckets are negated, rows with NULL in the column are in ELSE. The
+/// value of the bucket is a constant column of the query, results are merged as grouping sets.
+/// Unlike GROUP BY, a query without other dimensions returns a row for an empty bucket.
+pub fn bucket_queries(
+    query: &ast::Query,
+    select: &ast::Select,
+) -> CompilationResult<Option<Vec<BucketQuery>>> {
+    // Positions of GROUP BY are replaced by expressions of the projection
+    let group_by = select
+        .group_by
+        .iter()
+        .map(|expr| {
+            let item = match expr {
+                ast::Expr::Value(ast::Value::Number(position, _)) => position
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|position| select.projection.get(position.wrapping_sub(1))),
+                _ => None,
+            };
+            match item {
+                Some(ast::SelectItem::UnnamedExpr(item))
+                | Some(ast::SelectItem::ExprWithAlias { expr: item, .. }) => item.clone(),
+                _ => expr.clone(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let bucketing = select.projection.iter().enumerate().find_map(|(i, item)| {
+        let (expr, alias) = match item {
+            ast::SelectItem::UnnamedExpr(expr) => (expr, None),
+            ast::SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias)),
+            _ => return None,
+        };
+        if !group_by
+            .iter()
+            .any(|key| key == expr || is_alias(key, alias))
+        {
+            return None;
+        }
+
+        case_buckets(expr).map(|buckets| (i, expr.to_string(), alias, buckets))
+    });
+    let (position, key, alias, (column, conditions, values)) = match bucketing {
+        Some(bucketing) => bucketing,
+        None => return Ok(None),
+    };
+
+    let mut projection = select.projection.clone();
+    projection.remove(position);
+    let mut columns = (0..projection.len())
+        .map(GroupingColumn::Member)
+        .collect::<Vec<_>>();
+    let group_by = group_by
+        .into_iter()
+        .filter(|expr| expr.to_string() != key && !is_alias(expr, alias))
+        .collect::<Vec<_>>();
+
+    let mut queries = Vec::with_capacity(values.len());
+    for (i, value) in values.into_iter().enumerate() {
+        let mut filter = conditions.get(i).cloned();
+        for previous in conditions[..i].iter() {
+            filter = Some(match filter {
+                Some(filter) => and(filter, negate(previous)),
+                None => negate(previous),
+            });
+        }
+        // ELSE
+        if i == conditions.len() {
+            let is_null = ast::Expr::IsNull(Box::new(column.clone()));
+            filter = Some(match filter {
+                Some(filter) => ast::Expr::BinaryOp {
+                    left: nested(filter),
+                    op: ast::BinaryOperator::Or,
+                    right: Box::new(is_null),
+                },
+                None => is_null,
+            });
+        }
+
+        let mut bucket_select = select.clone();
+        bucket_select.projection = projection.clone();
+        bucket_select.group_by = group_by.clone();
+        bucket_select.selection = match (&select.selection, filter) {
+            (Some(selection), Some(filter)) => Some(and(selection.clone(), filter)),
+            (selection, filter) => selection.clone().or(filter),
+        };
+
+        let mut bucket_query = query.clone();
+        bucket_query.body = ast::SetExpr::Select(Box::new(bucket_select));
+        bucket_query.order_by = vec![];
+        bucket_query.limit = None;
+        bucket_query.offset = None;
+
+        columns.insert(position, GroupingColumn::Value(value));
+        queries.push(BucketQuery {
+            query: bucket_query,
+            columns: columns.clone(),
+        });
+        columns.remove(position);
+    }
+
+    Ok(Some(queries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+
+    fn buckets(sql: &str) -> Option<Vec<String>> {
+        let query = match Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0)
+        {
+            ast::Statement::Query(query) => query,
+            _ => panic!("{} must be a query", sql),
+        };
+        let select = match &query.body {
+            ast::SetExpr::Select(select) => select,
+            _ => panic!("{} must be a select", sql),
+        };
+
+        bucket_queries(&query, select).unwrap().map(|queries| {
+            queries
+                .into_iter()
+                .map(|bucket| bucket.query.to_string())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_bucket_queries() {
+        assert_eq!(
+            buckets(
+                "SELECT status, CASE WHEN amount < 100 THEN 'small' WHEN amount BETWEEN 100 AND 500 THEN 'medium' END AS size, \
+                COUNT(*) FROM orders WHERE status <> 'new' GROUP BY 1, size ORDER BY 2 LIMIT 10"
+            ),
+            Some(vec![
+                "SELECT status, COUNT(*) FROM orders WHERE (status <> 'new') AND (amount < 100) GROUP BY status".to_string(),
+                "SELECT status, COUNT(*) FROM orders WHERE (status <> 'new') AND (((amount >= 100) AND (amount <= 500)) AND (amount >= 100)) GROUP BY status".to_string(),
+                "SELECT status, COUNT(*) FROM orders WHERE (status <> 'new') AND (((amount >= 100) AND ((amount < 100) OR (amount > 500))) OR amount IS NULL) GROUP BY status".to_string(),
+            ])
+        );
+        assert_eq!(
+            buckets(
+                "SELECT CASE amount WHEN 1 THEN 1 ELSE 0.5 END, COUNT(*) FROM orders GROUP BY 1"
+            ),
+            Some(vec![
+                "SELECT COUNT(*) FROM orders WHERE amount = 1".to_string(),
+                "SELECT COUNT(*) FROM orders WHERE (amount <> 1) OR amount IS NULL".to_string(),
+            ])
+        );
+
+        // Conditions of different columns, results of expressions and CASE out of GROUP BY
+        assert_eq!(
+            buckets("SELECT CASE WHEN a < 1 THEN 'x' WHEN b < 1 THEN 'y' END, COUNT(*) FROM t GROUP BY 1"),
+            None
+        );
+        assert_eq!(
+            buckets("SELECT CASE WHEN a < 1 THEN a END, COUNT(*) FROM t GROUP BY 1"),
+            None
+        );
+        assert_eq!(
+            buckets("SELECT CASE WHEN a < 1 THEN 'x' END, COUNT(*) FROM t"),
+            None
+        );
+    }
+}
@@ -39,6 +39,7 @@ pub struct QueryBuilder {
     order: Vec<Vec<String>>,
     limit: Option<i32>,
     offset: Option<i32>,
+    timezone: Option<String>,
     // query meta for response hydration
     meta: Vec<CompiledQueryFieldMeta>,
 }
@@ -55,6 +56,7 @@ impl QueryBuilder {
             filters: vec![],
             limit: None,
             offset: None,
+            timezone: None,
         }
     }
 
@@ -124,6 +126,10 @@ impl QueryBuilder {
         self.offset = Some(offset);
     }
 
+    pub fn with_timezone(&mut self, timezone: String) {
+        self.timezone = Some(timezone);
+    }
+
     pub fn with_order(&mut self, order: Vec<String>) {
         self.order.push(order);
     }
@@ -159,6 +165,7 @@ impl QueryBuilder {
                 } else {
                     None
                 },
+                timezone: self.timezone,
             },
             meta: self.meta,
         }
@@ -23,6 +23,10 @@ pub enum Selection {
 pub struct QueryContext {
     pub meta: V1CubeMeta,
     aliases: HashMap<String, Selection>,
+    // TimeZone of the session, it's used by date_trunc without the time zone argument
+    session_timezone: String,
+    // A Cube query has one time zone for all time dimensions
+    timezone: Option<String>,
 }
 
 impl QueryContext {
@@ -30,9 +34,41 @@ impl QueryContext {
         QueryContext {
             meta: meta.clone(),
             aliases: HashMap::new(),
+            session_timezone: "UTC".to_string(),
+            timezone: None,
         }
     }
 
+    pub fn with_session_timezone(mut self, timezone: String) -> Self {
+        self.session_timezone = timezone;
+        self
+    }
+
+    /// Time zone of a time dimension, `date_trunc(granularity, column, timezone)` or TimeZone of
+    /// the session
+    pub fn use_timezone(&mut self, timezone: Option<String>) -> CompilationResult<()> {
+        let timezone = timezone.unwrap_or_else(|| self.session_timezone.clone());
+        match &self.timezone {
+            Some(current) if !current.eq_ignore_ascii_case(&timezone) => {
+                Err(CompilationError::Unsupported(format!(
+                    "Time dimensions in time zones {} and {} in one query",
+                    current, timezone
+                )))
+            }
+            _ => {
+                self.timezone = Some(timezone);
+                Ok(())
+            }
+        }
+    }
+
+    /// Time zone of the Cube query, None is for UTC
+    pub fn timezone(&self) -> Option<String> {
+        self.timezone
+            .clone()
+            .filter(|timezone| !timezone.eq_ignore_ascii_case("UTC"))
+    }
+
     pub fn find_selection_for_identifier(
         &self,
         identifier: &String,
@@ -275,9 +311,20 @@ impl QueryContext {
         &self,
         f: &ast::Function,
     ) -> CompilationResult<Selection> {
-        match f.args.as_slice() {
+        // The time zone of date_trunc(string, column, timezone) is taken by date_trunc_timezone
+        let args = match f.args.as_slice() {
+            [args @ .., ast::FunctionArg::Unnamed(ast::Expr::Value(ast::Value::SingleQuotedString(_)))]
+                if args.len() == 2 =>
+            {
+                args
+            }
+            args => args,
+        };
+
+        match args {
             [ast::FunctionArg::Unnamed(ast::Expr::Value(ast::Value::SingleQuotedString(granularity))), ast::FunctionArg::Unnamed(ast::Expr::Identifier(column))] => {
                 let possible_dimension_name = column.value.to_string();
+                let granularity = granularity.to_lowercase();
 
                 match granularity.as_str() {
                     "second" | "minute" | "hour" | "day" | "week" | "month" | "quarter" | "year" => (),
@@ -306,11 +353,26 @@ impl QueryContext {
                 }
             }
             _ => Err(CompilationError::User(
-                "Unsupported variation of arguments passed to date_trunc function, correct date_trunc(string, column)".to_string()
+                "Unsupported variation of arguments passed to date_trunc function, correct date_trunc(string, column[, timezone])".to_string()
             )),
         }
     }
 
+    /// Time zone argument of `date_trunc(string, column, timezone)`
+    pub fn date_trunc_timezone(expr: &ast::Expr) -> Option<String> {
+        match expr {
+            ast::Expr::Function(f) if f.name.to_string().eq_ignore_ascii_case("date_trunc") => {
+                match f.args.as_slice() {
+                    [_, _, ast::FunctionArg::Unnamed(ast::Expr::Value(
+                        ast::Value::SingleQuotedString(timezone),
+                    ))] => Some(timezone.clone()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
     pub fn find_selection_for_date_fn(&self, f: &ast::Function) -> CompilationResult<Selection> {
         match f.args.as_slice() {
             [ast::FunctionArg::Unnamed(ast::Expr::Function(date_sub))] => {
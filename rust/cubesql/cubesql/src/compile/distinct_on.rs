@@ -0,0 +1,289 @@
+use sqlparser::ast;
+
+use super::{CompilationError, CompilationResult};
+
+/// `DISTINCT ON (a, b)` isn't parsed by the pinned sqlparser, it's rewritten into the first
+/// item of the projection `__distinct_on(a, b)` by `parse_sql_to_statement` for PostgreSQL
+pub const DISTINCT_ON_FUNCTION: &str = "__distinct_on";
+
+const DISTINCT_ON_ALIAS: &str = "__distinct_on";
+const ROW_NUMBER_COLUMN: &str = "__distinct_on_row";
+
+/// Expressions of `DISTINCT ON`
+pub fn distinct_on(select: &ast::Select) -> CompilationResult<Option<Vec<ast::Expr>>> {
+    let fun = match select.projection.first() {
+        Some(ast::SelectItem::UnnamedExpr(ast::Expr::Function(fun)))
+            if fun
+                .name
+                .to_string()
+                .eq_ignore_ascii_case(DISTINCT_ON_FUNCTION) =>
+        {
+            fun
+        }
+        _ => return Ok(None),
+    };
+
+    let exprs = fun
+        .args
+        .iter()
+        .map(|arg| match arg {
+            ast::FunctionArg::Unnamed(expr) => Ok(expr.clone()),
+            ast::FunctionArg::Named { .. } => Err(CompilationError::Internal(
+                "DISTINCT ON can't have named arguments".to_string(),
+            )),
+        })
+        .collect::<CompilationResult<Vec<_>>>()?;
+
+    Ok(Some(exprs))
+}
+
+fn projection(select: &ast::Select) -> &[ast::SelectItem] {
+    &select.projection[1..]
+}
+
+/// Names of columns of the result, as they are named without DISTINCT ON
+fn output_name(item: &ast::SelectItem) -> CompilationResult<ast::Ident> {
+    match item {
+        ast::SelectItem::ExprWithAlias { alias, .. } => Ok(alias.clone()),
+        ast::SelectItem::UnnamedExpr(ast::Expr::Identifier(ident)) => Ok(ident.clone()),
+        ast::SelectItem::UnnamedExpr(ast::Expr::CompoundIdentifier(idents))
+            if !idents.is_empty() =>
+        {
+            Ok(idents[idents.len() - 1].clone())
+        }
+        ast::SelectItem::UnnamedExpr(expr) => Ok(ast::Ident::with_quote('"', expr.to_string())),
+        _ => Err(CompilationError::Unsupported(
+            "Wildcards can't be selected with DISTINCT ON".to_string(),
+        )),
+    }
+}
+
+/// Positions and aliases of the projection are replaced by their expressions, as they can't be
+/// referenced by window specifications
+fn resolve(expr: &ast::Expr, select: &ast::Select) -> ast::Expr {
+    let item = match expr {
+        ast::Expr::Value(ast::Value::Number(position, _)) => position
+            .parse::<usize>()
+            .ok()
+            .and_then(|position| projection(select).get(position.wrapping_sub(1))),
+        ast::Expr::Identifier(ident) => projection(select).iter().find(|item| match item {
+            ast::SelectItem::ExprWithAlias { alias, .. } => alias.value == ident.value,
+            _ => false,
+        }),
+        _ => None,
+    };
+
+    match item {
+        Some(ast::SelectItem::UnnamedExpr(expr))
+        | Some(ast::SelectItem::ExprWithAlias { expr, .. }) => expr.clone(),
+        _ => expr.clone(),
+    }
+}
+
+/// DISTINCT ON keeps the first row of every group of rows with equal expressions, as they are
+/// ordered by ORDER BY. Rows are numbered by `ROW_NUMBER() OVER (PARTITION BY ... ORDER BY ...)`
+/// in this query, the first rows are selected by `distinct_on_query`.
+pub fn distinct_on_window_query(
+    query: &ast::Query,
+    select: &ast::Select,
+    exprs: &[ast::Expr],
+) -> CompilationResult<ast::Query> {
+    let partition_by = exprs
+        .iter()
+        .map(|expr| resolve(expr, select))
+        .collect::<Vec<_>>();
+    let order_by = query
+        .order_by
+        .iter()
+        .map(|order_by| ast::OrderByExpr {
+            expr: resolve(&order_by.expr, select),
+            asc: order_by.asc,
+            nulls_first: order_by.nulls_first,
+        })
+        .collect::<Vec<_>>();
+
+    // The same restriction as in PostgreSQL
+    if !order_by.is_empty() {
+        let mut leading = order_by
+            .iter()
+            .take(partition_by.len())
+            .map(|order_by| order_by.expr.to_string())
+            .collect::<Vec<_>>();
+        let mut distinct = partition_by
+            .iter()
+            .map(|expr| expr.to_string())
+            .collect::<Vec<_>>();
+        leading.sort();
+        leading.dedup();
+        distinct.sort();
+        distinct.dedup();
+
+        if leading != distinct {
+            return Err(CompilationError::User(
+                "SELECT DISTINCT ON expressions must match initial ORDER BY expressions"
+                    .to_string(),
+            ));
+        }
+    }
+
+    let mut items = vec![];
+    for item in projection(select).iter() {
+        let alias = output_name(item)?;
+        items.push(match item {
+            ast::SelectItem::UnnamedExpr(expr) => ast::SelectItem::ExprWithAlias {
+                expr: expr.clone(),
+                alias,
+            },
+            item => item.clone(),
+        });
+    }
+    items.push(ast::SelectItem::ExprWithAlias {
+        expr: ast::Expr::Function(ast::Function {
+            name: ast::ObjectName(vec![ast::Ident::new("ROW_NUMBER")]),
+            args: vec![],
+            over: Some(ast::WindowSpec {
+                partition_by,
+                order_by,
+                window_frame: None,
+            }),
+            distinct: false,
+        }),
+        alias: ast::Ident::new(ROW_NUMBER_COLUMN),
+    });
+
+    let mut window_select = select.clone();
+    window_select.projection = items;
+
+    let mut window = query.clone();
+    window.body = ast::SetExpr::Select(Box::new(window_select));
+    window.order_by = vec![];
+    window.limit = None;
+    window.offset = None;
+
+    Ok(window)
+}
+
+/// Selects the first rows of `window` of `distinct_on_window_query`, ORDER BY and LIMIT of the
+/// query are applied to them
+pub fn distinct_on_query(
+    query: &ast::Query,
+    select: &ast::Select,
+    window: ast::Query,
+) -> CompilationResult<ast::Query> {
+    if query.offset.is_some() {
+        return Err(CompilationError::Unsupported(
+            "Query with OFFSET and DISTINCT ON".to_string(),
+        ));
+    }
+
+    let names = projection(select)
+        .iter()
+        .map(output_name)
+        .collect::<CompilationResult<Vec<_>>>()?;
+
+    let mut order_by = vec![];
+    for order_expr in query.order_by.iter() {
+        let name = match &order_expr.expr {
+            ast::Expr::Value(ast::Value::Number(position, _)) => position
+                .parse::<usize>()
+                .ok()
+                .and_then(|position| names.get(position.wrapping_sub(1))),
+            ast::Expr::Identifier(ident) => names.iter().find(|name| name.value == ident.value),
+            expr => projection(select)
+                .iter()
+                .position(|item| match item {
+                    ast::SelectItem::UnnamedExpr(item)
+                    | ast::SelectItem::ExprWithAlias { expr: item, .. } => item == expr,
+                    _ => false,
+                })
+                .map(|position| &names[position]),
+        };
+        let name = name.ok_or_else(|| {
+            CompilationError::Unsupported(format!(
+                "ORDER BY {} with DISTINCT ON, only columns of the result can be used",
+                order_expr.expr
+            ))
+        })?;
+
+        order_by.push(ast::OrderByExpr {
+            expr: ast::Expr::Identifier(name.clone()),
+            asc: order_expr.asc,
+            nulls_first: order_expr.nulls_first,
+        });
+    }
+
+    let mut outer_select = select.clone();
+    outer_select.projection = names
+        .into_iter()
+        .map(|name| ast::SelectItem::UnnamedExpr(ast::Expr::Identifier(name)))
+        .collect();
+    outer_select.from = vec![ast::TableWithJoins {
+        relation: ast::TableFactor::Derived {
+            lateral: false,
+            subquery: Box::new(window),
+            alias: Some(ast::TableAlias {
+                name: ast::Ident::new(DISTINCT_ON_ALIAS),
+                columns: vec![],
+            }),
+        },
+        joins: vec![],
+    }];
+    outer_select.selection = Some(ast::Expr::BinaryOp {
+        left: Box::new(ast::Expr::Identifier(ast::Ident::new(ROW_NUMBER_COLUMN))),
+        op: ast::BinaryOperator::Eq,
+        right: Box::new(ast::Expr::Value(ast::Value::Number("1".to_string(), false))),
+    });
+    outer_select.group_by = vec![];
+    outer_select.having = None;
+
+    let mut outer = query.clone();
+    outer.body = ast::SetExpr::Select(Box::new(outer_select));
+    outer.order_by = order_by;
+
+    Ok(outer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile::parser::parse_sql_to_statement, sql::session::DatabaseProtocol};
+
+    fn rewrite(sql: &str) -> CompilationResult<String> {
+        let query = match parse_sql_to_statement(&sql.to_string(), DatabaseProtocol::PostgreSQL)? {
+            ast::Statement::Query(query) => query,
+            _ => panic!("{} must be a query", sql),
+        };
+        let select = match &query.body {
+            ast::SetExpr::Select(select) => select,
+            _ => panic!("{} must be a select", sql),
+        };
+        let exprs = distinct_on(select)?.expect("DISTINCT ON must be parsed");
+
+        let window = distinct_on_window_query(&query, select, &exprs)?;
+        Ok(distinct_on_query(&query, select, window)?.to_string())
+    }
+
+    #[test]
+    fn test_distinct_on() {
+        assert_eq!(
+            rewrite(
+                "SELECT DISTINCT ON (customer_gender) customer_gender, taxful_total_price AS price \
+                FROM t WHERE price > 10 ORDER BY 1, price DESC LIMIT 5"
+            )
+            .unwrap(),
+            "SELECT customer_gender, price FROM (SELECT customer_gender AS customer_gender, \
+            taxful_total_price AS price, ROW_NUMBER() OVER (PARTITION BY customer_gender \
+            ORDER BY customer_gender, taxful_total_price DESC) AS __distinct_on_row FROM t \
+            WHERE price > 10) AS __distinct_on WHERE __distinct_on_row = 1 \
+            ORDER BY customer_gender, price DESC LIMIT 5"
+        );
+
+        assert_eq!(
+            rewrite("SELECT DISTINCT ON (a) a, b FROM t ORDER BY b").unwrap_err(),
+            CompilationError::User(
+                "SELECT DISTINCT ON expressions must match initial ORDER BY expressions"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -80,3 +80,157 @@ pub fn least_coercion(lhs_type: &DataType, rhs_type: &DataType) -> Option<DataTy
 
     hack_ty.or_else(|| numerical_coercion(lhs_type, rhs_type))
 }
+
+/// Largest precision of NUMERIC values, they are stored as 128-bit integers
+pub const MAX_DECIMAL_PRECISION: usize = 38;
+/// Minimal scale of quotients and averages of decimals
+pub const MIN_DECIMAL_DIVISION_SCALE: usize = 16;
+
+/// Precision and scale of a number as an operand of decimal arithmetic, integers have no
+/// fractional digits
+pub fn decimal_operand_type(dt: &DataType) -> Option<(usize, usize)> {
+    match dt {
+        DataType::Decimal(precision, scale) => Some((*precision, *scale)),
+        DataType::Int8 | DataType::UInt8 => Some((3, 0)),
+        DataType::Int16 | DataType::UInt16 => Some((5, 0)),
+        DataType::Int32 | DataType::UInt32 => Some((10, 0)),
+        DataType::Int64 => Some((19, 0)),
+        DataType::UInt64 => Some((20, 0)),
+        _ => None,
+    }
+}
+
+/// Operators of decimals, DataFusion can't calculate them. They are rewritten into calls of
+/// `numeric_*` functions before planning.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumericOperator {
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl NumericOperator {
+    pub const ALL: [NumericOperator; 10] = [
+        NumericOperator::Plus,
+        NumericOperator::Minus,
+        NumericOperator::Multiply,
+        NumericOperator::Divide,
+        NumericOperator::Eq,
+        NumericOperator::NotEq,
+        NumericOperator::Lt,
+        NumericOperator::LtEq,
+        NumericOperator::Gt,
+        NumericOperator::GtEq,
+    ];
+
+    pub fn function_name(&self) -> &'static str {
+        match self {
+            NumericOperator::Plus => "numeric_add",
+            NumericOperator::Minus => "numeric_sub",
+            NumericOperator::Multiply => "numeric_mul",
+            NumericOperator::Divide => "numeric_div",
+            NumericOperator::Eq => "numeric_eq",
+            NumericOperator::NotEq => "numeric_ne",
+            NumericOperator::Lt => "numeric_lt",
+            NumericOperator::LtEq => "numeric_le",
+            NumericOperator::Gt => "numeric_gt",
+            NumericOperator::GtEq => "numeric_ge",
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        !matches!(
+            self,
+            NumericOperator::Plus
+                | NumericOperator::Minus
+                | NumericOperator::Multiply
+                | NumericOperator::Divide
+        )
+    }
+}
+
+/// Precision and scale of results of decimal arithmetic: sums keep the largest scale, products
+/// add scales, quotients have at least `MIN_DECIMAL_DIVISION_SCALE` fractional digits
+pub fn decimal_arithmetic_coercion(
+    op: NumericOperator,
+    (lhs_precision, lhs_scale): (usize, usize),
+    (rhs_precision, rhs_scale): (usize, usize),
+) -> (usize, usize) {
+    let (precision, scale) = match op {
+        NumericOperator::Plus | NumericOperator::Minus => {
+            let scale = lhs_scale.max(rhs_scale);
+            let integral = (lhs_precision - lhs_scale).max(rhs_precision - rhs_scale);
+
+            (integral + scale + 1, scale)
+        }
+        NumericOperator::Multiply => (lhs_precision + rhs_precision, lhs_scale + rhs_scale),
+        _ => (
+            MAX_DECIMAL_PRECISION,
+            lhs_scale.max(rhs_scale).max(MIN_DECIMAL_DIVISION_SCALE),
+        ),
+    };
+    let scale = scale.min(MAX_DECIMAL_PRECISION);
+
+    (precision.min(MAX_DECIMAL_PRECISION).max(scale), scale)
+}
+
+/// Type of results of numeric operators. Comparisons are booleans, arithmetic with floats is
+/// calculated with floats, other numbers are decimals.
+pub fn numeric_operator_coercion(
+    op: NumericOperator,
+    lhs_type: &DataType,
+    rhs_type: &DataType,
+) -> Option<DataType> {
+    let is_float = |dt: &DataType| {
+        matches!(
+            dt,
+            DataType::Float16 | DataType::Float32 | DataType::Float64
+        )
+    };
+    // NULL has no digits, the result has the type of the other operand
+    let operand = |dt: &DataType| match dt {
+        DataType::Null => Some((1, 0)),
+        dt => decimal_operand_type(dt),
+    };
+
+    let is_number = |dt: &DataType| is_float(dt) || operand(dt).is_some();
+    if !is_number(lhs_type) || !is_number(rhs_type) {
+        return None;
+    }
+
+    if op.is_comparison() {
+        return Some(DataType::Boolean);
+    }
+    if is_float(lhs_type) || is_float(rhs_type) {
+        return Some(DataType::Float64);
+    }
+
+    let (precision, scale) =
+        decimal_arithmetic_coercion(op, operand(lhs_type)?, operand(rhs_type)?);
+    Some(DataType::Decimal(precision, scale))
+}
+
+/// Precision and scale of aggregates of decimals, sums have the largest precision
+pub fn decimal_aggregate_coercion(
+    fun: &str,
+    (precision, scale): (usize, usize),
+) -> Option<(usize, usize)> {
+    match fun {
+        "sum" => Some((MAX_DECIMAL_PRECISION, scale)),
+        "avg" => Some((
+            MAX_DECIMAL_PRECISION,
+            scale
+                .max(MIN_DECIMAL_DIVISION_SCALE)
+                .min(MAX_DECIMAL_PRECISION),
+        )),
+        "min" | "max" => Some((precision, scale)),
+        _ => None,
+    }
+}
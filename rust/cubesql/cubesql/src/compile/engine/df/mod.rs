@@ -1,5 +1,4 @@
 pub mod coerce;
 pub mod columar;
-pub mod intervals;
 pub mod planner;
 pub mod scan;
@@ -8,17 +8,21 @@ use datafusion::{
     physical_plan::{planner::DefaultPhysicalPlanner, ExecutionPlan, PhysicalPlanner},
 };
 
-use crate::transport::TransportService;
+use crate::{sql::session::SessionState, transport::TransportService};
 
 use super::scan::CubeScanExtensionPlanner;
 
 pub struct CubeQueryPlanner {
     pub transport: Arc<dyn TransportService>,
+    pub session_state: Arc<SessionState>,
 }
 
 impl CubeQueryPlanner {
-    pub fn new(transport: Arc<dyn TransportService>) -> Self {
-        Self { transport }
+    pub fn new(transport: Arc<dyn TransportService>, session_state: Arc<SessionState>) -> Self {
+        Self {
+            transport,
+            session_state,
+        }
     }
 }
 
@@ -35,6 +39,7 @@ impl QueryPlanner for CubeQueryPlanner {
         let physical_planner = DefaultPhysicalPlanner::with_extension_planners(vec![Arc::new(
             CubeScanExtensionPlanner {
                 transport: self.transport.clone(),
+                session_state: self.session_state.clone(),
             },
         )]);
         // Delegate most work of physical planning to the default physical planner
@@ -1,15 +1,18 @@
 use std::{
     any::Any,
     fmt,
-    sync::Arc,
+    sync::{Arc, RwLock as RwLockSync},
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
 use cubeclient::models::{V1LoadRequestQuery, V1LoadResult};
 use datafusion::{
     arrow::{
-        array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder},
+        array::{
+            ArrayRef, BooleanBuilder, DecimalBuilder, Float64Builder, Int64Builder, StringBuilder,
+        },
         datatypes::{DataType, SchemaRef},
         error::Result as ArrowResult,
         record_batch::RecordBatch,
@@ -25,7 +28,11 @@ use datafusion::{
 use futures::Stream;
 use log::{error, warn};
 
-use crate::{sql::AuthContext, transport::TransportService};
+use crate::{
+    sql::{dataframe::DecimalValue, session::SessionState, AuthContext},
+    telemetry::tracing::SpanContext,
+    transport::TransportService,
+};
 use chrono::{TimeZone, Utc};
 use datafusion::arrow::array::TimestampNanosecondBuilder;
 use datafusion::arrow::datatypes::TimeUnit;
@@ -100,6 +107,7 @@ impl UserDefinedLogicalNode for CubeScanNode {
 //  the logical plan node.
 pub struct CubeScanExtensionPlanner {
     pub transport: Arc<dyn TransportService>,
+    pub session_state: Arc<SessionState>,
 }
 
 impl ExtensionPlanner for CubeScanExtensionPlanner {
@@ -124,6 +132,9 @@ impl ExtensionPlanner for CubeScanExtensionPlanner {
                     transport: self.transport.clone(),
                     request: scan_node.request.clone(),
                     auth_context: scan_node.auth_context.clone(),
+                    // Plans are cached, the span of the current statement is taken on planning
+                    trace_context: self.session_state.trace_context(),
+                    stats: RwLockSync::new(None),
                 }))
             } else {
                 None
@@ -139,8 +150,47 @@ struct CubeScanExecutionPlan {
     member_fields: Vec<String>,
     request: V1LoadRequestQuery,
     auth_context: Arc<AuthContext>,
+    trace_context: Option<SpanContext>,
     // Shared references which will be injected by extension planner
     transport: Arc<dyn TransportService>,
+    // It's set by the execution
+    stats: RwLockSync<Option<CubeScanStats>>,
+}
+
+/// Time of the request to the Cube API and the number of received rows for EXPLAIN ANALYZE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CubeScanStats {
+    pub load_time: Duration,
+    pub rows: usize,
+}
+
+/// Stats of CubeScan nodes of the executed plan in the order of the logical plan, it's None
+/// for nodes which were not executed
+pub fn cube_scan_stats(plan: &Arc<dyn ExecutionPlan>) -> Vec<Option<CubeScanStats>> {
+    if let Some(scan) = plan.as_any().downcast_ref::<CubeScanExecutionPlan>() {
+        let stats = scan
+            .stats
+            .read()
+            .expect("failed to unlock cube scan stats for reading");
+
+        return vec![*stats];
+    }
+
+    plan.children().iter().flat_map(cube_scan_stats).collect()
+}
+
+/// Decimals are rounded to the scale of the column, as values of NUMERIC(p, s) in PostgreSQL
+fn append_decimal(builder: &mut DecimalBuilder, text: &str, scale: usize) -> Result<()> {
+    match DecimalValue::parse(text).and_then(|value| value.rescale(scale)) {
+        Some(value) => builder.append_value(value.value())?,
+        None => {
+            warn!("Unable to parse value as decimal: {}", text);
+
+            builder.append_null()?
+        }
+    };
+
+    Ok(())
 }
 
 impl CubeScanExecutionPlan {
@@ -256,6 +306,37 @@ impl CubeScanExecutionPlan {
 
                     Arc::new(builder.finish()) as ArrayRef
                 }
+                DataType::Decimal(precision, scale) => {
+                    let mut builder = DecimalBuilder::new(response.data.len(), *precision, *scale);
+
+                    for row in response.data.iter() {
+                        let value = row.as_object().unwrap().get(field_name).ok_or(
+                            DataFusionError::Internal(
+                                "Unexpected response from Cube.js, rows are not objects"
+                                    .to_string(),
+                            ),
+                        )?;
+                        match &value {
+                            serde_json::Value::Null => builder.append_null()?,
+                            serde_json::Value::String(s) => {
+                                append_decimal(&mut builder, s, *scale)?
+                            }
+                            serde_json::Value::Number(number) => {
+                                append_decimal(&mut builder, &number.to_string(), *scale)?
+                            }
+                            v => {
+                                error!(
+                                    "Unable to map value {:?} to DataType::Decimal (returning null)",
+                                    v
+                                );
+
+                                builder.append_null()?
+                            }
+                        };
+                    }
+
+                    Arc::new(builder.finish()) as ArrayRef
+                }
                 DataType::Boolean => {
                     let mut builder = BooleanBuilder::new(100);
 
@@ -363,9 +444,14 @@ impl ExecutionPlan for CubeScanExecutionPlan {
     }
 
     async fn execute(&self, _partition: usize) -> Result<SendableRecordBatchStream> {
+        let started = Instant::now();
         let result = self
             .transport
-            .load(self.request.clone(), self.auth_context.clone())
+            .load(
+                self.request.clone(),
+                self.auth_context.clone(),
+                self.trace_context,
+            )
             .await;
 
         let mut response = result.map_err(|err| DataFusionError::Execution(err.to_string()))?;
@@ -378,9 +464,20 @@ impl ExecutionPlan for CubeScanExecutionPlan {
             )));
         };
 
+        let batch = self.transform_response(result)?;
+        let mut stats = self
+            .stats
+            .write()
+            .expect("failed to unlock cube scan stats for writting");
+        *stats = Some(CubeScanStats {
+            load_time: started.elapsed(),
+            rows: batch.num_rows(),
+        });
+        drop(stats);
+
         Ok(Box::pin(CubeScanMemoryStream::new(
             // @todo Pagination?)
-            vec![self.transform_response(result)?],
+            vec![batch],
             self.schema.clone(),
         )))
     }
@@ -473,7 +570,11 @@ mod tests {
         #[async_trait]
         impl TransportService for TestConnectionTransport {
             // Load meta information about cubes
-            async fn meta(&self, _ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError> {
+            async fn meta(
+                &self,
+                _ctx: Arc<AuthContext>,
+                _span: Option<SpanContext>,
+            ) -> Result<Arc<MetaContext>, CubeError> {
                 panic!("It's a fake transport");
             }
 
@@ -482,6 +583,7 @@ mod tests {
                 &self,
                 _query: V1LoadRequestQuery,
                 _ctx: Arc<AuthContext>,
+                _span: Option<SpanContext>,
             ) -> Result<V1LoadResponse, CubeError> {
                 let response = r#"
                     {
@@ -525,7 +627,7 @@ mod tests {
             Field::new("KibanaSampleDataEcommerce.isBool", DataType::Boolean, false),
         ]));
 
-        let scan_node = CubeScanExecutionPlan {
+        let scan_node: Arc<dyn ExecutionPlan> = Arc::new(CubeScanExecutionPlan {
             schema: schema.clone(),
             member_fields: schema
                 .fields()
@@ -541,13 +643,17 @@ mod tests {
                 limit: None,
                 offset: None,
                 filters: None,
+                timezone: None,
             },
             auth_context: Arc::new(AuthContext {
                 access_token: "access_token".to_string(),
                 base_path: "base_path".to_string(),
             }),
+            trace_context: None,
             transport: get_test_transport(),
-        };
+            stats: RwLockSync::new(None),
+        });
+        assert_eq!(cube_scan_stats(&scan_node), vec![None]);
 
         let stream = scan_node.execute(0).await.unwrap();
         let batches = common::collect(stream).await.unwrap();
@@ -563,6 +669,10 @@ mod tests {
                 ],
             )
             .unwrap()
-        )
+        );
+        assert_eq!(
+            cube_scan_stats(&scan_node)[0].map(|stats| stats.rows),
+            Some(3)
+        );
     }
 }
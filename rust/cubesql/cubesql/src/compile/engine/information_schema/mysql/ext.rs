@@ -8,9 +8,10 @@ pub trait CubeColumnMySqlExt {
 impl CubeColumnMySqlExt for CubeColumn {
     fn get_data_type(&self) -> String {
         match self.get_column_type() {
-            ColumnType::Timestamp => "datetime".to_string(),
+            ColumnType::Timestamp | ColumnType::Timestamptz => "datetime".to_string(),
             ColumnType::Int64 => "int".to_string(),
             ColumnType::Double => "numeric".to_string(),
+            ColumnType::Decimal(_, _) => "decimal".to_string(),
             ColumnType::Blob => "boolean".to_string(),
             _ => "varchar".to_string(),
         }
@@ -18,9 +19,10 @@ impl CubeColumnMySqlExt for CubeColumn {
 
     fn get_mysql_column_type(&self) -> String {
         match self.get_column_type() {
-            ColumnType::Timestamp => "datetime".to_string(),
+            ColumnType::Timestamp | ColumnType::Timestamptz => "datetime".to_string(),
             ColumnType::Int64 => "int".to_string(),
             ColumnType::Double => "numeric".to_string(),
+            ColumnType::Decimal(precision, scale) => format!("decimal({},{})", precision, scale),
             ColumnType::Blob => "boolean".to_string(),
             _ => "varchar(255)".to_string(),
         }
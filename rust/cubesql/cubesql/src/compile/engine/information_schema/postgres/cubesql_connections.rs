@@ -0,0 +1,138 @@
+use std::{any::Any, collections::BTreeSet, sync::Arc};
+
+use async_trait::async_trait;
+
+use datafusion::{
+    arrow::{
+        array::{Array, StringBuilder, UInt32Builder},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::sql::{session::DatabaseProtocol, SessionManager};
+
+struct PgCatalogCubesqlConnectionsBuilder {
+    usename: StringBuilder,
+    connections: UInt32Builder,
+    connection_limit: UInt32Builder,
+}
+
+impl PgCatalogCubesqlConnectionsBuilder {
+    fn new() -> Self {
+        let capacity = 10;
+
+        Self {
+            usename: StringBuilder::new(capacity),
+            connections: UInt32Builder::new(capacity),
+            connection_limit: UInt32Builder::new(capacity),
+        }
+    }
+
+    fn add_row(&mut self, usename: Option<&str>, connections: usize, limit: Option<usize>) {
+        if let Some(usename) = usename {
+            self.usename.append_value(usename).unwrap();
+        } else {
+            self.usename.append_null().unwrap();
+        }
+
+        self.connections.append_value(connections as u32).unwrap();
+
+        if let Some(limit) = limit {
+            self.connection_limit.append_value(limit as u32).unwrap();
+        } else {
+            self.connection_limit.append_null().unwrap();
+        }
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+        columns.push(Arc::new(self.usename.finish()));
+        columns.push(Arc::new(self.connections.finish()));
+        columns.push(Arc::new(self.connection_limit.finish()));
+
+        columns
+    }
+}
+
+/// Current numbers of PostgreSQL connections and their limits. The first row (without a user)
+/// is the total and max_connections, other rows are users with connections or quotas.
+pub struct PgCatalogCubesqlConnectionsProvider {
+    sessions: Arc<SessionManager>,
+}
+
+impl PgCatalogCubesqlConnectionsProvider {
+    pub fn new(sessions: Arc<SessionManager>) -> Self {
+        Self { sessions }
+    }
+}
+
+#[async_trait]
+impl TableProvider for PgCatalogCubesqlConnectionsProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("usename", DataType::Utf8, true),
+            Field::new("connections", DataType::UInt32, false),
+            Field::new("connection_limit", DataType::UInt32, true),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _batch_size: usize,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let configuration = &self.sessions.server.configuration;
+        let counts = self
+            .sessions
+            .user_session_counts(&DatabaseProtocol::PostgreSQL);
+
+        let mut builder = PgCatalogCubesqlConnectionsBuilder::new();
+        builder.add_row(
+            None,
+            self.sessions.count_sessions(&DatabaseProtocol::PostgreSQL),
+            configuration.max_connections,
+        );
+
+        let users = counts
+            .keys()
+            .chain(configuration.user_connection_limits.keys())
+            .collect::<BTreeSet<_>>();
+        for user in users {
+            builder.add_row(
+                Some(user),
+                counts.get(user).cloned().unwrap_or(0),
+                configuration.user_connection_limits.get(user).cloned(),
+            );
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
@@ -16,8 +16,9 @@ impl CubeColumnPostgresExt for CubeColumn {
     fn get_data_type(&self) -> String {
         match self.get_column_type() {
             ColumnType::Timestamp => "timestamp without time zone".to_string(),
+            ColumnType::Timestamptz => "timestamp with time zone".to_string(),
             ColumnType::Int64 => "bigint".to_string(),
-            ColumnType::Double => "numeric".to_string(),
+            ColumnType::Double | ColumnType::Decimal(_, _) => "numeric".to_string(),
             ColumnType::Blob => "boolean".to_string(),
             _ => "text".to_string(),
         }
@@ -26,8 +27,9 @@ impl CubeColumnPostgresExt for CubeColumn {
     fn get_udt_name(&self) -> String {
         match self.get_column_type() {
             ColumnType::Timestamp => "timestamp".to_string(),
+            ColumnType::Timestamptz => "timestamptz".to_string(),
             ColumnType::Int64 => "int8".to_string(),
-            ColumnType::Double => "numeric".to_string(),
+            ColumnType::Double | ColumnType::Decimal(_, _) => "numeric".to_string(),
             ColumnType::Blob => "bool".to_string(),
             _ => "text".to_string(),
         }
@@ -48,6 +50,7 @@ impl CubeColumnPostgresExt for CubeColumn {
     fn get_numeric_precision(&self) -> Option<u32> {
         match self.get_column_type() {
             ColumnType::Int64 => Some(64),
+            ColumnType::Decimal(precision, _) => Some(precision as u32),
             _ => None,
         }
     }
@@ -55,7 +58,7 @@ impl CubeColumnPostgresExt for CubeColumn {
     fn numeric_precision_radix(&self) -> Option<u32> {
         match self.get_column_type() {
             ColumnType::Int64 => Some(2),
-            ColumnType::Double => Some(10),
+            ColumnType::Double | ColumnType::Decimal(_, _) => Some(10),
             _ => None,
         }
     }
@@ -63,13 +66,14 @@ impl CubeColumnPostgresExt for CubeColumn {
     fn numeric_scale(&self) -> Option<u32> {
         match self.get_column_type() {
             ColumnType::Int64 => Some(0),
+            ColumnType::Decimal(_, scale) => Some(scale as u32),
             _ => None,
         }
     }
 
     fn datetime_precision(&self) -> Option<u32> {
         match self.get_column_type() {
-            ColumnType::Timestamp => Some(6),
+            ColumnType::Timestamp | ColumnType::Timestamptz => Some(6),
             _ => None,
         }
     }
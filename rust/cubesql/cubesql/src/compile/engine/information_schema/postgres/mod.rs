@@ -3,13 +3,21 @@ pub mod ext;
 // information schema
 pub mod tables;
 // pg_catalog
+mod cubesql_connections;
 mod pg_namespace;
 mod pg_range;
+mod pg_settings;
+mod pg_stat_activity;
+mod pg_stat_statements;
 mod pg_tables;
 mod pg_type;
 
 use super::utils;
+pub use cubesql_connections::*;
 pub use pg_namespace::*;
 pub use pg_range::*;
+pub use pg_settings::*;
+pub use pg_stat_activity::*;
+pub use pg_stat_statements::*;
 pub use pg_tables::*;
 pub use pg_type::*;
@@ -0,0 +1,199 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+
+use datafusion::{
+    arrow::{
+        array::{Array, BooleanBuilder, Int32Builder, StringBuilder},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::sql::{postgres::settings::SettingInfo, SessionState};
+
+struct PgCatalogSettingsBuilder {
+    name: StringBuilder,
+    setting: StringBuilder,
+    unit: StringBuilder,
+    category: StringBuilder,
+    short_desc: StringBuilder,
+    extra_desc: StringBuilder,
+    context: StringBuilder,
+    vartype: StringBuilder,
+    source: StringBuilder,
+    min_val: StringBuilder,
+    max_val: StringBuilder,
+    enumvals: StringBuilder,
+    boot_val: StringBuilder,
+    reset_val: StringBuilder,
+    sourcefile: StringBuilder,
+    sourceline: Int32Builder,
+    pending_restart: BooleanBuilder,
+}
+
+impl PgCatalogSettingsBuilder {
+    fn new(capacity: usize) -> Self {
+        Self {
+            name: StringBuilder::new(capacity),
+            setting: StringBuilder::new(capacity),
+            unit: StringBuilder::new(capacity),
+            category: StringBuilder::new(capacity),
+            short_desc: StringBuilder::new(capacity),
+            extra_desc: StringBuilder::new(capacity),
+            context: StringBuilder::new(capacity),
+            vartype: StringBuilder::new(capacity),
+            source: StringBuilder::new(capacity),
+            min_val: StringBuilder::new(capacity),
+            max_val: StringBuilder::new(capacity),
+            enumvals: StringBuilder::new(capacity),
+            boot_val: StringBuilder::new(capacity),
+            reset_val: StringBuilder::new(capacity),
+            sourcefile: StringBuilder::new(capacity),
+            sourceline: Int32Builder::new(capacity),
+            pending_restart: BooleanBuilder::new(capacity),
+        }
+    }
+
+    fn append_option(builder: &mut StringBuilder, value: Option<String>) {
+        match value {
+            Some(value) => builder.append_value(value).unwrap(),
+            None => builder.append_null().unwrap(),
+        }
+    }
+
+    fn add_row(&mut self, info: SettingInfo) {
+        let definition = info.definition;
+        let range = info.range();
+
+        self.name.append_value(&info.name).unwrap();
+        self.setting.append_value(&info.setting).unwrap();
+        Self::append_option(
+            &mut self.unit,
+            definition.and_then(|definition| definition.unit.map(str::to_string)),
+        );
+        self.category.append_value(info.category()).unwrap();
+        self.short_desc.append_value(info.description()).unwrap();
+        self.extra_desc.append_null().unwrap();
+        self.context.append_value(info.context()).unwrap();
+        self.vartype.append_value(info.vartype()).unwrap();
+        self.source.append_value(info.source.as_str()).unwrap();
+        Self::append_option(&mut self.min_val, range.map(|(min, _)| min.to_string()));
+        Self::append_option(&mut self.max_val, range.map(|(_, max)| max.to_string()));
+        // Arrays are shown in the text representation
+        Self::append_option(
+            &mut self.enumvals,
+            info.enum_values()
+                .map(|values| format!("{{{}}}", values.join(","))),
+        );
+        Self::append_option(
+            &mut self.boot_val,
+            definition.map(|definition| definition.default.to_string()),
+        );
+        self.reset_val.append_value(&info.reset_value).unwrap();
+        self.sourcefile.append_null().unwrap();
+        self.sourceline.append_null().unwrap();
+        self.pending_restart.append_value(false).unwrap();
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+        columns.push(Arc::new(self.name.finish()));
+        columns.push(Arc::new(self.setting.finish()));
+        columns.push(Arc::new(self.unit.finish()));
+        columns.push(Arc::new(self.category.finish()));
+        columns.push(Arc::new(self.short_desc.finish()));
+        columns.push(Arc::new(self.extra_desc.finish()));
+        columns.push(Arc::new(self.context.finish()));
+        columns.push(Arc::new(self.vartype.finish()));
+        columns.push(Arc::new(self.source.finish()));
+        columns.push(Arc::new(self.min_val.finish()));
+        columns.push(Arc::new(self.max_val.finish()));
+        columns.push(Arc::new(self.enumvals.finish()));
+        columns.push(Arc::new(self.boot_val.finish()));
+        columns.push(Arc::new(self.reset_val.finish()));
+        columns.push(Arc::new(self.sourcefile.finish()));
+        columns.push(Arc::new(self.sourceline.finish()));
+        columns.push(Arc::new(self.pending_restart.finish()));
+
+        columns
+    }
+}
+
+/// Run-time parameters of the current session with their values
+pub struct PgCatalogSettingsProvider {
+    state: Arc<SessionState>,
+}
+
+impl PgCatalogSettingsProvider {
+    pub fn new(state: Arc<SessionState>) -> Self {
+        Self { state }
+    }
+}
+
+#[async_trait]
+impl TableProvider for PgCatalogSettingsProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("setting", DataType::Utf8, false),
+            Field::new("unit", DataType::Utf8, true),
+            Field::new("category", DataType::Utf8, false),
+            Field::new("short_desc", DataType::Utf8, false),
+            Field::new("extra_desc", DataType::Utf8, true),
+            Field::new("context", DataType::Utf8, false),
+            Field::new("vartype", DataType::Utf8, false),
+            Field::new("source", DataType::Utf8, false),
+            Field::new("min_val", DataType::Utf8, true),
+            Field::new("max_val", DataType::Utf8, true),
+            Field::new("enumvals", DataType::Utf8, true),
+            Field::new("boot_val", DataType::Utf8, true),
+            Field::new("reset_val", DataType::Utf8, false),
+            Field::new("sourcefile", DataType::Utf8, true),
+            Field::new("sourceline", DataType::Int32, true),
+            Field::new("pending_restart", DataType::Boolean, false),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _batch_size: usize,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let settings = self.state.settings().all();
+
+        let mut builder = PgCatalogSettingsBuilder::new(settings.len());
+        for info in settings {
+            builder.add_row(info);
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
@@ -0,0 +1,263 @@
+use std::{any::Any, net::SocketAddr, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use datafusion::{
+    arrow::{
+        array::{
+            Array, Int32Builder, Int64Builder, StringBuilder, TimestampNanosecondBuilder,
+            UInt32Builder,
+        },
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::sql::{
+    session::{ActivityState, DatabaseProtocol},
+    SessionManager, SessionProcessList,
+};
+
+struct PgCatalogStatActivityBuilder {
+    datid: UInt32Builder,
+    datname: StringBuilder,
+    pid: Int32Builder,
+    leader_pid: Int32Builder,
+    usesysid: UInt32Builder,
+    usename: StringBuilder,
+    application_name: StringBuilder,
+    client_addr: StringBuilder,
+    client_hostname: StringBuilder,
+    client_port: Int32Builder,
+    backend_start: TimestampNanosecondBuilder,
+    xact_start: TimestampNanosecondBuilder,
+    query_start: TimestampNanosecondBuilder,
+    state_change: TimestampNanosecondBuilder,
+    wait_event_type: StringBuilder,
+    wait_event: StringBuilder,
+    state: StringBuilder,
+    backend_xid: UInt32Builder,
+    backend_xmin: UInt32Builder,
+    query_id: Int64Builder,
+    query: StringBuilder,
+    backend_type: StringBuilder,
+}
+
+fn append_string(builder: &mut StringBuilder, value: Option<&str>) {
+    match value {
+        Some(value) => builder.append_value(value).unwrap(),
+        None => builder.append_null().unwrap(),
+    }
+}
+
+fn append_timestamp(builder: &mut TimestampNanosecondBuilder, value: Option<DateTime<Utc>>) {
+    match value {
+        Some(value) => builder.append_value(value.timestamp_nanos()).unwrap(),
+        None => builder.append_null().unwrap(),
+    }
+}
+
+impl PgCatalogStatActivityBuilder {
+    fn new() -> Self {
+        let capacity = 10;
+
+        Self {
+            datid: UInt32Builder::new(capacity),
+            datname: StringBuilder::new(capacity),
+            pid: Int32Builder::new(capacity),
+            leader_pid: Int32Builder::new(capacity),
+            usesysid: UInt32Builder::new(capacity),
+            usename: StringBuilder::new(capacity),
+            application_name: StringBuilder::new(capacity),
+            client_addr: StringBuilder::new(capacity),
+            client_hostname: StringBuilder::new(capacity),
+            client_port: Int32Builder::new(capacity),
+            backend_start: TimestampNanosecondBuilder::new(capacity),
+            xact_start: TimestampNanosecondBuilder::new(capacity),
+            query_start: TimestampNanosecondBuilder::new(capacity),
+            state_change: TimestampNanosecondBuilder::new(capacity),
+            wait_event_type: StringBuilder::new(capacity),
+            wait_event: StringBuilder::new(capacity),
+            state: StringBuilder::new(capacity),
+            backend_xid: UInt32Builder::new(capacity),
+            backend_xmin: UInt32Builder::new(capacity),
+            query_id: Int64Builder::new(capacity),
+            query: StringBuilder::new(capacity),
+            backend_type: StringBuilder::new(capacity),
+        }
+    }
+
+    fn add_row(&mut self, process: SessionProcessList) {
+        let activity = process.activity;
+
+        self.datid.append_null().unwrap();
+        append_string(&mut self.datname, process.database.as_deref());
+        self.pid.append_value(process.id as i32).unwrap();
+        self.leader_pid.append_null().unwrap();
+        self.usesysid.append_null().unwrap();
+        append_string(&mut self.usename, process.user.as_deref());
+        append_string(
+            &mut self.application_name,
+            Some(activity.application_name.as_deref().unwrap_or("")),
+        );
+
+        // Connections over the unix domain socket have no address, their port is -1
+        match process.host.parse::<SocketAddr>() {
+            Ok(addr) => {
+                append_string(&mut self.client_addr, Some(&addr.ip().to_string()));
+                self.client_port.append_value(addr.port() as i32).unwrap();
+            }
+            Err(_) => {
+                self.client_addr.append_null().unwrap();
+                self.client_port.append_value(-1).unwrap();
+            }
+        }
+        self.client_hostname.append_null().unwrap();
+
+        append_timestamp(&mut self.backend_start, Some(activity.backend_start));
+        append_timestamp(&mut self.xact_start, activity.xact_start);
+        append_timestamp(&mut self.query_start, activity.query_start);
+        append_timestamp(&mut self.state_change, Some(activity.state_change));
+
+        // Idle sessions are waiting for the next message of the client
+        let wait_event = match activity.state {
+            ActivityState::Active => None,
+            _ => Some(("Client", "ClientRead")),
+        };
+        append_string(
+            &mut self.wait_event_type,
+            wait_event.map(|(event_type, _)| event_type),
+        );
+        append_string(&mut self.wait_event, wait_event.map(|(_, event)| event));
+        append_string(&mut self.state, Some(activity.state.as_str()));
+
+        self.backend_xid.append_null().unwrap();
+        self.backend_xmin.append_null().unwrap();
+        self.query_id.append_null().unwrap();
+        append_string(
+            &mut self.query,
+            Some(activity.query.as_deref().unwrap_or("")),
+        );
+        append_string(&mut self.backend_type, Some("client backend"));
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+        columns.push(Arc::new(self.datid.finish()));
+        columns.push(Arc::new(self.datname.finish()));
+        columns.push(Arc::new(self.pid.finish()));
+        columns.push(Arc::new(self.leader_pid.finish()));
+        columns.push(Arc::new(self.usesysid.finish()));
+        columns.push(Arc::new(self.usename.finish()));
+        columns.push(Arc::new(self.application_name.finish()));
+        columns.push(Arc::new(self.client_addr.finish()));
+        columns.push(Arc::new(self.client_hostname.finish()));
+        columns.push(Arc::new(self.client_port.finish()));
+        columns.push(Arc::new(self.backend_start.finish()));
+        columns.push(Arc::new(self.xact_start.finish()));
+        columns.push(Arc::new(self.query_start.finish()));
+        columns.push(Arc::new(self.state_change.finish()));
+        columns.push(Arc::new(self.wait_event_type.finish()));
+        columns.push(Arc::new(self.wait_event.finish()));
+        columns.push(Arc::new(self.state.finish()));
+        columns.push(Arc::new(self.backend_xid.finish()));
+        columns.push(Arc::new(self.backend_xmin.finish()));
+        columns.push(Arc::new(self.query_id.finish()));
+        columns.push(Arc::new(self.query.finish()));
+        columns.push(Arc::new(self.backend_type.finish()));
+
+        columns
+    }
+}
+
+/// One row per PostgreSQL connection with its current activity, as in PostgreSQL
+pub struct PgCatalogStatActivityProvider {
+    sessions: Arc<SessionManager>,
+}
+
+impl PgCatalogStatActivityProvider {
+    pub fn new(sessions: Arc<SessionManager>) -> Self {
+        Self { sessions }
+    }
+}
+
+#[async_trait]
+impl TableProvider for PgCatalogStatActivityProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        let timestamp = DataType::Timestamp(TimeUnit::Nanosecond, None);
+
+        Arc::new(Schema::new(vec![
+            Field::new("datid", DataType::UInt32, true),
+            Field::new("datname", DataType::Utf8, true),
+            Field::new("pid", DataType::Int32, false),
+            Field::new("leader_pid", DataType::Int32, true),
+            Field::new("usesysid", DataType::UInt32, true),
+            Field::new("usename", DataType::Utf8, true),
+            Field::new("application_name", DataType::Utf8, false),
+            Field::new("client_addr", DataType::Utf8, true),
+            Field::new("client_hostname", DataType::Utf8, true),
+            Field::new("client_port", DataType::Int32, false),
+            Field::new("backend_start", timestamp.clone(), false),
+            Field::new("xact_start", timestamp.clone(), true),
+            Field::new("query_start", timestamp.clone(), true),
+            Field::new("state_change", timestamp, false),
+            Field::new("wait_event_type", DataType::Utf8, true),
+            Field::new("wait_event", DataType::Utf8, true),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("backend_xid", DataType::UInt32, true),
+            Field::new("backend_xmin", DataType::UInt32, true),
+            Field::new("query_id", DataType::Int64, true),
+            Field::new("query", DataType::Utf8, false),
+            Field::new("backend_type", DataType::Utf8, false),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _batch_size: usize,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let mut processes = self
+            .sessions
+            .process_list()
+            .into_iter()
+            .filter(|process| process.protocol == DatabaseProtocol::PostgreSQL)
+            .collect::<Vec<_>>();
+        processes.sort_by_key(|process| process.id);
+
+        let mut builder = PgCatalogStatActivityBuilder::new();
+        for process in processes {
+            builder.add_row(process);
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
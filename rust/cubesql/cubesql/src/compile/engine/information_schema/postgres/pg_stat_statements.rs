@@ -0,0 +1,172 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+
+use datafusion::{
+    arrow::{
+        array::{
+            Array, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder, UInt32Builder,
+        },
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+
+use crate::sql::{query_stats::QueryStatistic, SessionManager};
+
+struct PgCatalogStatStatementsBuilder {
+    userid: UInt32Builder,
+    usename: StringBuilder,
+    dbid: UInt32Builder,
+    toplevel: BooleanBuilder,
+    queryid: Int64Builder,
+    query: StringBuilder,
+    calls: Int64Builder,
+    total_exec_time: Float64Builder,
+    min_exec_time: Float64Builder,
+    max_exec_time: Float64Builder,
+    mean_exec_time: Float64Builder,
+    stddev_exec_time: Float64Builder,
+    rows: Int64Builder,
+}
+
+impl PgCatalogStatStatementsBuilder {
+    fn new(capacity: usize) -> Self {
+        Self {
+            userid: UInt32Builder::new(capacity),
+            usename: StringBuilder::new(capacity),
+            dbid: UInt32Builder::new(capacity),
+            toplevel: BooleanBuilder::new(capacity),
+            queryid: Int64Builder::new(capacity),
+            query: StringBuilder::new(capacity),
+            calls: Int64Builder::new(capacity),
+            total_exec_time: Float64Builder::new(capacity),
+            min_exec_time: Float64Builder::new(capacity),
+            max_exec_time: Float64Builder::new(capacity),
+            mean_exec_time: Float64Builder::new(capacity),
+            stddev_exec_time: Float64Builder::new(capacity),
+            rows: Int64Builder::new(capacity),
+        }
+    }
+
+    fn add_row(&mut self, statistic: QueryStatistic) {
+        self.userid.append_null().unwrap();
+        match &statistic.user {
+            Some(user) => self.usename.append_value(user).unwrap(),
+            None => self.usename.append_null().unwrap(),
+        }
+        self.dbid.append_null().unwrap();
+        self.toplevel.append_value(true).unwrap();
+        self.queryid.append_value(statistic.query_id).unwrap();
+        self.query.append_value(&statistic.query).unwrap();
+        self.calls.append_value(statistic.calls as i64).unwrap();
+        self.total_exec_time
+            .append_value(statistic.total_time)
+            .unwrap();
+        self.min_exec_time.append_value(statistic.min_time).unwrap();
+        self.max_exec_time.append_value(statistic.max_time).unwrap();
+        self.mean_exec_time
+            .append_value(statistic.mean_time())
+            .unwrap();
+        self.stddev_exec_time
+            .append_value(statistic.stddev_time())
+            .unwrap();
+        self.rows.append_value(statistic.rows as i64).unwrap();
+    }
+
+    fn finish(mut self) -> Vec<Arc<dyn Array>> {
+        let mut columns: Vec<Arc<dyn Array>> = vec![];
+        columns.push(Arc::new(self.userid.finish()));
+        columns.push(Arc::new(self.usename.finish()));
+        columns.push(Arc::new(self.dbid.finish()));
+        columns.push(Arc::new(self.toplevel.finish()));
+        columns.push(Arc::new(self.queryid.finish()));
+        columns.push(Arc::new(self.query.finish()));
+        columns.push(Arc::new(self.calls.finish()));
+        columns.push(Arc::new(self.total_exec_time.finish()));
+        columns.push(Arc::new(self.min_exec_time.finish()));
+        columns.push(Arc::new(self.max_exec_time.finish()));
+        columns.push(Arc::new(self.mean_exec_time.finish()));
+        columns.push(Arc::new(self.stddev_exec_time.finish()));
+        columns.push(Arc::new(self.rows.finish()));
+
+        columns
+    }
+}
+
+/// Statistics of normalized statements executed by all sessions, as the view of the
+/// pg_stat_statements extension. Times are in milliseconds.
+pub struct PgCatalogStatStatementsProvider {
+    sessions: Arc<SessionManager>,
+}
+
+impl PgCatalogStatStatementsProvider {
+    pub fn new(sessions: Arc<SessionManager>) -> Self {
+        Self { sessions }
+    }
+}
+
+#[async_trait]
+impl TableProvider for PgCatalogStatStatementsProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("userid", DataType::UInt32, true),
+            // Users are not identified by OIDs
+            Field::new("usename", DataType::Utf8, true),
+            Field::new("dbid", DataType::UInt32, true),
+            Field::new("toplevel", DataType::Boolean, false),
+            Field::new("queryid", DataType::Int64, false),
+            Field::new("query", DataType::Utf8, false),
+            Field::new("calls", DataType::Int64, false),
+            Field::new("total_exec_time", DataType::Float64, false),
+            Field::new("min_exec_time", DataType::Float64, false),
+            Field::new("max_exec_time", DataType::Float64, false),
+            Field::new("mean_exec_time", DataType::Float64, false),
+            Field::new("stddev_exec_time", DataType::Float64, false),
+            Field::new("rows", DataType::Int64, false),
+        ]))
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _batch_size: usize,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let mut statistics = self.sessions.query_stats.all();
+        statistics.sort_by(|a, b| (&a.user, &a.query).cmp(&(&b.user, &b.query)));
+
+        let mut builder = PgCatalogStatStatementsBuilder::new(statistics.len());
+        for statistic in statistics {
+            builder.add_row(statistic);
+        }
+
+        let batch = RecordBatch::try_new(self.schema(), builder.finish())?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
@@ -14,6 +14,8 @@ use datafusion::{
     physical_plan::{memory::MemoryExec, ExecutionPlan},
 };
 
+use crate::sql::postgres::pg_type::PgTypeId;
+
 struct PgType {
     oid: u32,
     typname: &'static str,
@@ -42,8 +44,8 @@ struct PgCatalogTypeBuilder {
     typdelim: StringBuilder,
     typrelid: StringBuilder,
     typsubscript: StringBuilder,
-    typelem: StringBuilder,
-    typarray: StringBuilder,
+    typelem: UInt32Builder,
+    typarray: UInt32Builder,
     typinput: StringBuilder,
     typoutput: StringBuilder,
     typreceive: StringBuilder,
@@ -82,8 +84,8 @@ impl PgCatalogTypeBuilder {
             typdelim: StringBuilder::new(capacity),
             typrelid: StringBuilder::new(capacity),
             typsubscript: StringBuilder::new(capacity),
-            typelem: StringBuilder::new(capacity),
-            typarray: StringBuilder::new(capacity),
+            typelem: UInt32Builder::new(capacity),
+            typarray: UInt32Builder::new(capacity),
             typinput: StringBuilder::new(capacity),
             typoutput: StringBuilder::new(capacity),
             typreceive: StringBuilder::new(capacity),
@@ -119,8 +121,14 @@ impl PgCatalogTypeBuilder {
         self.typdelim.append_null().unwrap();
         self.typrelid.append_null().unwrap();
         self.typsubscript.append_null().unwrap();
-        self.typelem.append_null().unwrap();
-        self.typarray.append_null().unwrap();
+        // Element and array types are linked by OIDs, 0 when there is none
+        let pg_type = PgTypeId::from_oid(typ.oid);
+        self.typelem
+            .append_value(pg_type.and_then(|t| t.element()).map_or(0, |t| t.to_oid()))
+            .unwrap();
+        self.typarray
+            .append_value(pg_type.and_then(|t| t.array()).map_or(0, |t| t.to_oid()))
+            .unwrap();
         self.typinput.append_null().unwrap();
         self.typoutput.append_null().unwrap();
         self.typreceive.append_null().unwrap();
@@ -246,6 +254,18 @@ impl PgCatalogTypeProvider {
             typisprefered: true,
             typisdefined: true,
         });
+        builder.add_type(&PgType {
+            oid: 114,
+            typname: "json",
+            typnamespace: 11,
+            typowner: 10,
+            typlen: -1,
+            typbyval: false,
+            typtype: "b",
+            typcategory: "U",
+            typisprefered: false,
+            typisdefined: true,
+        });
         builder.add_type(&PgType {
             oid: 1082,
             typname: "date",
@@ -294,6 +314,18 @@ impl PgCatalogTypeProvider {
             typisprefered: false,
             typisdefined: true,
         });
+        builder.add_type(&PgType {
+            oid: 3802,
+            typname: "jsonb",
+            typnamespace: 11,
+            typowner: 10,
+            typlen: -1,
+            typbyval: false,
+            typtype: "b",
+            typcategory: "U",
+            typisprefered: false,
+            typisdefined: true,
+        });
         builder.add_type(&PgType {
             oid: 3904,
             typname: "int4range",
@@ -426,6 +458,36 @@ impl PgCatalogTypeProvider {
             typisprefered: false,
             typisdefined: true,
         });
+        // Arrays of the base types above
+        for element in [
+            PgTypeId::Bool,
+            PgTypeId::Int8,
+            PgTypeId::Int2,
+            PgTypeId::Int4,
+            PgTypeId::Text,
+            PgTypeId::Json,
+            PgTypeId::Date,
+            PgTypeId::Timestamp,
+            PgTypeId::Timestamptz,
+            PgTypeId::Numeric,
+            PgTypeId::Jsonb,
+        ]
+        .iter()
+        {
+            let array = element.array().unwrap();
+            builder.add_type(&PgType {
+                oid: array.to_oid(),
+                typname: array.typname(),
+                typnamespace: 11,
+                typowner: 10,
+                typlen: -1,
+                typbyval: false,
+                typtype: "b",
+                typcategory: "A",
+                typisprefered: false,
+                typisdefined: true,
+            });
+        }
 
         Self {
             data: Arc::new(builder.finish()),
@@ -459,8 +521,8 @@ impl TableProvider for PgCatalogTypeProvider {
             Field::new("typdelim", DataType::Utf8, true),
             Field::new("typrelid", DataType::Utf8, true),
             Field::new("typsubscript", DataType::Utf8, true),
-            Field::new("typelem", DataType::Utf8, true),
-            Field::new("typarray", DataType::Utf8, true),
+            Field::new("typelem", DataType::UInt32, false),
+            Field::new("typarray", DataType::UInt32, false),
             Field::new("typinput", DataType::Utf8, true),
             Field::new("typoutput", DataType::Utf8, true),
             Field::new("typreceive", DataType::Utf8, true),
@@ -2,4 +2,5 @@ pub mod context;
 pub mod df;
 pub mod information_schema;
 pub mod provider;
+pub mod table_functions;
 pub mod udf;
@@ -23,11 +23,15 @@ use super::information_schema::mysql::{
     tables::InfoSchemaTableProvider as MySqlSchemaTableProvider,
     variables::PerfSchemaVariablesProvider as MySqlPerfSchemaVariablesProvider,
 };
+use super::table_functions::TableFunctionProvider;
+use super::udf::{create_numeric_cast_udf, parse_numeric_cast_name};
 
 use super::information_schema::postgres::{
     columns::InfoSchemaColumnsProvider as PostgresSchemaColumnsProvider,
-    tables::InfoSchemaTableProvider as PostgresSchemaTableProvider, PgCatalogNamespaceProvider,
-    PgCatalogRangeProvider, PgCatalogTableProvider, PgCatalogTypeProvider,
+    tables::InfoSchemaTableProvider as PostgresSchemaTableProvider,
+    PgCatalogCubesqlConnectionsProvider, PgCatalogNamespaceProvider, PgCatalogRangeProvider,
+    PgCatalogSettingsProvider, PgCatalogStatActivityProvider, PgCatalogStatStatementsProvider,
+    PgCatalogTableProvider, PgCatalogTypeProvider,
 };
 use crate::sql::ColumnType;
 use crate::transport::V1CubeMetaExt;
@@ -49,6 +53,8 @@ pub struct CubeContext {
     pub meta: Arc<MetaContext>,
     pub sessions: Arc<SessionManager>,
     pub session_state: Arc<SessionState>,
+    /// Results of table functions of the query, see `plan_table_functions`
+    pub table_functions: Vec<Arc<TableFunctionProvider>>,
 }
 
 impl CubeContext {
@@ -63,17 +69,53 @@ impl CubeContext {
             meta,
             sessions,
             session_state,
+            table_functions: vec![],
         }
     }
 
+    pub fn with_table_functions(
+        mut self,
+        table_functions: Vec<Arc<TableFunctionProvider>>,
+    ) -> Self {
+        self.table_functions = table_functions;
+        self
+    }
+
     pub fn table_name_by_table_provider(
         &self,
         table_provider: Arc<dyn datasource::TableProvider>,
     ) -> Result<String, CubeError> {
+        if let Some(t) = table_provider
+            .as_any()
+            .downcast_ref::<TableFunctionProvider>()
+        {
+            return Ok(t.table_name().to_string());
+        }
+
         self.session_state
             .protocol
             .table_name_by_table_provider(table_provider)
     }
+
+    /// Schema of the unqualified table, schemas of search_path are searched in order
+    /// as in PostgreSQL. Cubes are in `db`, it's returned for them.
+    pub fn search_table_schema(&self, table: &str) -> Option<String> {
+        let protocol = &self.session_state.protocol;
+        self.session_state
+            .search_path()
+            .into_iter()
+            .find_map(|schema| {
+                if protocol.is_cube_schema(&schema) {
+                    self.meta
+                        .find_cube_with_name(table.to_string())
+                        .map(|_| "db".to_string())
+                } else {
+                    protocol
+                        .get_provider(self, format!("{}.{}", schema, table))
+                        .map(|_| schema)
+                }
+            })
+    }
 }
 
 impl ContextProvider for CubeContext {
@@ -81,9 +123,19 @@ impl ContextProvider for CubeContext {
         &self,
         name: datafusion::catalog::TableReference,
     ) -> Option<std::sync::Arc<dyn datasource::TableProvider>> {
+        if let datafusion::catalog::TableReference::Bare { table } = &name {
+            if let Some(t) = self
+                .table_functions
+                .iter()
+                .find(|t| t.table_name() == *table)
+            {
+                return Some(t.clone());
+            }
+        }
+
         let table_path = match name {
             datafusion::catalog::TableReference::Partial { schema, table, .. } => {
-                if schema == "db" {
+                if self.session_state.protocol.is_cube_schema(schema) {
                     Some(table.to_string())
                 } else {
                     Some(format!("{}.{}", schema, table))
@@ -94,7 +146,15 @@ impl ContextProvider for CubeContext {
                 schema,
                 table,
             } => Some(format!("{}.{}.{}", catalog, schema, table)),
-            datafusion::catalog::TableReference::Bare { table } => Some(table.to_string()),
+            datafusion::catalog::TableReference::Bare { table } => {
+                match self.session_state.protocol {
+                    DatabaseProtocol::PostgreSQL => match self.search_table_schema(table) {
+                        Some(schema) if schema != "db" => Some(format!("{}.{}", schema, table)),
+                        _ => Some(table.to_string()),
+                    },
+                    DatabaseProtocol::MySQL => Some(table.to_string()),
+                }
+            }
         };
 
         if let Some(tp) = table_path {
@@ -105,7 +165,13 @@ impl ContextProvider for CubeContext {
     }
 
     fn get_function_meta(&self, name: &str) -> Option<Arc<ScalarUDF>> {
-        self.state.scalar_functions.get(name).cloned()
+        if let Some(fun) = self.state.scalar_functions.get(name) {
+            return Some(fun.clone());
+        }
+
+        // Casts to decimals have a function for every type, see `rewrite_decimals`
+        parse_numeric_cast_name(name)
+            .map(|(precision, scale)| Arc::new(create_numeric_cast_udf(precision, scale)))
     }
 
     fn get_aggregate_meta(&self, name: &str) -> Option<Arc<AggregateUDF>> {
@@ -114,6 +180,15 @@ impl ContextProvider for CubeContext {
 }
 
 impl DatabaseProtocol {
+    /// Cubes are in the `db` schema, `public` is its alias for PostgreSQL, as it's the default
+    /// schema of clients
+    pub fn is_cube_schema(&self, schema: &str) -> bool {
+        match self {
+            DatabaseProtocol::MySQL => schema == "db",
+            DatabaseProtocol::PostgreSQL => schema == "db" || schema == "public",
+        }
+    }
+
     fn get_provider(
         &self,
         context: &CubeContext,
@@ -258,6 +333,14 @@ impl DatabaseProtocol {
                 "pg_catalog.pg_namespace".to_string()
             } else if let Some(_) = any.downcast_ref::<PgCatalogRangeProvider>() {
                 "pg_catalog.pg_range".to_string()
+            } else if let Some(_) = any.downcast_ref::<PgCatalogCubesqlConnectionsProvider>() {
+                "pg_catalog.cubesql_connections".to_string()
+            } else if let Some(_) = any.downcast_ref::<PgCatalogStatActivityProvider>() {
+                "pg_catalog.pg_stat_activity".to_string()
+            } else if let Some(_) = any.downcast_ref::<PgCatalogStatStatementsProvider>() {
+                "pg_catalog.pg_stat_statements".to_string()
+            } else if let Some(_) = any.downcast_ref::<PgCatalogSettingsProvider>() {
+                "pg_catalog.pg_settings".to_string()
             } else {
                 return Err(CubeError::internal(format!(
                     "Unknown table provider with schema: {:?}",
@@ -300,6 +383,30 @@ impl DatabaseProtocol {
             return Some(Arc::new(PgCatalogRangeProvider::new()));
         }
 
+        if tp.eq_ignore_ascii_case("pg_catalog.cubesql_connections") {
+            return Some(Arc::new(PgCatalogCubesqlConnectionsProvider::new(
+                context.sessions.clone(),
+            )));
+        }
+
+        if tp.eq_ignore_ascii_case("pg_catalog.pg_stat_activity") {
+            return Some(Arc::new(PgCatalogStatActivityProvider::new(
+                context.sessions.clone(),
+            )));
+        }
+
+        if tp.eq_ignore_ascii_case("pg_catalog.pg_stat_statements") {
+            return Some(Arc::new(PgCatalogStatStatementsProvider::new(
+                context.sessions.clone(),
+            )));
+        }
+
+        if tp.eq_ignore_ascii_case("pg_catalog.pg_settings") {
+            return Some(Arc::new(PgCatalogSettingsProvider::new(
+                context.session_state.clone(),
+            )));
+        }
+
         None
     }
 }
@@ -349,6 +456,14 @@ impl TableProvider for CubeTableProvider {
                             ColumnType::Timestamp => {
                                 DataType::Timestamp(TimeUnit::Millisecond, None)
                             }
+                            ColumnType::Timestamptz => {
+                                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".to_string()))
+                            }
+                            ColumnType::Decimal(precision, scale) => {
+                                DataType::Decimal(precision, scale)
+                            }
+                            // Members of cubes are never arrays or intervals
+                            ColumnType::Interval | ColumnType::List(_) => DataType::Utf8,
                         },
                         true,
                     )
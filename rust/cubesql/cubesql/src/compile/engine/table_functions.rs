@@ -0,0 +1,707 @@
+use std::{any::Any, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use datafusion::{
+    arrow::{
+        array::{
+            ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray, TimestampNanosecondArray,
+        },
+        datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+        record_batch::RecordBatch,
+    },
+    datasource::{datasource::TableProviderFilterPushDown, TableProvider, TableType},
+    error::DataFusionError,
+    logical_plan::Expr,
+    physical_plan::{memory::MemoryExec, ExecutionPlan},
+};
+use sqlparser::ast;
+
+use crate::{
+    compile::{CompilationError, CompilationResult},
+    sql::{
+        dataframe::IntervalValue,
+        interval::{add_interval, interval_literal, parse_interval_text},
+        statement::array_elements,
+    },
+};
+
+pub const GENERATE_SERIES: &str = "generate_series";
+pub const UNNEST: &str = "unnest";
+
+const TABLE_FUNCTIONS: &[&str] = &[GENERATE_SERIES, UNNEST];
+
+/// Series are materialized while planning, longer series are rejected
+pub const MAX_SERIES_LENGTH: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SeriesValue {
+    Int64(i64),
+    Timestamp(NaiveDateTime),
+}
+
+fn parse_timestamp(text: &str) -> CompilationResult<NaiveDateTime> {
+    let text = text.trim();
+    for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(text, format) {
+            return Ok(timestamp);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date.and_hms(0, 0, 0));
+    }
+
+    Err(CompilationError::User(format!(
+        "invalid input syntax for type timestamp: \"{}\"",
+        text
+    )))
+}
+
+fn unpack_arg(arg: &ast::FunctionArg) -> &ast::Expr {
+    match arg {
+        ast::FunctionArg::Named { arg, .. } => arg,
+        ast::FunctionArg::Unnamed(expr) => expr,
+    }
+}
+
+fn unsupported_arg(expr: &ast::Expr) -> CompilationError {
+    CompilationError::Unsupported(format!("Unsupported argument of generate_series: {}", expr))
+}
+
+fn series_value(expr: &ast::Expr) -> CompilationResult<SeriesValue> {
+    match expr {
+        ast::Expr::Value(ast::Value::Number(n, _)) => n
+            .parse::<i64>()
+            .map(SeriesValue::Int64)
+            .map_err(|_| unsupported_arg(expr)),
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Minus,
+            expr: value,
+        } => match series_value(value)? {
+            SeriesValue::Int64(n) => Ok(SeriesValue::Int64(-n)),
+            _ => Err(unsupported_arg(expr)),
+        },
+        ast::Expr::Nested(value) => series_value(value),
+        ast::Expr::Value(ast::Value::SingleQuotedString(text)) => {
+            Ok(SeriesValue::Timestamp(parse_timestamp(text)?))
+        }
+        ast::Expr::TypedString { data_type, value } => match data_type {
+            ast::DataType::Date | ast::DataType::Timestamp => {
+                Ok(SeriesValue::Timestamp(parse_timestamp(value)?))
+            }
+            _ => Err(unsupported_arg(expr)),
+        },
+        ast::Expr::Cast {
+            expr: value,
+            data_type,
+        } => match (value.as_ref(), data_type) {
+            (
+                ast::Expr::Value(ast::Value::SingleQuotedString(text)),
+                ast::DataType::Date | ast::DataType::Timestamp,
+            ) => Ok(SeriesValue::Timestamp(parse_timestamp(text)?)),
+            (_, ast::DataType::Int | ast::DataType::BigInt | ast::DataType::SmallInt) => {
+                series_value(value)
+            }
+            _ => Err(unsupported_arg(expr)),
+        },
+        _ => Err(unsupported_arg(expr)),
+    }
+}
+
+/// Steps can also be untyped strings (`'1 day'`)
+fn series_interval(expr: &ast::Expr) -> CompilationResult<IntervalValue> {
+    let interval = match expr {
+        ast::Expr::Value(ast::Value::SingleQuotedString(text)) => {
+            Some(parse_interval_text(text).map_err(|e| CompilationError::User(e.message))?)
+        }
+        expr => interval_literal(expr).map_err(|e| CompilationError::User(e.message))?,
+    };
+
+    interval.ok_or_else(|| unsupported_arg(expr))
+}
+
+fn too_long() -> CompilationError {
+    CompilationError::User(format!(
+        "generate_series can't return more than {} rows",
+        MAX_SERIES_LENGTH
+    ))
+}
+
+fn step_is_zero() -> CompilationError {
+    CompilationError::User("step size cannot equal zero".to_string())
+}
+
+/// Values of `generate_series(start, stop [, step])`, stop is included
+fn generate_series(args: &[ast::FunctionArg]) -> CompilationResult<(DataType, ArrayRef)> {
+    let (start, stop, step) = match args {
+        [start, stop] => (unpack_arg(start), unpack_arg(stop), None),
+        [start, stop, step] => (unpack_arg(start), unpack_arg(stop), Some(unpack_arg(step))),
+        _ => {
+            return Err(CompilationError::User(
+                "generate_series requires 2 or 3 arguments".to_string(),
+            ))
+        }
+    };
+
+    match (series_value(start)?, series_value(stop)?) {
+        (SeriesValue::Int64(start), SeriesValue::Int64(stop)) => {
+            let step = match step {
+                Some(step) => match series_value(step)? {
+                    SeriesValue::Int64(step) => step,
+                    _ => return Err(unsupported_arg(step)),
+                },
+                None => 1,
+            };
+            if step == 0 {
+                return Err(step_is_zero());
+            }
+
+            let mut values = vec![];
+            let mut value = start;
+            while (step > 0 && value <= stop) || (step < 0 && value >= stop) {
+                if values.len() == MAX_SERIES_LENGTH {
+                    return Err(too_long());
+                }
+                values.push(value);
+                value = match value.checked_add(step) {
+                    Some(value) => value,
+                    None => break,
+                };
+            }
+
+            Ok((DataType::Int64, Arc::new(Int64Array::from(values))))
+        }
+        (SeriesValue::Timestamp(start), SeriesValue::Timestamp(stop)) => {
+            let step = match step {
+                Some(step) => series_interval(step)?,
+                None => {
+                    return Err(CompilationError::User(
+                        "generate_series of timestamps requires an interval step".to_string(),
+                    ))
+                }
+            };
+            let forward = match add_interval(&start, &step) {
+                Some(next) if next > start => true,
+                Some(next) if next < start => false,
+                _ => return Err(step_is_zero()),
+            };
+
+            let mut values = vec![];
+            let mut value = Some(start);
+            while let Some(timestamp) = value {
+                if (forward && timestamp > stop) || (!forward && timestamp < stop) {
+                    break;
+                }
+                if values.len() == MAX_SERIES_LENGTH {
+                    return Err(too_long());
+                }
+                values.push(timestamp.timestamp_nanos());
+                value = add_interval(&timestamp, &step);
+            }
+
+            Ok((
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                Arc::new(TimestampNanosecondArray::from(values)),
+            ))
+        }
+        _ => Err(CompilationError::User(
+            "arguments of generate_series must be both integers or timestamps".to_string(),
+        )),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Element {
+    Number(String),
+    Boolean(bool),
+    String(String),
+    Timestamp(NaiveDateTime),
+}
+
+fn unnest_element(expr: &ast::Expr) -> CompilationResult<Option<Element>> {
+    let unsupported =
+        || CompilationError::Unsupported(format!("Unsupported element of unnest: {}", expr));
+
+    match expr {
+        ast::Expr::Value(ast::Value::Null) => Ok(None),
+        ast::Expr::Value(ast::Value::Number(n, _)) => Ok(Some(Element::Number(n.clone()))),
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Minus,
+            expr: value,
+        } => match unnest_element(value)? {
+            Some(Element::Number(n)) => Ok(Some(Element::Number(format!("-{}", n)))),
+            _ => Err(unsupported()),
+        },
+        ast::Expr::Value(ast::Value::Boolean(b)) => Ok(Some(Element::Boolean(*b))),
+        ast::Expr::Value(ast::Value::SingleQuotedString(s)) => Ok(Some(Element::String(s.clone()))),
+        ast::Expr::TypedString {
+            data_type: ast::DataType::Date | ast::DataType::Timestamp,
+            value,
+        } => Ok(Some(Element::Timestamp(parse_timestamp(value)?))),
+        ast::Expr::Nested(value) => unnest_element(value),
+        _ => Err(unsupported()),
+    }
+}
+
+/// Elements of `unnest(array)` with literals: `ARRAY[...]`, array literals and bound array
+/// parameters. Numbers are integers unless one of them has a fraction.
+fn unnest(args: &[ast::FunctionArg]) -> CompilationResult<(DataType, ArrayRef)> {
+    let array = match args {
+        [array] => unpack_arg(array),
+        _ => {
+            return Err(CompilationError::Unsupported(
+                "unnest of multiple arrays is not supported".to_string(),
+            ))
+        }
+    };
+
+    let elements = array_elements(array)
+        .map_err(|e| CompilationError::User(e.message))?
+        .ok_or_else(|| {
+            CompilationError::Unsupported(format!("Unsupported argument of unnest: {}", array))
+        })?
+        .iter()
+        .map(unnest_element)
+        .collect::<CompilationResult<Vec<_>>>()?;
+
+    let mixed =
+        || CompilationError::User("elements of unnest must be of the same type".to_string());
+    let data: ArrayRef = match elements.iter().flatten().next() {
+        None | Some(Element::String(_)) => Arc::new(StringArray::from(
+            elements
+                .iter()
+                .map(|e| match e {
+                    None => Ok(None),
+                    Some(Element::String(s)) => Ok(Some(s.as_str())),
+                    _ => Err(mixed()),
+                })
+                .collect::<CompilationResult<Vec<_>>>()?,
+        )),
+        Some(Element::Boolean(_)) => Arc::new(BooleanArray::from(
+            elements
+                .iter()
+                .map(|e| match e {
+                    None => Ok(None),
+                    Some(Element::Boolean(b)) => Ok(Some(*b)),
+                    _ => Err(mixed()),
+                })
+                .collect::<CompilationResult<Vec<_>>>()?,
+        )),
+        Some(Element::Timestamp(_)) => Arc::new(TimestampNanosecondArray::from(
+            elements
+                .iter()
+                .map(|e| match e {
+                    None => Ok(None),
+                    Some(Element::Timestamp(t)) => Ok(Some(t.timestamp_nanos())),
+                    _ => Err(mixed()),
+                })
+                .collect::<CompilationResult<Vec<_>>>()?,
+        )),
+        Some(Element::Number(_)) => {
+            let numbers = elements
+                .iter()
+                .map(|e| match e {
+                    None => Ok(None),
+                    Some(Element::Number(n)) => Ok(Some(n.as_str())),
+                    _ => Err(mixed()),
+                })
+                .collect::<CompilationResult<Vec<_>>>()?;
+            let integers = numbers
+                .iter()
+                .map(|n| n.map(|n| n.parse::<i64>()).transpose())
+                .collect::<Result<Vec<_>, _>>();
+
+            match integers {
+                Ok(integers) => Arc::new(Int64Array::from(integers)),
+                Err(_) => Arc::new(Float64Array::from(
+                    numbers
+                        .iter()
+                        .map(|n| n.map(|n| n.parse::<f64>()).transpose())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| mixed())?,
+                )),
+            }
+        }
+    };
+
+    Ok((data.data_type().clone(), data))
+}
+
+/// Result of a call of a table function (`generate_series`, `unnest`), it's planned as a table
+/// which is named by `plan_table_functions`
+pub struct TableFunctionProvider {
+    name: String,
+    schema: SchemaRef,
+    data: ArrayRef,
+}
+
+impl TableFunctionProvider {
+    pub fn try_new(
+        name: String,
+        function: &str,
+        column: String,
+        args: &[ast::FunctionArg],
+    ) -> CompilationResult<Self> {
+        let (data_type, data) = match function {
+            UNNEST => unnest(args)?,
+            _ => generate_series(args)?,
+        };
+        let nullable = function == UNNEST;
+
+        Ok(Self {
+            name,
+            schema: Arc::new(Schema::new(vec![Field::new(&column, data_type, nullable)])),
+            data,
+        })
+    }
+
+    pub fn table_name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[async_trait]
+impl TableProvider for TableFunctionProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    async fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _batch_size: usize,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let batch = RecordBatch::try_new(self.schema(), vec![self.data.clone()])?;
+
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema(),
+            projection.clone(),
+        )?))
+    }
+
+    fn supports_filter_pushdown(
+        &self,
+        _filter: &Expr,
+    ) -> Result<TableProviderFilterPushDown, DataFusionError> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+}
+
+/// Name of the table function, which is called by `name`
+fn table_function(name: &ast::ObjectName) -> Option<&'static str> {
+    let function = match name.0.as_slice() {
+        [function] => function,
+        [schema, function] if schema.value.eq_ignore_ascii_case("pg_catalog") => function,
+        _ => return None,
+    };
+
+    TABLE_FUNCTIONS
+        .iter()
+        .find(|name| function.value.eq_ignore_ascii_case(name))
+        .cloned()
+}
+
+fn is_table_function(factor: &ast::TableFactor) -> bool {
+    match factor {
+        ast::TableFactor::Table { name, .. } => table_function(name).is_some(),
+        _ => false,
+    }
+}
+
+/// Table functions in FROM of the select, they are planned by DataFusion
+pub fn has_table_functions(select: &ast::Select) -> bool {
+    select.from.iter().any(|from| {
+        is_table_function(&from.relation)
+            || from
+                .joins
+                .iter()
+                .any(|join| is_table_function(&join.relation))
+    })
+}
+
+struct TableFunctionPlanner {
+    providers: Vec<Arc<TableFunctionProvider>>,
+}
+
+impl TableFunctionPlanner {
+    fn visit_query(&mut self, query: &mut ast::Query) -> CompilationResult<()> {
+        if let Some(with) = &mut query.with {
+            for cte in with.cte_tables.iter_mut() {
+                self.visit_query(&mut cte.query)?;
+            }
+        }
+
+        self.visit_set_expr(&mut query.body)
+    }
+
+    fn visit_set_expr(&mut self, set_expr: &mut ast::SetExpr) -> CompilationResult<()> {
+        match set_expr {
+            ast::SetExpr::Select(select) => {
+                Self::from_projection(select);
+                for from in select.from.iter_mut() {
+                    self.visit_table_with_joins(from)?;
+                }
+
+                Ok(())
+            }
+            ast::SetExpr::Query(query) => self.visit_query(query),
+            ast::SetExpr::SetOperation { left, right, .. } => {
+                self.visit_set_expr(left)?;
+                self.visit_set_expr(right)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// `SELECT generate_series(1, 3) AS n` is the same as `SELECT n FROM generate_series(1, 3) n`,
+    /// the same is true for unnest
+    fn from_projection(select: &mut ast::Select) {
+        if !select.from.is_empty() || select.projection.len() != 1 {
+            return;
+        }
+
+        let (fun, alias) = match &select.projection[0] {
+            ast::SelectItem::UnnamedExpr(ast::Expr::Function(fun)) => (fun.clone(), None),
+            ast::SelectItem::ExprWithAlias {
+                expr: ast::Expr::Function(fun),
+                alias,
+            } => (fun.clone(), Some(alias.clone())),
+            _ => return,
+        };
+        let function = match table_function(&fun.name) {
+            Some(function) if fun.over.is_none() => function,
+            _ => return,
+        };
+
+        let alias = alias.unwrap_or_else(|| ast::Ident::new(function));
+        select.from = vec![ast::TableWithJoins {
+            relation: ast::TableFactor::Table {
+                name: fun.name,
+                alias: Some(ast::TableAlias {
+                    name: alias.clone(),
+                    columns: vec![],
+                }),
+                args: fun.args,
+                with_hints: vec![],
+            },
+            joins: vec![],
+        }];
+        select.projection = vec![ast::SelectItem::UnnamedExpr(ast::Expr::Identifier(alias))];
+    }
+
+    fn visit_table_with_joins(&mut self, from: &mut ast::TableWithJoins) -> CompilationResult<()> {
+        self.visit_table_factor(&mut from.relation)?;
+        for join in from.joins.iter_mut() {
+            self.visit_table_factor(&mut join.relation)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) -> CompilationResult<()> {
+        if is_table_function(factor) {
+            if let ast::TableFactor::Table {
+                name, alias, args, ..
+            } = factor
+            {
+                let function = table_function(name).unwrap_or(GENERATE_SERIES);
+                // The column is named by the alias of the table, as in PostgreSQL
+                let (table_alias, column) = match alias {
+                    Some(alias) => (
+                        alias.name.clone(),
+                        alias.columns.first().unwrap_or(&alias.name).value.clone(),
+                    ),
+                    None => (ast::Ident::new(function), function.to_string()),
+                };
+                let table_name = format!("__{}_{}", function, self.providers.len());
+                self.providers.push(Arc::new(TableFunctionProvider::try_new(
+                    table_name.clone(),
+                    function,
+                    column,
+                    args,
+                )?));
+
+                *name = ast::ObjectName(vec![ast::Ident::new(table_name)]);
+                *alias = Some(ast::TableAlias {
+                    name: table_alias,
+                    columns: vec![],
+                });
+                *args = vec![];
+            }
+
+            return Ok(());
+        }
+
+        match factor {
+            ast::TableFactor::Derived { subquery, .. } => self.visit_query(subquery),
+            ast::TableFactor::NestedJoin(from) => self.visit_table_with_joins(from),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Calls of table functions in FROM are replaced by tables `__<function>_<n>`, providers of
+/// their results are returned to be resolved by CubeContext
+pub fn plan_table_functions(
+    stmt: &mut ast::Statement,
+) -> CompilationResult<Vec<Arc<TableFunctionProvider>>> {
+    let mut planner = TableFunctionPlanner { providers: vec![] };
+    if let ast::Statement::Query(query) = stmt {
+        planner.visit_query(query)?;
+    }
+
+    Ok(planner.providers)
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::{array::Array, util::display::array_value_to_string};
+
+    use super::*;
+    use crate::{compile::parser::parse_sql_to_statement, sql::session::DatabaseProtocol};
+
+    fn series(args: &str) -> CompilationResult<Vec<String>> {
+        let mut stmt = parse_sql_to_statement(
+            &format!("SELECT * FROM generate_series({})", args),
+            DatabaseProtocol::PostgreSQL,
+        )?;
+        let providers = plan_table_functions(&mut stmt)?;
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT * FROM __generate_series_0 AS generate_series"
+        );
+
+        let data = providers[0].data.clone();
+        Ok((0..data.len())
+            .map(|i| {
+                if let Some(values) = data.as_any().downcast_ref::<Int64Array>() {
+                    values.value(i).to_string()
+                } else {
+                    let values = data
+                        .as_any()
+                        .downcast_ref::<TimestampNanosecondArray>()
+                        .unwrap();
+                    values.value_as_datetime(i).unwrap().to_string()
+                }
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_generate_series() {
+        assert_eq!(series("1, 3").unwrap(), vec!["1", "2", "3"]);
+        assert_eq!(series("10, 1, -4").unwrap(), vec!["10", "6", "2"]);
+        assert_eq!(series("3, 1").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            series("'2022-01-31'::timestamp, '2022-04-30', INTERVAL '1 month'").unwrap(),
+            vec![
+                "2022-01-31 00:00:00",
+                "2022-02-28 00:00:00",
+                "2022-03-28 00:00:00",
+                "2022-04-28 00:00:00"
+            ]
+        );
+        assert_eq!(
+            series("TIMESTAMP '2022-01-01 12:00:00', '2022-01-01', '-6 hours'").unwrap(),
+            vec![
+                "2022-01-01 12:00:00",
+                "2022-01-01 06:00:00",
+                "2022-01-01 00:00:00"
+            ]
+        );
+
+        assert_eq!(
+            series("1, 3, 0").unwrap_err(),
+            CompilationError::User("step size cannot equal zero".to_string())
+        );
+        assert_eq!(
+            series("1, 10000000").unwrap_err(),
+            CompilationError::User(
+                "generate_series can't return more than 1000000 rows".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_generate_series_aliases() {
+        let mut stmt = parse_sql_to_statement(
+            &"SELECT s.n FROM generate_series(1, 2) AS s(n) \
+            UNION ALL SELECT generate_series(5, 6) AS n"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .unwrap();
+        let providers = plan_table_functions(&mut stmt).unwrap();
+
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT s.n FROM __generate_series_0 AS s \
+            UNION ALL SELECT n FROM __generate_series_1 AS n"
+        );
+        assert_eq!(
+            providers
+                .iter()
+                .map(|provider| provider.schema().field(0).name().clone())
+                .collect::<Vec<_>>(),
+            vec!["n".to_string(), "n".to_string()]
+        );
+    }
+
+    fn unnest_values(sql: &str) -> CompilationResult<(String, Vec<String>)> {
+        let mut stmt = parse_sql_to_statement(&sql.to_string(), DatabaseProtocol::PostgreSQL)?;
+        let providers = plan_table_functions(&mut stmt)?;
+        let data = providers[0].data.clone();
+
+        Ok((
+            stmt.to_string(),
+            (0..data.len())
+                .map(|i| {
+                    if data.is_null(i) {
+                        "NULL".to_string()
+                    } else {
+                        array_value_to_string(&data, i).unwrap()
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    #[test]
+    fn test_unnest() {
+        assert_eq!(
+            unnest_values("SELECT unnest(ARRAY[1, -2, NULL])").unwrap(),
+            (
+                "SELECT unnest FROM __unnest_0 AS unnest".to_string(),
+                vec!["1".to_string(), "-2".to_string(), "NULL".to_string()]
+            )
+        );
+        assert_eq!(
+            unnest_values("SELECT * FROM unnest(CAST('{1.5,2}' AS DOUBLE[])) AS u(v)")
+                .unwrap()
+                .1,
+            vec!["1.5".to_string(), "2".to_string()]
+        );
+        assert_eq!(
+            unnest_values("SELECT * FROM unnest('{a,\"b c\"}'::text[])")
+                .unwrap()
+                .1,
+            vec!["a".to_string(), "b c".to_string()]
+        );
+
+        assert_eq!(
+            unnest_values("SELECT unnest(ARRAY[1, 'a'])").unwrap_err(),
+            CompilationError::User("elements of unnest must be of the same type".to_string())
+        );
+    }
+}
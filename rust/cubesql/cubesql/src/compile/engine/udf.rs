@@ -1,40 +1,63 @@
 use std::any::type_name;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::sync::Arc;
 
 use datafusion::{
     arrow::{
         array::{
-            Array, ArrayRef, BooleanArray, BooleanBuilder, GenericStringArray,
-            IntervalDayTimeBuilder, PrimitiveArray, StringBuilder, UInt32Builder,
+            Array, ArrayRef, BooleanArray, BooleanBuilder, DecimalArray, DecimalBuilder,
+            Float64Array, Float64Builder, GenericStringArray, IntervalDayTimeBuilder, ListBuilder,
+            PrimitiveArray, StringBuilder, UInt32Builder,
         },
         compute::cast,
         datatypes::{
-            DataType, Int32Type, Int64Type, IntervalDayTimeType, IntervalUnit, TimeUnit,
-            TimestampNanosecondType, UInt64Type,
+            DataType, Date32Type, Field, Float64Type, Int32Type, Int64Type, IntervalDayTimeType,
+            IntervalUnit, TimeUnit, TimestampNanosecondType, UInt64Type,
         },
+        util::display::array_value_to_string,
     },
     error::DataFusionError,
     logical_plan::create_udf,
     physical_plan::{
-        functions::{make_scalar_function, ReturnTypeFunction, Signature, Volatility},
+        aggregates::{AccumulatorFunctionImplementation, StateTypeFunction},
+        functions::{
+            make_scalar_function, ReturnTypeFunction, Signature, TypeSignature, Volatility,
+        },
         udf::ScalarUDF,
+        Accumulator,
     },
 };
 
 use crate::{
     compile::engine::df::{
-        coerce::{if_coercion, least_coercion},
+        coerce::{
+            decimal_operand_type, if_coercion, least_coercion, numeric_operator_coercion,
+            NumericOperator, MAX_DECIMAL_PRECISION, MIN_DECIMAL_DIVISION_SCALE,
+        },
         columar::if_then_else,
     },
-    sql::SessionState,
+    sql::{
+        dataframe::{DecimalValue, IntervalValue},
+        formatting::{format_number, format_timestamp, FormatZone},
+        interval::add_interval,
+        session::DatabaseProtocol,
+        timezone::{local_time, local_to_unix_nano, parse_timestamptz},
+        SessionManager, SessionState,
+    },
+};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Offset, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use datafusion::arrow::array::{
+    IntervalDayTimeArray, IntervalYearMonthArray, StringArray, TimestampNanosecondArray,
 };
-use chrono::{Duration, NaiveDateTime};
-use datafusion::arrow::array::{IntervalDayTimeArray, StringArray, TimestampNanosecondArray};
 use datafusion::logical_plan::create_udaf;
 use datafusion::physical_plan::datetime_expressions::date_trunc;
 use datafusion::physical_plan::udaf::AggregateUDF;
 use datafusion::physical_plan::ColumnarValue;
 use datafusion::scalar::ScalarValue;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
 
 pub fn create_version_udf() -> ScalarUDF {
     let version = make_scalar_function(|_args: &[ArrayRef]| {
@@ -175,6 +198,170 @@ macro_rules! downcast_string_arg {
     }};
 }
 
+/// pg_cancel_backend(pid) cancels the query of the connection, only queries of the same user
+/// can be canceled
+pub fn create_pg_cancel_backend_udf(
+    state: Arc<SessionState>,
+    session_manager: Arc<SessionManager>,
+) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let pids = downcast_primitive_arg!(args[0], "pid", Int64Type);
+
+        let mut builder = BooleanBuilder::new(pids.len());
+        for pid in pids.iter() {
+            match pid {
+                None => builder.append_null()?,
+                Some(pid) => {
+                    let canceled = u32::try_from(pid)
+                        .ok()
+                        .and_then(|pid| session_manager.get_session(pid))
+                        .filter(|session| session.state.user() == state.user())
+                        .map(|session| session.state.cancel_query())
+                        .unwrap_or(false);
+                    builder.append_value(canceled)?;
+                }
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    create_udf(
+        "pg_cancel_backend",
+        vec![DataType::Int64],
+        Arc::new(DataType::Boolean),
+        Volatility::Volatile,
+        fun,
+    )
+}
+
+/// pg_terminate_backend(pid) closes the connection, only connections of the same user
+/// can be terminated
+pub fn create_pg_terminate_backend_udf(
+    state: Arc<SessionState>,
+    session_manager: Arc<SessionManager>,
+) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let pids = downcast_primitive_arg!(args[0], "pid", Int64Type);
+
+        let mut builder = BooleanBuilder::new(pids.len());
+        for pid in pids.iter() {
+            match pid {
+                None => builder.append_null()?,
+                Some(pid) => {
+                    let session = u32::try_from(pid)
+                        .ok()
+                        .and_then(|pid| session_manager.get_session(pid))
+                        .filter(|session| {
+                            session.state.protocol == DatabaseProtocol::PostgreSQL
+                                && session.state.user() == state.user()
+                        });
+                    if let Some(session) = &session {
+                        session.state.terminate();
+                    }
+                    builder.append_value(session.is_some())?;
+                }
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    create_udf(
+        "pg_terminate_backend",
+        vec![DataType::Int64],
+        Arc::new(DataType::Boolean),
+        Volatility::Volatile,
+        fun,
+    )
+}
+
+/// current_setting(name [, missing_ok]) returns the value of the run-time parameter as SHOW,
+/// NULL is returned for unknown parameters if `missing_ok` is true
+pub fn create_current_setting_udf(state: Arc<SessionState>) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let names = downcast_string_arg!(args[0], "setting_name", i32);
+        let missing_ok = match args.get(1) {
+            Some(arg) => Some(downcast_boolean_arr!(arg)),
+            None => None,
+        };
+
+        let settings = state.settings();
+        let mut builder = StringBuilder::new(names.len());
+        for (i, name) in names.iter().enumerate() {
+            match name {
+                None => builder.append_null()?,
+                Some(name) => match settings.show(name) {
+                    Ok(value) => builder.append_value(value)?,
+                    Err(_) if missing_ok.map(|arr| arr.value(i)).unwrap_or(false) => {
+                        builder.append_null()?
+                    }
+                    Err(e) => return Err(DataFusionError::Execution(e.message)),
+                },
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        "current_setting",
+        &Signature::one_of(
+            vec![
+                TypeSignature::Exact(vec![DataType::Utf8]),
+                TypeSignature::Exact(vec![DataType::Utf8, DataType::Boolean]),
+            ],
+            Volatility::Volatile,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
+/// set_config(name, value, is_local) sets the run-time parameter as SET, it returns the new
+/// value. Local values are discarded at the end of the transaction block.
+pub fn create_set_config_udf(state: Arc<SessionState>) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let names = downcast_string_arg!(args[0], "setting_name", i32);
+        let values = downcast_string_arg!(args[1], "new_value", i32);
+        let is_local = downcast_boolean_arr!(args[2]);
+
+        let mut settings = state.settings_mut();
+        let mut builder = StringBuilder::new(names.len());
+        for i in 0..names.len() {
+            if names.is_null(i) || is_local.is_null(i) {
+                builder.append_null()?;
+                continue;
+            }
+
+            // NULL resets the parameter to the default, as in PostgreSQL
+            let name = names.value(i);
+            let value = if values.is_null(i) {
+                None
+            } else {
+                Some(values.value(i))
+            };
+            let value = settings
+                .set(name, value, is_local.value(i))
+                .and_then(|_| settings.show(name))
+                .map_err(|e| DataFusionError::Execution(e.message))?;
+            builder.append_value(value)?;
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    create_udf(
+        "set_config",
+        vec![DataType::Utf8, DataType::Utf8, DataType::Boolean],
+        Arc::new(DataType::Utf8),
+        Volatility::Volatile,
+        fun,
+    )
+}
+
 // Returns the position of the first occurrence of substring substr in string str.
 // This is the same as the two-argument form of LOCATE(), except that the order of
 // the arguments is reversed.
@@ -830,51 +1017,58 @@ pub fn create_second_udf() -> ScalarUDF {
     )
 }
 
-pub fn create_date_sub_udf() -> ScalarUDF {
-    let fun = make_scalar_function(move |_args: &[ArrayRef]| todo!("Not implemented"));
-
-    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Int64)));
-
-    ScalarUDF::new(
-        "date_sub",
-        &Signature::exact(
-            vec![
-                DataType::Timestamp(TimeUnit::Millisecond, None),
-                DataType::Interval(IntervalUnit::DayTime),
-            ],
-            Volatility::Immutable,
-        ),
-        &return_type,
-        &fun,
-    )
-}
-
-pub fn create_date_add_udf() -> ScalarUDF {
+/// `date_add` and `date_sub` of timestamps and intervals, months are added as in PostgreSQL
+fn create_date_interval_udf(name: &'static str, negate: bool) -> ScalarUDF {
     let fun = make_scalar_function(move |args: &[ArrayRef]| {
         let timestamps = args[0]
             .as_any()
             .downcast_ref::<TimestampNanosecondArray>()
             .unwrap();
-        let intervals = args[1]
-            .as_any()
-            .downcast_ref::<IntervalDayTimeArray>()
-            .unwrap();
+        let intervals = (0..args[1].len())
+            .map(|i| match args[1].data_type() {
+                _ if args[1].is_null(i) => None,
+                DataType::Interval(IntervalUnit::YearMonth) => {
+                    let intervals = args[1]
+                        .as_any()
+                        .downcast_ref::<IntervalYearMonthArray>()
+                        .unwrap();
+                    Some(IntervalValue::new(intervals.value(i), 0, 0))
+                }
+                _ => {
+                    // Days are the high 32 bits, milliseconds are the low ones
+                    let intervals = args[1]
+                        .as_any()
+                        .downcast_ref::<IntervalDayTimeArray>()
+                        .unwrap();
+                    let value = intervals.value(i);
+                    Some(IntervalValue::new(
+                        0,
+                        (value >> 32) as i32,
+                        value as i32 as i64 * 1000,
+                    ))
+                }
+            })
+            .collect::<Vec<_>>();
+
         let mut builder = TimestampNanosecondArray::builder(timestamps.len());
         for i in 0..timestamps.len() {
+            let interval = match intervals[i] {
+                Some(interval) if !timestamps.is_null(i) => interval,
+                _ => {
+                    builder.append_null()?;
+                    continue;
+                }
+            };
+            let interval = if negate { interval.negate() } else { interval };
+
             let timestamp = timestamps.value(i);
-            let interval = intervals.value(i);
-            let interval_days = interval >> 32;
-            let interval_millis = interval & 0xffffffff;
             let timestamp = NaiveDateTime::from_timestamp(
-                timestamp / 1000000000,
-                (timestamp % 1000000000) as u32,
+                timestamp.div_euclid(1_000_000_000),
+                timestamp.rem_euclid(1_000_000_000) as u32,
             );
-            let timestamp = timestamp
-                .checked_add_signed(Duration::days(interval_days))
-                .unwrap();
-            let timestamp = timestamp
-                .checked_add_signed(Duration::milliseconds(interval_millis))
-                .unwrap();
+            let timestamp = add_interval(&timestamp, &interval).ok_or_else(|| {
+                DataFusionError::Execution(format!("timestamp out of range in {}", name))
+            })?;
             builder.append_value(timestamp.timestamp_nanos())?;
         }
         Ok(Arc::new(builder.finish()))
@@ -884,11 +1078,17 @@ pub fn create_date_add_udf() -> ScalarUDF {
         Arc::new(move |_| Ok(Arc::new(DataType::Timestamp(TimeUnit::Nanosecond, None))));
 
     ScalarUDF::new(
-        "date_add",
-        &Signature::exact(
+        name,
+        &Signature::one_of(
             vec![
-                DataType::Timestamp(TimeUnit::Nanosecond, None),
-                DataType::Interval(IntervalUnit::DayTime),
+                TypeSignature::Exact(vec![
+                    DataType::Timestamp(TimeUnit::Nanosecond, None),
+                    DataType::Interval(IntervalUnit::DayTime),
+                ]),
+                TypeSignature::Exact(vec![
+                    DataType::Timestamp(TimeUnit::Nanosecond, None),
+                    DataType::Interval(IntervalUnit::YearMonth),
+                ]),
             ],
             Volatility::Immutable,
         ),
@@ -897,6 +1097,14 @@ pub fn create_date_add_udf() -> ScalarUDF {
     )
 }
 
+pub fn create_date_sub_udf() -> ScalarUDF {
+    create_date_interval_udf("date_sub", true)
+}
+
+pub fn create_date_add_udf() -> ScalarUDF {
+    create_date_interval_udf("date_add", false)
+}
+
 pub fn create_str_to_date() -> ScalarUDF {
     let fun: Arc<dyn Fn(&[ColumnarValue]) -> Result<ColumnarValue, DataFusionError> + Send + Sync> =
         Arc::new(move |args: &[ColumnarValue]| {
@@ -961,3 +1169,1772 @@ pub fn create_measure_udaf() -> AggregateUDF {
         Arc::new(vec![DataType::Float64]),
     )
 }
+
+fn json_error(e: serde_json::Error) -> DataFusionError {
+    DataFusionError::Execution(format!("invalid input syntax for type json: {}", e))
+}
+
+/// Text of the value as PostgreSQL prints jsonb: `{"a": 1, "b": [1, 2]}`
+fn json_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(json_to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        serde_json::Value::Object(object) => format!(
+            "{{{}}}",
+            object
+                .iter()
+                .map(|(key, value)| format!(
+                    "{}: {}",
+                    serde_json::Value::String(key.clone()),
+                    json_to_string(value)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        value => value.to_string(),
+    }
+}
+
+/// Value of the row of the array, as it's converted by to_json. Numbers, booleans and strings
+/// are kept, other types are converted to their text.
+fn json_value(array: &ArrayRef, index: usize) -> Result<serde_json::Value, DataFusionError> {
+    let value = match ScalarValue::try_from_array(array, index)? {
+        value if value.is_null() => serde_json::Value::Null,
+        ScalarValue::Boolean(Some(v)) => v.into(),
+        ScalarValue::Int8(Some(v)) => v.into(),
+        ScalarValue::Int16(Some(v)) => v.into(),
+        ScalarValue::Int32(Some(v)) => v.into(),
+        ScalarValue::Int64(Some(v)) => v.into(),
+        ScalarValue::UInt8(Some(v)) => v.into(),
+        ScalarValue::UInt16(Some(v)) => v.into(),
+        ScalarValue::UInt32(Some(v)) => v.into(),
+        ScalarValue::UInt64(Some(v)) => v.into(),
+        ScalarValue::Float32(Some(v)) => (v as f64).into(),
+        ScalarValue::Float64(Some(v)) => v.into(),
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => v.into(),
+        _ => array_value_to_string(array, index)?.into(),
+    };
+
+    Ok(value)
+}
+
+/// Value of the path of object keys and array indexes, negative indexes count from the end of
+/// arrays as in `->`. NULL is returned if the path doesn't exist.
+fn json_extract_path(
+    json: &str,
+    path: &[Option<&str>],
+) -> Result<Option<serde_json::Value>, DataFusionError> {
+    let mut value = serde_json::from_str::<serde_json::Value>(json).map_err(json_error)?;
+    for key in path.iter() {
+        let key = match key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        let next = match value {
+            serde_json::Value::Object(mut object) => object.remove(*key),
+            serde_json::Value::Array(mut values) => key
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .map(|index| {
+                    if index < 0 {
+                        values.len() as i64 + index
+                    } else {
+                        index
+                    }
+                })
+                .filter(|index| *index >= 0 && (*index as usize) < values.len())
+                .map(|index| values.swap_remove(index as usize)),
+            _ => None,
+        };
+
+        value = match next {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+    }
+
+    Ok(Some(value))
+}
+
+/// json_extract_path(from_json, VARIADIC path_elems) returns the value at the path as json,
+/// json_extract_path_text returns it as text. `->` and `->>` are rewritten into these functions
+/// by the parser, jsonb variants are the same as json is represented by text.
+pub fn create_json_extract_path_udf(name: &'static str, as_text: bool) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let jsons = downcast_string_arg!(args[0], "from_json", i32);
+        let mut paths = vec![];
+        for arg in args[1..].iter() {
+            paths.push(downcast_string_arg!(arg, "path_elems", i32));
+        }
+
+        let mut builder = StringBuilder::new(jsons.len());
+        for i in 0..jsons.len() {
+            if jsons.is_null(i) {
+                builder.append_null()?;
+                continue;
+            }
+
+            let path = paths
+                .iter()
+                .map(|path| {
+                    if path.is_null(i) {
+                        None
+                    } else {
+                        Some(path.value(i))
+                    }
+                })
+                .collect::<Vec<_>>();
+            match json_extract_path(jsons.value(i), &path)? {
+                None => builder.append_null()?,
+                Some(serde_json::Value::Null) if as_text => builder.append_null()?,
+                Some(serde_json::Value::String(v)) if as_text => builder.append_value(v)?,
+                Some(value) => builder.append_value(json_to_string(&value))?,
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        name,
+        &Signature::variadic(vec![DataType::Utf8], Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// json_build_object(VARIADIC args) builds an object of pairs of keys and values. Keys are
+/// printed in the order of arguments as `{"a" : 1}` for json, jsonb keeps the last value of
+/// a key. Results of other json functions are strings, they aren't nested as objects.
+pub fn create_json_build_object_udf(name: &'static str, jsonb: bool) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let rows = args.first().map(|arg| arg.len()).unwrap_or(1);
+
+        let mut builder = StringBuilder::new(rows);
+        for i in 0..rows {
+            let mut pairs = vec![];
+            for pair in args.chunks(2) {
+                let key = match json_value(&pair[0], i)? {
+                    serde_json::Value::Null => {
+                        return Err(DataFusionError::Execution(
+                            "null value not allowed for object key".to_string(),
+                        ))
+                    }
+                    serde_json::Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                pairs.push((key, json_value(&pair[1], i)?));
+            }
+
+            let object = if jsonb {
+                json_to_string(&serde_json::Value::Object(pairs.into_iter().collect()))
+            } else {
+                format!(
+                    "{{{}}}",
+                    pairs
+                        .iter()
+                        .map(|(key, value)| format!(
+                            "{} : {}",
+                            serde_json::Value::String(key.clone()),
+                            json_to_string(value)
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            };
+            builder.append_value(object)?;
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    // Up to 100 arguments as in PostgreSQL, the number of them must be even
+    ScalarUDF::new(
+        name,
+        &Signature::one_of(
+            (0..=100).step_by(2).map(TypeSignature::Any).collect(),
+            Volatility::Immutable,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
+/// `x::json` and `x::jsonb` of the parser, see `rewrite_json_casts`
+pub const JSON_CAST_FUNCTION: &str = "__json";
+pub const JSONB_CAST_FUNCTION: &str = "__jsonb";
+
+/// Casts of text to json and jsonb: the text must be valid JSON, json keeps it as is, jsonb
+/// prints it as PostgreSQL prints jsonb. Results are texts as for other json functions.
+pub fn create_json_cast_udf(name: &'static str, jsonb: bool) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let texts = downcast_string_arg!(args[0], "json", i32);
+
+        let mut builder = StringBuilder::new(texts.len());
+        for i in 0..texts.len() {
+            if texts.is_null(i) {
+                builder.append_null()?;
+                continue;
+            }
+
+            let text = texts.value(i);
+            let value = serde_json::from_str::<serde_json::Value>(text).map_err(json_error)?;
+            if jsonb {
+                builder.append_value(json_to_string(&value))?;
+            } else {
+                builder.append_value(text)?;
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        name,
+        &Signature::exact(vec![DataType::Utf8], Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// Values of json_agg, they are kept as a json array in the state
+#[derive(Debug)]
+struct JsonAggAccumulator {
+    values: Vec<serde_json::Value>,
+}
+
+impl Accumulator for JsonAggAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>, DataFusionError> {
+        Ok(vec![ScalarValue::Utf8(Some(
+            serde_json::Value::Array(self.values.clone()).to_string(),
+        ))])
+    }
+
+    fn update(&mut self, values: &[ScalarValue]) -> Result<(), DataFusionError> {
+        self.values.push(json_value(&values[0].to_array(), 0)?);
+
+        Ok(())
+    }
+
+    fn merge(&mut self, states: &[ScalarValue]) -> Result<(), DataFusionError> {
+        if let ScalarValue::Utf8(Some(state)) = &states[0] {
+            match serde_json::from_str(state).map_err(json_error)? {
+                serde_json::Value::Array(values) => self.values.extend(values),
+                _ => {
+                    return Err(DataFusionError::Internal(
+                        "json_agg state must be an array".to_string(),
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue, DataFusionError> {
+        if self.values.is_empty() {
+            return Ok(ScalarValue::Utf8(None));
+        }
+
+        Ok(ScalarValue::Utf8(Some(json_to_string(
+            &serde_json::Value::Array(self.values.clone()),
+        ))))
+    }
+}
+
+/// json_agg(expression) aggregates values, including NULLs, into a json array
+pub fn create_json_agg_udaf(name: &'static str) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+    let accumulator: AccumulatorFunctionImplementation =
+        Arc::new(|| Ok(Box::new(JsonAggAccumulator { values: vec![] })));
+    let state_type: StateTypeFunction = Arc::new(|_| Ok(Arc::new(vec![DataType::Utf8])));
+
+    AggregateUDF::new(
+        name,
+        &Signature::any(1, Volatility::Immutable),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+fn list_type(element: &DataType) -> DataType {
+    DataType::List(Box::new(Field::new("item", element.clone(), true)))
+}
+
+/// make_array(VARIADIC args) builds an array of its arguments, `ARRAY[...]` is rewritten into
+/// this function by the parser
+pub fn create_make_array_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let element = args[0].data_type().clone();
+        let rows = (0..args[0].len())
+            .map(|i| {
+                let values = args
+                    .iter()
+                    .map(|arg| ScalarValue::try_from_array(arg, i))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(ScalarValue::List(Some(values), element.clone()))
+            })
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+
+        ScalarValue::iter_to_array(rows.into_iter())
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |types| Ok(Arc::new(list_type(&types[0]))));
+
+    ScalarUDF::new(
+        "make_array",
+        &Signature::variadic_equal(Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// Aggregates with `ORDER BY` keys: `__order_by(key, 'asc nulls last', ...)` of the parser is
+/// replaced by pairs of keys and directions after arguments by `rewrite_ordered_aggregates`
+pub const AGGREGATE_ORDER_BY_FUNCTION: &str = "__order_by";
+pub const STRING_AGG_DISTINCT_FUNCTION: &str = "__string_agg_distinct";
+pub const ARRAY_AGG_DISTINCT_FUNCTION: &str = "__array_agg_distinct";
+const MAX_AGGREGATE_ORDER_KEYS: usize = 32;
+
+fn aggregate_state_error(e: serde_json::Error) -> DataFusionError {
+    DataFusionError::Internal(format!("Invalid state of aggregate: {}", e))
+}
+
+/// Order of json values of the same type, numbers are compared as integers if both of them are
+fn compare_json_values(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
+    match (a, b) {
+        (serde_json::Value::Number(a), serde_json::Value::Number(b)) => {
+            match (a.as_i64(), b.as_i64()) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => a
+                    .as_f64()
+                    .partial_cmp(&b.as_f64())
+                    .unwrap_or(Ordering::Equal),
+            }
+        }
+        (serde_json::Value::String(a), serde_json::Value::String(b)) => a.cmp(b),
+        (serde_json::Value::Bool(a), serde_json::Value::Bool(b)) => a.cmp(b),
+        (a, b) => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+/// Keys of `ORDER BY` of rows of an aggregate, they are json values to be kept in the state as
+/// text. Directions are `asc` or `desc` followed by `nulls first` or `nulls last`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AggregateOrder {
+    directions: Vec<String>,
+    keys: Vec<Vec<serde_json::Value>>,
+}
+
+impl AggregateOrder {
+    /// Keys of a row, `args` are pairs of keys and their directions
+    fn push(&mut self, args: &[ScalarValue]) -> Result<(), DataFusionError> {
+        let mut directions = Vec::with_capacity(args.len() / 2);
+        let mut keys = Vec::with_capacity(args.len() / 2);
+        for pair in args.chunks(2) {
+            match pair {
+                [key, ScalarValue::Utf8(Some(direction))] => {
+                    keys.push(json_value(&key.to_array(), 0)?);
+                    directions.push(direction.clone());
+                }
+                _ => {
+                    return Err(DataFusionError::Internal(
+                        "ORDER BY of aggregate must be pairs of keys and directions".to_string(),
+                    ))
+                }
+            }
+        }
+
+        self.directions = directions;
+        self.keys.push(keys);
+
+        Ok(())
+    }
+
+    fn state(&self) -> Result<ScalarValue, DataFusionError> {
+        Ok(ScalarValue::Utf8(Some(
+            serde_json::to_string(self).map_err(aggregate_state_error)?,
+        )))
+    }
+
+    fn merge(&mut self, state: &ScalarValue) -> Result<(), DataFusionError> {
+        if let ScalarValue::Utf8(Some(state)) = state {
+            let state =
+                serde_json::from_str::<AggregateOrder>(state).map_err(aggregate_state_error)?;
+            if !state.keys.is_empty() {
+                self.directions = state.directions;
+                self.keys.extend(state.keys);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compare(&self, a: &[serde_json::Value], b: &[serde_json::Value]) -> Ordering {
+        for ((a, b), direction) in a.iter().zip(b.iter()).zip(self.directions.iter()) {
+            let nulls_first = direction.ends_with("nulls first");
+            let ordering = match (a.is_null(), b.is_null()) {
+                (true, true) => Ordering::Equal,
+                (true, false) if nulls_first => Ordering::Less,
+                (true, false) => Ordering::Greater,
+                (false, true) if nulls_first => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) if direction.starts_with("desc") => compare_json_values(b, a),
+                (false, false) => compare_json_values(a, b),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Indexes of `len` rows in the order, the sort is stable
+    fn sorted(&self, len: usize) -> Vec<usize> {
+        let mut indexes = (0..len).collect::<Vec<_>>();
+        if self.keys.len() == len && !self.directions.is_empty() {
+            indexes.sort_by(|a, b| self.compare(&self.keys[*a], &self.keys[*b]));
+        }
+
+        indexes
+    }
+}
+
+/// Signature of an aggregate of `args` arguments, which are followed by pairs of ORDER BY keys
+fn ordered_aggregate_signature(args: usize) -> Signature {
+    Signature::one_of(
+        (args..=args + 2 * MAX_AGGREGATE_ORDER_KEYS)
+            .step_by(2)
+            .map(TypeSignature::Any)
+            .collect(),
+        Volatility::Immutable,
+    )
+}
+
+#[derive(Debug)]
+struct ArrayAggAccumulator {
+    distinct: bool,
+    // Type of elements, it's known after the first value
+    data_type: Option<DataType>,
+    values: Vec<ScalarValue>,
+    order: AggregateOrder,
+}
+
+impl ArrayAggAccumulator {
+    fn list(&self, values: Vec<ScalarValue>) -> ScalarValue {
+        let values = if values.is_empty() {
+            None
+        } else {
+            Some(values)
+        };
+
+        ScalarValue::List(values, self.data_type.clone().unwrap_or(DataType::Utf8))
+    }
+}
+
+impl Accumulator for ArrayAggAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>, DataFusionError> {
+        Ok(vec![self.list(self.values.clone()), self.order.state()?])
+    }
+
+    fn update(&mut self, values: &[ScalarValue]) -> Result<(), DataFusionError> {
+        self.data_type = Some(values[0].get_datatype());
+        self.values.push(values[0].clone());
+        self.order.push(&values[1..])
+    }
+
+    fn merge(&mut self, states: &[ScalarValue]) -> Result<(), DataFusionError> {
+        if let ScalarValue::List(Some(values), data_type) = &states[0] {
+            if !values.is_empty() {
+                self.data_type = Some(data_type.clone());
+                self.values.extend(values.iter().cloned());
+                self.order.merge(&states[1])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue, DataFusionError> {
+        let mut seen = vec![];
+        let mut values = vec![];
+        for i in self.order.sorted(self.values.len()) {
+            let value = &self.values[i];
+            if self.distinct {
+                let key = json_value(&value.to_array(), 0)?;
+                if seen.contains(&key) {
+                    continue;
+                }
+                seen.push(key);
+            }
+            values.push(value.clone());
+        }
+
+        Ok(self.list(values))
+    }
+}
+
+/// array_agg(expression) aggregates values, including NULLs, into an array. The accumulator
+/// doesn't know the type of arguments, the aggregation of no rows is NULL of text[].
+pub fn create_array_agg_udaf(distinct: bool) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |types| Ok(Arc::new(list_type(&types[0]))));
+    let accumulator: AccumulatorFunctionImplementation = Arc::new(move || {
+        Ok(Box::new(ArrayAggAccumulator {
+            distinct,
+            data_type: None,
+            values: vec![],
+            order: AggregateOrder::default(),
+        }))
+    });
+    let state_type: StateTypeFunction =
+        Arc::new(|return_type| Ok(Arc::new(vec![return_type.clone(), DataType::Utf8])));
+
+    AggregateUDF::new(
+        if distinct {
+            ARRAY_AGG_DISTINCT_FUNCTION
+        } else {
+            "array_agg"
+        },
+        &ordered_aggregate_signature(1),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+#[derive(Debug)]
+struct StringAggAccumulator {
+    distinct: bool,
+    // Values with their delimiters, NULL values are skipped
+    values: Vec<(String, Option<String>)>,
+    order: AggregateOrder,
+}
+
+impl Accumulator for StringAggAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>, DataFusionError> {
+        Ok(vec![
+            ScalarValue::Utf8(Some(
+                serde_json::to_string(&self.values).map_err(aggregate_state_error)?,
+            )),
+            self.order.state()?,
+        ])
+    }
+
+    fn update(&mut self, values: &[ScalarValue]) -> Result<(), DataFusionError> {
+        let value = match &values[0] {
+            value if value.is_null() => return Ok(()),
+            ScalarValue::Utf8(Some(value)) => value.clone(),
+            value => array_value_to_string(&value.to_array(), 0)?,
+        };
+        let delimiter = match &values[1] {
+            ScalarValue::Utf8(delimiter) => delimiter.clone(),
+            delimiter if delimiter.is_null() => None,
+            delimiter => Some(array_value_to_string(&delimiter.to_array(), 0)?),
+        };
+
+        self.values.push((value, delimiter));
+        self.order.push(&values[2..])
+    }
+
+    fn merge(&mut self, states: &[ScalarValue]) -> Result<(), DataFusionError> {
+        if let ScalarValue::Utf8(Some(values)) = &states[0] {
+            let values = serde_json::from_str::<Vec<(String, Option<String>)>>(values)
+                .map_err(aggregate_state_error)?;
+            if !values.is_empty() {
+                self.values.extend(values);
+                self.order.merge(&states[1])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue, DataFusionError> {
+        let mut seen = vec![];
+        let mut result: Option<String> = None;
+        for i in self.order.sorted(self.values.len()) {
+            let (value, delimiter) = &self.values[i];
+            if self.distinct {
+                if seen.contains(&value) {
+                    continue;
+                }
+                seen.push(value);
+            }
+
+            result = Some(match result {
+                None => value.clone(),
+                Some(result) => {
+                    format!("{}{}{}", result, delimiter.as_deref().unwrap_or(""), value)
+                }
+            });
+        }
+
+        Ok(ScalarValue::Utf8(result))
+    }
+}
+
+/// string_agg(value, delimiter) concatenates non-NULL values, the delimiter of every value but
+/// the first one is written before it. listagg() is rewritten into this function.
+pub fn create_string_agg_udaf(distinct: bool) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+    let accumulator: AccumulatorFunctionImplementation = Arc::new(move || {
+        Ok(Box::new(StringAggAccumulator {
+            distinct,
+            values: vec![],
+            order: AggregateOrder::default(),
+        }))
+    });
+    let state_type: StateTypeFunction =
+        Arc::new(|_| Ok(Arc::new(vec![DataType::Utf8, DataType::Utf8])));
+
+    AggregateUDF::new(
+        if distinct {
+            STRING_AGG_DISTINCT_FUNCTION
+        } else {
+            "string_agg"
+        },
+        &ordered_aggregate_signature(2),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+/// Value of a number as a double, values which aren't numbers are None
+fn float_value(value: &ScalarValue) -> Result<Option<f64>, DataFusionError> {
+    let array = cast(&value.to_array(), &DataType::Float64)?;
+    let array = array.as_any().downcast_ref::<Float64Array>().unwrap();
+
+    Ok(if array.is_null(0) {
+        None
+    } else {
+        Some(array.value(0))
+    })
+}
+
+/// Values of percentile_cont() are doubles, values of percentile_disc() are kept. NULLs are
+/// skipped.
+#[derive(Debug)]
+struct PercentileAccumulator {
+    continuous: bool,
+    fraction: Option<f64>,
+    // Type of values, it's known after the first value
+    data_type: Option<DataType>,
+    values: Vec<ScalarValue>,
+    order: AggregateOrder,
+}
+
+impl PercentileAccumulator {
+    fn null(&self) -> Result<ScalarValue, DataFusionError> {
+        match &self.data_type {
+            Some(data_type) if !self.continuous => ScalarValue::try_from(data_type),
+            _ => Ok(ScalarValue::Float64(None)),
+        }
+    }
+}
+
+impl Accumulator for PercentileAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>, DataFusionError> {
+        let values = if self.values.is_empty() {
+            None
+        } else {
+            Some(self.values.clone())
+        };
+
+        Ok(vec![
+            ScalarValue::Float64(self.fraction),
+            ScalarValue::List(values, self.data_type.clone().unwrap_or(DataType::Float64)),
+            self.order.state()?,
+        ])
+    }
+
+    fn update(&mut self, values: &[ScalarValue]) -> Result<(), DataFusionError> {
+        if let Some(fraction) = float_value(&values[0])? {
+            if !(0.0..=1.0).contains(&fraction) {
+                return Err(DataFusionError::Execution(format!(
+                    "percentile value {} is not between 0 and 1",
+                    fraction
+                )));
+            }
+            self.fraction = Some(fraction);
+        }
+
+        let value = match &values[1] {
+            value if value.is_null() => return Ok(()),
+            value if self.continuous => match float_value(value)? {
+                Some(value) => ScalarValue::Float64(Some(value)),
+                None => return Ok(()),
+            },
+            value => value.clone(),
+        };
+        self.data_type = Some(value.get_datatype());
+        self.values.push(value);
+        self.order.push(&values[1..])
+    }
+
+    fn merge(&mut self, states: &[ScalarValue]) -> Result<(), DataFusionError> {
+        if let ScalarValue::Float64(Some(fraction)) = &states[0] {
+            self.fraction = Some(*fraction);
+        }
+        if let ScalarValue::List(Some(values), data_type) = &states[1] {
+            if !values.is_empty() {
+                self.data_type = Some(data_type.clone());
+                self.values.extend(values.iter().cloned());
+                self.order.merge(&states[2])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue, DataFusionError> {
+        let indexes = self.order.sorted(self.values.len());
+        let fraction = match self.fraction {
+            Some(fraction) if !indexes.is_empty() => fraction,
+            _ => return self.null(),
+        };
+
+        if self.continuous {
+            let position = fraction * (indexes.len() - 1) as f64;
+            let value = |position: f64| -> Result<f64, DataFusionError> {
+                Ok(float_value(&self.values[indexes[position as usize]])?.unwrap_or_default())
+            };
+            let lower = value(position.floor())?;
+            let upper = value(position.ceil())?;
+
+            Ok(ScalarValue::Float64(Some(
+                lower + (upper - lower) * (position - position.floor()),
+            )))
+        } else {
+            let position = ((fraction * indexes.len() as f64).ceil() as usize).max(1) - 1;
+
+            Ok(self.values[indexes[position]].clone())
+        }
+    }
+}
+
+/// percentile_cont(fraction, value, direction) interpolates between the values around the
+/// fraction, percentile_disc() returns the first value whose position is at least the fraction.
+/// They are `WITHIN GROUP (ORDER BY value)` of the parser, see `rewrite_ordered_aggregates`.
+pub fn create_percentile_udaf(continuous: bool) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |types| {
+        Ok(Arc::new(if continuous {
+            DataType::Float64
+        } else {
+            types[1].clone()
+        }))
+    });
+    let accumulator: AccumulatorFunctionImplementation = Arc::new(move || {
+        Ok(Box::new(PercentileAccumulator {
+            continuous,
+            fraction: None,
+            data_type: None,
+            values: vec![],
+            order: AggregateOrder::default(),
+        }))
+    });
+    let state_type: StateTypeFunction = Arc::new(|return_type| {
+        Ok(Arc::new(vec![
+            DataType::Float64,
+            list_type(return_type),
+            DataType::Utf8,
+        ]))
+    });
+
+    AggregateUDF::new(
+        if continuous {
+            "percentile_cont"
+        } else {
+            "percentile_disc"
+        },
+        &Signature::any(3, Volatility::Immutable),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+fn numeric_overflow() -> DataFusionError {
+    DataFusionError::Execution("numeric field overflow".to_string())
+}
+
+/// Values of a numeric argument as decimals, None is NULL. Floats are converted by their
+/// shortest representation, text is parsed.
+fn decimal_values(array: &ArrayRef) -> Result<Vec<Option<DecimalValue>>, DataFusionError> {
+    let parse = |text: &str| {
+        DecimalValue::parse(text).ok_or_else(|| {
+            DataFusionError::Execution(format!(
+                "invalid input syntax for type numeric: \"{}\"",
+                text
+            ))
+        })
+    };
+
+    match array.data_type() {
+        DataType::Null => Ok(vec![None; array.len()]),
+        DataType::Decimal(_, scale) => {
+            let values = array.as_any().downcast_ref::<DecimalArray>().unwrap();
+            Ok((0..values.len())
+                .map(|i| {
+                    if values.is_null(i) {
+                        None
+                    } else {
+                        Some(DecimalValue::new(values.value(i), *scale))
+                    }
+                })
+                .collect())
+        }
+        DataType::Utf8 => {
+            let values = downcast_string_arg!(array, "value", i32);
+            (0..values.len())
+                .map(|i| {
+                    if values.is_null(i) {
+                        Ok(None)
+                    } else {
+                        parse(values.value(i)).map(Some)
+                    }
+                })
+                .collect()
+        }
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => {
+            let values = cast(array, &DataType::Float64)?;
+            let values = downcast_primitive_arg!(values, "value", Float64Type);
+            (0..values.len())
+                .map(|i| {
+                    if values.is_null(i) {
+                        return Ok(None);
+                    }
+
+                    let value = values.value(i);
+                    if !value.is_finite() {
+                        return Err(DataFusionError::Execution(format!(
+                            "cannot convert {} to numeric",
+                            value
+                        )));
+                    }
+
+                    parse(&value.to_string()).map(Some)
+                })
+                .collect()
+        }
+        data_type if decimal_operand_type(data_type).is_some() => {
+            let values = cast(array, &DataType::Int64)?;
+            let values = downcast_primitive_arg!(values, "value", Int64Type);
+            Ok((0..values.len())
+                .map(|i| {
+                    if values.is_null(i) {
+                        None
+                    } else {
+                        Some(DecimalValue::new(values.value(i) as i128, 0))
+                    }
+                })
+                .collect())
+        }
+        data_type => Err(DataFusionError::Execution(format!(
+            "{:?} can't be converted to numeric",
+            data_type
+        ))),
+    }
+}
+
+/// Decimals of the type `(precision, scale)`, values which don't fit are an error
+fn decimal_array(
+    values: Vec<Option<DecimalValue>>,
+    precision: usize,
+    scale: usize,
+) -> Result<ArrayRef, DataFusionError> {
+    let mut builder = DecimalBuilder::new(values.len(), precision, scale);
+    for value in values {
+        match value {
+            None => builder.append_null()?,
+            Some(value) => {
+                let value = value
+                    .rescale(scale)
+                    .filter(|value| value.fits(precision))
+                    .ok_or_else(numeric_overflow)?;
+                builder.append_value(value.value())?;
+            }
+        }
+    }
+
+    Ok(Arc::new(builder.finish()) as ArrayRef)
+}
+
+fn decimal_operator(
+    op: NumericOperator,
+    left: &DecimalValue,
+    right: &DecimalValue,
+    scale: usize,
+) -> Result<DecimalValue, DataFusionError> {
+    let result = match op {
+        NumericOperator::Plus => left.checked_add(right),
+        NumericOperator::Minus => {
+            left.checked_add(&right.checked_neg().ok_or_else(numeric_overflow)?)
+        }
+        NumericOperator::Multiply => left.checked_mul(right),
+        NumericOperator::Divide if right.value() == 0 => {
+            return Err(DataFusionError::Execution("division by zero".to_string()))
+        }
+        NumericOperator::Divide => left.checked_div(right, scale),
+        _ => {
+            return Err(DataFusionError::Internal(format!(
+                "{} isn't an arithmetic operator",
+                op.function_name()
+            )))
+        }
+    };
+
+    result.ok_or_else(numeric_overflow)
+}
+
+fn float_operator(op: NumericOperator, left: f64, right: f64) -> Result<f64, DataFusionError> {
+    Ok(match op {
+        NumericOperator::Plus => left + right,
+        NumericOperator::Minus => left - right,
+        NumericOperator::Multiply => left * right,
+        NumericOperator::Divide if right == 0.0 => {
+            return Err(DataFusionError::Execution("division by zero".to_string()))
+        }
+        NumericOperator::Divide => left / right,
+        _ => {
+            return Err(DataFusionError::Internal(format!(
+                "{} isn't an arithmetic operator",
+                op.function_name()
+            )))
+        }
+    })
+}
+
+fn compare_operator(op: NumericOperator, ordering: Ordering) -> bool {
+    match op {
+        NumericOperator::Eq => ordering == Ordering::Equal,
+        NumericOperator::NotEq => ordering != Ordering::Equal,
+        NumericOperator::Lt => ordering == Ordering::Less,
+        NumericOperator::LtEq => ordering != Ordering::Greater,
+        NumericOperator::Gt => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Less,
+    }
+}
+
+/// numeric_add(a, b) and other functions of operators with decimals, the type of the result is
+/// resolved by `numeric_operator_coercion`. Integers are decimals without fractional digits.
+pub fn create_numeric_operator_udf(op: NumericOperator) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let return_type = numeric_operator_coercion(op, args[0].data_type(), args[1].data_type())
+            .ok_or_else(|| {
+            DataFusionError::Execution(format!(
+                "{} can't be applied to {:?} and {:?}",
+                op.function_name(),
+                args[0].data_type(),
+                args[1].data_type()
+            ))
+        })?;
+        let pairs = decimal_values(&args[0])?
+            .into_iter()
+            .zip(decimal_values(&args[1])?.into_iter());
+
+        match return_type {
+            DataType::Boolean => {
+                let mut builder = BooleanBuilder::new(args[0].len());
+                for pair in pairs {
+                    match pair {
+                        (Some(left), Some(right)) => {
+                            builder.append_value(compare_operator(op, left.cmp_value(&right)))?
+                        }
+                        _ => builder.append_null()?,
+                    }
+                }
+
+                Ok(Arc::new(builder.finish()) as ArrayRef)
+            }
+            DataType::Float64 => {
+                let mut builder = Float64Builder::new(args[0].len());
+                for pair in pairs {
+                    match pair {
+                        (Some(left), Some(right)) => builder.append_value(float_operator(
+                            op,
+                            left.to_f64(),
+                            right.to_f64(),
+                        )?)?,
+                        _ => builder.append_null()?,
+                    }
+                }
+
+                Ok(Arc::new(builder.finish()) as ArrayRef)
+            }
+            DataType::Decimal(precision, scale) => {
+                let values = pairs
+                    .map(|pair| match pair {
+                        (Some(left), Some(right)) => {
+                            decimal_operator(op, &left, &right, scale).map(Some)
+                        }
+                        _ => Ok(None),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                decimal_array(values, precision, scale)
+            }
+            data_type => Err(DataFusionError::Internal(format!(
+                "{} can't return {:?}",
+                op.function_name(),
+                data_type
+            ))),
+        }
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |types| {
+        numeric_operator_coercion(op, &types[0], &types[1])
+            .map(Arc::new)
+            .ok_or_else(|| {
+                DataFusionError::Plan(format!(
+                    "{} can't be applied to {:?} and {:?}",
+                    op.function_name(),
+                    types[0],
+                    types[1]
+                ))
+            })
+    });
+
+    ScalarUDF::new(
+        op.function_name(),
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+const NUMERIC_CAST_PREFIX: &str = "numeric_cast_";
+
+/// Name of the cast to `NUMERIC(precision, scale)`, as a function has one return type
+pub fn numeric_cast_name(precision: usize, scale: usize) -> String {
+    format!("{}{}_{}", NUMERIC_CAST_PREFIX, precision, scale)
+}
+
+/// Precision and scale of `numeric_cast_name`
+pub fn parse_numeric_cast_name(name: &str) -> Option<(usize, usize)> {
+    let (precision, scale) = name.strip_prefix(NUMERIC_CAST_PREFIX)?.split_once('_')?;
+    let (precision, scale) = (precision.parse().ok()?, scale.parse().ok()?);
+    if precision == 0 || precision > MAX_DECIMAL_PRECISION || scale > precision {
+        return None;
+    }
+
+    Some((precision, scale))
+}
+
+/// numeric_cast_<precision>_<scale>(value) casts numbers and text to decimals, extra fractional
+/// digits are rounded. They are resolved by name in `CubeContext`.
+pub fn create_numeric_cast_udf(precision: usize, scale: usize) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        decimal_array(decimal_values(&args[0])?, precision, scale)
+    });
+
+    let return_type: ReturnTypeFunction =
+        Arc::new(move |_| Ok(Arc::new(DataType::Decimal(precision, scale))));
+
+    ScalarUDF::new(
+        &numeric_cast_name(precision, scale),
+        &Signature::any(1, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// Aggregates of decimals, their results are text as there are no decimal scalars. The value is
+/// the sum for sum() and avg(), the smallest or the largest value for min() and max().
+#[derive(Debug)]
+struct NumericAccumulator {
+    fun: &'static str,
+    value: Option<DecimalValue>,
+    count: i64,
+}
+
+impl NumericAccumulator {
+    fn accumulate(&mut self, value: DecimalValue, count: i64) -> Result<(), DataFusionError> {
+        self.value = Some(match self.value {
+            None => value,
+            Some(current) => match self.fun {
+                "min" if value.cmp_value(&current) == Ordering::Less => value,
+                "max" if value.cmp_value(&current) == Ordering::Greater => value,
+                "min" | "max" => current,
+                _ => current.checked_add(&value).ok_or_else(numeric_overflow)?,
+            },
+        });
+        self.count += count;
+
+        Ok(())
+    }
+}
+
+impl Accumulator for NumericAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>, DataFusionError> {
+        Ok(vec![
+            ScalarValue::Utf8(self.value.map(|value| value.to_string())),
+            ScalarValue::Int64(Some(self.count)),
+        ])
+    }
+
+    fn update(&mut self, values: &[ScalarValue]) -> Result<(), DataFusionError> {
+        self.update_batch(&[values[0].to_array()])
+    }
+
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<(), DataFusionError> {
+        for value in decimal_values(&values[0])?.into_iter().flatten() {
+            self.accumulate(value, 1)?;
+        }
+
+        Ok(())
+    }
+
+    fn merge(&mut self, states: &[ScalarValue]) -> Result<(), DataFusionError> {
+        if let (ScalarValue::Utf8(Some(value)), ScalarValue::Int64(Some(count))) =
+            (&states[0], &states[1])
+        {
+            let value = DecimalValue::parse(value).ok_or_else(|| {
+                DataFusionError::Internal(format!("{} state isn't a number", self.fun))
+            })?;
+            self.accumulate(value, *count)?;
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue, DataFusionError> {
+        let value = match (self.fun, self.value) {
+            ("avg", Some(sum)) => {
+                let scale = sum.scale().max(MIN_DECIMAL_DIVISION_SCALE);
+                Some(
+                    sum.checked_div(&DecimalValue::new(self.count as i128, 0), scale)
+                        .ok_or_else(numeric_overflow)?,
+                )
+            }
+            (_, value) => value,
+        };
+
+        Ok(ScalarValue::Utf8(value.map(|value| value.to_string())))
+    }
+}
+
+/// numeric_sum(), numeric_avg(), numeric_min() and numeric_max() of decimals. Results are
+/// text, they are cast to the type of `decimal_aggregate_coercion` by the rewriting of queries.
+pub fn create_numeric_aggregate_udaf(fun: &'static str) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+    let accumulator: AccumulatorFunctionImplementation = Arc::new(move || {
+        Ok(Box::new(NumericAccumulator {
+            fun,
+            value: None,
+            count: 0,
+        }))
+    });
+    let state_type: StateTypeFunction =
+        Arc::new(|_| Ok(Arc::new(vec![DataType::Utf8, DataType::Int64])));
+
+    AggregateUDF::new(
+        &format!("numeric_{}", fun),
+        &Signature::any(1, Volatility::Immutable),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+pub const TO_TIMESTAMPTZ_FUNCTION: &str = "to_timestamptz";
+pub const TIMESTAMPTZ_TRUNC_FUNCTION: &str = "timestamptz_trunc";
+pub const TIMESTAMPTZ_PART_FUNCTION: &str = "timestamptz_part";
+pub const TIMESTAMPTZ_LOCAL_FUNCTION: &str = "timestamptz_local";
+/// `date_part` of timestamps without time zone, the built-in one knows only a few fields
+pub const TIMESTAMP_PART_FUNCTION: &str = "timestamp_part";
+
+/// Timestamps with time zone are UTC nanoseconds, the zone is applied by functions
+pub fn timestamptz_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".to_string()))
+}
+
+fn timestamptz_array(values: Vec<Option<i64>>) -> ArrayRef {
+    Arc::new(TimestampNanosecondArray::from_opt_vec(
+        values,
+        Some("UTC".to_string()),
+    ))
+}
+
+/// Nanoseconds of timestamps of any unit, with or without time zone, dates are midnights
+fn timestamp_nanos(array: &ArrayRef) -> Result<Vec<Option<i64>>, DataFusionError> {
+    let array = match array.data_type() {
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => array.clone(),
+        DataType::Date32 => {
+            let dates = downcast_primitive_arg!(array, "date", Date32Type);
+            return Ok(dates
+                .iter()
+                .map(|days| days.map(|days| days as i64 * 86_400_000_000_000))
+                .collect());
+        }
+        _ => cast(array, &DataType::Timestamp(TimeUnit::Nanosecond, None))?,
+    };
+    let array = downcast_primitive_arg!(array, "timestamp", TimestampNanosecondType);
+
+    Ok(array.iter().collect())
+}
+
+/// Units of date_trunc and fields of date_part are the same, plurals are allowed
+fn time_unit(unit: &str) -> String {
+    let unit = unit.trim().to_lowercase();
+    match unit.strip_suffix('s') {
+        Some(singular) if !singular.is_empty() => singular.to_string(),
+        _ => unit,
+    }
+}
+
+fn unrecognized_unit(unit: &str, with_zone: bool) -> DataFusionError {
+    DataFusionError::Execution(format!(
+        "unit \"{}\" not recognized for type timestamp {} time zone",
+        unit,
+        if with_zone { "with" } else { "without" }
+    ))
+}
+
+/// Start of the unit of a local time
+fn truncate_local_time(unit: &str, local: NaiveDateTime) -> Option<NaiveDateTime> {
+    let date = local.date();
+    let midnight = |date: NaiveDate| date.and_hms(0, 0, 0);
+
+    Some(match unit {
+        "microsecond" => local.with_nanosecond(local.nanosecond() / 1_000 * 1_000)?,
+        "millisecond" => local.with_nanosecond(local.nanosecond() / 1_000_000 * 1_000_000)?,
+        "second" => local.with_nanosecond(0)?,
+        "minute" => date.and_hms(local.hour(), local.minute(), 0),
+        "hour" => date.and_hms(local.hour(), 0, 0),
+        "day" => midnight(date),
+        "week" => midnight(date - Duration::days(date.weekday().num_days_from_monday() as i64)),
+        "month" => midnight(date.with_day(1)?),
+        "quarter" => midnight(NaiveDate::from_ymd(
+            date.year(),
+            (date.month() - 1) / 3 * 3 + 1,
+            1,
+        )),
+        "year" => midnight(NaiveDate::from_ymd(date.year(), 1, 1)),
+        "decade" => midnight(NaiveDate::from_ymd(date.year() - date.year() % 10, 1, 1)),
+        _ => return None,
+    })
+}
+
+/// Years, decades, centuries and millenniums of PostgreSQL, there is no year 0: the year 0
+/// of chrono is 1 BC
+fn year_period(year: i32, length: i32) -> i32 {
+    if year > 0 {
+        (year + length - 1) / length
+    } else {
+        -((length - 1 - (year - 1)) / length)
+    }
+}
+
+/// Field of a time as in PostgreSQL. `offset` is the offset from UTC of the local time of
+/// a timestamp with time zone, zone fields are not supported without it.
+fn time_part(field: &str, local: &NaiveDateTime, offset: Option<i32>) -> Option<f64> {
+    let seconds = local.second() as f64 + local.nanosecond() as f64 / 1_000_000_000.0;
+    let year = local.year();
+
+    Some(match field {
+        "millennium" => year_period(year, 1000) as f64,
+        "century" => year_period(year, 100) as f64,
+        "decade" if year >= 0 => (year / 10) as f64,
+        "decade" => (-((8 - (year - 1)) / 10)) as f64,
+        "year" => year_period(year, 1) as f64,
+        "isoyear" => local.iso_week().year() as f64,
+        "quarter" => ((local.month() - 1) / 3 + 1) as f64,
+        "month" => local.month() as f64,
+        "week" => local.iso_week().week() as f64,
+        "day" => local.day() as f64,
+        "dow" => local.weekday().num_days_from_sunday() as f64,
+        "isodow" => local.weekday().number_from_monday() as f64,
+        "doy" => local.ordinal() as f64,
+        "hour" => local.hour() as f64,
+        "minute" => local.minute() as f64,
+        "second" => seconds,
+        "millisecond" => seconds * 1_000.0,
+        "microsecond" => seconds * 1_000_000.0,
+        // Julian days are counted from November 24, 4714 BC, with a fraction of the day
+        "julian" => {
+            (local.num_days_from_ce() as i64 + 1_721_425) as f64
+                + local.num_seconds_from_midnight() as f64 / 86_400.0
+        }
+        "epoch" => {
+            (local.timestamp() - offset.unwrap_or(0) as i64) as f64
+                + local.nanosecond() as f64 / 1_000_000_000.0
+        }
+        "timezone" => offset? as f64,
+        "timezone_hour" => (offset? / 3600) as f64,
+        "timezone_minute" => (offset? % 3600 / 60) as f64,
+        _ => return None,
+    })
+}
+
+/// `CAST(value AS TIMESTAMPTZ)`, see `rewrite_timestamptz`. Text without an offset and
+/// timestamps without time zone are local times of `time_zone`, which is TimeZone of the session.
+pub fn create_to_timestamptz_udf(time_zone: Tz) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 1);
+
+        let values = match args[0].data_type() {
+            DataType::Null => vec![None; args[0].len()],
+            DataType::Utf8 => downcast_string_arg!(args[0], "value", i32)
+                .iter()
+                .map(|text| match text {
+                    Some(text) => parse_timestamptz(text, &time_zone)
+                        .map(Some)
+                        .ok_or_else(|| {
+                            DataFusionError::Execution(format!(
+                                "invalid input syntax for type timestamp with time zone: \"{}\"",
+                                text
+                            ))
+                        }),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            DataType::Timestamp(_, Some(_)) => timestamp_nanos(&args[0])?,
+            DataType::Timestamp(_, None) => timestamp_nanos(&args[0])?
+                .into_iter()
+                .map(|nanos| {
+                    nanos.map(|nanos| {
+                        local_to_unix_nano(&Utc.timestamp_nanos(nanos).naive_utc(), &time_zone)
+                    })
+                })
+                .collect(),
+            other => {
+                return Err(DataFusionError::Execution(format!(
+                    "cannot cast type {} to timestamp with time zone",
+                    other
+                )))
+            }
+        };
+
+        Ok(timestamptz_array(values))
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(timestamptz_type())));
+
+    ScalarUDF::new(
+        TO_TIMESTAMPTZ_FUNCTION,
+        &Signature::any(1, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// `date_trunc(unit, timestamptz)`: the local time of `time_zone` is truncated. As in
+/// PostgreSQL, units from a day are converted back in the zone, smaller units keep the offset
+/// of the value.
+pub fn create_timestamptz_trunc_udf(time_zone: Tz) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let units = downcast_string_arg!(args[0], "unit", i32);
+        let values = timestamp_nanos(&args[1])?;
+        let truncated = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, nanos)| {
+                let nanos = match nanos {
+                    Some(nanos) if !units.is_null(i) => nanos,
+                    _ => return Ok(None),
+                };
+                let unit = time_unit(units.value(i));
+                let local = local_time(nanos, &time_zone);
+                let start = truncate_local_time(&unit, local.naive_local())
+                    .ok_or_else(|| unrecognized_unit(units.value(i), true))?;
+
+                Ok(Some(match unit.as_str() {
+                    "microsecond" | "millisecond" | "second" | "minute" | "hour" => {
+                        let offset = local.offset().fix().local_minus_utc() as i64;
+                        start.timestamp_nanos() - offset * 1_000_000_000
+                    }
+                    _ => local_to_unix_nano(&start, &time_zone),
+                }))
+            })
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+
+        Ok(timestamptz_array(truncated))
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(timestamptz_type())));
+
+    ScalarUDF::new(
+        TIMESTAMPTZ_TRUNC_FUNCTION,
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// `date_part(field, timestamptz)` and `EXTRACT(field FROM timestamptz)` of the local time of
+/// `time_zone`
+pub fn create_timestamptz_part_udf(time_zone: Tz) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let fields = downcast_string_arg!(args[0], "field", i32);
+        let values = timestamp_nanos(&args[1])?;
+        let parts = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, nanos)| match nanos {
+                Some(nanos) if !fields.is_null(i) => {
+                    let local = local_time(nanos, &time_zone);
+                    let offset = local.offset().fix().local_minus_utc();
+                    time_part(
+                        &time_unit(fields.value(i)),
+                        &local.naive_local(),
+                        Some(offset),
+                    )
+                    .map(Some)
+                    .ok_or_else(|| unrecognized_unit(fields.value(i), true))
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+
+        Ok(Arc::new(Float64Array::from(parts)) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Float64)));
+
+    ScalarUDF::new(
+        TIMESTAMPTZ_PART_FUNCTION,
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// `date_part(field, timestamp)` and `EXTRACT(field FROM timestamp)` of timestamps without
+/// time zone and dates, see `rewrite_timestamptz`
+pub fn create_timestamp_part_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let fields = downcast_string_arg!(args[0], "field", i32);
+        let values = timestamp_nanos(&args[1])?;
+        let parts = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, nanos)| match nanos {
+                Some(nanos) if !fields.is_null(i) => {
+                    let value = NaiveDateTime::from_timestamp(
+                        nanos.div_euclid(1_000_000_000),
+                        nanos.rem_euclid(1_000_000_000) as u32,
+                    );
+                    time_part(&time_unit(fields.value(i)), &value, None)
+                        .map(Some)
+                        .ok_or_else(|| unrecognized_unit(fields.value(i), false))
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, DataFusionError>>()?;
+
+        Ok(Arc::new(Float64Array::from(parts)) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Float64)));
+
+    ScalarUDF::new(
+        TIMESTAMP_PART_FUNCTION,
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// `CAST(timestamptz AS TIMESTAMP)` is the local time of `time_zone`
+pub fn create_timestamptz_local_udf(time_zone: Tz) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 1);
+
+        let values = timestamp_nanos(&args[0])?
+            .into_iter()
+            .map(|nanos| {
+                nanos.map(|nanos| {
+                    local_time(nanos, &time_zone)
+                        .naive_local()
+                        .timestamp_nanos()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Arc::new(TimestampNanosecondArray::from_opt_vec(values, None)) as ArrayRef)
+    });
+
+    create_udf(
+        TIMESTAMPTZ_LOCAL_FUNCTION,
+        vec![timestamptz_type()],
+        Arc::new(DataType::Timestamp(TimeUnit::Nanosecond, None)),
+        Volatility::Immutable,
+        fun,
+    )
+}
+
+/// `to_char(value, template)` of timestamps, dates and numbers, timestamps with time zone are
+/// formatted in `time_zone`
+pub fn create_to_char_udf(time_zone: Tz) -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        assert!(args.len() == 2);
+
+        let templates = downcast_string_arg!(args[1], "template", i32);
+        let template = |i: usize| {
+            if templates.is_null(i) {
+                None
+            } else {
+                Some(templates.value(i))
+            }
+        };
+
+        let values = match args[0].data_type() {
+            DataType::Timestamp(_, zone) => {
+                let with_zone = zone.is_some();
+                timestamp_nanos(&args[0])?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, nanos)| {
+                        let (nanos, template) = (nanos?, template(i)?);
+                        if !with_zone {
+                            let value = NaiveDateTime::from_timestamp(
+                                nanos.div_euclid(1_000_000_000),
+                                nanos.rem_euclid(1_000_000_000) as u32,
+                            );
+                            return Some(format_timestamp(&value, None, template));
+                        }
+
+                        let local = local_time(nanos, &time_zone);
+                        let zone = FormatZone {
+                            abbreviation: local.format("%Z").to_string(),
+                            offset: local.offset().fix().local_minus_utc(),
+                        };
+                        Some(format_timestamp(
+                            &local.naive_local(),
+                            Some(&zone),
+                            template,
+                        ))
+                    })
+                    .collect::<Vec<_>>()
+            }
+            DataType::Date32 => {
+                let dates = downcast_primitive_arg!(args[0], "value", Date32Type);
+                // Days are counted from 1970-01-01, which is the day 719163 of the common era
+                (0..dates.len())
+                    .map(|i| {
+                        if dates.is_null(i) {
+                            return None;
+                        }
+
+                        let value = NaiveDate::from_num_days_from_ce(dates.value(i) + 719_163)
+                            .and_hms(0, 0, 0);
+                        Some(format_timestamp(&value, None, template(i)?))
+                    })
+                    .collect::<Vec<_>>()
+            }
+            _ => decimal_values(&args[0])?
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| Some(format_number(&value?, template(i)?)))
+                .collect::<Vec<_>>(),
+        };
+
+        let mut builder = StringBuilder::new(values.len());
+        for value in values {
+            match value {
+                Some(value) => builder.append_value(value)?,
+                None => builder.append_null()?,
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        "to_char",
+        &Signature::any(2, Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
+
+/// Regular expression of a PostgreSQL pattern with its flags: `i` matches case-insensitively,
+/// `c` case-sensitively, `n` (or `m`) is newline-sensitive, `.` matches newlines otherwise
+fn regexp_pattern(pattern: &str, flags: &str) -> Result<Regex, DataFusionError> {
+    let mut builder = RegexBuilder::new(pattern);
+    builder.dot_matches_new_line(true);
+    for flag in flags.chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            'c' => builder.case_insensitive(false),
+            'n' | 'm' => builder.dot_matches_new_line(false).multi_line(true),
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "invalid regular expression option: \"{}\"",
+                    flag
+                )))
+            }
+        };
+    }
+
+    builder.build().map_err(|e| {
+        DataFusionError::Execution(format!("invalid regular expression: {}", e.to_string()))
+    })
+}
+
+/// Compiled pattern of the previous row, patterns are constants in most queries
+#[derive(Default)]
+struct RegexpCache {
+    last: Option<(String, String, Regex)>,
+}
+
+impl RegexpCache {
+    fn get(&mut self, pattern: &str, flags: &str) -> Result<&Regex, DataFusionError> {
+        let cached = matches!(&self.last, Some((p, f, _)) if p == pattern && f == flags);
+        if !cached {
+            let regex = regexp_pattern(pattern, flags)?;
+            self.last = Some((pattern.to_string(), flags.to_string(), regex));
+        }
+
+        match &self.last {
+            Some((_, _, regex)) => Ok(regex),
+            None => Err(DataFusionError::Internal(
+                "Regular expression is not compiled".to_string(),
+            )),
+        }
+    }
+}
+
+/// regexp_matches(string, pattern [, flags]) returns substrings of groups of the first match
+/// (or the whole match for patterns without groups) as text[], NULL if the string doesn't
+/// match. PostgreSQL returns a row of every match for the `g` flag, which isn't supported.
+pub fn create_regexp_matches_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let strings = downcast_string_arg!(args[0], "string", i32);
+        let patterns = downcast_string_arg!(args[1], "pattern", i32);
+        let flags = match args.get(2) {
+            Some(flags) => Some(downcast_string_arg!(flags, "flags", i32)),
+            None => None,
+        };
+
+        let mut cache = RegexpCache::default();
+        let mut builder = ListBuilder::new(StringBuilder::new(strings.len()));
+        for i in 0..strings.len() {
+            let row_flags = match flags {
+                Some(flags) if flags.is_null(i) => None,
+                Some(flags) => Some(flags.value(i)),
+                None => Some(""),
+            };
+            let (string, pattern, row_flags) = match row_flags {
+                Some(row_flags) if !strings.is_null(i) && !patterns.is_null(i) => {
+                    (strings.value(i), patterns.value(i), row_flags)
+                }
+                _ => {
+                    builder.append(false)?;
+                    continue;
+                }
+            };
+            if row_flags.contains('g') {
+                return Err(DataFusionError::Execution(
+                    "regexp_matches() doesn't support the \"global\" option".to_string(),
+                ));
+            }
+
+            let captures = match cache.get(pattern, row_flags)?.captures(string) {
+                Some(captures) => captures,
+                None => {
+                    builder.append(false)?;
+                    continue;
+                }
+            };
+            if captures.len() == 1 {
+                builder.values().append_value(&captures[0])?;
+            } else {
+                for group in captures.iter().skip(1) {
+                    match group {
+                        Some(group) => builder.values().append_value(group.as_str())?,
+                        None => builder.values().append_null()?,
+                    }
+                }
+            }
+            builder.append(true)?;
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction =
+        Arc::new(move |_| Ok(Arc::new(list_type(&DataType::Utf8))));
+
+    ScalarUDF::new(
+        "regexp_matches",
+        &Signature::one_of(
+            vec![
+                TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8]),
+                TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8, DataType::Utf8]),
+            ],
+            Volatility::Immutable,
+        ),
+        &return_type,
+        &fun,
+    )
+}
+
+/// `substring(string FROM pattern)`, see `rewrite_regexp_substrings`
+pub const REGEXP_SUBSTRING_FUNCTION: &str = "regexp_substring";
+
+/// regexp_substring(string, pattern) returns the substring of the first group of the first
+/// match (or the whole match for patterns without groups), NULL if the string doesn't match
+pub fn create_regexp_substring_udf() -> ScalarUDF {
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        let strings = downcast_string_arg!(args[0], "string", i32);
+        let patterns = downcast_string_arg!(args[1], "pattern", i32);
+
+        let mut cache = RegexpCache::default();
+        let mut builder = StringBuilder::new(strings.len());
+        for i in 0..strings.len() {
+            if strings.is_null(i) || patterns.is_null(i) {
+                builder.append_null()?;
+                continue;
+            }
+
+            let captures = cache.get(patterns.value(i), "")?.captures(strings.value(i));
+            let group = captures.and_then(|captures| match captures.get(1) {
+                Some(group) => Some(group),
+                None if captures.len() == 1 => captures.get(0),
+                None => None,
+            });
+            match group {
+                Some(group) => builder.append_value(group.as_str())?,
+                None => builder.append_null()?,
+            }
+        }
+
+        Ok(Arc::new(builder.finish()) as ArrayRef)
+    });
+
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(DataType::Utf8)));
+
+    ScalarUDF::new(
+        REGEXP_SUBSTRING_FUNCTION,
+        &Signature::exact(vec![DataType::Utf8, DataType::Utf8], Volatility::Immutable),
+        &return_type,
+        &fun,
+    )
+}
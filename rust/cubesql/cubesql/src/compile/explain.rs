@@ -0,0 +1,261 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use cubeclient::models::V1LoadRequestQuery;
+use datafusion::{
+    logical_plan::{Expr, LogicalPlan},
+    physical_plan::collect,
+};
+use sqlparser::ast;
+
+use crate::{
+    sql::{
+        dataframe::{Column, DataFrame, Row, TableValue},
+        ColumnFlags, ColumnType, Session, StatusFlags,
+    },
+    CubeError,
+};
+
+use super::{
+    convert_statement_to_cube_query,
+    engine::df::scan::{cube_scan_stats, CubeScanNode},
+    MetaContext, QueryPlan,
+};
+
+/// How much of the statement is executed by the Cube API
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PushdownStatus {
+    /// DataFusion only renames columns of Cube queries
+    Full,
+    /// Results of Cube queries are post-processed by DataFusion
+    Partial,
+    /// The statement doesn't query cubes
+    None,
+}
+
+impl PushdownStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PushdownStatus::Full => "full",
+            PushdownStatus::Partial => "partial",
+            PushdownStatus::None => "none",
+        }
+    }
+}
+
+/// Description of the plan for EXPLAIN: nodes of the plan with the engine, which executes them
+/// (and why it's not Cube), and Cube queries of CubeScan nodes
+pub struct PlanExplanation {
+    pub status: PushdownStatus,
+    pub plan: Vec<String>,
+    pub cube_queries: Vec<V1LoadRequestQuery>,
+}
+
+impl PlanExplanation {
+    pub fn new(plan: &QueryPlan, meta: &MetaContext) -> Self {
+        match plan {
+            QueryPlan::DataFusionSelect(_, plan, _) => {
+                let mut explainer = Explainer {
+                    meta,
+                    lines: vec![],
+                    cube_queries: vec![],
+                    post_processed: false,
+                };
+                explainer.visit(plan, 0);
+
+                let status = if explainer.cube_queries.is_empty() {
+                    PushdownStatus::None
+                } else if explainer.post_processed {
+                    PushdownStatus::Partial
+                } else {
+                    PushdownStatus::Full
+                };
+
+                Self {
+                    status,
+                    plan: explainer.lines,
+                    cube_queries: explainer.cube_queries,
+                }
+            }
+            QueryPlan::MetaOk(_) | QueryPlan::MetaTabular(_, _) => Self {
+                status: PushdownStatus::None,
+                plan: vec!["Result  [SQL API: the result is known without execution]".to_string()],
+                cube_queries: vec![],
+            },
+        }
+    }
+
+    /// Lines of QUERY PLAN, Cube queries are printed as JSON after the plan
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Pushdown: {}", self.status.as_str())];
+        lines.extend(self.plan.iter().cloned());
+
+        for (i, query) in self.cube_queries.iter().enumerate() {
+            lines.push(format!("Cube query {}:", i + 1));
+            let json = serde_json::to_string_pretty(query).unwrap_or_default();
+            lines.extend(json.lines().map(|line| format!("  {}", line)));
+        }
+
+        lines
+    }
+}
+
+/// Result of EXPLAIN in PostgreSQL, every line is a row
+pub fn query_plan(lines: Vec<String>) -> QueryPlan {
+    QueryPlan::MetaTabular(
+        StatusFlags::empty(),
+        Arc::new(DataFrame::new(
+            vec![Column::new(
+                "QUERY PLAN".to_string(),
+                ColumnType::String,
+                ColumnFlags::empty(),
+            )],
+            lines
+                .into_iter()
+                .map(|line| Row::new(vec![TableValue::String(line)]))
+                .collect(),
+        )),
+    )
+}
+
+/// EXPLAIN ANALYZE executes the statement, times of its stages and numbers of rows are reported
+/// after the plan. The time of DataFusion is the time of the execution without requests to the
+/// Cube API.
+pub async fn explain_analyze(
+    stmt: &ast::Statement,
+    meta: Arc<MetaContext>,
+    session: Arc<Session>,
+) -> Result<QueryPlan, CubeError> {
+    let started = Instant::now();
+    let plan = convert_statement_to_cube_query(stmt, meta.clone(), session)?;
+    let rewrite_time = started.elapsed();
+    let mut lines = PlanExplanation::new(&plan, &meta).lines();
+
+    let started = Instant::now();
+    let (rows, cube_scans) = match plan {
+        QueryPlan::DataFusionSelect(_, plan, ctx) => {
+            let plan = ctx.optimize(&plan)?;
+            let plan = ctx.create_physical_plan(&plan).await?;
+            let batches = collect(plan.clone()).await?;
+
+            let rows = batches.iter().map(|batch| batch.num_rows()).sum();
+            (rows, cube_scan_stats(&plan))
+        }
+        QueryPlan::MetaTabular(_, frame) => (frame.len(), vec![]),
+        QueryPlan::MetaOk(_) => (0, vec![]),
+    };
+    let execution_time = started.elapsed();
+
+    lines.push(format!("Rewrite Time: {}", format_time(rewrite_time)));
+    let mut cube_time = Duration::default();
+    for (i, stats) in cube_scans.iter().enumerate() {
+        match stats {
+            Some(stats) => {
+                cube_time += stats.load_time;
+                lines.push(format!(
+                    "Cube query {}: time={} rows={}",
+                    i + 1,
+                    format_time(stats.load_time),
+                    stats.rows
+                ));
+            }
+            None => lines.push(format!("Cube query {}: never executed", i + 1)),
+        }
+    }
+    lines.push(format!(
+        "DataFusion Time: {}",
+        format_time(execution_time.saturating_sub(cube_time))
+    ));
+    lines.push(format!("Execution Time: {}", format_time(execution_time)));
+    lines.push(format!("Rows: {}", rows));
+
+    Ok(query_plan(lines))
+}
+
+fn format_time(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+struct Explainer<'a> {
+    meta: &'a MetaContext,
+    lines: Vec<String>,
+    cube_queries: Vec<V1LoadRequestQuery>,
+    // There are nodes, which change results of Cube queries
+    post_processed: bool,
+}
+
+impl<'a> Explainer<'a> {
+    fn visit(&mut self, plan: &LogicalPlan, depth: usize) {
+        let indent = "  ".repeat(depth);
+
+        if let LogicalPlan::Extension { node } = plan {
+            if let Some(scan) = node.as_any().downcast_ref::<CubeScanNode>() {
+                self.cube_queries.push(scan.request.clone());
+                self.lines.push(format!(
+                    "{}CubeScan: Cube query {}  [Cube]",
+                    indent,
+                    self.cube_queries.len()
+                ));
+
+                return;
+            }
+        }
+
+        let reason = if is_renaming(plan) {
+            "columns are renamed"
+        } else {
+            self.post_processed = true;
+            self.not_pushed_down_reason(plan)
+        };
+        self.lines.push(format!(
+            "{}{}  [DataFusion: {}]",
+            indent,
+            plan.display(),
+            reason
+        ));
+
+        for input in plan.inputs() {
+            self.visit(input, depth + 1);
+        }
+    }
+
+    fn not_pushed_down_reason(&self, plan: &LogicalPlan) -> &'static str {
+        match plan {
+            LogicalPlan::Projection { .. } => "columns are computed",
+            LogicalPlan::Filter { .. } => "the condition can't be expressed by Cube filters",
+            LogicalPlan::Aggregate { .. } => {
+                "the aggregation can't be expressed by measures and dimensions"
+            }
+            LogicalPlan::Sort { .. } => "the order can't be expressed by the Cube query",
+            LogicalPlan::Limit { .. } => "the limit is applied to results of DataFusion",
+            LogicalPlan::Join { .. } | LogicalPlan::CrossJoin { .. } => {
+                "joins are not pushed down to Cube"
+            }
+            LogicalPlan::Union { .. } => "unions are not pushed down to Cube",
+            LogicalPlan::TableScan { table_name, .. } => {
+                let cube = table_name.rsplit('.').next().unwrap_or(table_name);
+                if self.meta.find_cube_with_name(cube.to_string()).is_some() {
+                    "the cube couldn't be rewritten into a Cube query"
+                } else {
+                    "the table is not a cube"
+                }
+            }
+            LogicalPlan::EmptyRelation { .. } => "the statement has no tables",
+            _ => "the node is not supported by Cube queries",
+        }
+    }
+}
+
+/// Projection, which selects columns of its input without computations
+fn is_renaming(plan: &LogicalPlan) -> bool {
+    match plan {
+        LogicalPlan::Projection { expr, .. } => expr.iter().all(|expr| match expr {
+            Expr::Column(_) => true,
+            Expr::Alias(expr, _) => matches!(expr.as_ref(), Expr::Column(_)),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
@@ -0,0 +1,453 @@
+use std::convert::TryFrom;
+
+use datafusion::{
+    logical_plan::{Column, Expr, LogicalPlan, LogicalPlanBuilder},
+    scalar::ScalarValue,
+};
+use sqlparser::ast;
+
+use super::{CompilationError, CompilationResult};
+
+/// `GROUPING SETS (...)` and its sets are not parsed by the pinned sqlparser, they are
+/// rewritten into calls of these functions by `parse_sql_to_statement` for PostgreSQL
+pub const GROUPING_SETS_FUNCTION: &str = "__grouping_sets";
+pub const GROUPING_SET_FUNCTION: &str = "__grouping_set";
+
+/// The same limit as in PostgreSQL
+const MAX_GROUPING_SETS: usize = 4096;
+
+fn function_name(expr: &ast::Expr) -> Option<String> {
+    match expr {
+        ast::Expr::Function(fun) if fun.over.is_none() => Some(fun.name.to_string().to_lowercase()),
+        _ => None,
+    }
+}
+
+fn function_args(expr: &ast::Expr) -> CompilationResult<Vec<ast::Expr>> {
+    match expr {
+        ast::Expr::Function(fun) => fun
+            .args
+            .iter()
+            .map(|arg| match arg {
+                ast::FunctionArg::Unnamed(expr) => Ok(expr.clone()),
+                ast::FunctionArg::Named { .. } => Err(CompilationError::Unsupported(
+                    "Named arguments of grouping functions".to_string(),
+                )),
+            })
+            .collect(),
+        _ => Ok(vec![]),
+    }
+}
+
+fn is_grouping_element(expr: &ast::Expr) -> bool {
+    match function_name(expr) {
+        Some(name) => name == "rollup" || name == "cube" || name == GROUPING_SETS_FUNCTION,
+        None => false,
+    }
+}
+
+fn contains(exprs: &[ast::Expr], expr: &ast::Expr) -> bool {
+    let expr = expr.to_string();
+    exprs.iter().any(|e| e.to_string() == expr)
+}
+
+/// Grouping sets of an element of GROUP BY, in the order of PostgreSQL
+fn element_sets(element: &ast::Expr) -> CompilationResult<Vec<Vec<ast::Expr>>> {
+    match function_name(element).as_deref() {
+        Some("rollup") => {
+            let args = function_args(element)?;
+            Ok((0..=args.len())
+                .rev()
+                .map(|len| args[..len].to_vec())
+                .collect())
+        }
+        Some("cube") => {
+            let args = function_args(element)?;
+            if 1usize.checked_shl(args.len() as u32).unwrap_or(usize::MAX) > MAX_GROUPING_SETS {
+                return Err(CompilationError::Unsupported(format!(
+                    "CUBE with more than {} grouping sets",
+                    MAX_GROUPING_SETS
+                )));
+            }
+
+            Ok((0..1usize << args.len())
+                .rev()
+                .map(|mask| {
+                    args.iter()
+                        .enumerate()
+                        .filter(|(i, _)| mask & (1 << (args.len() - 1 - i)) != 0)
+                        .map(|(_, arg)| arg.clone())
+                        .collect()
+                })
+                .collect())
+        }
+        Some(GROUPING_SETS_FUNCTION) => {
+            let mut sets = vec![];
+            for arg in function_args(element)? {
+                if function_name(&arg).as_deref() == Some(GROUPING_SET_FUNCTION) {
+                    sets.push(function_args(&arg)?);
+                } else {
+                    sets.extend(element_sets(&arg)?);
+                }
+            }
+
+            Ok(sets)
+        }
+        _ => Ok(vec![vec![element.clone()]]),
+    }
+}
+
+/// Grouping sets of the select, if it's grouped by ROLLUP, CUBE or GROUPING SETS. Several
+/// elements of GROUP BY are combined by the cross product, positions are replaced by
+/// expressions of the projection.
+pub fn grouping_sets(select: &ast::Select) -> CompilationResult<Option<Vec<Vec<ast::Expr>>>> {
+    if !select.group_by.iter().any(is_grouping_element) {
+        return Ok(None);
+    }
+
+    let mut sets = vec![vec![]];
+    for element in select.group_by.iter() {
+        let element_sets = element_sets(element)?;
+        if sets.len() * element_sets.len() > MAX_GROUPING_SETS {
+            return Err(CompilationError::Unsupported(format!(
+                "Query with more than {} grouping sets",
+                MAX_GROUPING_SETS
+            )));
+        }
+
+        let mut product = Vec::with_capacity(sets.len() * element_sets.len());
+        for set in sets.iter() {
+            for element_set in element_sets.iter() {
+                let mut set = set.clone();
+                set.extend(element_set.iter().cloned());
+                product.push(set);
+            }
+        }
+        sets = product;
+    }
+
+    for set in sets.iter_mut() {
+        for expr in set.iter_mut() {
+            if let ast::Expr::Value(ast::Value::Number(position, _)) = expr {
+                let item = position
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|position| select.projection.get(position.wrapping_sub(1)));
+                *expr = match item {
+                    Some(ast::SelectItem::UnnamedExpr(item))
+                    | Some(ast::SelectItem::ExprWithAlias { expr: item, .. }) => item.clone(),
+                    _ => {
+                        return Err(CompilationError::User(format!(
+                            "GROUP BY position {} is not in select list",
+                            position
+                        )))
+                    }
+                };
+            }
+        }
+    }
+
+    Ok(Some(sets))
+}
+
+/// Column of the result, which is merged from results of grouping sets or CASE buckets
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupingColumn {
+    /// Column of the Cube query by its position
+    Member(usize),
+    /// Grouping column, which is not in the grouping set
+    Null,
+    /// Value of `GROUPING(...)`
+    Grouping(i64),
+    /// Value of the CASE bucket
+    Value(ScalarValue),
+}
+
+/// Query of a grouping set, it's compiled into a Cube query
+pub struct GroupingSetQuery {
+    pub query: ast::Query,
+    pub columns: Vec<GroupingColumn>,
+}
+
+impl GroupingSetQuery {
+    /// Grouping columns, which are not in `set`, are not selected, they are NULL in the result.
+    /// Members of `set` are selected even when they are not in the projection, as Cube queries
+    /// are grouped by selected dimensions.
+    pub fn new(
+        query: &ast::Query,
+        select: &ast::Select,
+        sets: &[Vec<ast::Expr>],
+        set: &[ast::Expr],
+    ) -> CompilationResult<Self> {
+        let grouped = sets.iter().flatten().cloned().collect::<Vec<_>>();
+
+        let mut projection = vec![];
+        let mut columns = vec![];
+        for item in select.projection.iter() {
+            let expr = match item {
+                ast::SelectItem::UnnamedExpr(expr)
+                | ast::SelectItem::ExprWithAlias { expr, .. } => expr,
+                _ => {
+                    return Err(CompilationError::Unsupported(
+                        "Wildcards can't be selected with grouping sets".to_string(),
+                    ))
+                }
+            };
+
+            if function_name(expr).as_deref() == Some("grouping") {
+                let mut mask = 0;
+                for arg in function_args(expr)? {
+                    if !contains(&grouped, &arg) {
+                        return Err(CompilationError::User(
+                            "Arguments to GROUPING must be grouping expressions".to_string(),
+                        ));
+                    }
+
+                    mask = (mask << 1) | (!contains(set, &arg) as i64);
+                }
+
+                columns.push(GroupingColumn::Grouping(mask));
+            } else if contains(&grouped, expr) && !contains(set, expr) {
+                columns.push(GroupingColumn::Null);
+            } else {
+                columns.push(GroupingColumn::Member(projection.len()));
+                projection.push(item.clone());
+            }
+        }
+
+        for expr in set.iter() {
+            let selected = projection.iter().any(|item| match item {
+                ast::SelectItem::UnnamedExpr(item)
+                | ast::SelectItem::ExprWithAlias { expr: item, .. } => {
+                    item.to_string() == expr.to_string()
+                }
+                _ => false,
+            });
+            if !selected {
+                projection.push(ast::SelectItem::UnnamedExpr(expr.clone()));
+            }
+        }
+
+        let mut set_select = select.clone();
+        set_select.projection = projection;
+        set_select.group_by = set.to_vec();
+
+        let mut set_query = query.clone();
+        set_query.body = ast::SetExpr::Select(Box::new(set_select));
+        set_query.order_by = vec![];
+        set_query.limit = None;
+        set_query.offset = None;
+
+        Ok(Self {
+            query: set_query,
+            columns,
+        })
+    }
+}
+
+/// Merges plans of grouping sets by UNION ALL, ORDER BY and LIMIT of `query` are applied to
+/// the merged result
+pub fn merge_grouping_sets(
+    plans: Vec<(LogicalPlan, Vec<GroupingColumn>)>,
+    select: &ast::Select,
+    query: &ast::Query,
+) -> CompilationResult<LogicalPlan> {
+    let mut names = vec![];
+    let mut types = vec![];
+    for (i, item) in select.projection.iter().enumerate() {
+        let member = plans.iter().find_map(|(plan, columns)| match columns[i] {
+            GroupingColumn::Member(position) => Some(plan.schema().field(position)),
+            _ => None,
+        });
+
+        names.push(match (item, member) {
+            (ast::SelectItem::ExprWithAlias { alias, .. }, _) => alias.value.clone(),
+            (_, Some(field)) => field.name().clone(),
+            (ast::SelectItem::UnnamedExpr(expr), None) => function_name(expr)
+                .filter(|name| name == "grouping")
+                .unwrap_or_else(|| expr.to_string()),
+            (item, None) => item.to_string(),
+        });
+        types.push(member.map(|field| field.data_type().clone()));
+    }
+
+    let mut builder: Option<LogicalPlanBuilder> = None;
+    for (plan, columns) in plans.into_iter() {
+        let mut projection = vec![];
+        for (i, column) in columns.iter().enumerate() {
+            let expr = match column {
+                GroupingColumn::Member(position) => Expr::Column(Column {
+                    relation: None,
+                    name: plan.schema().field(*position).name().clone(),
+                }),
+                GroupingColumn::Null => Expr::Literal(match &types[i] {
+                    Some(data_type) => ScalarValue::try_from(data_type)?,
+                    None => ScalarValue::Utf8(None),
+                }),
+                GroupingColumn::Grouping(mask) => Expr::Literal(ScalarValue::Int64(Some(*mask))),
+                GroupingColumn::Value(value) => Expr::Literal(value.clone()),
+            };
+            projection.push(Expr::Alias(Box::new(expr), names[i].clone()));
+        }
+
+        let plan = LogicalPlanBuilder::from(plan)
+            .project(projection)?
+            .build()?;
+        builder = Some(match builder {
+            Some(builder) => builder.union(plan)?,
+            None => LogicalPlanBuilder::from(plan),
+        });
+    }
+    let mut builder = builder.ok_or_else(|| {
+        CompilationError::Internal("Query without grouping sets can't be merged".to_string())
+    })?;
+
+    if !query.order_by.is_empty() {
+        let mut sort = vec![];
+        for order_by in query.order_by.iter() {
+            let name = match &order_by.expr {
+                ast::Expr::Value(ast::Value::Number(position, _)) => position
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|position| names.get(position.wrapping_sub(1))),
+                ast::Expr::Identifier(ident) => names
+                    .iter()
+                    .find(|name| name.eq_ignore_ascii_case(&ident.value)),
+                expr => select
+                    .projection
+                    .iter()
+                    .position(|item| match item {
+                        ast::SelectItem::UnnamedExpr(item)
+                        | ast::SelectItem::ExprWithAlias { expr: item, .. } => item == expr,
+                        _ => false,
+                    })
+                    .map(|position| &names[position]),
+            };
+            let name = name.ok_or_else(|| {
+                CompilationError::Unsupported(format!(
+                    "ORDER BY {} with grouping sets, only columns of the result can be used",
+                    order_by.expr
+                ))
+            })?;
+
+            let asc = order_by.asc.unwrap_or(true);
+            sort.push(Expr::Sort {
+                expr: Box::new(Expr::Column(Column {
+                    relation: None,
+                    name: name.clone(),
+                })),
+                asc,
+                nulls_first: order_by.nulls_first.unwrap_or(!asc),
+            });
+        }
+
+        builder = builder.sort(sort)?;
+    }
+
+    if query.offset.is_some() {
+        return Err(CompilationError::Unsupported(
+            "Query with OFFSET and grouping sets".to_string(),
+        ));
+    }
+
+    if let Some(limit) = &query.limit {
+        let limit = limit
+            .to_string()
+            .parse::<usize>()
+            .map_err(|e| CompilationError::Unsupported(format!("Unable to parse limit: {}", e)))?;
+        builder = builder.limit(limit)?;
+    }
+
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+
+    fn select(sql: &str) -> Box<ast::Select> {
+        match Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0)
+        {
+            ast::Statement::Query(query) => match query.body {
+                ast::SetExpr::Select(select) => select,
+                _ => panic!("{} must be a select", sql),
+            },
+            _ => panic!("{} must be a query", sql),
+        }
+    }
+
+    fn sets(sql: &str) -> Vec<String> {
+        grouping_sets(&select(sql))
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|set| {
+                let set = set.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                format!("({})", set.join(", "))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_grouping_sets() {
+        assert_eq!(
+            sets("SELECT a, b, COUNT(*) FROM t GROUP BY ROLLUP(a, b)"),
+            vec!["(a, b)", "(a)", "()"]
+        );
+        assert_eq!(
+            sets("SELECT a, b, COUNT(*) FROM t GROUP BY CUBE(1, 2)"),
+            vec!["(a, b)", "(a)", "(b)", "()"]
+        );
+        assert_eq!(
+            sets("SELECT a, b, c FROM t GROUP BY c, __grouping_sets(__grouping_set(a, b), a, __grouping_set())"),
+            vec!["(c, a, b)", "(c, a)", "(c)"]
+        );
+        assert_eq!(
+            grouping_sets(&select("SELECT a, COUNT(*) FROM t GROUP BY a")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_grouping_set_query() -> Result<(), CompilationError> {
+        let sql = "SELECT a, b AS bb, COUNT(*), GROUPING(a, b) AS g FROM t GROUP BY ROLLUP(a, b) ORDER BY 1 LIMIT 10";
+        let query = match Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0)
+        {
+            ast::Statement::Query(query) => query,
+            _ => panic!("{} must be a query", sql),
+        };
+        let select = select(sql);
+        let sets = grouping_sets(&select)?.unwrap();
+
+        let set_query = GroupingSetQuery::new(&query, &select, &sets, &sets[1])?;
+        assert_eq!(
+            set_query.query.to_string(),
+            "SELECT a, COUNT(*) FROM t GROUP BY a"
+        );
+        assert_eq!(
+            set_query.columns,
+            vec![
+                GroupingColumn::Member(0),
+                GroupingColumn::Null,
+                GroupingColumn::Member(1),
+                GroupingColumn::Grouping(1),
+            ]
+        );
+
+        // Members of the set are selected for grouping of the Cube query
+        let select = self::select("SELECT b, COUNT(*) FROM t GROUP BY ROLLUP(a, b)");
+        let sets = grouping_sets(&select)?.unwrap();
+        let set_query = GroupingSetQuery::new(&query, &select, &sets, &sets[0])?;
+        assert_eq!(
+            set_query.query.to_string(),
+            "SELECT b, COUNT(*), a FROM t GROUP BY a, b"
+        );
+
+        Ok(())
+    }
+}
@@ -6,7 +6,8 @@ use chrono::{prelude::*, Duration};
 use datafusion::arrow::datatypes::DataType;
 use datafusion::logical_plan::{DFField, DFSchema, DFSchemaRef, Expr};
 use datafusion::sql::parser::Statement as DFStatement;
-use datafusion::sql::planner::SqlToRel;
+use datafusion::catalog::TableReference;
+use datafusion::sql::planner::{ContextProvider, SqlToRel};
 use datafusion::variable::VarType;
 use datafusion::{logical_plan::LogicalPlan, prelude::*};
 use log::{debug, trace, warn};
@@ -18,7 +19,16 @@ use cubeclient::models::{
     V1LoadRequestQuery, V1LoadRequestQueryFilterItem, V1LoadRequestQueryTimeDimension,
 };
 
+use crate::sql::interval::{add_interval, interval_literal};
+use crate::sql::regexp::regexp_filter;
 use crate::sql::session::DatabaseProtocol;
+use crate::sql::statement::{
+    has_aggregate_filters, has_ordered_aggregates, has_window_functions,
+    push_down_aggregate_filters, rewrite_aggregate_filters, rewrite_array_comparisons,
+    rewrite_decimals, rewrite_intervals, rewrite_json_casts, rewrite_ordered_aggregates,
+    rewrite_regexp_substrings, rewrite_timestamptz, split_ordered_aggregates,
+    split_window_functions,
+};
 use crate::sql::{
     dataframe, types::StatusFlags, ColumnFlags, ColumnType, Session, SessionManager, SessionState,
 };
@@ -28,22 +38,39 @@ use crate::transport::V1CubeMetaExt;
 use crate::CubeError;
 use crate::{
     compile::builder::QueryBuilder,
-    transport::{V1CubeMetaDimensionExt, V1CubeMetaMeasureExt, V1CubeMetaSegmentExt},
+    transport::{
+        decimal_column_type, V1CubeMetaDimensionExt, V1CubeMetaMeasureExt, V1CubeMetaSegmentExt,
+    },
 };
 
 use self::builder::*;
 use self::context::*;
 use self::engine::context::VariablesProvider;
+use self::engine::df::coerce::NumericOperator;
 use self::engine::df::planner::CubeQueryPlanner;
 use self::engine::df::scan::CubeScanNode;
+use self::engine::table_functions::{has_table_functions, plan_table_functions};
 use self::engine::information_schema::mysql::ext::CubeColumnMySqlExt;
 use self::engine::provider::CubeContext;
 use self::engine::udf::{
-    create_connection_id_udf, create_convert_tz_udf, create_current_user_udf, create_db_udf,
-    create_if_udf, create_instr_udf, create_isnull_udf, create_least_udf, create_locate_udf,
-    create_time_format_udf, create_timediff_udf, create_ucase_udf, create_user_udf,
-    create_version_udf,
+    create_connection_id_udf, create_convert_tz_udf, create_current_setting_udf,
+    create_current_user_udf, create_db_udf, create_if_udf, create_instr_udf, create_isnull_udf,
+    create_least_udf, create_locate_udf, create_pg_cancel_backend_udf,
+    create_json_agg_udaf, create_json_build_object_udf, create_json_cast_udf,
+    create_json_extract_path_udf, JSONB_CAST_FUNCTION, JSON_CAST_FUNCTION,
+    create_array_agg_udaf, create_make_array_udf, create_numeric_aggregate_udaf,
+    create_numeric_operator_udf, create_percentile_udaf, create_string_agg_udaf,
+    create_regexp_matches_udf, create_regexp_substring_udf,
+    create_pg_terminate_backend_udf, create_set_config_udf, create_time_format_udf,
+    create_timediff_udf, create_timestamptz_local_udf, create_timestamptz_part_udf,
+    create_timestamp_part_udf, create_timestamptz_trunc_udf, create_to_char_udf,
+    create_to_timestamptz_udf, create_ucase_udf, create_user_udf, create_version_udf,
 };
+use self::bucketing::bucket_queries;
+use self::distinct_on::{distinct_on, distinct_on_query, distinct_on_window_query};
+use self::explain::{query_plan, PlanExplanation};
+use self::grouping::{grouping_sets, merge_grouping_sets, GroupingSetQuery};
+use self::set_operation::{merge_union, order_and_limit};
 use self::parser::parse_sql_to_statement;
 use crate::compile::engine::udf::{
     create_date_add_udf, create_date_sub_udf, create_date_udf, create_dayofmonth_udf,
@@ -53,12 +80,18 @@ use crate::compile::engine::udf::{
 };
 use crate::compile::rewrite::converter::LogicalPlanToLanguageConverter;
 
+pub mod bucketing;
 pub mod builder;
 pub mod context;
+pub mod distinct_on;
 pub mod engine;
+pub mod explain;
+pub mod grouping;
 pub mod parser;
 pub mod rewrite;
 pub mod service;
+pub mod set_operation;
+pub mod subquery;
 
 #[derive(Debug, PartialEq)]
 pub enum CompilationError {
@@ -101,6 +134,12 @@ impl From<serde_json::Error> for CompilationError {
     }
 }
 
+impl From<datafusion::error::DataFusionError> for CompilationError {
+    fn from(v: datafusion::error::DataFusionError) -> Self {
+        CompilationError::Internal(format!("{:?}\n{}", v, Backtrace::capture()))
+    }
+}
+
 fn compile_select_expr(
     expr: &ast::Expr,
     ctx: &mut QueryContext,
@@ -116,6 +155,8 @@ fn compile_select_expr(
 
     match selection {
         Selection::TimeDimension(dimension, granularity) => {
+            ctx.use_timezone(QueryContext::date_trunc_timezone(expr))?;
+
             if let Some(alias) = mb_alias.clone() {
                 ctx.with_alias(
                     alias,
@@ -162,7 +203,7 @@ fn compile_select_expr(
                     column_to: mb_alias.unwrap_or(dimension.get_real_name()),
                     column_type: match dimension._type.as_str() {
                         "number" => ColumnType::Double,
-                        _ => ColumnType::String,
+                        typ => decimal_column_type(typ).unwrap_or(ColumnType::String),
                     },
                 },
             );
@@ -423,6 +464,45 @@ fn date_add_function(
     }))
 }
 
+/// `date + interval`, `interval + date` and `date - interval`, months are added as in PostgreSQL
+fn interval_arithmetic(
+    left: &ast::Expr,
+    op: &ast::BinaryOperator,
+    right: &ast::Expr,
+    ctx: &QueryContext,
+) -> CompilationResult<CompiledExpression> {
+    let unsupported = || {
+        CompilationError::Unsupported(format!(
+            "Unable to compile expression: {} {} {}",
+            left, op, right
+        ))
+    };
+    let interval =
+        |expr: &ast::Expr| interval_literal(expr).map_err(|e| CompilationError::User(e.message));
+
+    let (date_expr, interval) = match (interval(left)?, interval(right)?) {
+        (None, Some(interval)) => (left, interval),
+        (Some(interval), None) if *op == ast::BinaryOperator::Plus => (right, interval),
+        _ => return Err(unsupported()),
+    };
+    let interval = if *op == ast::BinaryOperator::Minus {
+        interval.negate()
+    } else {
+        interval
+    };
+
+    let date = compile_expression(date_expr, ctx)?
+        .to_date()
+        .ok_or_else(unsupported)?;
+    let date = add_interval(&date.naive_utc(), &interval).ok_or_else(|| {
+        CompilationError::User(format!("timestamp out of range: {} {} {}", left, op, right))
+    })?;
+
+    Ok(CompiledExpression::DateLiteral(
+        Utc.from_utc_datetime(&date),
+    ))
+}
+
 fn compile_expression(
     expr: &ast::Expr,
     ctx: &QueryContext,
@@ -551,6 +631,33 @@ fn compile_expression(
                 val
             ))),
         },
+        ast::Expr::BinaryOp { left, op, right }
+            if matches!(op, ast::BinaryOperator::Plus | ast::BinaryOperator::Minus) =>
+        {
+            interval_arithmetic(left, op, right, ctx)
+        }
+        ast::Expr::Nested(expr) => compile_expression(expr, ctx),
+        // Literals of dates and timestamps (`'2022-03-01'::timestamp`, `DATE '2022-03-01'`)
+        ast::Expr::Cast {
+            expr,
+            data_type: ast::DataType::Date | ast::DataType::Timestamp,
+        } => match compile_expression(expr, ctx)? {
+            CompiledExpression::Selection(_) => Err(CompilationError::Unsupported(format!(
+                "Unable to compile expression: {:?}",
+                expr
+            ))),
+            value => value.to_date_literal().ok_or_else(|| {
+                CompilationError::User(format!("Unable to convert to a date value: {}", expr))
+            }),
+        },
+        ast::Expr::TypedString {
+            data_type: ast::DataType::Date | ast::DataType::Timestamp,
+            value,
+        } => CompiledExpression::StringLiteral(value.clone())
+            .to_date_literal()
+            .ok_or_else(|| {
+                CompilationError::User(format!("Unable to convert to a date value: {}", value))
+            }),
         ast::Expr::Function(f) => match f.name.to_string().to_lowercase().as_str() {
             "str_to_date" => str_to_date_function(&f),
             "date" => date_function(&f, &ctx),
@@ -667,6 +774,34 @@ fn compiled_binary_op_expr(
                     CompiledExpression::DateLiteral(_) => (filter_expr, "beforeDate".to_string()),
                     _ => (filter_expr, "lte".to_string()),
                 },
+                // Simple patterns are string filters
+                ast::BinaryOperator::PGRegexMatch
+                | ast::BinaryOperator::PGRegexIMatch
+                | ast::BinaryOperator::PGRegexNotMatch
+                | ast::BinaryOperator::PGRegexNotIMatch => {
+                    let case_insensitive = matches!(
+                        op,
+                        ast::BinaryOperator::PGRegexIMatch | ast::BinaryOperator::PGRegexNotIMatch
+                    );
+                    let negated = matches!(
+                        op,
+                        ast::BinaryOperator::PGRegexNotMatch
+                            | ast::BinaryOperator::PGRegexNotIMatch
+                    );
+                    let pattern = filter_expr.to_value_as_str()?;
+                    match regexp_filter(&pattern, case_insensitive, negated) {
+                        Some((operator, value)) => (
+                            CompiledExpression::StringLiteral(value),
+                            operator.to_string(),
+                        ),
+                        None => {
+                            return Err(CompilationError::Unsupported(format!(
+                                "Regular expression can't be compiled into a filter: {} {} {}",
+                                left, op, right
+                            )))
+                        }
+                    }
+                }
                 _ => {
                     return Err(CompilationError::Unsupported(format!(
                         "Operator in binary expression for dimension: {} {} {}",
@@ -1201,6 +1336,10 @@ fn compile_where(
         binary @ ast::Expr::BinaryOp { left, right, op } => match op {
             ast::BinaryOperator::Like
             | ast::BinaryOperator::NotLike
+            | ast::BinaryOperator::PGRegexMatch
+            | ast::BinaryOperator::PGRegexIMatch
+            | ast::BinaryOperator::PGRegexNotMatch
+            | ast::BinaryOperator::PGRegexNotIMatch
             | ast::BinaryOperator::Lt
             | ast::BinaryOperator::LtEq
             | ast::BinaryOperator::Gt
@@ -1251,8 +1390,49 @@ fn compile_where(
     Ok(())
 }
 
+// ORDER BY 1 references the first expression of the projection
+fn compile_order_selection(
+    expr: &ast::Expr,
+    select: &ast::Select,
+    ctx: &QueryContext,
+) -> CompilationResult<Option<Selection>> {
+    let expr = match expr {
+        ast::Expr::Value(ast::Value::Number(n, _)) => {
+            let position = n.parse::<usize>().unwrap_or(0);
+            match select.projection.get(position.wrapping_sub(1)) {
+                Some(ast::SelectItem::UnnamedExpr(expr))
+                | Some(ast::SelectItem::ExprWithAlias { expr, .. }) => expr,
+                _ => {
+                    return Err(CompilationError::Unsupported(format!(
+                        "Unsupported position in order: {}",
+                        n
+                    )))
+                }
+            }
+        }
+        expr => expr,
+    };
+
+    ctx.compile_selection(expr)
+}
+
+/// Sort keys can be pushed down to the Cube query when all of them are members
+fn is_order_by_members(
+    order_by: &Vec<ast::OrderByExpr>,
+    select: &ast::Select,
+    ctx: &QueryContext,
+) -> bool {
+    order_by.iter().all(|order_expr| {
+        matches!(
+            compile_order_selection(&order_expr.expr, select, ctx),
+            Ok(Some(_))
+        )
+    })
+}
+
 fn compile_order(
     order_by: &Vec<ast::OrderByExpr>,
+    select: &ast::Select,
     ctx: &QueryContext,
     builder: &mut QueryBuilder,
 ) -> CompilationResult<()> {
@@ -1261,9 +1441,8 @@ fn compile_order(
     };
 
     for order_expr in order_by.iter() {
-        let order_selection = ctx
-            .compile_selection(&order_expr.expr.clone())?
-            .ok_or_else(|| {
+        let order_selection =
+            compile_order_selection(&order_expr.expr, select, ctx)?.ok_or_else(|| {
                 CompilationError::Unsupported(format!(
                     "Unsupported expression in order: {:?}",
                     order_expr.expr
@@ -1313,7 +1492,7 @@ fn compile_select(expr: &ast::Select, ctx: &mut QueryContext) -> CompilationResu
                                 column_to: dimension.get_real_name(),
                                 column_type: match dimension._type.as_str() {
                                     "number" => ColumnType::Double,
-                                    _ => ColumnType::String,
+                                    typ => decimal_column_type(typ).unwrap_or(ColumnType::String),
                                 },
                             },
                         )
@@ -1374,8 +1553,29 @@ impl QueryPlanner {
             return self.create_df_logical_plan(stmt.clone());
         }
 
+        // CTEs are inlined by DataFusion into every reference, CTEs over cubes are rewritten
+        // into Cube queries by the rewrite engine
+        if let Some(with) = &q.with {
+            if with.recursive {
+                return Err(CompilationError::Unsupported(
+                    "Query with recursive CTE instruction(s)".to_string(),
+                ));
+            }
+
+            return self.create_df_logical_plan(stmt.clone());
+        }
+
         let select = match &q.body {
             sqlparser::ast::SetExpr::Select(select) => select,
+            ast::SetExpr::SetOperation { .. } => {
+                let plan = self.set_expr_to_plan(q, &q.body)?;
+
+                return Ok(QueryPlan::DataFusionSelect(
+                    StatusFlags::empty(),
+                    order_and_limit(plan, q)?,
+                    self.create_execution_ctx(),
+                ));
+            }
             _ => {
                 return Err(CompilationError::Unsupported(
                     "Unsupported Query".to_string(),
@@ -1383,6 +1583,34 @@ impl QueryPlanner {
             }
         };
 
+        // Results of table functions are tables of DataFusion
+        if has_table_functions(select) {
+            return self.create_df_logical_plan(stmt.clone());
+        }
+
+        // Rows are numbered by a window function, the first row of every group is selected by
+        // DataFusion
+        if let Some(exprs) = distinct_on(select)? {
+            let window = distinct_on_window_query(q, select, &exprs)?;
+            let window = match select.from.as_slice() {
+                [ast::TableWithJoins {
+                    relation: ast::TableFactor::Table { name, .. },
+                    joins,
+                }] if joins.is_empty()
+                    && name.0.last().map_or(false, |table_name| {
+                        self.meta.find_cube_with_name(table_name.value.clone()).is_some()
+                    }) =>
+                {
+                    split_window_functions(&window)
+                        .map_err(|e| CompilationError::Unsupported(e.message))?
+                }
+                _ => window,
+            };
+            let query = distinct_on_query(q, select, window)?;
+
+            return self.create_df_logical_plan(ast::Statement::Query(Box::new(query)));
+        }
+
         let from_table = if select.from.len() == 1 {
             &select.from[0]
         } else {
@@ -1397,7 +1625,8 @@ impl QueryPlanner {
                         (identifiers[0].value.clone(), identifiers[1].value.clone())
                     } else if identifiers.len() == 1 {
                         // `KibanaSampleDataEcommerce`
-                        ("db".to_string(), identifiers[0].value.clone())
+                        let table_name = identifiers[0].value.clone();
+                        (self.search_table_schema(&table_name)?, table_name)
                     } else {
                         return Err(CompilationError::Unsupported(
                             "Query with multiple tables in from".to_string(),
@@ -1426,12 +1655,6 @@ impl QueryPlanner {
             ));
         }
 
-        if q.with.is_some() {
-            return Err(CompilationError::Unsupported(
-                "Query with CTE instruction(s)".to_string(),
-            ));
-        }
-
         if !select.cluster_by.is_empty() {
             return Err(CompilationError::Unsupported(
                 "Query with CLUSTER BY instruction(s)".to_string(),
@@ -1444,13 +1667,11 @@ impl QueryPlanner {
             ));
         }
 
-        if select.having.is_some() {
-            return Err(CompilationError::Unsupported(
-                "Query with HAVING instruction(s)".to_string(),
-            ));
-        }
-
-        if schema_name.to_lowercase() != "db" {
+        if !self
+            .state
+            .protocol
+            .is_cube_schema(&schema_name.to_lowercase())
+        {
             return Err(CompilationError::Unsupported(format!(
                 "Unable to access schema {}",
                 schema_name
@@ -1479,9 +1700,55 @@ impl QueryPlanner {
         };
 
         if let Some(cube) = self.meta.find_cube_with_name(table_name.clone()) {
-            let mut ctx = QueryContext::new(&cube);
+            // Conditions of FILTER are filters of the Cube query, other filtered aggregates are
+            // conditional aggregates of DataFusion
+            if let Some(query) = push_down_aggregate_filters(q) {
+                let query = Box::new(query);
+                return self.select_to_plan(&ast::Statement::Query(query.clone()), &query);
+            }
+            if has_aggregate_filters(select) {
+                return self.create_df_logical_plan(stmt.clone());
+            }
+
+            // Members are fetched by a Cube query, window functions are evaluated by DataFusion
+            if has_window_functions(select) {
+                let query = split_window_functions(q)
+                    .map_err(|e| CompilationError::Unsupported(e.message))?;
+
+                return self.create_df_logical_plan(ast::Statement::Query(Box::new(query)));
+            }
+
+            // Values of members are fetched by a Cube query, they are aggregated by DataFusion
+            if has_ordered_aggregates(select) {
+                let query = split_ordered_aggregates(q)
+                    .map_err(|e| CompilationError::Unsupported(e.message))?;
+
+                return self.create_df_logical_plan(ast::Statement::Query(Box::new(query)));
+            }
+
+            // Predicates of HAVING are rewritten into filters of measures
+            if select.having.is_some() {
+                return self.create_df_logical_plan(stmt.clone());
+            }
+
+            if let Some(sets) = grouping_sets(select)? {
+                return self.grouping_sets_to_plan(q, select, sets);
+            }
+
+            if let Some(plan) = self.buckets_to_plan(q, select)? {
+                return Ok(plan);
+            }
+
+            let mut ctx =
+                QueryContext::new(&cube).with_session_timezone(self.state.settings().time_zone());
             let mut builder = compile_select(select, &mut ctx)?;
 
+            // Other sort keys are sorted by DataFusion, LIMIT is applied after the sort.
+            // DataFusion doesn't support OFFSET, such queries are rejected by compile_order.
+            if q.offset.is_none() && !is_order_by_members(&q.order_by, select, &ctx) {
+                return self.create_df_logical_plan(stmt.clone());
+            }
+
             if let Some(limit_expr) = &q.limit {
                 let limit = limit_expr.to_string().parse::<i32>().map_err(|e| {
                     CompilationError::Unsupported(format!(
@@ -1505,12 +1772,16 @@ impl QueryPlanner {
             }
 
             compile_group(&select.group_by, &ctx, &mut builder)?;
-            compile_order(&q.order_by, &ctx, &mut builder)?;
+            compile_order(&q.order_by, select, &ctx, &mut builder)?;
 
             if let Some(selection) = &select.selection {
                 compile_where(selection, &ctx, &mut builder)?;
             }
 
+            if let Some(timezone) = ctx.timezone() {
+                builder.with_timezone(timezone);
+            }
+
             let query = builder.build();
             let schema = query.meta_as_df_schema();
 
@@ -1551,6 +1822,109 @@ impl QueryPlanner {
         }
     }
 
+    /// Every grouping set is compiled into a Cube query, results are merged by DataFusion
+    fn grouping_sets_to_plan(
+        &self,
+        q: &Box<ast::Query>,
+        select: &ast::Select,
+        sets: Vec<Vec<ast::Expr>>,
+    ) -> CompilationResult<QueryPlan> {
+        let mut plans = Vec::with_capacity(sets.len());
+        for set in sets.iter() {
+            let set_query = GroupingSetQuery::new(q, select, &sets, set)?;
+            let query = Box::new(set_query.query);
+
+            match self.select_to_plan(&ast::Statement::Query(query.clone()), &query)? {
+                QueryPlan::DataFusionSelect(_, plan, _) => plans.push((plan, set_query.columns)),
+                _ => {
+                    return Err(CompilationError::Internal(
+                        "Grouping set must be compiled into a Cube query".to_string(),
+                    ))
+                }
+            }
+        }
+
+        Ok(QueryPlan::DataFusionSelect(
+            StatusFlags::empty(),
+            merge_grouping_sets(plans, select, q)?,
+            self.create_execution_ctx(),
+        ))
+    }
+
+    /// Every bucket of CASE in GROUP BY is compiled into a filtered Cube query, results are
+    /// merged by DataFusion. None if conditions of buckets aren't Cube filters.
+    fn buckets_to_plan(
+        &self,
+        q: &Box<ast::Query>,
+        select: &ast::Select,
+    ) -> CompilationResult<Option<QueryPlan>> {
+        let buckets = match bucket_queries(q, select)? {
+            Some(buckets) => buckets,
+            None => return Ok(None),
+        };
+
+        let mut plans = Vec::with_capacity(buckets.len());
+        for bucket in buckets.into_iter() {
+            let query = Box::new(bucket.query);
+
+            match self.select_to_plan(&ast::Statement::Query(query.clone()), &query) {
+                Ok(QueryPlan::DataFusionSelect(_, plan, _)) => plans.push((plan, bucket.columns)),
+                _ => return Ok(None),
+            }
+        }
+
+        Ok(Some(QueryPlan::DataFusionSelect(
+            StatusFlags::empty(),
+            merge_grouping_sets(plans, select, q)?,
+            self.create_execution_ctx(),
+        )))
+    }
+
+    /// Branches of UNION are compiled as separate queries, results are merged by DataFusion
+    fn set_expr_to_plan(
+        &self,
+        q: &ast::Query,
+        set_expr: &ast::SetExpr,
+    ) -> CompilationResult<LogicalPlan> {
+        let query = match set_expr {
+            ast::SetExpr::SetOperation {
+                op: ast::SetOperator::Union,
+                all,
+                left,
+                right,
+            } => {
+                let left = self.set_expr_to_plan(q, left)?;
+                let right = self.set_expr_to_plan(q, right)?;
+
+                return merge_union(left, right, *all);
+            }
+            ast::SetExpr::SetOperation { op, .. } => {
+                return Err(CompilationError::Unsupported(format!(
+                    "Query with {} instruction(s)",
+                    op
+                )));
+            }
+            // (SELECT ... ORDER BY ... LIMIT ...)
+            ast::SetExpr::Query(query) => query.clone(),
+            body => {
+                let mut query = Box::new(q.clone());
+                query.body = body.clone();
+                query.order_by = vec![];
+                query.limit = None;
+                query.offset = None;
+
+                query
+            }
+        };
+
+        match self.select_to_plan(&ast::Statement::Query(query.clone()), &query)? {
+            QueryPlan::DataFusionSelect(_, plan, _) => Ok(plan),
+            _ => Err(CompilationError::Unsupported(
+                "UNION with a query, which is not executed by DataFusion".to_string(),
+            )),
+        }
+    }
+
     pub fn plan(&self, stmt: &ast::Statement) -> CompilationResult<QueryPlan> {
         match (stmt, &self.state.protocol) {
             (ast::Statement::Query(q), _) => self.select_to_plan(stmt, q),
@@ -1616,6 +1990,12 @@ impl QueryPlanner {
             (ast::Statement::Explain { statement, .. }, DatabaseProtocol::MySQL) => {
                 self.explain_to_plan(&statement)
             }
+            (
+                ast::Statement::Explain {
+                    statement, analyze, ..
+                },
+                DatabaseProtocol::PostgreSQL,
+            ) => self.explain_postgres_to_plan(&statement, *analyze),
             (ast::Statement::Use { db_name }, DatabaseProtocol::MySQL) => {
                 self.use_to_plan(&db_name)
             }
@@ -1978,6 +2358,25 @@ WHERE `TABLE_SCHEMA` = '{}'",
         ));
     }
 
+    /// EXPLAIN of PostgreSQL shows the DataFusion plan, Cube queries and why nodes of the plan
+    /// were not pushed down to Cube
+    fn explain_postgres_to_plan(
+        &self,
+        statement: &Box<ast::Statement>,
+        analyze: bool,
+    ) -> Result<QueryPlan, CompilationError> {
+        // The statement is executed by explain_analyze
+        if analyze {
+            return Err(CompilationError::Unsupported(
+                "EXPLAIN ANALYZE can't be planned without execution".to_string(),
+            ));
+        }
+
+        let plan = self.plan(&statement)?;
+
+        Ok(query_plan(PlanExplanation::new(&plan, &self.meta).lines()))
+    }
+
     fn use_to_plan(&self, db_name: &ast::Ident) -> Result<QueryPlan, CompilationError> {
         self.state.set_database(Some(db_name.value.clone()));
 
@@ -2003,11 +2402,43 @@ WHERE `TABLE_SCHEMA` = '{}'",
         ))
     }
 
+    /// Schema of the unqualified table, PostgreSQL looks for it in schemas of search_path
+    fn search_table_schema(&self, table_name: &str) -> CompilationResult<String> {
+        if self.state.protocol != DatabaseProtocol::PostgreSQL {
+            return Ok("db".to_string());
+        }
+
+        let ctx = self.create_execution_ctx();
+        let cube_ctx = CubeContext::new(
+            Arc::new(ctx.state.lock().unwrap().clone()),
+            self.meta.clone(),
+            self.session_manager.clone(),
+            self.state.clone(),
+        );
+        match cube_ctx.search_table_schema(table_name) {
+            Some(schema) => Ok(schema),
+            // Unknown cubes are reported by the planner of cubes
+            None if self
+                .state
+                .search_path()
+                .iter()
+                .any(|schema| self.state.protocol.is_cube_schema(schema)) =>
+            {
+                Ok("db".to_string())
+            }
+            None => Err(CompilationError::User(format!(
+                "relation \"{}\" does not exist",
+                table_name
+            ))),
+        }
+    }
+
     fn create_execution_ctx(&self) -> ExecutionContext {
         let mut ctx = ExecutionContext::with_config(
             ExecutionConfig::new()
                 .with_query_planner(Arc::new(CubeQueryPlanner::new(
                     self.session_manager.server.transport.clone(),
+                    self.state.clone(),
                 )))
                 .with_information_schema(false),
         );
@@ -2017,6 +2448,47 @@ WHERE `TABLE_SCHEMA` = '{}'",
             ctx.register_variable(VarType::System, Arc::new(variable_provider));
         }
 
+        if self.state.protocol == DatabaseProtocol::PostgreSQL {
+            ctx.register_udf(create_pg_cancel_backend_udf(
+                self.state.clone(),
+                self.session_manager.clone(),
+            ));
+            ctx.register_udf(create_pg_terminate_backend_udf(
+                self.state.clone(),
+                self.session_manager.clone(),
+            ));
+            ctx.register_udf(create_current_setting_udf(self.state.clone()));
+            ctx.register_udf(create_set_config_udf(self.state.clone()));
+            ctx.register_udf(create_json_extract_path_udf("json_extract_path", false));
+            ctx.register_udf(create_json_extract_path_udf("jsonb_extract_path", false));
+            ctx.register_udf(create_json_extract_path_udf("json_extract_path_text", true));
+            ctx.register_udf(create_json_extract_path_udf("jsonb_extract_path_text", true));
+            ctx.register_udf(create_json_build_object_udf("json_build_object", false));
+            ctx.register_udf(create_json_build_object_udf("jsonb_build_object", true));
+            ctx.register_udf(create_json_cast_udf(JSON_CAST_FUNCTION, false));
+            ctx.register_udf(create_json_cast_udf(JSONB_CAST_FUNCTION, true));
+            ctx.register_udaf(create_json_agg_udaf("json_agg"));
+            ctx.register_udaf(create_json_agg_udaf("jsonb_agg"));
+            ctx.register_udf(create_make_array_udf());
+            ctx.register_udaf(create_array_agg_udaf(false));
+            ctx.register_udaf(create_array_agg_udaf(true));
+            ctx.register_udaf(create_string_agg_udaf(false));
+            ctx.register_udaf(create_string_agg_udaf(true));
+            ctx.register_udaf(create_percentile_udaf(true));
+            ctx.register_udaf(create_percentile_udaf(false));
+            ctx.register_udf(create_regexp_matches_udf());
+            ctx.register_udf(create_regexp_substring_udf());
+
+            // Timestamps with time zone are evaluated in TimeZone of the session
+            let time_zone = self.state.settings().session_time_zone();
+            ctx.register_udf(create_to_timestamptz_udf(time_zone));
+            ctx.register_udf(create_timestamptz_trunc_udf(time_zone));
+            ctx.register_udf(create_timestamptz_part_udf(time_zone));
+            ctx.register_udf(create_timestamptz_local_udf(time_zone));
+            ctx.register_udf(create_timestamp_part_udf());
+            ctx.register_udf(create_to_char_udf(time_zone));
+        }
+
         ctx.register_udf(create_version_udf());
         ctx.register_udf(create_db_udf("database".to_string(), self.state.clone()));
         ctx.register_udf(create_db_udf("schema".to_string(), self.state.clone()));
@@ -2045,6 +2517,12 @@ WHERE `TABLE_SCHEMA` = '{}'",
         ctx.register_udf(create_date_sub_udf());
         ctx.register_udf(create_date_add_udf());
         ctx.register_udf(create_str_to_date());
+        for op in NumericOperator::ALL.iter() {
+            ctx.register_udf(create_numeric_operator_udf(*op));
+        }
+        for fun in ["sum", "avg", "min", "max"].iter() {
+            ctx.register_udaf(create_numeric_aggregate_udaf(*fun));
+        }
 
         ctx.register_udaf(create_measure_udaf());
 
@@ -2054,13 +2532,67 @@ WHERE `TABLE_SCHEMA` = '{}'",
     fn create_df_logical_plan(&self, stmt: ast::Statement) -> CompilationResult<QueryPlan> {
         let ctx = self.create_execution_ctx();
 
+        let mut stmt = stmt;
+        let table_functions = plan_table_functions(&mut stmt)?;
+        if self.state.protocol == DatabaseProtocol::PostgreSQL {
+            rewrite_timestamptz(&mut stmt)
+                .map_err(|error| CompilationError::User(error.message))?;
+            rewrite_intervals(&mut stmt).map_err(|error| CompilationError::User(error.message))?;
+            rewrite_ordered_aggregates(&mut stmt)
+                .map_err(|error| CompilationError::User(error.message))?;
+            rewrite_aggregate_filters(&mut stmt)
+                .map_err(|error| CompilationError::User(error.message))?;
+            rewrite_regexp_substrings(&mut stmt)
+                .map_err(|error| CompilationError::User(error.message))?;
+            rewrite_json_casts(&mut stmt).map_err(|error| CompilationError::User(error.message))?;
+        }
+
         let state = Arc::new(ctx.state.lock().unwrap().clone());
         let cube_ctx = CubeContext::new(
             state,
             self.meta.clone(),
             self.session_manager.clone(),
             self.state.clone(),
-        );
+        )
+        .with_table_functions(table_functions);
+
+        // DataFusion can't calculate with decimals, they are calculated by numeric_* functions
+        let decimal_columns = |name: &ast::ObjectName| -> Vec<(String, usize, usize)> {
+            let idents = name.0.iter().map(|ident| ident.value.as_str()).collect::<Vec<_>>();
+            let table = match idents.as_slice() {
+                [table] => TableReference::Bare { table: *table },
+                [schema, table] => TableReference::Partial {
+                    schema: *schema,
+                    table: *table,
+                },
+                [catalog, schema, table] => TableReference::Full {
+                    catalog: *catalog,
+                    schema: *schema,
+                    table: *table,
+                },
+                _ => return vec![],
+            };
+
+            cube_ctx
+                .get_table_provider(table)
+                .map(|table| {
+                    table
+                        .schema()
+                        .fields()
+                        .iter()
+                        .filter_map(|field| match field.data_type() {
+                            DataType::Decimal(precision, scale) => {
+                                Some((field.name().to_lowercase(), *precision, *scale))
+                            }
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        rewrite_decimals(&mut stmt, &decimal_columns)
+            .map_err(|error| CompilationError::User(error.message))?;
+
         let df_query_planner = SqlToRel::new(&cube_ctx);
 
         let plan = df_query_planner
@@ -2099,7 +2631,12 @@ pub fn convert_statement_to_cube_query(
     session: Arc<Session>,
 ) -> CompilationResult<QueryPlan> {
     let planner = QueryPlanner::new(session.state.clone(), meta, session.session_manager.clone());
-    planner.plan(stmt)
+    // `x = ANY(array)` is compared as an IN list, parameters are already bound
+    let mut stmt = stmt.clone();
+    rewrite_array_comparisons(&mut stmt)
+        .map_err(|error| CompilationError::Unsupported(error.message))?;
+
+    planner.plan(&stmt)
 }
 
 #[derive(Debug, PartialEq, Serialize)]
@@ -2137,6 +2674,7 @@ impl CompiledQuery {
                     ColumnType::String => DataType::Utf8,
                     ColumnType::Double => DataType::Float64,
                     ColumnType::Int8 => DataType::Boolean,
+                    ColumnType::Decimal(precision, scale) => DataType::Decimal(precision, scale),
                     _ => panic!("Unimplemented support for {:?}", meta_field.column_type),
                 },
                 false,
@@ -2162,6 +2700,9 @@ impl CompiledQuery {
                         ColumnType::String => DataType::Utf8,
                         ColumnType::Double => DataType::Float64,
                         ColumnType::Int8 => DataType::Boolean,
+                        ColumnType::Decimal(precision, scale) => {
+                            DataType::Decimal(precision, scale)
+                        }
                         _ => panic!("Unimplemented support for {:?}", meta_field.column_type),
                     },
                     false,
@@ -2192,6 +2733,29 @@ impl QueryPlan {
         }
     }
 
+    /// Columns of the result, which are known without execution (for Describe of prepared
+    /// statements), None is returned for statements without a result set
+    pub fn result_columns(&self) -> Result<Option<Vec<dataframe::Column>>, CubeError> {
+        match self {
+            QueryPlan::MetaOk(_) => Ok(None),
+            QueryPlan::MetaTabular(_, frame) => Ok(Some(frame.get_columns().clone())),
+            // Types are converted the same way as the result batches, see `batch_to_dataframe`
+            QueryPlan::DataFusionSelect(_, plan, _) => plan
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| {
+                    Ok(dataframe::Column::new(
+                        field.name().clone(),
+                        dataframe::arrow_to_column_type(field.data_type().clone())?,
+                        ColumnFlags::empty(),
+                    ))
+                })
+                .collect::<Result<Vec<_>, CubeError>>()
+                .map(Some),
+        }
+    }
+
     pub fn print(&self, pretty: bool) -> Result<String, CubeError> {
         match self {
             QueryPlan::DataFusionSelect(_, plan, _) => {
@@ -2233,6 +2797,7 @@ mod tests {
             dataframe::batch_to_dataframe, server_manager::ServerConfiguration, types::StatusFlags,
             AuthContext, AuthenticateResponse, ServerManager, SqlAuthService,
         },
+        telemetry::tracing::SpanContext,
         transport::TransportService,
     };
     use datafusion::logical_plan::PlanVisitor;
@@ -2386,7 +2951,11 @@ mod tests {
         #[async_trait]
         impl TransportService for TestConnectionTransport {
             // Load meta information about cubes
-            async fn meta(&self, _ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError> {
+            async fn meta(
+                &self,
+                _ctx: Arc<AuthContext>,
+                _span: Option<SpanContext>,
+            ) -> Result<Arc<MetaContext>, CubeError> {
                 panic!("It's a fake transport");
             }
 
@@ -2395,6 +2964,7 @@ mod tests {
                 &self,
                 _query: V1LoadRequestQuery,
                 _ctx: Arc<AuthContext>,
+                _span: Option<SpanContext>,
             ) -> Result<V1LoadResponse, CubeError> {
                 panic!("It's a fake transport");
             }
@@ -2450,55 +3020,447 @@ mod tests {
     }
 
     #[test]
-    fn test_select_measure_via_function() {
-        let query_plan = convert_select_to_query_plan(
-            "SELECT MEASURE(maxPrice), MEASURE(minPrice), MEASURE(avgPrice) FROM KibanaSampleDataEcommerce".to_string(),
-        DatabaseProtocol::MySQL);
-
-        let logical_plan = query_plan.as_logical_plan();
+    fn test_select_cte() {
+        let logical_plan = convert_select_to_query_plan(
+            "WITH t AS (SELECT COUNT(*) AS cnt FROM KibanaSampleDataEcommerce) SELECT cnt FROM t"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
         assert_eq!(
-            logical_plan.find_cube_scan().request,
-            V1LoadRequestQuery {
-                measures: Some(vec![
-                    "KibanaSampleDataEcommerce.maxPrice".to_string(),
-                    "KibanaSampleDataEcommerce.minPrice".to_string(),
-                    "KibanaSampleDataEcommerce.avgPrice".to_string(),
-                ]),
-                segments: Some(vec![]),
-                dimensions: Some(vec![]),
-                time_dimensions: None,
-                order: None,
-                limit: None,
-                offset: None,
-                filters: None
-            }
+            logical_plan.find_cube_scan().request.measures,
+            Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+        );
+
+        // CTE over a system table is executed by DataFusion
+        convert_select_to_query_plan(
+            "WITH t AS (SELECT typname FROM pg_catalog.pg_type) SELECT typname FROM t".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        let query = convert_sql_to_cube_query(
+            &"WITH RECURSIVE t AS (SELECT 1 AS n) SELECT n FROM t".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
         );
+        match query {
+            Ok(_) => panic!("WITH RECURSIVE must be rejected"),
+            Err(e) => assert_eq!(
+                e,
+                CompilationError::Unsupported(
+                    "Query with recursive CTE instruction(s)".to_string()
+                )
+            ),
+        }
     }
 
     #[test]
-    fn test_select_compound_identifiers() {
-        let query_plan = convert_select_to_query_plan(
-            "SELECT MEASURE(`KibanaSampleDataEcommerce`.`maxPrice`) AS maxPrice, MEASURE(`KibanaSampleDataEcommerce`.`minPrice`) AS minPrice FROM KibanaSampleDataEcommerce".to_string(), DatabaseProtocol::MySQL
-        );
+    fn test_select_grouping_sets() {
+        let cube_queries = |query: &str| {
+            let plan =
+                convert_select_to_query_plan(query.to_string(), DatabaseProtocol::PostgreSQL);
+            PlanExplanation::new(&plan, &get_test_tenant_ctx()).cube_queries
+        };
 
-        let logical_plan = query_plan.as_logical_plan();
+        // A Cube query per grouping set
+        let queries = cube_queries(
+            "SELECT customer_gender, COUNT(*) AS cnt, GROUPING(customer_gender) AS g \
+            FROM KibanaSampleDataEcommerce GROUP BY ROLLUP(customer_gender) ORDER BY 1 LIMIT 10",
+        );
+        assert_eq!(queries.len(), 2);
         assert_eq!(
-            logical_plan.find_cube_scan().request,
-            V1LoadRequestQuery {
-                measures: Some(vec![
-                    "KibanaSampleDataEcommerce.maxPrice".to_string(),
-                    "KibanaSampleDataEcommerce.minPrice".to_string(),
-                ]),
-                segments: Some(vec![]),
-                dimensions: Some(vec![]),
-                time_dimensions: None,
-                order: None,
-                limit: None,
-                offset: None,
-                filters: None
-            }
+            queries[0].dimensions,
+            Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()])
         );
-    }
+        assert_eq!(queries[1].dimensions, Some(vec![]));
+        for query in queries.iter() {
+            assert_eq!(
+                query.measures,
+                Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+            );
+            assert_eq!(query.limit, None);
+        }
+
+        let queries = cube_queries(
+            "SELECT customer_gender, taxful_total_price, COUNT(*) FROM KibanaSampleDataEcommerce \
+            GROUP BY GROUPING SETS ((customer_gender, taxful_total_price), (customer_gender), ())",
+        );
+        assert_eq!(
+            queries
+                .iter()
+                .map(|query| query.dimensions.clone().unwrap_or_default().len())
+                .collect::<Vec<_>>(),
+            vec![2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn test_select_case_buckets() {
+        let cube_queries = |query: &str| {
+            let plan =
+                convert_select_to_query_plan(query.to_string(), DatabaseProtocol::PostgreSQL);
+            PlanExplanation::new(&plan, &get_test_tenant_ctx()).cube_queries
+        };
+
+        // A Cube query per bucket, ELSE is the last one
+        let queries = cube_queries(
+            "SELECT CASE WHEN taxful_total_price < 100 THEN 'small' WHEN taxful_total_price < 500 \
+            THEN 'medium' ELSE 'large' END AS size, customer_gender, COUNT(*) \
+            FROM KibanaSampleDataEcommerce GROUP BY 1, 2 ORDER BY 1 LIMIT 10",
+        );
+        assert_eq!(queries.len(), 3);
+        assert_eq!(
+            queries[0].filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.taxful_total_price".to_string()),
+                operator: Some("lt".to_string()),
+                values: Some(vec!["100".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+        for query in queries.iter() {
+            assert_eq!(
+                query.dimensions,
+                Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()])
+            );
+            assert_eq!(
+                query.measures,
+                Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+            );
+            assert_eq!(query.limit, None);
+        }
+    }
+
+    #[test]
+    fn test_select_union() {
+        let plan = convert_select_to_query_plan(
+            "SELECT customer_gender, COUNT(*) AS cnt FROM KibanaSampleDataEcommerce GROUP BY 1 \
+            UNION ALL SELECT customer_gender, MEASURE(maxPrice) FROM KibanaSampleDataEcommerce \
+            GROUP BY 1 ORDER BY cnt DESC LIMIT 10"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
+
+        let queries = PlanExplanation::new(&plan, &get_test_tenant_ctx()).cube_queries;
+        assert_eq!(
+            queries
+                .iter()
+                .map(|query| query.measures.clone().unwrap_or_default())
+                .collect::<Vec<_>>(),
+            vec![
+                vec!["KibanaSampleDataEcommerce.count".to_string()],
+                vec!["KibanaSampleDataEcommerce.maxPrice".to_string()]
+            ]
+        );
+
+        // Columns are named by the first branch, numbers are promoted to a common type
+        let logical_plan = plan.as_logical_plan();
+        let fields = logical_plan
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| (field.name().clone(), field.data_type().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                ("customer_gender".to_string(), DataType::Utf8),
+                ("cnt".to_string(), DataType::Float64),
+            ]
+        );
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT customer_gender FROM KibanaSampleDataEcommerce \
+            UNION SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce GROUP BY 1"
+                .to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        );
+        match query {
+            Ok(_) => panic!("UNION of different numbers of columns must be rejected"),
+            Err(e) => assert_eq!(
+                e,
+                CompilationError::User(
+                    "each UNION query must have the same number of columns".to_string()
+                )
+            ),
+        }
+    }
+
+    #[test]
+    fn test_select_window_functions() {
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT customer_gender, COUNT(*) AS cnt, \
+                ROW_NUMBER() OVER (ORDER BY COUNT(*) DESC) AS rn \
+                FROM KibanaSampleDataEcommerce GROUP BY 1 ORDER BY rn LIMIT 10"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        // ORDER BY and LIMIT are applied after window functions by DataFusion
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.measures,
+            Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+        );
+        assert_eq!(
+            request.dimensions,
+            Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()])
+        );
+        assert_eq!(request.order, None);
+        assert_eq!(request.limit, None);
+    }
+
+    #[test]
+    fn test_select_ordered_aggregates() {
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT customer_gender, \
+                PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY taxful_total_price) AS m \
+                FROM KibanaSampleDataEcommerce GROUP BY 1"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        // Values are fetched by Cube, the percentile is calculated by DataFusion
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.dimensions,
+            Some(vec![
+                "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_select_distinct_on() {
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT DISTINCT ON (customer_gender) customer_gender, taxful_total_price \
+                FROM KibanaSampleDataEcommerce \
+                ORDER BY customer_gender, taxful_total_price DESC LIMIT 10"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        // The first row of every group is selected by DataFusion
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.dimensions,
+            Some(vec![
+                "KibanaSampleDataEcommerce.customer_gender".to_string(),
+                "KibanaSampleDataEcommerce.taxful_total_price".to_string(),
+            ])
+        );
+        assert_eq!(request.order, None);
+        assert_eq!(request.limit, None);
+
+        let fields = logical_plan
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                "customer_gender".to_string(),
+                "taxful_total_price".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_having() {
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce \
+                GROUP BY 1 HAVING COUNT(*) > 10"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.measures,
+            Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+        );
+        assert_eq!(
+            request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.count".to_string()),
+                operator: Some("gt".to_string()),
+                values: Some(vec!["10".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_select_aggregate_filter() {
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT customer_gender, COUNT(*) FILTER (WHERE customer_gender = 'female') AS c \
+                FROM KibanaSampleDataEcommerce GROUP BY 1"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.measures,
+            Some(vec!["KibanaSampleDataEcommerce.count".to_string()])
+        );
+        assert_eq!(
+            request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["female".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_where_filter_regexp() {
+        let to_check = vec![
+            ("customer_gender ~ '^fem'", "startsWith", "fem"),
+            ("customer_gender ~* 'male$'", "endsWith", "male"),
+            ("customer_gender !~ 'fe\\.'", "notContains", "fe."),
+            ("customer_gender ~ '^female$'", "equals", "female"),
+        ];
+
+        for (sql, operator, value) in to_check.iter() {
+            let logical_plan = convert_select_to_query_plan(
+                format!(
+                    "SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE {}",
+                    sql
+                ),
+                DatabaseProtocol::PostgreSQL,
+            )
+            .as_logical_plan();
+
+            assert_eq!(
+                logical_plan.find_cube_scan().request.filters,
+                Some(vec![V1LoadRequestQueryFilterItem {
+                    member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                    operator: Some(operator.to_string()),
+                    values: Some(vec![value.to_string()]),
+                    or: None,
+                    and: None,
+                }]),
+                "Filters for {}",
+                sql
+            );
+        }
+
+        // Filters of DataFusion plans are rewritten by the same rules
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce \
+                WHERE customer_gender ~* '^fem' GROUP BY 1 HAVING COUNT(*) > 10"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+        assert!(logical_plan
+            .find_cube_scan()
+            .request
+            .filters
+            .unwrap_or_default()
+            .contains(&V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("startsWith".to_string()),
+                values: Some(vec!["fem".to_string()]),
+                or: None,
+                and: None,
+            }));
+    }
+
+    #[test]
+    fn test_select_measure_via_function() {
+        let query_plan = convert_select_to_query_plan(
+            "SELECT MEASURE(maxPrice), MEASURE(minPrice), MEASURE(avgPrice) FROM KibanaSampleDataEcommerce".to_string(),
+        DatabaseProtocol::MySQL);
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec![
+                    "KibanaSampleDataEcommerce.maxPrice".to_string(),
+                    "KibanaSampleDataEcommerce.minPrice".to_string(),
+                    "KibanaSampleDataEcommerce.avgPrice".to_string(),
+                ]),
+                segments: Some(vec![]),
+                dimensions: Some(vec![]),
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                timezone: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_query_plan_result_columns() -> Result<(), CubeError> {
+        let query_plan = convert_select_to_query_plan(
+            "SELECT COUNT(*) AS cnt, customer_gender FROM KibanaSampleDataEcommerce GROUP BY 2"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
+        let columns = query_plan.result_columns()?.unwrap();
+        assert_eq!(
+            columns
+                .iter()
+                .map(|column| (column.get_name(), column.get_type()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("cnt".to_string(), ColumnType::Int64),
+                ("customer_gender".to_string(), ColumnType::String),
+            ]
+        );
+
+        let query_plan =
+            convert_select_to_query_plan("BEGIN".to_string(), DatabaseProtocol::PostgreSQL);
+        assert!(query_plan.result_columns()?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_compound_identifiers() {
+        let query_plan = convert_select_to_query_plan(
+            "SELECT MEASURE(`KibanaSampleDataEcommerce`.`maxPrice`) AS maxPrice, MEASURE(`KibanaSampleDataEcommerce`.`minPrice`) AS minPrice FROM KibanaSampleDataEcommerce".to_string(), DatabaseProtocol::MySQL
+        );
+
+        let logical_plan = query_plan.as_logical_plan();
+        assert_eq!(
+            logical_plan.find_cube_scan().request,
+            V1LoadRequestQuery {
+                measures: Some(vec![
+                    "KibanaSampleDataEcommerce.maxPrice".to_string(),
+                    "KibanaSampleDataEcommerce.minPrice".to_string(),
+                ]),
+                segments: Some(vec![]),
+                dimensions: Some(vec![]),
+                time_dimensions: None,
+                order: None,
+                limit: None,
+                offset: None,
+                filters: None,
+                timezone: None
+            }
+        );
+    }
 
     #[test]
     fn test_select_measure_aggregate_functions() {
@@ -2522,7 +3484,8 @@ mod tests {
                 order: None,
                 limit: None,
                 offset: None,
-                filters: None
+                filters: None,
+                timezone: None
             }
         )
     }
@@ -2547,7 +3510,8 @@ mod tests {
                 ]]),
                 limit: None,
                 offset: None,
-                filters: None
+                filters: None,
+                timezone: None
             }
         )
     }
@@ -2571,7 +3535,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             ),
             // test_order_indentifier_default
@@ -2590,7 +3555,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             ),
             // test_order_compound_identifier_default
@@ -2609,7 +3575,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             ),
             // test_order_indentifier_asc
@@ -2628,7 +3595,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             ),
             // test_order_indentifier_desc
@@ -2647,7 +3615,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             ),
             // test_order_identifer_alias_ident_no_escape
@@ -2666,7 +3635,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             ),
             // test_order_identifer_alias_ident_escape
@@ -2685,7 +3655,8 @@ mod tests {
                     ]]),
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             ),
         ];
@@ -2700,6 +3671,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_order_by_pushdown() {
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT customer_gender, COUNT(*) FROM KibanaSampleDataEcommerce \
+                GROUP BY 1 ORDER BY 2 DESC LIMIT 5 OFFSET 10"
+                .to_string(),
+            DatabaseProtocol::MySQL,
+        )
+        .as_logical_plan();
+
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.order,
+            Some(vec![vec![
+                "KibanaSampleDataEcommerce.count".to_string(),
+                "desc".to_string(),
+            ]])
+        );
+        assert_eq!(request.limit, Some(5));
+        assert_eq!(request.offset, Some(10));
+
+        // Expressions are sorted by DataFusion, the limit is applied after the sort
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT customer_gender FROM KibanaSampleDataEcommerce \
+                ORDER BY LOWER(customer_gender) LIMIT 5"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.dimensions,
+            Some(vec!["KibanaSampleDataEcommerce.customer_gender".to_string()])
+        );
+        assert_eq!(request.order, None);
+        assert_eq!(request.limit, None);
+    }
+
     #[test]
     fn test_order_function_date() {
         let query_plan = convert_select_to_query_plan(
@@ -2725,7 +3735,8 @@ mod tests {
                 ]]),
                 limit: None,
                 offset: None,
-                filters: None
+                filters: None,
+                timezone: None
             }
         )
     }
@@ -2751,7 +3762,8 @@ mod tests {
                 order: None,
                 limit: Some(100),
                 offset: None,
-                filters: None
+                filters: None,
+                timezone: None
             }
         )
     }
@@ -2777,7 +3789,8 @@ mod tests {
                 order: None,
                 limit: Some(100),
                 offset: Some(50),
-                filters: None
+                filters: None,
+                timezone: None
             }
         )
     }
@@ -2803,6 +3816,7 @@ mod tests {
                 limit: None,
                 offset: None,
                 filters: None,
+                timezone: None,
             }
         )
     }
@@ -2829,6 +3843,7 @@ mod tests {
                 limit: None,
                 offset: None,
                 filters: None,
+                timezone: None,
             }
         );
 
@@ -2858,6 +3873,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    timezone: None,
                 },
             ),
             (
@@ -2871,6 +3887,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    timezone: None,
                 },
             ),
             (
@@ -2884,6 +3901,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    timezone: None,
                 },
             ),
             (
@@ -2897,6 +3915,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    timezone: None,
                 },
             ),
             (
@@ -2910,6 +3929,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    timezone: None,
                 },
             ),
             (
@@ -2923,6 +3943,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    timezone: None,
                 },
             ),
             (
@@ -2936,6 +3957,7 @@ mod tests {
                     limit: None,
                     offset: None,
                     filters: None,
+                    timezone: None,
                 },
             ),
         ];
@@ -3077,12 +4099,70 @@ mod tests {
                     order: None,
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             )
         }
     }
 
+    #[test]
+    fn test_group_by_date_trunc_timezone() {
+        let logical_plan = convert_select_to_query_plan(
+            "SELECT COUNT(*), DATE_TRUNC('Week', order_date, 'America/New_York') AS __timestamp \
+                FROM KibanaSampleDataEcommerce GROUP BY __timestamp"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .as_logical_plan();
+
+        let request = logical_plan.find_cube_scan().request;
+        assert_eq!(
+            request.time_dimensions,
+            Some(vec![V1LoadRequestQueryTimeDimension {
+                dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                granularity: Some("week".to_string()),
+                date_range: None,
+            }])
+        );
+        assert_eq!(request.timezone, Some("America/New_York".to_string()));
+
+        // TimeZone of the session is used without the time zone argument
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        session
+            .state
+            .settings_mut()
+            .set("TimeZone", Some("Europe/Berlin"), false)
+            .unwrap();
+        let query = convert_sql_to_cube_query(
+            &"SELECT COUNT(*), DATE_TRUNC('quarter', order_date) AS __timestamp \
+                FROM KibanaSampleDataEcommerce GROUP BY __timestamp"
+                .to_string(),
+            get_test_tenant_ctx(),
+            session.clone(),
+        );
+        let request = query.unwrap().as_logical_plan().find_cube_scan().request;
+        assert_eq!(request.timezone, Some("Europe/Berlin".to_string()));
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT COUNT(*), DATE_TRUNC('day', order_date, 'UTC') AS d, \
+                DATE_TRUNC('month', order_date) AS m \
+                FROM KibanaSampleDataEcommerce GROUP BY 2, 3"
+                .to_string(),
+            get_test_tenant_ctx(),
+            session,
+        );
+        match query {
+            Ok(_) => panic!("Time dimensions in different time zones must be rejected"),
+            Err(e) => assert_eq!(
+                e,
+                CompilationError::Unsupported(
+                    "Time dimensions in time zones UTC and Europe/Berlin in one query".to_string()
+                )
+            ),
+        }
+    }
+
     #[test]
     fn test_group_by_date_granularity_superset() {
         let supported_granularities = vec![
@@ -3128,7 +4208,8 @@ mod tests {
                     order: None,
                     limit: None,
                     offset: None,
-                    filters: None
+                    filters: None,
+                    timezone: None
                 }
             )
         }
@@ -3977,18 +5058,98 @@ mod tests {
     }
 
     #[test]
-    fn test_str_literal_to_date() {
-        let d = CompiledExpression::StringLiteral("2021-08-31".to_string())
-            .to_date_literal()
-            .unwrap();
-        assert_eq!(
-            d.to_value_as_str().unwrap(),
-            "2021-08-31T00:00:00.000Z".to_string()
-        );
+    fn test_interval_arithmetic_expr() {
+        let to_check = vec![
+            (
+                "date('2022-03-31 00:00:00.000000') - INTERVAL '1 month 2 days'",
+                "2022-02-26 00:00:00 UTC",
+            ),
+            (
+                "INTERVAL '1 hour 30 minutes' + '2022-03-01T00:00:00Z'::timestamp",
+                "2022-03-01 01:30:00 UTC",
+            ),
+            (
+                "TIMESTAMP '2022-01-31 10:00:00.000000' + INTERVAL '1' MONTH",
+                "2022-02-28 10:00:00 UTC",
+            ),
+            (
+                "(DATE '2022-03-01' - CAST('1 day' AS INTERVAL))",
+                "2022-02-28 00:00:00 UTC",
+            ),
+        ];
+
+        for (sql, expected_date) in to_check.iter() {
+            let compiled = compile_expression(
+                &parse_expr_from_projection(
+                    &format!("SELECT {}", sql),
+                    DatabaseProtocol::PostgreSQL,
+                ),
+                &QueryContext::new(&get_test_meta()[0]),
+            )
+            .unwrap();
+
+            match compiled {
+                CompiledExpression::DateLiteral(date) => {
+                    assert_eq!(date.to_string(), expected_date.to_string())
+                }
+                _ => panic!("Must be DateLiteral"),
+            };
+        }
+
+        assert_eq!(
+            compile_expression(
+                &parse_expr_from_projection(
+                    &"SELECT now() - INTERVAL '30 dayz'".to_string(),
+                    DatabaseProtocol::PostgreSQL,
+                ),
+                &QueryContext::new(&get_test_meta()[0]),
+            ),
+            Err(CompilationError::User(
+                "invalid input syntax for type interval: \"30 dayz\"".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_where_filter_interval_arithmetic() {
+        let query_plan = convert_select_to_query_plan(
+            "SELECT COUNT(*) FROM KibanaSampleDataEcommerce \
+            WHERE order_date >= date('2021-09-30 00:00:00.000000') - INTERVAL '30 days' \
+            AND order_date < date('2021-09-07 00:00:00.000000')"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
 
-        let d = CompiledExpression::StringLiteral("2021-08-31 00:00:00.000000".to_string())
-            .to_date_literal()
-            .unwrap();
+        assert_eq!(
+            query_plan
+                .as_logical_plan()
+                .find_cube_scan()
+                .request
+                .time_dimensions,
+            Some(vec![V1LoadRequestQueryTimeDimension {
+                dimension: "KibanaSampleDataEcommerce.order_date".to_string(),
+                granularity: None,
+                date_range: Some(json!(vec![
+                    "2021-08-31T00:00:00.000Z".to_string(),
+                    "2021-09-06T23:59:59.999Z".to_string()
+                ])),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_str_literal_to_date() {
+        let d = CompiledExpression::StringLiteral("2021-08-31".to_string())
+            .to_date_literal()
+            .unwrap();
+        assert_eq!(
+            d.to_value_as_str().unwrap(),
+            "2021-08-31T00:00:00.000Z".to_string()
+        );
+
+        let d = CompiledExpression::StringLiteral("2021-08-31 00:00:00.000000".to_string())
+            .to_date_literal()
+            .unwrap();
         assert_eq!(
             d.to_value_as_str().unwrap(),
             "2021-08-31T00:00:00.000Z".to_string()
@@ -4232,6 +5393,503 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_generate_series() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT n, n * 2 AS d FROM generate_series(1, 5, 2) AS s(n)".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+---+----+\n\
+            | n | d  |\n\
+            +---+----+\n\
+            | 1 | 2  |\n\
+            | 3 | 6  |\n\
+            | 5 | 10 |\n\
+            +---+----+"
+        );
+
+        assert_eq!(
+            execute_query(
+                "SELECT generate_series(-1, 0)".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-----------------+\n\
+            | generate_series |\n\
+            +-----------------+\n\
+            | -1              |\n\
+            | 0               |\n\
+            +-----------------+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_json_functions() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT '{\"a\": {\"b\": [1, \"x\"]}}'::jsonb -> 'a' -> 'b' ->> -1 AS v, \
+                json_extract_path('{\"a\": {\"b\": 1}}', 'a') AS o, \
+                json_build_object('n', 1, 's', 'x') AS j"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+---+----------+----------------------+\n\
+            | v | o        | j                    |\n\
+            +---+----------+----------------------+\n\
+            | x | {\"b\": 1} | {\"n\" : 1, \"s\" : \"x\"} |\n\
+            +---+----------+----------------------+"
+        );
+
+        // Casts validate the text, jsonb is printed as PostgreSQL prints it
+        assert_eq!(
+            execute_query(
+                "SELECT '{\"b\":1,\"a\":[1,2]}'::jsonb AS b, CAST('{\"b\":1}' AS json) AS j"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-----------------------+---------+\n\
+            | b                     | j       |\n\
+            +-----------------------+---------+\n\
+            | {\"a\": [1, 2], \"b\": 1} | {\"b\":1} |\n\
+            +-----------------------+---------+"
+        );
+        let err = execute_query(
+            "SELECT 'a'::jsonb".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.message.contains("invalid input syntax for type json"),
+            "{}",
+            err.message
+        );
+
+        assert_eq!(
+            execute_query(
+                "SELECT json_agg(n) AS a FROM generate_series(1, 3) AS s(n)".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-----------+\n\
+            | a         |\n\
+            +-----------+\n\
+            | [1, 2, 3] |\n\
+            +-----------+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_to_char() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT to_char(CAST('2022-03-01 15:04:05' AS TIMESTAMP), 'YYYY-MM') AS m, \
+                to_char(CAST('2022-03-01' AS DATE), 'FMDay, FMDDth') AS d, \
+                to_char(1234567.891, 'FM999,999,999.00') AS n, \
+                to_char(-42, '0000') AS z"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+---------+--------------+--------------+-------+\n\
+            | m       | d            | n            | z     |\n\
+            +---------+--------------+--------------+-------+\n\
+            | 2022-03 | Tuesday, 1st | 1,234,567.89 | -0042 |\n\
+            +---------+--------------+--------------+-------+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_string_agg() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT string_agg(CAST(n AS TEXT), ', ' ORDER BY n DESC) AS s, \
+                array_agg(DISTINCT n % 2 ORDER BY n % 2 DESC) AS a, \
+                listagg(CAST(n AS TEXT)) WITHIN GROUP (ORDER BY n) AS l \
+                FROM generate_series(1, 3) AS g(n)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+---------+-------+-----+\n\
+            | s       | a     | l   |\n\
+            +---------+-------+-----+\n\
+            | 3, 2, 1 | {1,0} | 123 |\n\
+            +---------+-------+-----+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_percentile() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT percentile_cont(0.25) WITHIN GROUP (ORDER BY n) AS c, \
+                percentile_disc(0.5) WITHIN GROUP (ORDER BY n DESC) AS d, \
+                median(n) AS m \
+                FROM generate_series(1, 4) AS g(n)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+------+---+-----+\n\
+            | c    | d | m   |\n\
+            +------+---+-----+\n\
+            | 1.75 | 3 | 2.5 |\n\
+            +------+---+-----+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_filter() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT COUNT(*) FILTER (WHERE n > 1) AS c, \
+                SUM(n) FILTER (WHERE n % 2 = 0) AS s, \
+                string_agg(CAST(n AS TEXT), ',' ORDER BY n DESC) FILTER (WHERE n < 4) AS l \
+                FROM generate_series(1, 4) AS g(n)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+---+---+-------+\n\
+            | c | s | l     |\n\
+            +---+---+-------+\n\
+            | 3 | 6 | 3,2,1 |\n\
+            +---+---+-------+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_regexp_functions() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT regexp_matches('foobarbequebaz', '(bar)(beque)') AS m, \
+                regexp_matches('Abc', 'b', 'i') AS i, \
+                regexp_replace('Thomas', '.[mN]a.', 'M') AS r, \
+                substring('foobar' from 'o(.)b') AS s"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-------------+-----+-----+---+\n\
+            | m           | i   | r   | s |\n\
+            +-------------+-----+-----+---+\n\
+            | {bar,beque} | {b} | ThM | o |\n\
+            +-------------+-----+-----+---+"
+        );
+
+        assert_eq!(
+            execute_query(
+                "SELECT COUNT(*) AS c FROM generate_series(1, 12) AS g(n) \
+                WHERE CAST(n AS TEXT) ~ '^1'"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+---+\n\
+            | c |\n\
+            +---+\n\
+            | 4 |\n\
+            +---+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_extract() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT EXTRACT(EPOCH FROM CAST('2022-03-01 00:00:00' AS TIMESTAMP)) AS e, \
+                date_part('dow', CAST('2022-03-01' AS DATE)) AS d, \
+                EXTRACT(DOY FROM CAST('2022-03-01' AS DATE)) AS y, \
+                EXTRACT(ISOYEAR FROM CAST('2022-01-01' AS DATE)) AS i, \
+                EXTRACT(WEEK FROM CAST('2022-01-01' AS DATE)) AS w"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+------------+---+----+------+----+\n\
+            | e          | d | y  | i    | w  |\n\
+            +------------+---+----+------+----+\n\
+            | 1646092800 | 2 | 60 | 2021 | 52 |\n\
+            +------------+---+----+------+----+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_arrays() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT ARRAY[1, 2] AS a, array_agg(n) AS b FROM generate_series(1, 3) AS s(n)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-------+---------+\n\
+            | a     | b       |\n\
+            +-------+---------+\n\
+            | {1,2} | {1,2,3} |\n\
+            +-------+---------+"
+        );
+
+        assert_eq!(
+            execute_query(
+                "SELECT n FROM unnest(ARRAY['a', 'b']) AS u(n)".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+---+\n\
+            | n |\n\
+            +---+\n\
+            | a |\n\
+            | b |\n\
+            +---+"
+        );
+
+        assert_eq!(
+            execute_query(
+                "SELECT n FROM generate_series(1, 5) AS s(n) WHERE n = ANY(ARRAY[2, 4])"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+---+\n\
+            | n |\n\
+            +---+\n\
+            | 2 |\n\
+            | 4 |\n\
+            +---+"
+        );
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE customer_gender = ANY(ARRAY['FEMALE', 'MALE'])".to_string(),
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )?;
+        assert_eq!(
+            query.as_logical_plan().find_cube_scan().request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["FEMALE".to_string(), "MALE".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_decimal_arithmetic() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "SELECT CAST(n AS NUMERIC(10, 2)) / 3 AS v FROM generate_series(1, 2) AS s(n)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+--------------------+\n\
+            | v                  |\n\
+            +--------------------+\n\
+            | 0.3333333333333333 |\n\
+            | 0.6666666666666667 |\n\
+            +--------------------+"
+        );
+
+        assert_eq!(
+            execute_query(
+                "SELECT SUM(CAST(n AS NUMERIC(10, 2)) * 1.5) AS total FROM generate_series(1, 3) AS s(n)"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-------+\n\
+            | total |\n\
+            +-------+\n\
+            | 9.000 |\n\
+            +-------+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timestamptz_session_time_zone() -> Result<(), CubeError> {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        session
+            .state
+            .settings_mut()
+            .set("TimeZone", Some("America/New_York"), false)?;
+
+        // 03:30 UTC is 22:30 of the previous day in New York
+        let query = convert_sql_to_cube_query(
+            &"SELECT \
+                date_trunc('day', CAST('2022-03-01 03:30:00+00' AS TIMESTAMPTZ)) AS d, \
+                EXTRACT(HOUR FROM CAST('2022-03-01 03:30:00+00' AS TIMESTAMPTZ)) AS h, \
+                CAST(CAST('2022-03-01 12:00:00' AS TIMESTAMPTZ) AS TIMESTAMP) AS l"
+                .to_string(),
+            get_test_tenant_ctx(),
+            session,
+        )?;
+        let batches = match query {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                DataFrameImpl::new(ctx.state, &plan).collect().await?
+            }
+            _ => panic!("Timestamps with time zone must be calculated by DataFusion"),
+        };
+        let frame = batch_to_dataframe(&batches)?;
+
+        assert_eq!(frame.get_columns()[0].get_type(), ColumnType::Timestamptz);
+        assert_eq!(
+            frame.print(),
+            "+--------------------------+----+--------------------------+\n\
+            | d                        | h  | l                        |\n\
+            +--------------------------+----+--------------------------+\n\
+            | 2022-02-28T05:00:00.000Z | 22 | 2022-03-01T12:00:00.000Z |\n\
+            +--------------------------+----+--------------------------+"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_path() {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let query = "SELECT COUNT(*) FROM KibanaSampleDataEcommerce".to_string();
+        let set_search_path = |value: &str| {
+            session
+                .state
+                .settings_mut()
+                .set("search_path", Some(value), false)
+                .unwrap()
+        };
+
+        // Cubes are in the public schema of the default search_path
+        assert!(convert_sql_to_cube_query(&query, get_test_tenant_ctx(), session.clone()).is_ok());
+
+        set_search_path("information_schema");
+        match convert_sql_to_cube_query(&query, get_test_tenant_ctx(), session.clone()) {
+            Err(CompilationError::User(message)) => assert_eq!(
+                message,
+                "relation \"KibanaSampleDataEcommerce\" does not exist"
+            ),
+            _ => panic!("the cube must not be found out of search_path"),
+        }
+
+        set_search_path("analytics, public");
+        assert_eq!(
+            session.state.search_path(),
+            vec!["pg_catalog", "analytics", "public"]
+        );
+        assert!(convert_sql_to_cube_query(&query, get_test_tenant_ctx(), session.clone()).is_ok());
+
+        set_search_path("information_schema, pg_catalog");
+        assert!(convert_sql_to_cube_query(
+            &"SELECT table_name FROM tables".to_string(),
+            get_test_tenant_ctx(),
+            session.clone()
+        )
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_pg_settings_functions() -> Result<(), CubeError> {
+        assert_eq!(
+            execute_query(
+                "select set_config('statement_timeout', '30000', false) as r1, \
+                current_setting('extra_float_digits') as r2, \
+                current_setting('unknown.setting', true) as r3"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-----+----+------+\n\
+            | r1  | r2 | r3   |\n\
+            +-----+----+------+\n\
+            | 30s | 1  | NULL |\n\
+            +-----+----+------+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pgcatalog_pg_settings_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "pgcatalog_pg_settings_postgres",
+            execute_query(
+                "SELECT name, setting, unit, context, vartype, source \
+                FROM pg_catalog.pg_settings \
+                WHERE name IN ('DateStyle', 'server_version', 'statement_timeout') \
+                ORDER BY name"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_terminate_backend() -> Result<(), CubeError> {
+        // The session 1 is terminated, the next message of its client closes the connection
+        assert_eq!(
+            execute_query(
+                "select pg_terminate_backend(1) as r1, pg_terminate_backend(100) as r2"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+------+-------+\n\
+            | r1   | r2    |\n\
+            +------+-------+\n\
+            | true | false |\n\
+            +------+-------+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_cancel_backend() -> Result<(), CubeError> {
+        // There is no query in progress for the session and no session 100
+        assert_eq!(
+            execute_query(
+                "select pg_cancel_backend(1) as r1, pg_cancel_backend(100) as r2".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?,
+            "+-------+-------+\n\
+            | r1    | r2    |\n\
+            +-------+-------+\n\
+            | false | false |\n\
+            +-------+-------+"
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_ucase() -> Result<(), CubeError> {
         assert_eq!(
@@ -4667,6 +6325,147 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_explain_postgres() -> Result<(), CubeError> {
+        let explain = |query: &str| -> Result<Vec<String>, CubeError> {
+            let plan = convert_sql_to_cube_query(
+                &query.to_string(),
+                get_test_tenant_ctx(),
+                get_test_session(DatabaseProtocol::PostgreSQL),
+            )?;
+            match plan {
+                QueryPlan::MetaTabular(_, frame) => Ok(frame
+                    .get_rows()
+                    .iter()
+                    .map(|row| match &row.values()[0] {
+                        dataframe::TableValue::String(line) => line.clone(),
+                        value => panic!("unexpected value of QUERY PLAN: {:?}", value),
+                    })
+                    .collect()),
+                _ => panic!("EXPLAIN must return QUERY PLAN"),
+            }
+        };
+
+        assert_eq!(
+            explain("EXPLAIN SELECT COUNT(*) AS cnt FROM KibanaSampleDataEcommerce")?,
+            vec![
+                "Pushdown: full",
+                "Projection: #KibanaSampleDataEcommerce.count AS cnt  [DataFusion: columns are renamed]",
+                "  CubeScan: Cube query 1  [Cube]",
+                "Cube query 1:",
+                "  {",
+                "    \"measures\": [",
+                "      \"KibanaSampleDataEcommerce.count\"",
+                "    ],",
+                "    \"dimensions\": [],",
+                "    \"segments\": []",
+                "  }",
+            ]
+        );
+
+        let lines = explain("EXPLAIN SELECT typname FROM pg_catalog.pg_type")?;
+        assert_eq!(lines[0], "Pushdown: none");
+        assert!(lines
+            .iter()
+            .any(|line| line.trim_start().starts_with("TableScan: ")
+                && line.ends_with("[DataFusion: the table is not a cube]")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_explain_analyze_postgres() -> Result<(), CubeError> {
+        let stmt = parse_sql_to_statement(
+            &"EXPLAIN ANALYZE SELECT typname FROM pg_catalog.pg_type".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )?;
+        let stmt = match stmt {
+            ast::Statement::Explain { statement, .. } => statement,
+            _ => panic!("EXPLAIN ANALYZE must be parsed as EXPLAIN"),
+        };
+
+        let plan = explain::explain_analyze(
+            &stmt,
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )
+        .await?;
+        let lines = match plan {
+            QueryPlan::MetaTabular(_, frame) => frame
+                .get_rows()
+                .iter()
+                .map(|row| match &row.values()[0] {
+                    dataframe::TableValue::String(line) => line.clone(),
+                    value => panic!("unexpected value of QUERY PLAN: {:?}", value),
+                })
+                .collect::<Vec<_>>(),
+            _ => panic!("EXPLAIN ANALYZE must return QUERY PLAN"),
+        };
+
+        assert_eq!(lines[0], "Pushdown: none");
+        assert!(lines.iter().any(|line| line.starts_with("Rewrite Time: ")));
+        assert!(lines.iter().any(|line| line.starts_with("DataFusion Time: ")));
+        assert!(lines.iter().all(|line| !line.contains("never executed")));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("Rows: ") && line != "Rows: 0"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_select_in_subquery() -> Result<(), CubeError> {
+        let stmt = parse_sql_to_statement(
+            &"SELECT COUNT(*) FROM KibanaSampleDataEcommerce WHERE customer_gender IN (SELECT typname FROM pg_catalog.pg_type WHERE typname = 'bool')".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )?;
+        let stmt = subquery::evaluate_subqueries(
+            &stmt,
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )
+        .await?
+        .expect("subquery must be evaluated");
+
+        let query = convert_statement_to_cube_query(
+            &stmt,
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )?;
+        assert_eq!(
+            query.as_logical_plan().find_cube_scan().request.filters,
+            Some(vec![V1LoadRequestQueryFilterItem {
+                member: Some("KibanaSampleDataEcommerce.customer_gender".to_string()),
+                operator: Some("equals".to_string()),
+                values: Some(vec!["bool".to_string()]),
+                or: None,
+                and: None,
+            }])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_select_correlated_system_subquery() -> Result<(), CubeError> {
+        let stmt = parse_sql_to_statement(
+            &"SELECT tablename FROM pg_catalog.pg_tables WHERE EXISTS (SELECT 1 FROM pg_catalog.pg_namespace n WHERE n.nspname = schemaname)".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )?;
+        let evaluated = subquery::evaluate_subqueries(
+            &stmt,
+            get_test_tenant_ctx(),
+            get_test_session(DatabaseProtocol::PostgreSQL),
+        )
+        .await?
+        .expect("subquery must be visited");
+
+        // The subquery references `schemaname` of the outer query, it's left to DataFusion
+        assert_eq!(evaluated.to_string(), stmt.to_string());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_metabase() -> Result<(), CubeError> {
         insta::assert_snapshot!(
@@ -4817,4 +6616,75 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_pgcatalog_cubesql_connections_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "pgcatalog_cubesql_connections_postgres",
+            execute_query(
+                "SELECT * FROM pg_catalog.cubesql_connections".to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pgcatalog_pg_stat_activity_postgres() -> Result<(), CubeError> {
+        insta::assert_snapshot!(
+            "pgcatalog_pg_stat_activity_postgres",
+            execute_query(
+                "SELECT pid, datname, usename, application_name, client_addr, client_port, \
+                wait_event_type, wait_event, state, query, backend_type \
+                FROM pg_catalog.pg_stat_activity"
+                    .to_string(),
+                DatabaseProtocol::PostgreSQL
+            )
+            .await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pgcatalog_pg_stat_statements() -> Result<(), CubeError> {
+        let session = get_test_session(DatabaseProtocol::PostgreSQL);
+        let stats = &session.session_manager.query_stats;
+        for millis in [10, 30] {
+            stats.record(
+                Some("ovr".to_string()),
+                "SELECT $1".to_string(),
+                std::time::Duration::from_millis(millis),
+                1,
+            );
+        }
+
+        let query = convert_sql_to_cube_query(
+            &"SELECT usename, query, calls, total_exec_time, mean_exec_time, rows \
+            FROM pg_stat_statements"
+                .to_string(),
+            get_test_tenant_ctx(),
+            session,
+        )?;
+        let frame = match query {
+            QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                let batches = DataFrameImpl::new(ctx.state, &plan).collect().await?;
+                batch_to_dataframe(&batches)?
+            }
+            _ => panic!("pg_stat_statements must be planned by DataFusion"),
+        };
+
+        assert_eq!(
+            frame.print(),
+            "+---------+-----------+-------+-----------------+----------------+------+\n\
+            | usename | query     | calls | total_exec_time | mean_exec_time | rows |\n\
+            +---------+-----------+-------+-----------------+----------------+------+\n\
+            | ovr     | SELECT $1 | 2     | 40              | 20             | 2    |\n\
+            +---------+-----------+-------+-----------------+----------------+------+"
+        );
+
+        Ok(())
+    }
 }
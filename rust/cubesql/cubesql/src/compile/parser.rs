@@ -2,7 +2,12 @@ use sqlparser::{ast::Statement, dialect::Dialect, dialect::PostgreSqlDialect, pa
 
 use crate::{compile::CompilationError, sql::session::DatabaseProtocol};
 
-use super::CompilationResult;
+use super::{
+    distinct_on::DISTINCT_ON_FUNCTION,
+    engine::udf::{AGGREGATE_FILTER_FUNCTION, AGGREGATE_ORDER_BY_FUNCTION},
+    grouping::{GROUPING_SETS_FUNCTION, GROUPING_SET_FUNCTION},
+    CompilationResult,
+};
 
 #[derive(Debug)]
 pub struct MySqlDialectWithBackTicks {}
@@ -29,6 +34,878 @@ impl Dialect for MySqlDialectWithBackTicks {
     }
 }
 
+fn is_word_part(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '$' || ch == '@'
+}
+
+/// End of `$tag$` (or `$$`) of a dollar-quoted string which starts at `i`. Placeholders (`$1`)
+/// are not tags.
+fn dollar_tag_end(chars: &[char], i: usize) -> Option<usize> {
+    if chars.get(i) != Some(&'$') {
+        return None;
+    }
+
+    let mut j = i + 1;
+    while let Some(ch) = chars.get(j) {
+        match ch {
+            '$' => return Some(j + 1),
+            ch if ch.is_alphabetic() || *ch == '_' => {}
+            ch if ch.is_ascii_digit() && j > i + 1 => {}
+            _ => return None,
+        }
+        j += 1;
+    }
+
+    None
+}
+
+/// End of a quoted string, identifier or comment which starts at `i`, `i` otherwise. Escape
+/// strings (`E'it\'s'`) and dollar-quoted strings (`$$a$$`, `$tag$a$tag$`) are strings too.
+fn quoted_end(chars: &[char], i: usize) -> usize {
+    let ch = chars[i];
+    let next = chars.get(i + 1).cloned();
+    // `E` and `$` of `name'` and `a$b` are parts of words
+    let word_start = i == 0 || !is_word_part(chars[i - 1]);
+
+    let end = match (ch, next) {
+        ('E', Some('\'')) | ('e', Some('\'')) if word_start => {
+            let mut j = i + 2;
+            loop {
+                match chars.get(j) {
+                    Some('\\') => j += 2,
+                    Some('\'') if chars.get(j + 1) == Some(&'\'') => j += 2,
+                    Some('\'') => break Some(j + 1),
+                    Some(_) => j += 1,
+                    None => break None,
+                }
+            }
+        }
+        ('$', _) if word_start => match dollar_tag_end(chars, i) {
+            Some(tag_end) => {
+                let tag = &chars[i..tag_end];
+                chars[tag_end..]
+                    .windows(tag.len())
+                    .position(|w| w == tag)
+                    .map(|p| tag_end + p + tag.len())
+            }
+            None => Some(i),
+        },
+        ('\'', _) | ('"', _) | ('`', _) => chars[i + 1..]
+            .iter()
+            .position(|c| *c == ch)
+            .map(|p| i + p + 2),
+        ('-', Some('-')) => chars[i..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map(|p| i + p + 1),
+        ('/', Some('*')) => chars[i + 2..]
+            .windows(2)
+            .position(|w| w == ['*', '/'])
+            .map(|p| i + p + 4),
+        _ => Some(i),
+    };
+
+    end.unwrap_or_else(|| chars.len())
+}
+
+/// Position of `)` which closes `(` at `open`
+fn closing_paren(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < chars.len() {
+        let end = quoted_end(chars, i);
+        if end > i {
+            i = end;
+            continue;
+        }
+
+        match chars[i] {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// Position of the next `word` after whitespaces, which starts at `i`
+fn next_word(chars: &[char], i: usize, word: &str) -> Option<usize> {
+    let start = (i..chars.len()).find(|j| !chars[*j].is_whitespace())?;
+    let end = start + word.len();
+
+    if end <= chars.len()
+        && chars[start..end]
+            .iter()
+            .collect::<String>()
+            .eq_ignore_ascii_case(word)
+        && !chars.get(end).map_or(false, |c| is_word_part(*c))
+    {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+/// `SELECT DISTINCT ON (a, b) a, c` is rewritten into `SELECT __distinct_on(a, b), a, c`, as the
+/// pinned sqlparser doesn't parse DISTINCT ON
+fn rewrite_distinct_on(query: &str) -> String {
+    if !query.to_lowercase().contains("distinct") {
+        return query.to_string();
+    }
+
+    let chars = query.chars().collect::<Vec<_>>();
+    let mut result = String::with_capacity(query.len());
+
+    let mut i = 0;
+    while i < chars.len() {
+        let end = quoted_end(&chars, i);
+        if end > i {
+            result.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        if is_word_part(chars[i]) {
+            let word_end = chars[i..]
+                .iter()
+                .position(|c| !is_word_part(*c))
+                .map(|p| i + p)
+                .unwrap_or_else(|| chars.len());
+            let word = chars[i..word_end].iter().collect::<String>();
+
+            if word.eq_ignore_ascii_case("distinct") {
+                let paren = next_word(&chars, word_end, "on")
+                    .and_then(|on_end| (on_end..chars.len()).find(|j| !chars[*j].is_whitespace()))
+                    .filter(|paren| chars[*paren] == '(');
+                let close = paren.and_then(|paren| closing_paren(&chars, paren));
+
+                if let (Some(paren), Some(close)) = (paren, close) {
+                    result.push_str(DISTINCT_ON_FUNCTION);
+                    result.extend(&chars[paren..=close]);
+                    result.push(',');
+                    i = close + 1;
+                    continue;
+                }
+            }
+
+            result.push_str(&word);
+            i = word_end;
+            continue;
+        }
+
+        result.push(chars[i]);
+        i += 1;
+    }
+
+    result
+}
+
+/// `GROUPING SETS ((a, b), a, ())` is rewritten into
+/// `__grouping_sets(__grouping_set(a, b), a, __grouping_set())`, as the pinned sqlparser
+/// parses neither grouping sets nor row values. Strings, quoted identifiers and comments are
+/// kept as is.
+fn rewrite_grouping_sets(query: &str) -> String {
+    if !query.to_lowercase().contains("grouping") {
+        return query.to_string();
+    }
+
+    let chars = query.chars().collect::<Vec<_>>();
+    let mut result = String::with_capacity(query.len());
+    // Depths of parentheses of GROUPING SETS, which contain sets
+    let mut sets_depths: Vec<usize> = vec![];
+    let mut depth = 0;
+    // Elements of GROUPING SETS begin after `(` and `,`
+    let mut element_start = false;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+
+        // Quoted strings, identifiers and comments are copied till their end
+        let end = quoted_end(&chars, i);
+        if end > i {
+            result.extend(&chars[i..end]);
+            element_start = false;
+            i = end;
+            continue;
+        }
+
+        if is_word_part(ch) {
+            let word_end = chars[i..]
+                .iter()
+                .position(|c| !is_word_part(*c))
+                .map(|p| i + p)
+                .unwrap_or_else(|| chars.len());
+            let word = chars[i..word_end].iter().collect::<String>();
+
+            if word.eq_ignore_ascii_case("grouping") {
+                let paren = next_word(&chars, word_end, "sets").and_then(|sets_end| {
+                    (sets_end..chars.len()).find(|j| !chars[*j].is_whitespace())
+                });
+
+                if let Some(paren) = paren {
+                    if chars[paren] == '(' {
+                        result.push_str(GROUPING_SETS_FUNCTION);
+                        result.push('(');
+                        depth += 1;
+                        sets_depths.push(depth);
+                        element_start = true;
+                        i = paren + 1;
+                        continue;
+                    }
+                }
+            }
+
+            result.push_str(&word);
+            element_start = false;
+            i = word_end;
+            continue;
+        }
+
+        match ch {
+            '(' => {
+                if element_start && sets_depths.last() == Some(&depth) {
+                    result.push_str(GROUPING_SET_FUNCTION);
+                }
+                depth += 1;
+                element_start = false;
+            }
+            ')' => {
+                if sets_depths.last() == Some(&depth) {
+                    sets_depths.pop();
+                }
+                depth = depth.saturating_sub(1);
+                element_start = false;
+            }
+            ',' => element_start = sets_depths.last() == Some(&depth),
+            ch if ch.is_whitespace() => {}
+            _ => element_start = false,
+        }
+        result.push(ch);
+        i += 1;
+    }
+
+    result
+}
+
+/// Starts of `(` of every `)` and starts of quoted strings, identifiers and comments by their
+/// last characters, to find operands before operators
+fn backward_starts(chars: &[char]) -> Vec<Option<usize>> {
+    let mut starts = vec![None; chars.len()];
+    let mut parens = vec![];
+
+    let mut i = 0;
+    while i < chars.len() {
+        let end = quoted_end(chars, i);
+        if end > i {
+            starts[end - 1] = Some(i);
+            i = end;
+            continue;
+        }
+
+        match chars[i] {
+            '(' => parens.push(i),
+            ')' => starts[i] = parens.pop(),
+            _ => {}
+        }
+        i += 1;
+    }
+
+    starts
+}
+
+/// Start of the operand which ends before `end`: a column, a function call, a parenthesized
+/// expression or a string, with its casts (`'{}'::jsonb`)
+fn operand_start(chars: &[char], starts: &[Option<usize>], end: usize) -> Option<usize> {
+    let end = (0..end).rev().find(|j| !chars[*j].is_whitespace())? + 1;
+
+    let mut start = end;
+    while let Some(last) = start.checked_sub(1) {
+        start = match (chars[last], starts[last]) {
+            ('\'', Some(quoted)) | ('$', Some(quoted)) if start == end => quoted,
+            ('"', Some(quoted)) | ('`', Some(quoted)) => quoted,
+            (')', Some(open)) if start == end => open,
+            (ch, _) if is_word_part(ch) || ch == '.' => last,
+            _ => break,
+        };
+    }
+
+    if start == end {
+        return None;
+    }
+
+    match (0..start).rev().find(|j| !chars[*j].is_whitespace()) {
+        Some(colon) if colon > 0 && chars[colon] == ':' && chars[colon - 1] == ':' => {
+            operand_start(chars, starts, colon - 1).or(Some(start))
+        }
+        _ => Some(start),
+    }
+}
+
+/// End of the key of `->` which starts after `begin`: a string, a number, a placeholder or
+/// a parenthesized expression. Numbers are returned as strings, as keys are passed as text.
+fn json_key(chars: &[char], begin: usize) -> Option<(usize, String)> {
+    let start = (begin..chars.len()).find(|j| !chars[*j].is_whitespace())?;
+    let word_end = |from: usize| {
+        chars[from..]
+            .iter()
+            .position(|c| !is_word_part(*c))
+            .map(|p| from + p)
+            .unwrap_or_else(|| chars.len())
+    };
+
+    let end = match chars[start] {
+        '\'' => quoted_end(chars, start),
+        '(' => closing_paren(chars, start)? + 1,
+        '$' => word_end(start + 1),
+        '-' if chars.get(start + 1).map_or(false, |c| c.is_ascii_digit()) => word_end(start + 1),
+        ch if ch.is_ascii_digit() => word_end(start),
+        _ => return None,
+    };
+
+    let key = chars[start..end].iter().collect::<String>();
+    if key.starts_with('-') || key.chars().all(|c| c.is_ascii_digit()) {
+        Some((end, format!("'{}'", key)))
+    } else {
+        Some((end, key))
+    }
+}
+
+/// `data -> 'a' ->> 0` is rewritten into `json_extract_path_text(json_extract_path(data, 'a'),
+/// '0')`, as the pinned sqlparser doesn't parse JSON operators. Casts of operands are kept,
+/// casts to json and jsonb are planned by `rewrite_json_casts`.
+fn rewrite_json_operators(query: &str) -> String {
+    if !query.contains("->") {
+        return query.to_string();
+    }
+
+    let mut chars = query.chars().collect::<Vec<_>>();
+
+    loop {
+        let starts = backward_starts(&chars);
+
+        let mut operator = None;
+        let mut i = 0;
+        while i < chars.len() {
+            let end = quoted_end(&chars, i);
+            if end > i {
+                i = end;
+                continue;
+            }
+
+            if chars[i] == '-' && chars.get(i + 1) == Some(&'>') {
+                operator = Some(i);
+                break;
+            }
+            i += 1;
+        }
+
+        let operator = match operator {
+            Some(operator) => operator,
+            None => break,
+        };
+        let (operator_end, function) = if chars.get(operator + 2) == Some(&'>') {
+            (operator + 3, "json_extract_path_text")
+        } else {
+            (operator + 2, "json_extract_path")
+        };
+
+        let start = operand_start(&chars, &starts, operator);
+        let key = json_key(&chars, operator_end);
+        let (start, (end, key)) = match (start, key) {
+            (Some(start), Some(key)) => (start, key),
+            // Not an operand, it's left for the parser to report
+            _ => break,
+        };
+        let operand = (start..operator)
+            .rev()
+            .find(|j| !chars[*j].is_whitespace())
+            .unwrap()
+            + 1;
+
+        let mut rewritten = chars[..start].to_vec();
+        rewritten.extend(function.chars());
+        rewritten.push('(');
+        rewritten.extend(&chars[start..operand]);
+        rewritten.extend(", ".chars());
+        rewritten.extend(key.chars());
+        rewritten.push(')');
+        rewritten.extend(&chars[end..]);
+        chars = rewritten;
+    }
+
+    chars.into_iter().collect()
+}
+
+/// `ARRAY[a, b]` is rewritten into `make_array(a, b)`, `x = ANY (SELECT ...)` into
+/// `x IN (SELECT ...)` and `x <> ALL (SELECT ...)` into `x NOT IN (SELECT ...)`, as the pinned
+/// sqlparser parses neither array constructors nor subqueries of ANY and ALL. ANY and ALL of
+/// arrays are parsed as functions, see `rewrite_array_comparisons`.
+fn rewrite_arrays(query: &str) -> String {
+    let lower = query.to_lowercase();
+    if !lower.contains("array") && !lower.contains("any") && !lower.contains("all") {
+        return query.to_string();
+    }
+
+    let chars = query.chars().collect::<Vec<_>>();
+    let mut result: Vec<char> = Vec::with_capacity(chars.len());
+    // Depths of brackets of array constructors, which are closed by `)`
+    let mut array_depths: Vec<usize> = vec![];
+    let mut depth = 0;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let end = quoted_end(&chars, i);
+        if end > i {
+            result.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        if is_word_part(chars[i]) {
+            let word_end = chars[i..]
+                .iter()
+                .position(|c| !is_word_part(*c))
+                .map(|p| i + p)
+                .unwrap_or_else(|| chars.len());
+            let word = chars[i..word_end].iter().collect::<String>().to_lowercase();
+            let next = (word_end..chars.len()).find(|j| !chars[*j].is_whitespace());
+
+            if word == "array" && next.map_or(false, |next| chars[next] == '[') {
+                depth += 1;
+                array_depths.push(depth);
+                result.extend("make_array(".chars());
+                i = next.unwrap_or(word_end) + 1;
+                continue;
+            }
+
+            let subquery = next
+                .filter(|next| chars[*next] == '(')
+                .filter(|next| next_word(&chars, next + 1, "select").is_some())
+                .filter(|_| ["any", "some", "all"].contains(&word.as_str()));
+            if let Some(paren) = subquery {
+                let operator_end = result
+                    .iter()
+                    .rposition(|c| !c.is_whitespace())
+                    .map_or(0, |p| p + 1);
+                let operator = &result[..operator_end];
+                let replacement = match word.as_str() {
+                    "all" if operator.ends_with(&['<', '>']) || operator.ends_with(&['!', '=']) => {
+                        Some((2, " NOT IN "))
+                    }
+                    "any" | "some"
+                        if operator.ends_with(&['='])
+                            && !operator.ends_with(&['<', '='])
+                            && !operator.ends_with(&['>', '='])
+                            && !operator.ends_with(&['!', '=']) =>
+                    {
+                        Some((1, " IN "))
+                    }
+                    _ => None,
+                };
+
+                if let Some((len, replacement)) = replacement {
+                    result.truncate(operator_end - len);
+                    while result.last().map_or(false, |c| c.is_whitespace()) {
+                        result.pop();
+                    }
+                    result.extend(replacement.chars());
+                    i = paren;
+                    continue;
+                }
+            }
+
+            result.extend(&chars[i..word_end]);
+            i = word_end;
+            continue;
+        }
+
+        match chars[i] {
+            '[' => depth += 1,
+            ']' => {
+                if array_depths.last() == Some(&depth) {
+                    array_depths.pop();
+                    depth -= 1;
+                    result.push(')');
+                    i += 1;
+                    continue;
+                }
+                depth = depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+        result.push(chars[i]);
+        i += 1;
+    }
+
+    result.into_iter().collect()
+}
+
+/// Position of the top level `ORDER BY` between `start` and `end` and the end of `BY`
+fn top_level_order_by(chars: &[char], start: usize, end: usize) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < end {
+        let quoted = quoted_end(chars, i);
+        if quoted > i {
+            i = quoted;
+            continue;
+        }
+
+        if is_word_part(chars[i]) {
+            let word_end = chars[i..]
+                .iter()
+                .position(|c| !is_word_part(*c))
+                .map(|p| i + p)
+                .unwrap_or_else(|| chars.len());
+            if depth == 0 && next_word(chars, i, "order") == Some(word_end) {
+                if let Some(by_end) = next_word(chars, word_end, "by") {
+                    return Some((i, by_end));
+                }
+            }
+            i = word_end;
+            continue;
+        }
+
+        match chars[i] {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// Expressions which are separated by top level commas
+fn split_top_level(chars: &[char]) -> Vec<String> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let end = quoted_end(chars, i);
+        if end > i {
+            i = end;
+            continue;
+        }
+
+        match chars[i] {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(chars[start..i].iter().collect());
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    parts.push(chars[start..].iter().collect());
+
+    parts
+}
+
+/// `text` without the trailing `word`, which follows a whitespace
+fn strip_last_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let text = text.trim_end();
+    let start = text.len().checked_sub(word.len())?;
+
+    if text.is_char_boundary(start)
+        && text[start..].eq_ignore_ascii_case(word)
+        && text[..start].ends_with(char::is_whitespace)
+    {
+        Some(text[..start].trim_end())
+    } else {
+        None
+    }
+}
+
+/// Arguments of `__order_by()` of `ORDER BY` keys: every key is followed by its direction with
+/// the position of NULLs, which are last for ASC and first for DESC by default
+fn order_by_arguments(chars: &[char]) -> String {
+    split_top_level(chars)
+        .iter()
+        .map(|key| {
+            let (key, nulls) = ["first", "last"]
+                .iter()
+                .find_map(|nulls| {
+                    strip_last_word(key, nulls)
+                        .and_then(|key| strip_last_word(key, "nulls"))
+                        .map(|key| (key, Some(*nulls)))
+                })
+                .unwrap_or((key, None));
+            let (key, desc) = match strip_last_word(key, "desc") {
+                Some(key) => (key, true),
+                None => (strip_last_word(key, "asc").unwrap_or(key), false),
+            };
+            let nulls = nulls.unwrap_or(if desc { "first" } else { "last" });
+
+            format!(
+                "{}, '{} nulls {}'",
+                rewrite_aggregate_order_by(key.trim()),
+                if desc { "desc" } else { "asc" },
+                nulls
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+const ORDERED_AGGREGATES: [&str; 5] = [
+    "string_agg",
+    "array_agg",
+    "listagg",
+    "percentile_cont",
+    "percentile_disc",
+];
+
+/// `string_agg(x, ', ' ORDER BY k DESC)` and `percentile_cont(0.5) WITHIN GROUP (ORDER BY k)`
+/// are rewritten into `string_agg(x, ', ', __order_by(k, 'desc nulls first'))` and
+/// `percentile_cont(0.5, __order_by(k, 'asc nulls last'))`, as the pinned sqlparser parses
+/// neither ORDER BY of aggregates nor WITHIN GROUP. See `rewrite_ordered_aggregates`.
+fn rewrite_aggregate_order_by(query: &str) -> String {
+    let lower = query.to_lowercase();
+    if !lower.contains("agg") && !lower.contains("percentile") {
+        return query.to_string();
+    }
+
+    let chars = query.chars().collect::<Vec<_>>();
+    let mut result = String::with_capacity(query.len());
+
+    let mut i = 0;
+    while i < chars.len() {
+        let end = quoted_end(&chars, i);
+        if end > i {
+            result.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        if is_word_part(chars[i]) {
+            let word_end = chars[i..]
+                .iter()
+                .position(|c| !is_word_part(*c))
+                .map(|p| i + p)
+                .unwrap_or_else(|| chars.len());
+            let word = chars[i..word_end].iter().collect::<String>();
+
+            let paren = (word_end..chars.len())
+                .find(|j| !chars[*j].is_whitespace())
+                .filter(|paren| chars[*paren] == '(')
+                .filter(|_| {
+                    ORDERED_AGGREGATES
+                        .iter()
+                        .any(|name| word.eq_ignore_ascii_case(name))
+                });
+            let close = paren.and_then(|paren| closing_paren(&chars, paren));
+
+            if let (Some(paren), Some(close)) = (paren, close) {
+                // Ends of arguments, the start and the end of keys and the end of the call
+                let order_by = top_level_order_by(&chars, paren + 1, close)
+                    .map(|(order, by_end)| (order, by_end, close, close + 1))
+                    .or_else(|| {
+                        let group_end = next_word(&chars, close + 1, "within")
+                            .and_then(|within_end| next_word(&chars, within_end, "group"))?;
+                        let open = (group_end..chars.len())
+                            .find(|j| !chars[*j].is_whitespace())
+                            .filter(|open| chars[*open] == '(')?;
+                        let group_close = closing_paren(&chars, open)?;
+                        let by_end = next_word(&chars, open + 1, "order")
+                            .and_then(|order_end| next_word(&chars, order_end, "by"))?;
+
+                        Some((close, by_end, group_close, group_close + 1))
+                    });
+
+                if let Some((args_end, keys_start, keys_end, call_end)) = order_by {
+                    let args = chars[paren + 1..args_end].iter().collect::<String>();
+                    result.push_str(&format!(
+                        "{}({}, {}({}))",
+                        word,
+                        rewrite_aggregate_order_by(args.trim_end()),
+                        AGGREGATE_ORDER_BY_FUNCTION,
+                        order_by_arguments(&chars[keys_start..keys_end])
+                    ));
+                    i = call_end;
+                    continue;
+                }
+            }
+
+            result.push_str(&word);
+            i = word_end;
+            continue;
+        }
+
+        result.push(chars[i]);
+        i += 1;
+    }
+
+    result
+}
+
+/// Field of `EXTRACT(field FROM ...)`, which is a word or a string, and the position after it
+fn extract_field(chars: &[char], i: usize) -> Option<(String, usize)> {
+    let start = (i..chars.len()).find(|j| !chars[*j].is_whitespace())?;
+    let end = if chars[start] == '\'' {
+        quoted_end(chars, start)
+    } else {
+        chars[start..]
+            .iter()
+            .position(|c| !is_word_part(*c))
+            .map(|p| start + p)
+            .unwrap_or_else(|| chars.len())
+    };
+
+    let field = chars[start..end]
+        .iter()
+        .filter(|c| **c != '\'')
+        .collect::<String>()
+        .to_lowercase();
+    if field.is_empty() {
+        None
+    } else {
+        Some((field, end))
+    }
+}
+
+/// `EXTRACT(field FROM x)` is rewritten into `date_part('field', x)`, as the pinned sqlparser
+/// parses only fields from YEAR to SECOND
+fn rewrite_extract(query: &str) -> String {
+    if !query.to_lowercase().contains("extract") {
+        return query.to_string();
+    }
+
+    let chars = query.chars().collect::<Vec<_>>();
+    let mut result = String::with_capacity(query.len());
+
+    let mut i = 0;
+    while i < chars.len() {
+        let end = quoted_end(&chars, i);
+        if end > i {
+            result.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        if is_word_part(chars[i]) {
+            let word_end = chars[i..]
+                .iter()
+                .position(|c| !is_word_part(*c))
+                .map(|p| i + p)
+                .unwrap_or_else(|| chars.len());
+            let word = chars[i..word_end].iter().collect::<String>();
+
+            if word.eq_ignore_ascii_case("extract") {
+                let field = (word_end..chars.len())
+                    .find(|j| !chars[*j].is_whitespace())
+                    .filter(|paren| chars[*paren] == '(')
+                    .and_then(|paren| extract_field(&chars, paren + 1))
+                    .and_then(|(field, end)| {
+                        next_word(&chars, end, "from").map(|from_end| (field, from_end))
+                    });
+
+                if let Some((field, from_end)) = field {
+                    result.push_str(&format!("date_part('{}',", field));
+                    i = from_end;
+                    continue;
+                }
+            }
+
+            result.push_str(&word);
+            i = word_end;
+            continue;
+        }
+
+        result.push(chars[i]);
+        i += 1;
+    }
+
+    result
+}
+
+/// Start of `FILTER` after an aggregate call, the `(` of its condition and the end of `WHERE`
+fn next_filter_clause(chars: &[char]) -> Option<(usize, usize, usize)> {
+    let mut i = 0;
+    while i < chars.len() {
+        let end = quoted_end(chars, i);
+        if end > i {
+            i = end;
+            continue;
+        }
+
+        if is_word_part(chars[i]) {
+            let word_end = chars[i..]
+                .iter()
+                .position(|c| !is_word_part(*c))
+                .map(|p| i + p)
+                .unwrap_or_else(|| chars.len());
+            let follows_call = (0..i)
+                .rev()
+                .find(|j| !chars[*j].is_whitespace())
+                .map_or(false, |j| chars[j] == ')');
+
+            if follows_call && next_word(chars, i, "filter") == Some(word_end) {
+                let clause = (word_end..chars.len())
+                    .find(|j| !chars[*j].is_whitespace())
+                    .filter(|open| chars[*open] == '(')
+                    .and_then(|open| Some((open, next_word(chars, open + 1, "where")?)));
+                if let Some((open, where_end)) = clause {
+                    return Some((i, open, where_end));
+                }
+            }
+
+            i = word_end;
+            continue;
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// `agg(x) FILTER (WHERE condition)` is rewritten into `__filter(agg(x), condition)`, as the
+/// pinned sqlparser doesn't parse FILTER. See `rewrite_aggregate_filters`.
+fn rewrite_aggregate_filters(query: &str) -> String {
+    if !query.to_lowercase().contains("filter") {
+        return query.to_string();
+    }
+
+    let mut chars = query.chars().collect::<Vec<_>>();
+    while let Some((filter, open, where_end)) = next_filter_clause(&chars) {
+        let starts = backward_starts(&chars);
+        let call_end = (0..filter)
+            .rev()
+            .find(|j| !chars[*j].is_whitespace())
+            .map_or(0, |j| j + 1);
+        let (start, close) = match (
+            operand_start(&chars, &starts, call_end),
+            closing_paren(&chars, open),
+        ) {
+            (Some(start), Some(close)) => (start, close),
+            _ => break,
+        };
+        let condition = chars[where_end..close].iter().collect::<String>();
+
+        let mut rewritten = chars[..start].to_vec();
+        rewritten.extend(format!("{}(", AGGREGATE_FILTER_FUNCTION).chars());
+        rewritten.extend(&chars[start..call_end]);
+        rewritten.extend(format!(", {})", condition.trim()).chars());
+        rewritten.extend(&chars[close + 1..]);
+        chars = rewritten;
+    }
+
+    chars.into_iter().collect()
+}
+
 pub fn parse_sql_to_statement(
     query: &String,
     protocol: DatabaseProtocol,
@@ -44,6 +921,15 @@ pub fn parse_sql_to_statement(
     let query = query.replace("SIGNED INTEGER", "bigint");
     let query = query.replace("unsigned integer", "bigint");
     let query = query.replace("UNSIGNED INTEGER", "bigint");
+    let query = match protocol {
+        DatabaseProtocol::MySQL => query,
+        DatabaseProtocol::PostgreSQL => {
+            let query = rewrite_distinct_on(&rewrite_grouping_sets(&query));
+            let query = rewrite_extract(&rewrite_aggregate_order_by(&query));
+            let query = rewrite_aggregate_filters(&query);
+            rewrite_arrays(&rewrite_json_operators(&query))
+        }
+    };
 
     let parse_result = match protocol {
         DatabaseProtocol::MySQL => Parser::parse_sql(&MySqlDialectWithBackTicks {}, query.as_str()),
@@ -77,6 +963,166 @@ pub fn parse_sql_to_statement(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rewrite_grouping_sets() {
+        assert_eq!(
+            rewrite_grouping_sets(
+                "SELECT a, b FROM t GROUP BY GROUPING SETS ((a, b), a, ( ), ROLLUP(b))"
+            ),
+            "SELECT a, b FROM t GROUP BY __grouping_sets(__grouping_set(a, b), a, __grouping_set( ), ROLLUP(b))"
+        );
+        assert_eq!(
+            rewrite_grouping_sets(
+                "SELECT 'grouping sets (a)', \"grouping\" FROM t -- grouping sets ((a))\nGROUP BY grouping  sets((\"a\"))"
+            ),
+            "SELECT 'grouping sets (a)', \"grouping\" FROM t -- grouping sets ((a))\nGROUP BY __grouping_sets(__grouping_set(\"a\"))"
+        );
+
+        let result = parse_sql_to_statement(
+            &"SELECT a, b, COUNT(*) FROM t GROUP BY GROUPING SETS ((a, b), ())".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        );
+        match result {
+            Ok(_) => {}
+            Err(err) => panic!("{}", err),
+        }
+
+        // MySQL has no GROUPING SETS, its queries are not rewritten
+        let result = parse_sql_to_statement(
+            &"SELECT a, b, COUNT(*) FROM t GROUP BY GROUPING SETS ((a, b), ())".to_string(),
+            DatabaseProtocol::MySQL,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_rewrite_distinct_on() {
+        assert_eq!(
+            rewrite_distinct_on("SELECT DISTINCT ON (a, lower(\"b\")) a, b FROM t ORDER BY a"),
+            "SELECT __distinct_on(a, lower(\"b\")), a, b FROM t ORDER BY a"
+        );
+        assert_eq!(
+            rewrite_distinct_on(
+                "SELECT DISTINCT a, 'distinct on (a)', COUNT(DISTINCT on_time) FROM t"
+            ),
+            "SELECT DISTINCT a, 'distinct on (a)', COUNT(DISTINCT on_time) FROM t"
+        );
+
+        // DISTINCT ON is PostgreSQL syntax, MySQL queries are not rewritten
+        let query = "SELECT DISTINCT ON (a) a, b FROM t".to_string();
+        assert!(parse_sql_to_statement(&query, DatabaseProtocol::PostgreSQL).is_ok());
+        assert!(parse_sql_to_statement(&query, DatabaseProtocol::MySQL).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_json_operators() {
+        assert_eq!(
+            rewrite_json_operators("SELECT data -> 'a' ->> 0, t.\"data\"->'b'->-1 FROM t"),
+            "SELECT json_extract_path_text(json_extract_path(data, 'a'), '0'), \
+            json_extract_path(json_extract_path(t.\"data\", 'b'), '-1') FROM t"
+        );
+        assert_eq!(
+            rewrite_json_operators(
+                "SELECT '{\"a\": 1}'::jsonb ->> 'a', lower(d::json ->> $1), 'a -> b' FROM t"
+            ),
+            "SELECT json_extract_path_text('{\"a\": 1}'::jsonb, 'a'), \
+            lower(json_extract_path_text(d::json, $1)), 'a -> b' FROM t"
+        );
+        assert_eq!(
+            rewrite_json_operators("SELECT (data -> 'a') ->> ('b') FROM t"),
+            "SELECT json_extract_path_text((json_extract_path(data, 'a')), ('b')) FROM t"
+        );
+    }
+
+    #[test]
+    fn test_rewrites_keep_escape_and_dollar_quoted_strings() {
+        let queries = vec![
+            r#"SELECT E'it\'s -> ''a'', ARRAY[1], EXTRACT(epoch FROM t), count(*) FILTER (WHERE x)' FROM t"#,
+            "SELECT $$d -> 'a', ARRAY[1], EXTRACT(epoch FROM t), count(*) FILTER (WHERE x)$$ FROM t",
+            "SELECT $tag$ $$ -> ARRAY[1] $tag$, $1, e'\\\\' FROM t",
+        ];
+        for query in queries.iter() {
+            assert_eq!(rewrite_json_operators(query), *query);
+            assert_eq!(rewrite_arrays(query), *query);
+            assert_eq!(rewrite_extract(query), *query);
+            assert_eq!(rewrite_aggregate_filters(query), *query);
+            assert_eq!(rewrite_aggregate_order_by(query), *query);
+        }
+
+        assert_eq!(
+            rewrite_json_operators(
+                r#"SELECT E'{"a": "\'"}' ->> 'a', $j$[1]$j$::json -> $1 FROM t"#
+            ),
+            r#"SELECT json_extract_path_text(E'{"a": "\'"}', 'a'), json_extract_path($j$[1]$j$::json, $1) FROM t"#
+        );
+    }
+
+    #[test]
+    fn test_rewrite_arrays() {
+        assert_eq!(
+            rewrite_arrays("SELECT ARRAY[1, 2], array [ARRAY['a]'], ARRAY[]] FROM t"),
+            "SELECT make_array(1, 2), make_array(make_array('a]'), make_array()) FROM t"
+        );
+        assert_eq!(
+            rewrite_arrays(
+                "SELECT * FROM t WHERE a = ANY (SELECT b FROM u) AND c <> all(select d FROM u) \
+                AND e >= ANY($1) AND f = ANY($2)"
+            ),
+            "SELECT * FROM t WHERE a IN (SELECT b FROM u) AND c NOT IN (select d FROM u) \
+            AND e >= ANY($1) AND f = ANY($2)"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_aggregate_order_by() {
+        assert_eq!(
+            rewrite_aggregate_order_by(
+                "SELECT string_agg(name, ', ' ORDER BY name DESC, id NULLS FIRST), \
+                array_agg(DISTINCT (a) order by (a) asc nulls last), \
+                LISTAGG(name, ',') WITHIN GROUP (ORDER BY f(x, y)), string_agg(name, ', ') FROM t"
+            ),
+            "SELECT string_agg(name, ', ', __order_by(name, 'desc nulls first', id, 'asc nulls first')), \
+            array_agg(DISTINCT (a), __order_by((a), 'asc nulls last')), \
+            LISTAGG(name, ',', __order_by(f(x, y), 'asc nulls last')), string_agg(name, ', ') FROM t"
+        );
+        assert_eq!(
+            rewrite_aggregate_order_by(
+                "SELECT PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY x DESC) FROM t"
+            ),
+            "SELECT PERCENTILE_CONT(0.5, __order_by(x, 'desc nulls first')) FROM t"
+        );
+        assert_eq!(
+            rewrite_aggregate_order_by("SELECT array_agg(x) FROM (SELECT x FROM t ORDER BY x) s"),
+            "SELECT array_agg(x) FROM (SELECT x FROM t ORDER BY x) s"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_extract() {
+        assert_eq!(
+            rewrite_extract(
+                "SELECT EXTRACT(EPOCH FROM ts), extract ( 'dow' from d ), 'extract(year from x)' FROM t"
+            ),
+            "SELECT date_part('epoch', ts), date_part('dow', d ), 'extract(year from x)' FROM t"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_aggregate_filters() {
+        assert_eq!(
+            rewrite_aggregate_filters(
+                "SELECT COUNT(*) FILTER (WHERE status = 'paid'), \
+                sum(t.x) filter ( where (a) > 1 ) AS s, 'count(*) filter (where x)' FROM t"
+            ),
+            "SELECT __filter(COUNT(*), status = 'paid'), \
+            __filter(sum(t.x), (a) > 1) AS s, 'count(*) filter (where x)' FROM t"
+        );
+        assert_eq!(
+            rewrite_aggregate_filters("SELECT filter FROM t WHERE filter = 'x'"),
+            "SELECT filter FROM t WHERE filter = 'x'"
+        );
+    }
+
     #[test]
     fn test_no_statements_mysql() {
         let result = parse_sql_to_statement(
@@ -265,7 +265,11 @@ impl LogicalPlanAnalysis {
                     .unwrap()
                     .to_string();
 
-                if &fun == "str_to_date" || &fun == "date_add" || &fun == "date" {
+                if &fun == "str_to_date"
+                    || &fun == "date_add"
+                    || &fun == "date_sub"
+                    || &fun == "date"
+                {
                     let args = match constant(params[1])? {
                         ConstantData::Intermediate(vec) => Some(vec),
                         _ => None,
@@ -333,7 +337,8 @@ impl LogicalPlanAnalysis {
                     .next()
                     .unwrap();
 
-                if fun.volatility() == Volatility::Immutable {
+                // Stable functions such as `now()` are constant during the query
+                if fun.volatility() != Volatility::Volatile {
                     let args = match constant(params[1])? {
                         ConstantData::Intermediate(vec) => Some(vec),
                         _ => None,
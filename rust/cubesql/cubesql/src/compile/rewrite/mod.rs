@@ -223,6 +223,7 @@ crate::plan_to_language! {
         },
         FilterReplacer {
             filters: Vec<LogicalPlan>,
+            column_name_to_member: Vec<(String, String)>,
             cube: Option<String>,
         },
         OrderReplacer {
@@ -507,8 +508,8 @@ fn order_replacer(members: impl Display, aliases: impl Display, cube: impl Displ
     format!("(OrderReplacer {} {} {})", members, aliases, cube)
 }
 
-fn filter_replacer(members: impl Display, cube: impl Display) -> String {
-    format!("(FilterReplacer {} {})", members, cube)
+fn filter_replacer(members: impl Display, aliases: impl Display, cube: impl Display) -> String {
+    format!("(FilterReplacer {} {} {})", members, aliases, cube)
 }
 
 fn cube_scan_members(left: impl Display, right: impl Display) -> String {
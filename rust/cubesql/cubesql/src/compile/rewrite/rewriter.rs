@@ -8,11 +8,13 @@ use crate::compile::rewrite::rules::members::MemberRules;
 use crate::compile::rewrite::rules::order::OrderRules;
 use crate::compile::rewrite::LogicalPlanLanguage;
 use crate::sql::AuthContext;
+use crate::telemetry::metrics::METRICS;
 use crate::CubeError;
 use datafusion::logical_plan::LogicalPlan;
 use datafusion::physical_plan::planner::DefaultPhysicalPlanner;
 use egg::{EGraph, Extractor, Id, Rewrite, Runner};
 use std::sync::Arc;
+use std::time::Instant;
 
 pub struct Rewriter {
     graph: EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>,
@@ -45,9 +47,14 @@ impl Rewriter {
         root: Id,
         auth_context: Arc<AuthContext>,
     ) -> Result<LogicalPlan, CubeError> {
+        let start = Instant::now();
         let runner = self.rewrite_runner();
         let rules = self.rewrite_rules();
         let runner = runner.run(rules.iter());
+        METRICS.rewrite_duration.observe(
+            self.cube_context.session_state.protocol.as_str(),
+            start.elapsed(),
+        );
         log::debug!("Iterations: {:?}", runner.iterations);
         let extractor = Extractor::new(&runner.egraph, BestCubePlan);
         let (_, best) = extractor.find_best(root);
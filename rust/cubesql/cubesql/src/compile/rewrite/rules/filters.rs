@@ -1,8 +1,10 @@
 use crate::compile::engine::provider::CubeContext;
 use crate::compile::rewrite::analysis::{ConstantData, LogicalPlanAnalysis};
 use crate::compile::rewrite::rewriter::RewriteRules;
+use crate::compile::rewrite::FilterMemberMember;
 use crate::compile::rewrite::FilterMemberOp;
 use crate::compile::rewrite::FilterMemberValues;
+use crate::compile::rewrite::FilterReplacerColumnNameToMember;
 use crate::compile::rewrite::FilterReplacerCube;
 use crate::compile::rewrite::InListExprNegated;
 use crate::compile::rewrite::LiteralExprValue;
@@ -13,7 +15,7 @@ use crate::compile::rewrite::TimeDimensionDateRangeReplacerDateRange;
 use crate::compile::rewrite::TimeDimensionDateRangeReplacerMember;
 use crate::compile::rewrite::TimeDimensionGranularity;
 use crate::compile::rewrite::TimeDimensionName;
-use crate::compile::rewrite::{between_expr, FilterMemberMember};
+use crate::compile::rewrite::{between_expr, column_name_to_member_name, expr_column_name};
 use crate::compile::rewrite::{
     binary_expr, column_expr, cube_scan, cube_scan_filters, filter, filter_member, filter_op,
     filter_op_filters, filter_replacer, literal_expr, rewrite, transforming_rewrite,
@@ -24,12 +26,13 @@ use crate::compile::rewrite::{
 };
 use crate::compile::rewrite::{inlist_expr, BinaryExprOp};
 use crate::compile::rewrite::{is_not_null_expr, is_null_expr, ColumnExprColumn};
+use crate::sql::regexp::regexp_filter;
 use crate::transport::ext::V1CubeMetaExt;
 use crate::transport::MemberType;
 use crate::var;
 use crate::var_iter;
 use chrono::{SecondsFormat, TimeZone, Utc};
-use datafusion::logical_plan::{Column, Operator};
+use datafusion::logical_plan::{Column, Expr, Operator};
 use datafusion::scalar::ScalarValue;
 use egg::{EGraph, Rewrite, Subst};
 use std::fmt::Display;
@@ -59,17 +62,24 @@ impl RewriteRules for FilterRules {
                 cube_scan(
                     "?source_table_name",
                     "?members",
-                    cube_scan_filters("?filters", filter_replacer("?expr", "?cube")),
+                    cube_scan_filters("?filters", filter_replacer("?expr", "?aliases", "?cube")),
                     "?order",
                     "?limit",
                     "?offset",
                 ),
-                self.push_down_filter("?source_table_name", "?expr", "?cube"),
+                self.push_down_filter(
+                    "?source_table_name",
+                    "?expr",
+                    "?members",
+                    "?aliases",
+                    "?cube",
+                ),
             ),
             transforming_rewrite(
                 "filter-replacer",
                 filter_replacer(
                     binary_expr(column_expr("?column"), "?op", literal_expr("?literal")),
+                    "?aliases",
                     "?cube",
                 ),
                 filter_member("?filter_member", "?filter_op", "?filter_values"),
@@ -77,6 +87,7 @@ impl RewriteRules for FilterRules {
                     "?column",
                     "?op",
                     "?literal",
+                    "?aliases",
                     "?cube",
                     "?filter_member",
                     "?filter_op",
@@ -87,6 +98,7 @@ impl RewriteRules for FilterRules {
                 "filter-replacer-in-filter",
                 filter_replacer(
                     inlist_expr(column_expr("?column"), "?list", "?negated"),
+                    "?aliases",
                     "?cube",
                 ),
                 filter_member("?filter_member", "?filter_op", "?filter_values"),
@@ -102,7 +114,7 @@ impl RewriteRules for FilterRules {
             ),
             transforming_rewrite(
                 "filter-replacer-is-null",
-                filter_replacer(is_null_expr(column_expr("?column")), "?cube"),
+                filter_replacer(is_null_expr(column_expr("?column")), "?aliases", "?cube"),
                 filter_member("?filter_member", "?filter_op", "?filter_values"),
                 self.transform_is_null(
                     "?column",
@@ -115,7 +127,11 @@ impl RewriteRules for FilterRules {
             ),
             transforming_rewrite(
                 "filter-replacer-is-not-null",
-                filter_replacer(is_not_null_expr(column_expr("?column")), "?cube"),
+                filter_replacer(
+                    is_not_null_expr(column_expr("?column")),
+                    "?aliases",
+                    "?cube",
+                ),
                 filter_member("?filter_member", "?filter_op", "?filter_values"),
                 self.transform_is_null(
                     "?column",
@@ -130,6 +146,7 @@ impl RewriteRules for FilterRules {
                 "filter-replacer-between",
                 filter_replacer(
                     between_expr(column_expr("?column"), "?negated", "?low", "?high"),
+                    "?aliases",
                     "?cube",
                 ),
                 filter_member("?filter_member", "?filter_op", "?filter_values"),
@@ -146,22 +163,22 @@ impl RewriteRules for FilterRules {
             ),
             rewrite(
                 "filter-replacer-and",
-                filter_replacer(binary_expr("?left", "AND", "?right"), "?cube"),
+                filter_replacer(binary_expr("?left", "AND", "?right"), "?aliases", "?cube"),
                 filter_op(
                     filter_op_filters(
-                        filter_replacer("?left", "?cube"),
-                        filter_replacer("?right", "?cube"),
+                        filter_replacer("?left", "?aliases", "?cube"),
+                        filter_replacer("?right", "?aliases", "?cube"),
                     ),
                     "and",
                 ),
             ),
             rewrite(
                 "filter-replacer-or",
-                filter_replacer(binary_expr("?left", "OR", "?right"), "?cube"),
+                filter_replacer(binary_expr("?left", "OR", "?right"), "?aliases", "?cube"),
                 filter_op(
                     filter_op_filters(
-                        filter_replacer("?left", "?cube"),
-                        filter_replacer("?right", "?cube"),
+                        filter_replacer("?left", "?aliases", "?cube"),
+                        filter_replacer("?right", "?aliases", "?cube"),
                     ),
                     "or",
                 ),
@@ -410,15 +427,36 @@ impl FilterRules {
         &self,
         table_name_var: &'static str,
         exp_var: &'static str,
+        members_var: &'static str,
+        aliases_var: &'static str,
         cube_var: &'static str,
     ) -> impl Fn(&mut EGraph<LogicalPlanLanguage, LogicalPlanAnalysis>, &mut Subst) -> bool {
         let table_name_var = var!(table_name_var);
         let exp_var = var!(exp_var);
+        let members_var = var!(members_var);
+        let aliases_var = var!(aliases_var);
         let cube_var = var!(cube_var);
         move |egraph, subst| {
             for table_name in var_iter!(egraph[subst[table_name_var]], TableScanSourceTableName) {
                 if let Some(_referenced_expr) = &egraph.index(subst[exp_var]).data.referenced_expr {
                     let table_name = table_name.to_string();
+                    // Filters over aggregates (HAVING) reference members by their aliases
+                    let member_name_to_expr = egraph
+                        .index(subst[members_var])
+                        .data
+                        .member_name_to_expr
+                        .clone()
+                        .unwrap_or_default();
+                    let column_name_to_member_name =
+                        column_name_to_member_name(member_name_to_expr, table_name.to_string());
+                    subst.insert(
+                        aliases_var,
+                        egraph.add(LogicalPlanLanguage::FilterReplacerColumnNameToMember(
+                            FilterReplacerColumnNameToMember(
+                                column_name_to_member_name.into_iter().collect(),
+                            ),
+                        )),
+                    );
                     // TODO check referenced_expr
                     subst.insert(
                         cube_var,
@@ -438,6 +476,7 @@ impl FilterRules {
         column_var: &'static str,
         op_var: &'static str,
         literal_var: &'static str,
+        column_name_to_member_var: &'static str,
         cube_var: &'static str,
         filter_member_var: &'static str,
         filter_op_var: &'static str,
@@ -446,22 +485,57 @@ impl FilterRules {
         let column_var = column_var.parse().unwrap();
         let op_var = op_var.parse().unwrap();
         let literal_var = literal_var.parse().unwrap();
+        let column_name_to_member_var = column_name_to_member_var.parse().unwrap();
         let cube_var = cube_var.parse().unwrap();
         let filter_member_var = filter_member_var.parse().unwrap();
         let filter_op_var = filter_op_var.parse().unwrap();
         let filter_values_var = filter_values_var.parse().unwrap();
         let meta_context = self.cube_context.meta.clone();
         move |egraph, subst| {
-            for cube in var_iter!(egraph[subst[cube_var]], FilterReplacerCube) {
+            for table_name in var_iter!(egraph[subst[cube_var]], FilterReplacerCube) {
                 for expr_op in var_iter!(egraph[subst[op_var]], BinaryExprOp) {
                     for literal in var_iter!(egraph[subst[literal_var]], LiteralExprValue) {
-                        if let Some(cube) = cube
+                        if let Some(cube) = table_name
                             .as_ref()
                             .and_then(|cube| meta_context.find_cube_with_name(cube.to_string()))
                         {
                             for column in var_iter!(egraph[subst[column_var]], ColumnExprColumn) {
-                                let member_name = format!("{}.{}", cube.name, column.name);
+                                // Aggregates of HAVING are columns of the aggregation, which are
+                                // mapped to their measures
+                                let column_name =
+                                    expr_column_name(Expr::Column(column.clone()), table_name);
+                                let member_name = var_iter!(
+                                    egraph[subst[column_name_to_member_var]],
+                                    FilterReplacerColumnNameToMember
+                                )
+                                .flat_map(|column_name_to_member| column_name_to_member.iter())
+                                .find(|(c, _)| c == &column_name)
+                                .map(|(_, member)| member.to_string())
+                                .unwrap_or_else(|| format!("{}.{}", cube.name, column.name));
                                 if let Some(member_type) = cube.member_type(&member_name) {
+                                    // Simple patterns of regular expressions are string filters
+                                    let regexp = match (expr_op, &member_type, literal) {
+                                        (
+                                            Operator::RegexMatch
+                                            | Operator::RegexIMatch
+                                            | Operator::RegexNotMatch
+                                            | Operator::RegexNotIMatch,
+                                            MemberType::String,
+                                            ScalarValue::Utf8(Some(pattern)),
+                                        ) => regexp_filter(
+                                            pattern,
+                                            matches!(
+                                                expr_op,
+                                                Operator::RegexIMatch | Operator::RegexNotIMatch
+                                            ),
+                                            matches!(
+                                                expr_op,
+                                                Operator::RegexNotMatch | Operator::RegexNotIMatch
+                                            ),
+                                        ),
+                                        _ => None,
+                                    };
+
                                     let op = match expr_op {
                                         Operator::Eq => "equals",
                                         Operator::NotEq => "notEquals",
@@ -471,6 +545,15 @@ impl FilterRules {
                                         Operator::GtEq => "gte",
                                         Operator::Like => "contains",
                                         Operator::NotLike => "notContains",
+                                        Operator::RegexMatch
+                                        | Operator::RegexIMatch
+                                        | Operator::RegexNotMatch
+                                        | Operator::RegexNotIMatch => match &regexp {
+                                            Some((op, _)) => *op,
+                                            None => {
+                                                continue;
+                                            }
+                                        },
                                         _ => {
                                             continue;
                                         }
@@ -514,6 +597,10 @@ impl FilterRules {
                                         }
                                         x => panic!("Unsupported filter scalar: {:?}", x),
                                     };
+                                    let value = match regexp {
+                                        Some((_, literal)) => literal,
+                                        None => value,
+                                    };
 
                                     subst.insert(
                                         filter_member_var,
@@ -0,0 +1,189 @@
+use datafusion::{
+    arrow::datatypes::DataType,
+    logical_plan::{Column, Expr, LogicalPlan, LogicalPlanBuilder},
+};
+use sqlparser::ast;
+
+use super::{CompilationError, CompilationResult};
+
+fn is_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+    )
+}
+
+fn is_numeric(data_type: &DataType) -> bool {
+    is_integer(data_type)
+        || matches!(
+            data_type,
+            DataType::Float32 | DataType::Float64 | DataType::Decimal(_, _)
+        )
+}
+
+/// Type of a column of UNION as it's resolved by PostgreSQL: NULL takes the type of the other
+/// branch, numbers are promoted, other types must match
+pub fn union_type(left: &DataType, right: &DataType) -> CompilationResult<DataType> {
+    match (left, right) {
+        (left, right) if left == right => Ok(left.clone()),
+        (DataType::Null, other) | (other, DataType::Null) => Ok(other.clone()),
+        (left, right) if is_integer(left) && is_integer(right) => Ok(DataType::Int64),
+        (left, right) if is_numeric(left) && is_numeric(right) => Ok(DataType::Float64),
+        (left, right) => Err(CompilationError::User(format!(
+            "UNION types {:?} and {:?} cannot be matched",
+            left, right
+        ))),
+    }
+}
+
+/// Concatenates results of branches, UNION without ALL removes duplicates. Columns are named
+/// by the left branch and cast to types of `union_type`.
+pub fn merge_union(
+    left: LogicalPlan,
+    right: LogicalPlan,
+    all: bool,
+) -> CompilationResult<LogicalPlan> {
+    let left_fields = left.schema().fields().clone();
+    let right_fields = right.schema().fields().clone();
+    if left_fields.len() != right_fields.len() {
+        return Err(CompilationError::User(
+            "each UNION query must have the same number of columns".to_string(),
+        ));
+    }
+
+    let types = left_fields
+        .iter()
+        .zip(right_fields.iter())
+        .map(|(left, right)| union_type(left.data_type(), right.data_type()))
+        .collect::<CompilationResult<Vec<_>>>()?;
+
+    let project = |plan: LogicalPlan| -> CompilationResult<LogicalPlan> {
+        let projection = plan
+            .schema()
+            .fields()
+            .iter()
+            .zip(left_fields.iter().zip(types.iter()))
+            .map(|(field, (name, data_type))| {
+                let column = Expr::Column(field.qualified_column());
+                let expr = if field.data_type() == data_type {
+                    column
+                } else {
+                    Expr::Cast {
+                        expr: Box::new(column),
+                        data_type: data_type.clone(),
+                    }
+                };
+
+                Expr::Alias(Box::new(expr), name.name().clone())
+            })
+            .collect::<Vec<_>>();
+
+        Ok(LogicalPlanBuilder::from(plan)
+            .project(projection)?
+            .build()?)
+    };
+
+    let builder = LogicalPlanBuilder::from(project(left)?).union(project(right)?)?;
+    let builder = if all { builder } else { builder.distinct()? };
+
+    Ok(builder.build()?)
+}
+
+/// ORDER BY and LIMIT of the query are applied to the merged result, only columns of the result
+/// can be referenced
+pub fn order_and_limit(plan: LogicalPlan, query: &ast::Query) -> CompilationResult<LogicalPlan> {
+    let names = plan
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect::<Vec<_>>();
+    let mut builder = LogicalPlanBuilder::from(plan);
+
+    if !query.order_by.is_empty() {
+        let mut sort = vec![];
+        for order_by in query.order_by.iter() {
+            let name = match &order_by.expr {
+                ast::Expr::Value(ast::Value::Number(position, _)) => position
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|position| names.get(position.wrapping_sub(1))),
+                ast::Expr::Identifier(ident) => names
+                    .iter()
+                    .find(|name| name.eq_ignore_ascii_case(&ident.value)),
+                _ => None,
+            };
+            let name = name.ok_or_else(|| {
+                CompilationError::Unsupported(format!(
+                    "ORDER BY {} with UNION, only columns of the result can be used",
+                    order_by.expr
+                ))
+            })?;
+
+            let asc = order_by.asc.unwrap_or(true);
+            sort.push(Expr::Sort {
+                expr: Box::new(Expr::Column(Column {
+                    relation: None,
+                    name: name.clone(),
+                })),
+                asc,
+                nulls_first: order_by.nulls_first.unwrap_or(!asc),
+            });
+        }
+        builder = builder.sort(sort)?;
+    }
+
+    if query.offset.is_some() {
+        return Err(CompilationError::Unsupported(
+            "Query with OFFSET and UNION".to_string(),
+        ));
+    }
+
+    if let Some(limit) = &query.limit {
+        let limit = limit
+            .to_string()
+            .parse::<usize>()
+            .map_err(|e| CompilationError::Unsupported(format!("Unable to parse limit: {}", e)))?;
+        builder = builder.limit(limit)?;
+    }
+
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union_type() {
+        assert_eq!(
+            union_type(&DataType::Utf8, &DataType::Utf8).unwrap(),
+            DataType::Utf8
+        );
+        assert_eq!(
+            union_type(&DataType::Null, &DataType::Utf8).unwrap(),
+            DataType::Utf8
+        );
+        assert_eq!(
+            union_type(&DataType::Int32, &DataType::Int64).unwrap(),
+            DataType::Int64
+        );
+        assert_eq!(
+            union_type(&DataType::Int64, &DataType::Float64).unwrap(),
+            DataType::Float64
+        );
+        assert_eq!(
+            union_type(&DataType::Utf8, &DataType::Int64),
+            Err(CompilationError::User(
+                "UNION types Utf8 and Int64 cannot be matched".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use datafusion::physical_plan::collect;
+use futures::future::{BoxFuture, FutureExt};
+use sqlparser::ast;
+
+use crate::{
+    sql::{
+        dataframe::{batch_to_dataframe, TableValue, TimestampValue},
+        interval::format_interval,
+        postgres::describe::statement_schema,
+        statement::{
+            expression_subqueries, referenced_columns, referenced_tables,
+            replace_expression_subqueries, BindValue, SubqueryKind,
+        },
+        Session,
+    },
+    CubeError,
+};
+
+use super::{convert_statement_to_cube_query, MetaContext, QueryPlan};
+
+/// Results of IN subqueries become filters of Cube queries, larger lists are not supported
+pub const MAX_SUBQUERY_VALUES: usize = 10000;
+
+/// Subqueries of expressions (`x IN (SELECT ...)`, `EXISTS (SELECT ...)` and `(SELECT ...)`)
+/// are executed before planning and replaced by their results. Correlated subqueries can't be
+/// planned on their own, they are kept. Returns None when the statement has no subqueries.
+pub fn evaluate_subqueries<'a>(
+    stmt: &'a ast::Statement,
+    meta: Arc<MetaContext>,
+    session: Arc<Session>,
+) -> BoxFuture<'a, Result<Option<ast::Statement>, CubeError>> {
+    async move {
+        let subqueries = expression_subqueries(stmt)?;
+        if subqueries.is_empty() {
+            return Ok(None);
+        }
+
+        let mut results = Vec::with_capacity(subqueries.len());
+        for (kind, query) in subqueries.into_iter() {
+            results.push(evaluate_subquery(kind, query, meta.clone(), session.clone()).await?);
+        }
+
+        let mut stmt = stmt.clone();
+        replace_expression_subqueries(&mut stmt, results)?;
+
+        Ok(Some(stmt))
+    }
+    .boxed()
+}
+
+/// A subquery can be correlated if it references a qualifier or a column, which it doesn't
+/// define: columns are known only for cubes, so every unqualified column of a subquery over
+/// system tables (`SELECT 1 FROM pg_namespace WHERE oid = relnamespace`) is such a reference
+fn is_correlated(subquery: &ast::Statement, meta: &MetaContext) -> Result<bool, CubeError> {
+    let tables = referenced_tables(subquery)?;
+    let schema = statement_schema(subquery, meta)?;
+
+    for column in referenced_columns(subquery)? {
+        let resolved = match &column.qualifier {
+            Some(qualifier) => tables.iter().any(|table| &table.qualifier == qualifier),
+            None => schema
+                .fields()
+                .iter()
+                .any(|field| field.name() == &column.name),
+        };
+        if !resolved {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+async fn evaluate_subquery(
+    kind: SubqueryKind,
+    query: ast::Query,
+    meta: Arc<MetaContext>,
+    session: Arc<Session>,
+) -> Result<Option<Vec<ast::Expr>>, CubeError> {
+    let stmt = ast::Statement::Query(Box::new(query));
+    // Subqueries of the subquery are evaluated first
+    let evaluated = evaluate_subqueries(&stmt, meta.clone(), session.clone()).await?;
+    let stmt = evaluated.unwrap_or(stmt);
+
+    let plan = match convert_statement_to_cube_query(&stmt, meta.clone(), session) {
+        Ok(plan) => plan,
+        // Correlated subqueries are planned by DataFusion with the outer query
+        Err(_) if is_correlated(&stmt, &meta)? => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let frame = match plan {
+        QueryPlan::DataFusionSelect(_, plan, ctx) => {
+            let plan = ctx.optimize(&plan)?;
+            let plan = ctx.create_physical_plan(&plan).await?;
+
+            Arc::new(batch_to_dataframe(&collect(plan).await?)?)
+        }
+        QueryPlan::MetaTabular(_, frame) => frame,
+        QueryPlan::MetaOk(_) => return Ok(None),
+    };
+
+    if kind != SubqueryKind::Exists && frame.get_columns().len() > 1 {
+        return Err(CubeError::user("subquery has too many columns".to_string()));
+    }
+    if kind == SubqueryKind::In && frame.len() > MAX_SUBQUERY_VALUES {
+        return Err(CubeError::user(format!(
+            "Subquery of IN returned {} rows, at most {} values are supported",
+            frame.len(),
+            MAX_SUBQUERY_VALUES
+        )));
+    }
+
+    let values = frame
+        .get_rows()
+        .iter()
+        .map(|row| match row.values().first() {
+            Some(value) => value_to_ast_expr(value),
+            None => Ok(ast::Expr::Value(ast::Value::Null)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Some(values))
+}
+
+fn naive_timestamp(value: &TimestampValue) -> NaiveDateTime {
+    let nanos = value.get_time_stamp();
+    NaiveDateTime::from_timestamp(
+        nanos.div_euclid(1_000_000_000),
+        nanos.rem_euclid(1_000_000_000) as u32,
+    )
+}
+
+fn value_to_bind_value(value: &TableValue) -> BindValue {
+    match value {
+        TableValue::Null => BindValue::Null,
+        TableValue::String(v) => BindValue::String(v.clone()),
+        TableValue::Int64(v) => BindValue::Int64(*v),
+        TableValue::Boolean(v) => BindValue::Bool(*v),
+        TableValue::Float64(v) => BindValue::Float64(*v),
+        TableValue::Decimal(v) => BindValue::Numeric(v.to_string()),
+        TableValue::Timestamp(v) => BindValue::Timestamp(naive_timestamp(v)),
+        TableValue::Timestamptz(v) => {
+            BindValue::TimestampTz(DateTime::from_utc(naive_timestamp(v), Utc))
+        }
+        TableValue::Interval(v) => BindValue::Interval(format_interval(v)),
+        TableValue::List(values) => {
+            BindValue::Array(values.iter().map(value_to_bind_value).collect())
+        }
+    }
+}
+
+fn value_to_ast_expr(value: &TableValue) -> Result<ast::Expr, CubeError> {
+    value_to_bind_value(value).to_ast_expr()
+}
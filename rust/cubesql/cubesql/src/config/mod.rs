@@ -4,17 +4,25 @@ pub mod processing_loop;
 use crate::config::injection::{DIService, Injector};
 use crate::config::processing_loop::ProcessingLoop;
 use crate::sql::{
-    MySqlServer, PostgresServer, ServerManager, SessionManager, SqlAuthDefaultImpl, SqlAuthService,
+    server_manager::ServerConfiguration, MySqlServer, PostgresServer, ServerManager,
+    SessionManager, SqlAuthDefaultImpl, SqlAuthLdapImpl, SqlAuthService,
+};
+use crate::telemetry::{
+    metrics_server::MetricsServer, start_track_event_loop, stop_track_event_loop,
+    query_log::QueryLogWriter,
+    tracing::SpanExporter,
 };
-use crate::telemetry::{start_track_event_loop, stop_track_event_loop};
 use crate::transport::{HttpTransport, TransportService};
 use crate::CubeError;
 use futures::future::join_all;
 use log::error;
 
 use mockall::automock;
+use serde_derive::Deserialize;
 
+use std::collections::HashMap;
 use std::env;
+use std::str::FromStr;
 
 use std::sync::Arc;
 
@@ -74,6 +82,39 @@ impl CubeServices {
             }));
         }
 
+        if self.injector.has_service_typed::<MetricsServer>().await {
+            let metrics_server = self.injector.get_service_typed::<MetricsServer>().await;
+            futures.push(tokio::spawn(async move {
+                if let Err(e) = metrics_server.processing_loop().await {
+                    error!("{}", e.to_string());
+                };
+
+                Ok(())
+            }));
+        }
+
+        if self.injector.has_service_typed::<QueryLogWriter>().await {
+            let query_log_writer = self.injector.get_service_typed::<QueryLogWriter>().await;
+            futures.push(tokio::spawn(async move {
+                if let Err(e) = query_log_writer.processing_loop().await {
+                    error!("{}", e.to_string());
+                };
+
+                Ok(())
+            }));
+        }
+
+        if self.injector.has_service_typed::<SpanExporter>().await {
+            let span_exporter = self.injector.get_service_typed::<SpanExporter>().await;
+            futures.push(tokio::spawn(async move {
+                if let Err(e) = span_exporter.processing_loop().await {
+                    error!("{}", e.to_string());
+                };
+
+                Ok(())
+            }));
+        }
+
         futures.push(tokio::spawn(async move {
             start_track_event_loop().await;
             Ok(())
@@ -99,6 +140,30 @@ impl CubeServices {
                 .await?;
         }
 
+        if self.injector.has_service_typed::<MetricsServer>().await {
+            self.injector
+                .get_service_typed::<MetricsServer>()
+                .await
+                .stop_processing()
+                .await?;
+        }
+
+        if self.injector.has_service_typed::<QueryLogWriter>().await {
+            self.injector
+                .get_service_typed::<QueryLogWriter>()
+                .await
+                .stop_processing()
+                .await?;
+        }
+
+        if self.injector.has_service_typed::<SpanExporter>().await {
+            self.injector
+                .get_service_typed::<SpanExporter>()
+                .await
+                .stop_processing()
+                .await?;
+        }
+
         stop_track_event_loop().await;
         Ok(())
     }
@@ -116,15 +181,147 @@ pub trait ConfigObj: DIService {
 
     fn postgres_bind_address(&self) -> &Option<String>;
 
+    fn postgres_tls(&self) -> &Option<PostgresTlsConfig>;
+
+    fn postgres_unix_socket(&self) -> &Option<PostgresUnixSocketConfig>;
+
+    fn postgres_auth_method(&self) -> PostgresAuthMethod;
+
+    fn postgres_max_prepared_statements(&self) -> usize;
+
+    fn postgres_idle_session_timeout(&self) -> u64;
+
+    fn postgres_idle_in_transaction_session_timeout(&self) -> u64;
+
+    fn postgres_max_connections(&self) -> Option<usize>;
+
+    fn postgres_user_connection_limits(&self) -> &HashMap<String, usize>;
+
+    fn postgres_shutdown_grace_period(&self) -> u64;
+
+    fn postgres_max_in_flight_batches(&self) -> usize;
+
+    fn ldap_auth(&self) -> &Option<LdapAuthConfig>;
+
+    fn metrics_bind_address(&self) -> &Option<String>;
+
+    fn otlp_endpoint(&self) -> &Option<String>;
+
+    fn query_log(&self) -> &Option<QueryLogConfig>;
+
+    fn slow_query_log_min_duration(&self) -> u64;
+
     fn query_timeout(&self) -> u64;
 
     fn nonce(&self) -> &Option<Vec<u8>>;
 }
 
+/// TLS for the PostgreSQL listener, paths to PEM files
+#[derive(Debug, Clone)]
+pub struct PostgresTlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    /// Client certificates are verified against these CAs, if it's specified
+    pub client_ca_path: Option<String>,
+}
+
+/// Unix domain socket, which is listened alongside the TCP address of PostgreSQL
+#[derive(Debug, Clone)]
+pub struct PostgresUnixSocketConfig {
+    pub path: String,
+    /// Access mode of the socket file, as unix_socket_permissions of PostgreSQL
+    pub permissions: u32,
+}
+
+/// Password authentication for the PostgreSQL listener, names are the same as in pg_hba.conf
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostgresAuthMethod {
+    /// The password is sent in clear text
+    Password,
+    ScramSha256,
+}
+
+impl FromStr for PostgresAuthMethod {
+    type Err = CubeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "password" => Ok(Self::Password),
+            "scram-sha-256" => Ok(Self::ScramSha256),
+            _ => Err(CubeError::user(format!(
+                "Unknown PostgreSQL authentication method: {}, expected: password or scram-sha-256",
+                s
+            ))),
+        }
+    }
+}
+
+/// Structured log of statements, see QueryLogWriter
+#[derive(Debug, Clone)]
+pub struct QueryLogConfig {
+    /// Records are written to stderr if it's not set
+    pub path: Option<String>,
+    /// The file is rotated when it reaches this size in bytes
+    pub max_size: u64,
+    /// Number of rotated files, which are kept
+    pub max_files: usize,
+}
+
+/// Authentication of users against an LDAP server, see SqlAuthLdapImpl
+#[derive(Debug, Clone)]
+pub struct LdapAuthConfig {
+    /// ldap:// or ldaps:// URL of the server
+    pub url: String,
+    /// DN for the bind with the user's password, `{user}` is replaced with the user name,
+    /// e.g. `uid={user},ou=people,dc=example,dc=com` or `{user}@corp.example.com`
+    pub bind_dn: String,
+    /// Groups are searched under this DN, otherwise they are read from the entry of the bound user
+    pub search_base: Option<String>,
+    /// Filter for the user's entry under `search_base`, `{user}` is replaced with the user name
+    pub search_filter: String,
+    /// Attribute with DNs of the user's groups
+    pub group_attribute: String,
+    /// Access tokens for groups, the first matching group is used
+    pub group_tokens: Vec<LdapGroupToken>,
+    /// Access token for users without any of `group_tokens` groups, they are denied if it's not set
+    pub default_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LdapGroupToken {
+    pub group: String,
+    pub token: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct ConfigObjImpl {
     pub bind_address: Option<String>,
     pub postgres_bind_address: Option<String>,
+    pub postgres_tls: Option<PostgresTlsConfig>,
+    pub postgres_unix_socket: Option<PostgresUnixSocketConfig>,
+    pub postgres_auth_method: PostgresAuthMethod,
+    /// Limit of prepared statements per session, least recently used ones are evicted
+    pub postgres_max_prepared_statements: usize,
+    /// Defaults of idle_session_timeout and idle_in_transaction_session_timeout in seconds,
+    /// 0 disables the timeout. Sessions can change them by SET.
+    pub postgres_idle_session_timeout: u64,
+    pub postgres_idle_in_transaction_session_timeout: u64,
+    /// Startup of a connection is rejected after the limit, None is for no limit
+    pub postgres_max_connections: Option<usize>,
+    /// Max numbers of connections by user names
+    pub postgres_user_connection_limits: HashMap<String, usize>,
+    /// Batches of a result, which are fetched ahead of sending them to the client
+    pub postgres_max_in_flight_batches: usize,
+    /// Seconds to wait for work of connections to finish on shutdown before terminating them
+    pub postgres_shutdown_grace_period: u64,
+    pub ldap_auth: Option<LdapAuthConfig>,
+    /// Address of the HTTP endpoint with Prometheus metrics, it's disabled if it's not set
+    pub metrics_bind_address: Option<String>,
+    /// Base URL of an OpenTelemetry collector (OTLP/HTTP), spans are not collected without it
+    pub otlp_endpoint: Option<String>,
+    pub query_log: Option<QueryLogConfig>,
+    /// Default of slow_query_log_min_duration of PostgreSQL sessions in milliseconds
+    pub slow_query_log_min_duration: u64,
     pub nonce: Option<Vec<u8>>,
     pub query_timeout: u64,
 }
@@ -141,6 +338,66 @@ impl ConfigObj for ConfigObjImpl {
         &self.postgres_bind_address
     }
 
+    fn postgres_tls(&self) -> &Option<PostgresTlsConfig> {
+        &self.postgres_tls
+    }
+
+    fn postgres_unix_socket(&self) -> &Option<PostgresUnixSocketConfig> {
+        &self.postgres_unix_socket
+    }
+
+    fn postgres_auth_method(&self) -> PostgresAuthMethod {
+        self.postgres_auth_method
+    }
+
+    fn postgres_max_prepared_statements(&self) -> usize {
+        self.postgres_max_prepared_statements
+    }
+
+    fn postgres_idle_session_timeout(&self) -> u64 {
+        self.postgres_idle_session_timeout
+    }
+
+    fn postgres_idle_in_transaction_session_timeout(&self) -> u64 {
+        self.postgres_idle_in_transaction_session_timeout
+    }
+
+    fn postgres_max_connections(&self) -> Option<usize> {
+        self.postgres_max_connections
+    }
+
+    fn postgres_user_connection_limits(&self) -> &HashMap<String, usize> {
+        &self.postgres_user_connection_limits
+    }
+
+    fn postgres_max_in_flight_batches(&self) -> usize {
+        self.postgres_max_in_flight_batches
+    }
+
+    fn postgres_shutdown_grace_period(&self) -> u64 {
+        self.postgres_shutdown_grace_period
+    }
+
+    fn ldap_auth(&self) -> &Option<LdapAuthConfig> {
+        &self.ldap_auth
+    }
+
+    fn metrics_bind_address(&self) -> &Option<String> {
+        &self.metrics_bind_address
+    }
+
+    fn otlp_endpoint(&self) -> &Option<String> {
+        &self.otlp_endpoint
+    }
+
+    fn query_log(&self) -> &Option<QueryLogConfig> {
+        &self.query_log
+    }
+
+    fn slow_query_log_min_duration(&self) -> u64 {
+        self.slow_query_log_min_duration
+    }
+
     fn nonce(&self) -> &Option<Vec<u8>> {
         &self.nonce
     }
@@ -173,6 +430,99 @@ impl Config {
                 postgres_bind_address: env::var("CUBESQL_PG_PORT")
                     .ok()
                     .map(|port| format!("0.0.0.0:{}", port.parse::<u16>().unwrap())),
+                postgres_tls: match (
+                    env::var("CUBESQL_PG_TLS_CERT").ok(),
+                    env::var("CUBESQL_PG_TLS_KEY").ok(),
+                ) {
+                    (Some(cert_path), Some(key_path)) => Some(PostgresTlsConfig {
+                        cert_path,
+                        key_path,
+                        client_ca_path: env::var("CUBESQL_PG_TLS_CLIENT_CA").ok(),
+                    }),
+                    _ => None,
+                },
+                postgres_unix_socket: env::var("CUBESQL_PG_UNIX_SOCKET").ok().map(|path| {
+                    PostgresUnixSocketConfig {
+                        path,
+                        // Octal, e.g. 0770
+                        permissions: env::var("CUBESQL_PG_UNIX_SOCKET_PERMISSIONS")
+                            .ok()
+                            .map(|v| u32::from_str_radix(&v, 8).unwrap())
+                            .unwrap_or(0o777),
+                    }
+                }),
+                postgres_auth_method: env::var("CUBESQL_PG_AUTH_METHOD")
+                    .ok()
+                    .map(|method| method.parse::<PostgresAuthMethod>().unwrap())
+                    .unwrap_or(PostgresAuthMethod::Password),
+                postgres_max_prepared_statements: env::var("CUBESQL_PG_MAX_PREPARED_STATEMENTS")
+                    .ok()
+                    .map(|v| v.parse::<usize>().unwrap().max(1))
+                    .unwrap_or(1000),
+                postgres_idle_session_timeout: env::var("CUBESQL_PG_IDLE_SESSION_TIMEOUT")
+                    .ok()
+                    .map(|v| v.parse::<u64>().unwrap())
+                    .unwrap_or(0),
+                postgres_idle_in_transaction_session_timeout: env::var(
+                    "CUBESQL_PG_IDLE_IN_TRANSACTION_SESSION_TIMEOUT",
+                )
+                .ok()
+                .map(|v| v.parse::<u64>().unwrap())
+                .unwrap_or(0),
+                postgres_max_connections: env::var("CUBESQL_PG_MAX_CONNECTIONS")
+                    .ok()
+                    .map(|v| v.parse::<usize>().unwrap()),
+                // JSON object, e.g. {"tableau": 20, "metabase": 5}
+                postgres_user_connection_limits: env::var("CUBESQL_PG_USER_CONNECTION_LIMITS")
+                    .ok()
+                    .map(|limits| serde_json::from_str(&limits).unwrap())
+                    .unwrap_or_default(),
+                postgres_max_in_flight_batches: env::var("CUBESQL_PG_MAX_IN_FLIGHT_BATCHES")
+                    .ok()
+                    .map(|v| v.parse::<usize>().unwrap())
+                    .unwrap_or(2),
+                postgres_shutdown_grace_period: env::var("CUBESQL_PG_SHUTDOWN_GRACE_PERIOD")
+                    .ok()
+                    .map(|v| v.parse::<u64>().unwrap())
+                    .unwrap_or(30),
+                ldap_auth: env::var("CUBESQL_LDAP_URL").ok().map(|url| LdapAuthConfig {
+                    url,
+                    bind_dn: env::var("CUBESQL_LDAP_BIND_DN").ok().unwrap_or_else(|| {
+                        panic!("CUBESQL_LDAP_BIND_DN is required for LDAP authentication")
+                    }),
+                    search_base: env::var("CUBESQL_LDAP_SEARCH_BASE").ok(),
+                    search_filter: env::var("CUBESQL_LDAP_SEARCH_FILTER")
+                        .ok()
+                        .unwrap_or_else(|| "(uid={user})".to_string()),
+                    group_attribute: env::var("CUBESQL_LDAP_GROUP_ATTRIBUTE")
+                        .ok()
+                        .unwrap_or_else(|| "memberOf".to_string()),
+                    group_tokens: env::var("CUBESQL_LDAP_GROUP_TOKENS")
+                        .ok()
+                        .map(|tokens| serde_json::from_str(&tokens).unwrap())
+                        .unwrap_or_default(),
+                    default_token: env::var("CUBESQL_LDAP_DEFAULT_TOKEN").ok(),
+                }),
+                metrics_bind_address: env::var("CUBESQL_METRICS_PORT")
+                    .ok()
+                    .map(|port| format!("0.0.0.0:{}", port.parse::<u16>().unwrap())),
+                otlp_endpoint: env::var("CUBESQL_OTLP_ENDPOINT").ok(),
+                // "stderr" or a path of the file
+                query_log: env::var("CUBESQL_QUERY_LOG").ok().map(|log| QueryLogConfig {
+                    path: if log == "stderr" { None } else { Some(log) },
+                    max_size: env::var("CUBESQL_QUERY_LOG_MAX_SIZE")
+                        .ok()
+                        .map(|v| v.parse::<u64>().unwrap())
+                        .unwrap_or(100 * 1024 * 1024),
+                    max_files: env::var("CUBESQL_QUERY_LOG_MAX_FILES")
+                        .ok()
+                        .map(|v| v.parse::<usize>().unwrap())
+                        .unwrap_or(5),
+                }),
+                slow_query_log_min_duration: env::var("CUBESQL_SLOW_QUERY_LOG_MIN_DURATION")
+                    .ok()
+                    .map(|v| v.parse::<u64>().unwrap())
+                    .unwrap_or(0),
                 nonce: None,
                 query_timeout,
             }),
@@ -186,6 +536,21 @@ impl Config {
             config_obj: Arc::new(ConfigObjImpl {
                 bind_address: None,
                 postgres_bind_address: None,
+                postgres_tls: None,
+                postgres_unix_socket: None,
+                postgres_auth_method: PostgresAuthMethod::Password,
+                postgres_max_prepared_statements: 1000,
+                postgres_idle_session_timeout: 0,
+                postgres_idle_in_transaction_session_timeout: 0,
+                postgres_max_connections: None,
+                postgres_user_connection_limits: HashMap::new(),
+                postgres_max_in_flight_batches: 2,
+                postgres_shutdown_grace_period: 0,
+                ldap_auth: None,
+                metrics_bind_address: None,
+                otlp_endpoint: None,
+                query_log: None,
+                slow_query_log_min_duration: 0,
                 nonce: None,
                 query_timeout,
             }),
@@ -230,6 +595,18 @@ impl Config {
                     i.get_service_typed().await,
                     i.get_service_typed().await,
                     config.nonce().clone(),
+                    ServerConfiguration {
+                        max_connections: config.postgres_max_connections(),
+                        user_connection_limits: config
+                            .postgres_user_connection_limits()
+                            .clone(),
+                        max_in_flight_batches: config.postgres_max_in_flight_batches(),
+                        idle_session_timeout: config.postgres_idle_session_timeout(),
+                        idle_in_transaction_session_timeout: config
+                            .postgres_idle_in_transaction_session_timeout(),
+                        slow_query_log_min_duration: config.slow_query_log_min_duration(),
+                        ..ServerConfiguration::default()
+                    },
                 ))
             })
             .await;
@@ -240,11 +617,19 @@ impl Config {
             })
             .await;
 
-        self.injector
-            .register_typed::<dyn SqlAuthService, _, _, _>(async move |_| {
-                Arc::new(SqlAuthDefaultImpl)
-            })
-            .await;
+        if let Some(ldap_auth) = self.config_obj.ldap_auth().clone() {
+            self.injector
+                .register_typed::<dyn SqlAuthService, _, _, _>(async move |_| {
+                    Arc::new(SqlAuthLdapImpl::new(ldap_auth))
+                })
+                .await;
+        } else {
+            self.injector
+                .register_typed::<dyn SqlAuthService, _, _, _>(async move |_| {
+                    Arc::new(SqlAuthDefaultImpl)
+                })
+                .await;
+        }
 
         if self.config_obj.bind_address().is_some() {
             self.injector
@@ -264,11 +649,45 @@ impl Config {
                     let config = i.get_service_typed::<dyn ConfigObj>().await;
                     PostgresServer::new(
                         config.postgres_bind_address().as_ref().unwrap().to_string(),
+                        config.postgres_tls().clone(),
+                        config.postgres_unix_socket().clone(),
+                        config.postgres_auth_method(),
+                        config.postgres_max_prepared_statements(),
+                        config.postgres_shutdown_grace_period(),
                         i.get_service_typed().await,
                     )
                 })
                 .await;
         }
+
+        if self.config_obj.metrics_bind_address().is_some() {
+            self.injector
+                .register_typed::<MetricsServer, _, _, _>(async move |i| {
+                    let config = i.get_service_typed::<dyn ConfigObj>().await;
+                    MetricsServer::new(
+                        config.metrics_bind_address().as_ref().unwrap().to_string(),
+                        i.get_service_typed().await,
+                    )
+                })
+                .await;
+        }
+
+        if let Some(query_log) = self.config_obj.query_log().clone() {
+            self.injector
+                .register_typed::<QueryLogWriter, _, _, _>(async move |_| {
+                    QueryLogWriter::new(query_log)
+                })
+                .await;
+        }
+
+        if self.config_obj.otlp_endpoint().is_some() {
+            self.injector
+                .register_typed::<SpanExporter, _, _, _>(async move |i| {
+                    let config = i.get_service_typed::<dyn ConfigObj>().await;
+                    SpanExporter::new(config.otlp_endpoint().as_ref().unwrap().to_string())
+                })
+                .await;
+        }
     }
 
     pub async fn cube_services(&self) -> CubeServices {
@@ -25,6 +25,22 @@ impl AuthenticateResponse {
 #[async_trait]
 pub trait SqlAuthService: Send + Sync + Debug {
     async fn authenticate(&self, user: Option<String>) -> Result<AuthenticateResponse, CubeError>;
+
+    /// Checks the password, which is received in clear text. Ok(None) is returned
+    /// if the password is wrong. Services, which can't return the expected password
+    /// from `authenticate`, should override it.
+    async fn authenticate_password(
+        &self,
+        user: Option<String>,
+        password: String,
+    ) -> Result<Option<AuthContext>, CubeError> {
+        let response = self.authenticate(user).await?;
+
+        Ok(match response.password {
+            Some(expected) if expected != password => None,
+            _ => Some(response.context),
+        })
+    }
 }
 
 #[derive(Debug)]
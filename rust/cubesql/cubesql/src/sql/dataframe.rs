@@ -1,20 +1,25 @@
-use std::fmt::{self, Debug, Formatter};
+use std::{
+    cmp::Ordering,
+    convert::TryFrom,
+    fmt::{self, Debug, Display, Formatter},
+    sync::Arc,
+};
 
 use chrono::{SecondsFormat, TimeZone, Utc};
 use comfy_table::{Cell, Table};
 use datafusion::arrow::{
     array::{
-        Array, BooleanArray, Float64Array, Int32Array, Int64Array, IntervalDayTimeArray,
-        IntervalYearMonthArray, StringArray, TimestampMicrosecondArray, TimestampNanosecondArray,
-        UInt32Array, UInt64Array,
+        Array, ArrayRef, BooleanArray, DecimalArray, Float64Array, Int32Array, Int64Array,
+        IntervalDayTimeArray, IntervalYearMonthArray, ListArray, StringArray,
+        TimestampMicrosecondArray, TimestampNanosecondArray, UInt32Array, UInt64Array,
     },
-    datatypes::{DataType, IntervalUnit, TimeUnit},
+    datatypes::{DataType, Field, IntervalUnit, Schema, TimeUnit},
     record_batch::RecordBatch,
 };
 
 use super::{ColumnFlags, ColumnType};
 
-use crate::{make_string_interval_day_time, make_string_interval_year_month, CubeError};
+use crate::CubeError;
 
 #[derive(Clone, Debug)]
 pub struct Column {
@@ -76,6 +81,36 @@ pub enum TableValue {
     Boolean(bool),
     Float64(f64),
     Timestamp(TimestampValue),
+    /// Timestamp with time zone, which is UTC
+    Timestamptz(TimestampValue),
+    Decimal(DecimalValue),
+    Interval(IntervalValue),
+    /// Elements of an array
+    List(Vec<TableValue>),
+}
+
+impl TableValue {
+    /// Text of the value for `DataFrame::print` and MySQL, arrays are written as `{1,2}`
+    pub fn to_text(&self) -> String {
+        match self {
+            TableValue::Null => "NULL".to_string(),
+            TableValue::String(s) => s.clone(),
+            TableValue::Int64(n) => n.to_string(),
+            TableValue::Boolean(b) => b.to_string(),
+            TableValue::Float64(n) => n.to_string(),
+            TableValue::Timestamp(t) | TableValue::Timestamptz(t) => t.to_string(),
+            TableValue::Decimal(d) => d.to_string(),
+            TableValue::Interval(i) => i.to_string(),
+            TableValue::List(values) => format!(
+                "{{{}}}",
+                values
+                    .iter()
+                    .map(|v| v.to_text())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -123,14 +158,7 @@ impl DataFrame {
             let mut table_row = vec![];
 
             for (_i, value) in row.values().iter().enumerate() {
-                match value {
-                    TableValue::Null => table_row.push("NULL".to_string()),
-                    TableValue::String(s) => table_row.push(s.clone()),
-                    TableValue::Int64(n) => table_row.push(n.to_string()),
-                    TableValue::Boolean(b) => table_row.push(b.to_string()),
-                    TableValue::Float64(n) => table_row.push(n.to_string()),
-                    TableValue::Timestamp(t) => table_row.push(t.to_string()),
-                }
+                table_row.push(value.to_text());
             }
 
             table.add_row(table_row);
@@ -174,6 +202,224 @@ impl ToString for TimestampValue {
     }
 }
 
+/// INTERVAL value as PostgreSQL stores it, months and days are separate from the time because
+/// their lengths vary
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct IntervalValue {
+    months: i32,
+    days: i32,
+    microseconds: i64,
+}
+
+impl IntervalValue {
+    pub fn new(months: i32, days: i32, microseconds: i64) -> IntervalValue {
+        IntervalValue {
+            months,
+            days,
+            microseconds,
+        }
+    }
+
+    pub fn months(&self) -> i32 {
+        self.months
+    }
+
+    pub fn days(&self) -> i32 {
+        self.days
+    }
+
+    pub fn microseconds(&self) -> i64 {
+        self.microseconds
+    }
+
+    pub fn negate(&self) -> IntervalValue {
+        IntervalValue::new(-self.months, -self.days, -self.microseconds)
+    }
+}
+
+/// The representation of `DataFrame::print` and MySQL: `0 years 1 mons 2 days 3 hours 4 mins
+/// 5.00 secs`, PostgreSQL writes intervals by `format_interval`
+impl ToString for IntervalValue {
+    fn to_string(&self) -> String {
+        let millis = self.microseconds / 1000;
+        let secs = millis / 1000;
+        let mins = secs / 60;
+        let hours = mins / 60;
+
+        format!(
+            "{} years {} mons {} days {} hours {} mins {}.{:02} secs",
+            self.months.div_euclid(12),
+            self.months.rem_euclid(12),
+            self.days,
+            hours,
+            mins - hours * 60,
+            secs - mins * 60,
+            millis % 1000
+        )
+    }
+}
+
+fn pow10(exp: usize) -> Option<i128> {
+    10_i128.checked_pow(u32::try_from(exp).ok()?)
+}
+
+/// NUMERIC value, which is `value` divided by 10^scale
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct DecimalValue {
+    value: i128,
+    scale: usize,
+}
+
+impl DecimalValue {
+    pub fn new(value: i128, scale: usize) -> DecimalValue {
+        DecimalValue { value, scale }
+    }
+
+    pub fn value(&self) -> i128 {
+        self.value
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    /// Parses `[+-]digits[.digits][e[+-]digits]`, the scale is the number of fractional digits
+    pub fn parse(text: &str) -> Option<DecimalValue> {
+        let text = text.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (mantissa, exponent) = match text.find(|c: char| c == 'e' || c == 'E') {
+            Some(position) => (&text[..position], text[position + 1..].parse::<i64>().ok()?),
+            None => (text, 0),
+        };
+        let (integral, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if integral.is_empty() && fraction.is_empty() {
+            return None;
+        }
+
+        let mut value: i128 = 0;
+        for c in integral.chars().chain(fraction.chars()) {
+            let digit = c.to_digit(10)? as i128;
+            value = value.checked_mul(10)?.checked_add(digit)?;
+        }
+        if negative {
+            value = -value;
+        }
+
+        let scale = fraction.len() as i64 - exponent;
+        if scale >= 0 {
+            Some(DecimalValue::new(value, scale as usize))
+        } else {
+            let value = value.checked_mul(pow10((-scale) as usize)?)?;
+            Some(DecimalValue::new(value, 0))
+        }
+    }
+
+    /// The value with another scale, extra digits are rounded half away from zero.
+    /// None on overflow.
+    pub fn rescale(&self, scale: usize) -> Option<DecimalValue> {
+        if scale >= self.scale {
+            let value = self.value.checked_mul(pow10(scale - self.scale)?)?;
+            return Some(DecimalValue::new(value, scale));
+        }
+
+        let value = match pow10(self.scale - scale) {
+            Some(divisor) => {
+                let remainder = (self.value % divisor).abs();
+                let rounding = if remainder >= divisor - remainder {
+                    self.value.signum()
+                } else {
+                    0
+                };
+
+                self.value / divisor + rounding
+            }
+            None => 0,
+        };
+
+        Some(DecimalValue::new(value, scale))
+    }
+
+    /// Whether the value has at most `precision` digits
+    pub fn fits(&self, precision: usize) -> bool {
+        pow10(precision).map_or(true, |limit| self.value.abs() < limit)
+    }
+
+    /// Sum of values, its scale is the largest of scales. None on overflow.
+    pub fn checked_add(&self, other: &DecimalValue) -> Option<DecimalValue> {
+        let scale = self.scale.max(other.scale);
+        let value = self
+            .rescale(scale)?
+            .value
+            .checked_add(other.rescale(scale)?.value)?;
+
+        Some(DecimalValue::new(value, scale))
+    }
+
+    pub fn checked_neg(&self) -> Option<DecimalValue> {
+        Some(DecimalValue::new(self.value.checked_neg()?, self.scale))
+    }
+
+    /// Product of values, its scale is the sum of scales. None on overflow.
+    pub fn checked_mul(&self, other: &DecimalValue) -> Option<DecimalValue> {
+        let value = self.value.checked_mul(other.value)?;
+
+        Some(DecimalValue::new(value, self.scale + other.scale))
+    }
+
+    /// Quotient with `scale` fractional digits, rounded half away from zero. None on overflow
+    /// and division by zero.
+    pub fn checked_div(&self, other: &DecimalValue, scale: usize) -> Option<DecimalValue> {
+        if other.value == 0 {
+            return None;
+        }
+
+        // One more digit than needed is calculated, it's rounded by `rescale`
+        let exponent = scale + 1 + other.scale;
+        let (dividend, divisor) = if exponent >= self.scale {
+            let multiplier = pow10(exponent - self.scale)?;
+            (self.value.checked_mul(multiplier)?, other.value)
+        } else {
+            let multiplier = pow10(self.scale - exponent)?;
+            (self.value, other.value.checked_mul(multiplier)?)
+        };
+
+        DecimalValue::new(dividend / divisor, scale + 1).rescale(scale)
+    }
+
+    /// Compares numbers, 1.0 and 1.00 are equal
+    pub fn cmp_value(&self, other: &DecimalValue) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        match (self.rescale(scale), other.rescale(scale)) {
+            (Some(left), Some(right)) => left.value.cmp(&right.value),
+            _ => self
+                .to_f64()
+                .partial_cmp(&other.to_f64())
+                .unwrap_or(Ordering::Equal),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+}
+
+impl Display for DecimalValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let sign = if self.value < 0 { "-" } else { "" };
+        let digits = self.value.unsigned_abs().to_string();
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+
+        let digits = format!("{:0>width$}", digits, width = self.scale + 1);
+        let (integral, fraction) = digits.split_at(digits.len() - self.scale);
+        write!(f, "{}{}.{}", sign, integral, fraction)
+    }
+}
+
 macro_rules! convert_array_cast_native {
     ($V: expr, (Vec<u8>)) => {{
         $V.to_vec()
@@ -200,9 +446,11 @@ pub fn arrow_to_column_type(arrow_type: DataType) -> Result<ColumnType, CubeErro
     match arrow_type {
         DataType::Binary => Ok(ColumnType::Blob),
         DataType::Utf8 | DataType::LargeUtf8 => Ok(ColumnType::String),
-        DataType::Timestamp(_, _) => Ok(ColumnType::String),
-        DataType::Interval(_) => Ok(ColumnType::String),
+        DataType::Timestamp(_, Some(_)) => Ok(ColumnType::Timestamptz),
+        DataType::Timestamp(_, None) => Ok(ColumnType::String),
+        DataType::Interval(_) => Ok(ColumnType::Interval),
         DataType::Float16 | DataType::Float64 => Ok(ColumnType::Double),
+        DataType::Decimal(precision, scale) => Ok(ColumnType::Decimal(precision, scale)),
         DataType::Boolean => Ok(ColumnType::Int8),
         DataType::Int8
         | DataType::Int16
@@ -212,10 +460,39 @@ pub fn arrow_to_column_type(arrow_type: DataType) -> Result<ColumnType, CubeErro
         | DataType::UInt16
         | DataType::UInt32
         | DataType::UInt64 => Ok(ColumnType::Int64),
+        DataType::List(field) => match arrow_to_column_type(field.data_type().clone())? {
+            ColumnType::String | ColumnType::VarStr => Ok(ColumnType::List(&ColumnType::String)),
+            ColumnType::Double => Ok(ColumnType::List(&ColumnType::Double)),
+            ColumnType::Int8 => Ok(ColumnType::List(&ColumnType::Int8)),
+            ColumnType::Int32 | ColumnType::Int64 => Ok(ColumnType::List(&ColumnType::Int64)),
+            ColumnType::Blob => Ok(ColumnType::List(&ColumnType::Blob)),
+            ColumnType::Timestamp => Ok(ColumnType::List(&ColumnType::Timestamp)),
+            ColumnType::Timestamptz => Ok(ColumnType::List(&ColumnType::Timestamptz)),
+            ColumnType::Interval => Ok(ColumnType::List(&ColumnType::Interval)),
+            ColumnType::Decimal(_, _) => Err(CubeError::internal(
+                "arrays of decimals are not supported".to_string(),
+            )),
+            ColumnType::List(_) => Err(CubeError::internal(
+                "multidimensional arrays are not supported".to_string(),
+            )),
+        },
         x => Err(CubeError::internal(format!("unsupported type {:?}", x))),
     }
 }
 
+/// Elements of a list, they're converted as a single column
+fn array_to_values(array: ArrayRef) -> Result<Vec<TableValue>, CubeError> {
+    let schema = Schema::new(vec![Field::new("item", array.data_type().clone(), true)]);
+    let batch = RecordBatch::try_new(Arc::new(schema), vec![array])
+        .map_err(|e| CubeError::internal(e.to_string()))?;
+
+    Ok(batch_to_dataframe(&vec![batch])?
+        .into_rows()
+        .into_iter()
+        .map(|row| row.values()[0].clone())
+        .collect())
+}
+
 pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeError> {
     let mut cols = vec![];
     let mut all_rows = vec![];
@@ -259,6 +536,16 @@ pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeE
                         });
                     }
                 }
+                DataType::Decimal(_, scale) => {
+                    let a = array.as_any().downcast_ref::<DecimalArray>().unwrap();
+                    for i in 0..num_rows {
+                        rows[i].push(if a.is_null(i) {
+                            TableValue::Null
+                        } else {
+                            TableValue::Decimal(DecimalValue::new(a.value(i), *scale))
+                        });
+                    }
+                }
                 DataType::Utf8 => {
                     let a = array.as_any().downcast_ref::<StringArray>().unwrap();
                     for i in 0..num_rows {
@@ -295,13 +582,36 @@ pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeE
                         });
                     }
                 }
+                DataType::Timestamp(TimeUnit::Nanosecond, Some(_)) => {
+                    let a = array
+                        .as_any()
+                        .downcast_ref::<TimestampNanosecondArray>()
+                        .unwrap();
+                    for i in 0..num_rows {
+                        rows[i].push(if a.is_null(i) {
+                            TableValue::Null
+                        } else {
+                            TableValue::Timestamptz(TimestampValue::new(a.value(i)))
+                        });
+                    }
+                }
                 DataType::Interval(IntervalUnit::DayTime) => {
                     let a = array
                         .as_any()
                         .downcast_ref::<IntervalDayTimeArray>()
                         .unwrap();
                     for i in 0..num_rows {
-                        rows[i].push(TableValue::String(make_string_interval_day_time!(a, i)));
+                        // Days are the high 32 bits, milliseconds are the low ones
+                        rows[i].push(if a.is_null(i) {
+                            TableValue::Null
+                        } else {
+                            let value = a.value(i);
+                            TableValue::Interval(IntervalValue::new(
+                                0,
+                                (value >> 32) as i32,
+                                value as i32 as i64 * 1000,
+                            ))
+                        });
                     }
                 }
                 DataType::Interval(IntervalUnit::YearMonth) => {
@@ -310,7 +620,11 @@ pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeE
                         .downcast_ref::<IntervalYearMonthArray>()
                         .unwrap();
                     for i in 0..num_rows {
-                        rows[i].push(TableValue::String(make_string_interval_year_month!(a, i)));
+                        rows[i].push(if a.is_null(i) {
+                            TableValue::Null
+                        } else {
+                            TableValue::Interval(IntervalValue::new(a.value(i), 0, 0))
+                        });
                     }
                 }
                 DataType::Boolean => {
@@ -323,6 +637,16 @@ pub fn batch_to_dataframe(batches: &Vec<RecordBatch>) -> Result<DataFrame, CubeE
                         });
                     }
                 }
+                DataType::List(_) => {
+                    let a = array.as_any().downcast_ref::<ListArray>().unwrap();
+                    for i in 0..num_rows {
+                        rows[i].push(if a.is_null(i) {
+                            TableValue::Null
+                        } else {
+                            TableValue::List(array_to_values(a.value(i))?)
+                        });
+                    }
+                }
                 x => panic!("Unsupported data type: {:?}", x),
             }
         }
@@ -356,4 +680,49 @@ mod tests {
             +------------+"
         );
     }
+
+    #[test]
+    fn test_decimal_value() {
+        let parse = |text: &str| DecimalValue::parse(text).map(|v| (v.value(), v.scale()));
+        assert_eq!(parse("12.340"), Some((12340, 3)));
+        assert_eq!(parse("-0.5"), Some((-5, 1)));
+        assert_eq!(parse("+7"), Some((7, 0)));
+        assert_eq!(parse("1.5e2"), Some((150, 0)));
+        assert_eq!(parse("25e-3"), Some((25, 3)));
+        assert_eq!(parse(".5"), Some((5, 1)));
+        assert_eq!(parse("1.2.3"), None);
+        assert_eq!(parse("abc"), None);
+        assert_eq!(parse(""), None);
+
+        let rescale = |value: i128, scale: usize, to: usize| {
+            DecimalValue::new(value, scale)
+                .rescale(to)
+                .map(|v| v.to_string())
+        };
+        assert_eq!(rescale(12345, 3, 2), Some("12.35".to_string()));
+        assert_eq!(rescale(-12345, 3, 2), Some("-12.35".to_string()));
+        assert_eq!(rescale(12344, 3, 0), Some("12".to_string()));
+        assert_eq!(rescale(5, 1, 3), Some("0.500".to_string()));
+        assert_eq!(rescale(i128::MAX, 0, 1), None);
+
+        assert_eq!(DecimalValue::new(-5, 2).to_string(), "-0.05");
+        assert_eq!(DecimalValue::new(0, 0).to_string(), "0");
+        assert!(DecimalValue::new(99999, 2).fits(5));
+        assert!(!DecimalValue::new(-100000, 2).fits(5));
+
+        let (a, b) = (DecimalValue::new(125, 2), DecimalValue::new(-3, 1));
+        assert_eq!(a.checked_add(&b).unwrap().to_string(), "0.95");
+        assert_eq!(a.checked_mul(&b).unwrap().to_string(), "-0.375");
+        assert_eq!(a.checked_div(&b, 3).unwrap().to_string(), "-4.167");
+        assert_eq!(
+            DecimalValue::new(2, 0)
+                .checked_div(&b, 0)
+                .unwrap()
+                .to_string(),
+            "-7"
+        );
+        assert_eq!(a.checked_div(&DecimalValue::new(0, 2), 2), None);
+        assert_eq!(a.cmp_value(&DecimalValue::new(12500, 4)), Ordering::Equal);
+        assert_eq!(b.cmp_value(&a), Ordering::Less);
+    }
 }
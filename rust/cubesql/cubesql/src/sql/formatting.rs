@@ -0,0 +1,597 @@
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+use crate::sql::dataframe::DecimalValue;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Patterns of dates and times, longer patterns are before their prefixes
+const DATE_PATTERNS: &[&str] = &[
+    "A.D.", "A.M.", "B.C.", "P.M.", "AD", "AM", "BC", "PM", "HH24", "HH12", "HH", "MI", "SSSSS",
+    "SSSS", "SS", "MS", "US", "Y,YYY", "YYYY", "YYY", "YY", "Y", "IYYY", "IYY", "IY", "IDDD", "ID",
+    "IW", "I", "MONTH", "MON", "MM", "DAY", "DDD", "DD", "DY", "D", "WW", "W", "Q", "J", "CC",
+    "RM", "TZH", "TZM", "TZ", "OF",
+];
+
+/// Patterns of numbers, longer patterns are before their prefixes
+const NUMBER_PATTERNS: &[&str] = &[
+    "EEEE", "FM", "PR", "PL", "MI", "SG", "RN", "TH", "S", "L", "V", "D", "G", "9", "0", ".", ",",
+];
+
+/// Zone of a timestamp with time zone: its abbreviation and offset from UTC in seconds
+#[derive(Debug)]
+pub struct FormatZone {
+    pub abbreviation: String,
+    pub offset: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LetterCase {
+    Upper,
+    Capitalized,
+    Lower,
+}
+
+/// Case of a text pattern: `MONTH`, `Month` or `month`
+fn letter_case(matched: &[char]) -> LetterCase {
+    let mut letters = matched.iter().filter(|c| c.is_alphabetic());
+    match (letters.next(), letters.next()) {
+        (Some(c), _) if c.is_lowercase() => LetterCase::Lower,
+        (Some(_), Some(c)) if c.is_lowercase() => LetterCase::Capitalized,
+        _ => LetterCase::Upper,
+    }
+}
+
+/// Names are capitalized
+fn with_case(text: &str, case: LetterCase) -> String {
+    match case {
+        LetterCase::Upper => text.to_uppercase(),
+        LetterCase::Capitalized => text.to_string(),
+        LetterCase::Lower => text.to_lowercase(),
+    }
+}
+
+/// Patterns are matched case-insensitively
+fn starts_with(chars: &[char], pattern: &str) -> bool {
+    pattern.len() <= chars.len()
+        && chars
+            .iter()
+            .zip(pattern.chars())
+            .all(|(c, p)| c.to_ascii_uppercase() == p)
+}
+
+fn ordinal_suffix(value: i64) -> &'static str {
+    let value = value.abs();
+    match (value % 100, value % 10) {
+        (11..=13, _) => "th",
+        (_, 1) => "st",
+        (_, 2) => "nd",
+        (_, 3) => "rd",
+        _ => "th",
+    }
+}
+
+fn roman(mut value: i64) -> String {
+    const NUMERALS: [(i64, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+
+    let mut out = String::new();
+    for (step, numeral) in NUMERALS.iter() {
+        while value >= *step {
+            out.push_str(numeral);
+            value -= step;
+        }
+    }
+
+    out
+}
+
+/// Text of a template, which is copied: double quoted text (`"Q"Q`) and characters escaped by
+/// a backslash
+fn literal(chars: &[char], out: &mut String) -> Option<usize> {
+    match chars.first() {
+        Some('"') => {
+            let mut i = 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    i += 1;
+                }
+                out.push(chars[i]);
+                i += 1;
+            }
+
+            Some((i + 1).min(chars.len()))
+        }
+        Some('\\') if chars.len() > 1 => {
+            out.push(chars[1]);
+            Some(2)
+        }
+        _ => None,
+    }
+}
+
+enum DateField {
+    Number(i64, usize),
+    Text(String),
+}
+
+fn date_field(pattern: &str, value: &NaiveDateTime, zone: Option<&FormatZone>) -> DateField {
+    let year = value.year() as i64;
+    let iso_year = value.iso_week().year() as i64;
+    let hour12 = match value.hour() % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    let offset = zone.map_or(0, |zone| zone.offset);
+    let offset_sign = if offset < 0 { '-' } else { '+' };
+
+    match pattern {
+        "HH24" => DateField::Number(value.hour() as i64, 2),
+        "HH12" | "HH" => DateField::Number(hour12 as i64, 2),
+        "MI" => DateField::Number(value.minute() as i64, 2),
+        "SS" => DateField::Number(value.second() as i64, 2),
+        "MS" => DateField::Number(value.nanosecond() as i64 / 1_000_000, 3),
+        "US" => DateField::Number(value.nanosecond() as i64 / 1_000, 6),
+        "SSSS" | "SSSSS" => DateField::Number(value.num_seconds_from_midnight() as i64, 0),
+        "AM" | "PM" => DateField::Text(if value.hour() < 12 { "AM" } else { "PM" }.to_string()),
+        "A.M." | "P.M." => {
+            DateField::Text(if value.hour() < 12 { "A.M." } else { "P.M." }.to_string())
+        }
+        "AD" | "BC" => DateField::Text(if year > 0 { "AD" } else { "BC" }.to_string()),
+        "A.D." | "B.C." => DateField::Text(if year > 0 { "A.D." } else { "B.C." }.to_string()),
+        "Y,YYY" => DateField::Text(format!("{},{:03}", year / 1000, year % 1000)),
+        "YYYY" => DateField::Number(year, 4),
+        "YYY" => DateField::Number(year % 1000, 3),
+        "YY" => DateField::Number(year % 100, 2),
+        "Y" => DateField::Number(year % 10, 1),
+        "IYYY" => DateField::Number(iso_year, 4),
+        "IYY" => DateField::Number(iso_year % 1000, 3),
+        "IY" => DateField::Number(iso_year % 100, 2),
+        "I" => DateField::Number(iso_year % 10, 1),
+        "IDDD" => DateField::Number(
+            (value.iso_week().week() as i64 - 1) * 7 + value.weekday().number_from_monday() as i64,
+            3,
+        ),
+        "ID" => DateField::Number(value.weekday().number_from_monday() as i64, 1),
+        "IW" => DateField::Number(value.iso_week().week() as i64, 2),
+        "MONTH" => DateField::Text(MONTHS[value.month0() as usize].to_string()),
+        "MON" => DateField::Text(MONTHS[value.month0() as usize][..3].to_string()),
+        "MM" => DateField::Number(value.month() as i64, 2),
+        "DAY" => DateField::Text(DAYS[value.weekday().num_days_from_sunday() as usize].to_string()),
+        "DY" => {
+            DateField::Text(DAYS[value.weekday().num_days_from_sunday() as usize][..3].to_string())
+        }
+        "DDD" => DateField::Number(value.ordinal() as i64, 3),
+        "DD" => DateField::Number(value.day() as i64, 2),
+        "D" => DateField::Number(value.weekday().number_from_sunday() as i64, 1),
+        "WW" => DateField::Number((value.ordinal0() / 7 + 1) as i64, 2),
+        "W" => DateField::Number((value.day0() / 7 + 1) as i64, 1),
+        "Q" => DateField::Number((value.month0() / 3 + 1) as i64, 1),
+        // Julian days are counted from November 24, 4714 BC
+        "J" => DateField::Number(value.num_days_from_ce() as i64 + 1_721_425, 0),
+        "CC" => DateField::Number((year + 99) / 100, 2),
+        "RM" => DateField::Text(roman(value.month() as i64)),
+        "TZ" => DateField::Text(zone.map_or(String::new(), |zone| zone.abbreviation.clone())),
+        "TZH" => DateField::Text(format!("{}{:02}", offset_sign, offset.abs() / 3600)),
+        "TZM" => DateField::Number((offset.abs() % 3600 / 60) as i64, 2),
+        _ => {
+            let mut text = format!("{}{:02}", offset_sign, offset.abs() / 3600);
+            if offset % 3600 != 0 {
+                text.push_str(&format!(":{:02}", offset.abs() % 3600 / 60));
+            }
+
+            DateField::Text(text)
+        }
+    }
+}
+
+/// `to_char(timestamp, template)` of PostgreSQL. Names are in English, as in the C locale of
+/// `lc_time`. `zone` is the zone of timestamps with time zone, `TZ` is empty without it.
+pub fn format_timestamp(
+    value: &NaiveDateTime,
+    zone: Option<&FormatZone>,
+    template: &str,
+) -> String {
+    let chars = template.chars().collect::<Vec<_>>();
+    let mut out = String::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(len) = literal(&chars[i..], &mut out) {
+            i += len;
+            continue;
+        }
+
+        // FM (fill mode) suppresses padding of the next pattern, FX is accepted for parsing only
+        let fill = starts_with(&chars[i..], "FM");
+        let start = if fill { i + 2 } else { i };
+        let pattern = match DATE_PATTERNS
+            .iter()
+            .find(|pattern| starts_with(&chars[start..], pattern))
+        {
+            Some(pattern) => *pattern,
+            None if fill || starts_with(&chars[i..], "FX") => {
+                i += 2;
+                continue;
+            }
+            None => {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            }
+        };
+        let mut end = start + pattern.len();
+        let case = letter_case(&chars[start..end]);
+
+        match date_field(pattern, value, zone) {
+            DateField::Number(number, width) => {
+                if fill {
+                    out.push_str(&number.to_string());
+                } else {
+                    out.push_str(&format!("{:0width$}", number, width = width));
+                }
+
+                // Ordinal suffix, `DDth` is `01st`
+                if starts_with(&chars[end..], "TH") {
+                    let suffix = ordinal_suffix(number);
+                    if chars[end].is_lowercase() {
+                        out.push_str(suffix);
+                    } else {
+                        out.push_str(&suffix.to_uppercase());
+                    }
+                    end += 2;
+                }
+            }
+            DateField::Text(text) => {
+                let text = with_case(&text, case);
+                let width = match pattern {
+                    "MONTH" | "DAY" => 9,
+                    "RM" => 4,
+                    _ => 0,
+                };
+                if fill {
+                    out.push_str(&text);
+                } else {
+                    out.push_str(&format!("{:width$}", text, width = width));
+                }
+            }
+        }
+
+        i = end;
+    }
+
+    out
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum NumberToken {
+    /// `0` is a digit which is printed even if it's a leading zero, `9` is blank then
+    Digit(bool),
+    Point,
+    Group,
+    Sign(&'static str),
+    Currency,
+    Shift,
+    Exponent,
+    Roman(LetterCase),
+    Ordinal(LetterCase),
+    Literal(char),
+}
+
+fn number_tokens(template: &str) -> (Vec<NumberToken>, bool) {
+    let chars = template.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut fill = false;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let mut text = String::new();
+        if let Some(len) = literal(&chars[i..], &mut text) {
+            tokens.extend(text.chars().map(NumberToken::Literal));
+            i += len;
+            continue;
+        }
+
+        let pattern = match NUMBER_PATTERNS
+            .iter()
+            .find(|pattern| starts_with(&chars[i..], pattern))
+        {
+            Some(pattern) => *pattern,
+            None => {
+                tokens.push(NumberToken::Literal(chars[i]));
+                i += 1;
+                continue;
+            }
+        };
+        let case = letter_case(&chars[i..i + pattern.len()]);
+
+        match pattern {
+            "FM" => fill = true,
+            "9" | "0" => tokens.push(NumberToken::Digit(pattern == "0")),
+            "." | "D" => tokens.push(NumberToken::Point),
+            "," | "G" => tokens.push(NumberToken::Group),
+            "PR" | "PL" | "MI" | "SG" | "S" => tokens.push(NumberToken::Sign(pattern)),
+            "L" => tokens.push(NumberToken::Currency),
+            "V" => tokens.push(NumberToken::Shift),
+            "EEEE" => tokens.push(NumberToken::Exponent),
+            "RN" => tokens.push(NumberToken::Roman(case)),
+            _ => tokens.push(NumberToken::Ordinal(case)),
+        }
+        i += pattern.len();
+    }
+
+    (tokens, fill)
+}
+
+/// `value * 10^exp`, None on overflow
+fn shift(value: &DecimalValue, exp: usize) -> Option<DecimalValue> {
+    if value.scale() >= exp {
+        Some(DecimalValue::new(value.value(), value.scale() - exp))
+    } else {
+        let multiplier = 10_i128.checked_pow((exp - value.scale()) as u32)?;
+        Some(DecimalValue::new(value.value().checked_mul(multiplier)?, 0))
+    }
+}
+
+/// `to_char(number, template)` of PostgreSQL with the C locale of `lc_numeric` and
+/// `lc_monetary`: the decimal point is `.`, the group separator is `,` and the currency symbol
+/// is blank. Without a sign pattern a position is reserved for `-` before the first digit,
+/// digits which don't fit are `#`.
+pub fn format_number(value: &DecimalValue, template: &str) -> String {
+    let (tokens, fill) = number_tokens(template);
+    let negative = value.value() < 0;
+
+    if let Some(NumberToken::Roman(case)) = tokens
+        .iter()
+        .find(|token| matches!(token, NumberToken::Roman(_)))
+    {
+        let number = value.rescale(0).map_or(0, |value| value.value() as i64);
+        let text = if (1..=3999).contains(&number) {
+            with_case(&roman(number), *case)
+        } else {
+            "#".repeat(15)
+        };
+
+        return if fill { text } else { format!("{:>15}", text) };
+    }
+
+    let point = tokens.iter().position(|token| *token == NumberToken::Point);
+    let is_digit = |token: &NumberToken| matches!(token, NumberToken::Digit(_));
+    let count_digits = |tokens: &[NumberToken]| tokens.iter().filter(|t| is_digit(t)).count();
+    let (int_positions, frac_positions) = match point {
+        Some(point) => (
+            count_digits(&tokens[..point]),
+            count_digits(&tokens[point..]),
+        ),
+        None => (count_digits(&tokens), 0),
+    };
+
+    if tokens.contains(&NumberToken::Exponent) {
+        let text = format!("{:.*e}", frac_positions, value.to_f64().abs());
+        let (mantissa, exp) = text.split_once('e').unwrap_or((&text, "0"));
+        let exp = exp.parse::<i32>().unwrap_or(0);
+        let sign = match (negative, fill) {
+            (true, _) => "-",
+            (false, true) => "",
+            (false, false) => " ",
+        };
+
+        return format!(
+            "{}{}e{}{:02}",
+            sign,
+            mantissa,
+            if exp < 0 { '-' } else { '+' },
+            exp.abs()
+        );
+    }
+
+    // V multiplies by 10^n, where n is the number of digits after it
+    let shift_digits = tokens
+        .iter()
+        .position(|token| *token == NumberToken::Shift)
+        .map_or(0, |position| count_digits(&tokens[position..]));
+    let rounded = shift(value, shift_digits).and_then(|value| value.rescale(frac_positions));
+    let digits = rounded.map_or(String::new(), |value| {
+        format!(
+            "{:0>width$}",
+            value.value().unsigned_abs(),
+            width = frac_positions + 1
+        )
+    });
+    let (integral, fraction) = digits.split_at(digits.len() - frac_positions.min(digits.len()));
+    // Zero is printed as `.5`, unless there is no fraction
+    let integral = if integral == "0" && point.is_some() {
+        ""
+    } else {
+        integral
+    };
+    let overflow = rounded.is_none() || integral.len() > int_positions;
+
+    // Trailing zeros of `9` positions of the fraction are suppressed by FM
+    let frac_keep = if fill {
+        let last_zero = tokens[point.unwrap_or(tokens.len())..]
+            .iter()
+            .filter(|token| is_digit(token))
+            .enumerate()
+            .filter(|(_, token)| **token == NumberToken::Digit(true))
+            .map(|(i, _)| i + 1)
+            .last()
+            .unwrap_or(0);
+        fraction.trim_end_matches('0').len().max(last_zero)
+    } else {
+        frac_positions
+    };
+
+    let lead = int_positions - integral.len().min(int_positions);
+    let integral = integral.chars().collect::<Vec<_>>();
+    let fraction = fraction.chars().collect::<Vec<_>>();
+    let has_sign = tokens
+        .iter()
+        .any(|token| matches!(token, NumberToken::Sign(_)));
+
+    let mut out = String::new();
+    let mut anchored = if has_sign {
+        None
+    } else {
+        Some(if negative { "-" } else { " " })
+    };
+    let mut started = false;
+    let mut after_point = false;
+    let (mut int_index, mut frac_index) = (0, 0);
+    for token in tokens.iter() {
+        match token {
+            NumberToken::Digit(zero) if !after_point => {
+                if overflow {
+                    out.push('#');
+                } else if int_index >= lead {
+                    out.push(integral[int_index - lead]);
+                    started = true;
+                } else if *zero || started {
+                    out.push('0');
+                    started = true;
+                } else {
+                    out.push(' ');
+                }
+                int_index += 1;
+            }
+            NumberToken::Digit(_) => {
+                if frac_index < frac_keep {
+                    out.push(if overflow { '#' } else { fraction[frac_index] });
+                }
+                frac_index += 1;
+            }
+            NumberToken::Point => {
+                after_point = true;
+                out.push('.');
+            }
+            NumberToken::Group => out.push(if started { ',' } else { ' ' }),
+            NumberToken::Sign("MI") => out.push(if negative { '-' } else { ' ' }),
+            NumberToken::Sign("PL") => out.push(if negative { ' ' } else { '+' }),
+            NumberToken::Sign("SG") => out.push(if negative { '-' } else { '+' }),
+            NumberToken::Sign("PR") => {
+                anchored = Some(if negative { "<" } else { " " });
+                out.push(if negative { '>' } else { ' ' });
+            }
+            // S is anchored to the number, before or after it
+            NumberToken::Sign(_) => {
+                let sign = if negative { "-" } else { "+" };
+                if int_index == 0 {
+                    anchored = Some(sign);
+                } else {
+                    out.push_str(sign);
+                }
+            }
+            NumberToken::Currency => out.push(' '),
+            NumberToken::Ordinal(case) if !overflow => {
+                let number = integral.iter().collect::<String>().parse().unwrap_or(0);
+                out.push_str(&with_case(ordinal_suffix(number), *case));
+            }
+            NumberToken::Literal(c) => out.push(*c),
+            _ => {}
+        }
+    }
+
+    if let Some(sign) = anchored {
+        let position = out.find(|c| c != ' ').unwrap_or(out.len());
+        out.insert_str(position, sign);
+    }
+
+    if fill {
+        out.trim().to_string()
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        let value =
+            NaiveDateTime::parse_from_str("2022-03-01 15:04:05.250", "%Y-%m-%d %H:%M:%S%.f")
+                .unwrap();
+        let format = |template: &str| format_timestamp(&value, None, template);
+
+        assert_eq!(format("YYYY-MM"), "2022-03");
+        assert_eq!(
+            format("YYYY-MM-DD HH24:MI:SS.MS"),
+            "2022-03-01 15:04:05.250"
+        );
+        assert_eq!(format("HH12:MI am"), "03:04 pm");
+        assert_eq!(format("Month DD, YYYY"), "March     01, 2022");
+        assert_eq!(format("FMMonth FMDDth, Dy"), "March 1st, Tue");
+        assert_eq!(format("DAY MON Q IW DDD"), "TUESDAY   MAR 1 09 060");
+        assert_eq!(format("\"Quarter\" Q, rm"), "Quarter 1, iii ");
+        assert_eq!(format("Y,YYY CC J"), "2,022 21 2459640");
+
+        let zone = FormatZone {
+            abbreviation: "IST".to_string(),
+            offset: 19800,
+        };
+        assert_eq!(
+            format_timestamp(&value, Some(&zone), "HH24:MI TZ OF"),
+            "15:04 IST +05:30"
+        );
+    }
+
+    #[test]
+    fn test_format_number() {
+        let format = |value: &str, template: &str| {
+            format_number(&DecimalValue::parse(value).unwrap(), template)
+        };
+
+        assert_eq!(format("1234567", "FM999,999,999"), "1,234,567");
+        assert_eq!(format("1234", "999,999"), "   1,234");
+        assert_eq!(format("-12", "9999"), "  -12");
+        assert_eq!(format("-12", "0000"), "-0012");
+        assert_eq!(format("123.456", "999.99"), " 123.46");
+        assert_eq!(format("0.5", "9.99"), "  .50");
+        assert_eq!(format("0", "999"), "   0");
+        assert_eq!(format("1.5", "FM9.99"), "1.5");
+        assert_eq!(format("12345", "999"), " ###");
+        assert_eq!(format("-485", "999PR"), "<485>");
+        assert_eq!(format("12", "S999"), " +12");
+        assert_eq!(format("-12", "999MI"), " 12-");
+        assert_eq!(format("12.4", "99V9"), " 124");
+        assert_eq!(format("1234.5", "9.99EEEE"), " 1.23e+03");
+        assert_eq!(format("2022", "FMRN"), "MMXXII");
+        assert_eq!(format("3", "FM9th"), "3rd");
+    }
+}
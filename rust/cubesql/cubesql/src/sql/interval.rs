@@ -0,0 +1,343 @@
+use std::convert::TryFrom;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use sqlparser::ast;
+
+use crate::{sql::dataframe::IntervalValue, CubeError};
+
+const MICROS_PER_SECOND: f64 = 1_000_000.0;
+const MICROS_PER_DAY: f64 = 86_400.0 * MICROS_PER_SECOND;
+/// Fractions of months are converted into days as in PostgreSQL
+const DAYS_PER_MONTH: f64 = 30.0;
+
+/// Parts of an interval, fractions of months and days are spread over the smaller parts
+#[derive(Default)]
+struct IntervalParts {
+    months: f64,
+    days: f64,
+    microseconds: f64,
+}
+
+impl IntervalParts {
+    fn add(&mut self, value: f64, unit: &str) -> Option<()> {
+        match unit {
+            "millennium" | "millennia" | "millenniums" | "mil" | "mils" => {
+                self.months += value * 12_000.0
+            }
+            "century" | "centuries" | "c" => self.months += value * 1200.0,
+            "decade" | "decades" | "dec" | "decs" => self.months += value * 120.0,
+            "year" | "years" | "y" | "yr" | "yrs" => self.months += value * 12.0,
+            "month" | "months" | "mon" | "mons" => self.months += value,
+            "week" | "weeks" | "w" => self.days += value * 7.0,
+            "day" | "days" | "d" => self.days += value,
+            "hour" | "hours" | "h" | "hr" | "hrs" => {
+                self.microseconds += value * 3600.0 * MICROS_PER_SECOND
+            }
+            "minute" | "minutes" | "m" | "min" | "mins" => {
+                self.microseconds += value * 60.0 * MICROS_PER_SECOND
+            }
+            "second" | "seconds" | "s" | "sec" | "secs" => {
+                self.microseconds += value * MICROS_PER_SECOND
+            }
+            "millisecond" | "milliseconds" | "ms" | "msec" | "msecs" => {
+                self.microseconds += value * 1000.0
+            }
+            "microsecond" | "microseconds" | "us" | "usec" | "usecs" => self.microseconds += value,
+            _ => return None,
+        };
+
+        Some(())
+    }
+
+    /// `[-]HH:MM[:SS[.f]]`
+    fn add_time(&mut self, text: &str) -> Option<()> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let parts = text.split(':').collect::<Vec<_>>();
+        if parts.len() < 2 || parts.len() > 3 {
+            return None;
+        }
+
+        let hours = parts[0].parse::<u32>().ok()? as f64;
+        let minutes = parts[1].parse::<u32>().ok()? as f64;
+        let seconds = match parts.get(2) {
+            Some(seconds) if !seconds.starts_with(|c: char| c == '-' || c == '+') => {
+                seconds.parse::<f64>().ok()?
+            }
+            Some(_) => return None,
+            None => 0.0,
+        };
+
+        let micros = ((hours * 60.0 + minutes) * 60.0 + seconds) * MICROS_PER_SECOND;
+        self.microseconds += if negative { -micros } else { micros };
+
+        Some(())
+    }
+
+    fn into_value(self, negate: bool) -> Option<IntervalValue> {
+        let sign = if negate { -1.0 } else { 1.0 };
+        let months = self.months.trunc();
+        let days = self.days + self.months.fract() * DAYS_PER_MONTH;
+        let microseconds = (self.microseconds + days.fract() * MICROS_PER_DAY).round();
+
+        if !months.is_finite() || !days.is_finite() || !microseconds.is_finite() {
+            return None;
+        }
+
+        Some(IntervalValue::new(
+            i32::try_from((months * sign) as i64).ok()?,
+            i32::try_from((days.trunc() * sign) as i64).ok()?,
+            (microseconds * sign) as i64,
+        ))
+    }
+}
+
+/// Parses the text of an interval, as PostgreSQL reads it: `1 year 2 mons`, `-3 days 04:05:06.5`,
+/// `@ 1 hour ago`. Units can be abbreviated and plural, a number without a unit is in seconds.
+pub fn parse_interval(text: &str) -> Option<IntervalValue> {
+    let text = text.trim().to_lowercase();
+    let text = text.strip_prefix('@').unwrap_or(&text);
+    let mut tokens = text.split_whitespace().collect::<Vec<_>>();
+    let ago = tokens.last() == Some(&"ago");
+    if ago {
+        tokens.pop();
+    }
+    if tokens.is_empty() {
+        return None;
+    }
+
+    let mut parts = IntervalParts::default();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        if token.contains(':') {
+            parts.add_time(token)?;
+            continue;
+        }
+
+        // The unit can be attached to the number (`30days`)
+        let unit_start = token
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or_else(|| token.len());
+        let value = token[..unit_start].parse::<f64>().ok()?;
+        let unit = if unit_start < token.len() {
+            &token[unit_start..]
+        } else {
+            match tokens.peek() {
+                Some(unit) if unit.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    tokens.next().unwrap()
+                }
+                _ => "second",
+            }
+        };
+
+        parts.add(value, unit)?;
+    }
+
+    parts.into_value(ago)
+}
+
+fn invalid_interval(text: &str) -> CubeError {
+    CubeError::user(format!(
+        "invalid input syntax for type interval: \"{}\"",
+        text
+    ))
+}
+
+/// Same as `parse_interval`, with the error of PostgreSQL for invalid text
+pub fn parse_interval_text(text: &str) -> Result<IntervalValue, CubeError> {
+    parse_interval(text).ok_or_else(|| invalid_interval(text))
+}
+
+/// Interval of a literal: `INTERVAL '1 day'`, `INTERVAL 1 DAY`, `CAST('1 day' AS INTERVAL)`,
+/// `INTERVAL '1 day'` prefixed by minus. None for other expressions.
+pub fn interval_literal(expr: &ast::Expr) -> Result<Option<IntervalValue>, CubeError> {
+    match expr {
+        ast::Expr::Value(ast::Value::Interval {
+            value,
+            leading_field,
+            ..
+        }) => {
+            let text = match value.as_ref() {
+                ast::Expr::Value(ast::Value::SingleQuotedString(text)) => text.clone(),
+                ast::Expr::Value(ast::Value::Number(n, _)) => n.clone(),
+                ast::Expr::UnaryOp {
+                    op: ast::UnaryOperator::Minus,
+                    expr,
+                } => match expr.as_ref() {
+                    ast::Expr::Value(ast::Value::Number(n, _)) => format!("-{}", n),
+                    _ => return Ok(None),
+                },
+                _ => return Ok(None),
+            };
+
+            match leading_field {
+                Some(field) => parse_interval_text(&format!("{} {}", text, field)).map(Some),
+                None => parse_interval_text(&text).map(Some),
+            }
+        }
+        ast::Expr::TypedString {
+            data_type: ast::DataType::Interval,
+            value,
+        } => parse_interval_text(value).map(Some),
+        ast::Expr::Cast {
+            expr,
+            data_type: ast::DataType::Interval,
+        } => match expr.as_ref() {
+            ast::Expr::Value(ast::Value::SingleQuotedString(text)) => {
+                parse_interval_text(text).map(Some)
+            }
+            _ => Ok(None),
+        },
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Minus,
+            expr,
+        } => Ok(interval_literal(expr)?.map(|interval| interval.negate())),
+        ast::Expr::Nested(expr) => interval_literal(expr),
+        _ => Ok(None),
+    }
+}
+
+fn plural(value: i64, unit: &str) -> String {
+    if value == 1 {
+        format!("{} {}", value, unit)
+    } else {
+        format!("{} {}s", value, unit)
+    }
+}
+
+/// Text of an interval in the `postgres` IntervalStyle: `1 year 2 mons 3 days 04:05:06.5`.
+/// Positive parts after a negative part are written with `+`, as PostgreSQL does.
+pub fn format_interval(value: &IntervalValue) -> String {
+    let mut parts = vec![];
+    let mut negative = false;
+    let mut push = |value: i64, text: String, parts: &mut Vec<String>| {
+        let text = if negative && value > 0 {
+            format!("+{}", text)
+        } else {
+            text
+        };
+        negative = value < 0;
+        parts.push(text);
+    };
+
+    let years = (value.months() / 12) as i64;
+    let months = (value.months() % 12) as i64;
+    if years != 0 {
+        push(years, plural(years, "year"), &mut parts);
+    }
+    if months != 0 {
+        push(months, plural(months, "mon"), &mut parts);
+    }
+    let days = value.days() as i64;
+    if days != 0 {
+        push(days, plural(days, "day"), &mut parts);
+    }
+
+    let micros = value.microseconds();
+    if micros != 0 || parts.is_empty() {
+        let abs = micros.unsigned_abs();
+        let seconds = abs / 1_000_000;
+        let mut time = format!(
+            "{}{:02}:{:02}:{:02}",
+            if micros < 0 { "-" } else { "" },
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        );
+        let fraction = abs % 1_000_000;
+        if fraction != 0 {
+            time.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+        }
+        push(micros, time, &mut parts);
+    }
+
+    parts.join(" ")
+}
+
+/// Adds an interval to a timestamp as PostgreSQL does: months are added to the month of the
+/// date, which is clamped to the end of the month (`2022-01-31 + 1 month` is `2022-02-28`),
+/// then days and microseconds are added
+pub fn add_interval(timestamp: &NaiveDateTime, value: &IntervalValue) -> Option<NaiveDateTime> {
+    let date = timestamp.date();
+    let months = date.year() as i64 * 12 + date.month0() as i64 + value.months() as i64;
+    let year = i32::try_from(months.div_euclid(12)).ok()?;
+    let month = months.rem_euclid(12) as u32 + 1;
+
+    let last_day = (28..=31)
+        .rev()
+        .find(|day| NaiveDate::from_ymd_opt(year, month, *day).is_some())?;
+    let date = NaiveDate::from_ymd_opt(year, month, date.day().min(last_day))?;
+
+    date.and_time(timestamp.time())
+        .checked_add_signed(Duration::days(value.days() as i64))?
+        .checked_add_signed(Duration::microseconds(value.microseconds()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        let parse =
+            |text: &str| parse_interval(text).map(|v| (v.months(), v.days(), v.microseconds()));
+
+        assert_eq!(parse("30 days"), Some((0, 30, 0)));
+        assert_eq!(parse("1 Year 2 mons"), Some((14, 0, 0)));
+        assert_eq!(parse("2 weeks"), Some((0, 14, 0)));
+        assert_eq!(parse("1 hour 30 minutes"), Some((0, 0, 5_400_000_000)));
+        assert_eq!(parse("-3 days 04:05:06.5"), Some((0, -3, 14_706_500_000)));
+        assert_eq!(parse("-01:00"), Some((0, 0, -3_600_000_000)));
+        assert_eq!(parse("@ 1 day ago"), Some((0, -1, 0)));
+        assert_eq!(parse("1.5 months"), Some((1, 15, 0)));
+        assert_eq!(parse("10s"), Some((0, 0, 10_000_000)));
+        assert_eq!(parse("90"), Some((0, 0, 90_000_000)));
+        assert_eq!(
+            parse("1 months 2 days 1500000 microseconds"),
+            Some((1, 2, 1_500_000))
+        );
+        assert_eq!(parse("1 fortnight"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn test_format_interval() {
+        let format = |months: i32, days: i32, micros: i64| {
+            format_interval(&IntervalValue::new(months, days, micros))
+        };
+
+        assert_eq!(format(0, 0, 0), "00:00:00");
+        assert_eq!(
+            format(14, 3, 14_706_500_000),
+            "1 year 2 mons 3 days 04:05:06.5"
+        );
+        assert_eq!(format(0, 1, 0), "1 day");
+        assert_eq!(format(-1, 0, 0), "-1 mons");
+        assert_eq!(format(0, -1, 7_200_000_000), "-1 days +02:00:00");
+        assert_eq!(format(0, 0, -90_000_000), "-00:01:30");
+    }
+
+    #[test]
+    fn test_add_interval() {
+        let add = |timestamp: &str, months: i32, days: i32, micros: i64| {
+            let timestamp = NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S").unwrap();
+            add_interval(&timestamp, &IntervalValue::new(months, days, micros))
+                .unwrap()
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+
+        assert_eq!(add("2022-01-31 10:00:00", 1, 0, 0), "2022-02-28 10:00:00");
+        assert_eq!(
+            add("2022-03-31 00:00:00", -1, -30, 0),
+            "2022-01-29 00:00:00"
+        );
+        assert_eq!(
+            add("2022-03-01 00:00:00", 0, 0, -1_000_000),
+            "2022-02-28 23:59:59"
+        );
+        assert_eq!(add("2020-02-29 00:00:00", 12, 0, 0), "2021-02-28 00:00:00");
+    }
+}
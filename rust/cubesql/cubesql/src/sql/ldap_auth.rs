@@ -0,0 +1,194 @@
+use std::env;
+
+use async_trait::async_trait;
+use ldap3::{dn_escape, ldap_escape, LdapConnAsync, Scope, SearchEntry};
+use log::{debug, error};
+
+use crate::{
+    config::{LdapAuthConfig, LdapGroupToken},
+    sql::{AuthContext, AuthenticateResponse, SqlAuthService},
+    CubeError,
+};
+
+/// Authenticates users by binding to the LDAP server with their credentials.
+/// The access token for Cube (security context) is selected by the groups of the user.
+#[derive(Debug)]
+pub struct SqlAuthLdapImpl {
+    config: LdapAuthConfig,
+}
+
+crate::di_service!(SqlAuthLdapImpl, [SqlAuthService]);
+
+impl SqlAuthLdapImpl {
+    pub fn new(config: LdapAuthConfig) -> Self {
+        Self { config }
+    }
+
+    async fn user_groups(
+        &self,
+        user: &str,
+        password: &str,
+    ) -> Result<Option<Vec<String>>, CubeError> {
+        let (connection, mut ldap) = LdapConnAsync::new(&self.config.url)
+            .await
+            .map_err(ldap_error)?;
+        ldap3::drive!(connection);
+
+        let bind_dn = self.config.bind_dn.replace("{user}", &dn_escape(user));
+        let bind = ldap
+            .simple_bind(&bind_dn, password)
+            .await
+            .map_err(ldap_error)?;
+        if bind.success().is_err() {
+            debug!("[ldap] Bind failed for {}", bind_dn);
+            return Ok(None);
+        }
+
+        // Without the search base, groups are read from the entry of the bound user
+        let (base, scope, filter) = match &self.config.search_base {
+            Some(search_base) => (
+                search_base.clone(),
+                Scope::Subtree,
+                self.config
+                    .search_filter
+                    .replace("{user}", &ldap_escape(user)),
+            ),
+            None => (bind_dn, Scope::Base, "(objectClass=*)".to_string()),
+        };
+        let (entries, _) = ldap
+            .search(
+                &base,
+                scope,
+                &filter,
+                vec![self.config.group_attribute.as_str()],
+            )
+            .await
+            .and_then(|result| result.success())
+            .map_err(ldap_error)?;
+
+        let groups = entries
+            .into_iter()
+            .flat_map(|entry| {
+                SearchEntry::construct(entry)
+                    .attrs
+                    .remove(&self.config.group_attribute)
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        if let Err(e) = ldap.unbind().await {
+            debug!("[ldap] Error during unbind: {}", e);
+        }
+
+        Ok(Some(groups))
+    }
+}
+
+/// The token of the first mapping, which matches one of `groups`, DNs are case-insensitive
+fn group_token<'a>(mappings: &'a [LdapGroupToken], groups: &[String]) -> Option<&'a str> {
+    mappings
+        .iter()
+        .find(|mapping| {
+            groups
+                .iter()
+                .any(|group| group.eq_ignore_ascii_case(&mapping.group))
+        })
+        .map(|mapping| mapping.token.as_str())
+}
+
+fn ldap_error(e: ldap3::LdapError) -> CubeError {
+    CubeError::internal(format!("LDAP error: {}", e))
+}
+
+#[async_trait]
+impl SqlAuthService for SqlAuthLdapImpl {
+    /// LDAP doesn't reveal passwords, only clear text password authentication is possible
+    async fn authenticate(&self, _user: Option<String>) -> Result<AuthenticateResponse, CubeError> {
+        Err(CubeError::user(
+            "LDAP authentication requires a clear text password".to_string(),
+        ))
+    }
+
+    async fn authenticate_password(
+        &self,
+        user: Option<String>,
+        password: String,
+    ) -> Result<Option<AuthContext>, CubeError> {
+        let user = match user {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        // Empty password is an unauthenticated bind, which is successful for any DN
+        if password.is_empty() {
+            return Ok(None);
+        }
+
+        let groups = match self.user_groups(&user, &password).await {
+            Ok(Some(groups)) => groups,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                error!("[ldap] Unable to authenticate {}: {}", user, e);
+                return Err(e);
+            }
+        };
+
+        let access_token = match group_token(&self.config.group_tokens, &groups) {
+            Some(token) => token.to_string(),
+            None => match &self.config.default_token {
+                Some(token) => token.clone(),
+                None => {
+                    debug!("[ldap] No security context for groups of {}", user);
+                    return Ok(None);
+                }
+            },
+        };
+
+        Ok(Some(AuthContext {
+            access_token,
+            base_path: env::var("CUBESQL_CUBE_URL")
+                .ok()
+                .unwrap_or_else(|| panic!("CUBESQL_CUBE_URL is a required ENV variable")),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_token() {
+        let mappings = vec![
+            LdapGroupToken {
+                group: "cn=admins,ou=groups,dc=example,dc=com".to_string(),
+                token: "admin_token".to_string(),
+            },
+            LdapGroupToken {
+                group: "cn=analysts,ou=groups,dc=example,dc=com".to_string(),
+                token: "analyst_token".to_string(),
+            },
+        ];
+
+        assert_eq!(
+            group_token(
+                &mappings,
+                &[
+                    "CN=Analysts,OU=Groups,DC=example,DC=com".to_string(),
+                    "cn=admins,ou=groups,dc=example,dc=com".to_string(),
+                ]
+            ),
+            Some("admin_token")
+        );
+        assert_eq!(
+            group_token(
+                &mappings,
+                &["CN=Analysts,OU=Groups,DC=example,DC=com".to_string()]
+            ),
+            Some("analyst_token")
+        );
+        assert_eq!(
+            group_token(&mappings, &["cn=guests,dc=example,dc=com".to_string()]),
+            None
+        );
+    }
+}
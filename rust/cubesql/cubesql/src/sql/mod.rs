@@ -1,16 +1,23 @@
 pub(crate) mod auth_service;
 pub(crate) mod database_variables;
 pub(crate) mod dataframe;
+pub(crate) mod formatting;
+pub(crate) mod interval;
+pub(crate) mod ldap_auth;
 pub(crate) mod mysql;
 pub(crate) mod postgres;
+pub(crate) mod query_stats;
+pub(crate) mod regexp;
 pub(crate) mod server_manager;
 pub(crate) mod service;
 pub(crate) mod session;
 pub(crate) mod session_manager;
 pub(crate) mod statement;
+pub(crate) mod timezone;
 pub(crate) mod types;
 
 pub use auth_service::{AuthContext, AuthenticateResponse, SqlAuthDefaultImpl, SqlAuthService};
+pub use ldap_auth::SqlAuthLdapImpl;
 pub use mysql::MySqlServer;
 pub use postgres::PostgresServer;
 pub use server_manager::ServerManager;
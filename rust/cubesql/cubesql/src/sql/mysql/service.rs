@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::io;
 
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
 use async_trait::async_trait;
 
@@ -22,12 +22,14 @@ use msql_srv::{
 use tokio::net::TcpListener;
 use tokio::sync::{watch, RwLock};
 
-use crate::compile::convert_sql_to_cube_query;
+use crate::compile::convert_statement_to_cube_query;
 use crate::compile::parser::parse_sql_to_statement;
+use crate::compile::subquery::evaluate_subqueries;
 use crate::config::processing_loop::ProcessingLoop;
 
 use crate::sql::session::DatabaseProtocol;
 use crate::sql::statement::BindValue;
+use crate::sql::statement::Binder;
 use crate::sql::statement::StatementBinder;
 use crate::sql::statement::StatementPrepare;
 use crate::sql::Session;
@@ -36,6 +38,8 @@ use crate::sql::{
     dataframe::{self, batch_to_dataframe},
     AuthContext, ColumnFlags, ColumnType, QueryResponse, StatusFlags,
 };
+use crate::telemetry::metrics::METRICS;
+use crate::telemetry::query_log::{Pushdown, QueryLogRecord, QUERY_LOG};
 use crate::CubeError;
 use msql_srv::ColumnType as MySQLColumnType;
 use sqlparser::ast;
@@ -83,7 +87,13 @@ impl MySqlConnection {
         query: &'a str,
         results: QueryResultWriter<'a, W>,
     ) -> Result<(), io::Error> {
-        match self.execute_query(query).await {
+        let started = Instant::now();
+        let result = self.execute_query(query).await;
+        if QUERY_LOG.is_enabled() {
+            self.log_query(query, started.elapsed(), &result);
+        }
+
+        match result {
             Err(e) => {
                 error!("Error during processing {}: {}", query, e.to_string());
                 results.error(ErrorKind::ER_INTERNAL_ERROR, e.message.as_bytes())?;
@@ -112,11 +122,18 @@ impl MySqlConnection {
                     for (_i, value) in row.values().iter().enumerate() {
                         match value {
                             dataframe::TableValue::String(s) => rw.write_col(s)?,
-                            dataframe::TableValue::Timestamp(s) => rw.write_col(s.to_string())?,
+                            dataframe::TableValue::Timestamp(s)
+                            | dataframe::TableValue::Timestamptz(s) => {
+                                rw.write_col(s.to_string())?
+                            }
                             dataframe::TableValue::Boolean(s) => rw.write_col(s.to_string())?,
                             dataframe::TableValue::Float64(s) => rw.write_col(s)?,
                             dataframe::TableValue::Int64(s) => rw.write_col(s)?,
+                            dataframe::TableValue::Decimal(s) => rw.write_col(s.to_string())?,
                             dataframe::TableValue::Null => rw.write_col(Option::<String>::None)?,
+                            dataframe::TableValue::Interval(_) | dataframe::TableValue::List(_) => {
+                                rw.write_col(value.to_text())?
+                            }
                         }
                     }
 
@@ -131,8 +148,35 @@ impl MySqlConnection {
     }
 
     // This method executes query and return it as DataFrame
+    /// Responses are materialized, so it's not known how the statement was executed
+    fn log_query(
+        &self,
+        query: &str,
+        elapsed: Duration,
+        result: &Result<QueryResponse, CubeError>,
+    ) {
+        let mut record = QueryLogRecord::new(
+            DatabaseProtocol::MySQL.as_str(),
+            self.session.state.connection_id,
+            self.session.state.user(),
+            query.to_string(),
+            elapsed,
+        );
+        match result {
+            Ok(QueryResponse::Ok(_)) => record.pushdown = Some(Pushdown::Meta),
+            Ok(QueryResponse::ResultSet(_, frame)) => {
+                record.rows = frame.get_rows().len() as u64
+            }
+            Err(e) => record.error = Some(e.message.clone()),
+        }
+
+        let min_duration = self.session.server.configuration.slow_query_log_min_duration;
+        QUERY_LOG.log(record, Duration::from_millis(min_duration));
+    }
+
     async fn execute_query<'a>(&'a mut self, query: &'a str) -> Result<QueryResponse, CubeError> {
         let _start = SystemTime::now();
+        METRICS.queries.inc(DatabaseProtocol::MySQL.as_str());
 
         let query = query.replace("SELECT FROM", "SELECT * FROM");
 
@@ -195,10 +239,14 @@ impl MySqlConnection {
             trace!("query was not detected");
 
             let meta = self.session.server.transport
-                .meta(self.auth_context()?)
+                .meta(self.auth_context()?, None)
                 .await?;
 
-            let plan = convert_sql_to_cube_query(&query, meta, self.session.clone())?;
+            let stmt = parse_sql_to_statement(&query, DatabaseProtocol::MySQL)?;
+            let evaluated = evaluate_subqueries(&stmt, meta.clone(), self.session.clone()).await?;
+            let stmt = evaluated.unwrap_or(stmt);
+
+            let plan = convert_statement_to_cube_query(&stmt, meta, self.session.clone())?;
             match plan {
                 crate::compile::QueryPlan::MetaOk(status) => {
                     return Ok(QueryResponse::Ok(status));
@@ -267,7 +315,13 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
             };
 
         let mut stmt_prepare = StatementPrepare::new();
-        let paramaters = stmt_prepare.prepare(&mut statement);
+        let paramaters = match stmt_prepare.prepare(&mut statement) {
+            Ok(p) => p,
+            Err(e) => {
+                info.error(ErrorKind::ER_INTERNAL_ERROR, e.message.as_bytes())?;
+                return Ok(());
+            }
+        };
 
         let mut state = self.statements.write().await;
         if state.statements.len()
@@ -315,6 +369,11 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
         let mut values_to_bind: Vec<BindValue> = vec![];
 
         for p in params_parser.into_iter() {
+            if p.value.is_null() {
+                values_to_bind.push(BindValue::Null);
+                continue;
+            }
+
             let bind_value = match p.coltype {
                 MySQLColumnType::MYSQL_TYPE_TINY => {
                     BindValue::Bool(Into::<u8>::into(p.value) == 0_u8)
@@ -334,6 +393,9 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
                 MySQLColumnType::MYSQL_TYPE_DOUBLE => {
                     BindValue::Float64(Into::<f64>::into(p.value))
                 }
+                MySQLColumnType::MYSQL_TYPE_NEWDECIMAL | MySQLColumnType::MYSQL_TYPE_DECIMAL => {
+                    BindValue::Numeric(Into::<&str>::into(p.value).to_string())
+                }
                 MySQLColumnType::MYSQL_TYPE_VAR_STRING | MySQLColumnType::MYSQL_TYPE_STRING => {
                     BindValue::String(Into::<&str>::into(p.value).to_string())
                 }
@@ -347,7 +409,9 @@ impl<W: io::Write + Send> AsyncMysqlShim<W> for MySqlConnection {
         }
 
         let mut binder = StatementBinder::new(values_to_bind);
-        binder.bind(&mut statement);
+        if let Err(e) = binder.bind(&mut statement) {
+            return results.error(ErrorKind::ER_INTERNAL_ERROR, e.message.as_bytes());
+        }
 
         self.handle_query(statement.to_string().as_str(), results)
             .await
@@ -20,6 +20,9 @@ pub async fn read_message<Reader: AsyncReadExt + Unpin + Send>(
         b'P' => FrontendMessage::Parse(protocol::Parse::deserialize(cursor).await?),
         b'B' => FrontendMessage::Bind(protocol::Bind::deserialize(cursor).await?),
         b'D' => FrontendMessage::Describe(protocol::Describe::deserialize(cursor).await?),
+        b'E' => FrontendMessage::Execute(protocol::Execute::deserialize(cursor).await?),
+        b'C' => FrontendMessage::Close(protocol::Close::deserialize(cursor).await?),
+        b'H' => FrontendMessage::Flush,
         b'p' => {
             FrontendMessage::PasswordMessage(protocol::PasswordMessage::deserialize(cursor).await?)
         }
@@ -34,6 +37,27 @@ pub async fn read_message<Reader: AsyncReadExt + Unpin + Send>(
     })
 }
 
+/// Reads a message, which contents depend on the state of the connection, e.g. 'p' is used
+/// for both PasswordMessage and SASL responses
+pub async fn read_typed_message<Reader: AsyncReadExt + Unpin + Send, Message: Deserialize>(
+    reader: &mut Reader,
+    expected_tag: u8,
+) -> Result<Message, Error> {
+    let message_tag = reader.read_u8().await?;
+    if message_tag != expected_tag {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Unexpected message identifier: {}, expected: {}",
+                message_tag, expected_tag
+            ),
+        ));
+    }
+
+    let cursor = read_contents(reader).await?;
+    Message::deserialize(cursor).await
+}
+
 pub async fn read_contents<Reader: AsyncReadExt + Unpin>(
     reader: &mut Reader,
 ) -> Result<Cursor<Vec<u8>>, Error> {
@@ -0,0 +1,451 @@
+use sqlparser::tokenizer::Token;
+
+use crate::CubeError;
+
+use super::tokens::{token_text, TokenParser};
+
+/// COPY (query) TO STDOUT or COPY table [(columns)] TO STDOUT, which is not supported by
+/// the SQL parser
+#[derive(Debug, PartialEq)]
+pub struct CopyTo {
+    pub query: String,
+    pub options: CopyOptions,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CopyFormat {
+    Text,
+    Csv,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CopyOptions {
+    pub format: CopyFormat,
+    pub delimiter: char,
+    /// Representation of NULL values
+    pub null: String,
+    pub header: bool,
+    /// CSV only
+    pub quote: char,
+    /// CSV only, the character which precedes quotes inside of quoted values
+    pub escape: char,
+}
+
+impl CopyOptions {
+    /// Encodes a row (or the header) with a line terminator, None is NULL
+    pub fn encode_row(&self, values: &[Option<String>]) -> String {
+        let mut line = values
+            .iter()
+            .map(|value| match value {
+                None => self.null.clone(),
+                Some(value) => match self.format {
+                    CopyFormat::Text => self.encode_text(value),
+                    CopyFormat::Csv => self.encode_csv(value),
+                },
+            })
+            .collect::<Vec<_>>()
+            .join(&self.delimiter.to_string());
+        line.push('\n');
+
+        line
+    }
+
+    fn encode_text(&self, value: &str) -> String {
+        let mut result = String::with_capacity(value.len());
+        for ch in value.chars() {
+            match ch {
+                '\\' => result.push_str("\\\\"),
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\t' => result.push_str("\\t"),
+                ch if ch == self.delimiter => {
+                    result.push('\\');
+                    result.push(ch);
+                }
+                ch => result.push(ch),
+            }
+        }
+
+        result
+    }
+
+    fn encode_csv(&self, value: &str) -> String {
+        // Non-NULL values which look like NULL are quoted to be distinguishable
+        let needs_quotes = value == self.null
+            || value.contains(|ch: char| {
+                ch == self.delimiter || ch == self.quote || ch == '\n' || ch == '\r'
+            });
+        if !needs_quotes {
+            return value.to_string();
+        }
+
+        let mut result = String::with_capacity(value.len() + 2);
+        result.push(self.quote);
+        for ch in value.chars() {
+            if ch == self.quote || ch == self.escape {
+                result.push(self.escape);
+            }
+            result.push(ch);
+        }
+        result.push(self.quote);
+
+        result
+    }
+}
+
+/// Options as they are specified in the statement, defaults depend on the format
+#[derive(Default)]
+struct CopyOptionsBuilder {
+    format: Option<CopyFormat>,
+    delimiter: Option<char>,
+    null: Option<String>,
+    header: bool,
+    quote: Option<char>,
+    escape: Option<char>,
+}
+
+impl CopyOptionsBuilder {
+    fn build(self) -> Result<CopyOptions, CubeError> {
+        let format = self.format.unwrap_or(CopyFormat::Text);
+        let csv_only =
+            |option: &str| CubeError::user(format!("COPY {} available only in CSV mode", option));
+
+        let options = match format {
+            CopyFormat::Text => {
+                if self.header {
+                    return Err(csv_only("HEADER"));
+                }
+                if self.quote.is_some() {
+                    return Err(csv_only("quote"));
+                }
+                if self.escape.is_some() {
+                    return Err(csv_only("escape"));
+                }
+
+                CopyOptions {
+                    format,
+                    delimiter: self.delimiter.unwrap_or('\t'),
+                    null: self.null.unwrap_or_else(|| "\\N".to_string()),
+                    header: false,
+                    quote: '"',
+                    escape: '"',
+                }
+            }
+            CopyFormat::Csv => {
+                let quote = self.quote.unwrap_or('"');
+
+                CopyOptions {
+                    format,
+                    delimiter: self.delimiter.unwrap_or(','),
+                    null: self.null.unwrap_or_default(),
+                    header: self.header,
+                    quote,
+                    escape: self.escape.unwrap_or(quote),
+                }
+            }
+        };
+
+        if options.delimiter == '\n' || options.delimiter == '\r' {
+            return Err(CubeError::user(
+                "COPY delimiter cannot be newline or carriage return".to_string(),
+            ));
+        }
+        if options.format == CopyFormat::Csv && options.delimiter == options.quote {
+            return Err(CubeError::user(
+                "COPY delimiter and quote must be different".to_string(),
+            ));
+        }
+
+        Ok(options)
+    }
+}
+
+/// Parses `query` as COPY ... TO STDOUT, Ok(None) is returned for other statements
+pub fn parse_copy_command(query: &str) -> Result<Option<CopyTo>, CubeError> {
+    let mut parser = TokenParser::new(query)?;
+    if !parser.parse_keyword("COPY") {
+        return Ok(None);
+    }
+
+    let query = if parser.parse_token(&Token::LParen) {
+        parse_parenthesized(&mut parser)?
+    } else {
+        let mut table = String::new();
+        while let Some(token) = parser.peek_token() {
+            match token {
+                Token::Word(word)
+                    if word.quote_style.is_some()
+                        || !["TO", "FROM"].contains(&word.value.to_uppercase().as_str()) =>
+                {
+                    table.push_str(&token_text(token));
+                }
+                Token::Period => table.push('.'),
+                _ => break,
+            }
+            parser.next_token();
+        }
+        if table.is_empty() {
+            return Err(parser.expected("table name or query"));
+        }
+
+        let columns = if parser.parse_token(&Token::LParen) {
+            parse_parenthesized(&mut parser)?
+        } else {
+            "*".to_string()
+        };
+
+        format!("SELECT {} FROM {}", columns, table)
+    };
+
+    if parser.parse_keyword("FROM") {
+        return Err(CubeError::user("COPY FROM is not supported".to_string()));
+    }
+    parser.expect_keyword("TO")?;
+    if !parser.parse_keyword("STDOUT") {
+        return Err(CubeError::user(
+            "COPY to a file or a program is not supported, use COPY TO STDOUT".to_string(),
+        ));
+    }
+
+    let mut options = CopyOptionsBuilder::default();
+    parser.parse_keyword("WITH");
+    if parser.parse_token(&Token::LParen) {
+        parse_options(&mut parser, &mut options)?;
+    } else {
+        parse_legacy_options(&mut parser, &mut options)?;
+    }
+    parser.expect_end()?;
+
+    Ok(Some(CopyTo {
+        query,
+        options: options.build()?,
+    }))
+}
+
+/// Text of tokens until the matching right parenthesis, which is consumed
+fn parse_parenthesized(parser: &mut TokenParser) -> Result<String, CubeError> {
+    let mut text = String::new();
+    let mut depth = 1;
+    while let Some(token) = parser.next_raw_token() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(text.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+
+        text.push_str(&token_text(&token));
+    }
+
+    Err(parser.expected(")"))
+}
+
+/// ( option [value] [, ...] ), the left parenthesis is already consumed
+fn parse_options(
+    parser: &mut TokenParser,
+    options: &mut CopyOptionsBuilder,
+) -> Result<(), CubeError> {
+    loop {
+        let option = parser
+            .next_keyword()
+            .ok_or_else(|| parser.expected("COPY option"))?;
+        match option.as_str() {
+            "FORMAT" => {
+                options.format = Some(match parser.parse_name()?.as_str() {
+                    "text" => CopyFormat::Text,
+                    "csv" => CopyFormat::Csv,
+                    format => return Err(unsupported_format(format)),
+                })
+            }
+            "DELIMITER" => options.delimiter = Some(parse_char(parser, "delimiter")?),
+            "NULL" => options.null = Some(parse_string(parser)?),
+            "HEADER" => options.header = parse_bool(parser)?,
+            "QUOTE" => options.quote = Some(parse_char(parser, "quote")?),
+            "ESCAPE" => options.escape = Some(parse_char(parser, "escape")?),
+            option => {
+                return Err(CubeError::user(format!(
+                    "COPY option {} is not supported",
+                    option
+                )))
+            }
+        }
+
+        if parser.parse_token(&Token::RParen) {
+            return Ok(());
+        }
+        if !parser.parse_token(&Token::Comma) {
+            return Err(parser.expected(", or )"));
+        }
+    }
+}
+
+/// Options in the syntax before PostgreSQL 9.0: [BINARY] [DELIMITER [AS] 'c'] [NULL [AS] 's'] [CSV [HEADER] ...]
+fn parse_legacy_options(
+    parser: &mut TokenParser,
+    options: &mut CopyOptionsBuilder,
+) -> Result<(), CubeError> {
+    while let Some(option) = parser.next_keyword() {
+        match option.as_str() {
+            "BINARY" => return Err(unsupported_format("binary")),
+            "CSV" => options.format = Some(CopyFormat::Csv),
+            "HEADER" => options.header = true,
+            "DELIMITER" => {
+                parser.parse_keyword("AS");
+                options.delimiter = Some(parse_char(parser, "delimiter")?);
+            }
+            "NULL" => {
+                parser.parse_keyword("AS");
+                options.null = Some(parse_string(parser)?);
+            }
+            "QUOTE" => {
+                parser.parse_keyword("AS");
+                options.quote = Some(parse_char(parser, "quote")?);
+            }
+            "ESCAPE" => {
+                parser.parse_keyword("AS");
+                options.escape = Some(parse_char(parser, "escape")?);
+            }
+            option => {
+                return Err(CubeError::user(format!(
+                    "COPY option {} is not supported",
+                    option
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_string(parser: &mut TokenParser) -> Result<String, CubeError> {
+    match parser.next_token() {
+        Some(Token::SingleQuotedString(value)) => Ok(value),
+        _ => Err(parser.expected("string literal")),
+    }
+}
+
+fn parse_char(parser: &mut TokenParser, option: &str) -> Result<char, CubeError> {
+    let value = parse_string(parser)?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) if ch.is_ascii() => Ok(ch),
+        _ => Err(CubeError::user(format!(
+            "COPY {} must be a single one-byte character",
+            option
+        ))),
+    }
+}
+
+/// HEADER without a value is true
+fn parse_bool(parser: &mut TokenParser) -> Result<bool, CubeError> {
+    let value = match parser.peek_token() {
+        Some(Token::Word(word)) => word.value.to_lowercase(),
+        Some(Token::Number(number, _)) => number.clone(),
+        Some(Token::SingleQuotedString(value)) => value.to_lowercase(),
+        _ => return Ok(true),
+    };
+
+    let result = match value.as_str() {
+        "true" | "on" | "1" => true,
+        "false" | "off" | "0" => false,
+        _ => {
+            return Err(CubeError::user(format!(
+                "HEADER requires a Boolean value, got {}",
+                value
+            )))
+        }
+    };
+    parser.next_token();
+
+    Ok(result)
+}
+
+fn unsupported_format(format: &str) -> CubeError {
+    CubeError::user(format!("COPY format \"{}\" is not supported", format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> CopyTo {
+        parse_copy_command(query).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_copy() {
+        let copy = parse("COPY (SELECT a, count(*) FROM t GROUP BY 1) TO STDOUT WITH CSV HEADER");
+        assert_eq!(copy.query, "SELECT a, count(*) FROM t GROUP BY 1");
+        assert_eq!(copy.options.format, CopyFormat::Csv);
+        assert_eq!(copy.options.delimiter, ',');
+        assert_eq!(copy.options.null, "");
+        assert!(copy.options.header);
+
+        let copy = parse(
+            "copy \"Orders\" (id, status) to stdout (format csv, delimiter ';', header false)",
+        );
+        assert_eq!(copy.query, "SELECT id, status FROM \"Orders\"");
+        assert_eq!(copy.options.delimiter, ';');
+        assert!(!copy.options.header);
+
+        let copy = parse("COPY public.orders TO STDOUT;");
+        assert_eq!(copy.query, "SELECT * FROM public.orders");
+        assert_eq!(copy.options.format, CopyFormat::Text);
+        assert_eq!(copy.options.delimiter, '\t');
+        assert_eq!(copy.options.null, "\\N");
+
+        assert_eq!(parse_copy_command("SELECT 1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_copy_errors() {
+        let cases = [
+            ("COPY t FROM STDIN", "COPY FROM is not supported"),
+            (
+                "COPY t TO '/tmp/file'",
+                "COPY to a file or a program is not supported, use COPY TO STDOUT",
+            ),
+            (
+                "COPY t TO STDOUT (FORMAT binary)",
+                "COPY format \"binary\" is not supported",
+            ),
+            (
+                "COPY t TO STDOUT HEADER",
+                "COPY HEADER available only in CSV mode",
+            ),
+            (
+                "COPY t TO STDOUT (FORMAT csv, DELIMITER ',,')",
+                "COPY delimiter must be a single one-byte character",
+            ),
+        ];
+
+        for (query, message) in cases {
+            assert_eq!(parse_copy_command(query).unwrap_err().message, message);
+        }
+    }
+
+    #[test]
+    fn test_encode_row() {
+        let text = parse("COPY t TO STDOUT").options;
+        assert_eq!(
+            text.encode_row(&[Some("a\tb\\c\nd".to_string()), None, Some("".to_string())]),
+            "a\\tb\\\\c\\nd\t\\N\t\n"
+        );
+
+        let csv = parse("COPY t TO STDOUT CSV").options;
+        assert_eq!(
+            csv.encode_row(&[
+                Some("a,b".to_string()),
+                Some("say \"hi\"".to_string()),
+                None,
+                Some("".to_string()),
+                Some("plain".to_string())
+            ]),
+            "\"a,b\",\"say \"\"hi\"\"\",,\"\",plain\n"
+        );
+    }
+}
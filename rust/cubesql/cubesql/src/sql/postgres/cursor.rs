@@ -0,0 +1,271 @@
+use std::{ops::Range, sync::Arc};
+
+use sqlparser::tokenizer::Token;
+
+use crate::{sql::dataframe::DataFrame, CubeError};
+
+use super::tokens::TokenParser;
+
+/// Commands for server-side cursors, which are not supported by the SQL parser
+#[derive(Debug, PartialEq)]
+pub enum CursorCommand {
+    /// DECLARE name CURSOR FOR query
+    Declare { name: String, query: String },
+    /// FETCH [direction] [FROM | IN] name
+    Fetch {
+        name: String,
+        direction: FetchDirection,
+    },
+    /// MOVE [direction] [FROM | IN] name, it's FETCH without rows
+    Move {
+        name: String,
+        direction: FetchDirection,
+    },
+    /// CLOSE name, None is CLOSE ALL
+    Close { name: Option<String> },
+}
+
+/// Cursors are NO SCROLL, only forward directions are supported
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FetchDirection {
+    /// Next `n` rows (NEXT, FORWARD n, n)
+    Forward(usize),
+    /// All remaining rows (ALL, FORWARD ALL)
+    ForwardAll,
+}
+
+/// Server-side cursor over a materialized result
+#[derive(Debug)]
+pub struct Cursor {
+    frame: Arc<DataFrame>,
+    /// Number of rows which were already fetched or skipped
+    position: usize,
+}
+
+impl Cursor {
+    pub fn new(frame: Arc<DataFrame>) -> Self {
+        Self { frame, position: 0 }
+    }
+
+    pub fn frame(&self) -> Arc<DataFrame> {
+        self.frame.clone()
+    }
+
+    /// Range of rows for FETCH (MOVE) in `direction`, the position is moved to the end of the range
+    pub fn advance(&mut self, direction: FetchDirection) -> Range<usize> {
+        let total = self.frame.len();
+        let start = self.position.min(total);
+        let end = match direction {
+            FetchDirection::Forward(count) => total.min(start + count),
+            FetchDirection::ForwardAll => total,
+        };
+        self.position = end;
+
+        start..end
+    }
+}
+
+/// Parses `query` as a cursor command, Ok(None) is returned for other statements
+pub fn parse_cursor_command(query: &str) -> Result<Option<CursorCommand>, CubeError> {
+    let mut parser = TokenParser::new(query)?;
+
+    let command = match parser.next_keyword().as_deref() {
+        Some("DECLARE") => parse_declare(&mut parser)?,
+        Some("FETCH") => {
+            let (name, direction) = parse_fetch(&mut parser)?;
+            CursorCommand::Fetch { name, direction }
+        }
+        Some("MOVE") => {
+            let (name, direction) = parse_fetch(&mut parser)?;
+            CursorCommand::Move { name, direction }
+        }
+        Some("CLOSE") => {
+            let name = if parser.parse_keyword("ALL") {
+                None
+            } else {
+                Some(parser.parse_name()?)
+            };
+            parser.expect_end()?;
+
+            CursorCommand::Close { name }
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(command))
+}
+
+fn parse_declare(parser: &mut TokenParser) -> Result<CursorCommand, CubeError> {
+    let name = parser.parse_name()?;
+
+    loop {
+        match parser.peek_keyword().as_deref() {
+            Some("BINARY") => {
+                return Err(CubeError::user(
+                    "Binary cursors are not supported".to_string(),
+                ))
+            }
+            // Results are materialized, sensitivity and scrolling options don't change them
+            Some("ASENSITIVE") | Some("INSENSITIVE") | Some("NO") | Some("SCROLL") => {
+                parser.next_keyword();
+            }
+            _ => break,
+        }
+    }
+    parser.expect_keyword("CURSOR")?;
+
+    // Cursors are kept until CLOSE or the end of the session, WITH HOLD is the only mode
+    if parser.parse_keyword("WITH") || parser.parse_keyword("WITHOUT") {
+        parser.expect_keyword("HOLD")?;
+    }
+    parser.expect_keyword("FOR")?;
+
+    let query = parser.rest();
+    if query.is_empty() {
+        return Err(parser.expected("query"));
+    }
+
+    Ok(CursorCommand::Declare { name, query })
+}
+
+fn parse_fetch(parser: &mut TokenParser) -> Result<(String, FetchDirection), CubeError> {
+    let direction = match parser.peek_keyword().as_deref() {
+        Some("NEXT") => {
+            parser.next_keyword();
+            FetchDirection::Forward(1)
+        }
+        Some("ALL") => {
+            parser.next_keyword();
+            FetchDirection::ForwardAll
+        }
+        Some("FORWARD") => {
+            parser.next_keyword();
+            if parser.parse_keyword("ALL") {
+                FetchDirection::ForwardAll
+            } else {
+                FetchDirection::Forward(parse_count(parser)?.unwrap_or(1))
+            }
+        }
+        Some("PRIOR") | Some("FIRST") | Some("LAST") | Some("ABSOLUTE") | Some("RELATIVE")
+        | Some("BACKWARD") => return Err(backward()),
+        _ => FetchDirection::Forward(parse_count(parser)?.unwrap_or(1)),
+    };
+
+    if !parser.parse_keyword("FROM") {
+        parser.parse_keyword("IN");
+    }
+    let name = parser.parse_name()?;
+    parser.expect_end()?;
+
+    Ok((name, direction))
+}
+
+fn parse_count(parser: &mut TokenParser) -> Result<Option<usize>, CubeError> {
+    match parser.peek_token() {
+        Some(Token::Number(number, _)) => {
+            let count = number.parse::<usize>().map_err(|_| {
+                CubeError::user(format!("Unable to parse: invalid row count {}", number))
+            })?;
+            parser.next_token();
+
+            Ok(Some(count))
+        }
+        Some(Token::Minus) => Err(backward()),
+        _ => Ok(None),
+    }
+}
+
+fn backward() -> CubeError {
+    CubeError::user("cursor can only scan forward".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> CursorCommand {
+        parse_cursor_command(query).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_declare() {
+        assert_eq!(
+            parse("DECLARE c CURSOR FOR SELECT * FROM KibanaSampleDataEcommerce;"),
+            CursorCommand::Declare {
+                name: "c".to_string(),
+                query: "SELECT * FROM KibanaSampleDataEcommerce".to_string(),
+            }
+        );
+        assert_eq!(
+            parse("declare \"Cur\" no scroll cursor with hold for select 1"),
+            CursorCommand::Declare {
+                name: "Cur".to_string(),
+                query: "select 1".to_string(),
+            }
+        );
+
+        let err = parse_cursor_command("DECLARE c BINARY CURSOR FOR SELECT 1").unwrap_err();
+        assert_eq!(err.message, "Binary cursors are not supported");
+    }
+
+    #[test]
+    fn test_parse_fetch_move_close() {
+        let fetch = |direction| CursorCommand::Fetch {
+            name: "c".to_string(),
+            direction,
+        };
+
+        assert_eq!(
+            parse("FETCH 1000 FROM c"),
+            fetch(FetchDirection::Forward(1000))
+        );
+        assert_eq!(parse("FETCH c"), fetch(FetchDirection::Forward(1)));
+        assert_eq!(parse("FETCH NEXT IN c"), fetch(FetchDirection::Forward(1)));
+        assert_eq!(
+            parse("fetch forward all from C"),
+            fetch(FetchDirection::ForwardAll)
+        );
+        assert_eq!(
+            parse("MOVE FORWARD 5 IN c;"),
+            CursorCommand::Move {
+                name: "c".to_string(),
+                direction: FetchDirection::Forward(5),
+            }
+        );
+        assert_eq!(
+            parse("CLOSE c"),
+            CursorCommand::Close {
+                name: Some("c".to_string())
+            }
+        );
+        assert_eq!(parse("CLOSE ALL"), CursorCommand::Close { name: None });
+
+        for query in [
+            "FETCH BACKWARD 1 FROM c",
+            "FETCH PRIOR FROM c",
+            "FETCH -1 FROM c",
+        ] {
+            let err = parse_cursor_command(query).unwrap_err();
+            assert_eq!(err.message, "cursor can only scan forward");
+        }
+
+        assert!(parse_cursor_command("FETCH 1 FROM c d").is_err());
+        assert_eq!(parse_cursor_command("SELECT 1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_cursor_advance() {
+        let mut cursor = Cursor::new(Arc::new(DataFrame::new(
+            vec![],
+            vec![
+                crate::sql::dataframe::Row::new(vec![]),
+                crate::sql::dataframe::Row::new(vec![]),
+                crate::sql::dataframe::Row::new(vec![]),
+            ],
+        )));
+
+        assert_eq!(cursor.advance(FetchDirection::Forward(2)), 0..2);
+        assert_eq!(cursor.advance(FetchDirection::Forward(2)), 2..3);
+        assert_eq!(cursor.advance(FetchDirection::ForwardAll), 3..3);
+    }
+}
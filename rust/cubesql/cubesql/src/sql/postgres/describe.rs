@@ -0,0 +1,211 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use datafusion::{
+    datasource::TableProvider,
+    logical_plan::{DFField, DFSchema},
+};
+use sqlparser::ast;
+
+use crate::{
+    compile::{engine::provider::CubeTableProvider, MetaContext},
+    sql::statement::{
+        parameter_metadata, referenced_tables, BindValue, Binder, PlaceholderClause,
+        StatementBinder,
+    },
+    CubeError,
+};
+
+use super::pg_type::PgTypeId;
+
+/// Columns of cubes, which are referenced by the statement, qualified by their aliases.
+/// Names are lower-cased, as unquoted identifiers are folded by the type inference.
+pub fn statement_schema(stmt: &ast::Statement, meta: &MetaContext) -> Result<DFSchema, CubeError> {
+    let mut fields: Vec<DFField> = vec![];
+
+    for table in referenced_tables(stmt)? {
+        let cube = match meta.find_cube_with_name(table.name.clone()) {
+            Some(cube) => cube,
+            // System tables and subquery aliases don't have types for the inference
+            None => continue,
+        };
+
+        for field in CubeTableProvider::new(cube).schema().fields() {
+            let name = field.name().to_lowercase();
+            // The same cube can be used twice with the same qualifier (UNION)
+            if fields
+                .iter()
+                .any(|f| f.qualifier() == Some(&table.qualifier) && f.name() == &name)
+            {
+                continue;
+            }
+
+            fields.push(DFField::new(
+                Some(&table.qualifier),
+                &name,
+                field.data_type().clone(),
+                field.is_nullable(),
+            ));
+        }
+    }
+
+    Ok(DFSchema::new(fields)?)
+}
+
+/// OIDs for ParameterDescription: declared types of Parse are kept, unspecified (0) types
+/// are inferred from the usage of parameters, 0 is left when the type is unknown
+pub fn describe_parameter_types(
+    stmt: &ast::Statement,
+    declared: &[u32],
+    meta: Option<&MetaContext>,
+) -> Result<Vec<u32>, CubeError> {
+    let schema = match meta {
+        Some(meta) => Some(statement_schema(stmt, meta)?),
+        None => None,
+    };
+    let inferred = parameter_metadata(stmt, schema.as_ref())?;
+
+    Ok((0..declared.len().max(inferred.len()))
+        .map(|i| match declared.get(i) {
+            Some(oid) if *oid != 0 => *oid,
+            _ => inferred.get(i).map(|param| param.oid).unwrap_or(0),
+        })
+        .collect())
+}
+
+/// Binds placeholders to sample values of `param_types` (described OIDs), so the statement
+/// can be planned before Bind to describe its result. Parameters of unknown types are text,
+/// the same as in PostgreSQL.
+pub fn bind_sample_values(
+    stmt: &ast::Statement,
+    param_types: &[u32],
+) -> Result<ast::Statement, CubeError> {
+    let values = parameter_metadata(stmt, None)?
+        .iter()
+        .enumerate()
+        .map(|(i, param)| match param.clause {
+            PlaceholderClause::Limit => BindValue::Int64(1),
+            PlaceholderClause::Offset => BindValue::Int64(0),
+            _ => sample_value(param_types.get(i).cloned().and_then(PgTypeId::from_oid)),
+        })
+        .collect();
+
+    let mut stmt = stmt.clone();
+    StatementBinder::new(values).bind(&mut stmt)?;
+
+    Ok(stmt)
+}
+
+fn sample_value(typ: Option<PgTypeId>) -> BindValue {
+    let epoch = NaiveDate::from_ymd(2000, 1, 1);
+
+    match typ {
+        Some(typ) if typ.element().is_some() => BindValue::Array(vec![sample_value(typ.element())]),
+        Some(PgTypeId::Bool) => BindValue::Bool(false),
+        Some(PgTypeId::Int2) | Some(PgTypeId::Int4) | Some(PgTypeId::Int8)
+        | Some(PgTypeId::Oid) => BindValue::Int64(0),
+        Some(PgTypeId::Float4) | Some(PgTypeId::Float8) => BindValue::Float64(0.0),
+        Some(PgTypeId::Numeric) => BindValue::Numeric("0".to_string()),
+        Some(PgTypeId::Date) => BindValue::Date(epoch),
+        Some(PgTypeId::Timestamp) => BindValue::Timestamp(epoch.and_hms(0, 0, 0)),
+        Some(PgTypeId::Timestamptz) => {
+            BindValue::TimestampTz(DateTime::from_utc(epoch.and_hms(0, 0, 0), Utc))
+        }
+        Some(PgTypeId::Interval) => BindValue::Interval("0".to_string()),
+        Some(PgTypeId::Time) => BindValue::String("00:00:00".to_string()),
+        Some(PgTypeId::Json) | Some(PgTypeId::Jsonb) => BindValue::String("null".to_string()),
+        Some(PgTypeId::Uuid) => {
+            BindValue::String("00000000-0000-0000-0000-000000000000".to_string())
+        }
+        // Text, varchar and bytea
+        _ => BindValue::String(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cubeclient::models::{V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure};
+
+    use super::*;
+    use crate::{compile::parser::parse_sql_to_statement, sql::session::DatabaseProtocol};
+
+    fn test_meta() -> MetaContext {
+        MetaContext::new(vec![V1CubeMeta {
+            name: "Orders".to_string(),
+            title: None,
+            dimensions: vec![
+                V1CubeMetaDimension {
+                    name: "Orders.status".to_string(),
+                    _type: "string".to_string(),
+                },
+                V1CubeMetaDimension {
+                    name: "Orders.createdAt".to_string(),
+                    _type: "time".to_string(),
+                },
+            ],
+            measures: vec![V1CubeMetaMeasure {
+                name: "Orders.count".to_string(),
+                title: None,
+                _type: "number".to_string(),
+                agg_type: Some("count".to_string()),
+            }],
+            segments: vec![],
+        }])
+    }
+
+    fn describe(query: &str, declared: &[u32]) -> Result<Vec<u32>, CubeError> {
+        let stmt = parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL)?;
+
+        describe_parameter_types(&stmt, declared, Some(&test_meta()))
+    }
+
+    #[test]
+    fn test_bind_sample_values() -> Result<(), CubeError> {
+        let stmt = parse_sql_to_statement(
+            &"SELECT $1, $2 FROM Orders WHERE count > $3 LIMIT $4".to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )?;
+        let bound = bind_sample_values(
+            &stmt,
+            &[PgTypeId::Int4.to_oid(), 0, PgTypeId::Bool.to_oid()],
+        )?;
+
+        assert_eq!(
+            bound.to_string(),
+            "SELECT 0, '' FROM Orders WHERE count > false LIMIT 1"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_parameter_types() -> Result<(), CubeError> {
+        assert_eq!(
+            describe(
+                "SELECT count FROM Orders o WHERE o.status = $1 AND createdAt BETWEEN $2 AND $3 LIMIT $4",
+                &[],
+            )?,
+            vec![
+                PgTypeId::Text.to_oid(),
+                PgTypeId::Timestamp.to_oid(),
+                PgTypeId::Timestamp.to_oid(),
+                PgTypeId::Int8.to_oid(),
+            ]
+        );
+
+        // Declared types win, casts and functions give types without the schema
+        assert_eq!(
+            describe(
+                "SELECT date_trunc($1, createdAt) FROM Orders WHERE status LIKE $2 AND count > $3::int AND $4 = $5",
+                &[PgTypeId::Varchar.to_oid()],
+            )?,
+            vec![
+                PgTypeId::Varchar.to_oid(),
+                PgTypeId::Text.to_oid(),
+                PgTypeId::Int4.to_oid(),
+                0,
+                0,
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -1,6 +1,21 @@
 pub(crate) mod buffer;
+pub(crate) mod copy;
+pub(crate) mod cursor;
+pub(crate) mod describe;
+pub(crate) mod pg_type;
+pub(crate) mod plan_cache;
+pub(crate) mod portal;
+pub(crate) mod prepared;
 pub(crate) mod protocol;
+pub(crate) mod scram;
 pub(crate) mod service;
+pub(crate) mod settings;
 pub(crate) mod shim;
+pub(crate) mod stream;
+pub(crate) mod tls;
+pub(crate) mod tokens;
+pub(crate) mod transaction;
+pub(crate) mod unix_socket;
+pub(crate) mod writer;
 
 pub use service::*;
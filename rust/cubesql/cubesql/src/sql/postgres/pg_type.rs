@@ -0,0 +1,305 @@
+use std::fmt::{self, Display, Formatter};
+
+use datafusion::arrow::datatypes::DataType;
+
+/// Built-in PostgreSQL types which are known by the protocol implementation,
+/// https://github.com/postgres/postgres/blob/REL_14_STABLE/src/include/catalog/pg_type.dat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PgTypeId {
+    Bool,
+    Bytea,
+    Int8,
+    Int2,
+    Int4,
+    Text,
+    Oid,
+    Float4,
+    Float8,
+    Varchar,
+    Date,
+    Time,
+    Timestamp,
+    Timestamptz,
+    Interval,
+    Numeric,
+    Uuid,
+    Json,
+    Jsonb,
+    ArrayBool,
+    ArrayBytea,
+    ArrayInt8,
+    ArrayInt2,
+    ArrayInt4,
+    ArrayText,
+    ArrayOid,
+    ArrayFloat4,
+    ArrayFloat8,
+    ArrayVarchar,
+    ArrayDate,
+    ArrayTime,
+    ArrayTimestamp,
+    ArrayTimestamptz,
+    ArrayInterval,
+    ArrayNumeric,
+    ArrayUuid,
+    ArrayJson,
+    ArrayJsonb,
+}
+
+impl PgTypeId {
+    pub fn from_oid(oid: u32) -> Option<Self> {
+        match oid {
+            16 => Some(Self::Bool),
+            17 => Some(Self::Bytea),
+            20 => Some(Self::Int8),
+            21 => Some(Self::Int2),
+            23 => Some(Self::Int4),
+            25 => Some(Self::Text),
+            26 => Some(Self::Oid),
+            700 => Some(Self::Float4),
+            701 => Some(Self::Float8),
+            1043 => Some(Self::Varchar),
+            1082 => Some(Self::Date),
+            1083 => Some(Self::Time),
+            1114 => Some(Self::Timestamp),
+            1184 => Some(Self::Timestamptz),
+            1186 => Some(Self::Interval),
+            1700 => Some(Self::Numeric),
+            2950 => Some(Self::Uuid),
+            114 => Some(Self::Json),
+            3802 => Some(Self::Jsonb),
+            1000 => Some(Self::ArrayBool),
+            1001 => Some(Self::ArrayBytea),
+            1016 => Some(Self::ArrayInt8),
+            1005 => Some(Self::ArrayInt2),
+            1007 => Some(Self::ArrayInt4),
+            1009 => Some(Self::ArrayText),
+            1028 => Some(Self::ArrayOid),
+            1021 => Some(Self::ArrayFloat4),
+            1022 => Some(Self::ArrayFloat8),
+            1015 => Some(Self::ArrayVarchar),
+            1182 => Some(Self::ArrayDate),
+            1183 => Some(Self::ArrayTime),
+            1115 => Some(Self::ArrayTimestamp),
+            1185 => Some(Self::ArrayTimestamptz),
+            1187 => Some(Self::ArrayInterval),
+            1231 => Some(Self::ArrayNumeric),
+            2951 => Some(Self::ArrayUuid),
+            199 => Some(Self::ArrayJson),
+            3807 => Some(Self::ArrayJsonb),
+            _ => None,
+        }
+    }
+
+    /// Type by the name from SQL, aliases (`integer`, `double precision`) are supported
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        if let Some(element) = name.strip_suffix("[]") {
+            return Self::from_name(element)?.array();
+        }
+        if let Some(element) = name.strip_prefix('_') {
+            return Self::from_name(element)?.array();
+        }
+
+        match name.as_str() {
+            "bool" | "boolean" => Some(Self::Bool),
+            "bytea" => Some(Self::Bytea),
+            "int8" | "bigint" => Some(Self::Int8),
+            "int2" | "smallint" => Some(Self::Int2),
+            "int4" | "int" | "integer" => Some(Self::Int4),
+            "text" => Some(Self::Text),
+            "oid" => Some(Self::Oid),
+            "float4" | "real" => Some(Self::Float4),
+            "float8" | "float" | "double" | "double precision" => Some(Self::Float8),
+            "varchar" | "character varying" | "char" | "character" => Some(Self::Varchar),
+            "date" => Some(Self::Date),
+            "time" | "time without time zone" => Some(Self::Time),
+            "timestamp" | "timestamp without time zone" => Some(Self::Timestamp),
+            "timestamptz" | "timestamp with time zone" => Some(Self::Timestamptz),
+            "interval" => Some(Self::Interval),
+            "numeric" | "decimal" => Some(Self::Numeric),
+            "uuid" => Some(Self::Uuid),
+            "json" => Some(Self::Json),
+            "jsonb" => Some(Self::Jsonb),
+            _ => None,
+        }
+    }
+
+    pub fn from_arrow(data_type: &DataType) -> Option<Self> {
+        match data_type {
+            DataType::Boolean => Some(Self::Bool),
+            DataType::Int8 | DataType::Int16 | DataType::UInt8 => Some(Self::Int2),
+            DataType::Int32 | DataType::UInt16 => Some(Self::Int4),
+            DataType::Int64 | DataType::UInt32 | DataType::UInt64 => Some(Self::Int8),
+            DataType::Float32 => Some(Self::Float4),
+            DataType::Float64 => Some(Self::Float8),
+            DataType::Utf8 | DataType::LargeUtf8 => Some(Self::Text),
+            DataType::Binary | DataType::LargeBinary => Some(Self::Bytea),
+            DataType::Date32 | DataType::Date64 => Some(Self::Date),
+            DataType::Timestamp(_, None) => Some(Self::Timestamp),
+            DataType::Timestamp(_, Some(_)) => Some(Self::Timestamptz),
+            DataType::Interval(_) => Some(Self::Interval),
+            DataType::Decimal(_, _) => Some(Self::Numeric),
+            DataType::List(field) => Self::from_arrow(field.data_type())?.array(),
+            _ => None,
+        }
+    }
+
+    pub fn to_oid(&self) -> u32 {
+        match self {
+            Self::Bool => 16,
+            Self::Bytea => 17,
+            Self::Int8 => 20,
+            Self::Int2 => 21,
+            Self::Int4 => 23,
+            Self::Text => 25,
+            Self::Oid => 26,
+            Self::Float4 => 700,
+            Self::Float8 => 701,
+            Self::Varchar => 1043,
+            Self::Date => 1082,
+            Self::Time => 1083,
+            Self::Timestamp => 1114,
+            Self::Timestamptz => 1184,
+            Self::Interval => 1186,
+            Self::Numeric => 1700,
+            Self::Uuid => 2950,
+            Self::Json => 114,
+            Self::Jsonb => 3802,
+            Self::ArrayBool => 1000,
+            Self::ArrayBytea => 1001,
+            Self::ArrayInt8 => 1016,
+            Self::ArrayInt2 => 1005,
+            Self::ArrayInt4 => 1007,
+            Self::ArrayText => 1009,
+            Self::ArrayOid => 1028,
+            Self::ArrayFloat4 => 1021,
+            Self::ArrayFloat8 => 1022,
+            Self::ArrayVarchar => 1015,
+            Self::ArrayDate => 1182,
+            Self::ArrayTime => 1183,
+            Self::ArrayTimestamp => 1115,
+            Self::ArrayTimestamptz => 1185,
+            Self::ArrayInterval => 1187,
+            Self::ArrayNumeric => 1231,
+            Self::ArrayUuid => 2951,
+            Self::ArrayJson => 199,
+            Self::ArrayJsonb => 3807,
+        }
+    }
+
+    /// Size of the type in pg_type.typlen, -1 for variable-length types
+    pub fn typlen(&self) -> i16 {
+        match self {
+            Self::Bool => 1,
+            Self::Int2 => 2,
+            Self::Int4 | Self::Oid | Self::Float4 | Self::Date => 4,
+            Self::Int8 | Self::Float8 | Self::Time | Self::Timestamp | Self::Timestamptz => 8,
+            Self::Interval | Self::Uuid => 16,
+            // Bytea, Text, Varchar, Numeric, Json, Jsonb and arrays
+            _ => -1,
+        }
+    }
+
+    /// Name of the type in pg_type.typname
+    pub fn typname(&self) -> &'static str {
+        match self {
+            Self::Bool => "bool",
+            Self::Bytea => "bytea",
+            Self::Int8 => "int8",
+            Self::Int2 => "int2",
+            Self::Int4 => "int4",
+            Self::Text => "text",
+            Self::Oid => "oid",
+            Self::Float4 => "float4",
+            Self::Float8 => "float8",
+            Self::Varchar => "varchar",
+            Self::Date => "date",
+            Self::Time => "time",
+            Self::Timestamp => "timestamp",
+            Self::Timestamptz => "timestamptz",
+            Self::Interval => "interval",
+            Self::Numeric => "numeric",
+            Self::Uuid => "uuid",
+            Self::Json => "json",
+            Self::Jsonb => "jsonb",
+            Self::ArrayBool => "_bool",
+            Self::ArrayBytea => "_bytea",
+            Self::ArrayInt8 => "_int8",
+            Self::ArrayInt2 => "_int2",
+            Self::ArrayInt4 => "_int4",
+            Self::ArrayText => "_text",
+            Self::ArrayOid => "_oid",
+            Self::ArrayFloat4 => "_float4",
+            Self::ArrayFloat8 => "_float8",
+            Self::ArrayVarchar => "_varchar",
+            Self::ArrayDate => "_date",
+            Self::ArrayTime => "_time",
+            Self::ArrayTimestamp => "_timestamp",
+            Self::ArrayTimestamptz => "_timestamptz",
+            Self::ArrayInterval => "_interval",
+            Self::ArrayNumeric => "_numeric",
+            Self::ArrayUuid => "_uuid",
+            Self::ArrayJson => "_json",
+            Self::ArrayJsonb => "_jsonb",
+        }
+    }
+
+    /// Type of elements of an array type
+    pub fn element(&self) -> Option<Self> {
+        match self {
+            Self::ArrayBool => Some(Self::Bool),
+            Self::ArrayBytea => Some(Self::Bytea),
+            Self::ArrayInt8 => Some(Self::Int8),
+            Self::ArrayInt2 => Some(Self::Int2),
+            Self::ArrayInt4 => Some(Self::Int4),
+            Self::ArrayText => Some(Self::Text),
+            Self::ArrayOid => Some(Self::Oid),
+            Self::ArrayFloat4 => Some(Self::Float4),
+            Self::ArrayFloat8 => Some(Self::Float8),
+            Self::ArrayVarchar => Some(Self::Varchar),
+            Self::ArrayDate => Some(Self::Date),
+            Self::ArrayTime => Some(Self::Time),
+            Self::ArrayTimestamp => Some(Self::Timestamp),
+            Self::ArrayTimestamptz => Some(Self::Timestamptz),
+            Self::ArrayInterval => Some(Self::Interval),
+            Self::ArrayNumeric => Some(Self::Numeric),
+            Self::ArrayUuid => Some(Self::Uuid),
+            Self::ArrayJson => Some(Self::Json),
+            Self::ArrayJsonb => Some(Self::Jsonb),
+            _ => None,
+        }
+    }
+
+    /// Array type with elements of this type
+    pub fn array(&self) -> Option<Self> {
+        match self {
+            Self::Bool => Some(Self::ArrayBool),
+            Self::Bytea => Some(Self::ArrayBytea),
+            Self::Int8 => Some(Self::ArrayInt8),
+            Self::Int2 => Some(Self::ArrayInt2),
+            Self::Int4 => Some(Self::ArrayInt4),
+            Self::Text => Some(Self::ArrayText),
+            Self::Oid => Some(Self::ArrayOid),
+            Self::Float4 => Some(Self::ArrayFloat4),
+            Self::Float8 => Some(Self::ArrayFloat8),
+            Self::Varchar => Some(Self::ArrayVarchar),
+            Self::Date => Some(Self::ArrayDate),
+            Self::Time => Some(Self::ArrayTime),
+            Self::Timestamp => Some(Self::ArrayTimestamp),
+            Self::Timestamptz => Some(Self::ArrayTimestamptz),
+            Self::Interval => Some(Self::ArrayInterval),
+            Self::Numeric => Some(Self::ArrayNumeric),
+            Self::Uuid => Some(Self::ArrayUuid),
+            Self::Json => Some(Self::ArrayJson),
+            Self::Jsonb => Some(Self::ArrayJsonb),
+            _ => None,
+        }
+    }
+}
+
+impl Display for PgTypeId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.typname())
+    }
+}
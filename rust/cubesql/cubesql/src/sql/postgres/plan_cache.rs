@@ -0,0 +1,373 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use datafusion::{
+    error::DataFusionError,
+    execution::context::ExecutionContext,
+    logical_plan::{Expr, ExprRewriter, LogicalPlan},
+    optimizer::utils::from_plan,
+    scalar::ScalarValue,
+};
+use serde_json::Value;
+
+use crate::{
+    compile::{engine::df::scan::CubeScanNode, MetaContext, QueryPlan},
+    sql::{statement::BindValue, StatusFlags},
+    CubeError,
+};
+
+/// Plans of a prepared statement are cached for this number of parameter signatures
+const MAX_PLANS_PER_STATEMENT: usize = 16;
+
+const STRING_MARKER_PREFIX: &str = "__cubesql_param_";
+/// Integer markers are far away from values, which are used in practice, numbers around them
+/// are results of constant folding over a marker
+const INT_MARKER_BASE: i64 = -(1 << 62);
+const INT_MARKER_WINDOW: i64 = 1 << 40;
+
+/// Kind of a bound value, plans are cached per list of kinds. Only strings and integers
+/// have markers, which can be found in a plan and replaced by values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParamKind {
+    String,
+    Int64,
+}
+
+/// Kinds of `values`, None is returned if some value can't be substituted into a plan
+pub fn signature(values: &[BindValue]) -> Option<Vec<ParamKind>> {
+    values
+        .iter()
+        .map(|value| match value {
+            BindValue::String(_) => Some(ParamKind::String),
+            BindValue::Int64(_) => Some(ParamKind::Int64),
+            _ => None,
+        })
+        .collect()
+}
+
+fn string_marker(index: usize) -> String {
+    format!("{}{}__", STRING_MARKER_PREFIX, index + 1)
+}
+
+fn int_marker(index: usize) -> i64 {
+    INT_MARKER_BASE + index as i64
+}
+
+/// Values, which are bound in place of parameters to plan the statement once per signature
+pub fn marker_values(signature: &[ParamKind]) -> Vec<BindValue> {
+    signature
+        .iter()
+        .enumerate()
+        .map(|(index, kind)| match kind {
+            ParamKind::String => BindValue::String(string_marker(index)),
+            ParamKind::Int64 => BindValue::Int64(int_marker(index)),
+        })
+        .collect()
+}
+
+/// Replaces markers by values in expressions of the plan and in requests of Cube scans.
+/// Occurrences of markers are counted to verify, that the plan can be reused.
+struct MarkerReplacer<'a> {
+    values: &'a [BindValue],
+    counts: Vec<usize>,
+    /// A value, which was derived from a marker (by constant folding), was found
+    tainted: bool,
+}
+
+impl<'a> MarkerReplacer<'a> {
+    fn new(values: &'a [BindValue]) -> Self {
+        Self {
+            values,
+            counts: vec![0; values.len()],
+            tainted: false,
+        }
+    }
+
+    fn found(&mut self, index: usize) -> Option<&'a BindValue> {
+        let value = self.values.get(index)?;
+        self.counts[index] += 1;
+
+        Some(value)
+    }
+
+    fn string_marker_index(&mut self, value: &str) -> Option<usize> {
+        if !value.contains(STRING_MARKER_PREFIX) {
+            return None;
+        }
+
+        let index = value
+            .strip_prefix(STRING_MARKER_PREFIX)
+            .and_then(|rest| rest.strip_suffix("__"))
+            .and_then(|number| number.parse::<usize>().ok())
+            .filter(|number| *number > 0 && string_marker(number - 1) == value)
+            .map(|number| number - 1);
+        if index.is_none() {
+            self.tainted = true;
+        }
+
+        index
+    }
+
+    fn int_marker_index(&mut self, value: i64) -> Option<usize> {
+        let offset = value.checked_sub(INT_MARKER_BASE)?;
+        if offset.abs() >= INT_MARKER_WINDOW {
+            return None;
+        }
+
+        if offset < 0 || offset as usize >= self.values.len() {
+            self.tainted = true;
+            return None;
+        }
+
+        Some(offset as usize)
+    }
+
+    fn replace_string(&mut self, value: String) -> Result<String, String> {
+        if let Some(index) = self.string_marker_index(&value) {
+            if let Some(BindValue::String(v)) = self.found(index) {
+                return Ok(v.clone());
+            }
+        }
+
+        // Integer markers in requests of Cube are strings
+        match value
+            .parse::<i64>()
+            .ok()
+            .and_then(|v| self.int_marker_index(v))
+        {
+            Some(index) => match self.found(index) {
+                Some(BindValue::Int64(v)) => Ok(v.to_string()),
+                _ => Err(value),
+            },
+            None => Err(value),
+        }
+    }
+
+    fn replace_scalar(&mut self, value: ScalarValue) -> ScalarValue {
+        match value {
+            ScalarValue::Utf8(Some(v)) => {
+                ScalarValue::Utf8(Some(self.replace_string(v).unwrap_or_else(|v| v)))
+            }
+            ScalarValue::LargeUtf8(Some(v)) => {
+                ScalarValue::LargeUtf8(Some(self.replace_string(v).unwrap_or_else(|v| v)))
+            }
+            ScalarValue::Int64(Some(v)) => match self.int_marker_index(v) {
+                Some(index) => match self.found(index) {
+                    Some(BindValue::Int64(value)) => ScalarValue::Int64(Some(*value)),
+                    _ => ScalarValue::Int64(Some(v)),
+                },
+                None => ScalarValue::Int64(Some(v)),
+            },
+            value => value,
+        }
+    }
+
+    fn replace_json(&mut self, value: Value) -> Value {
+        match value {
+            Value::String(v) => Value::String(self.replace_string(v).unwrap_or_else(|v| v)),
+            Value::Number(n) => match n.as_i64().and_then(|v| self.int_marker_index(v)) {
+                Some(index) => match self.found(index) {
+                    Some(BindValue::Int64(value)) => Value::from(*value),
+                    _ => Value::Number(n),
+                },
+                None => Value::Number(n),
+            },
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|v| self.replace_json(v)).collect())
+            }
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, self.replace_json(v)))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    fn replace_plan(&mut self, plan: &LogicalPlan) -> Result<LogicalPlan, CubeError> {
+        if let LogicalPlan::Extension { node } = plan {
+            if let Some(scan) = node.as_any().downcast_ref::<CubeScanNode>() {
+                let request = self.replace_json(serde_json::to_value(&scan.request)?);
+
+                return Ok(LogicalPlan::Extension {
+                    node: Arc::new(CubeScanNode {
+                        request: serde_json::from_value(request)?,
+                        ..scan.clone()
+                    }),
+                });
+            }
+        }
+
+        let exprs = plan
+            .expressions()
+            .into_iter()
+            .map(|expr| expr.rewrite(self))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inputs = plan
+            .inputs()
+            .into_iter()
+            .map(|input| self.replace_plan(input))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(from_plan(plan, &exprs, &inputs)?)
+    }
+}
+
+impl<'a> ExprRewriter for MarkerReplacer<'a> {
+    fn mutate(&mut self, expr: Expr) -> Result<Expr, DataFusionError> {
+        match expr {
+            Expr::Literal(value) => Ok(Expr::Literal(self.replace_scalar(value))),
+            expr => Ok(expr),
+        }
+    }
+}
+
+/// Logical plan of a prepared statement, which was planned with marker values
+pub struct PlanTemplate {
+    meta: Arc<MetaContext>,
+    status: StatusFlags,
+    plan: LogicalPlan,
+    ctx: ExecutionContext,
+}
+
+impl PlanTemplate {
+    /// Template from the plan of the statement, which was bound with `marker_values`. None is
+    /// returned if the plan doesn't keep every marker as is (constant folding, LIMIT), such
+    /// statements are planned on every Bind.
+    pub fn new(
+        query_plan: QueryPlan,
+        signature: &[ParamKind],
+        meta: Arc<MetaContext>,
+    ) -> Result<Option<Self>, CubeError> {
+        // Meta responses are computed while planning, they depend on values
+        let (status, plan, ctx) = match query_plan {
+            QueryPlan::DataFusionSelect(status, plan, ctx) => (status, plan, ctx),
+            _ => return Ok(None),
+        };
+
+        let markers = marker_values(signature);
+        let mut replacer = MarkerReplacer::new(&markers);
+        replacer.replace_plan(&plan)?;
+        if replacer.tainted || replacer.counts.iter().any(|count| *count != 1) {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            meta,
+            status,
+            plan,
+            ctx,
+        }))
+    }
+
+    /// Plan for `values`, which must have the signature of the template
+    pub fn instantiate(&self, values: &[BindValue]) -> Result<QueryPlan, CubeError> {
+        let plan = MarkerReplacer::new(values).replace_plan(&self.plan)?;
+
+        Ok(QueryPlan::DataFusionSelect(
+            self.status,
+            plan,
+            self.ctx.clone(),
+        ))
+    }
+}
+
+pub enum PlanLookup {
+    Hit(Arc<PlanTemplate>),
+    /// The plan for the signature can't be reused
+    Uncacheable,
+    Miss,
+}
+
+/// Plan templates of a prepared statement by parameter signatures
+#[derive(Default)]
+pub struct PlanCache {
+    templates: HashMap<Vec<ParamKind>, Option<Arc<PlanTemplate>>>,
+}
+
+impl PlanCache {
+    /// Templates are planned against the schema of cubes, they're stale after its reload
+    pub fn lookup(&self, signature: &[ParamKind], meta: &Arc<MetaContext>) -> PlanLookup {
+        match self.templates.get(signature) {
+            Some(Some(template)) if Arc::ptr_eq(&template.meta, meta) => {
+                PlanLookup::Hit(template.clone())
+            }
+            Some(None) => PlanLookup::Uncacheable,
+            _ => PlanLookup::Miss,
+        }
+    }
+
+    pub fn insert(&mut self, signature: Vec<ParamKind>, template: Option<Arc<PlanTemplate>>) {
+        if self.templates.len() >= MAX_PLANS_PER_STATEMENT
+            && !self.templates.contains_key(&signature)
+        {
+            return;
+        }
+
+        self.templates.insert(signature, template);
+    }
+}
+
+impl fmt::Debug for PlanCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlanCache")
+            .field("signatures", &self.templates.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(sql: &str, signature: &[ParamKind]) -> Result<Option<PlanTemplate>, CubeError> {
+        let ctx = ExecutionContext::new();
+        let plan = ctx.create_logical_plan(sql)?;
+
+        PlanTemplate::new(
+            QueryPlan::DataFusionSelect(StatusFlags::empty(), plan, ctx),
+            signature,
+            Arc::new(MetaContext::new(vec![])),
+        )
+    }
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature(&[BindValue::String("a".to_string()), BindValue::Int64(1)]),
+            Some(vec![ParamKind::String, ParamKind::Int64])
+        );
+        assert_eq!(signature(&[BindValue::Null]), None);
+    }
+
+    #[test]
+    fn test_plan_template() -> Result<(), CubeError> {
+        let template = template(
+            "SELECT '__cubesql_param_1__' AS a, concat('x', '__cubesql_param_2__') AS b",
+            &[ParamKind::String, ParamKind::String],
+        )?
+        .unwrap();
+
+        let plan = template
+            .instantiate(&[
+                BindValue::String("abc".to_string()),
+                BindValue::String("def".to_string()),
+            ])?
+            .as_logical_plan();
+        let plan = format!("{:?}", plan);
+        assert!(plan.contains("Utf8(\"abc\")"), "{}", plan);
+        assert!(plan.contains("Utf8(\"def\")"), "{}", plan);
+        assert!(!plan.contains(STRING_MARKER_PREFIX), "{}", plan);
+
+        // Markers must be kept as is, exactly once
+        assert!(template(
+            "SELECT '__cubesql_param_1__' AS a, '__cubesql_param_1__' AS b",
+            &[ParamKind::String]
+        )?
+        .is_none());
+        assert!(template("SELECT 'x__cubesql_param_1__' AS a", &[ParamKind::String])?.is_none());
+        assert!(template("SELECT 1 AS a", &[ParamKind::Int64])?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,838 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    ops::Range,
+    time::Duration as StdDuration,
+};
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+
+use sqlparser::ast;
+
+use crate::{
+    compile::QueryPlan,
+    sql::{
+        dataframe::{Column, IntervalValue},
+        interval::{format_interval, parse_interval},
+        statement::{parse_array_literal, BindValue},
+        QueryResponse,
+    },
+    CubeError,
+};
+
+use super::{
+    pg_type::PgTypeId,
+    plan_cache::PlanCache,
+    protocol::{Bind, Format},
+    stream::QueryResult,
+};
+
+/// Format of the parameter at `index` (0 based) in the Bind message:
+/// - no format codes, all parameters are text
+/// - one format code, it's applied to all parameters
+/// - otherwise, there must be a format code per parameter
+pub fn parameter_format(formats: &[Format], index: usize) -> Format {
+    match formats {
+        [] => Format::Text,
+        [format] => *format,
+        formats => formats.get(index).cloned().unwrap_or(Format::Text),
+    }
+}
+
+/// Statement which was created by the Parse message
+#[derive(Debug)]
+pub struct PreparedStatement {
+    pub query: ast::Statement,
+    /// OIDs which were specified by the client, 0 means that the type is unspecified
+    pub param_types: Vec<u32>,
+    /// OIDs which were sent in ParameterDescription by Describe, including inferred types of
+    /// unspecified parameters. Clients encode parameters by these types.
+    pub described_types: Vec<u32>,
+    /// Plans, which are reused by Bind, see `PlanTemplate`
+    pub plans: PlanCache,
+}
+
+impl PreparedStatement {
+    pub fn new(query: ast::Statement, param_types: Vec<u32>) -> Self {
+        Self {
+            query,
+            param_types,
+            described_types: vec![],
+            plans: PlanCache::default(),
+        }
+    }
+
+    /// Types to decode parameters of Bind: specified by the client, or described otherwise
+    pub fn bind_types(&self) -> Vec<u32> {
+        (0..self.param_types.len().max(self.described_types.len()))
+            .map(|i| match self.param_types.get(i) {
+                Some(oid) if *oid != 0 => *oid,
+                _ => self.described_types.get(i).cloned().unwrap_or(0),
+            })
+            .collect()
+    }
+}
+
+/// Statement with bound parameters which was created by the Bind message
+pub struct Portal {
+    pub statement: ast::Statement,
+    pub result_formats: Vec<Format>,
+    /// Plan from the plan cache of the prepared statement, `statement` is not bound then
+    pub plan: Option<QueryPlan>,
+    /// Result of the execution, it's populated by Describe or the first Execute
+    pub result: Option<QueryResult>,
+    /// Number of rows of a materialized result, which were already sent by previous Executes
+    position: usize,
+    /// Rows and time of Executes of the portal, for pg_stat_statements
+    pub sent_rows: u64,
+    pub execution_time: StdDuration,
+}
+
+impl Portal {
+    pub fn new(statement: ast::Statement, result_formats: Vec<Format>) -> Self {
+        Self {
+            statement,
+            result_formats,
+            plan: None,
+            result: None,
+            position: 0,
+            sent_rows: 0,
+            execution_time: StdDuration::default(),
+        }
+    }
+
+    /// Range of rows for the next Execute with the `max_rows` limit (0 is no limit) over the
+    /// result with `total` rows, the position is moved to the end of the range
+    pub fn next_rows(&mut self, max_rows: i32, total: usize) -> Range<usize> {
+        let start = self.position.min(total);
+        let end = if max_rows > 0 {
+            total.min(start + max_rows as usize)
+        } else {
+            total
+        };
+        self.position = end;
+
+        start..end
+    }
+
+    /// Columns of the result, None is returned if the statement doesn't return rows
+    pub fn result_columns(&self) -> Option<&Vec<Column>> {
+        match &self.result {
+            Some(QueryResult::Response(QueryResponse::ResultSet(_, frame))) => {
+                Some(frame.get_columns())
+            }
+            Some(QueryResult::Stream(stream)) => Some(stream.columns()),
+            _ => None,
+        }
+    }
+
+    /// Format of the result column at `index`, result formats follow the same rules as
+    /// parameter formats
+    pub fn result_format(&self, index: usize) -> Format {
+        parameter_format(&self.result_formats, index)
+    }
+}
+
+/// Decodes raw parameters from the Bind message. `param_types` are OIDs which were specified
+/// by the client in the Parse message (0 or missing OID means that the type is unspecified).
+pub fn bind_values(bind: &Bind, param_types: &[u32]) -> Result<Vec<BindValue>, CubeError> {
+    let formats = &bind.parameter_formats;
+    if formats.len() > 1 && formats.len() != bind.parameter_values.len() {
+        return Err(CubeError::user(format!(
+            "bind message has {} parameter formats but {} parameters",
+            formats.len(),
+            bind.parameter_values.len()
+        )));
+    }
+
+    let mut values = Vec::with_capacity(bind.parameter_values.len());
+
+    for (index, raw) in bind.parameter_values.iter().enumerate() {
+        let typ = param_types
+            .get(index)
+            .and_then(|oid| PgTypeId::from_oid(*oid));
+
+        let value = match raw {
+            None => BindValue::Null,
+            Some(raw) => match parameter_format(formats, index) {
+                Format::Text => decode_text(index, raw, typ)?,
+                Format::Binary => decode_binary(index, raw, typ)?,
+            },
+        };
+
+        values.push(value);
+    }
+
+    Ok(values)
+}
+
+/// Values in the text representation (EXECUTE parameters), they're decoded as text Bind values
+pub fn text_values(
+    values: Vec<Option<String>>,
+    param_types: &[u32],
+) -> Result<Vec<BindValue>, CubeError> {
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| match value {
+            None => Ok(BindValue::Null),
+            Some(text) => {
+                let typ = param_types
+                    .get(index)
+                    .and_then(|oid| PgTypeId::from_oid(*oid));
+
+                decode_text(index, text.as_bytes(), typ)
+            }
+        })
+        .collect()
+}
+
+fn decode_text(index: usize, raw: &[u8], typ: Option<PgTypeId>) -> Result<BindValue, CubeError> {
+    let text = String::from_utf8(raw.to_vec())?;
+    let invalid = || {
+        CubeError::user(format!(
+            "invalid input for parameter ${} of type {}: \"{}\"",
+            index + 1,
+            typ.map(|t| t.typname()).unwrap_or("unknown"),
+            text
+        ))
+    };
+
+    if let Some(element) = typ.and_then(|typ| typ.element()) {
+        let values = parse_array_literal(&text)
+            .ok_or_else(invalid)?
+            .into_iter()
+            .map(|value| match value {
+                Some(value) => decode_text(index, value.as_bytes(), Some(element)),
+                None => Ok(BindValue::Null),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        return Ok(BindValue::Array(values));
+    }
+
+    let value = match typ {
+        Some(PgTypeId::Int2) | Some(PgTypeId::Int4) | Some(PgTypeId::Int8) => {
+            BindValue::Int64(text.trim().parse::<i64>().map_err(|_| invalid())?)
+        }
+        Some(PgTypeId::Float4) | Some(PgTypeId::Float8) => {
+            BindValue::Float64(text.trim().parse::<f64>().map_err(|_| invalid())?)
+        }
+        // Validated as a number, but passed as is to keep the precision
+        Some(PgTypeId::Numeric) => {
+            let text = text.trim();
+            text.parse::<f64>().map_err(|_| invalid())?;
+
+            BindValue::Numeric(text.to_string())
+        }
+        Some(PgTypeId::Date) => BindValue::Date(
+            NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(|_| invalid())?,
+        ),
+        Some(PgTypeId::Timestamp) => {
+            BindValue::Timestamp(parse_timestamp(text.trim()).ok_or_else(invalid)?)
+        }
+        Some(PgTypeId::Timestamptz) => {
+            BindValue::TimestampTz(parse_timestamptz(text.trim()).ok_or_else(invalid)?)
+        }
+        // Validated, but passed as is, it's parsed again by the query
+        Some(PgTypeId::Interval) => {
+            parse_interval(&text).ok_or_else(invalid)?;
+
+            BindValue::Interval(text.trim().to_string())
+        }
+        Some(PgTypeId::Json) | Some(PgTypeId::Jsonb) => {
+            serde_json::from_str::<serde_json::Value>(&text).map_err(|_| invalid())?;
+
+            BindValue::String(text)
+        }
+        Some(PgTypeId::Bool) => match text.trim().to_lowercase().as_str() {
+            "t" | "true" | "y" | "yes" | "on" | "1" => BindValue::Bool(true),
+            "f" | "false" | "n" | "no" | "off" | "0" => BindValue::Bool(false),
+            _ => return Err(invalid()),
+        },
+        _ => BindValue::String(text),
+    };
+
+    Ok(value)
+}
+
+fn parse_timestamp(text: &str) -> Option<NaiveDateTime> {
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+}
+
+fn parse_timestamptz(text: &str) -> Option<DateTime<Utc>> {
+    // Postgres sends offsets without minutes (`+03`)
+    ["%Y-%m-%d %H:%M:%S%.f%#z", "%Y-%m-%dT%H:%M:%S%.f%#z"]
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(text, format).ok())
+        .map(|v| v.with_timezone(&Utc))
+        // Without an offset, the value is in UTC (session time zone)
+        .or_else(|| parse_timestamp(text).map(|v| DateTime::from_utc(v, Utc)))
+}
+
+/// Binary numeric: ndigits, weight, sign and dscale (display scale) headers, followed by
+/// ndigits base-10000 digits, the first one is multiplied by 10000^weight
+fn decode_numeric(raw: &[u8]) -> Option<String> {
+    let header = |i: usize| -> Option<i16> {
+        Some(i16::from_be_bytes(
+            raw.get(i * 2..i * 2 + 2)?.try_into().ok()?,
+        ))
+    };
+
+    let ndigits = header(0)? as usize;
+    let weight = header(1)? as i64;
+    let sign = header(2)? as u16;
+    let dscale = header(3)? as usize;
+
+    let digits = (0..ndigits)
+        .map(|i| header(4 + i))
+        .collect::<Option<Vec<_>>>()?;
+    let digit = |i: i64| -> i16 {
+        if i >= 0 && (i as usize) < digits.len() {
+            digits[i as usize]
+        } else {
+            0
+        }
+    };
+
+    let mut result = String::new();
+    match sign {
+        0x0000 => {}
+        0x4000 => result.push('-'),
+        0xC000 => return Some("NaN".to_string()),
+        _ => return None,
+    };
+
+    if weight < 0 {
+        result.push('0');
+    } else {
+        for i in 0..=weight {
+            if i == 0 {
+                result.push_str(&digit(i).to_string());
+            } else {
+                result.push_str(&format!("{:04}", digit(i)));
+            }
+        }
+    }
+
+    if dscale > 0 {
+        let mut fraction = String::new();
+        let mut i = weight + 1;
+        while fraction.len() < dscale {
+            fraction.push_str(&format!("{:04}", digit(i)));
+            i += 1;
+        }
+        fraction.truncate(dscale);
+
+        result.push('.');
+        result.push_str(&fraction);
+    }
+
+    Some(result)
+}
+
+/// Binary dates and timestamps are relative to 2000-01-01
+fn pg_epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0)
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if rest.len() < len {
+        return None;
+    }
+
+    let (head, tail) = rest.split_at(len);
+    *rest = tail;
+
+    Some(head)
+}
+
+fn take_i32(rest: &mut &[u8]) -> Option<i32> {
+    Some(i32::from_be_bytes(take(rest, 4)?.try_into().ok()?))
+}
+
+/// Binary arrays: the number of dimensions, the flag of NULL elements, the OID of elements,
+/// sizes and lower bounds of dimensions, elements prefixed by their lengths (-1 for NULL)
+fn decode_array_binary(
+    index: usize,
+    raw: &[u8],
+    element: PgTypeId,
+) -> Result<BindValue, CubeError> {
+    let invalid = || {
+        CubeError::user(format!(
+            "incorrect binary data format in parameter ${}",
+            index + 1
+        ))
+    };
+
+    let mut rest = raw;
+    let ndim = take_i32(&mut rest).ok_or_else(invalid)?;
+    let _has_null = take_i32(&mut rest).ok_or_else(invalid)?;
+    let oid = take_i32(&mut rest).ok_or_else(invalid)?;
+    if oid as u32 != element.to_oid() {
+        return Err(invalid());
+    }
+
+    let len = match ndim {
+        0 => 0,
+        1 => {
+            let len = take_i32(&mut rest).ok_or_else(invalid)?;
+            let _lower_bound = take_i32(&mut rest).ok_or_else(invalid)?;
+            usize::try_from(len).map_err(|_| invalid())?
+        }
+        _ => {
+            return Err(CubeError::user(format!(
+                "multidimensional arrays are not supported for parameter ${}",
+                index + 1
+            )))
+        }
+    };
+
+    let mut values = vec![];
+    for _ in 0..len {
+        let size = take_i32(&mut rest).ok_or_else(invalid)?;
+        values.push(if size < 0 {
+            BindValue::Null
+        } else {
+            let raw = take(&mut rest, size as usize).ok_or_else(invalid)?;
+            decode_binary(index, raw, Some(element))?
+        });
+    }
+
+    if !rest.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(BindValue::Array(values))
+}
+
+fn decode_binary(index: usize, raw: &[u8], typ: Option<PgTypeId>) -> Result<BindValue, CubeError> {
+    let invalid = || {
+        CubeError::user(format!(
+            "incorrect binary data format in parameter ${}",
+            index + 1
+        ))
+    };
+
+    // Values from the client can be out of range of chrono (including infinity, which is
+    // i32::MAX days or i64::MAX microseconds), any i32 of days is in range of Duration
+    let since_epoch = |duration: Duration| {
+        pg_epoch()
+            .checked_add_signed(duration)
+            .ok_or_else(|| CubeError::user(format!("parameter ${} out of range", index + 1)))
+    };
+
+    if let Some(element) = typ.and_then(|typ| typ.element()) {
+        return decode_array_binary(index, raw, element);
+    }
+
+    let value = match typ {
+        Some(PgTypeId::Int2) => {
+            BindValue::Int64(i16::from_be_bytes(raw.try_into().map_err(|_| invalid())?) as i64)
+        }
+        Some(PgTypeId::Int4) => {
+            BindValue::Int64(i32::from_be_bytes(raw.try_into().map_err(|_| invalid())?) as i64)
+        }
+        Some(PgTypeId::Int8) => {
+            BindValue::Int64(i64::from_be_bytes(raw.try_into().map_err(|_| invalid())?))
+        }
+        Some(PgTypeId::Float4) => {
+            BindValue::Float64(f32::from_be_bytes(raw.try_into().map_err(|_| invalid())?) as f64)
+        }
+        Some(PgTypeId::Float8) => {
+            BindValue::Float64(f64::from_be_bytes(raw.try_into().map_err(|_| invalid())?))
+        }
+        Some(PgTypeId::Bool) => match raw {
+            [v] => BindValue::Bool(*v != 0),
+            _ => return Err(invalid()),
+        },
+        Some(PgTypeId::Text) | Some(PgTypeId::Varchar) | Some(PgTypeId::Json) => {
+            BindValue::String(String::from_utf8(raw.to_vec())?)
+        }
+        // The version of the format, which is 1, and the text
+        Some(PgTypeId::Jsonb) => match raw.split_first() {
+            Some((1, text)) => BindValue::String(String::from_utf8(text.to_vec())?),
+            _ => return Err(invalid()),
+        },
+        Some(PgTypeId::Date) => {
+            let days = i32::from_be_bytes(raw.try_into().map_err(|_| invalid())?);
+            BindValue::Date(since_epoch(Duration::days(days as i64))?.date())
+        }
+        Some(PgTypeId::Timestamp) => {
+            let micros = i64::from_be_bytes(raw.try_into().map_err(|_| invalid())?);
+            BindValue::Timestamp(since_epoch(Duration::microseconds(micros))?)
+        }
+        Some(PgTypeId::Timestamptz) => {
+            let micros = i64::from_be_bytes(raw.try_into().map_err(|_| invalid())?);
+            BindValue::TimestampTz(DateTime::from_utc(
+                since_epoch(Duration::microseconds(micros))?,
+                Utc,
+            ))
+        }
+        Some(PgTypeId::Numeric) => BindValue::Numeric(decode_numeric(raw).ok_or_else(invalid)?),
+        Some(PgTypeId::Uuid) => BindValue::String(
+            uuid::Uuid::from_slice(raw)
+                .map_err(|_| invalid())?
+                .to_hyphenated()
+                .to_string(),
+        ),
+        // Microseconds, days and months
+        Some(PgTypeId::Interval) => {
+            if raw.len() != 16 {
+                return Err(invalid());
+            }
+
+            let micros = i64::from_be_bytes(raw[0..8].try_into().map_err(|_| invalid())?);
+            let days = i32::from_be_bytes(raw[8..12].try_into().map_err(|_| invalid())?);
+            let months = i32::from_be_bytes(raw[12..16].try_into().map_err(|_| invalid())?);
+
+            BindValue::Interval(format_interval(&IntervalValue::new(months, days, micros)))
+        }
+        typ => {
+            return Err(CubeError::user(format!(
+                "binary format is not supported for parameter ${} of type {}",
+                index + 1,
+                typ.map(|t| t.typname()).unwrap_or("unknown")
+            )))
+        }
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(parameter_formats: Vec<Format>, parameter_values: Vec<Option<Vec<u8>>>) -> Bind {
+        Bind {
+            portal: "".to_string(),
+            statement: "".to_string(),
+            parameter_formats,
+            parameter_values,
+            result_formats: vec![],
+        }
+    }
+
+    fn values_to_string(values: Vec<BindValue>) -> String {
+        format!("{:?}", values)
+    }
+
+    #[test]
+    fn test_bind_values_no_formats() -> Result<(), CubeError> {
+        let values = bind_values(
+            &bind(vec![], vec![Some(b"5".to_vec()), Some(b"test".to_vec())]),
+            &[PgTypeId::Int4.to_oid(), 0],
+        )?;
+
+        assert_eq!(values_to_string(values), "[Int64(5), String(\"test\")]");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_values_single_format() -> Result<(), CubeError> {
+        let values = bind_values(
+            &bind(
+                vec![Format::Binary],
+                vec![Some(5_i32.to_be_bytes().to_vec()), Some(vec![1])],
+            ),
+            &[PgTypeId::Int4.to_oid(), PgTypeId::Bool.to_oid()],
+        )?;
+
+        assert_eq!(values_to_string(values), "[Int64(5), Bool(true)]");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_values_format_per_parameter() -> Result<(), CubeError> {
+        let values = bind_values(
+            &bind(
+                vec![Format::Text, Format::Binary, Format::Text],
+                vec![
+                    Some(b"5".to_vec()),
+                    Some(2.5_f64.to_be_bytes().to_vec()),
+                    Some(b"true".to_vec()),
+                ],
+            ),
+            &[
+                PgTypeId::Int8.to_oid(),
+                PgTypeId::Float8.to_oid(),
+                PgTypeId::Bool.to_oid(),
+            ],
+        )?;
+
+        assert_eq!(
+            values_to_string(values),
+            "[Int64(5), Float64(2.5), Bool(true)]"
+        );
+
+        let err = bind_values(
+            &bind(
+                vec![Format::Text, Format::Binary],
+                vec![
+                    Some(b"1".to_vec()),
+                    Some(b"2".to_vec()),
+                    Some(b"3".to_vec()),
+                ],
+            ),
+            &[],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.message,
+            "bind message has 2 parameter formats but 3 parameters"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_values_null_and_numeric() -> Result<(), CubeError> {
+        let values = bind_values(
+            &bind(vec![], vec![None, Some(b" 10.250 ".to_vec())]),
+            &[PgTypeId::Int4.to_oid(), PgTypeId::Numeric.to_oid()],
+        )?;
+
+        assert_eq!(values_to_string(values), "[Null, Numeric(\"10.250\")]");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_values_temporal() -> Result<(), CubeError> {
+        let values = bind_values(
+            &bind(
+                vec![],
+                vec![
+                    Some(b"2022-03-01".to_vec()),
+                    Some(b"2022-03-01 10:30:00.5".to_vec()),
+                    Some(b"2022-03-01 10:30:00+03".to_vec()),
+                    Some(b"1 day".to_vec()),
+                ],
+            ),
+            &[
+                PgTypeId::Date.to_oid(),
+                PgTypeId::Timestamp.to_oid(),
+                PgTypeId::Timestamptz.to_oid(),
+                PgTypeId::Interval.to_oid(),
+            ],
+        )?;
+
+        assert_eq!(
+            values_to_string(values),
+            "[Date(2022-03-01), Timestamp(2022-03-01T10:30:00.500), TimestampTz(2022-03-01T07:30:00Z), Interval(\"1 day\")]"
+        );
+
+        let error = bind_values(
+            &bind(vec![], vec![Some(b"30 dayz".to_vec())]),
+            &[PgTypeId::Interval.to_oid()],
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.message,
+            "invalid input for parameter $1 of type interval: \"30 dayz\""
+        );
+
+        let mut interval = 1_500_000_i64.to_be_bytes().to_vec();
+        interval.extend_from_slice(&2_i32.to_be_bytes());
+        interval.extend_from_slice(&1_i32.to_be_bytes());
+
+        let values = bind_values(
+            &bind(
+                vec![Format::Binary],
+                vec![
+                    Some(31_i32.to_be_bytes().to_vec()),
+                    Some(86_400_000_000_i64.to_be_bytes().to_vec()),
+                    Some(interval),
+                ],
+            ),
+            &[
+                PgTypeId::Date.to_oid(),
+                PgTypeId::Timestamp.to_oid(),
+                PgTypeId::Interval.to_oid(),
+            ],
+        )?;
+
+        assert_eq!(
+            values_to_string(values),
+            "[Date(2000-02-01), Timestamp(2000-01-02T00:00:00), Interval(\"1 mon 2 days 00:00:01.5\")]"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_values_described_types() -> Result<(), CubeError> {
+        let query = sqlparser::parser::Parser::parse_sql(
+            &sqlparser::dialect::PostgreSqlDialect {},
+            "SELECT $1, $2",
+        )
+        .unwrap()
+        .remove(0);
+        let mut statement = PreparedStatement::new(query, vec![0, PgTypeId::Int4.to_oid()]);
+        statement.described_types = vec![PgTypeId::Int8.to_oid(), PgTypeId::Int8.to_oid()];
+        assert_eq!(
+            statement.bind_types(),
+            vec![PgTypeId::Int8.to_oid(), PgTypeId::Int4.to_oid()]
+        );
+
+        // Unspecified parameters are decoded by described types
+        let values = bind_values(
+            &bind(
+                vec![Format::Binary],
+                vec![
+                    Some(5_i64.to_be_bytes().to_vec()),
+                    Some(7_i32.to_be_bytes().to_vec()),
+                ],
+            ),
+            &statement.bind_types(),
+        )?;
+        assert_eq!(values_to_string(values), "[Int64(5), Int64(7)]");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_values_binary_temporal_out_of_range() {
+        let out_of_range = vec![
+            (PgTypeId::Date, i32::MAX.to_be_bytes().to_vec()),
+            (PgTypeId::Date, i32::MIN.to_be_bytes().to_vec()),
+            (PgTypeId::Timestamp, i64::MAX.to_be_bytes().to_vec()),
+            (PgTypeId::Timestamp, i64::MIN.to_be_bytes().to_vec()),
+            (PgTypeId::Timestamptz, i64::MIN.to_be_bytes().to_vec()),
+        ];
+
+        for (typ, raw) in out_of_range.into_iter() {
+            let error = bind_values(
+                &bind(
+                    vec![Format::Binary],
+                    vec![Some(1_i32.to_be_bytes().to_vec()), Some(raw)],
+                ),
+                &[PgTypeId::Int4.to_oid(), typ.to_oid()],
+            )
+            .unwrap_err();
+            assert_eq!(error.message, "parameter $2 out of range", "{:?}", typ);
+        }
+    }
+
+    #[test]
+    fn test_bind_values_binary_numeric_uuid() -> Result<(), CubeError> {
+        let numeric = |headers: [i16; 4], digits: &[i16]| -> Option<Vec<u8>> {
+            Some(
+                headers
+                    .iter()
+                    .chain(digits.iter())
+                    .flat_map(|v| v.to_be_bytes().to_vec())
+                    .collect(),
+            )
+        };
+
+        let values = bind_values(
+            &bind(
+                vec![Format::Binary],
+                vec![
+                    // 123.45
+                    numeric([2, 0, 0, 2], &[123, 4500]),
+                    // -0.5
+                    numeric([1, -1, 0x4000, 1], &[5000]),
+                    // 10000000.0001
+                    numeric([3, 1, 0, 4], &[1000, 0, 1]),
+                    Some(
+                        uuid::Uuid::parse_str("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8")
+                            .unwrap()
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+                ],
+            ),
+            &[
+                PgTypeId::Numeric.to_oid(),
+                PgTypeId::Numeric.to_oid(),
+                PgTypeId::Numeric.to_oid(),
+                PgTypeId::Uuid.to_oid(),
+            ],
+        )?;
+
+        assert_eq!(
+            values_to_string(values),
+            "[Numeric(\"123.45\"), Numeric(\"-0.5\"), Numeric(\"10000000.0001\"), String(\"a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8\")]"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_values_arrays() -> Result<(), CubeError> {
+        let values = bind_values(
+            &bind(
+                vec![],
+                vec![
+                    Some(b"{a,\"b c\",NULL,\"NULL\"}".to_vec()),
+                    Some(b"{1, 2}".to_vec()),
+                    Some(b"{}".to_vec()),
+                ],
+            ),
+            &[
+                PgTypeId::ArrayText.to_oid(),
+                PgTypeId::ArrayInt4.to_oid(),
+                PgTypeId::ArrayBool.to_oid(),
+            ],
+        )?;
+        assert_eq!(
+            values_to_string(values),
+            "[Array([String(\"a\"), String(\"b c\"), Null, String(\"NULL\")]), Array([Int64(1), Int64(2)]), Array([])]"
+        );
+
+        let binary = [
+            &1_i32.to_be_bytes()[..],
+            &1_i32.to_be_bytes(),
+            &PgTypeId::Int8.to_oid().to_be_bytes(),
+            &2_i32.to_be_bytes(),
+            &1_i32.to_be_bytes(),
+            &8_i32.to_be_bytes(),
+            &5_i64.to_be_bytes(),
+            &(-1_i32).to_be_bytes(),
+        ]
+        .concat();
+        let values = bind_values(
+            &bind(vec![Format::Binary], vec![Some(binary)]),
+            &[PgTypeId::ArrayInt8.to_oid()],
+        )?;
+        assert_eq!(values_to_string(values), "[Array([Int64(5), Null])]");
+
+        let error = bind_values(
+            &bind(vec![], vec![Some(b"{1,{2}}".to_vec())]),
+            &[PgTypeId::ArrayInt4.to_oid()],
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.message,
+            "invalid input for parameter $1 of type _int4: \"{1,{2}}\""
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_portal_next_rows() -> Result<(), CubeError> {
+        let statement = crate::compile::parser::parse_sql_to_statement(
+            &"SELECT 1".to_string(),
+            crate::sql::session::DatabaseProtocol::PostgreSQL,
+        )?;
+        let mut portal = Portal::new(statement, vec![]);
+
+        assert_eq!(portal.next_rows(2, 5), 0..2);
+        assert_eq!(portal.next_rows(2, 5), 2..4);
+        assert_eq!(portal.next_rows(2, 5), 4..5);
+        // Drained portal returns no rows
+        assert_eq!(portal.next_rows(2, 5), 5..5);
+
+        let mut portal = Portal::new(portal.statement, vec![]);
+        assert_eq!(portal.next_rows(0, 5), 0..5);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use sqlparser::ast;
+
+use crate::CubeError;
+
+use super::{pg_type::PgTypeId, tokens::TokenParser};
+
+/// SQL-level commands for prepared statements, which are not supported by the SQL parser
+#[derive(Debug, PartialEq)]
+pub enum PreparedCommand {
+    /// DEALLOCATE [PREPARE] { name | ALL }, None is DEALLOCATE ALL
+    Deallocate { name: Option<String> },
+}
+
+/// Parses `query` as a prepared statement command, Ok(None) is returned for other statements
+pub fn parse_prepared_command(query: &str) -> Result<Option<PreparedCommand>, CubeError> {
+    let mut parser = TokenParser::new(query)?;
+
+    let command = match parser.next_keyword().as_deref() {
+        Some("DEALLOCATE") => {
+            parser.parse_keyword("PREPARE");
+            let name = if parser.parse_keyword("ALL") {
+                None
+            } else {
+                Some(parser.parse_name()?)
+            };
+
+            PreparedCommand::Deallocate { name }
+        }
+        _ => return Ok(None),
+    };
+    parser.expect_end()?;
+
+    Ok(Some(command))
+}
+
+/// Name of the statement in PREPARE and EXECUTE, unquoted names are folded to lower case
+pub fn statement_name(name: &ast::Ident) -> String {
+    match name.quote_style {
+        Some(_) => name.value.clone(),
+        None => name.value.to_lowercase(),
+    }
+}
+
+/// OIDs of the parameter types which are declared in PREPARE name(types)
+pub fn parameter_types(data_types: &[ast::DataType]) -> Result<Vec<u32>, CubeError> {
+    data_types
+        .iter()
+        .map(|data_type| {
+            // Type modifiers (`varchar(10)`, `numeric(10, 2)`) don't change the OID
+            let full_name = data_type.to_string();
+            let name = full_name.split('(').next().unwrap_or_default().trim();
+
+            PgTypeId::from_name(name)
+                .map(|typ| typ.to_oid())
+                .ok_or_else(|| CubeError::user(format!("type \"{}\" does not exist", full_name)))
+        })
+        .collect()
+}
+
+/// Parameters of EXECUTE in the text representation, None is NULL. They are decoded
+/// by the declared types as text values of Bind.
+pub fn parameter_values(parameters: &[ast::Expr]) -> Result<Vec<Option<String>>, CubeError> {
+    parameters.iter().map(parameter_value).collect()
+}
+
+fn parameter_value(expr: &ast::Expr) -> Result<Option<String>, CubeError> {
+    match expr {
+        ast::Expr::Value(ast::Value::Null) => Ok(None),
+        ast::Expr::Value(ast::Value::Number(number, _)) => Ok(Some(number.clone())),
+        ast::Expr::Value(ast::Value::SingleQuotedString(value)) => Ok(Some(value.clone())),
+        ast::Expr::Value(ast::Value::Boolean(value)) => Ok(Some(value.to_string())),
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Minus,
+            expr,
+        } => match expr.as_ref() {
+            ast::Expr::Value(ast::Value::Number(number, _)) => Ok(Some(format!("-{}", number))),
+            _ => Err(unsupported_parameter(expr)),
+        },
+        // The declared type of the parameter is used instead of the cast
+        ast::Expr::Cast { expr, .. } | ast::Expr::Nested(expr) => parameter_value(expr),
+        ast::Expr::TypedString { value, .. } => Ok(Some(value.clone())),
+        expr => Err(unsupported_parameter(expr)),
+    }
+}
+
+fn unsupported_parameter(expr: &ast::Expr) -> CubeError {
+    CubeError::user(format!(
+        "EXECUTE parameters must be constants, found: {}",
+        expr
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_deallocate() {
+        assert_eq!(
+            parse_prepared_command("DEALLOCATE s1;").unwrap(),
+            Some(PreparedCommand::Deallocate {
+                name: Some("s1".to_string())
+            })
+        );
+        assert_eq!(
+            parse_prepared_command("deallocate prepare \"S1\"").unwrap(),
+            Some(PreparedCommand::Deallocate {
+                name: Some("S1".to_string())
+            })
+        );
+        assert_eq!(
+            parse_prepared_command("DEALLOCATE ALL").unwrap(),
+            Some(PreparedCommand::Deallocate { name: None })
+        );
+        assert!(parse_prepared_command("DEALLOCATE").is_err());
+        assert_eq!(parse_prepared_command("SELECT 1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_prepare_execute_parameters() -> Result<(), CubeError> {
+        let stmt = crate::compile::parser::parse_sql_to_statement(
+            &"PREPARE Q(int, character varying(10), numeric(10, 2)) AS SELECT $1, $2, $3"
+                .to_string(),
+            crate::sql::session::DatabaseProtocol::PostgreSQL,
+        )?;
+        match stmt {
+            ast::Statement::Prepare {
+                name, data_types, ..
+            } => {
+                assert_eq!(statement_name(&name), "q");
+                assert_eq!(parameter_types(&data_types)?, vec![23, 1043, 1700]);
+            }
+            stmt => panic!("Unexpected statement: {}", stmt),
+        }
+
+        let stmt = crate::compile::parser::parse_sql_to_statement(
+            &"EXECUTE q(-5, 'abc', NULL, '1.5'::numeric, true)".to_string(),
+            crate::sql::session::DatabaseProtocol::PostgreSQL,
+        )?;
+        match stmt {
+            ast::Statement::Execute { parameters, .. } => assert_eq!(
+                parameter_values(&parameters)?,
+                vec![
+                    Some("-5".to_string()),
+                    Some("abc".to_string()),
+                    None,
+                    Some("1.5".to_string()),
+                    Some("true".to_string()),
+                ]
+            ),
+            stmt => panic!("Unexpected statement: {}", stmt),
+        }
+
+        Ok(())
+    }
+}
@@ -8,11 +8,15 @@ use std::{
 use async_trait::async_trait;
 use tokio::io::AsyncReadExt;
 
-use super::buffer;
+use crate::telemetry::metrics::METRICS;
+
+use super::{buffer, pg_type::PgTypeId};
 
 const DEFAULT_CAPACITY: usize = 64;
 
 pub const SSL_REQUEST_PROTOCOL: u16 = 1234;
+/// CancelRequest uses the same major version as SSLRequest
+pub const CANCEL_REQUEST_CODE: u16 = 5678;
 
 pub struct StartupMessage {
     pub protocol_version: ProtocolVersion,
@@ -45,6 +49,52 @@ impl StartupMessage {
     }
 }
 
+/// Request to cancel the query of another connection, it's sent instead of StartupMessage
+#[derive(Debug, PartialEq)]
+pub struct CancelRequest {
+    pub process_id: u32,
+    pub secret_key: u32,
+}
+
+impl CancelRequest {
+    /// `buffer` is positioned after the protocol version
+    pub async fn from(buffer: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
+        let process_id = buffer.read_u32().await?;
+        let secret_key = buffer.read_u32().await?;
+
+        Ok(Self {
+            process_id,
+            secret_key,
+        })
+    }
+}
+
+/// Identifies the connection for CancelRequest
+pub struct BackendKeyData {
+    process_id: u32,
+    secret_key: u32,
+}
+
+impl BackendKeyData {
+    pub fn new(process_id: u32, secret_key: u32) -> Self {
+        Self {
+            process_id,
+            secret_key,
+        }
+    }
+}
+
+impl Serialize for BackendKeyData {
+    const CODE: u8 = b'K';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(8);
+        buffer.extend_from_slice(&self.process_id.to_be_bytes());
+        buffer.extend_from_slice(&self.secret_key.to_be_bytes());
+        Some(buffer)
+    }
+}
+
 pub struct ErrorResponse {
     // https://www.postgresql.org/docs/14/protocol-error-fields.html
     pub severity: ErrorSeverity,
@@ -54,6 +104,8 @@ pub struct ErrorResponse {
 
 impl ErrorResponse {
     pub fn new(severity: ErrorSeverity, code: ErrorCode, message: String) -> Self {
+        METRICS.errors.inc(&code.to_string());
+
         Self {
             severity,
             code,
@@ -66,28 +118,74 @@ impl Serialize for ErrorResponse {
     const CODE: u8 = b'E';
 
     fn serialize(&self) -> Option<Vec<u8>> {
-        let mut buffer = Vec::with_capacity(DEFAULT_CAPACITY);
+        Some(serialize_notice_fields(
+            &self.severity,
+            &self.code,
+            &self.message,
+        ))
+    }
+}
 
-        let severity = self.severity.to_string();
-        buffer.push(b'S');
-        buffer::write_string(&mut buffer, &severity);
-        buffer.push(b'V');
-        buffer::write_string(&mut buffer, &severity);
-        buffer.push(b'C');
-        buffer::write_string(&mut buffer, &self.code.to_string());
-        buffer.push(b'M');
-        buffer::write_string(&mut buffer, &self.message);
-        buffer.push(0);
+/// Warning, which doesn't affect processing of the query or the session
+pub struct NoticeResponse {
+    pub severity: ErrorSeverity,
+    pub code: ErrorCode,
+    pub message: String,
+}
 
-        Some(buffer)
+impl NoticeResponse {
+    pub fn new(severity: ErrorSeverity, code: ErrorCode, message: String) -> Self {
+        Self {
+            severity,
+            code,
+            message,
+        }
+    }
+}
+
+impl Serialize for NoticeResponse {
+    const CODE: u8 = b'N';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(serialize_notice_fields(
+            &self.severity,
+            &self.code,
+            &self.message,
+        ))
     }
 }
 
-pub struct SSLResponse {}
+/// Fields of ErrorResponse and NoticeResponse, they have the same format
+fn serialize_notice_fields(severity: &ErrorSeverity, code: &ErrorCode, message: &str) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(DEFAULT_CAPACITY);
+
+    let severity = severity.to_string();
+    buffer.push(b'S');
+    buffer::write_string(&mut buffer, &severity);
+    buffer.push(b'V');
+    buffer::write_string(&mut buffer, &severity);
+    buffer.push(b'C');
+    buffer::write_string(&mut buffer, &code.to_string());
+    buffer.push(b'M');
+    buffer::write_string(&mut buffer, message);
+    buffer.push(0);
+
+    buffer
+}
+
+pub struct SSLResponse {
+    accepted: bool,
+}
 
 impl SSLResponse {
+    /// SSL is not supported, the client continues without it
     pub fn new() -> Self {
-        Self {}
+        Self { accepted: false }
+    }
+
+    /// The client starts the TLS handshake after the response
+    pub fn accepted() -> Self {
+        Self { accepted: true }
     }
 }
 
@@ -97,6 +195,14 @@ impl Serialize for SSLResponse {
     fn serialize(&self) -> Option<Vec<u8>> {
         None
     }
+
+    fn code(&self) -> u8 {
+        if self.accepted {
+            b'S'
+        } else {
+            Self::CODE
+        }
+    }
 }
 
 pub struct Authentication {
@@ -172,7 +278,11 @@ impl Serialize for CommandComplete {
     const CODE: u8 = b'C';
 
     fn serialize(&self) -> Option<Vec<u8>> {
-        let string = format!("{} {}", self.tag, self.rows);
+        let string = if self.tag.has_rows() {
+            format!("{} {}", self.tag, self.rows)
+        } else {
+            self.tag.to_string()
+        };
         let mut buffer = Vec::with_capacity(DEFAULT_CAPACITY);
         buffer::write_string(&mut buffer, &string);
         Some(buffer)
@@ -233,14 +343,40 @@ impl RowDescriptionField {
             format_code: 0,
         }
     }
+
+    pub fn with_type(name: String, typ: PgTypeId, format: Format) -> Self {
+        Self {
+            data_type_oid: typ.to_oid() as i32,
+            data_type_size: typ.typlen(),
+            format_code: format.to_code(),
+            ..Self::new(name)
+        }
+    }
+
+    pub fn with_type_modifier(self, type_modifier: i32) -> Self {
+        Self {
+            type_modifier,
+            ..self
+        }
+    }
 }
 
 pub struct DataRow {
-    values: Vec<Option<String>>,
+    values: Vec<Option<Vec<u8>>>,
 }
 
 impl DataRow {
     pub fn new(values: Vec<Option<String>>) -> Self {
+        Self {
+            values: values
+                .into_iter()
+                .map(|v| v.map(String::into_bytes))
+                .collect(),
+        }
+    }
+
+    /// Values which are already encoded in the format of their columns
+    pub fn from_bytes(values: Vec<Option<Vec<u8>>>) -> Self {
         Self { values }
     }
 }
@@ -258,7 +394,7 @@ impl Serialize for DataRow {
                 Some(value) => {
                     let size = u32::try_from(value.len()).unwrap();
                     buffer.extend_from_slice(&size.to_be_bytes());
-                    buffer.extend_from_slice(value.as_bytes());
+                    buffer.extend_from_slice(value);
                 }
             };
         }
@@ -283,6 +419,47 @@ impl Deserialize for PasswordMessage {
     }
 }
 
+/// The first 'p' message of the SASL exchange, a response to AuthenticationSASL
+#[derive(Debug, PartialEq)]
+pub struct SASLInitialResponse {
+    pub mechanism: String,
+    pub data: Vec<u8>,
+}
+
+#[async_trait]
+impl Deserialize for SASLInitialResponse {
+    async fn deserialize(mut buffer: Cursor<Vec<u8>>) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let mechanism = buffer::read_string(&mut buffer).await?;
+        // -1 is used if there is no initial response
+        let length = buffer.read_i32().await?;
+        let mut data = vec![0; usize::try_from(length).unwrap_or(0)];
+        buffer.read_exact(&mut data).await?;
+
+        Ok(Self { mechanism, data })
+    }
+}
+
+/// The following 'p' messages of the SASL exchange, responses to AuthenticationSASLContinue
+#[derive(Debug, PartialEq)]
+pub struct SASLResponse {
+    pub data: Vec<u8>,
+}
+
+#[async_trait]
+impl Deserialize for SASLResponse {
+    async fn deserialize(buffer: Cursor<Vec<u8>>) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            data: buffer.into_inner(),
+        })
+    }
+}
+
 /// This command is used for prepared statement creation on the server side
 #[derive(Debug, PartialEq)]
 pub struct Parse {
@@ -382,7 +559,7 @@ impl Deserialize for Bind {
             statement,
             parameter_formats,
             parameter_values,
-            result_formats: vec![],
+            result_formats,
         })
     }
 }
@@ -421,6 +598,244 @@ impl Deserialize for Describe {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct Execute {
+    /// The name of the portal to execute (an empty string selects the unnamed portal).
+    pub portal: String,
+    /// Maximum number of rows to return, zero denotes "no limit"
+    pub max_rows: i32,
+}
+
+#[async_trait]
+impl Deserialize for Execute {
+    async fn deserialize(mut buffer: Cursor<Vec<u8>>) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let portal = buffer::read_string(&mut buffer).await?;
+        let max_rows = buffer.read_i32().await?;
+
+        Ok(Self { portal, max_rows })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CloseType {
+    Statement,
+    Portal,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Close {
+    pub typ: CloseType,
+    pub name: String,
+}
+
+#[async_trait]
+impl Deserialize for Close {
+    async fn deserialize(mut buffer: Cursor<Vec<u8>>) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let typ = match buffer.read_u8().await? {
+            b'S' => CloseType::Statement,
+            b'P' => CloseType::Portal,
+            t => {
+                return Err(Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Unknown close code: {}", t),
+                ));
+            }
+        };
+        let name = buffer::read_string(&mut buffer).await?;
+
+        Ok(Self { typ, name })
+    }
+}
+
+pub struct ParseComplete {}
+
+impl ParseComplete {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for ParseComplete {
+    const CODE: u8 = b'1';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
+pub struct BindComplete {}
+
+impl BindComplete {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for BindComplete {
+    const CODE: u8 = b'2';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
+pub struct CloseComplete {}
+
+impl CloseComplete {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for CloseComplete {
+    const CODE: u8 = b'3';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
+/// Response to Describe for statements (portals) which don't return rows
+pub struct NoData {}
+
+impl NoData {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for NoData {
+    const CODE: u8 = b'n';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
+/// Response to a Query without statements, it's sent instead of CommandComplete
+pub struct EmptyQueryResponse {}
+
+impl EmptyQueryResponse {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for EmptyQueryResponse {
+    const CODE: u8 = b'I';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
+/// Start of COPY TO STDOUT, it's followed by CopyData messages and CopyDone
+pub struct CopyOutResponse {
+    columns: u16,
+}
+
+impl CopyOutResponse {
+    pub fn new(columns: u16) -> Self {
+        Self { columns }
+    }
+}
+
+impl Serialize for CopyOutResponse {
+    const CODE: u8 = b'H';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(DEFAULT_CAPACITY);
+        // Textual format for the whole result and every column, binary COPY is not supported
+        buffer.push(0);
+        buffer.extend_from_slice(&self.columns.to_be_bytes());
+        for _ in 0..self.columns {
+            buffer.extend_from_slice(&Format::Text.to_code().to_be_bytes());
+        }
+        Some(buffer)
+    }
+}
+
+pub struct CopyData {
+    data: Vec<u8>,
+}
+
+impl CopyData {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+}
+
+impl Serialize for CopyData {
+    const CODE: u8 = b'd';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(self.data.clone())
+    }
+}
+
+pub struct CopyDone {}
+
+impl CopyDone {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for CopyDone {
+    const CODE: u8 = b'c';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
+/// Response to Execute when the row limit was reached before the end of the result
+pub struct PortalSuspended {}
+
+impl PortalSuspended {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Serialize for PortalSuspended {
+    const CODE: u8 = b's';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+}
+
+pub struct ParameterDescription {
+    param_types: Vec<u32>,
+}
+
+impl ParameterDescription {
+    pub fn new(param_types: Vec<u32>) -> Self {
+        Self { param_types }
+    }
+}
+
+impl Serialize for ParameterDescription {
+    const CODE: u8 = b't';
+
+    fn serialize(&self) -> Option<Vec<u8>> {
+        let size = u16::try_from(self.param_types.len()).unwrap();
+        let mut buffer = Vec::with_capacity(DEFAULT_CAPACITY);
+        buffer.extend_from_slice(&size.to_be_bytes());
+        for typ in self.param_types.iter() {
+            buffer.extend_from_slice(&typ.to_be_bytes());
+        }
+        Some(buffer)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Query {
     pub query: String,
@@ -438,12 +853,21 @@ impl Deserialize for Query {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Format {
     Text,
     Binary,
 }
 
+impl Format {
+    pub fn to_code(&self) -> i16 {
+        match self {
+            Self::Text => 0,
+            Self::Binary => 1,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct ProtocolVersion {
     pub major: u16,
@@ -463,12 +887,35 @@ pub enum FrontendMessage {
     Parse(Parse),
     Bind(Bind),
     Describe(Describe),
+    Execute(Execute),
+    /// Close prepared statement or portal
+    Close(Close),
+    /// Flush pending output
+    Flush,
     /// Close connection
     Terminate,
     /// Finish
     Sync,
 }
 
+impl FrontendMessage {
+    /// Name of the message in metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PasswordMessage(_) => "password",
+            Self::Query(_) => "query",
+            Self::Parse(_) => "parse",
+            Self::Bind(_) => "bind",
+            Self::Describe(_) => "describe",
+            Self::Execute(_) => "execute",
+            Self::Close(_) => "close",
+            Self::Flush => "flush",
+            Self::Terminate => "terminate",
+            Self::Sync => "sync",
+        }
+    }
+}
+
 /// https://www.postgresql.org/docs/14/errcodes-appendix.html
 pub enum ErrorCode {
     // 0A — Feature Not Supported
@@ -476,6 +923,14 @@ pub enum ErrorCode {
     // 28 - Invalid Authorization Specification
     InvalidAuthorizationSpecification,
     InvalidPassword,
+    // 25 - Invalid Transaction State
+    IdleInTransactionSessionTimeout,
+    // 53 - Insufficient Resources
+    TooManyConnections,
+    // 57 - Operator Intervention
+    QueryCanceled,
+    AdminShutdown,
+    IdleSessionTimeout,
     // XX - Internal Error
     InternalError,
 }
@@ -488,6 +943,14 @@ impl Display for ErrorCode {
             Self::InvalidAuthorizationSpecification => "28000",
             Self::InvalidPassword => "28P01",
 
+            Self::IdleInTransactionSessionTimeout => "25P03",
+
+            Self::TooManyConnections => "53300",
+
+            Self::QueryCanceled => "57014",
+            Self::AdminShutdown => "57P01",
+            Self::IdleSessionTimeout => "57P05",
+
             Self::InternalError => "XX000",
         };
         write!(f, "{}", string)
@@ -499,6 +962,8 @@ pub enum ErrorSeverity {
     Error,
     Fatal,
     // Panic,
+    // Severity of NoticeResponse
+    Warning,
 }
 
 impl Display for ErrorSeverity {
@@ -507,35 +972,89 @@ impl Display for ErrorSeverity {
             Self::Error => "ERROR",
             Self::Fatal => "FATAL",
             // Self::Panic => "PANIC",
+            Self::Warning => "WARNING",
         };
         write!(f, "{}", string)
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TransactionStatus {
     Idle,
-    // InTransactionBlock,
-    // InFailedTransactionBlock,
+    InTransactionBlock,
+    InFailedTransactionBlock,
 }
 
 impl TransactionStatus {
     pub fn to_byte(&self) -> u8 {
         match self {
             Self::Idle => b'I',
-            // Self::InTransactionBlock => b'T',
-            // Self::InFailedTransactionBlock => b'E',
+            Self::InTransactionBlock => b'T',
+            Self::InFailedTransactionBlock => b'E',
         }
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub enum CommandCompleteTag {
     Select,
+    Fetch,
+    Move,
+    Copy,
+    DeclareCursor,
+    CloseCursor,
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint,
+    Release,
+    Prepare,
+    Deallocate,
+    DeallocateAll,
+    Set,
+    Show,
+}
+
+impl CommandCompleteTag {
+    /// Tags of commands without a row count are sent without a number
+    pub fn has_rows(&self) -> bool {
+        match self {
+            Self::Select | Self::Fetch | Self::Move | Self::Copy => true,
+            Self::DeclareCursor
+            | Self::CloseCursor
+            | Self::Begin
+            | Self::Commit
+            | Self::Rollback
+            | Self::Savepoint
+            | Self::Release
+            | Self::Prepare
+            | Self::Deallocate
+            | Self::DeallocateAll
+            | Self::Set
+            | Self::Show => false,
+        }
+    }
 }
 
 impl Display for CommandCompleteTag {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let string = match self {
             Self::Select => "SELECT",
+            Self::Fetch => "FETCH",
+            Self::Move => "MOVE",
+            Self::Copy => "COPY",
+            Self::DeclareCursor => "DECLARE CURSOR",
+            Self::CloseCursor => "CLOSE CURSOR",
+            Self::Begin => "BEGIN",
+            Self::Commit => "COMMIT",
+            Self::Rollback => "ROLLBACK",
+            Self::Savepoint => "SAVEPOINT",
+            Self::Release => "RELEASE",
+            Self::Prepare => "PREPARE",
+            Self::Deallocate => "DEALLOCATE",
+            Self::DeallocateAll => "DEALLOCATE ALL",
+            Self::Set => "SET",
+            Self::Show => "SHOW",
         };
         write!(f, "{}", string)
     }
@@ -544,17 +1063,39 @@ impl Display for CommandCompleteTag {
 pub enum AuthenticationRequest {
     Ok,
     CleartextPassword,
+    /// AuthenticationSASL with the list of supported mechanisms
+    SASLMechanisms(Vec<String>),
+    /// AuthenticationSASLContinue with the mechanism specific data
+    SASLContinue(Vec<u8>),
+    /// AuthenticationSASLFinal with the mechanism specific data
+    SASLFinal(Vec<u8>),
 }
 
 impl AuthenticationRequest {
     pub fn to_bytes(&self) -> Vec<u8> {
-        self.to_code().to_be_bytes().to_vec()
+        let mut bytes = self.to_code().to_be_bytes().to_vec();
+        match self {
+            Self::SASLMechanisms(mechanisms) => {
+                for mechanism in mechanisms {
+                    bytes.extend_from_slice(mechanism.as_bytes());
+                    bytes.push(0);
+                }
+                bytes.push(0);
+            }
+            Self::SASLContinue(data) | Self::SASLFinal(data) => bytes.extend_from_slice(data),
+            Self::Ok | Self::CleartextPassword => {}
+        }
+
+        bytes
     }
 
     pub fn to_code(&self) -> u32 {
         match self {
             Self::Ok => 0,
             Self::CleartextPassword => 3,
+            Self::SASLMechanisms(_) => 10,
+            Self::SASLContinue(_) => 11,
+            Self::SASLFinal(_) => 12,
         }
     }
 }
@@ -658,7 +1199,7 @@ mod tests {
                             Some(vec![116, 101, 115, 116]),
                             Some(vec![116, 114, 117, 101]),
                         ],
-                        result_formats: vec![]
+                        result_formats: vec![Format::Text]
                     },
                 )
             }
@@ -668,6 +1209,53 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_frontend_message_parse_bind_result_formats() -> Result<(), CubeError> {
+        let buffer = parse_hex_dump(
+            r#"
+            42 00 00 00 10 00 00 00 00 00 00 00 02 00 00 00   B...............
+            01                                                .
+            "#
+            .to_string(),
+        );
+        let mut cursor = Cursor::new(buffer);
+
+        let message = read_message(&mut cursor).await?;
+        match message {
+            FrontendMessage::Bind(bind) => {
+                assert_eq!(bind.result_formats, vec![Format::Text, Format::Binary])
+            }
+            _ => panic!("Wrong message, must be Bind"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_parse_execute() -> Result<(), CubeError> {
+        let buffer = parse_hex_dump(
+            r#"
+            45 00 00 00 09 00 00 00 00 0a                     E.........
+            "#
+            .to_string(),
+        );
+        let mut cursor = Cursor::new(buffer);
+
+        let message = read_message(&mut cursor).await?;
+        match message {
+            FrontendMessage::Execute(execute) => assert_eq!(
+                execute,
+                Execute {
+                    portal: "".to_string(),
+                    max_rows: 10,
+                },
+            ),
+            _ => panic!("Wrong message, must be Execute"),
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_frontend_message_parse_describe() -> Result<(), CubeError> {
         let buffer = parse_hex_dump(
@@ -739,4 +1327,74 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_command_complete_tags() {
+        let serialize = |tag, rows| {
+            String::from_utf8(CommandComplete::new(tag, rows).serialize().unwrap()).unwrap()
+        };
+
+        assert_eq!(serialize(CommandCompleteTag::Fetch, 10), "FETCH 10\0");
+        assert_eq!(serialize(CommandCompleteTag::Move, 0), "MOVE 0\0");
+        assert_eq!(serialize(CommandCompleteTag::Copy, 3), "COPY 3\0");
+        assert_eq!(
+            serialize(CommandCompleteTag::DeclareCursor, 0),
+            "DECLARE CURSOR\0"
+        );
+        assert_eq!(
+            serialize(CommandCompleteTag::CloseCursor, 0),
+            "CLOSE CURSOR\0"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_frontend_message_parse_sasl_initial_response() -> Result<(), CubeError> {
+        let buffer = parse_hex_dump(
+            r#"
+            70 00 00 00 32 53 43 52 41 4d 2d 53 48 41 2d 32   p...2SCRAM-SHA-2
+            35 36 00 00 00 00 1c 6e 2c 2c 6e 3d 2c 72 3d 72   56.....n,,n=,r=r
+            4f 70 72 4e 47 66 77 45 62 65 52 57 67 62 4e 45   OprNGfwEbeRWgbNE
+            6b 71 4f                                          kqO
+            "#
+            .to_string(),
+        );
+        let mut cursor = Cursor::new(buffer);
+
+        let message: SASLInitialResponse = buffer::read_typed_message(&mut cursor, b'p').await?;
+        assert_eq!(
+            message,
+            SASLInitialResponse {
+                mechanism: "SCRAM-SHA-256".to_string(),
+                data: b"n,,n=,r=rOprNGfwEbeRWgbNEkqO".to_vec(),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_notice_response_serialize() {
+        assert_eq!(
+            NoticeResponse::new(
+                ErrorSeverity::Warning,
+                ErrorCode::AdminShutdown,
+                "bye".to_string()
+            )
+            .serialize()
+            .unwrap(),
+            b"SWARNING\0VWARNING\0C57P01\0Mbye\0\0".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_authentication_sasl_serialize() {
+        assert_eq!(
+            AuthenticationRequest::SASLMechanisms(vec!["SCRAM-SHA-256".to_string()]).to_bytes(),
+            b"\0\0\0\x0aSCRAM-SHA-256\0\0".to_vec()
+        );
+        assert_eq!(
+            AuthenticationRequest::SASLFinal(b"v=abc".to_vec()).to_bytes(),
+            b"\0\0\0\x0cv=abc".to_vec()
+        );
+    }
 }
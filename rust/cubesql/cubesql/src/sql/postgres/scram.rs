@@ -0,0 +1,254 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::CubeError;
+
+/// The only SASL mechanism, which is supported by the server. Channel binding
+/// (SCRAM-SHA-256-PLUS) is not advertised.
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+
+const ITERATIONS: u32 = 4096;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Server side of the SCRAM-SHA-256 exchange, https://datatracker.ietf.org/doc/html/rfc7677
+pub struct ScramServer {
+    password: String,
+    salt: Vec<u8>,
+    iterations: u32,
+    server_nonce: String,
+    // Filled by server_first
+    gs2_header: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+}
+
+impl ScramServer {
+    pub fn new(password: String) -> Self {
+        let mut salt = vec![0; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let mut nonce = [0; 18];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        Self::with_salt(password, salt, base64::encode(nonce), ITERATIONS)
+    }
+
+    fn with_salt(password: String, salt: Vec<u8>, server_nonce: String, iterations: u32) -> Self {
+        Self {
+            password,
+            salt,
+            iterations,
+            server_nonce,
+            gs2_header: String::new(),
+            client_first_bare: String::new(),
+            server_first: String::new(),
+            nonce: String::new(),
+        }
+    }
+
+    /// Handles client-first-message, returns server-first-message for AuthenticationSASLContinue
+    pub fn server_first(&mut self, client_first: &[u8]) -> Result<Vec<u8>, CubeError> {
+        let client_first = to_str(client_first)?;
+
+        // gs2-header is "cbind-flag,[authzid],", the user name is taken from the startup message
+        let mut parts = client_first.splitn(3, ',');
+        let (cbind_flag, authzid, client_first_bare) =
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(cbind_flag), Some(authzid), Some(bare)) => (cbind_flag, authzid, bare),
+                _ => return Err(malformed("client-first-message")),
+            };
+        match cbind_flag {
+            "n" | "y" => {}
+            flag if flag.starts_with("p=") => {
+                return Err(CubeError::user(
+                    "SCRAM channel binding is not supported".to_string(),
+                ))
+            }
+            _ => return Err(malformed("client-first-message")),
+        }
+        if !authzid.is_empty() && !authzid.starts_with("a=") {
+            return Err(malformed("client-first-message"));
+        }
+
+        let client_nonce =
+            attribute(client_first_bare, 'r').ok_or_else(|| malformed("client-first-message"))?;
+        if client_nonce.is_empty() {
+            return Err(malformed("client-first-message"));
+        }
+
+        self.gs2_header = format!("{},{},", cbind_flag, authzid);
+        self.client_first_bare = client_first_bare.to_string();
+        self.nonce = format!("{}{}", client_nonce, self.server_nonce);
+        self.server_first = format!(
+            "r={},s={},i={}",
+            self.nonce,
+            base64::encode(&self.salt),
+            self.iterations
+        );
+
+        Ok(self.server_first.as_bytes().to_vec())
+    }
+
+    /// Handles client-final-message, returns server-final-message for AuthenticationSASLFinal,
+    /// Ok(None) is returned if the proof doesn't match the password
+    pub fn server_final(&self, client_final: &[u8]) -> Result<Option<Vec<u8>>, CubeError> {
+        let client_final = to_str(client_final)?;
+
+        let proof_position = client_final
+            .rfind(",p=")
+            .ok_or_else(|| malformed("client-final-message"))?;
+        let client_final_without_proof = &client_final[..proof_position];
+        let proof = base64::decode(&client_final[proof_position + 3..])
+            .map_err(|_| malformed("client-final-message"))?;
+
+        let channel_binding = attribute(client_final_without_proof, 'c')
+            .and_then(|c| base64::decode(c).ok())
+            .ok_or_else(|| malformed("client-final-message"))?;
+        if channel_binding != self.gs2_header.as_bytes() {
+            return Err(CubeError::user(
+                "SCRAM channel binding check failed".to_string(),
+            ));
+        }
+        if attribute(client_final_without_proof, 'r') != Some(self.nonce.as_str()) {
+            return Err(CubeError::user("SCRAM nonce doesn't match".to_string()));
+        }
+
+        let salted_password = hi(self.password.as_bytes(), &self.salt, self.iterations);
+        let client_key = hmac(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let auth_message = format!(
+            "{},{},{}",
+            self.client_first_bare, self.server_first, client_final_without_proof
+        );
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+
+        if proof.len() != client_signature.len() {
+            return Ok(None);
+        }
+        // ClientKey is restored from the proof, its hash must match StoredKey
+        let proof_key = proof
+            .iter()
+            .zip(client_signature.iter())
+            .map(|(p, s)| p ^ s)
+            .collect::<Vec<_>>();
+        if Sha256::digest(&proof_key) != stored_key {
+            return Ok(None);
+        }
+
+        let server_key = hmac(&salted_password, b"Server Key");
+        let server_signature = hmac(&server_key, auth_message.as_bytes());
+
+        Ok(Some(
+            format!("v={}", base64::encode(server_signature)).into_bytes(),
+        ))
+    }
+}
+
+fn to_str(message: &[u8]) -> Result<&str, CubeError> {
+    std::str::from_utf8(message)
+        .map_err(|_| CubeError::user("SCRAM message is not valid UTF-8".to_string()))
+}
+
+fn malformed(message: &str) -> CubeError {
+    CubeError::user(format!("malformed SCRAM message: invalid {}", message))
+}
+
+/// Value of the `name=value` attribute in the comma-separated list
+fn attribute(message: &str, name: char) -> Option<&str> {
+    message.split(',').find_map(|part| {
+        let mut chars = part.chars();
+        match (chars.next(), chars.next()) {
+            (Some(n), Some('=')) if n == name => Some(&part[2..]),
+            _ => None,
+        }
+    })
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+
+    let mut result = [0; 32];
+    result.copy_from_slice(&mac.finalize().into_bytes());
+    result
+}
+
+/// Hi() from RFC 5802, it's PBKDF2 with HMAC-SHA-256 and a single block
+fn hi(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&1_u32.to_be_bytes());
+
+    let mut u = hmac(password, &block);
+    let mut result = u;
+    for _ in 1..iterations {
+        u = hmac(password, &u);
+        for (r, x) in result.iter_mut().zip(u.iter()) {
+            *r ^= x;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://datatracker.ietf.org/doc/html/rfc7677#section-3
+    fn rfc_server() -> ScramServer {
+        ScramServer::with_salt(
+            "pencil".to_string(),
+            base64::decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap(),
+            "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0".to_string(),
+            4096,
+        )
+    }
+
+    #[test]
+    fn test_scram_exchange() {
+        let mut server = rfc_server();
+
+        let server_first = server
+            .server_first(b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(server_first).unwrap(),
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+        );
+
+        let server_final = server
+            .server_final(b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(server_final).unwrap(),
+            "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4="
+        );
+    }
+
+    #[test]
+    fn test_scram_wrong_proof() {
+        let mut server = rfc_server();
+        server
+            .server_first(b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO")
+            .unwrap();
+
+        let result = server
+            .server_final(b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=AAAAZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=")
+            .unwrap();
+        assert_eq!(result, None);
+
+        // Nonce of another exchange
+        assert!(server
+            .server_final(
+                b"c=biws,r=rOprNGfwEbeRWgbNEkqO,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+            )
+            .is_err());
+        assert!(server
+            .server_first(b"p=tls-server-end-point,,n=,r=abc")
+            .is_err());
+        assert!(server.server_first(b"n=user,r=abc").is_err());
+    }
+}
@@ -1,25 +1,54 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use log::error;
+use log::{error, info};
 use tokio::{
     net::TcpListener,
     sync::{watch, RwLock},
+    time::Instant,
 };
 
 use crate::{
-    config::processing_loop::ProcessingLoop,
+    config::{
+        processing_loop::ProcessingLoop, PostgresAuthMethod, PostgresTlsConfig,
+        PostgresUnixSocketConfig,
+    },
     sql::{session::DatabaseProtocol, SessionManager},
     CubeError,
 };
 
-use super::shim::AsyncPostgresShim;
+use super::{
+    shim::AsyncPostgresShim,
+    tls::{create_tls_acceptor, PgStream},
+    unix_socket::{accept_unix_socket, UnixSocketListener},
+};
+
+/// Connections are checked with this interval while the server is waiting for them to close
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Time for terminated connections to send the error and close
+const SHUTDOWN_TERMINATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stages of the shutdown, which are observed by connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownState {
+    Running,
+    /// New connections are not accepted, connections are closed after their work
+    Draining,
+    /// The grace period is over, connections are closed with admin shutdown errors
+    Terminating,
+}
 
 pub struct PostgresServer {
     // options
     address: String,
+    tls: Option<PostgresTlsConfig>,
+    unix_socket: Option<PostgresUnixSocketConfig>,
+    auth_method: PostgresAuthMethod,
+    max_prepared_statements: usize,
+    shutdown_grace_period: u64,
     close_socket_rx: RwLock<watch::Receiver<bool>>,
     close_socket_tx: watch::Sender<bool>,
+    shutdown_tx: watch::Sender<ShutdownState>,
     // reference
     session_manager: Arc<SessionManager>,
 }
@@ -30,22 +59,45 @@ crate::di_service!(PostgresServer, []);
 impl ProcessingLoop for PostgresServer {
     async fn processing_loop(&self) -> Result<(), CubeError> {
         let listener = TcpListener::bind(self.address.clone()).await?;
+        // Certificates are loaded once, errors in them stop the listener
+        let tls_acceptor = match &self.tls {
+            Some(tls) => Some(Arc::new(create_tls_acceptor(tls)?)),
+            None => None,
+        };
+
+        let unix_listener = match &self.unix_socket {
+            Some(unix_socket) => Some(UnixSocketListener::bind(unix_socket)?),
+            None => None,
+        };
 
         println!("🔗 Cube SQL (pg) is listening on {}", self.address);
+        if let Some(unix_socket) = &self.unix_socket {
+            println!("🔗 Cube SQL (pg) is listening on {}", unix_socket.path);
+        }
 
         loop {
             let mut stop_receiver = self.close_socket_rx.write().await;
-            let (socket, _) = tokio::select! {
+            let (socket, host) = tokio::select! {
                 res = stop_receiver.changed() => {
                     if res.is_err() || *stop_receiver.borrow() {
-                        return Ok(());
+                        break;
                     } else {
                         continue;
                     }
                 }
                 accept_res = listener.accept() => {
                     match accept_res {
-                        Ok(res) => res,
+                        Ok((socket, addr)) => (PgStream::Plain(socket), addr.to_string()),
+                        Err(err) => {
+                            error!("Network error: {}", err);
+                            continue;
+                        }
+                    }
+                }
+                accept_res = accept_unix_socket(&unix_listener) => {
+                    match accept_res {
+                        // The same as the host of local connections in logs of PostgreSQL
+                        Ok(socket) => (socket, "[local]".to_string()),
                         Err(err) => {
                             error!("Network error: {}", err);
                             continue;
@@ -54,17 +106,39 @@ impl ProcessingLoop for PostgresServer {
                 }
             };
 
-            let session = self.session_manager.create_session(
-                DatabaseProtocol::PostgreSQL,
-                socket.peer_addr().unwrap().to_string(),
-            );
+            let session = self
+                .session_manager
+                .create_session(DatabaseProtocol::PostgreSQL, host);
 
+            // TLS is not used over unix domain sockets, as in PostgreSQL
+            let tls_acceptor = match socket {
+                PgStream::Plain(_) => tls_acceptor.clone(),
+                _ => None,
+            };
+            let auth_method = self.auth_method;
+            let max_prepared_statements = self.max_prepared_statements;
+            let shutdown = self.shutdown_tx.subscribe();
             tokio::spawn(async move {
-                if let Err(e) = AsyncPostgresShim::run_on(socket, session).await {
+                if let Err(e) = AsyncPostgresShim::run_on(
+                    socket,
+                    session,
+                    tls_acceptor,
+                    auth_method,
+                    max_prepared_statements,
+                    shutdown,
+                )
+                .await
+                {
                     error!("Error during processing PostgreSQL connection: {}", e);
                 }
             });
         }
+
+        drop(listener);
+        drop(unix_listener);
+        self.shutdown().await;
+
+        Ok(())
     }
 
     async fn stop_processing(&self) -> Result<(), CubeError> {
@@ -74,13 +148,71 @@ impl ProcessingLoop for PostgresServer {
 }
 
 impl PostgresServer {
-    pub fn new(address: String, session_manager: Arc<SessionManager>) -> Arc<Self> {
+    pub fn new(
+        address: String,
+        tls: Option<PostgresTlsConfig>,
+        unix_socket: Option<PostgresUnixSocketConfig>,
+        auth_method: PostgresAuthMethod,
+        max_prepared_statements: usize,
+        shutdown_grace_period: u64,
+        session_manager: Arc<SessionManager>,
+    ) -> Arc<Self> {
         let (close_socket_tx, close_socket_rx) = watch::channel(false);
+        let (shutdown_tx, _) = watch::channel(ShutdownState::Running);
         Arc::new(Self {
             address,
+            tls,
+            unix_socket,
+            auth_method,
+            max_prepared_statements,
+            shutdown_grace_period,
             session_manager,
             close_socket_rx: RwLock::new(close_socket_rx),
             close_socket_tx,
+            shutdown_tx,
         })
     }
+
+    /// Waits up to the grace period for connections to finish their work, the rest of them
+    /// are terminated
+    async fn shutdown(&self) {
+        let grace_period = Duration::from_secs(self.shutdown_grace_period);
+        if !self
+            .wait_connections(grace_period, ShutdownState::Draining)
+            .await
+        {
+            info!(
+                "[pg] Terminating {} connection(s) after the shutdown grace period",
+                self.connections()
+            );
+            self.wait_connections(SHUTDOWN_TERMINATION_TIMEOUT, ShutdownState::Terminating)
+                .await;
+        }
+    }
+
+    /// Moves connections to `state` and waits for them to close, false is returned if some
+    /// connections are still open after `timeout`
+    async fn wait_connections(&self, timeout: Duration, state: ShutdownState) -> bool {
+        if self.connections() == 0 {
+            return true;
+        }
+        // There are no receivers if all connections are closed already
+        self.shutdown_tx.send(state).ok();
+
+        let deadline = Instant::now() + timeout;
+        while self.connections() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+
+            tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+        }
+
+        true
+    }
+
+    fn connections(&self) -> usize {
+        self.session_manager
+            .count_sessions(&DatabaseProtocol::PostgreSQL)
+    }
 }
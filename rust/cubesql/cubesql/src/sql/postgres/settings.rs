@@ -0,0 +1,1189 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono_tz::Tz;
+use sqlparser::{ast, tokenizer::Token};
+
+use log::debug;
+
+use crate::{sql::timezone::parse_time_zone, CubeError};
+
+use super::tokens::TokenParser;
+
+/// Commands for run-time parameters of the session
+#[derive(Debug, PartialEq)]
+pub enum SettingCommand {
+    /// SET [SESSION | LOCAL] name { TO | = } { value | DEFAULT }, None is DEFAULT
+    Set {
+        name: String,
+        value: Option<String>,
+        local: bool,
+    },
+    /// RESET { name | ALL }, None is RESET ALL
+    Reset { name: Option<String> },
+    /// SHOW name
+    Show { name: String },
+    /// SHOW ALL
+    ShowAll,
+}
+
+/// Parses `query` as a setting command, Ok(None) is returned for other statements
+/// (SET TRANSACTION, SET SESSION AUTHORIZATION)
+pub fn parse_setting_command(query: &str) -> Result<Option<SettingCommand>, CubeError> {
+    let mut parser = TokenParser::new(query)?;
+
+    let command = match parser.next_keyword().as_deref() {
+        Some("SET") => {
+            let local = parser.parse_keyword("LOCAL");
+            if !local {
+                parser.parse_keyword("SESSION");
+            }
+            match parser.peek_keyword().as_deref() {
+                Some("TRANSACTION") | Some("AUTHORIZATION") | Some("CHARACTERISTICS") => {
+                    return Ok(None)
+                }
+                _ => {}
+            }
+
+            let time_zone = parser.parse_keyword("TIME");
+            let name = if time_zone {
+                parser.expect_keyword("ZONE")?;
+                "timezone".to_string()
+            } else {
+                let name = parse_setting_name(&mut parser)?;
+                if !parser.parse_token(&Token::Eq) {
+                    parser.expect_keyword("TO")?;
+                }
+                name
+            };
+            // SET TIME ZONE LOCAL is the same as DEFAULT
+            let value = if parser.parse_keyword("DEFAULT")
+                || (time_zone && parser.parse_keyword("LOCAL"))
+            {
+                None
+            } else {
+                Some(parse_setting_value(&mut parser)?)
+            };
+
+            SettingCommand::Set { name, value, local }
+        }
+        Some("RESET") => {
+            let name = if parser.parse_keyword("ALL") {
+                None
+            } else {
+                Some(parse_setting_name(&mut parser)?)
+            };
+
+            SettingCommand::Reset { name }
+        }
+        Some("SHOW") => {
+            let name = match parser.peek_keyword().as_deref() {
+                Some("ALL") => {
+                    parser.next_keyword();
+                    parser.expect_end()?;
+                    return Ok(Some(SettingCommand::ShowAll));
+                }
+                Some("TIME") => {
+                    parser.next_keyword();
+                    parser.expect_keyword("ZONE")?;
+                    "timezone".to_string()
+                }
+                _ => parse_setting_name(&mut parser)?,
+            };
+
+            SettingCommand::Show { name }
+        }
+        _ => return Ok(None),
+    };
+    parser.expect_end()?;
+
+    Ok(Some(command))
+}
+
+/// Setting commands, which were parsed by the SQL parser (the extended query protocol)
+pub fn setting_command_from_statement(
+    stmt: &ast::Statement,
+) -> Result<Option<SettingCommand>, CubeError> {
+    match stmt {
+        ast::Statement::SetVariable { .. } | ast::Statement::ShowVariable { .. } => {
+            parse_setting_command(&stmt.to_string())
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Names of custom parameters are qualified (`app.user_id`)
+fn parse_setting_name(parser: &mut TokenParser) -> Result<String, CubeError> {
+    let mut name = parser.parse_name()?;
+    while parser.parse_token(&Token::Period) {
+        name.push('.');
+        name.push_str(&parser.parse_name()?);
+    }
+
+    Ok(name)
+}
+
+/// Value in the text representation, a list is joined by commas (`search_path`)
+fn parse_setting_value(parser: &mut TokenParser) -> Result<String, CubeError> {
+    let mut values = vec![];
+    loop {
+        let value = match parser.next_token() {
+            Some(Token::SingleQuotedString(value)) => value,
+            Some(Token::Number(number, _)) => number,
+            Some(Token::Minus) => match parser.next_token() {
+                Some(Token::Number(number, _)) => format!("-{}", number),
+                _ => return Err(parser.expected("number")),
+            },
+            Some(Token::Word(word)) => match word.quote_style {
+                Some(_) => word.value,
+                None => word.value.to_lowercase(),
+            },
+            _ => return Err(parser.expected("parameter value")),
+        };
+        values.push(value);
+
+        if !parser.parse_token(&Token::Comma) {
+            break;
+        }
+    }
+
+    Ok(values.join(", "))
+}
+
+/// Type of the parameter, values are validated and normalized by it
+#[derive(Debug, Clone, Copy)]
+pub enum SettingType {
+    /// `on` or `off`
+    Bool,
+    /// Integer in the range
+    Integer(i64, i64),
+    String,
+    /// One of lowercase values
+    Enum(&'static [&'static str]),
+    /// Milliseconds, they are shown in the largest exact unit
+    Duration,
+    /// Name of a time zone, see `parse_time_zone`
+    TimeZone,
+}
+
+impl SettingType {
+    /// Name of the type in pg_settings
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SettingType::Bool => "bool",
+            SettingType::Integer(_, _) | SettingType::Duration => "integer",
+            SettingType::String | SettingType::TimeZone => "string",
+            SettingType::Enum(_) => "enum",
+        }
+    }
+}
+
+/// Run-time parameter, which is known by the server
+#[derive(Debug)]
+pub struct SettingDefinition {
+    pub name: &'static str,
+    pub setting_type: SettingType,
+    pub default: &'static str,
+    pub unit: Option<&'static str>,
+    pub category: &'static str,
+    pub description: &'static str,
+    /// Read-only parameters describe the server (`internal` context of PostgreSQL)
+    pub read_only: bool,
+    /// The value is reported to the client in ParameterStatus on the startup
+    pub report: bool,
+}
+
+impl SettingDefinition {
+    const fn new(
+        name: &'static str,
+        setting_type: SettingType,
+        default: &'static str,
+        category: &'static str,
+        description: &'static str,
+    ) -> Self {
+        let unit = match setting_type {
+            SettingType::Duration => Some("ms"),
+            _ => None,
+        };
+
+        Self {
+            name,
+            setting_type,
+            default,
+            unit,
+            category,
+            description,
+            read_only: false,
+            report: false,
+        }
+    }
+
+    const fn unit(mut self, unit: &'static str) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    const fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    const fn report(mut self) -> Self {
+        self.report = true;
+        self
+    }
+
+    /// Context of the parameter in pg_settings
+    pub fn context(&self) -> &'static str {
+        if self.read_only {
+            "internal"
+        } else {
+            "user"
+        }
+    }
+}
+
+const CATEGORY_COMPATIBILITY: &str =
+    "Version and Platform Compatibility / Previous PostgreSQL Versions";
+const CATEGORY_LOCALE: &str = "Client Connection Defaults / Locale and Formatting";
+const CATEGORY_LOGGING: &str = "Reporting and Logging / What to Log";
+const CATEGORY_MEMORY: &str = "Resource Usage / Memory";
+const CATEGORY_PLANNER: &str = "Query Tuning / Other Planner Options";
+const CATEGORY_PRESET: &str = "Preset Options";
+const CATEGORY_STATEMENT: &str = "Client Connection Defaults / Statement Behavior";
+
+/// Category of parameters, which are not known by the server (`app.user_id`)
+const CATEGORY_CUSTOM: &str = "Customized Options";
+
+const ISOLATION_LEVELS: &[&str] = &[
+    "serializable",
+    "repeatable read",
+    "read committed",
+    "read uncommitted",
+];
+
+const MAX_INTEGER: i64 = i32::MAX as i64;
+
+/// Parameters, which are known by the server, they are sorted by name
+pub const SETTINGS: &[SettingDefinition] = &[
+    SettingDefinition::new(
+        "application_name",
+        SettingType::String,
+        "",
+        CATEGORY_LOGGING,
+        "Sets the application name to be reported in statistics and logs.",
+    )
+    .report(),
+    SettingDefinition::new(
+        "bytea_output",
+        SettingType::Enum(&["escape", "hex"]),
+        "hex",
+        CATEGORY_STATEMENT,
+        "Sets the output format for bytea.",
+    ),
+    SettingDefinition::new(
+        "client_encoding",
+        SettingType::String,
+        "UTF8",
+        CATEGORY_LOCALE,
+        "Sets the client's character set encoding.",
+    )
+    .report(),
+    SettingDefinition::new(
+        "client_min_messages",
+        SettingType::Enum(&[
+            "debug5", "debug4", "debug3", "debug2", "debug1", "log", "notice", "warning", "error",
+        ]),
+        "notice",
+        CATEGORY_STATEMENT,
+        "Sets the message levels that are sent to the client.",
+    ),
+    SettingDefinition::new(
+        "DateStyle",
+        SettingType::String,
+        "ISO, MDY",
+        CATEGORY_LOCALE,
+        "Sets the display format for date and time values.",
+    )
+    .report(),
+    SettingDefinition::new(
+        "default_transaction_isolation",
+        SettingType::Enum(ISOLATION_LEVELS),
+        "read committed",
+        CATEGORY_STATEMENT,
+        "Sets the transaction isolation level of each new transaction.",
+    ),
+    SettingDefinition::new(
+        "default_transaction_read_only",
+        SettingType::Bool,
+        "off",
+        CATEGORY_STATEMENT,
+        "Sets the default read-only status of new transactions.",
+    ),
+    SettingDefinition::new(
+        "extra_float_digits",
+        SettingType::Integer(-15, 3),
+        "1",
+        CATEGORY_LOCALE,
+        "Sets the number of digits displayed for floating-point values.",
+    ),
+    SettingDefinition::new(
+        "idle_in_transaction_session_timeout",
+        SettingType::Duration,
+        "0",
+        CATEGORY_STATEMENT,
+        "Sets the maximum allowed idle time between queries, when in a transaction.",
+    ),
+    SettingDefinition::new(
+        "idle_session_timeout",
+        SettingType::Duration,
+        "0",
+        CATEGORY_STATEMENT,
+        "Sets the maximum allowed idle time between queries, when not in a transaction.",
+    ),
+    SettingDefinition::new(
+        "integer_datetimes",
+        SettingType::Bool,
+        "on",
+        CATEGORY_PRESET,
+        "Shows whether datetimes are integer based.",
+    )
+    .read_only()
+    .report(),
+    SettingDefinition::new(
+        "IntervalStyle",
+        SettingType::Enum(&["postgres", "postgres_verbose", "sql_standard", "iso_8601"]),
+        "postgres",
+        CATEGORY_LOCALE,
+        "Sets the display format for interval values.",
+    )
+    .report(),
+    SettingDefinition::new(
+        "is_superuser",
+        SettingType::Bool,
+        "off",
+        CATEGORY_PRESET,
+        "Shows whether the current user is a superuser.",
+    )
+    .read_only()
+    .report(),
+    SettingDefinition::new(
+        "jit",
+        SettingType::Bool,
+        "off",
+        CATEGORY_PLANNER,
+        "Allow JIT compilation.",
+    ),
+    SettingDefinition::new(
+        "lc_messages",
+        SettingType::String,
+        "C",
+        CATEGORY_LOCALE,
+        "Sets the language in which messages are displayed.",
+    ),
+    SettingDefinition::new(
+        "lc_monetary",
+        SettingType::String,
+        "C",
+        CATEGORY_LOCALE,
+        "Sets the locale for formatting monetary amounts.",
+    ),
+    SettingDefinition::new(
+        "lc_numeric",
+        SettingType::String,
+        "C",
+        CATEGORY_LOCALE,
+        "Sets the locale for formatting numbers.",
+    ),
+    SettingDefinition::new(
+        "lc_time",
+        SettingType::String,
+        "C",
+        CATEGORY_LOCALE,
+        "Sets the locale for formatting date and time values.",
+    ),
+    SettingDefinition::new(
+        "lock_timeout",
+        SettingType::Duration,
+        "0",
+        CATEGORY_STATEMENT,
+        "Sets the maximum allowed duration of any wait for a lock.",
+    ),
+    SettingDefinition::new(
+        "max_identifier_length",
+        SettingType::Integer(63, 63),
+        "63",
+        CATEGORY_PRESET,
+        "Shows the maximum identifier length.",
+    )
+    .read_only(),
+    SettingDefinition::new(
+        "row_security",
+        SettingType::Bool,
+        "on",
+        CATEGORY_STATEMENT,
+        "Enable row security.",
+    ),
+    SettingDefinition::new(
+        "search_path",
+        SettingType::String,
+        "\"$user\", public",
+        CATEGORY_STATEMENT,
+        "Sets the schema search order for names that are not schema-qualified.",
+    ),
+    SettingDefinition::new(
+        "server_encoding",
+        SettingType::String,
+        "UTF8",
+        CATEGORY_PRESET,
+        "Shows the server (database) character set encoding.",
+    )
+    .read_only()
+    .report(),
+    SettingDefinition::new(
+        "server_version",
+        SettingType::String,
+        "14.2 (Cube SQL)",
+        CATEGORY_PRESET,
+        "Shows the server version.",
+    )
+    .read_only()
+    .report(),
+    SettingDefinition::new(
+        "server_version_num",
+        SettingType::Integer(140002, 140002),
+        "140002",
+        CATEGORY_PRESET,
+        "Shows the server version as an integer.",
+    )
+    .read_only(),
+    SettingDefinition::new(
+        "slow_query_log_min_duration",
+        SettingType::Duration,
+        "0",
+        CATEGORY_LOGGING,
+        "Sets the minimum execution time above which statements will be logged.",
+    ),
+    SettingDefinition::new(
+        "standard_conforming_strings",
+        SettingType::Bool,
+        "on",
+        CATEGORY_COMPATIBILITY,
+        "Causes '...' strings to treat backslashes literally.",
+    )
+    .report(),
+    SettingDefinition::new(
+        "statement_timeout",
+        SettingType::Duration,
+        "0",
+        CATEGORY_STATEMENT,
+        "Sets the maximum allowed duration of any statement.",
+    ),
+    SettingDefinition::new(
+        "TimeZone",
+        SettingType::TimeZone,
+        "UTC",
+        CATEGORY_LOCALE,
+        "Sets the time zone for displaying and interpreting time stamps.",
+    )
+    .report(),
+    SettingDefinition::new(
+        "transaction_isolation",
+        SettingType::Enum(ISOLATION_LEVELS),
+        "read committed",
+        CATEGORY_STATEMENT,
+        "Sets the current transaction's isolation level.",
+    ),
+    SettingDefinition::new(
+        "transaction_read_only",
+        SettingType::Bool,
+        "off",
+        CATEGORY_STATEMENT,
+        "Sets the current transaction's read-only status.",
+    ),
+    SettingDefinition::new(
+        "work_mem",
+        SettingType::Integer(64, MAX_INTEGER),
+        "4096",
+        CATEGORY_MEMORY,
+        "Sets the maximum memory to be used for query workspaces.",
+    )
+    .unit("kB"),
+];
+
+/// Known parameter by the name, names are case-insensitive
+pub fn find_setting(name: &str) -> Option<&'static SettingDefinition> {
+    SETTINGS
+        .iter()
+        .find(|definition| definition.name.eq_ignore_ascii_case(name))
+}
+
+/// Source of the current value in pg_settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingSource {
+    Default,
+    /// The configuration of the server
+    Configuration,
+    /// Parameters of the startup packet
+    Client,
+    Session,
+}
+
+impl SettingSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SettingSource::Default => "default",
+            SettingSource::Configuration => "configuration file",
+            SettingSource::Client => "client",
+            SettingSource::Session => "session",
+        }
+    }
+}
+
+/// Parameter with its current value for SHOW ALL and pg_settings
+#[derive(Debug)]
+pub struct SettingInfo {
+    pub name: String,
+    /// Value as it's shown by SHOW
+    pub value: String,
+    /// Value in the unit of the parameter (`setting` of pg_settings)
+    pub setting: String,
+    /// None for parameters, which are not known by the server
+    pub definition: Option<&'static SettingDefinition>,
+    pub source: SettingSource,
+    /// The value, which is restored by RESET
+    pub reset_value: String,
+}
+
+impl SettingInfo {
+    pub fn category(&self) -> &'static str {
+        self.definition
+            .map(|definition| definition.category)
+            .unwrap_or(CATEGORY_CUSTOM)
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.definition
+            .map(|definition| definition.description)
+            .unwrap_or("")
+    }
+
+    pub fn vartype(&self) -> &'static str {
+        self.definition
+            .map(|definition| definition.setting_type.as_str())
+            .unwrap_or("string")
+    }
+
+    pub fn context(&self) -> &'static str {
+        self.definition
+            .map(|definition| definition.context())
+            .unwrap_or("user")
+    }
+
+    /// Range of integer parameters
+    pub fn range(&self) -> Option<(i64, i64)> {
+        match self.definition?.setting_type {
+            SettingType::Integer(min, max) => Some((min, max)),
+            SettingType::Duration => Some((0, MAX_INTEGER)),
+            _ => None,
+        }
+    }
+
+    /// Values of enum parameters
+    pub fn enum_values(&self) -> Option<&'static [&'static str]> {
+        match self.definition?.setting_type {
+            SettingType::Enum(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Run-time parameters of the session. Values of unknown parameters are kept as is,
+/// as clients set a lot of them. Names are case-insensitive, they are stored in lowercase.
+#[derive(Debug)]
+pub struct Settings {
+    /// Built-in defaults and defaults from the configuration of the server or the startup
+    /// packet, RESET returns to them
+    defaults: HashMap<String, (String, SettingSource)>,
+    session: HashMap<String, String>,
+    /// Values of SET LOCAL, they are discarded at the end of the transaction
+    local: HashMap<String, String>,
+    /// SET LOCAL has no effect outside of transaction blocks
+    in_transaction: bool,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self {
+            defaults: SETTINGS
+                .iter()
+                .map(|definition| {
+                    (
+                        definition.name.to_lowercase(),
+                        (definition.default.to_string(), SettingSource::Default),
+                    )
+                })
+                .collect(),
+            session: HashMap::new(),
+            local: HashMap::new(),
+            in_transaction: false,
+        }
+    }
+
+    /// Overrides the default of the parameter by the configuration of the server,
+    /// RESET returns to it
+    pub fn set_default(&mut self, name: &str, value: String) {
+        let value = normalize_value(name, &value).unwrap_or(value);
+        self.defaults
+            .insert(name.to_lowercase(), (value, SettingSource::Configuration));
+    }
+
+    /// Overrides the default of the parameter by the startup packet, as in PostgreSQL
+    pub fn set_client_default(&mut self, name: &str, value: &str) -> Result<(), CubeError> {
+        check_writable(name)?;
+        let value = normalize_value(name, value)?;
+        self.defaults
+            .insert(name.to_lowercase(), (value, SettingSource::Client));
+
+        Ok(())
+    }
+
+    /// Sets the value of the parameter, None resets it to the default
+    pub fn set(&mut self, name: &str, value: Option<&str>, local: bool) -> Result<(), CubeError> {
+        check_writable(name)?;
+        let value = match value {
+            Some(value) => Some(normalize_value(name, value)?),
+            None => None,
+        };
+        let name = name.to_lowercase();
+
+        if local {
+            // SET LOCAL outside of transaction blocks has no effect, as in PostgreSQL
+            if !self.in_transaction {
+                debug!(
+                    "[pg] SET LOCAL {} is ignored outside of transaction blocks",
+                    name
+                );
+                return Ok(());
+            }
+
+            match value.or_else(|| self.defaults.get(&name).map(|(value, _)| value.clone())) {
+                Some(value) => self.local.insert(name, value),
+                None => self.local.remove(&name),
+            };
+        } else {
+            // SET overrides SET LOCAL of the current transaction
+            self.local.remove(&name);
+            match value {
+                Some(value) => self.session.insert(name, value),
+                None => self.session.remove(&name),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Resets the parameter, None resets all parameters
+    pub fn reset(&mut self, name: Option<&str>) {
+        match name {
+            Some(name) => {
+                let name = name.to_lowercase();
+                self.session.remove(&name);
+                self.local.remove(&name);
+            }
+            None => {
+                self.session.clear();
+                self.local.clear();
+            }
+        }
+    }
+
+    /// Current value of the parameter for SHOW
+    pub fn show(&self, name: &str) -> Result<String, CubeError> {
+        self.get(name).ok_or_else(|| {
+            CubeError::user(format!("unrecognized configuration parameter \"{}\"", name))
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        let name = name.to_lowercase();
+        self.local
+            .get(&name)
+            .or_else(|| self.session.get(&name))
+            .or_else(|| self.defaults.get(&name).map(|(value, _)| value))
+            .cloned()
+    }
+
+    /// All parameters with their current values, they are sorted by name
+    pub fn all(&self) -> Vec<SettingInfo> {
+        let mut names = self
+            .defaults
+            .keys()
+            .chain(self.session.keys())
+            .chain(self.local.keys())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        names
+            .into_iter()
+            .map(|name| {
+                let definition = find_setting(name);
+                let (reset_value, default_source) = self
+                    .defaults
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_else(|| ("".to_string(), SettingSource::Session));
+                let (value, source) = match self.local.get(name).or_else(|| self.session.get(name))
+                {
+                    Some(value) => (value.clone(), SettingSource::Session),
+                    None => (reset_value.clone(), default_source),
+                };
+
+                SettingInfo {
+                    name: definition
+                        .map(|definition| definition.name.to_string())
+                        .unwrap_or_else(|| name.clone()),
+                    setting: raw_value(definition, &value),
+                    value,
+                    definition,
+                    source,
+                    reset_value: raw_value(definition, &reset_value),
+                }
+            })
+            .collect()
+    }
+
+    /// Current values of parameters, which are reported to the client on the startup
+    pub fn reported(&self) -> Vec<(String, String)> {
+        SETTINGS
+            .iter()
+            .filter(|definition| definition.report)
+            .map(|definition| {
+                (
+                    definition.name.to_string(),
+                    self.get(definition.name).unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    /// SET LOCAL is applied until the end of the transaction block
+    pub fn begin_transaction(&mut self) {
+        self.in_transaction = true;
+    }
+
+    /// Values of SET LOCAL are discarded by COMMIT and ROLLBACK
+    pub fn end_transaction(&mut self) {
+        self.in_transaction = false;
+        self.local.clear();
+    }
+
+    /// Name of the client application, it's shown in pg_stat_activity
+    pub fn application_name(&self) -> Option<String> {
+        self.get("application_name")
+    }
+
+    /// Schemas of search_path, quoted names are case-sensitive
+    pub fn search_path(&self) -> Vec<String> {
+        self.get("search_path")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|schema| !schema.is_empty())
+            .map(|schema| {
+                if schema.len() > 1 && schema.starts_with('"') && schema.ends_with('"') {
+                    schema[1..schema.len() - 1].replace("\"\"", "\"")
+                } else {
+                    schema.to_lowercase()
+                }
+            })
+            .collect()
+    }
+
+    /// Time zone of timestamps, it's passed to Cube queries with time dimensions
+    pub fn time_zone(&self) -> String {
+        self.get("TimeZone").unwrap_or_else(|| "UTC".to_string())
+    }
+
+    /// Zone of `time_zone`, timestamptz values are rendered and evaluated in it
+    pub fn session_time_zone(&self) -> Tz {
+        parse_time_zone(&self.time_zone()).unwrap_or(Tz::UTC)
+    }
+
+    /// Maximum duration of a statement, None is for no limit (0)
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        self.timeout("statement_timeout")
+    }
+
+    /// Statements, which are faster, are not written to the query log
+    pub fn slow_query_log_min_duration(&self) -> Duration {
+        self.get("slow_query_log_min_duration")
+            .and_then(|value| parse_duration(&value).ok())
+            .unwrap_or_default()
+    }
+
+    /// Maximum time of waiting for a query outside of transaction blocks
+    pub fn idle_session_timeout(&self) -> Option<Duration> {
+        self.timeout("idle_session_timeout")
+    }
+
+    /// Maximum time of waiting for a query inside of the transaction block
+    pub fn idle_in_transaction_session_timeout(&self) -> Option<Duration> {
+        self.timeout("idle_in_transaction_session_timeout")
+    }
+
+    fn timeout(&self, name: &str) -> Option<Duration> {
+        self.get(name)
+            .and_then(|value| parse_duration(&value).ok())
+            .filter(|timeout| !timeout.is_zero())
+    }
+}
+
+fn check_writable(name: &str) -> Result<(), CubeError> {
+    match find_setting(name) {
+        Some(definition) if definition.read_only => Err(CubeError::user(format!(
+            "parameter \"{}\" cannot be changed",
+            definition.name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Validates the value of known parameters
+fn normalize_value(name: &str, value: &str) -> Result<String, CubeError> {
+    let definition = match find_setting(name) {
+        Some(definition) => definition,
+        None => return Ok(value.to_string()),
+    };
+    let invalid_value = || {
+        CubeError::user(format!(
+            "invalid value for parameter \"{}\": \"{}\"",
+            definition.name, value
+        ))
+    };
+
+    match definition.setting_type {
+        SettingType::Bool => match parse_bool(value) {
+            Some(true) => Ok("on".to_string()),
+            Some(false) => Ok("off".to_string()),
+            None => Err(CubeError::user(format!(
+                "parameter \"{}\" requires a Boolean value",
+                definition.name
+            ))),
+        },
+        SettingType::Integer(min, max) => {
+            let number = value.trim().parse::<i64>().map_err(|_| invalid_value())?;
+            if number < min || number > max {
+                return Err(CubeError::user(format!(
+                    "{} is outside the valid range for parameter \"{}\" ({} .. {})",
+                    number, definition.name, min, max
+                )));
+            }
+
+            Ok(number.to_string())
+        }
+        SettingType::String => Ok(value.to_string()),
+        SettingType::Enum(values) => values
+            .iter()
+            .find(|candidate| candidate.eq_ignore_ascii_case(value.trim()))
+            .map(|candidate| candidate.to_string())
+            .ok_or_else(invalid_value),
+        SettingType::Duration => {
+            let duration = parse_duration(value).map_err(|_| invalid_value())?;
+
+            Ok(format_duration(duration))
+        }
+        SettingType::TimeZone => parse_time_zone(value)
+            .map(|tz| tz.name().to_string())
+            .ok_or_else(invalid_value),
+    }
+}
+
+/// Booleans are `on`, `off` and prefixes of `true`, `false`, `yes`, `no`, as in PostgreSQL
+fn parse_bool(value: &str) -> Option<bool> {
+    let value = value.trim().to_lowercase();
+    let is_prefix = |word: &str| !value.is_empty() && word.starts_with(&value);
+    match value.as_str() {
+        "on" | "1" => Some(true),
+        "off" | "0" => Some(false),
+        _ if is_prefix("true") || is_prefix("yes") => Some(true),
+        _ if is_prefix("false") || is_prefix("no") => Some(false),
+        _ => None,
+    }
+}
+
+/// Value in the unit of the parameter, durations are shown in milliseconds
+fn raw_value(definition: Option<&SettingDefinition>, value: &str) -> String {
+    match definition.map(|definition| definition.setting_type) {
+        Some(SettingType::Duration) => parse_duration(value)
+            .map(|duration| duration.as_millis().to_string())
+            .unwrap_or_else(|_| value.to_string()),
+        _ => value.to_string(),
+    }
+}
+
+const DURATION_UNITS: &[(&str, f64)] = &[
+    ("us", 0.001),
+    ("ms", 1.0),
+    ("s", 1_000.0),
+    ("min", 60_000.0),
+    ("h", 3_600_000.0),
+    ("d", 86_400_000.0),
+];
+
+/// Parses a duration in the format of PostgreSQL parameters (`30s`, `5 min`), numbers without
+/// a unit are milliseconds
+pub fn parse_duration(value: &str) -> Result<Duration, CubeError> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or_else(|| value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| CubeError::user(format!("invalid duration: \"{}\"", value)))?;
+    let multiplier = match unit.trim() {
+        "" => 1.0,
+        unit => DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, multiplier)| *multiplier)
+            .ok_or_else(|| CubeError::user(format!("invalid duration unit: \"{}\"", unit)))?,
+    };
+
+    let millis = (number * multiplier).round();
+    if !(0.0..=i32::MAX as f64).contains(&millis) {
+        return Err(CubeError::user(format!(
+            "duration is out of range: \"{}\"",
+            value
+        )));
+    }
+
+    Ok(Duration::from_millis(millis as u64))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis() as u64;
+    if millis == 0 {
+        return "0".to_string();
+    }
+
+    let units = [
+        ("d", 86_400_000),
+        ("h", 3_600_000),
+        ("min", 60_000),
+        ("s", 1_000),
+    ];
+    for (unit, size) in units {
+        if millis % size == 0 {
+            return format!("{}{}", millis / size, unit);
+        }
+    }
+
+    format!("{}ms", millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> SettingCommand {
+        parse_setting_command(query).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_setting_command() {
+        assert_eq!(
+            parse("SET statement_timeout = '30s'"),
+            SettingCommand::Set {
+                name: "statement_timeout".to_string(),
+                value: Some("30s".to_string()),
+                local: false,
+            }
+        );
+        assert_eq!(
+            parse("set local Statement_Timeout to 1000;"),
+            SettingCommand::Set {
+                name: "statement_timeout".to_string(),
+                value: Some("1000".to_string()),
+                local: true,
+            }
+        );
+        assert_eq!(
+            parse("SET SESSION search_path TO analytics, \"Public\""),
+            SettingCommand::Set {
+                name: "search_path".to_string(),
+                value: Some("analytics, Public".to_string()),
+                local: false,
+            }
+        );
+        assert_eq!(
+            parse("SET app.user_id = DEFAULT"),
+            SettingCommand::Set {
+                name: "app.user_id".to_string(),
+                value: None,
+                local: false,
+            }
+        );
+        assert_eq!(
+            parse("SET TIME ZONE 'UTC'"),
+            SettingCommand::Set {
+                name: "timezone".to_string(),
+                value: Some("UTC".to_string()),
+                local: false,
+            }
+        );
+        assert_eq!(
+            parse("SET TIME ZONE LOCAL"),
+            SettingCommand::Set {
+                name: "timezone".to_string(),
+                value: None,
+                local: false,
+            }
+        );
+        assert_eq!(parse("RESET ALL"), SettingCommand::Reset { name: None });
+        assert_eq!(parse("show all"), SettingCommand::ShowAll);
+        assert_eq!(
+            parse("SHOW statement_timeout"),
+            SettingCommand::Show {
+                name: "statement_timeout".to_string()
+            }
+        );
+
+        assert!(parse_setting_command("SET statement_timeout").is_err());
+        assert_eq!(
+            parse_setting_command("SET TRANSACTION READ ONLY").unwrap(),
+            None
+        );
+        assert_eq!(parse_setting_command("SELECT 1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_duration() -> Result<(), CubeError> {
+        assert_eq!(parse_duration("1500")?, Duration::from_millis(1500));
+        assert_eq!(parse_duration("30s")?, Duration::from_secs(30));
+        assert_eq!(parse_duration("1.5 min")?, Duration::from_secs(90));
+        assert_eq!(parse_duration("0")?, Duration::from_millis(0));
+        assert!(parse_duration("-1").is_err());
+        assert!(parse_duration("10 years").is_err());
+        assert!(parse_duration("abc").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings() -> Result<(), CubeError> {
+        let mut settings = Settings::new();
+        assert_eq!(settings.show("statement_timeout")?, "0");
+        assert_eq!(settings.statement_timeout(), None);
+        assert!(settings.show("unknown").is_err());
+
+        settings.set("statement_timeout", Some("60000"), false)?;
+        assert_eq!(settings.show("statement_timeout")?, "1min");
+        assert_eq!(settings.statement_timeout(), Some(Duration::from_secs(60)));
+        assert!(settings
+            .set("statement_timeout", Some("soon"), false)
+            .is_err());
+
+        // SET LOCAL has no effect outside of transaction blocks
+        settings.set("statement_timeout", Some("250ms"), true)?;
+        assert_eq!(settings.show("statement_timeout")?, "1min");
+        settings.begin_transaction();
+        settings.set("statement_timeout", Some("250ms"), true)?;
+        assert_eq!(settings.show("statement_timeout")?, "250ms");
+        settings.end_transaction();
+        assert_eq!(settings.show("statement_timeout")?, "1min");
+
+        settings.set_default("idle_session_timeout", "600s".to_string());
+        settings.set("idle_session_timeout", Some("10"), false)?;
+        assert_eq!(
+            settings.idle_session_timeout(),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(settings.idle_in_transaction_session_timeout(), None);
+
+        settings.set("application_name", Some("psql"), false)?;
+        assert_eq!(settings.show("application_name")?, "psql");
+        settings.set("app.user_id", Some("42"), false)?;
+        assert_eq!(settings.show("app.user_id")?, "42");
+        settings.reset(None);
+        assert_eq!(settings.show("statement_timeout")?, "0");
+        assert_eq!(settings.show("idle_session_timeout")?, "10min");
+        assert_eq!(settings.show("application_name")?, "");
+        assert!(settings.show("app.user_id").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_validation() -> Result<(), CubeError> {
+        let mut settings = Settings::new();
+
+        settings.set("Standard_Conforming_Strings", Some("false"), false)?;
+        assert_eq!(settings.show("standard_conforming_strings")?, "off");
+        settings.set("jit", Some("Y"), false)?;
+        assert_eq!(settings.show("jit")?, "on");
+        assert!(settings.set("jit", Some("maybe"), false).is_err());
+
+        settings.set("extra_float_digits", Some("3"), false)?;
+        assert_eq!(settings.show("extra_float_digits")?, "3");
+        assert!(settings
+            .set("extra_float_digits", Some("4"), false)
+            .is_err());
+
+        settings.set("IntervalStyle", Some("ISO_8601"), false)?;
+        assert_eq!(settings.show("intervalstyle")?, "iso_8601");
+
+        settings.set("timezone", Some("america/new_york"), false)?;
+        assert_eq!(settings.show("TimeZone")?, "America/New_York");
+        assert_eq!(settings.session_time_zone(), Tz::America__New_York);
+        settings.set("timezone", Some("-5"), false)?;
+        assert_eq!(settings.show("TimeZone")?, "Etc/GMT+5");
+        assert!(settings
+            .set("timezone", Some("Mars/Olympus_Mons"), false)
+            .is_err());
+        assert!(settings.set("bytea_output", Some("base64"), false).is_err());
+
+        assert!(settings.set("server_version", Some("15"), false).is_err());
+        assert!(settings.set_client_default("is_superuser", "on").is_err());
+        settings.set_client_default("DateStyle", "ISO")?;
+        assert_eq!(settings.show("datestyle")?, "ISO");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_all() -> Result<(), CubeError> {
+        let mut settings = Settings::new();
+        settings.set_default("statement_timeout", "10s".to_string());
+        settings.set("lock_timeout", Some("1min"), false)?;
+        settings.set("app.user_id", Some("42"), false)?;
+
+        let all = settings.all();
+        assert_eq!(all.len(), SETTINGS.len() + 1);
+
+        let find = |name: &str| all.iter().find(|info| info.name == name).unwrap();
+        let statement_timeout = find("statement_timeout");
+        assert_eq!(statement_timeout.value, "10s");
+        assert_eq!(statement_timeout.setting, "10000");
+        assert_eq!(statement_timeout.source, SettingSource::Configuration);
+        assert_eq!(statement_timeout.vartype(), "integer");
+
+        let lock_timeout = find("lock_timeout");
+        assert_eq!(lock_timeout.setting, "60000");
+        assert_eq!(lock_timeout.reset_value, "0");
+        assert_eq!(lock_timeout.source, SettingSource::Session);
+
+        let custom = find("app.user_id");
+        assert_eq!(custom.value, "42");
+        assert_eq!(custom.category(), CATEGORY_CUSTOM);
+        assert_eq!(find("TimeZone").value, "UTC");
+        assert_eq!(
+            find("bytea_output").enum_values(),
+            Some(&["escape", "hex"][..])
+        );
+
+        Ok(())
+    }
+}
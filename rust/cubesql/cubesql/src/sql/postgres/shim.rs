@@ -1,32 +1,212 @@
 use std::{
     collections::HashMap,
+    convert::TryFrom,
+    future::Future,
     io::{Error, ErrorKind},
+    ops::Range,
     sync::Arc,
+    time::Duration,
 };
 
-use datafusion::{dataframe::DataFrame, execution::dataframe_impl::DataFrameImpl};
+use chrono_tz::Tz;
 use log::{debug, error, trace};
-use tokio::{io::AsyncWriteExt, net::TcpStream};
+use lru::LruCache;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{watch, Notify},
+    time::Instant,
+};
+use tokio_rustls::TlsAcceptor;
+
+use sqlparser::ast;
 
 use crate::{
-    compile::convert_sql_to_cube_query,
+    compile::{
+        convert_statement_to_cube_query, explain::explain_analyze, parser::parse_sql_to_statement,
+        subquery::evaluate_subqueries, MetaContext, QueryPlan,
+    },
+    config::PostgresAuthMethod,
     sql::{
-        dataframe::{batch_to_dataframe, TableValue},
-        AuthContext, QueryResponse, Session,
+        dataframe::{Column, DataFrame as CubeDataFrame, Row, TableValue},
+        session::{ActivityState, DatabaseProtocol},
+        statement::{normalize_statement, placeholder_report, BindValue, Binder, StatementBinder},
+        AuthContext, AuthenticateResponse, ColumnFlags, ColumnType, QueryResponse, Session,
+        StatusFlags,
+    },
+    telemetry::{
+        metrics::METRICS,
+        query_log::{Pushdown, QueryLogRecord, QUERY_LOG},
+        tracing::{Span, SpanKind},
     },
     CubeError,
 };
 
 use super::{
     buffer,
-    protocol::{self, FrontendMessage, SSL_REQUEST_PROTOCOL},
+    copy::{parse_copy_command, CopyTo},
+    cursor::{parse_cursor_command, Cursor, CursorCommand},
+    describe::{bind_sample_values, describe_parameter_types},
+    pg_type::PgTypeId,
+    plan_cache::{marker_values, signature, PlanLookup, PlanTemplate},
+    portal::{bind_values, text_values, Portal, PreparedStatement},
+    prepared::{
+        parameter_types, parameter_values, parse_prepared_command, statement_name, PreparedCommand,
+    },
+    protocol::{
+        self, Format, FrontendMessage, TransactionStatus, CANCEL_REQUEST_CODE, SSL_REQUEST_PROTOCOL,
+    },
+    scram::{ScramServer, SCRAM_SHA_256},
+    service::ShutdownState,
+    settings::{parse_setting_command, setting_command_from_statement, SettingCommand},
+    stream::{QueryResult, ResultStream},
+    tls::PgStream,
+    tokens::split_statements,
+    transaction::{
+        parse_transaction_command, transaction_command_from_statement, Transaction,
+        TransactionCommand,
+    },
+    writer::{column_pg_type, column_type_modifier, encode_value},
 };
 
 pub struct AsyncPostgresShim {
-    socket: TcpStream,
+    socket: PgStream,
+    // it's None if TLS is not configured for the listener
+    tls_acceptor: Option<Arc<TlsAcceptor>>,
+    auth_method: PostgresAuthMethod,
     #[allow(unused)]
     parameters: HashMap<String, String>,
     session: Arc<Session>,
+    // Least recently used statements are evicted after the limit from the configuration
+    statements: LruCache<String, PreparedStatement>,
+    portals: HashMap<String, Portal>,
+    // After an error in the extended query protocol, messages are discarded until Sync
+    ignore_till_sync: bool,
+    transaction: Transaction,
+    shutdown: watch::Receiver<ShutdownState>,
+    // NoticeResponse about the shutdown was sent
+    shutdown_notified: bool,
+    // Span of the current Query or of messages of the extended query protocol until Sync
+    trace: Option<Span>,
+}
+
+/// Error during processing of a message in the extended query protocol
+enum ConnectionError {
+    /// Error which is reported to the client, the connection stays open
+    Cube(CubeError),
+    /// The statement was canceled by CancelRequest, statement_timeout or the shutdown
+    Canceled(protocol::ErrorCode, String),
+    /// IO or protocol error, the connection is closed
+    Protocol(Error),
+}
+
+impl ConnectionError {
+    fn message(&self) -> String {
+        match self {
+            ConnectionError::Cube(e) => e.message.clone(),
+            ConnectionError::Canceled(_, message) => message.clone(),
+            ConnectionError::Protocol(e) => e.to_string(),
+        }
+    }
+}
+
+impl From<CubeError> for ConnectionError {
+    fn from(e: CubeError) -> Self {
+        ConnectionError::Cube(e)
+    }
+}
+
+impl From<Error> for ConnectionError {
+    fn from(e: Error) -> Self {
+        ConnectionError::Protocol(e)
+    }
+}
+
+/// The statement in progress, it can be canceled by CancelRequest, pg_cancel_backend,
+/// pg_terminate_backend and by statement_timeout until the guard is dropped
+struct QueryGuard {
+    session: Arc<Session>,
+    cancel: Arc<Notify>,
+    deadline: Option<Instant>,
+    shutdown: watch::Receiver<ShutdownState>,
+    started: Instant,
+}
+
+impl QueryGuard {
+    fn new(
+        session: Arc<Session>,
+        timeout: Option<Duration>,
+        shutdown: watch::Receiver<ShutdownState>,
+    ) -> Self {
+        let cancel = session.state.begin_query();
+        let started = Instant::now();
+
+        Self {
+            session,
+            cancel,
+            deadline: timeout.map(|timeout| started + timeout),
+            shutdown,
+            started,
+        }
+    }
+
+    /// Time since the statement was started
+    fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Runs a step of the statement (planning or receiving of rows). Dropping the step on
+    /// cancellation aborts DataFusion and requests to the Cube API.
+    async fn run<T>(
+        &self,
+        step: impl Future<Output = Result<T, CubeError>>,
+    ) -> Result<T, ConnectionError> {
+        let deadline = self.deadline;
+        let timeout = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now));
+        tokio::select! {
+            result = step => result.map_err(ConnectionError::from),
+            _ = self.cancel.notified() => Err(ConnectionError::Canceled(
+                protocol::ErrorCode::QueryCanceled,
+                "canceling statement due to user request".to_string(),
+            )),
+            _ = timeout, if deadline.is_some() => {
+                Err(ConnectionError::Canceled(
+                    protocol::ErrorCode::QueryCanceled,
+                    "canceling statement due to statement timeout".to_string(),
+                ))
+            }
+            _ = terminated(self.shutdown.clone()) => Err(ConnectionError::Canceled(
+                protocol::ErrorCode::AdminShutdown,
+                ADMIN_SHUTDOWN_MESSAGE.to_string(),
+            )),
+            _ = self.session.state.terminated() => Err(ConnectionError::Canceled(
+                protocol::ErrorCode::AdminShutdown,
+                ADMIN_SHUTDOWN_MESSAGE.to_string(),
+            )),
+        }
+    }
+}
+
+impl Drop for QueryGuard {
+    fn drop(&mut self) {
+        self.session.state.end_query();
+    }
+}
+
+const ADMIN_SHUTDOWN_MESSAGE: &str = "terminating connection due to administrator command";
+
+/// Resolves when the state of the shutdown is changed, it never resolves if the server is
+/// dropped
+async fn shutdown_changed(shutdown: &mut watch::Receiver<ShutdownState>) {
+    if shutdown.changed().await.is_err() {
+        futures::future::pending::<()>().await;
+    }
+}
+
+/// Resolves when the server starts to terminate connections after the grace period
+async fn terminated(mut shutdown: watch::Receiver<ShutdownState>) {
+    while *shutdown.borrow() != ShutdownState::Terminating {
+        shutdown_changed(&mut shutdown).await;
+    }
 }
 
 #[derive(PartialEq, Eq)]
@@ -34,14 +214,48 @@ pub enum StartupState {
     Success,
     SslRequested,
     Denied,
+    CancelRequested,
 }
 
 impl AsyncPostgresShim {
-    pub async fn run_on(socket: TcpStream, session: Arc<Session>) -> Result<(), Error> {
+    pub async fn run_on(
+        socket: PgStream,
+        session: Arc<Session>,
+        tls_acceptor: Option<Arc<TlsAcceptor>>,
+        auth_method: PostgresAuthMethod,
+        max_prepared_statements: usize,
+        shutdown: watch::Receiver<ShutdownState>,
+    ) -> Result<(), Error> {
+        let configuration = &session.server.configuration;
+        {
+            let mut settings = session.state.settings_mut();
+            settings.set_default(
+                "idle_session_timeout",
+                format!("{}s", configuration.idle_session_timeout),
+            );
+            settings.set_default(
+                "idle_in_transaction_session_timeout",
+                format!("{}s", configuration.idle_in_transaction_session_timeout),
+            );
+            settings.set_default(
+                "slow_query_log_min_duration",
+                format!("{}ms", configuration.slow_query_log_min_duration),
+            );
+        }
+
         let mut shim = Self {
             socket,
+            tls_acceptor,
+            auth_method,
             parameters: HashMap::new(),
             session,
+            statements: LruCache::new(max_prepared_statements),
+            portals: HashMap::new(),
+            ignore_till_sync: false,
+            transaction: Transaction::new(),
+            shutdown,
+            shutdown_notified: false,
+            trace: None,
         };
         match shim.run().await {
             Err(e) => {
@@ -67,22 +281,56 @@ impl AsyncPostgresShim {
                     return Ok(());
                 }
             }
-            StartupState::Denied => return Ok(()),
+            StartupState::Denied | StartupState::CancelRequested => return Ok(()),
         }
 
-        match buffer::read_message(&mut self.socket).await? {
-            FrontendMessage::PasswordMessage(password_message) => {
-                if !self.authenticate(password_message).await? {
-                    return Ok(());
+        let authenticated = match self.auth_method {
+            PostgresAuthMethod::Password => match buffer::read_message(&mut self.socket).await? {
+                FrontendMessage::PasswordMessage(password_message) => {
+                    self.authenticate(password_message).await?
                 }
-            }
-            _ => return Ok(()),
+                _ => false,
+            },
+            PostgresAuthMethod::ScramSha256 => self.authenticate_scram().await?,
+        };
+        if !authenticated {
+            return Ok(());
         }
         self.ready().await?;
 
+        // ReadyForQuery was sent, the client is not in the middle of the extended query protocol
+        let mut ready = true;
         loop {
-            match buffer::read_message(&mut self.socket).await? {
-                FrontendMessage::Query(query) => self.process_query(query).await?,
+            let message = match self.read_message(ready).await? {
+                Some(message) => message,
+                None => return Ok(()),
+            };
+            METRICS.pg_messages.inc(message.name());
+            ready = matches!(message, FrontendMessage::Query(_) | FrontendMessage::Sync);
+            if self.ignore_till_sync
+                && !matches!(message, FrontendMessage::Sync | FrontendMessage::Terminate)
+            {
+                continue;
+            }
+            self.start_trace(&message);
+
+            let result = match message {
+                FrontendMessage::Query(query) => {
+                    self.process_query(query).await?;
+                    Ok(())
+                }
+                FrontendMessage::Parse(parse) => self.parse(parse).await,
+                FrontendMessage::Bind(bind) => self.bind(bind).await,
+                FrontendMessage::Describe(describe) => self.describe(describe).await,
+                FrontendMessage::Execute(execute) => self.execute(execute).await,
+                FrontendMessage::Close(close) => self.close(close).await,
+                FrontendMessage::Flush => Ok(()),
+                FrontendMessage::Sync => {
+                    self.ignore_till_sync = false;
+                    self.write(protocol::ReadyForQuery::new(self.transaction.status()))
+                        .await?;
+                    Ok(())
+                }
                 FrontendMessage::Terminate => return Ok(()),
                 command_id => {
                     return Err(Error::new(
@@ -90,10 +338,182 @@ impl AsyncPostgresShim {
                         format!("Unsupported operation: {:?}", command_id),
                     ))
                 }
+            };
+
+            match result {
+                Ok(()) => {}
+                Err(ConnectionError::Cube(e)) => {
+                    let error_message = e.to_string();
+                    error!("Error during processing of the message: {}", error_message);
+                    self.trace_error(&error_message);
+                    self.write_error(protocol::ErrorCode::InternalError, error_message)
+                        .await?;
+                    self.ignore_till_sync = true;
+                }
+                Err(ConnectionError::Canceled(code, message)) => {
+                    debug!("Canceled processing of the message: {}", message);
+                    self.trace_error(&message);
+                    self.write_error(code, message).await?;
+                    self.ignore_till_sync = true;
+                }
+                Err(ConnectionError::Protocol(e)) => return Err(e),
             }
+
+            if ready {
+                self.end_trace();
+            }
+        }
+    }
+
+    /// Starts the span of the query on its first message, the span of the extended query
+    /// protocol lasts until Sync
+    fn start_trace(&mut self, message: &FrontendMessage) {
+        if self.trace.is_some()
+            || matches!(
+                message,
+                FrontendMessage::Sync | FrontendMessage::Flush | FrontendMessage::Terminate
+            )
+        {
+            return;
+        }
+
+        let mut span = Span::start("query", SpanKind::Server, None);
+        span.set_attribute("db.system", "postgresql");
+        if let Some(user) = self.session.state.user() {
+            span.set_attribute("db.user", user);
+        }
+        if let FrontendMessage::Query(query) = message {
+            span.set_attribute("db.statement", &query.query);
+        }
+
+        // Requests to the Cube API, which are sent for the query, are traced under its span
+        self.session.state.set_trace_context(Some(span.context()));
+        self.trace = Some(span);
+    }
+
+    fn end_trace(&mut self) {
+        self.session.state.set_trace_context(None);
+        self.trace = None;
+    }
+
+    fn trace_error(&mut self, message: &str) {
+        if let Some(span) = &mut self.trace {
+            span.set_error(message);
         }
     }
 
+    /// Span of a step of the current query (parsing, binding, rewriting or execution)
+    fn step_span(&self, name: &'static str) -> Span {
+        Span::start(name, SpanKind::Internal, self.session.state.trace_context())
+    }
+
+    /// Waits for the next message, None is returned if the connection was closed because of
+    /// idle_session_timeout, idle_in_transaction_session_timeout, pg_terminate_backend or
+    /// the shutdown. On the shutdown, the connection is closed when the client is `ready`
+    /// outside of transaction blocks, other connections are closed after the grace period.
+    async fn read_message(&mut self, ready: bool) -> Result<Option<FrontendMessage>, Error> {
+        let (timeout, code, message) = match self.transaction.status() {
+            TransactionStatus::Idle => (
+                self.session.state.settings().idle_session_timeout(),
+                protocol::ErrorCode::IdleSessionTimeout,
+                "terminating connection due to idle-session timeout",
+            ),
+            _ => (
+                self.session
+                    .state
+                    .settings()
+                    .idle_in_transaction_session_timeout(),
+                protocol::ErrorCode::IdleInTransactionSessionTimeout,
+                "terminating connection due to idle-in-transaction timeout",
+            ),
+        };
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.session
+            .state
+            .set_idle(match self.transaction.status() {
+                TransactionStatus::Idle => ActivityState::Idle,
+                TransactionStatus::InTransactionBlock => ActivityState::IdleInTransaction,
+                TransactionStatus::InFailedTransactionBlock => {
+                    ActivityState::IdleInTransactionAborted
+                }
+            });
+
+        loop {
+            // pg_terminate_backend closes the connection immediately
+            if self.session.state.is_terminated() {
+                return self
+                    .close_connection(protocol::ErrorCode::AdminShutdown, ADMIN_SHUTDOWN_MESSAGE)
+                    .await;
+            }
+
+            let state = *self.shutdown.borrow();
+            match state {
+                ShutdownState::Running => {}
+                ShutdownState::Draining => {
+                    if !self.shutdown_notified {
+                        self.shutdown_notified = true;
+                        self.write(protocol::NoticeResponse::new(
+                            protocol::ErrorSeverity::Warning,
+                            protocol::ErrorCode::AdminShutdown,
+                            "the server is shutting down, the connection will be closed"
+                                .to_string(),
+                        ))
+                        .await?;
+                    }
+
+                    if ready && self.transaction.status() == TransactionStatus::Idle {
+                        return self
+                            .close_connection(
+                                protocol::ErrorCode::AdminShutdown,
+                                ADMIN_SHUTDOWN_MESSAGE,
+                            )
+                            .await;
+                    }
+                }
+                ShutdownState::Terminating => {
+                    return self
+                        .close_connection(
+                            protocol::ErrorCode::AdminShutdown,
+                            ADMIN_SHUTDOWN_MESSAGE,
+                        )
+                        .await;
+                }
+            }
+
+            // Reading is restarted when the state of the shutdown is changed, it happens twice
+            // at most, while the client is idle
+            let sleep = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now));
+            tokio::select! {
+                message = buffer::read_message(&mut self.socket) => return Ok(Some(message?)),
+                _ = sleep, if deadline.is_some() => {
+                    return self.close_connection(code, message).await;
+                }
+                _ = shutdown_changed(&mut self.shutdown) => {}
+                _ = self.session.state.terminated() => {}
+            }
+        }
+    }
+
+    /// Sends a FATAL error before closing of the connection by the server
+    async fn close_connection(
+        &mut self,
+        code: protocol::ErrorCode,
+        message: &str,
+    ) -> Result<Option<FrontendMessage>, Error> {
+        debug!(
+            "[pg] Closing connection {}: {}",
+            self.session.state.connection_id, message
+        );
+        self.write(protocol::ErrorResponse::new(
+            protocol::ErrorSeverity::Fatal,
+            code,
+            message.to_string(),
+        ))
+        .await?;
+
+        Ok(None)
+    }
+
     pub async fn write<Message: protocol::Serialize>(
         &mut self,
         message: Message,
@@ -106,8 +526,22 @@ impl AsyncPostgresShim {
 
         let startup_message = protocol::StartupMessage::from(&mut buffer).await?;
 
+        if startup_message.protocol_version.major == SSL_REQUEST_PROTOCOL
+            && startup_message.protocol_version.minor == CANCEL_REQUEST_CODE
+        {
+            let cancel_request = protocol::CancelRequest::from(&mut buffer).await?;
+            self.cancel_query(cancel_request);
+            return Ok(StartupState::CancelRequested);
+        }
+
         if startup_message.protocol_version.major == SSL_REQUEST_PROTOCOL {
-            self.write(protocol::SSLResponse::new()).await?;
+            match self.tls_acceptor.clone() {
+                Some(acceptor) => {
+                    self.write(protocol::SSLResponse::accepted()).await?;
+                    self.socket.upgrade(&acceptor).await?;
+                }
+                None => self.write(protocol::SSLResponse::new()).await?,
+            }
             return Ok(StartupState::SslRequested);
         }
 
@@ -127,6 +561,24 @@ impl AsyncPostgresShim {
         }
 
         self.parameters = startup_message.parameters;
+        // Parameters of the startup packet are defaults of the session, as in PostgreSQL
+        for (name, value) in &self.parameters {
+            if matches!(
+                name.as_str(),
+                "user" | "database" | "options" | "replication"
+            ) {
+                continue;
+            }
+
+            let result = self
+                .session
+                .state
+                .settings_mut()
+                .set_client_default(name, value);
+            if let Err(e) = result {
+                debug!("[pg] Ignoring startup parameter {}: {}", name, e);
+            }
+        }
         if !self.parameters.contains_key("user") {
             let error_response = protocol::ErrorResponse::new(
                 protocol::ErrorSeverity::Fatal,
@@ -136,6 +588,15 @@ impl AsyncPostgresShim {
             buffer::write_message(&mut self.socket, error_response).await?;
             return Ok(StartupState::Denied);
         }
+        if let Err(message) = self.check_connection_limits() {
+            let error_response = protocol::ErrorResponse::new(
+                protocol::ErrorSeverity::Fatal,
+                protocol::ErrorCode::TooManyConnections,
+                message,
+            );
+            buffer::write_message(&mut self.socket, error_response).await?;
+            return Ok(StartupState::Denied);
+        }
         if !self.parameters.contains_key("database") {
             self.parameters.insert(
                 "database".to_string(),
@@ -143,49 +604,150 @@ impl AsyncPostgresShim {
             );
         }
 
-        self.write(protocol::Authentication::new(
-            protocol::AuthenticationRequest::CleartextPassword,
-        ))
-        .await?;
+        let authentication_request = match self.auth_method {
+            PostgresAuthMethod::Password => protocol::AuthenticationRequest::CleartextPassword,
+            PostgresAuthMethod::ScramSha256 => {
+                protocol::AuthenticationRequest::SASLMechanisms(vec![SCRAM_SHA_256.to_string()])
+            }
+        };
+        self.write(protocol::Authentication::new(authentication_request))
+            .await?;
 
         return Ok(StartupState::Success);
     }
 
+    /// max_connections and the quota of the user are checked before authentication, as it's
+    /// done by PostgreSQL. The user is assigned to the session to be counted by the quota.
+    fn check_connection_limits(&self) -> Result<(), String> {
+        let configuration = &self.session.server.configuration;
+        let sessions = &self.session.session_manager;
+
+        if let Some(max_connections) = configuration.max_connections {
+            // The session of this connection is counted too
+            if sessions.count_sessions(&self.session.state.protocol) > max_connections {
+                return Err("sorry, too many clients already".to_string());
+            }
+        }
+
+        let user = self.parameters.get("user").unwrap().clone();
+        let limit = configuration.user_connection_limits.get(&user).cloned();
+        if !sessions.try_set_user(&self.session.state, user.clone(), limit) {
+            return Err(format!("too many connections for role \"{}\"", user));
+        }
+
+        Ok(())
+    }
+
     pub async fn authenticate(
         &mut self,
         password_message: protocol::PasswordMessage,
     ) -> Result<bool, Error> {
         let user = self.parameters.get("user").unwrap().clone();
-        let authenticate_response = self
+        let auth_context = self
             .session
             .server
             .auth
-            .authenticate(Some(user.clone()))
-            .await;
-        let mut auth_context: Option<AuthContext> = None;
-        let auth_success = match authenticate_response {
-            Ok(authenticate_response) => {
-                auth_context = Some(authenticate_response.context);
-                match authenticate_response.password {
-                    None => true,
-                    Some(password) => password == password_message.password,
-                }
+            .authenticate_password(Some(user), password_message.password)
+            .await
+            .unwrap_or_else(|e| {
+                debug!("[pg] Error during authentication: {}", e);
+                None
+            });
+
+        self.finish_authentication(auth_context).await
+    }
+
+    /// SCRAM-SHA-256 exchange, it's finished right after SASLInitialResponse if the auth
+    /// service doesn't require a password, because the server signature can't be computed
+    pub async fn authenticate_scram(&mut self) -> Result<bool, Error> {
+        let initial_response: protocol::SASLInitialResponse =
+            buffer::read_typed_message(&mut self.socket, b'p').await?;
+        if initial_response.mechanism != SCRAM_SHA_256 {
+            return self
+                .reject_authentication(
+                    protocol::ErrorCode::InvalidAuthorizationSpecification,
+                    format!(
+                        "client selected an invalid SASL authentication mechanism: {}",
+                        initial_response.mechanism
+                    ),
+                )
+                .await;
+        }
+
+        let response = match self.authenticate_user().await {
+            Some(response) => response,
+            None => return self.finish_authentication(None).await,
+        };
+        let password = match response.password {
+            Some(password) => password,
+            None => return self.finish_authentication(Some(response.context)).await,
+        };
+
+        let mut scram = ScramServer::new(password);
+        let server_first = match scram.server_first(&initial_response.data) {
+            Ok(server_first) => server_first,
+            Err(e) => {
+                return self
+                    .reject_authentication(
+                        protocol::ErrorCode::InvalidAuthorizationSpecification,
+                        e.message,
+                    )
+                    .await
             }
-            _ => false,
         };
+        self.write(protocol::Authentication::new(
+            protocol::AuthenticationRequest::SASLContinue(server_first),
+        ))
+        .await?;
 
-        if !auth_success {
-            let error_response = protocol::ErrorResponse::new(
-                protocol::ErrorSeverity::Fatal,
-                protocol::ErrorCode::InvalidPassword,
-                format!("password authentication failed for user \"{}\"", &user),
-            );
-            buffer::write_message(&mut self.socket, error_response).await?;
-            return Ok(false);
+        let client_final: protocol::SASLResponse =
+            buffer::read_typed_message(&mut self.socket, b'p').await?;
+        match scram.server_final(&client_final.data) {
+            Ok(Some(server_final)) => {
+                self.write(protocol::Authentication::new(
+                    protocol::AuthenticationRequest::SASLFinal(server_final),
+                ))
+                .await?;
+
+                self.finish_authentication(Some(response.context)).await
+            }
+            Ok(None) => self.finish_authentication(None).await,
+            Err(e) => {
+                self.reject_authentication(
+                    protocol::ErrorCode::InvalidAuthorizationSpecification,
+                    e.message,
+                )
+                .await
+            }
         }
+    }
+
+    async fn authenticate_user(&self) -> Option<AuthenticateResponse> {
+        let user = self.parameters.get("user").unwrap().clone();
+
+        self.session.server.auth.authenticate(Some(user)).await.ok()
+    }
+
+    /// Sends AuthenticationOk, None is used if the password doesn't match
+    async fn finish_authentication(
+        &mut self,
+        auth_context: Option<AuthContext>,
+    ) -> Result<bool, Error> {
+        let user = self.parameters.get("user").unwrap().clone();
+        let auth_context = match auth_context {
+            Some(auth_context) => auth_context,
+            None => {
+                return self
+                    .reject_authentication(
+                        protocol::ErrorCode::InvalidPassword,
+                        format!("password authentication failed for user \"{}\"", &user),
+                    )
+                    .await
+            }
+        };
 
         self.session.state.set_user(Some(user));
-        self.session.state.set_auth_context(auth_context);
+        self.session.state.set_auth_context(Some(auth_context));
 
         self.write(protocol::Authentication::new(
             protocol::AuthenticationRequest::Ok,
@@ -195,49 +757,268 @@ impl AsyncPostgresShim {
         Ok(true)
     }
 
+    async fn reject_authentication(
+        &mut self,
+        code: protocol::ErrorCode,
+        message: String,
+    ) -> Result<bool, Error> {
+        let error_response =
+            protocol::ErrorResponse::new(protocol::ErrorSeverity::Fatal, code, message);
+        buffer::write_message(&mut self.socket, error_response).await?;
+
+        Ok(false)
+    }
+
+    /// There is no response to CancelRequest, the connection is closed after it
+    fn cancel_query(&self, cancel_request: protocol::CancelRequest) {
+        let session = self
+            .session
+            .session_manager
+            .get_session(cancel_request.process_id);
+
+        match session {
+            Some(session) if session.state.secret_key == cancel_request.secret_key => {
+                debug!(
+                    "[pg] Canceling query of connection {}",
+                    cancel_request.process_id
+                );
+                session.state.cancel_query();
+            }
+            _ => debug!(
+                "[pg] Ignoring CancelRequest for connection {}: unknown connection or wrong key",
+                cancel_request.process_id
+            ),
+        }
+    }
+
     pub async fn ready(&mut self) -> Result<(), Error> {
-        let params = [
-            ("server_version".to_string(), "14.2 (Cube SQL)".to_string()),
-            ("server_encoding".to_string(), "UTF8".to_string()),
-            ("client_encoding".to_string(), "UTF8".to_string()),
-            ("DateStyle".to_string(), "ISO".to_string()),
-        ];
+        let params = self.session.state.settings().reported();
 
         for (key, value) in params {
             self.write(protocol::ParameterStatus::new(key, value))
                 .await?;
         }
 
-        self.write(protocol::ReadyForQuery::new(
-            protocol::TransactionStatus::Idle,
+        self.write(protocol::BackendKeyData::new(
+            self.session.state.connection_id,
+            self.session.state.secret_key,
         ))
         .await?;
 
+        self.write(protocol::ReadyForQuery::new(self.transaction.status()))
+            .await?;
+
         Ok(())
     }
 
     pub async fn process_query(&mut self, query: protocol::Query) -> Result<(), Error> {
         let query = query.query;
         debug!("Query: {}", query);
-        match self.execute_query(&query).await {
-            Err(e) => {
-                let error_message = e.to_string();
-                error!("Error during processing {}: {}", query, error_message);
-                self.write(protocol::ErrorResponse::new(
-                    protocol::ErrorSeverity::Error,
-                    protocol::ErrorCode::InternalError,
-                    error_message,
-                ))
-                .await?;
+        self.session.state.set_active(query.clone());
+
+        let statements = split_statements(&query);
+        if statements.is_empty() {
+            self.write(protocol::EmptyQueryResponse::new()).await?;
+        }
+
+        // Statements are executed one by one, an error aborts the remaining statements
+        for statement in statements {
+            METRICS.queries.inc(DatabaseProtocol::PostgreSQL.as_str());
+            let started = Instant::now();
+            let result = self.process_simple_query(&statement).await;
+            if let Err(e) = &result {
+                self.log_failed_query(statement.clone(), started.elapsed(), e);
             }
-            Ok(QueryResponse::Ok(_)) => {
+
+            match result {
+                Ok(()) => {}
+                Err(ConnectionError::Cube(e)) => {
+                    let error_message = e.to_string();
+                    error!("Error during processing {}: {}", statement, error_message);
+                    self.trace_error(&error_message);
+                    self.write_error(protocol::ErrorCode::InternalError, error_message)
+                        .await?;
+                    break;
+                }
+                Err(ConnectionError::Canceled(code, message)) => {
+                    debug!("Canceled processing {}: {}", statement, message);
+                    self.trace_error(&message);
+                    self.write_error(code, message).await?;
+                    break;
+                }
+                Err(ConnectionError::Protocol(e)) => return Err(e),
+            }
+        }
+
+        self.write(protocol::ReadyForQuery::new(self.transaction.status()))
+            .await?;
+        Ok(())
+    }
+
+    async fn process_simple_query(&mut self, query: &str) -> Result<(), ConnectionError> {
+        if let Some(command) = parse_transaction_command(query)? {
+            let tag = self.apply_transaction_command(&command)?;
+            self.write(protocol::CommandComplete::new(tag, 0)).await?;
+            return Ok(());
+        }
+        self.transaction.check_active()?;
+
+        if let Some(command) = parse_setting_command(query)? {
+            let (tag, response) = self.apply_setting_command(command)?;
+            self.write_response(response, tag).await?;
+            return Ok(());
+        }
+
+        if self.process_extension_query(query).await? {
+            return Ok(());
+        }
+
+        let span = self.step_span("parse");
+        let stmt = parse_sql_to_statement(&query.to_string(), DatabaseProtocol::PostgreSQL)
+            .map_err(CubeError::from)?;
+        drop(span);
+        let stmt = match stmt {
+            ast::Statement::Prepare {
+                name,
+                data_types,
+                statement,
+            } => {
+                self.prepare(&name, &data_types, *statement)?;
                 self.write(protocol::CommandComplete::new(
-                    protocol::CommandCompleteTag::Select,
+                    protocol::CommandCompleteTag::Prepare,
                     0,
                 ))
                 .await?;
+
+                return Ok(());
+            }
+            ast::Statement::Execute { name, parameters } => {
+                self.bind_prepared(&name, &parameters)?
+            }
+            stmt => stmt,
+        };
+
+        let guard = self.begin_query();
+        let mut span = self.step_span("execute");
+        let result = self.execute_statement(&stmt, &guard).await?;
+        let pushdown = result.pushdown();
+        let rows = self
+            .write_result(result, protocol::CommandCompleteTag::Select, &guard)
+            .await?;
+        span.set_attribute("db.rows", rows);
+        drop(span);
+        self.finish_statement(query.to_string(), &stmt, guard.elapsed(), rows, pushdown);
+
+        Ok(())
+    }
+
+    /// Registers the execution of the statement for pg_stat_statements and the query log
+    fn finish_statement(
+        &self,
+        sql: String,
+        stmt: &ast::Statement,
+        elapsed: Duration,
+        rows: u64,
+        pushdown: Pushdown,
+    ) {
+        let query = normalize_statement(stmt).unwrap_or_else(|_| stmt.to_string());
+        self.session.session_manager.query_stats.record(
+            self.session.state.user(),
+            query.clone(),
+            elapsed,
+            rows,
+        );
+
+        if QUERY_LOG.is_enabled() {
+            let mut record = self.query_log_record(sql, elapsed);
+            record.normalized_sql = Some(query);
+            record.rows = rows;
+            record.pushdown = Some(pushdown);
+            if pushdown == Pushdown::Cube {
+                record.cube_request_id = self
+                    .session
+                    .state
+                    .trace_context()
+                    .map(|context| context.request_id());
+            }
+
+            self.log_query(record);
+        }
+    }
+
+    fn log_failed_query(&self, sql: String, elapsed: Duration, error: &ConnectionError) {
+        if QUERY_LOG.is_enabled() {
+            let mut record = self.query_log_record(sql, elapsed);
+            record.error = Some(error.message());
+
+            self.log_query(record);
+        }
+    }
+
+    fn query_log_record(&self, sql: String, elapsed: Duration) -> QueryLogRecord {
+        QueryLogRecord::new(
+            DatabaseProtocol::PostgreSQL.as_str(),
+            self.session.state.connection_id,
+            self.session.state.user(),
+            sql,
+            elapsed,
+        )
+    }
+
+    fn log_query(&self, record: QueryLogRecord) {
+        let min_duration = self.session.state.settings().slow_query_log_min_duration();
+        QUERY_LOG.log(record, min_duration);
+    }
+
+    /// Writes the result of the statement in the simple query protocol, rows of streams are
+    /// sent as they are received. The number of sent rows is returned.
+    async fn write_result(
+        &mut self,
+        result: QueryResult,
+        tag: protocol::CommandCompleteTag,
+        guard: &QueryGuard,
+    ) -> Result<u64, ConnectionError> {
+        let mut stream = match result {
+            QueryResult::Response(response) => return self.write_response(response, tag).await,
+            QueryResult::Stream(stream) => stream,
+        };
+
+        let fields = stream
+            .columns()
+            .iter()
+            .map(|column| protocol::RowDescriptionField::new(column.get_name()))
+            .collect();
+        self.write(protocol::RowDescription::new(fields)).await?;
+
+        let formats = vec![(PgTypeId::Text, Format::Text); stream.columns().len()];
+        let time_zone = self.session.state.settings().session_time_zone();
+        let rows = write_stream_rows(
+            &mut self.socket,
+            &mut stream,
+            &formats,
+            &time_zone,
+            0,
+            guard,
+        )
+        .await?;
+        self.write(protocol::CommandComplete::new(tag, 0)).await?;
+
+        Ok(rows as u64)
+    }
+
+    /// Writes the result of the statement in the simple query protocol, the number of sent
+    /// rows is returned
+    async fn write_response(
+        &mut self,
+        response: QueryResponse,
+        tag: protocol::CommandCompleteTag,
+    ) -> Result<u64, ConnectionError> {
+        let rows = match response {
+            QueryResponse::Ok(_) => {
+                self.write(protocol::CommandComplete::new(tag, 0)).await?;
+                0
             }
-            Ok(QueryResponse::ResultSet(_, frame)) => {
+            QueryResponse::ResultSet(_, frame) => {
                 let mut fields = Vec::new();
                 for column in frame.get_columns().iter() {
                     fields.push(protocol::RowDescriptionField::new(column.get_name()))
@@ -245,61 +1026,832 @@ impl AsyncPostgresShim {
 
                 self.write(protocol::RowDescription::new(fields)).await?;
 
-                for row in frame.get_rows().iter() {
-                    let mut values = Vec::new();
-                    for value in row.values().iter() {
-                        let value = match value {
-                            TableValue::Null => None,
-                            TableValue::String(v) => Some(v.clone()),
-                            TableValue::Int64(v) => Some(format!("{}", v)),
-                            TableValue::Boolean(v) => {
-                                Some((if *v { "t" } else { "v" }).to_string())
-                            }
-                            TableValue::Float64(v) => Some(format!("{}", v)),
-                            TableValue::Timestamp(v) => Some(v.to_string()),
-                        };
-                        values.push(value);
+                // All columns are declared as text in the simple query protocol
+                let formats = vec![(PgTypeId::Text, Format::Text); frame.get_columns().len()];
+                let time_zone = self.session.state.settings().session_time_zone();
+                let range = 0..frame.get_rows().len();
+                let rows =
+                    write_rows(&mut self.socket, &frame, &formats, &time_zone, range).await?;
+                self.write(protocol::CommandComplete::new(tag, 0)).await?;
+                rows as u64
+            }
+        };
+
+        Ok(rows)
+    }
+
+    /// PREPARE name [(types)] AS statement, it shares statements with the Parse message
+    fn prepare(
+        &mut self,
+        name: &ast::Ident,
+        data_types: &[ast::DataType],
+        statement: ast::Statement,
+    ) -> Result<(), CubeError> {
+        let name = statement_name(name);
+        if self.statements.contains(&name) {
+            return Err(CubeError::user(format!(
+                "prepared statement \"{}\" already exists",
+                name
+            )));
+        }
+
+        self.statements.put(
+            name,
+            PreparedStatement::new(statement, parameter_types(data_types)?),
+        );
+
+        Ok(())
+    }
+
+    /// Binds EXECUTE parameters to the prepared statement as text values of Bind
+    fn bind_prepared(
+        &mut self,
+        name: &ast::Ident,
+        parameters: &[ast::Expr],
+    ) -> Result<ast::Statement, CubeError> {
+        let name = statement_name(name);
+        let statement = self.statements.get(&name).ok_or_else(|| {
+            CubeError::user(format!("prepared statement \"{}\" does not exist", name))
+        })?;
+
+        let expected = placeholder_report(&statement.query)?
+            .max_index
+            .max(statement.param_types.len());
+        if parameters.len() != expected {
+            return Err(CubeError::user(format!(
+                "wrong number of parameters for prepared statement \"{}\": expected {}, got {}",
+                name,
+                expected,
+                parameters.len()
+            )));
+        }
+
+        let values = text_values(parameter_values(parameters)?, &statement.param_types)?;
+        let types = statement
+            .param_types
+            .iter()
+            .map(|oid| PgTypeId::from_oid(*oid))
+            .collect();
+
+        let mut query = statement.query.clone();
+        StatementBinder::with_types(values, types).bind(&mut query)?;
+
+        Ok(query)
+    }
+
+    /// Sends ErrorResponse, the transaction block becomes failed
+    async fn write_error(
+        &mut self,
+        code: protocol::ErrorCode,
+        message: String,
+    ) -> Result<(), Error> {
+        self.transaction.fail();
+
+        self.write(protocol::ErrorResponse::new(
+            protocol::ErrorSeverity::Error,
+            code,
+            message,
+        ))
+        .await
+    }
+
+    /// Applies the transaction command, values of SET LOCAL are discarded at the end of
+    /// the transaction
+    fn apply_transaction_command(
+        &mut self,
+        command: &TransactionCommand,
+    ) -> Result<protocol::CommandCompleteTag, CubeError> {
+        let tag = self.transaction.apply(command)?;
+        let mut settings = self.session.state.settings_mut();
+        if self.transaction.status() == TransactionStatus::Idle {
+            settings.end_transaction();
+        } else {
+            settings.begin_transaction();
+        }
+
+        Ok(tag)
+    }
+
+    /// Applies SET and RESET, SHOW returns values as rows
+    fn apply_setting_command(
+        &mut self,
+        command: SettingCommand,
+    ) -> Result<(protocol::CommandCompleteTag, QueryResponse), CubeError> {
+        let mut settings = self.session.state.settings_mut();
+        let frame = match command {
+            SettingCommand::Set { name, value, local } => {
+                settings.set(&name, value.as_deref(), local)?;
+                None
+            }
+            SettingCommand::Reset { name } => {
+                settings.reset(name.as_deref());
+                None
+            }
+            SettingCommand::Show { name } => {
+                let value = settings.show(&name)?;
+                Some(CubeDataFrame::new(
+                    vec![Column::new(name, ColumnType::String, ColumnFlags::empty())],
+                    vec![Row::new(vec![TableValue::String(value)])],
+                ))
+            }
+            SettingCommand::ShowAll => Some(CubeDataFrame::new(
+                ["name", "setting", "description"]
+                    .iter()
+                    .map(|name| {
+                        Column::new(name.to_string(), ColumnType::String, ColumnFlags::empty())
+                    })
+                    .collect(),
+                settings
+                    .all()
+                    .into_iter()
+                    .map(|info| {
+                        let description = info.description().to_string();
+                        Row::new(vec![
+                            TableValue::String(info.name),
+                            TableValue::String(info.value),
+                            TableValue::String(description),
+                        ])
+                    })
+                    .collect(),
+            )),
+        };
+
+        Ok(match frame {
+            Some(frame) => (
+                protocol::CommandCompleteTag::Show,
+                QueryResponse::ResultSet(StatusFlags::empty(), Arc::new(frame)),
+            ),
+            None => (
+                protocol::CommandCompleteTag::Set,
+                QueryResponse::Ok(StatusFlags::empty()),
+            ),
+        })
+    }
+
+    /// Processes commands which are not supported by the SQL parser (cursors, COPY and
+    /// DEALLOCATE), false is returned for other queries
+    async fn process_extension_query(&mut self, query: &str) -> Result<bool, ConnectionError> {
+        if let Some(command) = parse_prepared_command(query)? {
+            self.process_prepared_command(command).await?;
+            return Ok(true);
+        }
+
+        if let Some(command) = parse_cursor_command(query)? {
+            self.process_cursor_command(command).await?;
+            return Ok(true);
+        }
+
+        if let Some(copy) = parse_copy_command(query)? {
+            self.process_copy(copy).await?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    async fn process_prepared_command(
+        &mut self,
+        command: PreparedCommand,
+    ) -> Result<(), ConnectionError> {
+        match command {
+            PreparedCommand::Deallocate { name } => {
+                let tag = match name {
+                    Some(name) => {
+                        if self.statements.pop(&name).is_none() {
+                            return Err(CubeError::user(format!(
+                                "prepared statement \"{}\" does not exist",
+                                name
+                            ))
+                            .into());
+                        }
+
+                        protocol::CommandCompleteTag::Deallocate
                     }
+                    None => {
+                        self.statements.clear();
 
-                    self.write(protocol::DataRow::new(values)).await?;
+                        protocol::CommandCompleteTag::DeallocateAll
+                    }
+                };
+
+                self.write(protocol::CommandComplete::new(tag, 0)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn process_copy(&mut self, copy: CopyTo) -> Result<(), ConnectionError> {
+        let stmt = parse_sql_to_statement(&copy.query, DatabaseProtocol::PostgreSQL)
+            .map_err(CubeError::from)?;
+        let guard = self.begin_query();
+        let mut stream = match self.execute_statement(&stmt, &guard).await? {
+            QueryResult::Stream(stream) => stream,
+            QueryResult::Response(QueryResponse::ResultSet(_, frame)) => {
+                ResultStream::from_frame(frame)
+            }
+            QueryResult::Response(QueryResponse::Ok(_)) => {
+                return Err(CubeError::user(
+                    "COPY can only be used with queries which return rows".to_string(),
+                )
+                .into())
+            }
+        };
+
+        let columns = u16::try_from(stream.columns().len())
+            .map_err(|_| CubeError::user("COPY result has too many columns".to_string()))?;
+        self.write(protocol::CopyOutResponse::new(columns)).await?;
+
+        if copy.options.header {
+            let names = stream
+                .columns()
+                .iter()
+                .map(|column| Some(column.get_name()))
+                .collect::<Vec<_>>();
+            self.write(protocol::CopyData::new(
+                copy.options.encode_row(&names).into_bytes(),
+            ))
+            .await?;
+        }
+
+        let time_zone = self.session.state.settings().session_time_zone();
+        let mut rows = 0;
+        while let Some((frame, range)) = guard.run(stream.next_rows(0)).await? {
+            for row in frame.get_rows()[range].iter() {
+                let values = row
+                    .values()
+                    .iter()
+                    .map(|value| {
+                        let bytes = encode_value(value, PgTypeId::Text, Format::Text, &time_zone)?;
+                        Ok(match bytes {
+                            Some(bytes) => Some(String::from_utf8(bytes)?),
+                            None => None,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, CubeError>>()?;
+
+                // A message per row, as PostgreSQL does
+                self.write(protocol::CopyData::new(
+                    copy.options.encode_row(&values).into_bytes(),
+                ))
+                .await?;
+                rows += 1;
+            }
+        }
+
+        self.write(protocol::CopyDone::new()).await?;
+        self.write(protocol::CommandComplete::new(
+            protocol::CommandCompleteTag::Copy,
+            rows,
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    async fn process_cursor_command(
+        &mut self,
+        command: CursorCommand,
+    ) -> Result<(), ConnectionError> {
+        match command {
+            CursorCommand::Declare { name, query } => {
+                let stmt = parse_sql_to_statement(&query, DatabaseProtocol::PostgreSQL)
+                    .map_err(CubeError::from)?;
+                // Results of cursors are materialized on DECLARE
+                let guard = self.begin_query();
+                let frame = match self.execute_statement(&stmt, &guard).await? {
+                    QueryResult::Stream(stream) => Arc::new(guard.run(stream.collect()).await?),
+                    QueryResult::Response(QueryResponse::ResultSet(_, frame)) => frame,
+                    QueryResult::Response(QueryResponse::Ok(_)) => {
+                        return Err(CubeError::user(
+                            "DECLARE CURSOR can only be used with queries which return rows"
+                                .to_string(),
+                        )
+                        .into())
+                    }
+                };
+
+                self.session
+                    .state
+                    .declare_cursor(name, Cursor::new(frame))?;
+                self.write(protocol::CommandComplete::new(
+                    protocol::CommandCompleteTag::DeclareCursor,
+                    0,
+                ))
+                .await?;
+            }
+            CursorCommand::Fetch { name, direction } => {
+                let (frame, range) = self.session.state.fetch_cursor(&name, direction)?;
+
+                let fields = frame
+                    .get_columns()
+                    .iter()
+                    .map(|column| protocol::RowDescriptionField::new(column.get_name()))
+                    .collect();
+                self.write(protocol::RowDescription::new(fields)).await?;
+
+                let formats = vec![(PgTypeId::Text, Format::Text); frame.get_columns().len()];
+                let time_zone = self.session.state.settings().session_time_zone();
+                let rows =
+                    write_rows(&mut self.socket, &frame, &formats, &time_zone, range).await?;
+                self.write(protocol::CommandComplete::new(
+                    protocol::CommandCompleteTag::Fetch,
+                    rows,
+                ))
+                .await?;
+            }
+            CursorCommand::Move { name, direction } => {
+                let (_, range) = self.session.state.fetch_cursor(&name, direction)?;
+
+                self.write(protocol::CommandComplete::new(
+                    protocol::CommandCompleteTag::Move,
+                    range.len() as u32,
+                ))
+                .await?;
+            }
+            CursorCommand::Close { name } => {
+                match name {
+                    Some(name) => self.session.state.close_cursor(&name)?,
+                    None => self.session.state.close_all_cursors(),
                 }
 
                 self.write(protocol::CommandComplete::new(
-                    protocol::CommandCompleteTag::Select,
+                    protocol::CommandCompleteTag::CloseCursor,
                     0,
                 ))
                 .await?;
             }
         }
-        self.write(protocol::ReadyForQuery::new(
-            protocol::TransactionStatus::Idle,
-        ))
-        .await?;
+
+        Ok(())
+    }
+
+    async fn parse(&mut self, parse: protocol::Parse) -> Result<(), ConnectionError> {
+        let _span = self.step_span("parse");
+        let query = parse_sql_to_statement(&parse.query, DatabaseProtocol::PostgreSQL)
+            .map_err(CubeError::from)?;
+
+        self.statements
+            .put(parse.name, PreparedStatement::new(query, parse.param_types));
+        self.write(protocol::ParseComplete::new()).await?;
+
         Ok(())
     }
 
-    pub async fn execute_query(&mut self, query: &str) -> Result<QueryResponse, CubeError> {
+    async fn bind(&mut self, bind: protocol::Bind) -> Result<(), ConnectionError> {
+        let _span = self.step_span("bind");
+        let statement = self.statements.get(&bind.statement).ok_or_else(|| {
+            CubeError::user(format!(
+                "prepared statement \"{}\" does not exist",
+                bind.statement
+            ))
+        })?;
+
+        let values = bind_values(&bind, &statement.bind_types())?;
+        let types = statement
+            .param_types
+            .iter()
+            .map(|oid| PgTypeId::from_oid(*oid))
+            .collect::<Vec<_>>();
+
+        let mut query = statement.query.clone();
+        let plan = self.cached_plan(&bind.statement, &values, &types).await?;
+        if plan.is_none() {
+            StatementBinder::with_types(values, types).bind(&mut query)?;
+        }
+
+        let mut portal = Portal::new(query, bind.result_formats);
+        portal.plan = plan;
+        self.portals.insert(bind.portal, portal);
+        self.write(protocol::BindComplete::new()).await?;
+
+        Ok(())
+    }
+
+    /// Plan of the prepared statement for `values` from its plan cache. The statement is planned
+    /// with marker values on the first Bind of every signature, None is returned if the plan
+    /// can't be reused for the signature and the statement must be bound and planned.
+    async fn cached_plan(
+        &mut self,
+        name: &str,
+        values: &[BindValue],
+        types: &[Option<PgTypeId>],
+    ) -> Result<Option<QueryPlan>, CubeError> {
+        let signature = match signature(values) {
+            Some(signature) => signature,
+            None => return Ok(None),
+        };
         let meta = self
             .session
             .server
             .transport
-            .meta(self.auth_context()?)
+            .meta(self.auth_context()?, self.session.state.trace_context())
             .await?;
+        let session = self.session.clone();
+
+        let statement = self.statements.get_mut(name).ok_or_else(|| {
+            CubeError::user(format!("prepared statement \"{}\" does not exist", name))
+        })?;
+        let template = match statement.plans.lookup(&signature, &meta) {
+            PlanLookup::Hit(template) => template,
+            PlanLookup::Uncacheable => return Ok(None),
+            PlanLookup::Miss => {
+                let mut query = statement.query.clone();
+                let template =
+                    StatementBinder::with_types(marker_values(&signature), types.to_vec())
+                        .bind(&mut query)
+                        .and_then(|_| {
+                            Ok(convert_statement_to_cube_query(
+                                &query,
+                                meta.clone(),
+                                session,
+                            )?)
+                        })
+                        .and_then(|plan| PlanTemplate::new(plan, &signature, meta.clone()));
+                // Markers can be rejected by the compiler (dates), values are planned then
+                let template = match template {
+                    Ok(template) => template.map(Arc::new),
+                    Err(e) => {
+                        debug!("Unable to cache the plan of {}: {}", statement.query, e);
+                        None
+                    }
+                };
+                statement.plans.insert(signature, template.clone());
+
+                match template {
+                    Some(template) => template,
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        Ok(Some(template.instantiate(values)?))
+    }
 
-        let plan = convert_sql_to_cube_query(&query.to_string(), meta, self.session.clone())?;
+    async fn describe(&mut self, describe: protocol::Describe) -> Result<(), ConnectionError> {
+        let _span = self.step_span("describe");
+        match describe.typ {
+            protocol::DescribeType::Statement => {
+                let statement = self.statements.get(&describe.name).ok_or_else(|| {
+                    CubeError::user(format!(
+                        "prepared statement \"{}\" does not exist",
+                        describe.name
+                    ))
+                })?;
+
+                let query = statement.query.clone();
+                let declared = statement.param_types.clone();
+
+                let meta = self
+                    .session
+                    .server
+                    .transport
+                    .meta(self.auth_context()?, self.session.state.trace_context())
+                    .await?;
+                // Types, which are still unknown, are reported as 0, clients send such
+                // parameters as text
+                let param_types = describe_parameter_types(&query, &declared, Some(&meta))?;
+                let columns = self.describe_result(&query, &param_types, meta);
+                // Clients encode parameters of Bind by described types, not by declared ones
+                if let Some(statement) = self.statements.get_mut(&describe.name) {
+                    statement.described_types = param_types.clone();
+                }
+
+                self.write(protocol::ParameterDescription::new(param_types))
+                    .await?;
+                match columns {
+                    // Result formats are not known until Bind, they are described as text
+                    Some(columns) => {
+                        let fields = columns
+                            .iter()
+                            .map(|column| {
+                                protocol::RowDescriptionField::with_type(
+                                    column.get_name(),
+                                    column_pg_type(column.get_type()),
+                                    Format::Text,
+                                )
+                                .with_type_modifier(column_type_modifier(column.get_type()))
+                            })
+                            .collect();
+
+                        self.write(protocol::RowDescription::new(fields)).await?;
+                    }
+                    None => self.write(protocol::NoData::new()).await?,
+                }
+            }
+            protocol::DescribeType::Portal => {
+                self.ensure_portal_result(&describe.name).await?;
+
+                let portal = self.portals.get(&describe.name).unwrap();
+                match portal.result_columns() {
+                    Some(columns) => {
+                        let fields = columns
+                            .iter()
+                            .enumerate()
+                            .map(|(i, column)| {
+                                protocol::RowDescriptionField::with_type(
+                                    column.get_name(),
+                                    column_pg_type(column.get_type()),
+                                    portal.result_format(i),
+                                )
+                                .with_type_modifier(column_type_modifier(column.get_type()))
+                            })
+                            .collect();
+
+                        buffer::write_message(
+                            &mut self.socket,
+                            protocol::RowDescription::new(fields),
+                        )
+                        .await?;
+                    }
+                    None => {
+                        buffer::write_message(&mut self.socket, protocol::NoData::new()).await?
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn execute(&mut self, execute: protocol::Execute) -> Result<(), ConnectionError> {
+        let started = Instant::now();
+        let statement = match self.portals.get(&execute.portal) {
+            Some(portal) if QUERY_LOG.is_enabled() => Some(portal.statement.to_string()),
+            _ => None,
+        };
+
+        let result = self.execute_portal(execute).await;
+        if let (Err(e), Some(statement)) = (&result, statement) {
+            self.log_failed_query(statement, started.elapsed(), e);
+        }
+
+        result
+    }
+
+    async fn execute_portal(&mut self, execute: protocol::Execute) -> Result<(), ConnectionError> {
+        METRICS.queries.inc(DatabaseProtocol::PostgreSQL.as_str());
+        let mut span = self.step_span("execute");
+        if let Some(portal) = self.portals.get(&execute.portal) {
+            let statement = portal.statement.to_string();
+            if let Some(trace) = &mut self.trace {
+                trace.set_attribute("db.statement", &statement);
+            }
+            self.session.state.set_active(statement);
+        }
+        if let Some(command) = self.portal_transaction_command(&execute.portal)? {
+            let tag = self.apply_transaction_command(&command)?;
+            self.write(protocol::CommandComplete::new(tag, 0)).await?;
+            return Ok(());
+        }
+        self.ensure_portal_result(&execute.portal).await?;
+
+        let guard = self.begin_query();
+        let time_zone = self.session.state.settings().session_time_zone();
+        let portal = self.portals.get_mut(&execute.portal).unwrap();
+        let formats = portal
+            .result_columns()
+            .map(|columns| {
+                columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| (column_pg_type(column.get_type()), portal.result_format(i)))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let written = match &mut portal.result {
+            Some(QueryResult::Stream(stream)) => {
+                // As in PostgreSQL, the portal is suspended when `max_rows` rows were sent,
+                // even if there are no more rows
+                let max_rows = execute.max_rows.max(0) as usize;
+                write_stream_rows(
+                    &mut self.socket,
+                    stream,
+                    &formats,
+                    &time_zone,
+                    max_rows,
+                    &guard,
+                )
+                .await
+                .map(|rows| (rows, max_rows > 0 && rows as usize == max_rows))
+            }
+            Some(QueryResult::Response(QueryResponse::ResultSet(_, frame))) => {
+                let frame = frame.clone();
+                let total = frame.get_rows().len();
+                let range = portal.next_rows(execute.max_rows, total);
+                let suspended = range.end < total;
+
+                write_rows(&mut self.socket, &frame, &formats, &time_zone, range)
+                    .await
+                    .map(|rows| (rows, suspended))
+            }
+            _ => Ok((0, false)),
+        };
+        // The stream can't be resumed after an error
+        let (rows, suspended) = match written {
+            Ok(written) => written,
+            Err(e) => {
+                self.portals.remove(&execute.portal);
+                return Err(e);
+            }
+        };
+
+        // Executions of the portal are registered as a single execution of its statement
+        let portal = self.portals.get_mut(&execute.portal).unwrap();
+        portal.sent_rows += rows as u64;
+        portal.execution_time += guard.elapsed();
+        if !suspended {
+            let (sent_rows, execution_time) = (portal.sent_rows, portal.execution_time);
+            let statement = portal.statement.clone();
+            let pushdown = portal
+                .result
+                .as_ref()
+                .map(|result| result.pushdown())
+                .unwrap_or(Pushdown::Meta);
+            self.finish_statement(
+                statement.to_string(),
+                &statement,
+                execution_time,
+                sent_rows,
+                pushdown,
+            );
+        }
+        span.set_attribute("db.rows", rows);
+        drop(span);
+
+        if suspended {
+            // The client resumes the portal by the next Execute
+            self.write(protocol::PortalSuspended::new()).await?;
+        } else {
+            self.write(protocol::CommandComplete::new(
+                protocol::CommandCompleteTag::Select,
+                rows,
+            ))
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn close(&mut self, close: protocol::Close) -> Result<(), ConnectionError> {
+        // Closing a nonexistent statement or portal is not an error
+        match close.typ {
+            protocol::CloseType::Statement => {
+                self.statements.pop(&close.name);
+            }
+            protocol::CloseType::Portal => {
+                self.portals.remove(&close.name);
+            }
+        }
+        self.write(protocol::CloseComplete::new()).await?;
+
+        Ok(())
+    }
+
+    /// Columns of the statement, which is planned with sample values of its parameters.
+    /// Statements, which can't be planned before Bind, are described as NoData, their result
+    /// is described by Describe of the portal.
+    fn describe_result(
+        &self,
+        query: &ast::Statement,
+        param_types: &[u32],
+        meta: Arc<MetaContext>,
+    ) -> Option<Vec<Column>> {
+        let result = bind_sample_values(query, param_types).and_then(|stmt| {
+            convert_statement_to_cube_query(&stmt, meta, self.session.clone())?.result_columns()
+        });
+
+        match result {
+            Ok(columns) => columns,
+            Err(e) => {
+                debug!("Unable to describe the result of {}: {}", query, e);
+                None
+            }
+        }
+    }
+
+    fn portal_transaction_command(
+        &self,
+        name: &str,
+    ) -> Result<Option<TransactionCommand>, ConnectionError> {
+        let portal = self
+            .portals
+            .get(name)
+            .ok_or_else(|| CubeError::user(format!("portal \"{}\" does not exist", name)))?;
+
+        Ok(transaction_command_from_statement(&portal.statement)?)
+    }
+
+    /// Executes the portal if it was not executed by Describe or a previous Execute
+    async fn ensure_portal_result(&mut self, name: &str) -> Result<(), ConnectionError> {
+        // Transaction commands are applied by Execute, there are no rows to describe
+        if self.portal_transaction_command(name)?.is_some() {
+            self.portals.get_mut(name).unwrap().result = Some(QueryResult::Response(
+                QueryResponse::Ok(StatusFlags::empty()),
+            ));
+            return Ok(());
+        }
+
+        let portal = self.portals.get(name).unwrap();
+        if portal.result.is_some() {
+            return Ok(());
+        }
+        self.transaction.check_active()?;
+
+        let statement = portal.statement.clone();
+        if let Some(command) = setting_command_from_statement(&statement)? {
+            let (_, response) = self.apply_setting_command(command)?;
+            self.portals.get_mut(name).unwrap().result = Some(QueryResult::Response(response));
+            return Ok(());
+        }
+
+        let plan = self.portals.get_mut(name).unwrap().plan.take();
+        let guard = self.begin_query();
+        let result = self.execute_plan(&statement, plan, &guard).await?;
+        self.portals.get_mut(name).unwrap().result = Some(result);
+
+        Ok(())
+    }
+
+    /// Starts the statement, it can be canceled until the guard is dropped
+    fn begin_query(&self) -> QueryGuard {
+        QueryGuard::new(
+            self.session.clone(),
+            self.session.state.settings().statement_timeout(),
+            self.shutdown.clone(),
+        )
+    }
+
+    async fn execute_statement(
+        &mut self,
+        stmt: &ast::Statement,
+        guard: &QueryGuard,
+    ) -> Result<QueryResult, ConnectionError> {
+        self.execute_plan(stmt, None, guard).await
+    }
+
+    /// Executes the plan of the statement (from the plan cache), the statement is planned
+    /// without it. Rows of DataFusion plans are streamed, they must be received under `guard`.
+    async fn execute_plan(
+        &mut self,
+        stmt: &ast::Statement,
+        plan: Option<QueryPlan>,
+        guard: &QueryGuard,
+    ) -> Result<QueryResult, ConnectionError> {
+        guard.run(self.plan_and_execute(stmt, plan)).await
+    }
+
+    async fn plan_and_execute(
+        &mut self,
+        stmt: &ast::Statement,
+        plan: Option<QueryPlan>,
+    ) -> Result<QueryResult, CubeError> {
+        let plan = match plan {
+            Some(plan) => plan,
+            None => {
+                let meta = self
+                    .session
+                    .server
+                    .transport
+                    .meta(self.auth_context()?, self.session.state.trace_context())
+                    .await?;
+
+                let evaluated =
+                    evaluate_subqueries(stmt, meta.clone(), self.session.clone()).await?;
+                let stmt = evaluated.as_ref().unwrap_or(stmt);
+
+                // EXPLAIN ANALYZE executes the statement to report its timings
+                if let ast::Statement::Explain {
+                    statement,
+                    analyze: true,
+                    ..
+                } = stmt
+                {
+                    explain_analyze(statement, meta, self.session.clone()).await?
+                } else {
+                    let mut span = self.step_span("rewrite");
+                    let plan = convert_statement_to_cube_query(stmt, meta, self.session.clone());
+                    if let Err(e) = &plan {
+                        span.set_error(e);
+                    }
+                    plan?
+                }
+            }
+        };
         match plan {
             crate::compile::QueryPlan::MetaOk(status) => {
-                return Ok(QueryResponse::Ok(status));
+                return Ok(QueryResult::Response(QueryResponse::Ok(status)));
             }
             crate::compile::QueryPlan::MetaTabular(status, data_frame) => {
-                return Ok(QueryResponse::ResultSet(status, data_frame));
+                return Ok(QueryResult::Response(QueryResponse::ResultSet(
+                    status, data_frame,
+                )));
             }
-            crate::compile::QueryPlan::DataFusionSelect(status, plan, ctx) => {
-                let df = DataFrameImpl::new(ctx.state, &plan);
-                let batches = df.collect().await?;
-                let response = batch_to_dataframe(&batches)?;
+            crate::compile::QueryPlan::DataFusionSelect(_, plan, ctx) => {
+                let max_in_flight = self.session.server.configuration.max_in_flight_batches;
+                let stream = ResultStream::execute(plan, ctx, max_in_flight).await?;
 
-                return Ok(QueryResponse::ResultSet(status, Arc::new(response)));
+                return Ok(QueryResult::Stream(stream));
             }
         }
     }
@@ -313,6 +1865,56 @@ impl AsyncPostgresShim {
     }
 }
 
+/// Writes rows of the frame in `range` as DataRow messages, `formats` is a type and a format
+/// per column, timestamps with time zone are written in `time_zone`
+async fn write_rows(
+    socket: &mut PgStream,
+    frame: &CubeDataFrame,
+    formats: &[(PgTypeId, Format)],
+    time_zone: &Tz,
+    range: Range<usize>,
+) -> Result<u32, ConnectionError> {
+    let rows = &frame.get_rows()[range];
+    for row in rows.iter() {
+        let values = row
+            .values()
+            .iter()
+            .zip(formats.iter())
+            .map(|(value, (typ, format))| encode_value(value, *typ, *format, time_zone))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        buffer::write_message(socket, protocol::DataRow::from_bytes(values)).await?;
+    }
+
+    Ok(rows.len() as u32)
+}
+
+/// Writes rows of the stream as DataRow messages until its end or `max_rows` rows (0 is no
+/// limit), rows are received under the guard of the statement
+async fn write_stream_rows(
+    socket: &mut PgStream,
+    stream: &mut ResultStream,
+    formats: &[(PgTypeId, Format)],
+    time_zone: &Tz,
+    max_rows: usize,
+    guard: &QueryGuard,
+) -> Result<u32, ConnectionError> {
+    let mut rows = 0;
+    while max_rows == 0 || rows < max_rows {
+        match guard
+            .run(stream.next_rows(max_rows.saturating_sub(rows)))
+            .await?
+        {
+            Some((frame, range)) => {
+                rows += write_rows(socket, &frame, formats, time_zone, range).await? as usize
+            }
+            None => break,
+        }
+    }
+
+    Ok(rows as u32)
+}
+
 impl Drop for AsyncPostgresShim {
     fn drop(&mut self) {
         trace!(
@@ -0,0 +1,235 @@
+use std::{ops::Range, sync::Arc};
+
+use datafusion::{
+    error::DataFusionError,
+    execution::context::ExecutionContext,
+    logical_plan::LogicalPlan,
+    physical_plan::{coalesce_partitions::CoalescePartitionsExec, ExecutionPlan},
+};
+use futures::StreamExt;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    sql::{
+        dataframe::{arrow_to_column_type, batch_to_dataframe, Column, DataFrame},
+        ColumnFlags, QueryResponse,
+    },
+    telemetry::query_log::Pushdown,
+    CubeError,
+};
+
+/// Result of the statement: a materialized response (meta queries, SHOW) or a stream of
+/// batches from DataFusion
+pub enum QueryResult {
+    Response(QueryResponse),
+    Stream(ResultStream),
+}
+
+impl QueryResult {
+    pub fn pushdown(&self) -> Pushdown {
+        match self {
+            QueryResult::Response(_) => Pushdown::Meta,
+            QueryResult::Stream(stream) => stream.pushdown,
+        }
+    }
+}
+
+/// Batches of the DataFusion plan, which is executed by a background task. The task is
+/// suspended while `max_in_flight` batches are waiting to be sent, so the socket pushes back
+/// on DataFusion and the Cube API. The task is aborted when the stream is dropped.
+pub struct ResultStream {
+    columns: Vec<Column>,
+    receiver: mpsc::Receiver<Result<Arc<DataFrame>, CubeError>>,
+    // It's None for materialized results
+    task: Option<JoinHandle<()>>,
+    /// The last received batch and the number of its rows, which were already returned
+    pending: Option<(Arc<DataFrame>, usize)>,
+    pushdown: Pushdown,
+}
+
+impl ResultStream {
+    pub async fn execute(
+        plan: LogicalPlan,
+        ctx: ExecutionContext,
+        max_in_flight: usize,
+    ) -> Result<Self, CubeError> {
+        let pushdown = Pushdown::of_plan(&plan);
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan).await?;
+        let plan: Arc<dyn ExecutionPlan> = if plan.output_partitioning().partition_count() == 1 {
+            plan
+        } else {
+            Arc::new(CoalescePartitionsExec::new(plan))
+        };
+
+        let columns = plan
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| {
+                Ok(Column::new(
+                    field.name().clone(),
+                    arrow_to_column_type(field.data_type().clone())?,
+                    ColumnFlags::empty(),
+                ))
+            })
+            .collect::<Result<Vec<_>, CubeError>>()?;
+
+        let mut batches = plan.execute(0).await?;
+        let (sender, receiver) = mpsc::channel(max_in_flight.max(1));
+        let task = tokio::spawn(async move {
+            while let Some(batch) = batches.next().await {
+                let frame = batch
+                    .map_err(|e| CubeError::from(DataFusionError::ArrowError(e)))
+                    .and_then(|batch| batch_to_dataframe(&vec![batch]))
+                    .map(Arc::new);
+                let failed = frame.is_err();
+
+                // The receiver is dropped if the result is not needed anymore
+                if sender.send(frame).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            columns,
+            receiver,
+            task: Some(task),
+            pending: None,
+            pushdown,
+        })
+    }
+
+    /// Stream over the materialized result
+    pub fn from_frame(frame: Arc<DataFrame>) -> Self {
+        let (_, receiver) = mpsc::channel(1);
+
+        Self {
+            columns: frame.get_columns().clone(),
+            receiver,
+            task: None,
+            pending: Some((frame, 0)),
+            pushdown: Pushdown::Meta,
+        }
+    }
+
+    pub fn columns(&self) -> &Vec<Column> {
+        &self.columns
+    }
+
+    /// Next rows of the result as a batch and a range of its rows, there are at most
+    /// `max_rows` rows (0 is the rest of the batch). None is returned at the end of the result.
+    pub async fn next_rows(
+        &mut self,
+        max_rows: usize,
+    ) -> Result<Option<(Arc<DataFrame>, Range<usize>)>, CubeError> {
+        loop {
+            if let Some((frame, position)) = &mut self.pending {
+                let total = frame.len();
+                if *position < total {
+                    let start = *position;
+                    let end = if max_rows > 0 {
+                        total.min(start + max_rows)
+                    } else {
+                        total
+                    };
+                    *position = end;
+
+                    return Ok(Some((frame.clone(), start..end)));
+                }
+            }
+
+            match self.receiver.recv().await {
+                Some(frame) => self.pending = Some((frame?, 0)),
+                None => {
+                    self.pending = None;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Receives the rest of the result
+    pub async fn collect(mut self) -> Result<DataFrame, CubeError> {
+        let mut rows = vec![];
+        while let Some((frame, range)) = self.next_rows(0).await? {
+            rows.extend_from_slice(&frame.get_rows()[range]);
+        }
+
+        Ok(DataFrame::new(self.columns.clone(), rows))
+    }
+}
+
+impl Drop for ResultStream {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{
+        arrow::{
+            array::Int64Array,
+            datatypes::{DataType, Field, Schema},
+            record_batch::RecordBatch,
+        },
+        datasource::MemTable,
+    };
+
+    use super::*;
+
+    fn test_stream_context() -> Result<ExecutionContext, DataFusionError> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let batch = |values: Vec<i64>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(values))])
+        };
+        let table = MemTable::try_new(
+            schema.clone(),
+            vec![vec![batch(vec![1, 2, 3])?, batch(vec![4, 5, 6])?]],
+        )?;
+
+        let mut ctx = ExecutionContext::new();
+        ctx.register_table("t", Arc::new(table))?;
+
+        Ok(ctx)
+    }
+
+    #[tokio::test]
+    async fn test_result_stream() -> Result<(), CubeError> {
+        let ctx = test_stream_context()?;
+        let plan = ctx.create_logical_plan("SELECT a FROM t")?;
+        let mut stream = ResultStream::execute(plan, ctx, 1).await?;
+        assert_eq!(stream.columns().len(), 1);
+        assert_eq!(stream.pushdown, Pushdown::DataFusion);
+
+        let mut ranges = vec![];
+        while let Some((frame, range)) = stream.next_rows(2).await? {
+            assert_eq!(frame.len(), 3);
+            ranges.push(range);
+        }
+        assert_eq!(ranges, vec![0..2, 2..3, 0..2, 2..3]);
+        assert!(stream.next_rows(2).await?.is_none());
+
+        let ctx = test_stream_context()?;
+        let plan = ctx.create_logical_plan("SELECT a FROM t")?;
+        let frame = ResultStream::execute(plan, ctx, 1).await?.collect().await?;
+        assert_eq!(frame.len(), 6);
+
+        let mut stream = ResultStream::from_frame(Arc::new(frame));
+        assert_eq!(
+            stream.next_rows(4).await?.map(|(_, range)| range),
+            Some(0..4)
+        );
+        assert_eq!(
+            stream.next_rows(0).await?.map(|(_, range)| range),
+            Some(4..6)
+        );
+        assert!(stream.next_rows(0).await?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Error, ErrorKind},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{
+        server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore, ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+use crate::{config::PostgresTlsConfig, CubeError};
+
+/// Builds the acceptor for connections which send SSLRequest
+pub fn create_tls_acceptor(config: &PostgresTlsConfig) -> Result<TlsAcceptor, CubeError> {
+    let certs = read_certs(&config.cert_path)?;
+    let key = read_private_key(&config.key_path)?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.client_ca_path {
+        // Clients must present a certificate, which is signed by one of CAs
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(client_ca_path)? {
+                roots.add(&cert).map_err(|e| {
+                    CubeError::user(format!(
+                        "Invalid client CA certificate in {}: {}",
+                        client_ca_path, e
+                    ))
+                })?;
+            }
+
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let server_config = builder.with_single_cert(certs, key).map_err(|e| {
+        CubeError::user(format!(
+            "Invalid TLS certificate or key for the PostgreSQL listener: {}",
+            e
+        ))
+    })?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+fn read_certs(path: &str) -> Result<Vec<Certificate>, CubeError> {
+    let mut reader = BufReader::new(open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).map_err(|e| {
+        CubeError::user(format!("Unable to read certificates from {}: {}", path, e))
+    })?;
+    if certs.is_empty() {
+        return Err(CubeError::user(format!(
+            "No certificates found in {}",
+            path
+        )));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// The first PKCS#8 or RSA key from the file
+fn read_private_key(path: &str) -> Result<PrivateKey, CubeError> {
+    let mut reader = BufReader::new(open(path)?);
+    let items = rustls_pemfile::read_all(&mut reader)
+        .map_err(|e| CubeError::user(format!("Unable to read private key from {}: {}", path, e)))?;
+
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) => {
+                Some(PrivateKey(key))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| CubeError::user(format!("No private key found in {}", path)))
+}
+
+fn open(path: &str) -> Result<File, CubeError> {
+    File::open(path).map_err(|e| CubeError::user(format!("Unable to open {}: {}", path, e)))
+}
+
+/// Connection with the client, it's upgraded to TLS after SSLRequest
+pub enum PgStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    /// Connections over unix domain sockets are not upgraded
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+    /// The stream is taken for the TLS handshake, it's never exposed after a failed handshake
+    Upgrading,
+}
+
+impl PgStream {
+    /// Performs the TLS handshake, it must be called right after the response to SSLRequest
+    pub async fn upgrade(&mut self, acceptor: &TlsAcceptor) -> Result<(), Error> {
+        match std::mem::replace(self, PgStream::Upgrading) {
+            PgStream::Plain(stream) => {
+                let stream = acceptor.accept(stream).await?;
+                *self = PgStream::Tls(Box::new(stream));
+
+                Ok(())
+            }
+            stream => {
+                *self = stream;
+                Err(Error::new(
+                    ErrorKind::Other,
+                    "TLS was already negotiated for the connection",
+                ))
+            }
+        }
+    }
+}
+
+fn not_connected() -> Error {
+    Error::new(ErrorKind::NotConnected, "TLS handshake is in progress")
+}
+
+impl AsyncRead for PgStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PgStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            PgStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            PgStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            PgStream::Upgrading => Poll::Ready(Err(not_connected())),
+        }
+    }
+}
+
+impl AsyncWrite for PgStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            PgStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            PgStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            PgStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            PgStream::Upgrading => Poll::Ready(Err(not_connected())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PgStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            PgStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            PgStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            PgStream::Upgrading => Poll::Ready(Err(not_connected())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            PgStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            PgStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            PgStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            PgStream::Upgrading => Poll::Ready(Err(not_connected())),
+        }
+    }
+}
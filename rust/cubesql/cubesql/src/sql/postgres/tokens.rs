@@ -0,0 +1,218 @@
+use sqlparser::{
+    dialect::PostgreSqlDialect,
+    tokenizer::{Token, Tokenizer},
+};
+
+use crate::CubeError;
+
+/// Parser over tokens for commands which are not supported by the SQL parser
+pub struct TokenParser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl TokenParser {
+    pub fn new(query: &str) -> Result<Self, CubeError> {
+        let tokens = Tokenizer::new(&PostgreSqlDialect {}, query)
+            .tokenize()
+            .map_err(|e| CubeError::user(format!("Unable to parse: {:?}", e)))?;
+
+        Ok(Self { tokens, index: 0 })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(Token::Whitespace(_)) = self.tokens.get(self.index) {
+            self.index += 1;
+        }
+    }
+
+    pub fn peek_token(&mut self) -> Option<&Token> {
+        self.skip_whitespace();
+        self.tokens.get(self.index)
+    }
+
+    pub fn next_token(&mut self) -> Option<Token> {
+        let token = self.peek_token().cloned();
+        if token.is_some() {
+            self.index += 1;
+        }
+
+        token
+    }
+
+    /// Next token including whitespaces
+    pub fn next_raw_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).cloned();
+        if token.is_some() {
+            self.index += 1;
+        }
+
+        token
+    }
+
+    /// Consumes the token if it's equal to `expected`
+    pub fn parse_token(&mut self, expected: &Token) -> bool {
+        if self.peek_token() == Some(expected) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Unquoted word in upper case
+    pub fn peek_keyword(&mut self) -> Option<String> {
+        match self.peek_token() {
+            Some(Token::Word(word)) if word.quote_style.is_none() => {
+                Some(word.value.to_uppercase())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn next_keyword(&mut self) -> Option<String> {
+        let keyword = self.peek_keyword();
+        if keyword.is_some() {
+            self.index += 1;
+        }
+
+        keyword
+    }
+
+    pub fn parse_keyword(&mut self, expected: &str) -> bool {
+        if self.peek_keyword().as_deref() == Some(expected) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect_keyword(&mut self, expected: &str) -> Result<(), CubeError> {
+        if self.parse_keyword(expected) {
+            Ok(())
+        } else {
+            Err(self.expected(expected))
+        }
+    }
+
+    /// Unquoted names are folded to lower case
+    pub fn parse_name(&mut self) -> Result<String, CubeError> {
+        match self.peek_token() {
+            Some(Token::Word(word)) => {
+                let name = match word.quote_style {
+                    Some(_) => word.value.clone(),
+                    None => word.value.to_lowercase(),
+                };
+                self.index += 1;
+
+                Ok(name)
+            }
+            _ => Err(self.expected("identifier")),
+        }
+    }
+
+    /// Text of the remaining tokens without the trailing semicolon
+    pub fn rest(&mut self) -> String {
+        self.skip_whitespace();
+        let text = self.tokens[self.index..]
+            .iter()
+            .map(token_text)
+            .collect::<String>();
+        self.index = self.tokens.len();
+
+        text.trim().trim_end_matches(';').trim_end().to_string()
+    }
+
+    pub fn expect_end(&mut self) -> Result<(), CubeError> {
+        while self.parse_token(&Token::SemiColon) {}
+
+        match self.peek_token() {
+            None => Ok(()),
+            _ => Err(self.expected("end of statement")),
+        }
+    }
+
+    pub fn expected(&mut self, expected: &str) -> CubeError {
+        let found = match self.peek_token() {
+            Some(token) => token_text(token),
+            None => "EOF".to_string(),
+        };
+
+        CubeError::user(format!(
+            "Unable to parse: expected {}, found: {}",
+            expected, found
+        ))
+    }
+}
+
+/// Splits the query into statements by semicolons, empty statements (only whitespaces
+/// and comments) are skipped. A query with a single statement is returned as is, the SQL parser
+/// reports errors of queries, which can't be tokenized.
+pub fn split_statements(query: &str) -> Vec<String> {
+    let tokens = match Tokenizer::new(&PostgreSqlDialect {}, query).tokenize() {
+        Ok(tokens) => tokens,
+        Err(_) => return vec![query.to_string()],
+    };
+
+    let mut statements = vec![];
+    let mut statement = String::new();
+    let mut is_empty = true;
+    for token in tokens.iter() {
+        match token {
+            Token::SemiColon => {
+                if !is_empty {
+                    statements.push(statement.trim().to_string());
+                }
+                statement.clear();
+                is_empty = true;
+            }
+            // Leading whitespaces and comments are skipped
+            Token::Whitespace(_) if is_empty => {}
+            token => {
+                is_empty = false;
+                statement.push_str(&token_text(token));
+            }
+        }
+    }
+    if !is_empty {
+        statements.push(statement.trim().to_string());
+    }
+
+    if statements.len() == 1 {
+        vec![query.to_string()]
+    } else {
+        statements
+    }
+}
+
+/// Text of the token as it was written in the query
+pub fn token_text(token: &Token) -> String {
+    match token {
+        // The tokenizer unescapes quotes
+        Token::SingleQuotedString(value) => format!("'{}'", value.replace('\'', "''")),
+        token => token.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements("SET a = 1; SELECT 'a;b'; -- comment\n SELECT 2;;"),
+            vec![
+                "SET a = 1".to_string(),
+                "SELECT 'a;b'".to_string(),
+                "SELECT 2".to_string()
+            ]
+        );
+        assert_eq!(
+            split_statements("SELECT 1;  "),
+            vec!["SELECT 1;  ".to_string()]
+        );
+        assert_eq!(split_statements(" ; -- comment"), Vec::<String>::new());
+    }
+}
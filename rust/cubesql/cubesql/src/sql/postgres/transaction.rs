@@ -0,0 +1,303 @@
+use sqlparser::ast;
+
+use crate::CubeError;
+
+use super::{
+    protocol::{CommandCompleteTag, TransactionStatus},
+    tokens::TokenParser,
+};
+
+/// Transaction control commands, they don't touch data, only the state of the session
+#[derive(Debug, PartialEq)]
+pub enum TransactionCommand {
+    /// BEGIN, START TRANSACTION
+    Begin,
+    /// COMMIT, END
+    Commit,
+    /// ROLLBACK, ABORT
+    Rollback,
+    Savepoint(String),
+    /// RELEASE [SAVEPOINT] name
+    Release(String),
+    /// ROLLBACK TO [SAVEPOINT] name
+    RollbackToSavepoint(String),
+}
+
+/// Parses `query` as a transaction command, Ok(None) is returned for other statements
+pub fn parse_transaction_command(query: &str) -> Result<Option<TransactionCommand>, CubeError> {
+    let mut parser = TokenParser::new(query)?;
+
+    let command = match parser.next_keyword().as_deref() {
+        Some("BEGIN") => {
+            parse_work(&mut parser);
+            parse_modes(&mut parser);
+            TransactionCommand::Begin
+        }
+        Some("START") => {
+            if !parser.parse_keyword("TRANSACTION") {
+                return Ok(None);
+            }
+            parse_modes(&mut parser);
+            TransactionCommand::Begin
+        }
+        Some("COMMIT") | Some("END") => {
+            parse_work(&mut parser);
+            parse_chain(&mut parser)?;
+            TransactionCommand::Commit
+        }
+        Some("ROLLBACK") | Some("ABORT") => {
+            parse_work(&mut parser);
+            if parser.parse_keyword("TO") {
+                parser.parse_keyword("SAVEPOINT");
+                TransactionCommand::RollbackToSavepoint(parser.parse_name()?)
+            } else {
+                parse_chain(&mut parser)?;
+                TransactionCommand::Rollback
+            }
+        }
+        Some("SAVEPOINT") => TransactionCommand::Savepoint(parser.parse_name()?),
+        Some("RELEASE") => {
+            parser.parse_keyword("SAVEPOINT");
+            TransactionCommand::Release(parser.parse_name()?)
+        }
+        _ => return Ok(None),
+    };
+    parser.expect_end()?;
+
+    Ok(Some(command))
+}
+
+/// Transaction commands, which were parsed by the SQL parser (the extended query protocol)
+pub fn transaction_command_from_statement(
+    stmt: &ast::Statement,
+) -> Result<Option<TransactionCommand>, CubeError> {
+    match stmt {
+        ast::Statement::StartTransaction { .. }
+        | ast::Statement::Commit { .. }
+        | ast::Statement::Rollback { .. } => parse_transaction_command(&stmt.to_string()),
+        _ => Ok(None),
+    }
+}
+
+fn parse_work(parser: &mut TokenParser) {
+    if !parser.parse_keyword("WORK") {
+        parser.parse_keyword("TRANSACTION");
+    }
+}
+
+/// Isolation level and access modes don't change anything, all queries are read only
+fn parse_modes(parser: &mut TokenParser) {
+    parser.rest();
+}
+
+fn parse_chain(parser: &mut TokenParser) -> Result<(), CubeError> {
+    if parser.parse_keyword("AND") {
+        if !parser.parse_keyword("NO") {
+            return Err(CubeError::user("AND CHAIN is not supported".to_string()));
+        }
+        parser.expect_keyword("CHAIN")?;
+    }
+
+    Ok(())
+}
+
+/// State of the transaction block of a session. Queries are read only, so transactions
+/// only track the status, which is reported to clients in ReadyForQuery.
+#[derive(Debug)]
+pub struct Transaction {
+    status: TransactionStatus,
+    savepoints: Vec<String>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self {
+            status: TransactionStatus::Idle,
+            savepoints: vec![],
+        }
+    }
+
+    pub fn status(&self) -> TransactionStatus {
+        self.status
+    }
+
+    /// Statements, except of COMMIT and ROLLBACK, are rejected in the failed transaction block
+    pub fn check_active(&self) -> Result<(), CubeError> {
+        if self.status == TransactionStatus::InFailedTransactionBlock {
+            return Err(CubeError::user(
+                "current transaction is aborted, commands ignored until end of transaction block"
+                    .to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Errors inside of the transaction block fail it, errors outside of it don't change the state
+    pub fn fail(&mut self) {
+        if self.status == TransactionStatus::InTransactionBlock {
+            self.status = TransactionStatus::InFailedTransactionBlock;
+        }
+    }
+
+    /// Applies `command`, the tag for CommandComplete is returned
+    pub fn apply(&mut self, command: &TransactionCommand) -> Result<CommandCompleteTag, CubeError> {
+        match command {
+            // BEGIN inside of the transaction block is ignored with a warning by PostgreSQL
+            TransactionCommand::Begin => {
+                self.check_active()?;
+                self.status = TransactionStatus::InTransactionBlock;
+
+                Ok(CommandCompleteTag::Begin)
+            }
+            TransactionCommand::Commit => {
+                let failed = self.status == TransactionStatus::InFailedTransactionBlock;
+                self.end();
+
+                // COMMIT of the failed transaction is ROLLBACK
+                Ok(if failed {
+                    CommandCompleteTag::Rollback
+                } else {
+                    CommandCompleteTag::Commit
+                })
+            }
+            TransactionCommand::Rollback => {
+                self.end();
+
+                Ok(CommandCompleteTag::Rollback)
+            }
+            TransactionCommand::Savepoint(name) => {
+                self.check_block("SAVEPOINT")?;
+                self.check_active()?;
+                self.savepoints.push(name.clone());
+
+                Ok(CommandCompleteTag::Savepoint)
+            }
+            TransactionCommand::Release(name) => {
+                self.check_block("RELEASE SAVEPOINT")?;
+                self.check_active()?;
+                // The savepoint and all savepoints, which were created after it, are released
+                let position = self.find_savepoint(name)?;
+                self.savepoints.truncate(position);
+
+                Ok(CommandCompleteTag::Release)
+            }
+            TransactionCommand::RollbackToSavepoint(name) => {
+                self.check_block("ROLLBACK TO SAVEPOINT")?;
+                // The savepoint is kept, the transaction block becomes active again
+                let position = self.find_savepoint(name)?;
+                self.savepoints.truncate(position + 1);
+                self.status = TransactionStatus::InTransactionBlock;
+
+                Ok(CommandCompleteTag::Rollback)
+            }
+        }
+    }
+
+    fn end(&mut self) {
+        self.status = TransactionStatus::Idle;
+        self.savepoints.clear();
+    }
+
+    fn check_block(&self, command: &str) -> Result<(), CubeError> {
+        if self.status == TransactionStatus::Idle {
+            return Err(CubeError::user(format!(
+                "{} can only be used in transaction blocks",
+                command
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn find_savepoint(&self, name: &str) -> Result<usize, CubeError> {
+        self.savepoints
+            .iter()
+            .rposition(|savepoint| savepoint == name)
+            .ok_or_else(|| CubeError::user(format!("savepoint \"{}\" does not exist", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> TransactionCommand {
+        parse_transaction_command(query).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_transaction_command() {
+        assert_eq!(parse("BEGIN"), TransactionCommand::Begin);
+        assert_eq!(
+            parse("begin transaction isolation level read committed;"),
+            TransactionCommand::Begin
+        );
+        assert_eq!(
+            parse("START TRANSACTION READ ONLY"),
+            TransactionCommand::Begin
+        );
+        assert_eq!(parse("COMMIT WORK"), TransactionCommand::Commit);
+        assert_eq!(parse("END"), TransactionCommand::Commit);
+        assert_eq!(parse("ROLLBACK AND NO CHAIN"), TransactionCommand::Rollback);
+        assert_eq!(parse("ABORT"), TransactionCommand::Rollback);
+        assert_eq!(
+            parse("SAVEPOINT \"Sp\""),
+            TransactionCommand::Savepoint("Sp".to_string())
+        );
+        assert_eq!(
+            parse("RELEASE SAVEPOINT sp"),
+            TransactionCommand::Release("sp".to_string())
+        );
+        assert_eq!(
+            parse("ROLLBACK TO sp"),
+            TransactionCommand::RollbackToSavepoint("sp".to_string())
+        );
+
+        assert!(parse_transaction_command("COMMIT AND CHAIN").is_err());
+        assert_eq!(parse_transaction_command("START SLAVE").unwrap(), None);
+        assert_eq!(parse_transaction_command("SELECT 1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_transaction_state() -> Result<(), CubeError> {
+        let mut transaction = Transaction::new();
+        // Errors outside of transaction blocks don't change the state
+        transaction.fail();
+        assert_eq!(transaction.status(), TransactionStatus::Idle);
+        assert!(transaction
+            .apply(&TransactionCommand::Savepoint("sp".to_string()))
+            .is_err());
+
+        assert_eq!(
+            transaction.apply(&TransactionCommand::Begin)?,
+            CommandCompleteTag::Begin
+        );
+        assert_eq!(transaction.status(), TransactionStatus::InTransactionBlock);
+        transaction.apply(&TransactionCommand::Savepoint("sp".to_string()))?;
+
+        transaction.fail();
+        assert_eq!(
+            transaction.status(),
+            TransactionStatus::InFailedTransactionBlock
+        );
+        assert!(transaction.check_active().is_err());
+        assert!(transaction.apply(&TransactionCommand::Begin).is_err());
+
+        transaction.apply(&TransactionCommand::RollbackToSavepoint("sp".to_string()))?;
+        assert_eq!(transaction.status(), TransactionStatus::InTransactionBlock);
+        transaction.apply(&TransactionCommand::Release("sp".to_string()))?;
+        assert!(transaction
+            .apply(&TransactionCommand::Release("sp".to_string()))
+            .is_err());
+
+        transaction.fail();
+        assert_eq!(
+            transaction.apply(&TransactionCommand::Commit)?,
+            CommandCompleteTag::Rollback
+        );
+        assert_eq!(transaction.status(), TransactionStatus::Idle);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::Error;
+
+use crate::{config::PostgresUnixSocketConfig, CubeError};
+
+use super::tls::PgStream;
+
+/// Listener of the unix domain socket, the socket file is removed when it's dropped
+pub struct UnixSocketListener {
+    #[cfg(unix)]
+    listener: tokio::net::UnixListener,
+    #[cfg(unix)]
+    path: String,
+}
+
+impl UnixSocketListener {
+    /// Binds the socket, a stale socket file of a previous process is replaced
+    #[cfg(unix)]
+    pub fn bind(config: &PostgresUnixSocketConfig) -> Result<Self, CubeError> {
+        use std::{
+            fs,
+            os::unix::fs::{FileTypeExt, PermissionsExt},
+        };
+
+        let path = &config.path;
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(CubeError::user(format!(
+                    "Unable to listen on {}: the file exists and it's not a socket",
+                    path
+                )));
+            }
+
+            fs::remove_file(path)?;
+        }
+
+        let listener = tokio::net::UnixListener::bind(path)
+            .map_err(|e| CubeError::user(format!("Unable to listen on {}: {}", path, e)))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(config.permissions))?;
+
+        Ok(Self {
+            listener,
+            path: path.clone(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(_config: &PostgresUnixSocketConfig) -> Result<Self, CubeError> {
+        Err(CubeError::user(
+            "Unix domain sockets are not supported on this platform".to_string(),
+        ))
+    }
+
+    #[cfg(unix)]
+    async fn accept(&self) -> Result<PgStream, Error> {
+        let (stream, _) = self.listener.accept().await?;
+
+        Ok(PgStream::Unix(stream))
+    }
+
+    #[cfg(not(unix))]
+    async fn accept(&self) -> Result<PgStream, Error> {
+        futures::future::pending().await
+    }
+}
+
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Unable to remove the socket file {}: {}", self.path, e);
+        }
+    }
+}
+
+/// Accepts a connection from the listener, it never resolves if the socket is not configured
+pub async fn accept_unix_socket(listener: &Option<UnixSocketListener>) -> Result<PgStream, Error> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => futures::future::pending().await,
+    }
+}
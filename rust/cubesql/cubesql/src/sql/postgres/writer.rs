@@ -0,0 +1,492 @@
+use std::convert::TryFrom;
+
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    sql::{
+        dataframe::{DecimalValue, TableValue},
+        interval::format_interval,
+        timezone::format_timestamptz,
+        ColumnType,
+    },
+    CubeError,
+};
+
+use super::{pg_type::PgTypeId, protocol::Format};
+
+/// Microseconds between the Unix epoch and 2000-01-01, which is the epoch of binary timestamps
+const PG_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
+/// Type of a result column, which is declared in RowDescription
+pub fn column_pg_type(typ: ColumnType) -> PgTypeId {
+    match typ {
+        ColumnType::String | ColumnType::VarStr => PgTypeId::Text,
+        ColumnType::Double => PgTypeId::Float8,
+        // Booleans are represented as Int8, see arrow_to_column_type
+        ColumnType::Int8 => PgTypeId::Bool,
+        ColumnType::Int32 => PgTypeId::Int4,
+        ColumnType::Int64 => PgTypeId::Int8,
+        ColumnType::Blob => PgTypeId::Bytea,
+        ColumnType::Timestamp => PgTypeId::Timestamp,
+        ColumnType::Timestamptz => PgTypeId::Timestamptz,
+        ColumnType::Interval => PgTypeId::Interval,
+        ColumnType::Decimal(_, _) => PgTypeId::Numeric,
+        ColumnType::List(element) => column_pg_type(*element)
+            .array()
+            .unwrap_or(PgTypeId::ArrayText),
+    }
+}
+
+/// Type modifier of a result column, which is declared in RowDescription: NUMERIC(p, s) is
+/// `((p << 16) | s) + 4`, other types have no modifier (-1)
+pub fn column_type_modifier(typ: ColumnType) -> i32 {
+    match typ {
+        ColumnType::Decimal(precision, scale) => (((precision << 16) | scale) + 4) as i32,
+        _ => -1,
+    }
+}
+
+/// Encodes a value of a column with the type `typ` for DataRow, None is NULL. Timestamps with
+/// time zone are written in `time_zone` (TimeZone of the session) in the text format.
+pub fn encode_value(
+    value: &TableValue,
+    typ: PgTypeId,
+    format: Format,
+    time_zone: &Tz,
+) -> Result<Option<Vec<u8>>, CubeError> {
+    match format {
+        Format::Text => Ok(encode_text(value, typ, time_zone).map(String::into_bytes)),
+        Format::Binary => encode_binary(value, typ, time_zone),
+    }
+}
+
+fn encode_text(value: &TableValue, typ: PgTypeId, time_zone: &Tz) -> Option<String> {
+    match value {
+        TableValue::Null => None,
+        TableValue::String(v) => Some(v.clone()),
+        TableValue::Int64(v) => Some(v.to_string()),
+        TableValue::Boolean(v) => Some((if *v { "t" } else { "f" }).to_string()),
+        TableValue::Float64(v) => Some(v.to_string()),
+        TableValue::Decimal(v) => Some(v.to_string()),
+        // Columns which are declared as text keep the RFC 3339 representation
+        TableValue::Timestamp(v) if typ == PgTypeId::Timestamp => Some(
+            Utc.timestamp_nanos(v.get_time_stamp())
+                .format("%Y-%m-%d %H:%M:%S%.f")
+                .to_string(),
+        ),
+        TableValue::Timestamp(v) => Some(v.to_string()),
+        TableValue::Timestamptz(v) => Some(format_timestamptz(v.get_time_stamp(), time_zone)),
+        TableValue::Interval(v) => Some(format_interval(v)),
+        TableValue::List(values) => {
+            let element = typ.element().unwrap_or(PgTypeId::Text);
+            let mut out = String::from("{");
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                match encode_text(value, element, time_zone) {
+                    Some(text) => write_array_element(&mut out, &text),
+                    None => out.push_str("NULL"),
+                }
+            }
+            out.push('}');
+
+            Some(out)
+        }
+    }
+}
+
+/// Elements of arrays are quoted when they're empty, contain delimiters, quotes, backslashes
+/// or whitespace, or can be confused with NULL
+fn write_array_element(out: &mut String, text: &str) {
+    let quote = text.is_empty()
+        || text.eq_ignore_ascii_case("NULL")
+        || text
+            .chars()
+            .any(|c| matches!(c, '{' | '}' | ',' | '"' | '\\') || c.is_whitespace());
+    if !quote {
+        out.push_str(text);
+        return;
+    }
+
+    out.push('"');
+    for c in text.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+}
+
+/// Binary arrays: the number of dimensions, the flag of NULL elements, the OID of elements,
+/// the size and the lower bound (1) of the dimension, elements prefixed by their lengths
+/// (-1 for NULL). Empty arrays have no dimensions.
+fn encode_array_binary(
+    values: &[TableValue],
+    element: PgTypeId,
+    time_zone: &Tz,
+) -> Result<Vec<u8>, CubeError> {
+    let mut out = vec![];
+    let ndim: i32 = if values.is_empty() { 0 } else { 1 };
+    let has_null = values.iter().any(|v| matches!(v, TableValue::Null));
+    out.extend_from_slice(&ndim.to_be_bytes());
+    out.extend_from_slice(&(has_null as i32).to_be_bytes());
+    out.extend_from_slice(&element.to_oid().to_be_bytes());
+    if ndim > 0 {
+        out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+        out.extend_from_slice(&1_i32.to_be_bytes());
+    }
+
+    for value in values {
+        match encode_binary(value, element, time_zone)? {
+            Some(bytes) => {
+                out.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+                out.extend_from_slice(&bytes);
+            }
+            None => out.extend_from_slice(&(-1_i32).to_be_bytes()),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Binary numeric, which is decoded by decode_numeric of the portal: ndigits, weight, sign and
+/// dscale headers, followed by base-10000 digits without leading and trailing zeros
+fn encode_numeric(value: &DecimalValue) -> Vec<u8> {
+    let text = value.to_string();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text.as_str()),
+    };
+    let (integral, fraction) = text.split_once('.').unwrap_or((text, ""));
+    // Digits are grouped by 4 from the decimal point
+    let integral = format!(
+        "{:0>width$}",
+        integral,
+        width = (integral.len() + 3) / 4 * 4
+    );
+    let fraction = format!(
+        "{:0<width$}",
+        fraction,
+        width = (fraction.len() + 3) / 4 * 4
+    );
+    let groups = |digits: &str| -> Vec<i16> {
+        digits
+            .as_bytes()
+            .chunks(4)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0, |group, digit| group * 10 + (digit - b'0') as i16)
+            })
+            .collect()
+    };
+
+    let mut digits = groups(&integral);
+    let mut weight = digits.len() as i16 - 1;
+    digits.extend(groups(&fraction));
+    while digits.first() == Some(&0) {
+        digits.remove(0);
+        weight -= 1;
+    }
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    if digits.is_empty() {
+        weight = 0;
+    }
+    let sign: u16 = if negative && !digits.is_empty() {
+        0x4000
+    } else {
+        0x0000
+    };
+
+    let mut out = vec![];
+    out.extend_from_slice(&(digits.len() as i16).to_be_bytes());
+    out.extend_from_slice(&weight.to_be_bytes());
+    out.extend_from_slice(&sign.to_be_bytes());
+    out.extend_from_slice(&(value.scale() as u16).to_be_bytes());
+    for digit in digits {
+        out.extend_from_slice(&digit.to_be_bytes());
+    }
+
+    out
+}
+
+fn encode_binary(
+    value: &TableValue,
+    typ: PgTypeId,
+    time_zone: &Tz,
+) -> Result<Option<Vec<u8>>, CubeError> {
+    let out_of_range = |v: &dyn ToString| {
+        CubeError::internal(format!(
+            "value {} is out of range for type {}",
+            v.to_string(),
+            typ
+        ))
+    };
+
+    let bytes = match (value, typ) {
+        (TableValue::Null, _) => return Ok(None),
+        (TableValue::Int64(v), PgTypeId::Int2) => i16::try_from(*v)
+            .map_err(|_| out_of_range(v))?
+            .to_be_bytes()
+            .to_vec(),
+        (TableValue::Int64(v), PgTypeId::Int4) => i32::try_from(*v)
+            .map_err(|_| out_of_range(v))?
+            .to_be_bytes()
+            .to_vec(),
+        (TableValue::Int64(v), PgTypeId::Float8) => (*v as f64).to_be_bytes().to_vec(),
+        (TableValue::Int64(v), PgTypeId::Bool) => vec![(*v != 0) as u8],
+        (TableValue::Int64(v), PgTypeId::Int8) => v.to_be_bytes().to_vec(),
+        (TableValue::Int64(v), PgTypeId::Numeric) => {
+            encode_numeric(&DecimalValue::new(*v as i128, 0))
+        }
+        (TableValue::Float64(v), PgTypeId::Float8) => v.to_be_bytes().to_vec(),
+        (TableValue::Float64(v), PgTypeId::Float4) => (*v as f32).to_be_bytes().to_vec(),
+        (TableValue::Decimal(v), PgTypeId::Numeric) => encode_numeric(v),
+        (TableValue::Decimal(v), PgTypeId::Float8) => v.to_f64().to_be_bytes().to_vec(),
+        (TableValue::Boolean(v), PgTypeId::Bool) => vec![*v as u8],
+        (TableValue::Timestamp(v), PgTypeId::Timestamp)
+        | (TableValue::Timestamptz(v), PgTypeId::Timestamptz) => (v.get_time_stamp() / 1000
+            - PG_EPOCH_MICROS)
+            .to_be_bytes()
+            .to_vec(),
+        // Microseconds, days and months
+        (TableValue::Interval(v), PgTypeId::Interval) => [
+            &v.microseconds().to_be_bytes()[..],
+            &v.days().to_be_bytes(),
+            &v.months().to_be_bytes(),
+        ]
+        .concat(),
+        // The binary representation of text is the text itself
+        (value, PgTypeId::Text)
+        | (value, PgTypeId::Varchar)
+        | (value, PgTypeId::Bytea)
+        | (value, PgTypeId::Json) => match encode_text(value, typ, time_zone) {
+            Some(v) => v.into_bytes(),
+            None => return Ok(None),
+        },
+        // The version of the format, which is 1, and the text
+        (value, PgTypeId::Jsonb) => match encode_text(value, typ, time_zone) {
+            Some(v) => [vec![1], v.into_bytes()].concat(),
+            None => return Ok(None),
+        },
+        (TableValue::List(values), typ) => match typ.element() {
+            Some(element) => encode_array_binary(values, element, time_zone)?,
+            None => {
+                return Err(CubeError::internal(format!(
+                    "array {:?} can't be encoded as {}",
+                    values, typ
+                )))
+            }
+        },
+        (value, typ) => {
+            return Err(CubeError::internal(format!(
+                "binary format is not supported for value {:?} of type {}",
+                value, typ
+            )))
+        }
+    };
+
+    Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::dataframe::{IntervalValue, TimestampValue};
+
+    #[test]
+    fn test_encode_value_text() -> Result<(), CubeError> {
+        let encode = |value: TableValue, typ: PgTypeId| -> Result<Option<String>, CubeError> {
+            Ok(encode_value(&value, typ, Format::Text, &Tz::UTC)?
+                .map(|v| String::from_utf8(v).unwrap()))
+        };
+
+        assert_eq!(encode(TableValue::Null, PgTypeId::Text)?, None);
+        assert_eq!(
+            encode(TableValue::Boolean(false), PgTypeId::Bool)?,
+            Some("f".to_string())
+        );
+        assert_eq!(
+            encode(TableValue::Int64(-5), PgTypeId::Int8)?,
+            Some("-5".to_string())
+        );
+        assert_eq!(
+            encode(
+                TableValue::Timestamp(TimestampValue::new(1_646_130_600_000_000_000)),
+                PgTypeId::Timestamp
+            )?,
+            Some("2022-03-01 10:30:00".to_string())
+        );
+        assert_eq!(
+            encode(
+                TableValue::Timestamptz(TimestampValue::new(1_646_130_600_000_000_000)),
+                PgTypeId::Timestamptz
+            )?,
+            Some("2022-03-01 10:30:00+00".to_string())
+        );
+        assert_eq!(
+            encode_value(
+                &TableValue::Timestamptz(TimestampValue::new(1_646_130_600_000_000_000)),
+                PgTypeId::Timestamptz,
+                Format::Text,
+                &Tz::America__New_York
+            )?,
+            Some(b"2022-03-01 05:30:00-05".to_vec())
+        );
+        assert_eq!(
+            encode(
+                TableValue::Interval(IntervalValue::new(1, 2, 3_600_000_000)),
+                PgTypeId::Interval
+            )?,
+            Some("1 mon 2 days 01:00:00".to_string())
+        );
+        assert_eq!(
+            encode(
+                TableValue::List(vec![
+                    TableValue::String("a".to_string()),
+                    TableValue::String("b c".to_string()),
+                    TableValue::Null,
+                    TableValue::String("NULL".to_string()),
+                    TableValue::String("\"{}\"".to_string()),
+                ]),
+                PgTypeId::ArrayText
+            )?,
+            Some("{a,\"b c\",NULL,\"NULL\",\"\\\"{}\\\"\"}".to_string())
+        );
+        assert_eq!(
+            encode(
+                TableValue::List(vec![TableValue::Int64(1), TableValue::Int64(2)]),
+                PgTypeId::ArrayInt8
+            )?,
+            Some("{1,2}".to_string())
+        );
+        assert_eq!(
+            encode(
+                TableValue::Decimal(DecimalValue::new(-1050, 3)),
+                PgTypeId::Numeric
+            )?,
+            Some("-1.050".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_value_binary() -> Result<(), CubeError> {
+        let encode = |value: TableValue, typ: PgTypeId| -> Result<Option<Vec<u8>>, CubeError> {
+            encode_value(&value, typ, Format::Binary, &Tz::UTC)
+        };
+
+        assert_eq!(encode(TableValue::Null, PgTypeId::Int8)?, None);
+        assert_eq!(
+            encode(TableValue::Int64(5), PgTypeId::Int8)?,
+            Some(5_i64.to_be_bytes().to_vec())
+        );
+        assert_eq!(
+            encode(TableValue::Int64(5), PgTypeId::Int4)?,
+            Some(5_i32.to_be_bytes().to_vec())
+        );
+        assert_eq!(
+            encode(TableValue::Float64(2.5), PgTypeId::Float8)?,
+            Some(2.5_f64.to_be_bytes().to_vec())
+        );
+        assert_eq!(
+            encode(TableValue::Boolean(true), PgTypeId::Bool)?,
+            Some(vec![1])
+        );
+        assert_eq!(
+            encode(TableValue::String("test".to_string()), PgTypeId::Text)?,
+            Some(b"test".to_vec())
+        );
+        assert_eq!(
+            encode(TableValue::String("{}".to_string()), PgTypeId::Jsonb)?,
+            Some(b"\x01{}".to_vec())
+        );
+        assert_eq!(
+            encode(
+                TableValue::List(vec![TableValue::Int64(7), TableValue::Null]),
+                PgTypeId::ArrayInt4
+            )?,
+            Some(
+                [
+                    &1_i32.to_be_bytes()[..],
+                    &1_i32.to_be_bytes(),
+                    &23_u32.to_be_bytes(),
+                    &2_i32.to_be_bytes(),
+                    &1_i32.to_be_bytes(),
+                    &4_i32.to_be_bytes(),
+                    &7_i32.to_be_bytes(),
+                    &(-1_i32).to_be_bytes(),
+                ]
+                .concat()
+            )
+        );
+        // 2000-01-02 00:00:00
+        assert_eq!(
+            encode(
+                TableValue::Timestamp(TimestampValue::new(946_771_200_000_000_000)),
+                PgTypeId::Timestamp
+            )?,
+            Some(86_400_000_000_i64.to_be_bytes().to_vec())
+        );
+        // Timestamps with time zone are UTC in the binary format
+        assert_eq!(
+            encode_value(
+                &TableValue::Timestamptz(TimestampValue::new(946_771_200_000_000_000)),
+                PgTypeId::Timestamptz,
+                Format::Binary,
+                &Tz::America__New_York
+            )?,
+            Some(86_400_000_000_i64.to_be_bytes().to_vec())
+        );
+        assert_eq!(
+            encode(
+                TableValue::Interval(IntervalValue::new(1, 2, 1_500_000)),
+                PgTypeId::Interval
+            )?,
+            Some(
+                [
+                    &1_500_000_i64.to_be_bytes()[..],
+                    &2_i32.to_be_bytes(),
+                    &1_i32.to_be_bytes(),
+                ]
+                .concat()
+            )
+        );
+
+        assert_eq!(
+            encode(TableValue::Int64(100_000), PgTypeId::Int2)
+                .unwrap_err()
+                .message,
+            "value 100000 is out of range for type int2"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_numeric() {
+        let encode = |value: i128, scale: usize| -> Vec<i16> {
+            encode_numeric(&DecimalValue::new(value, scale))
+                .chunks(2)
+                .map(|chunk| i16::from_be_bytes([chunk[0], chunk[1]]))
+                .collect()
+        };
+
+        // 12345.60 is 1 2345 . 6000
+        assert_eq!(encode(1_234_560, 2), vec![3, 1, 0, 2, 1, 2345, 6000]);
+        // -0.05 is 0 . 0500
+        assert_eq!(encode(-5, 2), vec![1, -1, 0x4000, 2, 500]);
+        // 10000 is 1 0000
+        assert_eq!(encode(10_000, 0), vec![1, 1, 0, 0, 1]);
+        assert_eq!(encode(0, 3), vec![0, 0, 0, 3]);
+
+        assert_eq!(column_type_modifier(ColumnType::Decimal(10, 2)), 655_366);
+        assert_eq!(column_type_modifier(ColumnType::Double), -1);
+    }
+}
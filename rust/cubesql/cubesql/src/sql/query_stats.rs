@@ -0,0 +1,185 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::RwLock as RwLockSync,
+    time::Duration,
+};
+
+/// Maximum number of tracked statements as `pg_stat_statements.max` in PostgreSQL, the least
+/// executed statement is evicted to track a new one
+const MAX_STATEMENTS: usize = 5000;
+
+/// Execution statistics of a normalized statement of a user
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryStatistic {
+    pub query_id: i64,
+    pub user: Option<String>,
+    pub query: String,
+    pub calls: u64,
+    pub rows: u64,
+    /// Times of executions in milliseconds
+    pub total_time: f64,
+    pub min_time: f64,
+    pub max_time: f64,
+    /// Sum of squared times to compute the standard deviation
+    sum_of_squares: f64,
+}
+
+impl QueryStatistic {
+    pub fn mean_time(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.total_time / self.calls as f64
+        }
+    }
+
+    /// Population standard deviation of times as in PostgreSQL
+    pub fn stddev_time(&self) -> f64 {
+        if self.calls == 0 {
+            return 0.0;
+        }
+
+        let mean = self.mean_time();
+        (self.sum_of_squares / self.calls as f64 - mean * mean)
+            .max(0.0)
+            .sqrt()
+    }
+}
+
+/// Collector of statistics of executed statements, which is shared by all sessions
+#[derive(Debug)]
+pub struct QueryStats {
+    statements: RwLockSync<HashMap<(Option<String>, String), QueryStatistic>>,
+    max_statements: usize,
+}
+
+impl QueryStats {
+    pub fn new() -> Self {
+        Self::with_capacity(MAX_STATEMENTS)
+    }
+
+    pub fn with_capacity(max_statements: usize) -> Self {
+        Self {
+            statements: RwLockSync::new(HashMap::new()),
+            max_statements: max_statements.max(1),
+        }
+    }
+
+    /// Registers an execution of the normalized `query`, which took `elapsed` and
+    /// returned `rows`
+    pub fn record(&self, user: Option<String>, query: String, elapsed: Duration, rows: u64) {
+        let time = elapsed.as_nanos() as f64 / 1_000_000.0;
+
+        let mut guard = self
+            .statements
+            .write()
+            .expect("failed to unlock query stats for recording");
+
+        let key = (user, query);
+        if !guard.contains_key(&key) && guard.len() >= self.max_statements {
+            let evicted = guard
+                .iter()
+                .min_by_key(|(_, statistic)| statistic.calls)
+                .map(|(key, _)| key.clone());
+            if let Some(evicted) = evicted {
+                guard.remove(&evicted);
+            }
+        }
+
+        let statistic = guard.entry(key.clone()).or_insert_with(|| QueryStatistic {
+            query_id: query_id(&key.1),
+            user: key.0,
+            query: key.1,
+            calls: 0,
+            rows: 0,
+            total_time: 0.0,
+            min_time: time,
+            max_time: time,
+            sum_of_squares: 0.0,
+        });
+        statistic.calls += 1;
+        statistic.rows += rows;
+        statistic.total_time += time;
+        statistic.min_time = statistic.min_time.min(time);
+        statistic.max_time = statistic.max_time.max(time);
+        statistic.sum_of_squares += time * time;
+    }
+
+    pub fn all(&self) -> Vec<QueryStatistic> {
+        let guard = self
+            .statements
+            .read()
+            .expect("failed to unlock query stats for reading");
+
+        guard.values().cloned().collect()
+    }
+}
+
+impl Default for QueryStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Identifier of the normalized query, it's the same for all users
+fn query_id(query: &str) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    query.hash(&mut hasher);
+
+    hasher.finish() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_stats() {
+        let stats = QueryStats::with_capacity(2);
+        let user = Some("ovr".to_string());
+        stats.record(
+            user.clone(),
+            "SELECT $1".to_string(),
+            Duration::from_millis(10),
+            1,
+        );
+        stats.record(
+            user.clone(),
+            "SELECT $1".to_string(),
+            Duration::from_millis(30),
+            1,
+        );
+        stats.record(None, "SELECT $1".to_string(), Duration::from_millis(5), 1);
+
+        let mut all = stats.all();
+        all.sort_by_key(|statistic| statistic.user.clone());
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].query_id, all[1].query_id);
+
+        let statistic = &all[1];
+        assert_eq!(statistic.calls, 2);
+        assert_eq!(statistic.rows, 2);
+        assert_eq!(statistic.total_time, 40.0);
+        assert_eq!(statistic.min_time, 10.0);
+        assert_eq!(statistic.max_time, 30.0);
+        assert_eq!(statistic.mean_time(), 20.0);
+        assert_eq!(statistic.stddev_time(), 10.0);
+
+        // The statement of the anonymous user is executed less often
+        stats.record(user, "SELECT 1".to_string(), Duration::from_millis(1), 1);
+        let mut queries = stats
+            .all()
+            .into_iter()
+            .map(|statistic| (statistic.user, statistic.query))
+            .collect::<Vec<_>>();
+        queries.sort();
+        assert_eq!(
+            queries,
+            vec![
+                (Some("ovr".to_string()), "SELECT $1".to_string()),
+                (Some("ovr".to_string()), "SELECT 1".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,98 @@
+/// Text of a regular expression without operators, escaped punctuation is unescaped. Classes
+/// (`\d`) and other escapes aren't literals.
+fn regexp_literal(pattern: &str) -> Option<String> {
+    let mut literal = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(ch) if !ch.is_alphanumeric() => literal.push(ch),
+                _ => return None,
+            },
+            '.' | '[' | ']' | '(' | ')' | '*' | '+' | '?' | '{' | '}' | '|' | '^' | '$' => {
+                return None
+            }
+            ch => literal.push(ch),
+        }
+    }
+
+    Some(literal)
+}
+
+/// Operator and value of the Cube filter of `member ~ 'pattern'`: literals are `contains`,
+/// `^literal` is `startsWith`, `literal$` is `endsWith`, `^literal$` is `equals`. As LIKE, these
+/// filters match case-insensitively, except `equals`, which can't be used for `~*`. Negated
+/// operators (`!~`) are `notContains` and `notEquals`. None if the pattern isn't simple.
+pub fn regexp_filter(
+    pattern: &str,
+    case_insensitive: bool,
+    negated: bool,
+) -> Option<(&'static str, String)> {
+    let (pattern, starts) = match pattern.strip_prefix('^') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let (pattern, ends) = match pattern.strip_suffix('$') {
+        Some(pattern) if !pattern.ends_with('\\') => (pattern, true),
+        _ => (pattern, false),
+    };
+
+    // Every string matches an empty pattern
+    let literal = regexp_literal(pattern).filter(|literal| !literal.is_empty())?;
+    let operator = match (starts, ends, negated) {
+        (false, false, false) => "contains",
+        (false, false, true) => "notContains",
+        (true, false, false) => "startsWith",
+        (false, true, false) => "endsWith",
+        (true, true, false) if !case_insensitive => "equals",
+        (true, true, true) if !case_insensitive => "notEquals",
+        _ => return None,
+    };
+
+    Some((operator, literal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regexp_filter() {
+        let filter = |pattern: &str, case_insensitive: bool, negated: bool| {
+            regexp_filter(pattern, case_insensitive, negated)
+                .map(|(operator, value)| format!("{} {}", operator, value))
+        };
+
+        assert_eq!(
+            filter("fem", false, false),
+            Some("contains fem".to_string())
+        );
+        assert_eq!(
+            filter("^fem", true, false),
+            Some("startsWith fem".to_string())
+        );
+        assert_eq!(
+            filter("ale$", false, false),
+            Some("endsWith ale".to_string())
+        );
+        assert_eq!(
+            filter("^female$", false, false),
+            Some("equals female".to_string())
+        );
+        assert_eq!(filter("^female$", true, false), None);
+        assert_eq!(
+            filter("a\\.b c", false, true),
+            Some("notContains a.b c".to_string())
+        );
+        assert_eq!(filter("^fem", false, true), None);
+        assert_eq!(filter("fe.ale", false, false), None);
+        assert_eq!(filter("\\d+", false, false), None);
+        assert_eq!(filter("a|b", false, false), None);
+        assert_eq!(filter("^$", false, false), None);
+        assert_eq!(filter("", false, false), None);
+        assert_eq!(
+            filter("cost\\$", false, false),
+            Some("contains cost$".to_string())
+        );
+    }
+}
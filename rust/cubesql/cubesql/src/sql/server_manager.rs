@@ -12,12 +12,30 @@ use super::{database_variables::DatabaseVariables, session::DatabaseProtocol};
 pub struct ServerConfiguration {
     /// Max number of prepared statements which can be allocated per connection
     pub connection_max_prepared_statements: usize,
+    /// Max number of PostgreSQL connections, None is for no limit
+    pub max_connections: Option<usize>,
+    /// Max numbers of PostgreSQL connections of users
+    pub user_connection_limits: HashMap<String, usize>,
+    /// Max number of batches of a PostgreSQL result, which are fetched ahead of the socket
+    pub max_in_flight_batches: usize,
+    /// Defaults of idle_session_timeout and idle_in_transaction_session_timeout of PostgreSQL
+    /// sessions in seconds, 0 disables the timeout
+    pub idle_session_timeout: u64,
+    pub idle_in_transaction_session_timeout: u64,
+    /// Default of slow_query_log_min_duration in milliseconds, MySQL sessions always use it
+    pub slow_query_log_min_duration: u64,
 }
 
 impl Default for ServerConfiguration {
     fn default() -> Self {
         Self {
             connection_max_prepared_statements: 50,
+            max_connections: None,
+            user_connection_limits: HashMap::new(),
+            max_in_flight_batches: 2,
+            idle_session_timeout: 0,
+            idle_in_transaction_session_timeout: 0,
+            slow_query_log_min_duration: 0,
         }
     }
 }
@@ -44,12 +62,13 @@ impl ServerManager {
         auth: Arc<dyn SqlAuthService>,
         transport: Arc<dyn TransportService>,
         nonce: Option<Vec<u8>>,
+        configuration: ServerConfiguration,
     ) -> Self {
         Self {
             auth,
             transport,
             nonce,
-            configuration: ServerConfiguration::default(),
+            configuration,
         }
     }
 
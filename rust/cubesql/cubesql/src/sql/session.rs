@@ -1,9 +1,27 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock as RwLockSync},
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock as RwLockSync, RwLockReadGuard, RwLockWriteGuard,
+    },
 };
 
-use crate::sql::database_variables::mysql_default_session_variables;
+use chrono::{DateTime, Utc};
+use tokio::sync::Notify;
+
+use crate::{
+    sql::{
+        database_variables::mysql_default_session_variables,
+        dataframe::DataFrame,
+        postgres::{
+            cursor::{Cursor, FetchDirection},
+            settings::Settings,
+        },
+    },
+    telemetry::tracing::SpanContext,
+    CubeError,
+};
 
 use super::{
     database_variables::DatabaseVariables, server_manager::ServerManager,
@@ -18,6 +36,16 @@ pub enum DatabaseProtocol {
     PostgreSQL,
 }
 
+impl DatabaseProtocol {
+    /// Name of the protocol in metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MySQL => "mysql",
+            Self::PostgreSQL => "postgres",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SessionProperties {
     user: Option<String>,
@@ -30,6 +58,54 @@ impl SessionProperties {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityState {
+    Active,
+    Idle,
+    IdleInTransaction,
+    IdleInTransactionAborted,
+}
+
+impl ActivityState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityState::Active => "active",
+            ActivityState::Idle => "idle",
+            ActivityState::IdleInTransaction => "idle in transaction",
+            ActivityState::IdleInTransactionAborted => "idle in transaction (aborted)",
+        }
+    }
+}
+
+/// What the session is doing right now, it's shown in pg_stat_activity
+#[derive(Debug, Clone)]
+pub struct SessionActivity {
+    pub application_name: Option<String>,
+    pub state: ActivityState,
+    // The current query or the last one if the session is idle
+    pub query: Option<String>,
+    pub backend_start: DateTime<Utc>,
+    pub xact_start: Option<DateTime<Utc>>,
+    pub query_start: Option<DateTime<Utc>>,
+    pub state_change: DateTime<Utc>,
+}
+
+impl SessionActivity {
+    fn new() -> Self {
+        let now = Utc::now();
+
+        Self {
+            application_name: None,
+            state: ActivityState::Idle,
+            query: None,
+            backend_start: now,
+            xact_start: None,
+            query_start: None,
+            state_change: now,
+        }
+    }
+}
+
 lazy_static! {
     static ref POSTGRES_DEFAULT_VARIABLES: DatabaseVariables = HashMap::new();
     static ref MYSQL_DEFAULT_VARIABLES: DatabaseVariables = mysql_default_session_variables();
@@ -43,6 +119,8 @@ pub struct SessionState {
     pub host: String,
     // client protocol, mysql/postgresql, immutable
     pub protocol: DatabaseProtocol,
+    // secret key for CancelRequest, it's sent to the client in BackendKeyData, immutable
+    pub secret_key: u32,
 
     // session db variables
     variables: Option<Arc<RwLockSync<DatabaseVariables>>>,
@@ -53,6 +131,24 @@ pub struct SessionState {
     // @todo Remove RWLock after split of Connection & SQLWorker
     // Context for Transport
     auth_context: RwLockSync<Option<AuthContext>>,
+
+    // Server-side cursors (DECLARE), they are kept until CLOSE or the end of the session
+    cursors: RwLockSync<HashMap<String, Cursor>>,
+
+    // Notifier of the query in progress, it's triggered to cancel the query
+    query_cancel: RwLockSync<Option<Arc<Notify>>>,
+
+    activity: RwLockSync<SessionActivity>,
+
+    // Run-time parameters of PostgreSQL (SET, SHOW, pg_settings)
+    settings: RwLockSync<Settings>,
+
+    // The session was terminated by pg_terminate_backend, the connection must be closed
+    terminated: AtomicBool,
+    terminate_notify: Notify,
+
+    // Span of the statement in progress, requests to the Cube API are traced under it
+    trace_context: RwLockSync<Option<SpanContext>>,
 }
 
 impl SessionState {
@@ -66,9 +162,17 @@ impl SessionState {
             connection_id,
             host,
             protocol,
+            secret_key: rand::random(),
             variables: None,
             properties: RwLockSync::new(SessionProperties::new(None, None)),
             auth_context: RwLockSync::new(auth_context),
+            cursors: RwLockSync::new(HashMap::new()),
+            query_cancel: RwLockSync::new(None),
+            activity: RwLockSync::new(SessionActivity::new()),
+            settings: RwLockSync::new(Settings::new()),
+            terminated: AtomicBool::new(false),
+            terminate_notify: Notify::new(),
+            trace_context: RwLockSync::new(None),
         }
     }
 
@@ -120,6 +224,215 @@ impl SessionState {
         *guard = auth_context;
     }
 
+    /// Registers the query in progress, the returned notifier is triggered by cancel_query
+    pub fn begin_query(&self) -> Arc<Notify> {
+        let mut guard = self
+            .query_cancel
+            .write()
+            .expect("failed to unlock query_cancel for writting");
+        let notify = Arc::new(Notify::new());
+        *guard = Some(notify.clone());
+
+        notify
+    }
+
+    pub fn end_query(&self) {
+        let mut guard = self
+            .query_cancel
+            .write()
+            .expect("failed to unlock query_cancel for writting");
+        *guard = None;
+    }
+
+    /// Cancels the query in progress, false is returned if there is no query
+    pub fn cancel_query(&self) -> bool {
+        let guard = self
+            .query_cancel
+            .read()
+            .expect("failed to unlock query_cancel for reading");
+        match &*guard {
+            Some(notify) => {
+                // The permit is stored if the query is not waiting for the notification yet
+                notify.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn activity(&self) -> SessionActivity {
+        let mut activity = self
+            .activity
+            .read()
+            .expect("failed to unlock activity for reading")
+            .clone();
+        activity.application_name = self.settings().application_name();
+
+        activity
+    }
+
+    /// Schemas to look for unqualified tables, `$user` is replaced by the user. pg_catalog is
+    /// searched first if it's not in search_path, as in PostgreSQL.
+    pub fn search_path(&self) -> Vec<String> {
+        let user = self.user();
+        let mut schemas = self
+            .settings()
+            .search_path()
+            .into_iter()
+            .filter_map(|schema| match schema.as_str() {
+                "$user" => user.clone(),
+                _ => Some(schema),
+            })
+            .collect::<Vec<_>>();
+        if !schemas.iter().any(|schema| schema == "pg_catalog") {
+            schemas.insert(0, "pg_catalog".to_string());
+        }
+
+        schemas
+    }
+
+    pub fn settings(&self) -> RwLockReadGuard<'_, Settings> {
+        self.settings
+            .read()
+            .expect("failed to unlock settings for reading")
+    }
+
+    pub fn settings_mut(&self) -> RwLockWriteGuard<'_, Settings> {
+        self.settings
+            .write()
+            .expect("failed to unlock settings for writting")
+    }
+
+    /// Marks the session as running `query`, a transaction is started by the query
+    /// if the session was not in a transaction
+    pub fn set_active(&self, query: String) {
+        let mut guard = self
+            .activity
+            .write()
+            .expect("failed to unlock activity for writting");
+        let now = Utc::now();
+        if guard.xact_start.is_none() {
+            guard.xact_start = Some(now);
+        }
+        guard.state = ActivityState::Active;
+        guard.query = Some(query);
+        guard.query_start = Some(now);
+        guard.state_change = now;
+    }
+
+    /// Marks the session as waiting for the client
+    pub fn set_idle(&self, state: ActivityState) {
+        let mut guard = self
+            .activity
+            .write()
+            .expect("failed to unlock activity for writting");
+        if guard.state == state {
+            return;
+        }
+
+        let now = Utc::now();
+        match state {
+            ActivityState::Idle => guard.xact_start = None,
+            _ => {
+                if guard.xact_start.is_none() {
+                    guard.xact_start = Some(now);
+                }
+            }
+        }
+        guard.state = state;
+        guard.state_change = now;
+    }
+
+    /// Requests closing of the connection, the query in progress is canceled
+    pub fn terminate(&self) {
+        self.terminated.store(true, Ordering::SeqCst);
+        // The permit is stored if the connection is not waiting for the notification yet
+        self.terminate_notify.notify_one();
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated.load(Ordering::SeqCst)
+    }
+
+    /// Resolves when the session is terminated
+    pub async fn terminated(&self) {
+        while !self.is_terminated() {
+            self.terminate_notify.notified().await;
+        }
+    }
+
+    pub fn trace_context(&self) -> Option<SpanContext> {
+        let guard = self
+            .trace_context
+            .read()
+            .expect("failed to unlock trace_context for reading");
+        *guard
+    }
+
+    pub fn set_trace_context(&self, trace_context: Option<SpanContext>) {
+        let mut guard = self
+            .trace_context
+            .write()
+            .expect("failed to unlock trace_context for writting");
+        *guard = trace_context;
+    }
+
+    pub fn declare_cursor(&self, name: String, cursor: Cursor) -> Result<(), CubeError> {
+        let mut guard = self
+            .cursors
+            .write()
+            .expect("failed to unlock cursors for writting");
+        if guard.contains_key(&name) {
+            return Err(CubeError::user(format!(
+                "cursor \"{}\" already exists",
+                name
+            )));
+        }
+
+        guard.insert(name, cursor);
+        Ok(())
+    }
+
+    /// Moves the cursor in `direction`, returns its result and the range of passed rows
+    pub fn fetch_cursor(
+        &self,
+        name: &str,
+        direction: FetchDirection,
+    ) -> Result<(Arc<DataFrame>, Range<usize>), CubeError> {
+        let mut guard = self
+            .cursors
+            .write()
+            .expect("failed to unlock cursors for writting");
+        let cursor = guard
+            .get_mut(name)
+            .ok_or_else(|| CubeError::user(format!("cursor \"{}\" does not exist", name)))?;
+
+        let range = cursor.advance(direction);
+        Ok((cursor.frame(), range))
+    }
+
+    pub fn close_cursor(&self, name: &str) -> Result<(), CubeError> {
+        let mut guard = self
+            .cursors
+            .write()
+            .expect("failed to unlock cursors for writting");
+        match guard.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(CubeError::user(format!(
+                "cursor \"{}\" does not exist",
+                name
+            ))),
+        }
+    }
+
+    pub fn close_all_cursors(&self) {
+        let mut guard = self
+            .cursors
+            .write()
+            .expect("failed to unlock cursors for writting");
+        guard.clear();
+    }
+
     pub fn all_variables(&self) -> DatabaseVariables {
         match &self.variables {
             Some(vars) => vars
@@ -150,6 +463,8 @@ impl Session {
             host: self.state.host.clone(),
             user: self.state.user(),
             database: self.state.database(),
+            protocol: self.state.protocol.clone(),
+            activity: self.state.activity(),
         }
     }
 }
@@ -160,4 +475,6 @@ pub struct SessionProcessList {
     pub user: Option<String>,
     pub host: String,
     pub database: Option<String>,
+    pub protocol: DatabaseProtocol,
+    pub activity: SessionActivity,
 }
@@ -9,6 +9,7 @@ use std::{
 use crate::CubeError;
 
 use super::{
+    query_stats::QueryStats,
     server_manager::ServerManager,
     session::{DatabaseProtocol, Session, SessionProcessList, SessionState},
 };
@@ -20,6 +21,8 @@ pub struct SessionManager {
     sessions: RwLockSync<HashMap<u32, Arc<Session>>>,
     // Backref
     pub server: Arc<ServerManager>,
+    /// Statistics of statements executed by all sessions
+    pub query_stats: QueryStats,
 }
 
 crate::di_service!(SessionManager, []);
@@ -30,6 +33,7 @@ impl SessionManager {
             last_id: AtomicU32::new(1),
             sessions: RwLockSync::new(HashMap::new()),
             server,
+            query_stats: QueryStats::new(),
         }
     }
 
@@ -57,6 +61,76 @@ impl SessionManager {
         session_ref
     }
 
+    pub fn get_session(&self, connection_id: u32) -> Option<Arc<Session>> {
+        let guard = self
+            .sessions
+            .read()
+            .expect("failed to unlock sessions for reading session");
+
+        guard.get(&connection_id).cloned()
+    }
+
+    /// Number of sessions of the protocol, including sessions which are not authenticated yet
+    pub fn count_sessions(&self, protocol: &DatabaseProtocol) -> usize {
+        let guard = self
+            .sessions
+            .read()
+            .expect("failed to unlock sessions for counting sessions");
+
+        guard
+            .values()
+            .filter(|session| &session.state.protocol == protocol)
+            .count()
+    }
+
+    /// Numbers of sessions of the protocol by users
+    pub fn user_session_counts(&self, protocol: &DatabaseProtocol) -> HashMap<String, usize> {
+        let guard = self
+            .sessions
+            .read()
+            .expect("failed to unlock sessions for counting sessions");
+
+        let mut counts = HashMap::new();
+        for session in guard.values() {
+            if &session.state.protocol != protocol {
+                continue;
+            }
+
+            if let Some(user) = session.state.user() {
+                *counts.entry(user).or_insert(0) += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// Sets the user of the session if the user has less than `limit` other sessions of
+    /// the same protocol, false is returned otherwise. Sessions are locked during the check,
+    /// so concurrent connections can't exceed the limit.
+    pub fn try_set_user(&self, state: &SessionState, user: String, limit: Option<usize>) -> bool {
+        let guard = self
+            .sessions
+            .write()
+            .expect("failed to unlock sessions for setting user");
+
+        if let Some(limit) = limit {
+            let count = guard
+                .values()
+                .filter(|session| {
+                    session.state.protocol == state.protocol
+                        && session.state.connection_id != state.connection_id
+                        && session.state.user().as_ref() == Some(&user)
+                })
+                .count();
+            if count >= limit {
+                return false;
+            }
+        }
+
+        state.set_user(Some(user));
+        true
+    }
+
     pub fn process_list(self: &Arc<Self>) -> Vec<SessionProcessList> {
         let guard = self
             .sessions
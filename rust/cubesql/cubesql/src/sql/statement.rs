@@ -1,5 +1,37 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryFrom,
+    ops::Range,
+};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
 use msql_srv::{Column, ColumnFlags, ColumnType};
-use sqlparser::ast;
+use sqlparser::{ast, dialect::Dialect, parser::Parser};
+
+use datafusion::{arrow::datatypes::DataType, logical_plan::DFSchema};
+
+use crate::{
+    compile::engine::{
+        df::coerce::{
+            decimal_aggregate_coercion, decimal_arithmetic_coercion, decimal_operand_type,
+            NumericOperator, MAX_DECIMAL_PRECISION,
+        },
+        udf::{
+            numeric_cast_name, parse_numeric_cast_name, AGGREGATE_FILTER_FUNCTION,
+            AGGREGATE_ORDER_BY_FUNCTION, ARRAY_AGG_DISTINCT_FUNCTION, JSONB_CAST_FUNCTION,
+            JSON_CAST_FUNCTION, REGEXP_SUBSTRING_FUNCTION, STRING_AGG_DISTINCT_FUNCTION,
+            TIMESTAMPTZ_LOCAL_FUNCTION, TIMESTAMPTZ_PART_FUNCTION, TIMESTAMPTZ_TRUNC_FUNCTION,
+            TIMESTAMP_PART_FUNCTION, TO_TIMESTAMPTZ_FUNCTION,
+        },
+    },
+    sql::{
+        dataframe::{DecimalValue, IntervalValue},
+        interval::interval_literal,
+        postgres::pg_type::PgTypeId,
+    },
+    transport::{DEFAULT_DECIMAL_PRECISION, DEFAULT_DECIMAL_SCALE},
+    CubeError,
+};
 
 #[derive(Debug)]
 pub enum BindValue {
@@ -8,110 +40,818 @@ pub enum BindValue {
     #[allow(unused)]
     UInt64(u64),
     Float64(f64),
+    /// Decimal in the text representation, to keep the precision
+    Numeric(String),
     Bool(bool),
+    Date(NaiveDate),
+    Timestamp(NaiveDateTime),
+    TimestampTz(DateTime<Utc>),
+    /// Interval in the text representation (`1 mon 2 days 00:00:01`)
+    Interval(String),
+    Null,
+    /// Elements must be of the same kind, nested arrays are multidimensional
+    Array(Vec<BindValue>),
+}
+
+impl BindValue {
+    fn variant_name(&self) -> &'static str {
+        match self {
+            BindValue::String(_) => "String",
+            BindValue::Int64(_) => "Int64",
+            BindValue::UInt64(_) => "UInt64",
+            BindValue::Float64(_) => "Float64",
+            BindValue::Numeric(_) => "Numeric",
+            BindValue::Bool(_) => "Bool",
+            BindValue::Date(_) => "Date",
+            BindValue::Timestamp(_) => "Timestamp",
+            BindValue::TimestampTz(_) => "TimestampTz",
+            BindValue::Interval(_) => "Interval",
+            BindValue::Null => "Null",
+            BindValue::Array(_) => "Array",
+        }
+    }
+
+    /// Text representation of temporal values, None for other values
+    fn temporal_text(&self) -> Option<String> {
+        match self {
+            BindValue::Date(v) => Some(v.format("%Y-%m-%d").to_string()),
+            BindValue::Timestamp(v) => Some(v.format("%Y-%m-%d %H:%M:%S%.f").to_string()),
+            BindValue::TimestampTz(v) => Some(v.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            BindValue::Interval(v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    fn timestamptz_type() -> ast::DataType {
+        ast::DataType::Custom(ast::ObjectName(vec![ast::Ident::new("TIMESTAMPTZ")]))
+    }
+
+    /// Type of elements of an array (`values`) in SQL, None for empty arrays
+    fn array_element_type(values: &[BindValue]) -> Result<Option<ast::DataType>, CubeError> {
+        let mut element_type: Option<(ast::DataType, &'static str)> = None;
+
+        for value in values {
+            let typ = match value {
+                BindValue::String(_) => ast::DataType::Text,
+                BindValue::Int64(_) | BindValue::UInt64(_) => ast::DataType::BigInt,
+                BindValue::Float64(_) => ast::DataType::Double,
+                BindValue::Numeric(_) => ast::DataType::Decimal(None, None),
+                BindValue::Bool(_) => ast::DataType::Boolean,
+                BindValue::Date(_) => ast::DataType::Date,
+                BindValue::Timestamp(_) => ast::DataType::Timestamp,
+                BindValue::TimestampTz(_) => Self::timestamptz_type(),
+                BindValue::Interval(_) => ast::DataType::Interval,
+                // NULL is allowed in arrays of any type
+                BindValue::Null => continue,
+                BindValue::Array(values) => match Self::array_element_type(values)? {
+                    Some(typ) => typ,
+                    None => continue,
+                },
+            };
+
+            match &element_type {
+                None => element_type = Some((typ, value.variant_name())),
+                Some((expected, name)) if *expected != typ => {
+                    return Err(CubeError::user(format!(
+                        "array elements must be of the same type, got {} and {}",
+                        name,
+                        value.variant_name()
+                    )))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(element_type.map(|(typ, _)| typ))
+    }
+
+    /// Text representation of an array element, https://www.postgresql.org/docs/current/arrays.html#ARRAYS-IO
+    fn write_array_element(&self, out: &mut String) {
+        match self {
+            BindValue::Date(_)
+            | BindValue::TimestampTz(_)
+            | BindValue::Timestamp(_)
+            | BindValue::Interval(_) => {
+                let text = BindValue::String(self.temporal_text().unwrap_or_default());
+                text.write_array_element(out)
+            }
+            BindValue::String(v) => {
+                out.push('"');
+                for c in v.chars() {
+                    if c == '"' || c == '\\' {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                out.push('"');
+            }
+            BindValue::Int64(v) => out.push_str(&v.to_string()),
+            BindValue::UInt64(v) => out.push_str(&v.to_string()),
+            BindValue::Float64(v) => out.push_str(&v.to_string()),
+            BindValue::Numeric(v) => out.push_str(v),
+            BindValue::Bool(v) => out.push(if *v { 't' } else { 'f' }),
+            BindValue::Null => out.push_str("NULL"),
+            BindValue::Array(values) => {
+                out.push('{');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    value.write_array_element(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    fn is_compatible_with(&self, typ: PgTypeId) -> bool {
+        if let BindValue::Null = self {
+            return true;
+        }
+        if let Some(element) = typ.element() {
+            return match self {
+                BindValue::Array(values) => values.iter().all(|v| v.is_compatible_with(element)),
+                _ => false,
+            };
+        }
+
+        match typ {
+            PgTypeId::Bool => matches!(self, BindValue::Bool(_)),
+            PgTypeId::Int2 | PgTypeId::Int4 | PgTypeId::Int8 | PgTypeId::Oid => {
+                matches!(self, BindValue::Int64(_) | BindValue::UInt64(_))
+            }
+            PgTypeId::Float4 | PgTypeId::Float8 | PgTypeId::Numeric => matches!(
+                self,
+                BindValue::Int64(_)
+                    | BindValue::UInt64(_)
+                    | BindValue::Float64(_)
+                    | BindValue::Numeric(_)
+            ),
+            PgTypeId::Date => matches!(self, BindValue::Date(_) | BindValue::String(_)),
+            PgTypeId::Timestamp => {
+                matches!(self, BindValue::Timestamp(_) | BindValue::String(_))
+            }
+            PgTypeId::Timestamptz => matches!(
+                self,
+                BindValue::TimestampTz(_) | BindValue::Timestamp(_) | BindValue::String(_)
+            ),
+            PgTypeId::Interval => matches!(self, BindValue::Interval(_) | BindValue::String(_)),
+            // Everything else is passed in the text representation
+            _ => matches!(self, BindValue::String(_)),
+        }
+    }
+
+    /// Stricter than `is_compatible_with`: the value must be of the same kind as the type,
+    /// nothing is left to implicit coercion (a string for a timestamp, an integer for a float)
+    fn is_exact_match(&self, typ: PgTypeId) -> bool {
+        if let BindValue::Null = self {
+            return true;
+        }
+        if let Some(element) = typ.element() {
+            return match self {
+                BindValue::Array(values) => values.iter().all(|v| v.is_exact_match(element)),
+                _ => false,
+            };
+        }
+
+        match typ {
+            PgTypeId::Bool => matches!(self, BindValue::Bool(_)),
+            PgTypeId::Int2 | PgTypeId::Int4 | PgTypeId::Int8 | PgTypeId::Oid => {
+                matches!(self, BindValue::Int64(_) | BindValue::UInt64(_))
+            }
+            PgTypeId::Float4 | PgTypeId::Float8 => matches!(self, BindValue::Float64(_)),
+            PgTypeId::Numeric => matches!(self, BindValue::Numeric(_)),
+            PgTypeId::Text | PgTypeId::Varchar => matches!(self, BindValue::String(_)),
+            PgTypeId::Date => matches!(self, BindValue::Date(_)),
+            PgTypeId::Timestamp => matches!(self, BindValue::Timestamp(_)),
+            PgTypeId::Timestamptz => matches!(self, BindValue::TimestampTz(_)),
+            PgTypeId::Interval => matches!(self, BindValue::Interval(_)),
+            // There is no value without coercion for these types
+            _ => false,
+        }
+    }
+
+    pub fn to_ast_value(self) -> ast::Value {
+        match self {
+            BindValue::String(v) => ast::Value::SingleQuotedString(v),
+            BindValue::Bool(v) => ast::Value::Boolean(v),
+            BindValue::UInt64(v) => ast::Value::Number(v.to_string(), false),
+            // The second field is the long (`L` suffix) flag, not the sign
+            BindValue::Int64(v) => ast::Value::Number(v.to_string(), false),
+            BindValue::Float64(v) => ast::Value::Number(v.to_string(), false),
+            BindValue::Numeric(v) => ast::Value::Number(v, false),
+            BindValue::Null => ast::Value::Null,
+            BindValue::Interval(v) => ast::Value::Interval {
+                value: Box::new(ast::Expr::Value(ast::Value::SingleQuotedString(v))),
+                leading_field: None,
+                leading_precision: None,
+                last_field: None,
+                fractional_seconds_precision: None,
+            },
+            // Untyped literals, see `to_ast_expr`
+            BindValue::Date(_) | BindValue::Timestamp(_) | BindValue::TimestampTz(_) => {
+                ast::Value::SingleQuotedString(self.temporal_text().unwrap_or_default())
+            }
+            // Array literal without a type, see `to_ast_expr`
+            BindValue::Array(_) => {
+                let mut literal = String::new();
+                self.write_array_element(&mut literal);
+
+                ast::Value::SingleQuotedString(literal)
+            }
+        }
+    }
+
+    /// Same as `to_ast_value`, but arrays are rendered element by element as `ARRAY['a', 'b']`
+    /// (`make_array('a', 'b')`, like `parse_sql_to_statement` does), mixed element types are
+    /// rejected. Dates and timestamps are rendered as typed literals
+    /// (`TIMESTAMP '2022-01-01 00:00:00'`), timestamps with time zone are cast, the typed
+    /// literal form isn't parsed by sqlparser.
+    pub fn to_ast_expr(self) -> Result<ast::Expr, CubeError> {
+        match self {
+            BindValue::Date(_) => Ok(ast::Expr::TypedString {
+                data_type: ast::DataType::Date,
+                value: self.temporal_text().unwrap_or_default(),
+            }),
+            BindValue::Timestamp(_) => Ok(ast::Expr::TypedString {
+                data_type: ast::DataType::Timestamp,
+                value: self.temporal_text().unwrap_or_default(),
+            }),
+            BindValue::TimestampTz(_) => Ok(ast::Expr::Cast {
+                expr: Box::new(ast::Expr::Value(self.to_ast_value())),
+                data_type: Self::timestamptz_type(),
+            }),
+            // make_array() needs at least one argument, the element type is unknown anyway
+            BindValue::Array(values) if values.is_empty() => {
+                Ok(ast::Expr::Value(BindValue::Array(values).to_ast_value()))
+            }
+            BindValue::Array(values) => {
+                Self::array_element_type(&values)?;
+
+                let elements = values
+                    .into_iter()
+                    .map(BindValue::to_ast_expr)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(function_call("make_array".to_string(), elements))
+            }
+            value => Ok(ast::Expr::Value(value.to_ast_value())),
+        }
+    }
+
+    /// Same as `to_ast_value`, but checks that the value can be used for the parameter
+    /// `$index` of the `expected` type
+    pub fn try_to_ast_value(
+        self,
+        index: usize,
+        expected: Option<PgTypeId>,
+    ) -> Result<ast::Value, CubeError> {
+        self.check_type(index, expected)?;
+
+        Ok(self.to_ast_value())
+    }
+
+    /// Same as `to_ast_expr`, with the type check of `try_to_ast_value`
+    pub fn try_to_ast_expr(
+        self,
+        index: usize,
+        expected: Option<PgTypeId>,
+    ) -> Result<ast::Expr, CubeError> {
+        self.check_type(index, expected)?;
+
+        self.to_ast_expr()
+    }
+
+    fn check_type(&self, index: usize, expected: Option<PgTypeId>) -> Result<(), CubeError> {
+        match expected {
+            Some(typ) if !self.is_compatible_with(typ) => Err(CubeError::user(format!(
+                "parameter ${}: expected {}, got {}",
+                index,
+                typ,
+                self.variant_name()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Elements of a one-dimensional array in the text representation (`{a,"b c",NULL}`), NULL
+/// elements are None. None is returned for malformed and multidimensional arrays.
+pub fn parse_array_literal(text: &str) -> Option<Vec<Option<String>>> {
+    let inner = text.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let chars = inner.chars().collect::<Vec<_>>();
+    let mut elements = vec![];
+    if inner.trim().is_empty() {
+        return Some(elements);
+    }
+
+    let mut i = 0;
+    loop {
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+
+        let mut element = String::new();
+        let mut quoted = false;
+        if i < chars.len() && chars[i] == '"' {
+            quoted = true;
+            i += 1;
+            loop {
+                match chars.get(i)? {
+                    '"' => break,
+                    '\\' => {
+                        i += 1;
+                        element.push(*chars.get(i)?);
+                    }
+                    c => element.push(*c),
+                }
+                i += 1;
+            }
+            i += 1;
+        } else {
+            while i < chars.len() && chars[i] != ',' {
+                match chars[i] {
+                    '{' | '}' | '"' => return None,
+                    '\\' => {
+                        i += 1;
+                        element.push(*chars.get(i)?);
+                    }
+                    c => element.push(c),
+                }
+                i += 1;
+            }
+            element = element.trim_end().to_string();
+            if element.is_empty() {
+                return None;
+            }
+        }
+
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+
+        if !quoted && element.eq_ignore_ascii_case("NULL") {
+            elements.push(None);
+        } else {
+            elements.push(Some(element));
+        }
+
+        match chars.get(i) {
+            None => return Some(elements),
+            Some(',') => i += 1,
+            Some(_) => return None,
+        }
+    }
 }
 
 trait Visitor<'ast> {
-    fn visit_value(&mut self, _val: &mut ast::Value) {}
+    fn visit_value(&mut self, _val: &mut ast::Value) -> Result<(), CubeError> {
+        Ok(())
+    }
+
+    /// Called for literal (placeholder) expressions, unlike `visit_value` it's able to replace
+    /// the whole expression
+    fn visit_value_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        if let ast::Expr::Value(value) = expr {
+            self.visit_value(value)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit_identifier(&mut self, _identifier: &mut ast::Ident) -> Result<(), CubeError> {
+        Ok(())
+    }
 
-    fn visit_identifier(&mut self, _identifier: &mut ast::Ident) {}
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)
+    }
 
-    fn visit_expr(&mut self, expr: &mut ast::Expr) {
+    /// Default traversal of an expression, visitors which override `visit_expr` call it to
+    /// continue with nested expressions.
+    ///
+    /// Timezone conversions (`ts AT TIME ZONE tz`, `ts AT LOCAL`) are not modeled by the pinned
+    /// sqlparser, there is no operand to visit until it's upgraded. The same is true for row
+    /// values (`(a, b) = ($1, $2)`), which are rejected by the parser: once tuples are parsed,
+    /// the BinaryOp arm already visits both sides. `(start1, end1) OVERLAPS ($1, $2)` is built
+    /// from the same row values, so it's rejected as well.
+    ///
+    /// Every `ast::Expr` variant is listed in `expr_binding_coverage` (tests), it doesn't compile
+    /// when sqlparser adds a variant: decide whether it can hold a placeholder, add an arm here
+    /// and mark it there.
+    fn walk_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
         match expr {
-            ast::Expr::Value(value) => self.visit_value(value),
-            ast::Expr::Identifier(identifier) => self.visit_identifier(identifier),
-            ast::Expr::Nested(v) => self.visit_expr(&mut *v),
+            ast::Expr::Value(_) => self.visit_value_expr(expr)?,
+            ast::Expr::Identifier(identifier) => self.visit_identifier(identifier)?,
+            ast::Expr::Nested(v) => self.visit_expr(&mut *v)?,
+            ast::Expr::Cast { expr, .. } => self.visit_expr(&mut *expr)?,
+            ast::Expr::TryCast { expr, .. } => self.visit_expr(&mut *expr)?,
+            // The field is kept as is. The pinned sqlparser knows only YEAR..SECOND fields, other
+            // ones (EXTRACT(EPOCH FROM $1)) are date_part() calls of parse_sql_to_statement
+            ast::Expr::Extract { expr, .. } => self.visit_expr(&mut *expr)?,
             ast::Expr::Between {
                 expr,
                 negated: _,
                 low,
                 high,
             } => {
-                self.visit_expr(&mut *expr);
-                self.visit_expr(&mut *low);
-                self.visit_expr(&mut *high);
+                self.visit_expr(&mut *expr)?;
+                self.visit_expr(&mut *low)?;
+                self.visit_expr(&mut *high)?;
             }
+            // Any operator. `->` and `->>` are json_extract_path() calls of parse_sql_to_statement,
+            // path operators (`data #> $1`) are not parsed by the pinned sqlparser yet
             ast::Expr::BinaryOp { left, op: _, right } => {
-                self.visit_expr(&mut *left);
-                self.visit_expr(&mut *right);
+                self.visit_expr(&mut *left)?;
+                self.visit_expr(&mut *right)?;
             }
             ast::Expr::InList { expr, list, .. } => {
-                self.visit_expr(&mut *expr);
+                self.visit_expr(&mut *expr)?;
 
                 for v in list.iter_mut() {
-                    self.visit_expr(v);
+                    self.visit_expr(v)?;
+                }
+            }
+            ast::Expr::InSubquery { expr, subquery, .. } => {
+                self.visit_expr(&mut *expr)?;
+                self.visit_query(subquery)?;
+            }
+            ast::Expr::Subquery(query) | ast::Expr::Exists(query) => self.visit_query(query)?,
+            ast::Expr::UnaryOp { expr, .. } => self.visit_expr(&mut *expr)?,
+            // `$1 IS NULL OR col = $1` is what clients send for optional filters
+            ast::Expr::IsNull(expr) | ast::Expr::IsNotNull(expr) => self.visit_expr(&mut *expr)?,
+            ast::Expr::IsDistinctFrom(left, right) | ast::Expr::IsNotDistinctFrom(left, right) => {
+                self.visit_expr(&mut *left)?;
+                self.visit_expr(&mut *right)?;
+            }
+            // The spec (LEADING, TRAILING, BOTH) is kept, trimmed characters are written first
+            ast::Expr::Trim { expr, trim_where } => {
+                if let Some((_, what)) = trim_where {
+                    self.visit_expr(&mut *what)?;
+                }
+
+                self.visit_expr(&mut *expr)?;
+            }
+            ast::Expr::Substring {
+                expr,
+                substring_from,
+                substring_for,
+            } => {
+                self.visit_expr(&mut *expr)?;
+
+                if let Some(from) = substring_from {
+                    self.visit_expr(&mut *from)?;
+                }
+
+                if let Some(length) = substring_for {
+                    self.visit_expr(&mut *length)?;
+                }
+            }
+            ast::Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                if let Some(operand) = operand {
+                    self.visit_expr(&mut *operand)?;
+                }
+
+                // WHEN ... THEN ... pairs are stored separately, but written interleaved
+                for (condition, result) in conditions.iter_mut().zip(results.iter_mut()) {
+                    self.visit_expr(condition)?;
+                    self.visit_expr(result)?;
+                }
+
+                if let Some(else_result) = else_result {
+                    self.visit_expr(&mut *else_result)?;
+                }
+            }
+            ast::Expr::Function(fun) => self.visit_function(fun)?,
+            // STRING_AGG(expr, delimiter) is a regular function, the aggregate ORDER BY inside
+            // of the arguments (`STRING_AGG(name, $1 ORDER BY name)`) is not parsed by the pinned
+            // sqlparser, use LISTAGG(...) WITHIN GROUP (ORDER BY ...) instead
+            ast::Expr::ListAgg(agg) => {
+                self.visit_expr(&mut *agg.expr)?;
+
+                if let Some(separator) = &mut agg.separator {
+                    self.visit_expr(&mut *separator)?;
+                }
+
+                if let Some(ast::ListAggOnOverflow::Truncate {
+                    filler: Some(filler),
+                    ..
+                }) = &mut agg.on_overflow
+                {
+                    self.visit_expr(&mut *filler)?;
                 }
+
+                self.visit_order_by(&mut agg.within_group)?;
             }
             _ => {}
+        };
+
+        Ok(())
+    }
+
+    /// Called for every expression reached by `walk_expr_iterative`, which doesn't pass
+    /// children through `visit_expr`
+    fn check_expr(&mut self, _expr: &ast::Expr) -> Result<(), CubeError> {
+        Ok(())
+    }
+
+    /// Same traversal as `walk_expr`, but operators, parentheses, casts, IS NULL, BETWEEN, lists,
+    /// CASE and functions (with their window specifications) are walked with an explicit stack
+    /// instead of recursion, so long OR chains and deeply nested calls are not limited by the
+    /// thread stack. Children are not passed through `visit_expr` (nor functions through
+    /// `visit_function`), it's meant for visitors which use `visit_expr` only to choose a
+    /// traversal. Other variants (subqueries, LISTAGG, SUBSTRING, ...) go through `walk_expr`,
+    /// their nested expressions come back here via `visit_expr`: this recursion is not
+    /// stack-safe, visitors must limit its depth (see `BinderOptions::iterative`).
+    fn walk_expr_iterative(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        let mut stack = vec![expr];
+
+        while let Some(expr) = stack.pop() {
+            self.check_expr(expr)?;
+
+            // Children are pushed in reverse, to be visited in the textual order
+            match expr {
+                ast::Expr::Nested(v)
+                | ast::Expr::Cast { expr: v, .. }
+                | ast::Expr::TryCast { expr: v, .. }
+                | ast::Expr::UnaryOp { expr: v, .. }
+                | ast::Expr::IsNull(v)
+                | ast::Expr::IsNotNull(v) => stack.push(&mut **v),
+                ast::Expr::Between {
+                    expr, low, high, ..
+                } => {
+                    stack.push(&mut **high);
+                    stack.push(&mut **low);
+                    stack.push(&mut **expr);
+                }
+                ast::Expr::BinaryOp { left, right, .. } => {
+                    stack.push(&mut **right);
+                    stack.push(&mut **left);
+                }
+                ast::Expr::InList { expr, list, .. } => {
+                    for v in list.iter_mut().rev() {
+                        stack.push(v);
+                    }
+
+                    stack.push(&mut **expr);
+                }
+                ast::Expr::Case {
+                    operand,
+                    conditions,
+                    results,
+                    else_result,
+                } => {
+                    if let Some(else_result) = else_result {
+                        stack.push(&mut **else_result);
+                    }
+
+                    for (condition, result) in conditions.iter_mut().zip(results.iter_mut()).rev() {
+                        stack.push(result);
+                        stack.push(condition);
+                    }
+
+                    if let Some(operand) = operand {
+                        stack.push(&mut **operand);
+                    }
+                }
+                ast::Expr::Function(fun) => {
+                    if let Some(over) = &mut fun.over {
+                        for order_by in over.order_by.iter_mut().rev() {
+                            stack.push(&mut order_by.expr);
+                        }
+
+                        for v in over.partition_by.iter_mut().rev() {
+                            stack.push(v);
+                        }
+                    }
+
+                    for arg in fun.args.iter_mut().rev() {
+                        match arg {
+                            ast::FunctionArg::Named { arg, .. } => stack.push(arg),
+                            ast::FunctionArg::Unnamed(arg) => stack.push(arg),
+                        };
+                    }
+                }
+                expr => self.walk_expr(expr)?,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// MySQL `CONVERT(expr, type)` is a regular function call for the parser, the transcoding
+    /// form `CONVERT(expr USING charset)` is not supported by the pinned sqlparser yet.
+    fn visit_function(&mut self, fun: &mut ast::Function) -> Result<(), CubeError> {
+        for arg in fun.args.iter_mut() {
+            match arg {
+                ast::FunctionArg::Named { arg, .. } => self.visit_expr(arg)?,
+                ast::FunctionArg::Unnamed(arg) => self.visit_expr(arg)?,
+            };
+        }
+
+        if let Some(over) = &mut fun.over {
+            self.visit_window_spec(over)?;
+        }
+
+        Ok(())
+    }
+
+    /// The pinned sqlparser doesn't model the `WINDOW w AS (...)` clause (there is no
+    /// `select.named_window`) and frame bounds are plain numbers, so only inline `OVER (...)`
+    /// specifications can carry placeholders.
+    fn visit_window_spec(&mut self, spec: &mut ast::WindowSpec) -> Result<(), CubeError> {
+        for expr in spec.partition_by.iter_mut() {
+            self.visit_expr(expr)?;
+        }
+
+        self.visit_order_by(&mut spec.order_by)?;
+
+        Ok(())
+    }
+
+    fn visit_order_by(&mut self, order_by: &mut Vec<ast::OrderByExpr>) -> Result<(), CubeError> {
+        for expr in order_by.iter_mut() {
+            self.visit_order_by_expr(expr)?;
         }
+
+        Ok(())
+    }
+
+    fn visit_order_by_expr(&mut self, order_by: &mut ast::OrderByExpr) -> Result<(), CubeError> {
+        self.visit_expr(&mut order_by.expr)
     }
 
-    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) {
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) -> Result<(), CubeError> {
         match factor {
             ast::TableFactor::Derived { subquery, .. } => {
-                self.visit_query(subquery);
+                self.visit_query(subquery)?;
             }
             _ => {}
-        }
+        };
+
+        Ok(())
     }
 
-    fn visit_join(&mut self, join: &mut ast::Join) {
-        self.visit_table_factor(&mut join.relation);
+    fn visit_join(&mut self, join: &mut ast::Join) -> Result<(), CubeError> {
+        self.visit_table_factor(&mut join.relation)
     }
 
-    fn visit_table_with_joins(&mut self, twj: &mut ast::TableWithJoins) {
-        self.visit_table_factor(&mut twj.relation);
+    fn visit_table_with_joins(&mut self, twj: &mut ast::TableWithJoins) -> Result<(), CubeError> {
+        self.visit_table_factor(&mut twj.relation)?;
 
         for join in twj.joins.iter_mut() {
-            self.visit_join(join);
+            self.visit_join(join)?;
         }
+
+        Ok(())
     }
 
-    fn visit_select_item(&mut self, select: &mut ast::SelectItem) {
+    fn visit_select_item(&mut self, select: &mut ast::SelectItem) -> Result<(), CubeError> {
         match select {
-            ast::SelectItem::UnnamedExpr(expr) => self.visit_expr(expr),
+            ast::SelectItem::UnnamedExpr(expr) => self.visit_expr(expr)?,
+            ast::SelectItem::ExprWithAlias { expr, .. } => self.visit_expr(expr)?,
             _ => {}
-        }
-    }
-
-    fn visit_select(&mut self, select: &mut Box<ast::Select>) {
-        if let Some(selection) = &mut select.selection {
-            self.visit_expr(selection);
         };
 
+        Ok(())
+    }
+
+    /// Clauses are visited in the same order as they are written, which is required
+    /// for positional (`?`) placeholders. `QUALIFY` is not modeled by the pinned sqlparser
+    /// (there is no `select.qualify`), such queries fail to parse.
+    fn visit_select(&mut self, select: &mut Box<ast::Select>) -> Result<(), CubeError> {
         for projection in &mut select.projection {
-            self.visit_select_item(projection);
+            self.visit_select_item(projection)?;
         }
 
         for from in &mut select.from {
-            self.visit_table_with_joins(from);
+            self.visit_table_with_joins(from)?;
+        }
+
+        if let Some(selection) = &mut select.selection {
+            self.visit_expr(selection)?;
+        };
+
+        // ROLLUP/CUBE and GROUPING SETS (rewritten by parse_sql_to_statement) are parsed as
+        // function calls, their arguments are visited in order
+        for group_by in &mut select.group_by {
+            self.visit_expr(group_by)?;
         }
+
+        if let Some(having) = &mut select.having {
+            self.visit_expr(having)?;
+        };
+
+        Ok(())
     }
 
-    fn visit_set_expr(&mut self, body: &mut ast::SetExpr) {
+    fn visit_set_expr(&mut self, body: &mut ast::SetExpr) -> Result<(), CubeError> {
         match body {
-            ast::SetExpr::Select(select) => self.visit_select(select),
-            ast::SetExpr::Query(query) => self.visit_query(query),
+            ast::SetExpr::Select(select) => self.visit_select(select)?,
+            ast::SetExpr::Query(query) => self.visit_query(query)?,
             ast::SetExpr::SetOperation { left, right, .. } => {
-                self.visit_set_expr(&mut *left);
-                self.visit_set_expr(&mut *right);
+                self.visit_set_expr(&mut *left)?;
+                self.visit_set_expr(&mut *right)?;
+            }
+            ast::SetExpr::Values(values) => {
+                for row in values.0.iter_mut() {
+                    for expr in row.iter_mut() {
+                        self.visit_expr(expr)?;
+                    }
+                }
             }
             _ => {}
+        };
+
+        Ok(())
+    }
+
+    fn visit_limit(&mut self, limit: &mut Option<ast::Expr>) -> Result<(), CubeError> {
+        if let Some(limit) = limit {
+            self.visit_expr(limit)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit_offset(&mut self, offset: &mut Option<ast::Offset>) -> Result<(), CubeError> {
+        if let Some(offset) = offset {
+            self.visit_expr(&mut offset.value)?;
         }
+
+        Ok(())
+    }
+
+    fn visit_cte(&mut self, cte: &mut ast::Cte) -> Result<(), CubeError> {
+        self.visit_query(&mut cte.query)
     }
 
-    fn visit_query(&mut self, query: &mut Box<ast::Query>) {
-        self.visit_set_expr(&mut query.body);
+    /// Query is rewritten in place, so clauses without expressions are kept as is. Locking
+    /// clauses (`FOR UPDATE OF t NOWAIT`) are not modeled by the pinned sqlparser and such
+    /// queries are rejected at parse time instead of silently losing the lock. CTEs are
+    /// visited first, as they are written before the body.
+    fn visit_query(&mut self, query: &mut ast::Query) -> Result<(), CubeError> {
+        if let Some(with) = &mut query.with {
+            for cte in with.cte_tables.iter_mut() {
+                self.visit_cte(cte)?;
+            }
+        }
+
+        self.visit_set_expr(&mut query.body)?;
+        self.visit_order_by(&mut query.order_by)?;
+        self.visit_limit(&mut query.limit)?;
+        self.visit_offset(&mut query.offset)?;
+
+        Ok(())
     }
 
-    fn visit_statement(&mut self, statement: &mut ast::Statement) {
+    fn visit_statement(&mut self, statement: &mut ast::Statement) -> Result<(), CubeError> {
         match statement {
-            ast::Statement::Query(query) => self.visit_query(query),
+            ast::Statement::Query(query) => self.visit_query(query)?,
+            // Statement is rewritten in place, everything around the source (target columns,
+            // modifiers) is kept as is. OVERRIDING { SYSTEM | USER } VALUE and DEFAULT VALUES
+            // are not parsed by the pinned sqlparser yet, the source is always a query.
+            ast::Statement::Insert { source, .. } => self.visit_query(source)?,
+            #[cfg(feature = "extended-statements")]
+            ast::Statement::Explain { statement, .. } => self.visit_statement(&mut *statement)?,
+            #[cfg(feature = "extended-statements")]
+            ast::Statement::Update {
+                assignments,
+                selection,
+                ..
+            } => {
+                for assignment in assignments.iter_mut() {
+                    self.visit_expr(&mut assignment.value)?;
+                }
+
+                if let Some(selection) = selection {
+                    self.visit_expr(selection)?;
+                }
+            }
+            #[cfg(feature = "extended-statements")]
+            ast::Statement::Delete { selection, .. } => {
+                if let Some(selection) = selection {
+                    self.visit_expr(selection)?;
+                }
+            }
             _ => {}
-        }
+        };
+
+        Ok(())
     }
 }
 
+/// Common interface for everything that substitutes or rewrites placeholders in a statement.
+/// It's object-safe, so a binding strategy can be chosen at runtime as `Box<dyn Binder>`.
+pub trait Binder {
+    fn bind(&mut self, stmt: &mut ast::Statement) -> Result<(), CubeError>;
+}
+
 #[derive(Debug)]
 pub struct StatementPrepare {
     parameters: Vec<Column>,
@@ -122,183 +862,4656 @@ impl StatementPrepare {
         Self { parameters: vec![] }
     }
 
-    pub fn prepare(&mut self, stmt: &mut ast::Statement) -> &Vec<Column> {
-        self.visit_statement(stmt);
+    pub fn prepare(&mut self, stmt: &mut ast::Statement) -> Result<&Vec<Column>, CubeError> {
+        self.visit_statement(stmt)?;
 
-        &self.parameters
+        Ok(&self.parameters)
     }
 }
 
 impl<'ast> Visitor<'ast> for StatementPrepare {
-    fn visit_value(&mut self, _: &mut ast::Value) {
+    fn visit_value(&mut self, _: &mut ast::Value) -> Result<(), CubeError> {
         self.parameters.push(Column {
             table: String::new(),
             column: "not implemented".to_owned(),
             coltype: ColumnType::MYSQL_TYPE_VAR_STRING,
             colflags: ColumnFlags::empty(),
-        })
+        });
+
+        Ok(())
     }
 }
 
+/// Default limit of nesting, which is walked recursively by the iterative traversal
+pub const MAX_ITERATIVE_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Default)]
+pub struct BinderOptions {
+    /// Render Int64 values for int2/int4 parameters as narrow typed casts when they fit,
+    /// some backends can't use an index for int8 literals
+    pub narrow_integers: bool,
+    /// Walk operators, functions and CASE without recursion, for statements from untrusted
+    /// clients (see `walk_expr_iterative`). Other nesting (subqueries) is limited by `max_depth`,
+    /// or by `MAX_ITERATIVE_DEPTH` if it's not set.
+    pub iterative: bool,
+    /// Reject values which are not an exact match for the expected parameter type
+    /// (see `BindValue::is_exact_match`) instead of relying on implicit coercion by SQL
+    pub strict_types: bool,
+    /// Render booleans as 1/0 instead of TRUE/FALSE (for MySQL style backends), everywhere
+    /// a placeholder is bound: comparisons, IN lists, function arguments
+    pub bools_as_integers: bool,
+    /// Maximum number of elements in an IN list, larger lists are rejected instead of
+    /// inflating the plan
+    pub max_in_list_size: Option<usize>,
+    /// Every supplied value must be used and numbered placeholders must not have gaps
+    pub strict_values: bool,
+    /// Maximum nesting of expressions for the recursive traversal
+    pub max_depth: Option<usize>,
+    /// Maximum number of placeholders in a statement
+    pub max_placeholders: Option<usize>,
+    /// Maximum length (in bytes) of a string value
+    pub max_string_length: Option<usize>,
+}
+
 #[derive(Debug)]
 pub struct StatementBinder {
     position: usize,
-    values: Vec<BindValue>,
+    // Values by the number of the parameter, `$N` is the value at `N - 1`; a parameter can be
+    // referenced many times and in any order. A value is moved into its last reference, only
+    // repeated placeholders are cloned
+    values: Vec<Option<BindValue>>,
+    // References left for every placeholder number, counted by `bind`
+    references: BTreeMap<usize, usize>,
+    // Expected types of parameters (by the number of the parameter), unknown types are not checked
+    types: Vec<Option<PgTypeId>>,
+    options: BinderOptions,
+    depth: usize,
 }
 
 impl StatementBinder {
     pub fn new(values: Vec<BindValue>) -> Self {
         Self {
             position: 0,
-            values,
+            values: values.into_iter().map(Some).collect(),
+            references: BTreeMap::new(),
+            types: vec![],
+            options: BinderOptions::default(),
+            depth: 0,
         }
     }
 
-    pub fn bind(&mut self, stmt: &mut ast::Statement) {
-        self.visit_statement(stmt);
+    pub fn with_types(values: Vec<BindValue>, types: Vec<Option<PgTypeId>>) -> Self {
+        Self {
+            types,
+            ..Self::new(values)
+        }
     }
-}
 
-impl<'ast> Visitor<'ast> for StatementBinder {
-    fn visit_value(&mut self, value: &mut ast::Value) {
-        match &value {
-            ast::Value::Placeholder(_) => {
-                let to_replace = self.values.get(self.position).expect(
-                    format!(
-                        "Unable to find value for placeholder at position: {}",
-                        self.position
-                    )
-                    .as_str(),
-                );
-                self.position += 1;
+    pub fn with_options(mut self, options: BinderOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-                match to_replace {
-                    BindValue::String(v) => {
-                        *value = ast::Value::SingleQuotedString(v.clone());
-                    }
-                    BindValue::Bool(v) => {
-                        *value = ast::Value::Boolean(*v);
-                    }
-                    BindValue::UInt64(v) => {
-                        *value = ast::Value::Number(v.to_string(), false);
-                    }
-                    BindValue::Int64(v) => {
-                        *value = ast::Value::Number(v.to_string(), *v < 0_i64);
-                    }
-                    BindValue::Float64(v) => {
-                        *value = ast::Value::Number(v.to_string(), *v < 0_f64);
-                    }
-                }
+    fn narrow_integer(&self, value: &BindValue, typ: Option<PgTypeId>) -> Option<ast::DataType> {
+        if !self.options.narrow_integers {
+            return None;
+        }
+
+        match (value, typ) {
+            (BindValue::Int64(v), Some(PgTypeId::Int2)) if i16::try_from(*v).is_ok() => {
+                Some(ast::DataType::SmallInt)
             }
-            _ => {}
+            (BindValue::Int64(v), Some(PgTypeId::Int4)) if i32::try_from(*v).is_ok() => {
+                Some(ast::DataType::Int)
+            }
+            _ => None,
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
+impl Binder for StatementBinder {
+    fn bind(&mut self, stmt: &mut ast::Statement) -> Result<(), CubeError> {
+        let mut collector = PlaceholderCollector::new(0);
+        collector.visit_statement(stmt)?;
+
+        if self.options.strict_values {
+            if let Some(gap) = collector.gaps().first() {
+                return Err(CubeError::user(format!(
+                    "parameter ${} is not used in the statement",
+                    gap
+                )));
+            }
+        }
+
+        let unused = (1..=self.values.len())
+            .filter(|number| !collector.references.contains_key(number))
+            .count();
+        self.references = collector.references;
+
+        self.visit_statement(stmt)?;
+
+        if self.options.strict_values {
+            if unused > 0 {
+                return Err(CubeError::user(format!(
+                    "{} of {} supplied values are not used in the statement",
+                    unused,
+                    self.values.len()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'ast> Visitor<'ast> for StatementBinder {
+    fn check_expr(&mut self, expr: &ast::Expr) -> Result<(), CubeError> {
+        if let (ast::Expr::InList { list, .. }, Some(max)) = (expr, self.options.max_in_list_size) {
+            if list.len() > max {
+                return Err(CubeError::user(format!(
+                    "IN list has {} elements, which exceeds the limit of {}",
+                    list.len(),
+                    max
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.check_expr(expr)?;
+
+        let max_depth = match (self.options.max_depth, self.options.iterative) {
+            (None, true) => Some(MAX_ITERATIVE_DEPTH),
+            (max_depth, _) => max_depth,
+        };
+        match max_depth {
+            Some(max) if self.depth >= max => {
+                return Err(CubeError::user(format!(
+                    "Expression nesting exceeds the limit of {}",
+                    max
+                )))
+            }
+            _ => {}
+        };
+
+        self.depth += 1;
+        let result = if self.options.iterative {
+            self.walk_expr_iterative(expr)
+        } else {
+            self.walk_expr(expr)
+        };
+        self.depth -= 1;
+
+        result
+    }
+
+    fn visit_value_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        match expr {
+            ast::Expr::Value(ast::Value::Placeholder(placeholder)) => {
+                let position = self.position;
+                // MySQL style placeholders (?) are numbered by their position
+                let number = placeholder_number(placeholder, position + 1)?;
+                let index = number - 1;
+
+                match self.options.max_placeholders {
+                    Some(max) if position >= max => {
+                        return Err(CubeError::user(format!(
+                            "Statement has more than {} placeholders",
+                            max
+                        )))
+                    }
+                    _ => {}
+                };
+
+                let last = match self.references.get_mut(&number) {
+                    Some(references) => {
+                        *references = references.saturating_sub(1);
+                        *references == 0
+                    }
+                    None => false,
+                };
+                let to_replace = self
+                    .values
+                    .get_mut(index)
+                    .and_then(|value| if last { value.take() } else { value.clone() })
+                    .ok_or_else(|| {
+                        CubeError::user(format!("Unable to find value for parameter ${}", number))
+                    })?;
+                self.position += 1;
+
+                if let (BindValue::String(v), Some(max)) =
+                    (&to_replace, self.options.max_string_length)
+                {
+                    if v.len() > max {
+                        return Err(CubeError::user(format!(
+                            "parameter ${}: string of {} bytes exceeds the limit of {}",
+                            number,
+                            v.len(),
+                            max
+                        )));
+                    }
+                }
+
+                let expected = self.types.get(index).cloned().flatten();
+                if let Some(typ) = expected {
+                    if self.options.strict_types && !to_replace.is_exact_match(typ) {
+                        return Err(CubeError::user(format!(
+                            "parameter ${}: {} requires implicit coercion to {}, which is not allowed in strict mode",
+                            number,
+                            to_replace.variant_name(),
+                            typ
+                        )));
+                    }
+                }
+
+                let narrow = self.narrow_integer(&to_replace, expected);
+                let value = match to_replace.try_to_ast_expr(number, expected)? {
+                    ast::Expr::Value(ast::Value::Boolean(v)) if self.options.bools_as_integers => {
+                        ast::Expr::Value(ast::Value::Number(
+                            if v { "1" } else { "0" }.to_string(),
+                            false,
+                        ))
+                    }
+                    value => value,
+                };
+
+                *expr = match narrow {
+                    Some(data_type) => ast::Expr::Cast {
+                        expr: Box::new(value),
+                        data_type,
+                    },
+                    None => value,
+                };
+            }
+            _ => {}
+        };
+
+        Ok(())
+    }
+}
+
+/// Binds `values` into `sql` and parses the result once more, to make sure that the bound
+/// statement is still valid for the same dialect (defense against serialization bugs).
+pub fn bind_and_validate(
+    sql: &str,
+    dialect: &dyn Dialect,
+    values: Vec<BindValue>,
+) -> Result<ast::Statement, CubeError> {
+    let mut stmt = match Parser::parse_sql(dialect, sql)?.as_slice() {
+        [stmt] => stmt.clone(),
+        stmts => {
+            return Err(CubeError::user(format!(
+                "Expected exactly one statement to bind, got: {}",
+                stmts.len()
+            )))
+        }
+    };
+
+    StatementBinder::new(values).bind(&mut stmt)?;
+
+    let bound = stmt.to_string();
+    match Parser::parse_sql(dialect, &bound) {
+        Ok(mut stmts) if stmts.len() == 1 => Ok(stmts.remove(0)),
+        Ok(stmts) => Err(CubeError::internal(format!(
+            "Bound statement was parsed back into {} statements: {}",
+            stmts.len(),
+            bound
+        ))),
+        Err(e) => Err(CubeError::internal(format!(
+            "Bound statement can't be parsed back: {:?}, statement: {}",
+            e, bound
+        ))),
+    }
+}
+
+/// Caps LIMIT of every query to `max_limit`: the top-level query, CTEs, derived tables and
+/// subqueries of expressions. A LIMIT is not added to queries without one. Placeholders are
+/// left untouched, run it after `StatementBinder` to cap bound limits.
+#[derive(Debug)]
+pub struct LimitCapper {
+    max_limit: u64,
+}
+
+impl LimitCapper {
+    pub fn new(max_limit: u64) -> Self {
+        Self { max_limit }
+    }
+}
+
+impl Binder for LimitCapper {
+    fn bind(&mut self, stmt: &mut ast::Statement) -> Result<(), CubeError> {
+        self.visit_statement(stmt)
+    }
+}
+
+impl<'ast> Visitor<'ast> for LimitCapper {
+    fn visit_limit(&mut self, limit: &mut Option<ast::Expr>) -> Result<(), CubeError> {
+        if let Some(ast::Expr::Value(ast::Value::Number(n, _))) = limit {
+            let exceeds = match n.parse::<u64>() {
+                Ok(n) => n > self.max_limit,
+                // Doesn't fit into u64
+                Err(_) => n.chars().all(|c| c.is_ascii_digit()),
+            };
+
+            if exceeds {
+                *limit = Some(ast::Expr::Value(ast::Value::Number(
+                    self.max_limit.to_string(),
+                    false,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Numbers of placeholders used by a statement, see `placeholder_report`
+#[derive(Debug, PartialEq)]
+pub struct PlaceholderReport {
+    /// The highest placeholder number, 0 for statements without placeholders
+    pub max_index: usize,
+    pub distinct: BTreeSet<usize>,
+    /// Numbers in 1..max_index which are not used by the statement, usually a client bug
+    pub gaps: Vec<usize>,
+}
+
+/// 1-based number of the parameter referenced by a placeholder, MySQL style placeholders (?)
+/// are numbered by their `position` in the statement
+fn placeholder_number(placeholder: &str, position: usize) -> Result<usize, CubeError> {
+    match placeholder.strip_prefix('$') {
+        Some(number) => number
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| CubeError::user(format!("Malformed placeholder: {}", placeholder))),
+        None => Ok(position),
+    }
+}
+
+#[derive(Debug)]
+struct PlaceholderCollector {
+    position: usize,
+    // Number of references of every placeholder number
+    references: BTreeMap<usize, usize>,
+}
+
+impl PlaceholderCollector {
+    fn new(position: usize) -> Self {
+        Self {
+            position,
+            references: BTreeMap::new(),
+        }
+    }
+
+    fn max_index(&self) -> usize {
+        self.references.keys().next_back().cloned().unwrap_or(0)
+    }
+
+    fn gaps(&self) -> Vec<usize> {
+        (1..self.max_index())
+            .filter(|i| !self.references.contains_key(i))
+            .collect()
+    }
+}
+
+impl<'ast> Visitor<'ast> for PlaceholderCollector {
+    // Runs before binding, deep expressions must not exhaust the stack here either
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr_iterative(expr)
+    }
+
+    fn visit_value(&mut self, value: &mut ast::Value) -> Result<(), CubeError> {
+        if let ast::Value::Placeholder(placeholder) = value {
+            self.position += 1;
+            *self
+                .references
+                .entry(placeholder_number(placeholder, self.position)?)
+                .or_insert(0) += 1;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn placeholder_report(stmt: &ast::Statement) -> Result<PlaceholderReport, CubeError> {
+    let mut collector = PlaceholderCollector::new(0);
+    collector.visit_statement(&mut stmt.clone())?;
+
+    Ok(PlaceholderReport {
+        max_index: collector.max_index(),
+        gaps: collector.gaps(),
+        distinct: collector.references.into_iter().map(|(i, _)| i).collect(),
+    })
+}
+
+/// Parses `sql` with (possibly) multiple statements, every statement is returned with the range
+/// of 0-based parameter indexes it references. Postgres numbers parameters across the whole
+/// Parse message, `?` placeholders continue the numbering of the previous statements.
+pub fn split_statements_with_param_offsets(
+    sql: &str,
+    dialect: &dyn Dialect,
+) -> Result<Vec<(ast::Statement, Range<usize>)>, CubeError> {
+    let stmts = Parser::parse_sql(dialect, sql)?;
+
+    let mut result = Vec::with_capacity(stmts.len());
+    let mut position = 0;
+    let mut end = 0;
+
+    for mut stmt in stmts {
+        let mut collector = PlaceholderCollector::new(position);
+        collector.visit_statement(&mut stmt)?;
+        position = collector.position;
+
+        let range = match (
+            collector.references.keys().next(),
+            collector.references.keys().next_back(),
+        ) {
+            (Some(min), Some(max)) => (min - 1)..*max,
+            // Statement without parameters
+            _ => end..end,
+        };
+        end = range.end;
+
+        result.push((stmt, range));
+    }
+
+    Ok(result)
+}
+
+/// Part of the statement where a placeholder is used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaceholderClause {
+    Projection,
+    From,
+    Where,
+    GroupBy,
+    Having,
+    OrderBy,
+    Limit,
+    Offset,
+    Other,
+}
+
+/// Type hint for a parameter, see `parameter_metadata`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamMeta {
+    /// Inferred type, 0 (unspecified) when it's unknown
+    pub oid: u32,
+    /// Nullability of the column the parameter is compared with, true when unknown
+    pub nullable: bool,
+    pub clause: PlaceholderClause,
+}
+
+impl ParamMeta {
+    fn unknown() -> Self {
+        Self {
+            oid: 0,
+            nullable: true,
+            clause: PlaceholderClause::Other,
+        }
+    }
+
+    pub fn pg_type(&self) -> Option<PgTypeId> {
+        PgTypeId::from_oid(self.oid)
+    }
+}
+
+/// Infers types of placeholders from their context: comparison with a column from `schema`,
+/// casts, arguments of pattern matching and date functions, usage in LIMIT/OFFSET
+struct PlaceholderTypeInference<'a> {
+    schema: Option<&'a DFSchema>,
+    position: usize,
+    clause: PlaceholderClause,
+    // Type of the operand a placeholder is compared with, only for the direct operand
+    hint: Option<(PgTypeId, bool)>,
+    params: Vec<Option<ParamMeta>>,
+}
+
+impl<'a> PlaceholderTypeInference<'a> {
+    fn new(schema: Option<&'a DFSchema>) -> Self {
+        Self {
+            schema,
+            position: 0,
+            clause: PlaceholderClause::Other,
+            hint: None,
+            params: vec![],
+        }
+    }
+
+    fn column_type(&self, expr: &ast::Expr) -> Option<(PgTypeId, bool)> {
+        let schema = self.schema?;
+        let field = match expr {
+            ast::Expr::Identifier(ident) => schema
+                .field_with_unqualified_name(&normalize_ident(ident))
+                .ok()?,
+            ast::Expr::CompoundIdentifier(idents) => match idents.as_slice() {
+                [qualifier, name] => schema
+                    .field_with_qualified_name(&normalize_ident(qualifier), &normalize_ident(name))
+                    .ok()?,
+                _ => return None,
+            },
+            ast::Expr::Nested(expr) => return self.column_type(expr),
+            ast::Expr::Cast { data_type, .. } | ast::Expr::TryCast { data_type, .. } => {
+                return Some((cast_type(data_type)?, true))
+            }
+            ast::Expr::Function(fun) => return Some((function_type(fun)?, true)),
+            _ => return None,
+        };
+
+        Some((
+            PgTypeId::from_arrow(field.data_type())?,
+            field.is_nullable(),
+        ))
+    }
+
+    fn visit_operand(
+        &mut self,
+        expr: &mut ast::Expr,
+        hint: Option<(PgTypeId, bool)>,
+    ) -> Result<(), CubeError> {
+        self.hint = hint;
+        self.visit_expr(expr)
+    }
+
+    fn visit_in_clause(
+        &mut self,
+        clause: PlaceholderClause,
+        expr: &mut ast::Expr,
+    ) -> Result<(), CubeError> {
+        let prev = std::mem::replace(&mut self.clause, clause);
+        self.visit_expr(expr)?;
+        self.clause = prev;
+
+        Ok(())
+    }
+
+    fn add_param(&mut self, number: usize, hint: Option<(PgTypeId, bool)>) {
+        if self.params.len() < number {
+            self.params.resize(number, None);
+        }
+
+        let param = &mut self.params[number - 1];
+        // The same parameter can be used multiple times, the first known type wins
+        if param.as_ref().map(|p| p.oid != 0).unwrap_or(false) {
+            return;
+        }
+
+        *param = Some(ParamMeta {
+            oid: hint.map(|(typ, _)| typ.to_oid()).unwrap_or(0),
+            nullable: hint.map(|(_, nullable)| nullable).unwrap_or(true),
+            clause: self.clause,
+        });
+    }
+}
+
+/// Type of `CAST(x AS type)`, type modifiers (`varchar(10)`) don't change the type
+fn cast_type(data_type: &ast::DataType) -> Option<PgTypeId> {
+    let name = data_type.to_string();
+
+    PgTypeId::from_name(name.split('(').next().unwrap_or_default().trim())
+}
+
+fn function_name(fun: &ast::Function) -> String {
+    fun.name.to_string().to_lowercase()
+}
+
+/// Result type of date functions, placeholders are often compared with them
+fn function_type(fun: &ast::Function) -> Option<PgTypeId> {
+    match function_name(fun).as_str() {
+        "now" | "date_trunc" | "current_timestamp" | "localtimestamp" => Some(PgTypeId::Timestamp),
+        "current_date" => Some(PgTypeId::Date),
+        _ => None,
+    }
+}
+
+/// `ANY(array)`, `SOME(array)` and `ALL(array)` of comparisons are parsed as functions
+fn is_array_comparison(fun: &ast::Function) -> bool {
+    matches!(function_name(fun).as_str(), "any" | "some" | "all")
+        && fun.args.len() == 1
+        && fun.over.is_none()
+}
+
+/// Types of positional arguments of functions, which are known to the inference
+fn function_arg_types(fun: &ast::Function) -> &'static [PgTypeId] {
+    match function_name(fun).as_str() {
+        "date_trunc" | "date_part" => &[PgTypeId::Text, PgTypeId::Timestamp],
+        "to_char" => &[PgTypeId::Timestamp, PgTypeId::Text],
+        _ => &[],
+    }
+}
+
+fn normalize_ident(ident: &ast::Ident) -> String {
+    match ident.quote_style {
+        Some(_) => ident.value.clone(),
+        None => ident.value.to_lowercase(),
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for PlaceholderTypeInference<'a> {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        let hint = self.hint.take();
+
+        match expr {
+            ast::Expr::Value(ast::Value::Placeholder(placeholder)) => {
+                self.position += 1;
+                let number = placeholder_number(placeholder, self.position)?;
+                self.add_param(number, hint);
+            }
+            ast::Expr::Cast { expr, data_type } | ast::Expr::TryCast { expr, data_type } => {
+                let hint = cast_type(data_type).map(|typ| (typ, true));
+                self.visit_operand(expr, hint)?;
+            }
+            ast::Expr::BinaryOp { left, op, right } => {
+                let hint = match op {
+                    ast::BinaryOperator::Like
+                    | ast::BinaryOperator::NotLike
+                    | ast::BinaryOperator::ILike
+                    | ast::BinaryOperator::NotILike => Some((PgTypeId::Text, true)),
+                    _ => self.column_type(left).or_else(|| self.column_type(right)),
+                };
+                self.visit_operand(left, hint)?;
+                self.visit_operand(right, hint)?;
+            }
+            ast::Expr::Extract { expr, .. } => {
+                self.visit_operand(expr, Some((PgTypeId::Timestamp, true)))?;
+            }
+            ast::Expr::Between {
+                expr, low, high, ..
+            } => {
+                let hint = self.column_type(expr);
+                self.visit_operand(expr, hint)?;
+                self.visit_operand(low, hint)?;
+                self.visit_operand(high, hint)?;
+            }
+            ast::Expr::InList { expr, list, .. } => {
+                let hint = self.column_type(expr);
+                self.visit_operand(expr, hint)?;
+                for item in list.iter_mut() {
+                    self.visit_operand(item, hint)?;
+                }
+            }
+            // `x = ANY($1)`, the parameter is an array of the type of `x`
+            ast::Expr::Function(fun) if is_array_comparison(fun) => {
+                let hint = hint.and_then(|(typ, nullable)| Some((typ.array()?, nullable)));
+                for arg in fun.args.iter_mut() {
+                    match arg {
+                        ast::FunctionArg::Unnamed(arg) | ast::FunctionArg::Named { arg, .. } => {
+                            self.visit_operand(arg, hint)?
+                        }
+                    }
+                }
+            }
+            _ => self.walk_expr(expr)?,
+        };
+
+        Ok(())
+    }
+
+    fn visit_select(&mut self, select: &mut Box<ast::Select>) -> Result<(), CubeError> {
+        let prev = self.clause;
+
+        self.clause = PlaceholderClause::Projection;
+        for projection in &mut select.projection {
+            self.visit_select_item(projection)?;
+        }
+
+        self.clause = PlaceholderClause::From;
+        for from in &mut select.from {
+            self.visit_table_with_joins(from)?;
+        }
+
+        if let Some(selection) = &mut select.selection {
+            self.visit_in_clause(PlaceholderClause::Where, selection)?;
+        };
+
+        for group_by in &mut select.group_by {
+            self.visit_in_clause(PlaceholderClause::GroupBy, group_by)?;
+        }
+
+        if let Some(having) = &mut select.having {
+            self.visit_in_clause(PlaceholderClause::Having, having)?;
+        };
+
+        self.clause = prev;
+
+        Ok(())
+    }
+
+    fn visit_order_by_expr(&mut self, order_by: &mut ast::OrderByExpr) -> Result<(), CubeError> {
+        self.visit_in_clause(PlaceholderClause::OrderBy, &mut order_by.expr)
+    }
+
+    fn visit_function(&mut self, fun: &mut ast::Function) -> Result<(), CubeError> {
+        let arg_types = function_arg_types(fun);
+
+        for (i, arg) in fun.args.iter_mut().enumerate() {
+            let hint = arg_types.get(i).map(|typ| (*typ, true));
+            match arg {
+                ast::FunctionArg::Named { arg, .. } => self.visit_operand(arg, hint)?,
+                ast::FunctionArg::Unnamed(arg) => self.visit_operand(arg, hint)?,
+            };
+        }
+
+        if let Some(over) = &mut fun.over {
+            self.visit_window_spec(over)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit_limit(&mut self, limit: &mut Option<ast::Expr>) -> Result<(), CubeError> {
+        if let Some(limit) = limit {
+            let prev = std::mem::replace(&mut self.clause, PlaceholderClause::Limit);
+            self.visit_operand(limit, Some((PgTypeId::Int8, false)))?;
+            self.clause = prev;
+        }
+
+        Ok(())
+    }
+
+    fn visit_offset(&mut self, offset: &mut Option<ast::Offset>) -> Result<(), CubeError> {
+        if let Some(offset) = offset {
+            let prev = std::mem::replace(&mut self.clause, PlaceholderClause::Offset);
+            self.visit_operand(&mut offset.value, Some((PgTypeId::Int8, false)))?;
+            self.clause = prev;
+        }
+
+        Ok(())
+    }
+}
+
+/// Per parameter (by number) type hints, for JDBC ParameterMetaData / ParameterDescription.
+/// Types are inferred from comparisons with columns of `schema`.
+pub fn parameter_metadata(
+    stmt: &ast::Statement,
+    schema: Option<&DFSchema>,
+) -> Result<Vec<ParamMeta>, CubeError> {
+    let mut inference = PlaceholderTypeInference::new(schema);
+    inference.visit_statement(&mut stmt.clone())?;
+
+    Ok(inference
+        .params
+        .into_iter()
+        .map(|p| p.unwrap_or_else(ParamMeta::unknown))
+        .collect())
+}
+
+/// Table in FROM or JOIN, see `referenced_tables`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableReference {
+    /// The last part of the name, as written
+    pub name: String,
+    /// Alias or the normalized name, columns are qualified by it
+    pub qualifier: String,
+}
+
+#[derive(Debug)]
+struct TableCollector {
+    tables: Vec<TableReference>,
+}
+
+impl<'ast> Visitor<'ast> for TableCollector {
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) -> Result<(), CubeError> {
+        match factor {
+            ast::TableFactor::Table { name, alias, .. } => {
+                if let Some(ident) = name.0.last() {
+                    self.tables.push(TableReference {
+                        name: ident.value.clone(),
+                        qualifier: alias
+                            .as_ref()
+                            .map(|alias| normalize_ident(&alias.name))
+                            .unwrap_or_else(|| normalize_ident(ident)),
+                    });
+                }
+            }
+            ast::TableFactor::Derived { subquery, .. } => self.visit_query(subquery)?,
+            ast::TableFactor::NestedJoin(twj) => self.visit_table_with_joins(twj)?,
+            _ => {}
+        };
+
+        Ok(())
+    }
+}
+
+/// Tables of the statement, including tables of subqueries, in the order they are written
+pub fn referenced_tables(stmt: &ast::Statement) -> Result<Vec<TableReference>, CubeError> {
+    let mut collector = TableCollector { tables: vec![] };
+    collector.visit_statement(&mut stmt.clone())?;
+
+    Ok(collector.tables)
+}
+
+/// Column of an expression, see `referenced_columns`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnReference {
+    /// Table or alias for qualified columns (`t.col`)
+    pub qualifier: Option<String>,
+    pub name: String,
+}
+
+#[derive(Debug)]
+struct ColumnCollector {
+    columns: Vec<ColumnReference>,
+}
+
+impl<'ast> Visitor<'ast> for ColumnCollector {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        match expr {
+            ast::Expr::Identifier(ident) => self.columns.push(ColumnReference {
+                qualifier: None,
+                name: normalize_ident(ident),
+            }),
+            ast::Expr::CompoundIdentifier(idents) if idents.len() >= 2 => {
+                self.columns.push(ColumnReference {
+                    qualifier: Some(normalize_ident(&idents[idents.len() - 2])),
+                    name: normalize_ident(&idents[idents.len() - 1]),
+                })
+            }
+            _ => {}
+        };
+
+        self.walk_expr(expr)
+    }
+}
+
+/// Columns of expressions of the statement, including columns of subqueries. Names are
+/// normalized the same way as qualifiers of `referenced_tables`.
+pub fn referenced_columns(stmt: &ast::Statement) -> Result<Vec<ColumnReference>, CubeError> {
+    let mut collector = ColumnCollector { columns: vec![] };
+    collector.visit_statement(&mut stmt.clone())?;
+
+    Ok(collector.columns)
+}
+
+/// Kind of a subquery in an expression, see `expression_subqueries`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubqueryKind {
+    /// `x [NOT] IN (SELECT ...)`
+    In,
+    /// `EXISTS (SELECT ...)`
+    Exists,
+    /// `(SELECT ...)`
+    Scalar,
+}
+
+#[derive(Debug, Default)]
+struct SubqueryCollector {
+    subqueries: Vec<(SubqueryKind, ast::Query)>,
+}
+
+impl<'ast> Visitor<'ast> for SubqueryCollector {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        match expr {
+            ast::Expr::InSubquery { expr, subquery, .. } => {
+                self.visit_expr(&mut *expr)?;
+                self.subqueries
+                    .push((SubqueryKind::In, (**subquery).clone()));
+            }
+            ast::Expr::Exists(subquery) => {
+                self.subqueries
+                    .push((SubqueryKind::Exists, (**subquery).clone()));
+            }
+            ast::Expr::Subquery(subquery) => {
+                self.subqueries
+                    .push((SubqueryKind::Scalar, (**subquery).clone()));
+            }
+            _ => self.walk_expr(expr)?,
+        };
+
+        Ok(())
+    }
+}
+
+/// Subqueries of expressions in the order they are written, subqueries of FROM are not
+/// included. Subqueries of subqueries are not included as well, they are a part of the
+/// outer subquery.
+pub fn expression_subqueries(
+    stmt: &ast::Statement,
+) -> Result<Vec<(SubqueryKind, ast::Query)>, CubeError> {
+    let mut collector = SubqueryCollector::default();
+    collector.visit_statement(&mut stmt.clone())?;
+
+    Ok(collector.subqueries)
+}
+
+struct SubqueryReplacer {
+    results: std::vec::IntoIter<Option<Vec<ast::Expr>>>,
+}
+
+impl SubqueryReplacer {
+    fn next(&mut self) -> Result<Option<Vec<ast::Expr>>, CubeError> {
+        self.results.next().ok_or_else(|| {
+            CubeError::internal("Results of subqueries don't match the statement".to_string())
+        })
+    }
+}
+
+impl<'ast> Visitor<'ast> for SubqueryReplacer {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        let replacement = match expr {
+            ast::Expr::InSubquery {
+                expr: left,
+                negated,
+                ..
+            } => {
+                self.visit_expr(&mut *left)?;
+
+                self.next()?.map(|values| {
+                    if values.is_empty() {
+                        ast::Expr::Value(ast::Value::Boolean(*negated))
+                    } else {
+                        ast::Expr::InList {
+                            expr: left.clone(),
+                            list: values,
+                            negated: *negated,
+                        }
+                    }
+                })
+            }
+            ast::Expr::Exists(_) => self
+                .next()?
+                .map(|values| ast::Expr::Value(ast::Value::Boolean(!values.is_empty()))),
+            ast::Expr::Subquery(_) => match self.next()? {
+                Some(values) if values.len() > 1 => {
+                    return Err(CubeError::user(
+                        "more than one row returned by a subquery used as an expression"
+                            .to_string(),
+                    ))
+                }
+                Some(values) => Some(
+                    values
+                        .into_iter()
+                        .next()
+                        .unwrap_or(ast::Expr::Value(ast::Value::Null)),
+                ),
+                None => None,
+            },
+            _ => return self.walk_expr(expr),
+        };
+
+        if let Some(replacement) = replacement {
+            *expr = replacement;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces subqueries of `expression_subqueries` by values of their first column: IN lists,
+/// booleans for EXISTS and values of scalar subqueries. Subqueries without results are kept.
+pub fn replace_expression_subqueries(
+    stmt: &mut ast::Statement,
+    results: Vec<Option<Vec<ast::Expr>>>,
+) -> Result<(), CubeError> {
+    let mut replacer = SubqueryReplacer {
+        results: results.into_iter(),
+    };
+
+    replacer.visit_statement(stmt)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaceholderSyntax {
+    /// PostgreSQL, $1
+    Dollar,
+    /// MySQL, ?
+    QuestionMark,
+}
+
+#[derive(Debug)]
+struct PlaceholderRetargeter {
+    target: PlaceholderSyntax,
+    position: usize,
+    mapping: Vec<usize>,
+}
+
+impl<'ast> Visitor<'ast> for PlaceholderRetargeter {
+    fn visit_value(&mut self, value: &mut ast::Value) -> Result<(), CubeError> {
+        if let ast::Value::Placeholder(placeholder) = value {
+            self.position += 1;
+            let number = placeholder_number(placeholder, self.position)?;
+
+            *placeholder = match self.target {
+                PlaceholderSyntax::Dollar => format!("${}", number),
+                PlaceholderSyntax::QuestionMark => "?".to_string(),
+            };
+            self.mapping.push(number - 1);
+        }
+
+        Ok(())
+    }
+}
+
+/// Rewrites every placeholder into the `target` syntax without binding values. Returns
+/// (0-based) indexes of original parameters for every placeholder of the rewritten statement,
+/// in their order, to reorder values for the target engine.
+pub fn retarget_placeholders(
+    stmt: &mut ast::Statement,
+    target: PlaceholderSyntax,
+) -> Result<Vec<usize>, CubeError> {
+    let mut retargeter = PlaceholderRetargeter {
+        target,
+        position: 0,
+        mapping: vec![],
+    };
+    retargeter.visit_statement(stmt)?;
+
+    Ok(retargeter.mapping)
+}
+
+#[derive(Debug)]
+struct QueryNormalizer {
+    next_number: usize,
+}
+
+impl<'ast> Visitor<'ast> for QueryNormalizer {
+    fn visit_value(&mut self, value: &mut ast::Value) -> Result<(), CubeError> {
+        match value {
+            ast::Value::Number(..) | ast::Value::SingleQuotedString(_) => {
+                *value = ast::Value::Placeholder(format!("${}", self.next_number));
+                self.next_number += 1;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Text of the statement with constants replaced by placeholders, as queries are shown by
+/// pg_stat_statements. Constants are numbered after placeholders of the statement.
+pub fn normalize_statement(stmt: &ast::Statement) -> Result<String, CubeError> {
+    let mut normalizer = QueryNormalizer {
+        next_number: placeholder_report(stmt)?.max_index + 1,
+    };
+    let mut stmt = stmt.clone();
+    normalizer.visit_statement(&mut stmt)?;
+
+    Ok(stmt.to_string())
+}
+
+/// Rewrites `ORDER BY x NULLS { FIRST | LAST }` for backends without NULLS ordering support into
+/// `ORDER BY CASE WHEN x IS NULL THEN 1 ELSE 0 END, x`
+#[derive(Debug)]
+pub struct NullsOrderingRewriter {}
+
+impl NullsOrderingRewriter {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Binder for NullsOrderingRewriter {
+    fn bind(&mut self, stmt: &mut ast::Statement) -> Result<(), CubeError> {
+        self.visit_statement(stmt)
+    }
+}
+
+impl<'ast> Visitor<'ast> for NullsOrderingRewriter {
+    fn visit_order_by(&mut self, order_by: &mut Vec<ast::OrderByExpr>) -> Result<(), CubeError> {
+        let mut rewritten = Vec::with_capacity(order_by.len());
+
+        for mut expr in order_by.drain(..) {
+            self.visit_expr(&mut expr.expr)?;
+
+            if let Some(nulls_first) = expr.nulls_first.take() {
+                let (nulls, not_nulls) = if nulls_first { ("0", "1") } else { ("1", "0") };
+
+                rewritten.push(ast::OrderByExpr {
+                    expr: ast::Expr::Case {
+                        operand: None,
+                        conditions: vec![ast::Expr::IsNull(Box::new(expr.expr.clone()))],
+                        results: vec![ast::Expr::Value(ast::Value::Number(
+                            nulls.to_string(),
+                            false,
+                        ))],
+                        else_result: Some(Box::new(ast::Expr::Value(ast::Value::Number(
+                            not_nulls.to_string(),
+                            false,
+                        )))),
+                    },
+                    asc: None,
+                    nulls_first: None,
+                });
+            }
+
+            rewritten.push(expr);
+        }
+
+        *order_by = rewritten;
+
+        Ok(())
+    }
+}
+
+/// Elements of an array expression: `make_array(...)` (`ARRAY[...]`), a literal (`'{a,b}'`) or
+/// a literal cast to an array type (`'{1,2}'::int[]`, bound array parameters). Elements of
+/// numeric, boolean and temporal arrays are typed, other elements are strings. None is returned
+/// for other expressions.
+pub fn array_elements(expr: &ast::Expr) -> Result<Option<Vec<ast::Expr>>, CubeError> {
+    let (text, typ) = match expr {
+        ast::Expr::Nested(expr) => return array_elements(expr),
+        ast::Expr::Function(fun) if function_name(fun) == "make_array" => {
+            let elements = fun
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    ast::FunctionArg::Unnamed(arg) | ast::FunctionArg::Named { arg, .. } => {
+                        arg.clone()
+                    }
+                })
+                .collect();
+
+            return Ok(Some(elements));
+        }
+        ast::Expr::Value(ast::Value::SingleQuotedString(text)) => (text, None),
+        ast::Expr::Cast {
+            expr,
+            data_type: ast::DataType::Array(element),
+        } => match expr.as_ref() {
+            ast::Expr::Value(ast::Value::SingleQuotedString(text)) => (text, cast_type(element)),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    let invalid = || CubeError::user(format!("malformed array literal: \"{}\"", text));
+    let elements = parse_array_literal(text).ok_or_else(invalid)?;
+
+    elements
+        .into_iter()
+        .map(|element| {
+            let element = match element {
+                Some(element) => element,
+                None => return Ok(ast::Expr::Value(ast::Value::Null)),
+            };
+
+            Ok(match typ {
+                Some(PgTypeId::Bool) => match element.to_lowercase().as_str() {
+                    "t" | "true" => ast::Expr::Value(ast::Value::Boolean(true)),
+                    "f" | "false" => ast::Expr::Value(ast::Value::Boolean(false)),
+                    _ => return Err(invalid()),
+                },
+                Some(PgTypeId::Int2)
+                | Some(PgTypeId::Int4)
+                | Some(PgTypeId::Int8)
+                | Some(PgTypeId::Float4)
+                | Some(PgTypeId::Float8)
+                | Some(PgTypeId::Numeric) => {
+                    element.trim().parse::<f64>().map_err(|_| invalid())?;
+
+                    ast::Expr::Value(ast::Value::Number(element.trim().to_string(), false))
+                }
+                Some(PgTypeId::Date) => ast::Expr::TypedString {
+                    data_type: ast::DataType::Date,
+                    value: element,
+                },
+                Some(PgTypeId::Timestamp) => ast::Expr::TypedString {
+                    data_type: ast::DataType::Timestamp,
+                    value: element,
+                },
+                _ => ast::Expr::Value(ast::Value::SingleQuotedString(element)),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+#[derive(Debug)]
+struct ArrayComparisonRewriter {}
+
+impl<'ast> Visitor<'ast> for ArrayComparisonRewriter {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)?;
+
+        let (left, op, fun) = match &*expr {
+            ast::Expr::BinaryOp { left, op, right } => match right.as_ref() {
+                ast::Expr::Function(fun) if is_array_comparison(fun) => (left, op, fun),
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+
+        let name = function_name(fun);
+        let negated = match (name.as_str(), op) {
+            ("any", ast::BinaryOperator::Eq) | ("some", ast::BinaryOperator::Eq) => false,
+            ("all", ast::BinaryOperator::NotEq) => true,
+            _ => {
+                return Err(CubeError::user(format!(
+                    "{} {}(...) is not supported, only = ANY(...) and <> ALL(...) are",
+                    op,
+                    name.to_uppercase()
+                )))
+            }
+        };
+
+        let array = match &fun.args[0] {
+            ast::FunctionArg::Unnamed(arg) | ast::FunctionArg::Named { arg, .. } => arg,
+        };
+        let list = array_elements(array)?.ok_or_else(|| {
+            CubeError::user(format!(
+                "{}({}) is not supported, only array literals and parameters can be compared",
+                name.to_uppercase(),
+                array
+            ))
+        })?;
+
+        *expr = if list.is_empty() {
+            // Nothing equals an element of an empty array
+            ast::Expr::Value(ast::Value::Boolean(negated))
+        } else {
+            ast::Expr::InList {
+                expr: left.clone(),
+                list,
+                negated,
+            }
+        };
+
+        Ok(())
+    }
+}
+
+/// Rewrites comparisons with elements of arrays into IN lists: `x = ANY(array)` into
+/// `x IN (...)`, `x <> ALL(array)` into `x NOT IN (...)`. Arrays of `array_elements` are
+/// supported, they must be bound before the rewrite.
+pub fn rewrite_array_comparisons(stmt: &mut ast::Statement) -> Result<(), CubeError> {
+    ArrayComparisonRewriter {}.visit_statement(stmt)
+}
+
+/// Decimal column of a relation, aggregates of columns of tables are calculated by Cube
+#[derive(Debug, Clone, Copy)]
+struct DecimalColumn {
+    table: bool,
+    precision: usize,
+    scale: usize,
+}
+
+type DecimalColumns = HashMap<String, DecimalColumn>;
+
+struct DecimalRewriter<'a> {
+    /// Decimal columns of tables, with lowercased names
+    tables: &'a dyn Fn(&ast::ObjectName) -> Vec<(String, usize, usize)>,
+    ctes: HashMap<String, DecimalColumns>,
+    /// Columns of relations of the current SELECT, they are collected while FROM is visited
+    relations: DecimalColumns,
+    /// Columns of relations of SELECTs which are visited, the innermost is the last
+    scopes: Vec<DecimalColumns>,
+    /// Decimal columns of the result of the last visited query
+    output: DecimalColumns,
+}
+
+fn numeric_operator(op: &ast::BinaryOperator) -> Option<NumericOperator> {
+    match op {
+        ast::BinaryOperator::Plus => Some(NumericOperator::Plus),
+        ast::BinaryOperator::Minus => Some(NumericOperator::Minus),
+        ast::BinaryOperator::Multiply => Some(NumericOperator::Multiply),
+        ast::BinaryOperator::Divide => Some(NumericOperator::Divide),
+        ast::BinaryOperator::Eq => Some(NumericOperator::Eq),
+        ast::BinaryOperator::NotEq => Some(NumericOperator::NotEq),
+        ast::BinaryOperator::Lt => Some(NumericOperator::Lt),
+        ast::BinaryOperator::LtEq => Some(NumericOperator::LtEq),
+        ast::BinaryOperator::Gt => Some(NumericOperator::Gt),
+        ast::BinaryOperator::GtEq => Some(NumericOperator::GtEq),
+        _ => None,
+    }
+}
+
+fn function_call(name: String, args: Vec<ast::Expr>) -> ast::Expr {
+    ast::Expr::Function(ast::Function {
+        name: ast::ObjectName(vec![ast::Ident::new(name)]),
+        args: args.into_iter().map(ast::FunctionArg::Unnamed).collect(),
+        over: None,
+        distinct: false,
+    })
+}
+
+fn function_args(fun: &ast::Function) -> Vec<&ast::Expr> {
+    fun.args
+        .iter()
+        .map(|arg| match arg {
+            ast::FunctionArg::Unnamed(arg) | ast::FunctionArg::Named { arg, .. } => arg,
+        })
+        .collect()
+}
+
+/// Precision and scale of `NUMERIC(precision, scale)`, the scale is 0 when only the precision
+/// is defined
+fn decimal_cast_type(
+    precision: &Option<u64>,
+    scale: &Option<u64>,
+) -> Result<(usize, usize), CubeError> {
+    let (precision, scale) = match (precision, scale) {
+        (None, _) => (DEFAULT_DECIMAL_PRECISION, DEFAULT_DECIMAL_SCALE),
+        (Some(precision), scale) => (*precision as usize, scale.unwrap_or(0) as usize),
+    };
+    if precision == 0 || precision > MAX_DECIMAL_PRECISION {
+        return Err(CubeError::user(format!(
+            "NUMERIC precision {} must be between 1 and {}",
+            precision, MAX_DECIMAL_PRECISION
+        )));
+    }
+    if scale > precision {
+        return Err(CubeError::user(format!(
+            "NUMERIC scale {} must be between 0 and precision {}",
+            scale, precision
+        )));
+    }
+
+    Ok((precision, scale))
+}
+
+fn is_fractional_literal(expr: &ast::Expr) -> bool {
+    match expr {
+        ast::Expr::Value(ast::Value::Number(value, _)) => {
+            value.contains(|c: char| c == '.' || c == 'e' || c == 'E')
+        }
+        _ => false,
+    }
+}
+
+/// Literals with fractional digits are floats for DataFusion, they are cast to decimals to be
+/// calculated exactly with other decimals
+fn decimal_literal(expr: ast::Expr) -> ast::Expr {
+    let decimal = match &expr {
+        ast::Expr::Value(ast::Value::Number(value, _)) if is_fractional_literal(&expr) => {
+            DecimalValue::parse(value).map(|decimal| (value.clone(), decimal))
+        }
+        _ => None,
+    };
+
+    match decimal {
+        Some((value, decimal)) => {
+            let digits = decimal.value().unsigned_abs().to_string().len();
+            let precision = digits.max(decimal.scale());
+            if precision > MAX_DECIMAL_PRECISION {
+                return expr;
+            }
+
+            function_call(
+                numeric_cast_name(precision, decimal.scale()),
+                vec![ast::Expr::Value(ast::Value::SingleQuotedString(value))],
+            )
+        }
+        None => expr,
+    }
+}
+
+impl<'a> DecimalRewriter<'a> {
+    fn column(&self, expr: &ast::Expr) -> Option<DecimalColumn> {
+        let ident = match expr {
+            ast::Expr::Identifier(ident) => ident,
+            ast::Expr::CompoundIdentifier(idents) => idents.last()?,
+            _ => return None,
+        };
+        let name = ident.value.to_lowercase();
+
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name).cloned())
+    }
+
+    /// Precision and scale of decimal expressions, operands are already rewritten. Columns
+    /// are resolved by their names only, a wrong guess is still calculated correctly by
+    /// `numeric_*` functions.
+    fn decimal_type(&self, expr: &ast::Expr) -> Option<(usize, usize)> {
+        let fun = match expr {
+            ast::Expr::Nested(expr) => return self.decimal_type(expr),
+            ast::Expr::UnaryOp {
+                op: ast::UnaryOperator::Plus,
+                expr,
+            } => return self.decimal_type(expr),
+            ast::Expr::Function(fun) => fun,
+            expr => {
+                return self
+                    .column(expr)
+                    .map(|column| (column.precision, column.scale))
+            }
+        };
+
+        let name = function_name(fun);
+        if let Some(decimal) = parse_numeric_cast_name(&name) {
+            return Some(decimal);
+        }
+
+        let args = function_args(fun);
+        if let Some(op) = NumericOperator::ALL
+            .iter()
+            .find(|op| op.function_name() == name)
+        {
+            if op.is_comparison() || args.len() != 2 {
+                return None;
+            }
+
+            // Other numbers are integers for the guess
+            let operand = |expr: &ast::Expr| {
+                if is_fractional_literal(expr) {
+                    None
+                } else {
+                    self.decimal_type(expr)
+                        .or_else(|| decimal_operand_type(&DataType::Int64))
+                }
+            };
+
+            return Some(decimal_arithmetic_coercion(
+                *op,
+                operand(args[0])?,
+                operand(args[1])?,
+            ));
+        }
+
+        // Aggregates of columns of tables, which are kept as is by `visit_expr`
+        match args.as_slice() {
+            [arg] if fun.over.is_none() => {
+                decimal_aggregate_coercion(&name, self.decimal_type(arg)?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Columns of a relation of FROM
+    fn relation_columns(
+        &mut self,
+        factor: &mut ast::TableFactor,
+    ) -> Result<DecimalColumns, CubeError> {
+        Ok(match factor {
+            ast::TableFactor::Table { name, .. } => {
+                let cte = match name.0.as_slice() {
+                    [ident] => self.ctes.get(&ident.value.to_lowercase()).cloned(),
+                    _ => None,
+                };
+
+                match cte {
+                    Some(columns) => columns,
+                    None => (self.tables)(&*name)
+                        .into_iter()
+                        .map(|(name, precision, scale)| {
+                            let column = DecimalColumn {
+                                table: true,
+                                precision,
+                                scale,
+                            };
+
+                            (name, column)
+                        })
+                        .collect(),
+                }
+            }
+            ast::TableFactor::Derived { subquery, .. } => {
+                self.visit_query(subquery)?;
+
+                std::mem::take(&mut self.output)
+            }
+            ast::TableFactor::NestedJoin(twj) => {
+                self.visit_table_with_joins(twj)?;
+
+                DecimalColumns::new()
+            }
+            _ => DecimalColumns::new(),
+        })
+    }
+
+    /// `agg(x)` of decimals are calculated by `numeric_*` aggregates, their results are cast back
+    /// to decimals. Aggregates of columns of tables are kept, they are calculated by Cube.
+    fn rewrite_aggregate(&self, fun: &ast::Function) -> Option<ast::Expr> {
+        let name = function_name(fun);
+        if fun.over.is_some() || fun.distinct {
+            return None;
+        }
+
+        let arg = match function_args(fun).as_slice() {
+            [arg] => (*arg).clone(),
+            _ => return None,
+        };
+        if self.column(&arg).map_or(false, |column| column.table) {
+            return None;
+        }
+
+        let (precision, scale) = decimal_aggregate_coercion(&name, self.decimal_type(&arg)?)?;
+        Some(function_call(
+            numeric_cast_name(precision, scale),
+            vec![function_call(format!("numeric_{}", name), vec![arg])],
+        ))
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for DecimalRewriter<'a> {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)?;
+
+        let rewritten = match expr {
+            ast::Expr::BinaryOp { left, op, right } => match numeric_operator(op) {
+                Some(op)
+                    if self.decimal_type(left).is_some() || self.decimal_type(right).is_some() =>
+                {
+                    function_call(
+                        op.function_name().to_string(),
+                        vec![
+                            decimal_literal(left.as_ref().clone()),
+                            decimal_literal(right.as_ref().clone()),
+                        ],
+                    )
+                }
+                _ => return Ok(()),
+            },
+            ast::Expr::UnaryOp {
+                op: ast::UnaryOperator::Minus,
+                expr: operand,
+            } if self.decimal_type(operand).is_some() => function_call(
+                NumericOperator::Minus.function_name().to_string(),
+                vec![
+                    ast::Expr::Value(ast::Value::Number("0".to_string(), false)),
+                    operand.as_ref().clone(),
+                ],
+            ),
+            ast::Expr::Cast {
+                expr: operand,
+                data_type: ast::DataType::Decimal(precision, scale),
+            } => {
+                let (precision, scale) = decimal_cast_type(precision, scale)?;
+
+                function_call(
+                    numeric_cast_name(precision, scale),
+                    vec![operand.as_ref().clone()],
+                )
+            }
+            ast::Expr::Function(fun) => match self.rewrite_aggregate(fun) {
+                Some(rewritten) => rewritten,
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        *expr = rewritten;
+
+        Ok(())
+    }
+
+    /// FROM is visited first, its relations are the scope of other clauses. Decimal columns of
+    /// the projection are the output of the query.
+    fn visit_select(&mut self, select: &mut Box<ast::Select>) -> Result<(), CubeError> {
+        let outer_relations = std::mem::take(&mut self.relations);
+        for from in select.from.iter_mut() {
+            self.visit_table_with_joins(from)?;
+        }
+        let scope = std::mem::replace(&mut self.relations, outer_relations);
+        self.scopes.push(scope);
+
+        for from in select.from.iter_mut() {
+            for join in from.joins.iter_mut() {
+                match &mut join.join_operator {
+                    ast::JoinOperator::Inner(ast::JoinConstraint::On(on))
+                    | ast::JoinOperator::LeftOuter(ast::JoinConstraint::On(on))
+                    | ast::JoinOperator::RightOuter(ast::JoinConstraint::On(on))
+                    | ast::JoinOperator::FullOuter(ast::JoinConstraint::On(on)) => {
+                        self.visit_expr(on)?
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut output = DecimalColumns::new();
+        for item in select.projection.iter_mut() {
+            self.visit_select_item(item)?;
+
+            let (name, expr) = match &*item {
+                ast::SelectItem::ExprWithAlias { expr, alias } => (alias.value.clone(), expr),
+                ast::SelectItem::UnnamedExpr(expr) => match expr {
+                    ast::Expr::Identifier(ident) => (ident.value.clone(), expr),
+                    ast::Expr::CompoundIdentifier(idents) if !idents.is_empty() => {
+                        (idents[idents.len() - 1].value.clone(), expr)
+                    }
+                    _ => continue,
+                },
+                // Wildcards select every column of relations
+                _ => {
+                    output.extend(
+                        self.scopes
+                            .last()
+                            .into_iter()
+                            .flatten()
+                            .map(|(name, column)| (name.clone(), *column)),
+                    );
+                    continue;
+                }
+            };
+
+            if let Some((precision, scale)) = self.decimal_type(expr) {
+                let column = DecimalColumn {
+                    table: false,
+                    precision,
+                    scale,
+                };
+                output.insert(name.to_lowercase(), column);
+            }
+        }
+
+        if let Some(selection) = &mut select.selection {
+            self.visit_expr(selection)?;
+        };
+
+        for group_by in &mut select.group_by {
+            self.visit_expr(group_by)?;
+        }
+
+        if let Some(having) = &mut select.having {
+            self.visit_expr(having)?;
+        };
+
+        self.scopes.pop();
+        self.output = output;
+
+        Ok(())
+    }
+
+    fn visit_table_factor(&mut self, factor: &mut ast::TableFactor) -> Result<(), CubeError> {
+        let columns = self.relation_columns(factor)?;
+        self.relations.extend(columns);
+
+        Ok(())
+    }
+
+    /// Columns of CTEs are known to the following CTEs and the body, ORDER BY references
+    /// columns of the result
+    fn visit_query(&mut self, query: &mut ast::Query) -> Result<(), CubeError> {
+        let outer_ctes = self.ctes.clone();
+        if let Some(with) = &mut query.with {
+            for cte in with.cte_tables.iter_mut() {
+                self.visit_query(&mut cte.query)?;
+                let columns = std::mem::take(&mut self.output);
+                self.ctes
+                    .insert(cte.alias.name.value.to_lowercase(), columns);
+            }
+        }
+
+        self.visit_set_expr(&mut query.body)?;
+        let output = std::mem::take(&mut self.output);
+
+        self.scopes.push(output.clone());
+        self.visit_order_by(&mut query.order_by)?;
+        self.scopes.pop();
+
+        self.ctes = outer_ctes;
+        self.output = output;
+
+        Ok(())
+    }
+}
+
+/// Rewrites operators, casts and aggregates of decimals into `numeric_*` functions, as
+/// DataFusion can't calculate with decimals. `tables` returns decimal columns of tables.
+pub fn rewrite_decimals(
+    stmt: &mut ast::Statement,
+    tables: &dyn Fn(&ast::ObjectName) -> Vec<(String, usize, usize)>,
+) -> Result<(), CubeError> {
+    let mut rewriter = DecimalRewriter {
+        tables,
+        ctes: HashMap::new(),
+        relations: DecimalColumns::new(),
+        scopes: vec![],
+        output: DecimalColumns::new(),
+    };
+
+    rewriter.visit_statement(stmt)
+}
+
+fn is_timestamptz_type(data_type: &ast::DataType) -> bool {
+    match data_type {
+        ast::DataType::Custom(name) => name.0.last().map_or(false, |ident| {
+            ident.value.eq_ignore_ascii_case("timestamptz")
+        }),
+        _ => false,
+    }
+}
+
+/// Expressions which are timestamps with time zone after `TimestamptzRewriter`
+fn is_timestamptz(expr: &ast::Expr) -> bool {
+    match expr {
+        ast::Expr::Nested(expr) => is_timestamptz(expr),
+        ast::Expr::Function(fun) => matches!(
+            function_name(fun).as_str(),
+            TO_TIMESTAMPTZ_FUNCTION | TIMESTAMPTZ_TRUNC_FUNCTION
+        ),
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+struct TimestamptzRewriter {}
+
+impl<'ast> Visitor<'ast> for TimestamptzRewriter {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)?;
+
+        let (name, args) = match &*expr {
+            ast::Expr::Cast { expr, data_type } if is_timestamptz_type(data_type) => {
+                (TO_TIMESTAMPTZ_FUNCTION, vec![*expr.clone()])
+            }
+            ast::Expr::TypedString { data_type, value } if is_timestamptz_type(data_type) => (
+                TO_TIMESTAMPTZ_FUNCTION,
+                vec![ast::Expr::Value(ast::Value::SingleQuotedString(
+                    value.clone(),
+                ))],
+            ),
+            ast::Expr::Cast {
+                expr,
+                data_type: ast::DataType::Timestamp,
+            } if is_timestamptz(expr) => (TIMESTAMPTZ_LOCAL_FUNCTION, vec![*expr.clone()]),
+            ast::Expr::Extract { field, expr } => (
+                if is_timestamptz(expr) {
+                    TIMESTAMPTZ_PART_FUNCTION
+                } else {
+                    TIMESTAMP_PART_FUNCTION
+                },
+                vec![
+                    ast::Expr::Value(ast::Value::SingleQuotedString(
+                        field.to_string().to_lowercase(),
+                    )),
+                    *expr.clone(),
+                ],
+            ),
+            ast::Expr::Function(fun) if fun.over.is_none() => {
+                // date_trunc(unit, column, zone) is calculated by Cube
+                let args = function_args(fun);
+                if args.len() != 2 {
+                    return Ok(());
+                }
+
+                let name = match function_name(fun).as_str() {
+                    "date_trunc" if is_timestamptz(args[1]) => TIMESTAMPTZ_TRUNC_FUNCTION,
+                    "date_part" if is_timestamptz(args[1]) => TIMESTAMPTZ_PART_FUNCTION,
+                    "date_part" => TIMESTAMP_PART_FUNCTION,
+                    _ => return Ok(()),
+                };
+
+                (name, args.into_iter().cloned().collect())
+            }
+            _ => return Ok(()),
+        };
+
+        *expr = function_call(name.to_string(), args);
+
+        Ok(())
+    }
+}
+
+/// Timestamps with time zone are evaluated in TimeZone of the session by `timestamptz_*`
+/// functions: casts to TIMESTAMPTZ are rewritten into `to_timestamptz()`, date_trunc, date_part,
+/// EXTRACT and casts to TIMESTAMP of them into `timestamptz_trunc()`, `timestamptz_part()` and
+/// `timestamptz_local()`. date_part and EXTRACT of other timestamps are `timestamp_part()`,
+/// they keep the built-in date_trunc, which is pushed down to Cube.
+pub fn rewrite_timestamptz(stmt: &mut ast::Statement) -> Result<(), CubeError> {
+    TimestamptzRewriter {}.visit_statement(stmt)
+}
+
+/// Parts of an interval which are added separately, as DataFusion doesn't mix months with
+/// days and time in a literal. Values are signed, zero ones are skipped.
+fn interval_parts(interval: &IntervalValue) -> Vec<Vec<(i64, &'static str)>> {
+    // The precision of DataFusion intervals is milliseconds
+    let millis = interval.microseconds() / 1000;
+    let parts = vec![
+        vec![(interval.months() as i64, "month")],
+        vec![(interval.days() as i64, "day")],
+        vec![
+            (millis / 3_600_000, "hour"),
+            (millis / 60_000 % 60, "minute"),
+            (millis / 1000 % 60, "second"),
+            (millis % 1000, "millisecond"),
+        ],
+    ];
+
+    parts
+        .into_iter()
+        .map(|part| {
+            part.into_iter()
+                .filter(|(value, _)| *value != 0)
+                .collect::<Vec<_>>()
+        })
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Interval literal of parts, units are singular as DataFusion parses only them
+fn interval_expr<'a>(parts: impl Iterator<Item = &'a (i64, &'static str)>, abs: bool) -> ast::Expr {
+    let text = parts
+        .map(|(value, unit)| {
+            let value = if abs { value.abs() } else { *value };
+            format!("{} {}", value, unit)
+        })
+        .collect::<Vec<_>>();
+    let text = if text.is_empty() {
+        "0 second".to_string()
+    } else {
+        text.join(" ")
+    };
+
+    ast::Expr::Value(ast::Value::Interval {
+        value: Box::new(ast::Expr::Value(ast::Value::SingleQuotedString(text))),
+        leading_field: None,
+        leading_precision: None,
+        last_field: None,
+        fractional_seconds_precision: None,
+    })
+}
+
+#[derive(Debug)]
+struct IntervalRewriter {}
+
+impl<'ast> Visitor<'ast> for IntervalRewriter {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)?;
+
+        if let ast::Expr::BinaryOp { left, op, right } = &*expr {
+            let negate = match op {
+                ast::BinaryOperator::Plus => false,
+                ast::BinaryOperator::Minus => true,
+                _ => return Ok(()),
+            };
+            let (timestamp, interval) = match (interval_literal(left)?, interval_literal(right)?) {
+                (Some(_), Some(_)) => return Ok(()),
+                (_, Some(interval)) => (left, interval),
+                (Some(interval), None) if !negate => (right, interval),
+                _ => return Ok(()),
+            };
+            let interval = if negate { interval.negate() } else { interval };
+
+            let mut result = timestamp.as_ref().clone();
+            for part in interval_parts(&interval) {
+                let name = if part[0].0 < 0 {
+                    "date_sub"
+                } else {
+                    "date_add"
+                };
+                result = function_call(
+                    name.to_string(),
+                    vec![result, interval_expr(part.iter(), true)],
+                );
+            }
+            *expr = result;
+
+            return Ok(());
+        }
+
+        if let Some(interval) = interval_literal(expr)? {
+            *expr = interval_expr(interval_parts(&interval).iter().flatten(), false);
+        }
+
+        Ok(())
+    }
+}
+
+/// Arithmetic of timestamps and intervals (`ts - INTERVAL '1 month 2 days'`) is rewritten into
+/// `date_add()` and `date_sub()` of every part of the interval, as in PostgreSQL months are
+/// added first. Interval literals and casts of strings to INTERVAL are written as DataFusion
+/// parses them.
+pub fn rewrite_intervals(stmt: &mut ast::Statement) -> Result<(), CubeError> {
+    IntervalRewriter {}.visit_statement(stmt)
+}
+
+/// Aggregates which are evaluated by DataFusion, they can't be expressed by Cube queries
+const ORDERED_AGGREGATES: [&str; 6] = [
+    "string_agg",
+    "listagg",
+    "array_agg",
+    "percentile_cont",
+    "percentile_disc",
+    "median",
+];
+
+#[derive(Debug)]
+struct OrderedAggregateRewriter {}
+
+impl<'ast> Visitor<'ast> for OrderedAggregateRewriter {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)?;
+
+        let fun = match expr {
+            ast::Expr::Function(fun) if fun.over.is_none() => fun,
+            _ => return Ok(()),
+        };
+        let name = function_name(fun);
+        if !ORDERED_AGGREGATES.contains(&name.as_str()) {
+            return Ok(());
+        }
+
+        let mut args = function_args(fun).into_iter().cloned().collect::<Vec<_>>();
+        let mut keys = match args.last() {
+            Some(ast::Expr::Function(order_by))
+                if function_name(order_by) == AGGREGATE_ORDER_BY_FUNCTION =>
+            {
+                function_args(order_by).into_iter().cloned().collect()
+            }
+            _ => vec![],
+        };
+        if !keys.is_empty() {
+            args.pop();
+        }
+        // listagg(value) concatenates values without a delimiter
+        if name == "listagg" && args.len() == 1 {
+            args.push(ast::Expr::Value(ast::Value::SingleQuotedString(
+                "".to_string(),
+            )));
+        }
+
+        let (function, arity) = match name.as_str() {
+            "string_agg" | "listagg" if fun.distinct => (STRING_AGG_DISTINCT_FUNCTION, 2),
+            "string_agg" | "listagg" => ("string_agg", 2),
+            "array_agg" if fun.distinct => (ARRAY_AGG_DISTINCT_FUNCTION, 1),
+            "array_agg" => ("array_agg", 1),
+            "percentile_cont" | "percentile_disc" if keys.is_empty() => {
+                return Err(CubeError::user(format!(
+                    "WITHIN GROUP is required for ordered-set aggregate {}",
+                    name
+                )))
+            }
+            "percentile_cont" if keys.len() == 2 => ("percentile_cont", 1),
+            "percentile_disc" if keys.len() == 2 => ("percentile_disc", 1),
+            // median(x) is percentile_cont(0.5) WITHIN GROUP (ORDER BY x)
+            "median" if keys.is_empty() && args.len() == 1 => {
+                keys = vec![
+                    args.remove(0),
+                    ast::Expr::Value(ast::Value::SingleQuotedString("asc nulls last".to_string())),
+                ];
+                args.push(ast::Expr::Value(ast::Value::Number(
+                    "0.5".to_string(),
+                    false,
+                )));
+                ("percentile_cont", 1)
+            }
+            _ => (name.as_str(), 0),
+        };
+        if args.len() != arity {
+            return Err(CubeError::user(format!(
+                "Wrong number of arguments of {}()",
+                name
+            )));
+        }
+
+        args.extend(keys);
+        *expr = function_call(function.to_string(), args);
+
+        Ok(())
+    }
+}
+
+/// `ORDER BY` and DISTINCT of string_agg() and array_agg() and `WITHIN GROUP` of percentile_cont()
+/// and percentile_disc() are evaluated by their aggregates: keys of `__order_by()` of the parser
+/// are passed after arguments with their directions, DISTINCT aggregates are `__*_distinct()`.
+/// listagg() is string_agg() with an empty delimiter by default, median() is percentile_cont().
+pub fn rewrite_ordered_aggregates(stmt: &mut ast::Statement) -> Result<(), CubeError> {
+    OrderedAggregateRewriter {}.visit_statement(stmt)
+}
+
+#[derive(Debug, Default)]
+struct WindowFunctionFinder {
+    found: bool,
+}
+
+impl<'ast> Visitor<'ast> for WindowFunctionFinder {
+    fn visit_function(&mut self, fun: &mut ast::Function) -> Result<(), CubeError> {
+        if fun.over.is_some() {
+            self.found = true;
+
+            return Ok(());
+        }
+
+        for arg in fun.args.iter_mut() {
+            match arg {
+                ast::FunctionArg::Named { arg, .. } => self.visit_expr(arg)?,
+                ast::FunctionArg::Unnamed(arg) => self.visit_expr(arg)?,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+fn has_window_function(expr: &ast::Expr) -> bool {
+    let mut finder = WindowFunctionFinder::default();
+    // Expressions without placeholders can't fail
+    finder.visit_expr(&mut expr.clone()).ok();
+
+    finder.found
+}
+
+/// Whether the projection of `select` uses window functions (`ROW_NUMBER() OVER (...)`)
+pub fn has_window_functions(select: &ast::Select) -> bool {
+    select.projection.iter().any(|item| match item {
+        ast::SelectItem::UnnamedExpr(expr) | ast::SelectItem::ExprWithAlias { expr, .. } => {
+            has_window_function(expr)
+        }
+        _ => false,
+    })
+}
+
+/// Finds ordered aggregates and `__order_by()` of their keys
+#[derive(Debug, Default)]
+struct OrderedAggregateFinder {
+    found: bool,
+}
+
+impl<'ast> Visitor<'ast> for OrderedAggregateFinder {
+    fn visit_function(&mut self, fun: &mut ast::Function) -> Result<(), CubeError> {
+        let name = function_name(fun);
+        if fun.over.is_none()
+            && (ORDERED_AGGREGATES.contains(&name.as_str()) || name == AGGREGATE_ORDER_BY_FUNCTION)
+        {
+            self.found = true;
+
+            return Ok(());
+        }
+
+        for arg in fun.args.iter_mut() {
+            match arg {
+                ast::FunctionArg::Named { arg, .. } => self.visit_expr(arg)?,
+                ast::FunctionArg::Unnamed(arg) => self.visit_expr(arg)?,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+fn has_ordered_aggregate(expr: &ast::Expr) -> bool {
+    let mut finder = OrderedAggregateFinder::default();
+    finder.visit_expr(&mut expr.clone()).ok();
+
+    finder.found
+}
+
+/// Whether the projection of `select` uses aggregates which are evaluated by DataFusion
+/// (`percentile_cont(0.5) WITHIN GROUP (ORDER BY x)`), see `rewrite_ordered_aggregates`
+pub fn has_ordered_aggregates(select: &ast::Select) -> bool {
+    select.projection.iter().any(|item| match item {
+        ast::SelectItem::UnnamedExpr(expr) | ast::SelectItem::ExprWithAlias { expr, .. } => {
+            has_ordered_aggregate(expr)
+        }
+        _ => false,
+    })
+}
+
+/// Replaces expressions without window functions (or ordered aggregates) by columns of the
+/// members subquery, see `split_window_functions`
+#[derive(Debug, Default)]
+struct WindowMemberExtractor {
+    aggregates: bool,
+    members: Vec<ast::SelectItem>,
+    // Text of the expression and the name of its column in the subquery
+    names: Vec<(String, ast::Ident)>,
+}
+
+impl WindowMemberExtractor {
+    fn member(&mut self, expr: &ast::Expr) -> ast::Ident {
+        let key = expr.to_string();
+        if let Some((_, name)) = self.names.iter().find(|(k, _)| k == &key) {
+            return name.clone();
+        }
+
+        let column = match expr {
+            ast::Expr::Identifier(ident) => Some(ident.clone()),
+            ast::Expr::CompoundIdentifier(idents) => idents.last().cloned(),
+            _ => None,
+        };
+        let name = match column {
+            Some(column)
+                if !self
+                    .names
+                    .iter()
+                    .any(|(_, name)| name.value == column.value) =>
+            {
+                column
+            }
+            _ => ast::Ident::new(format!("__window_member_{}", self.names.len() + 1)),
+        };
+
+        self.members.push(match expr {
+            ast::Expr::Identifier(ident) if ident == &name => {
+                ast::SelectItem::UnnamedExpr(expr.clone())
+            }
+            _ => ast::SelectItem::ExprWithAlias {
+                expr: expr.clone(),
+                alias: name.clone(),
+            },
+        });
+        self.names.push((key, name.clone()));
+
+        name
+    }
+}
+
+impl<'ast> Visitor<'ast> for WindowMemberExtractor {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        if has_window_function(expr) || (self.aggregates && has_ordered_aggregate(expr)) {
+            return self.walk_expr(expr);
+        }
+
+        match expr {
+            ast::Expr::Value(_) | ast::Expr::Wildcard => {}
+            _ => *expr = ast::Expr::Identifier(self.member(expr)),
+        };
+
+        Ok(())
+    }
+}
+
+/// Window functions can't be expressed by Cube queries. The query is split into a subquery,
+/// which selects members used by the projection and window specifications with the original
+/// filters and grouping (it's rewritten into a Cube query), and the outer query, where
+/// DataFusion evaluates window functions. ORDER BY, LIMIT and OFFSET are applied after window
+/// functions, as they are in SQL.
+pub fn split_window_functions(query: &ast::Query) -> Result<ast::Query, CubeError> {
+    split_members(query, false)
+}
+
+/// Ordered aggregates are split as window functions, but the subquery is grouped by all its
+/// dimensions and the outer query aggregates its rows by the original grouping
+pub fn split_ordered_aggregates(query: &ast::Query) -> Result<ast::Query, CubeError> {
+    split_members(query, true)
+}
+
+fn split_members(query: &ast::Query, aggregates: bool) -> Result<ast::Query, CubeError> {
+    let functions = if aggregates {
+        "ordered aggregates"
+    } else {
+        "window functions"
+    };
+    let select = match &query.body {
+        ast::SetExpr::Select(select) => select,
+        _ => {
+            return Err(CubeError::internal(format!(
+                "Expressions of {} can be split only from SELECT",
+                functions
+            )))
+        }
+    };
+    let table_alias = match select.from.first().map(|from| &from.relation) {
+        Some(ast::TableFactor::Table { name, alias, .. }) => match alias {
+            Some(alias) => alias.name.clone(),
+            None => name
+                .0
+                .last()
+                .cloned()
+                .unwrap_or_else(|| ast::Ident::new("t")),
+        },
+        _ => {
+            return Err(CubeError::internal(format!(
+                "Expressions of {} can be split only from a table",
+                functions
+            )))
+        }
+    };
+
+    let mut extractor = WindowMemberExtractor {
+        aggregates,
+        ..WindowMemberExtractor::default()
+    };
+    let mut outer_select = select.clone();
+    let mut output_names = vec![];
+    for item in outer_select.projection.iter_mut() {
+        match item {
+            // Columns are named as they are without the split
+            ast::SelectItem::UnnamedExpr(expr) => {
+                let alias = match expr {
+                    ast::Expr::Identifier(ident) => ident.clone(),
+                    ast::Expr::CompoundIdentifier(idents) if !idents.is_empty() => {
+                        idents[idents.len() - 1].clone()
+                    }
+                    expr => ast::Ident::with_quote('"', expr.to_string()),
+                };
+                let mut expr = expr.clone();
+                extractor.visit_expr(&mut expr)?;
+
+                output_names.push(alias.clone());
+                *item = ast::SelectItem::ExprWithAlias { expr, alias };
+            }
+            ast::SelectItem::ExprWithAlias { expr, alias } => {
+                extractor.visit_expr(expr)?;
+                output_names.push(alias.clone());
+            }
+            _ => {
+                return Err(CubeError::user(format!(
+                    "Wildcards can't be selected with {}",
+                    functions
+                )))
+            }
+        }
+    }
+
+    let mut outer = query.clone();
+    for order_by in outer.order_by.iter_mut() {
+        match &order_by.expr {
+            // Columns of the projection and positions are kept as is
+            ast::Expr::Identifier(ident)
+                if output_names.iter().any(|name| name.value == ident.value) => {}
+            ast::Expr::Value(_) => {}
+            _ => extractor.visit_expr(&mut order_by.expr)?,
+        };
+    }
+
+    let mut inner_select = select.clone();
+    // Positions of GROUP BY don't match columns of the subquery
+    for group_by in inner_select.group_by.iter_mut() {
+        if let ast::Expr::Value(ast::Value::Number(position, _)) = group_by {
+            let item = position
+                .parse::<usize>()
+                .ok()
+                .and_then(|position| select.projection.get(position.wrapping_sub(1)));
+            match item {
+                Some(ast::SelectItem::UnnamedExpr(expr))
+                | Some(ast::SelectItem::ExprWithAlias { expr, .. }) => *group_by = expr.clone(),
+                _ => {}
+            }
+        }
+    }
+
+    // Ordered aggregates are grouped by the outer query, measures of the subquery are
+    // aggregated by Cube
+    let mut outer_group_by = vec![];
+    let mut outer_having = None;
+    if aggregates {
+        for group_by in inner_select.group_by.iter() {
+            let mut group_by = group_by.clone();
+            extractor.visit_expr(&mut group_by)?;
+            outer_group_by.push(group_by);
+        }
+        if let Some(having) = &select.having {
+            let mut having = having.clone();
+            extractor.visit_expr(&mut having)?;
+            outer_having = Some(having);
+        }
+
+        inner_select.group_by = extractor
+            .members
+            .iter()
+            .filter_map(|member| match member {
+                ast::SelectItem::UnnamedExpr(expr)
+                | ast::SelectItem::ExprWithAlias { expr, .. }
+                    if !matches!(expr, ast::Expr::Function(_)) =>
+                {
+                    Some(expr.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        inner_select.having = None;
+    }
+    inner_select.projection = extractor.members;
+    inner_select.distinct = false;
+    let mut inner = query.clone();
+    inner.body = ast::SetExpr::Select(inner_select);
+    inner.order_by = vec![];
+    inner.limit = None;
+    inner.offset = None;
+
+    outer_select.from = vec![ast::TableWithJoins {
+        relation: ast::TableFactor::Derived {
+            lateral: false,
+            subquery: Box::new(inner),
+            alias: Some(ast::TableAlias {
+                name: table_alias,
+                columns: vec![],
+            }),
+        },
+        joins: vec![],
+    }];
+    outer_select.selection = None;
+    outer_select.group_by = outer_group_by;
+    outer_select.having = outer_having;
+    outer.body = ast::SetExpr::Select(outer_select);
+
+    Ok(outer)
+}
+
+#[derive(Debug)]
+struct RegexpSubstringRewriter {}
+
+impl<'ast> Visitor<'ast> for RegexpSubstringRewriter {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)?;
+
+        let call = match expr {
+            ast::Expr::Substring {
+                expr: string,
+                substring_from: Some(pattern),
+                substring_for: None,
+            } if matches!(
+                pattern.as_ref(),
+                ast::Expr::Value(ast::Value::SingleQuotedString(_))
+            ) =>
+            {
+                function_call(
+                    REGEXP_SUBSTRING_FUNCTION.to_string(),
+                    vec![string.as_ref().clone(), pattern.as_ref().clone()],
+                )
+            }
+            _ => return Ok(()),
+        };
+        *expr = call;
+
+        Ok(())
+    }
+}
+
+/// As in PostgreSQL, `substring(string FROM 'pattern')` (or `substring(string, 'pattern')`)
+/// with a text pattern returns the match of the regular expression, it's regexp_substring()
+pub fn rewrite_regexp_substrings(stmt: &mut ast::Statement) -> Result<(), CubeError> {
+    RegexpSubstringRewriter {}.visit_statement(stmt)
+}
+
+#[derive(Debug)]
+struct JsonCastRewriter {}
+
+impl<'ast> Visitor<'ast> for JsonCastRewriter {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)?;
+
+        let function = match expr {
+            ast::Expr::Cast {
+                data_type: ast::DataType::Custom(name),
+                ..
+            } => match name.to_string().to_lowercase().as_str() {
+                "json" => JSON_CAST_FUNCTION,
+                "jsonb" => JSONB_CAST_FUNCTION,
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        if let ast::Expr::Cast { expr: value, .. } = expr {
+            let text = ast::Expr::Cast {
+                expr: value.clone(),
+                data_type: ast::DataType::Text,
+            };
+            *expr = function_call(function.to_string(), vec![text]);
+        }
+
+        Ok(())
+    }
+}
+
+/// `x::json` and `CAST(x AS jsonb)` are not planned by DataFusion, they are rewritten into
+/// functions which validate the text of `x` (and normalize it for jsonb)
+pub fn rewrite_json_casts(stmt: &mut ast::Statement) -> Result<(), CubeError> {
+    JsonCastRewriter {}.visit_statement(stmt)
+}
+
+/// Aggregate and condition of `__filter(aggregate, condition)` of the parser
+fn aggregate_filter(expr: &ast::Expr) -> Option<(&ast::Function, &ast::Expr)> {
+    let fun = match expr {
+        ast::Expr::Function(fun) if function_name(fun) == AGGREGATE_FILTER_FUNCTION => fun,
+        _ => return None,
+    };
+
+    match fun.args.as_slice() {
+        [ast::FunctionArg::Unnamed(ast::Expr::Function(aggregate)), ast::FunctionArg::Unnamed(condition)] => {
+            Some((aggregate, condition))
+        }
+        _ => None,
+    }
+}
+
+/// Arguments of filtered aggregates are NULL for rows which don't match the condition
+fn filtered_argument(arg: &mut ast::Expr, condition: &ast::Expr) {
+    let value = match arg {
+        ast::Expr::Value(_) => return,
+        ast::Expr::Wildcard => ast::Expr::Value(ast::Value::Number("1".to_string(), false)),
+        arg => arg.clone(),
+    };
+
+    *arg = ast::Expr::Case {
+        operand: None,
+        conditions: vec![condition.clone()],
+        results: vec![value],
+        else_result: None,
+    };
+}
+
+#[derive(Debug)]
+struct AggregateFilterRewriter {}
+
+impl<'ast> Visitor<'ast> for AggregateFilterRewriter {
+    fn visit_expr(&mut self, expr: &mut ast::Expr) -> Result<(), CubeError> {
+        self.walk_expr(expr)?;
+
+        let (mut aggregate, condition) = match aggregate_filter(expr) {
+            Some((aggregate, condition)) => (aggregate.clone(), condition.clone()),
+            None => return Ok(()),
+        };
+        // NULL values are collected by these aggregates
+        let name = function_name(&aggregate);
+        if matches!(
+            name.as_str(),
+            "array_agg" | ARRAY_AGG_DISTINCT_FUNCTION | "json_agg" | "jsonb_agg"
+        ) {
+            return Err(CubeError::user(format!(
+                "FILTER is not supported by {}()",
+                name.trim_start_matches("__").trim_end_matches("_distinct")
+            )));
+        }
+
+        for arg in aggregate.args.iter_mut() {
+            match arg {
+                ast::FunctionArg::Named { arg, .. } | ast::FunctionArg::Unnamed(arg) => {
+                    filtered_argument(arg, &condition)
+                }
+            }
+        }
+        *expr = ast::Expr::Function(aggregate);
+
+        Ok(())
+    }
+}
+
+/// `agg(x) FILTER (WHERE condition)`, which is `__filter(agg(x), condition)` of the parser, is
+/// the conditional aggregation `agg(CASE WHEN condition THEN x END)`
+pub fn rewrite_aggregate_filters(stmt: &mut ast::Statement) -> Result<(), CubeError> {
+    AggregateFilterRewriter {}.visit_statement(stmt)
+}
+
+/// Finds `__filter()` of filtered aggregates and other aggregates
+#[derive(Debug, Default)]
+struct AggregateFinder {
+    filters: bool,
+    aggregates: bool,
+}
+
+impl<'ast> Visitor<'ast> for AggregateFinder {
+    fn visit_function(&mut self, fun: &mut ast::Function) -> Result<(), CubeError> {
+        match function_name(fun).as_str() {
+            AGGREGATE_FILTER_FUNCTION => self.filters = true,
+            "count" | "sum" | "avg" | "min" | "max" | "measure" => self.aggregates = true,
+            name if ORDERED_AGGREGATES.contains(&name) => self.aggregates = true,
+            _ => {}
+        };
+
+        for arg in fun.args.iter_mut() {
+            match arg {
+                ast::FunctionArg::Named { arg, .. } => self.visit_expr(arg)?,
+                ast::FunctionArg::Unnamed(arg) => self.visit_expr(arg)?,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+fn find_aggregates(expr: &ast::Expr) -> AggregateFinder {
+    let mut finder = AggregateFinder::default();
+    finder.visit_expr(&mut expr.clone()).ok();
+
+    finder
+}
+
+/// Whether the projection of `select` uses filtered aggregates (`COUNT(*) FILTER (WHERE ...)`)
+pub fn has_aggregate_filters(select: &ast::Select) -> bool {
+    select.projection.iter().any(|item| match item {
+        ast::SelectItem::UnnamedExpr(expr) | ast::SelectItem::ExprWithAlias { expr, .. } => {
+            find_aggregates(expr).filters
+        }
+        _ => false,
+    })
+}
+
+/// When every aggregate of the projection and ORDER BY is filtered by the same condition, the
+/// condition is a filter of the query (or a segment), which is the Cube query of measures
+/// without FILTER. Unlike FILTER, groups without matching rows aren't returned. None if
+/// aggregates can't be pushed down.
+pub fn push_down_aggregate_filters(query: &ast::Query) -> Option<ast::Query> {
+    let mut query = query.clone();
+    let mut select = match &query.body {
+        ast::SetExpr::Select(select) if select.having.is_none() => select.as_ref().clone(),
+        _ => return None,
+    };
+
+    let items = select.projection.iter_mut().filter_map(|item| match item {
+        ast::SelectItem::UnnamedExpr(expr) | ast::SelectItem::ExprWithAlias { expr, .. } => {
+            Some(expr)
+        }
+        _ => None,
+    });
+    let order_by = query.order_by.iter_mut().map(|order_by| &mut order_by.expr);
+    let mut condition = None;
+    for expr in items.chain(order_by) {
+        let filtered = aggregate_filter(expr)
+            .map(|(aggregate, condition)| (aggregate.clone(), condition.clone()));
+        match filtered {
+            Some((aggregate, item_condition)) => {
+                if condition.get_or_insert_with(|| item_condition.clone()) != &item_condition
+                    || find_aggregates(&ast::Expr::Function(aggregate.clone())).filters
+                {
+                    return None;
+                }
+
+                *expr = ast::Expr::Function(aggregate);
+            }
+            None => {
+                let finder = find_aggregates(expr);
+                if finder.filters || finder.aggregates {
+                    return None;
+                }
+            }
+        }
+    }
+
+    let condition = condition?;
+    select.selection = Some(match select.selection.take() {
+        Some(selection) => ast::Expr::BinaryOp {
+            left: Box::new(ast::Expr::Nested(Box::new(selection))),
+            op: ast::BinaryOperator::And,
+            right: Box::new(ast::Expr::Nested(Box::new(condition))),
+        },
+        None => condition,
+    });
+    query.body = ast::SetExpr::Select(Box::new(select));
+
+    Some(query)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
     use super::*;
-    use crate::CubeError;
+    use crate::{
+        compile::parser::{parse_sql_to_statement, MySqlDialectWithBackTicks},
+        sql::session::DatabaseProtocol,
+    };
     use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+    use test::Bencher;
+
+    fn test_binder(input: &str, output: &str, values: Vec<BindValue>) -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+
+        let mut binder = StatementBinder::new(values);
+        let mut input = stmts[0].clone();
+        binder.bind(&mut input)?;
+
+        assert_eq!(input.to_string(), output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_named() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT ?",
+            "SELECT 'test'",
+            vec![BindValue::String("test".to_string())],
+        )?;
+
+        // binary op
+        test_binder(
+            r#"
+                SELECT *
+                FROM testdata
+                WHERE fieldA = $1 AND fieldB = $2 OR (fieldC = $3 AND fieldD = $4)
+            "#,
+            "SELECT * FROM testdata WHERE fieldA = 'test' AND fieldB = 1 OR (fieldC = 2 AND fieldD = 2)",
+            vec![
+                BindValue::String("test".to_string()),
+                BindValue::Int64(1),
+                BindValue::UInt64(2),
+                BindValue::Float64(2.0),
+                BindValue::Bool(true),
+            ],
+        )?;
+
+        // IN
+        test_binder(
+            r#"
+                SELECT *
+                FROM testdata
+                WHERE fieldA IN ($1, $2)
+            "#,
+            "SELECT * FROM testdata WHERE fieldA IN ('test1', 'test2')",
+            vec![
+                BindValue::String("test1".to_string()),
+                BindValue::String("test2".to_string()),
+            ],
+        )?;
+
+        // BETWEEN
+        test_binder(
+            r#"
+                SELECT *
+                FROM testdata
+                WHERE fieldA BETWEEN $1 AND $2
+            "#,
+            "SELECT * FROM testdata WHERE fieldA BETWEEN 'test1' AND 'test2'",
+            vec![
+                BindValue::String("test1".to_string()),
+                BindValue::String("test2".to_string()),
+            ],
+        )?;
+
+        test_binder(
+            r#"
+                SELECT *
+                FROM testdata
+                WHERE fieldA = $1
+                UNION ALL
+                SELECT *
+                FROM testdata
+                WHERE fieldA = $2
+            "#,
+            "SELECT * FROM testdata WHERE fieldA = 'test1' UNION ALL SELECT * FROM testdata WHERE fieldA = 'test2'",
+            vec![
+                BindValue::String(
+                    "test1".to_string(),
+                ),
+                BindValue::String(
+                    "test2".to_string(),
+                ),
+            ]
+        )?;
+
+        test_binder(
+            r#"
+                SELECT * FROM (
+                    SELECT *
+                    FROM testdata
+                    WHERE fieldA = $1
+                )
+            "#,
+            "SELECT * FROM (SELECT * FROM testdata WHERE fieldA = 'test1')",
+            vec![BindValue::String("test1".to_string())],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_query_clauses() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT a FROM testdata ORDER BY $1 LIMIT $2 OFFSET $3",
+            "SELECT a FROM testdata ORDER BY 1 LIMIT 100 OFFSET 200",
+            vec![
+                BindValue::Int64(1),
+                BindValue::Int64(100),
+                BindValue::Int64(200),
+            ],
+        )?;
+
+        test_binder(
+            "SELECT $1::text AS label, a FROM testdata GROUP BY a HAVING COUNT(*) > $2",
+            "SELECT CAST('total' AS TEXT) AS label, a FROM testdata GROUP BY a HAVING COUNT(*) > 5",
+            vec![BindValue::String("total".to_string()), BindValue::Int64(5)],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_case_unary() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT CASE WHEN fieldA = $1 THEN $2 WHEN fieldA = $3 THEN $4 ELSE $5 END FROM testdata",
+            "SELECT CASE WHEN fieldA = 'a' THEN 1 WHEN fieldA = 'b' THEN 2 ELSE 0 END FROM testdata",
+            vec![
+                BindValue::String("a".to_string()),
+                BindValue::Int64(1),
+                BindValue::String("b".to_string()),
+                BindValue::Int64(2),
+                BindValue::Int64(0),
+            ],
+        )?;
+
+        test_binder(
+            "SELECT CASE $1 WHEN 1 THEN 'one' END, -$2, NOT $3 FROM testdata WHERE fieldA IN ($4, $5)",
+            "SELECT CASE 1 WHEN 1 THEN 'one' END, - 5, NOT true FROM testdata WHERE fieldA IN ('x', 'y')",
+            vec![
+                BindValue::Int64(1),
+                BindValue::Int64(5),
+                BindValue::Bool(true),
+                BindValue::String("x".to_string()),
+                BindValue::String("y".to_string()),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_subquery() -> Result<(), CubeError> {
+        // Correlated subquery in the projection
+        test_binder(
+            r#"
+                SELECT id, (SELECT max(x) FROM t2 WHERE t2.fk = t1.id AND t2.k = $1) AS m
+                FROM t1
+                WHERE t1.status = $2
+            "#,
+            "SELECT id, (SELECT max(x) FROM t2 WHERE t2.fk = t1.id AND t2.k = 5) AS m FROM t1 WHERE t1.status = 'active'",
+            vec![BindValue::Int64(5), BindValue::String("active".to_string())],
+        )?;
+
+        test_binder(
+            "SELECT * FROM t1 WHERE id IN (SELECT fk FROM t2 WHERE k = $1) AND EXISTS (SELECT 1 FROM t3 WHERE k = $2)",
+            "SELECT * FROM t1 WHERE id IN (SELECT fk FROM t2 WHERE k = 1) AND EXISTS (SELECT 1 FROM t3 WHERE k = 2)",
+            vec![BindValue::Int64(1), BindValue::Int64(2)],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_cte() -> Result<(), CubeError> {
+        test_binder(
+            r#"
+                WITH t AS (SELECT id, x FROM t1 WHERE k = $1), u AS (SELECT id FROM t2 WHERE k = $2)
+                SELECT * FROM t JOIN u ON t.id = u.id WHERE t.x = $3
+            "#,
+            "WITH t AS (SELECT id, x FROM t1 WHERE k = 1), u AS (SELECT id FROM t2 WHERE k = 2) SELECT * FROM t JOIN u ON t.id = u.id WHERE t.x = 'a'",
+            vec![
+                BindValue::Int64(1),
+                BindValue::Int64(2),
+                BindValue::String("a".to_string()),
+            ],
+        )?;
+
+        // Positional placeholders are numbered in the order they are written
+        let stmts = Parser::parse_sql(
+            &MySqlDialectWithBackTicks {},
+            "WITH t AS (SELECT * FROM t1 WHERE k = ?) SELECT * FROM t WHERE x = ?",
+        )
+        .unwrap();
+        let mut stmt = stmts[0].clone();
+        StatementBinder::new(vec![BindValue::Int64(1), BindValue::Int64(2)]).bind(&mut stmt)?;
+        assert_eq!(
+            stmt.to_string(),
+            "WITH t AS (SELECT * FROM t1 WHERE k = 1) SELECT * FROM t WHERE x = 2"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_bools_in_list() -> Result<(), CubeError> {
+        let bind = |dialect: &dyn Dialect,
+                    input: &str,
+                    bools_as_integers: bool|
+         -> Result<String, CubeError> {
+            let mut stmt = Parser::parse_sql(dialect, input).unwrap()[0].clone();
+
+            StatementBinder::new(vec![
+                BindValue::Bool(true),
+                BindValue::Bool(false),
+                BindValue::Bool(true),
+            ])
+            .with_options(BinderOptions {
+                bools_as_integers,
+                ..BinderOptions::default()
+            })
+            .bind(&mut stmt)?;
+
+            Ok(stmt.to_string())
+        };
+
+        assert_eq!(
+            bind(
+                &PostgreSqlDialect {},
+                "SELECT * FROM testdata WHERE flag IN ($1, $2) AND other = $3",
+                false
+            )?,
+            "SELECT * FROM testdata WHERE flag IN (true, false) AND other = true"
+        );
+
+        assert_eq!(
+            bind(
+                &MySqlDialectWithBackTicks {},
+                "SELECT * FROM testdata WHERE flag IN (?, ?) AND other = ?",
+                true
+            )?,
+            "SELECT * FROM testdata WHERE flag IN (1, 0) AND other = 1"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_max_in_list_size() -> Result<(), CubeError> {
+        let bind = |input: &str, values: Vec<BindValue>| -> Result<String, CubeError> {
+            let mut stmt = Parser::parse_sql(&PostgreSqlDialect {}, input).unwrap()[0].clone();
+
+            StatementBinder::new(values)
+                .with_options(BinderOptions {
+                    max_in_list_size: Some(2),
+                    ..BinderOptions::default()
+                })
+                .bind(&mut stmt)?;
+
+            Ok(stmt.to_string())
+        };
+
+        assert_eq!(
+            bind(
+                "SELECT * FROM testdata WHERE fieldA IN ($1, $2)",
+                vec![BindValue::Int64(1), BindValue::Int64(2)]
+            )?,
+            "SELECT * FROM testdata WHERE fieldA IN (1, 2)"
+        );
+
+        assert_eq!(
+            bind(
+                "SELECT * FROM testdata WHERE fieldA = 1 OR fieldB IN ($1, $2, 3)",
+                vec![BindValue::Int64(1), BindValue::Int64(2)]
+            )
+            .unwrap_err()
+            .message,
+            "IN list has 3 elements, which exceeds the limit of 2"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_value_types() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT * FROM testdata WHERE a = $1 AND b = $2 AND c = $3 AND d = $4 AND e = $5",
+            "SELECT * FROM testdata WHERE a = -1 AND b = -2.5 AND c = 12345678901234567890.123 AND d = NULL AND e = 0.5",
+            vec![
+                BindValue::Int64(-1),
+                BindValue::Float64(-2.5),
+                BindValue::Numeric("12345678901234567890.123".to_string()),
+                BindValue::Null,
+                BindValue::Float64(0.5),
+            ],
+        )?;
+
+        // NULL is accepted for parameters of any type
+        let mut stmt = Parser::parse_sql(&PostgreSqlDialect {}, "SELECT $1, $2").unwrap()[0].clone();
+        StatementBinder::with_types(
+            vec![BindValue::Null, BindValue::Numeric("1.50".to_string())],
+            vec![Some(PgTypeId::Int4), Some(PgTypeId::Numeric)],
+        )
+        .bind(&mut stmt)?;
+        assert_eq!(stmt.to_string(), "SELECT NULL, 1.50");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_temporal_values() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT * FROM testdata WHERE a >= $1 AND b < $2 AND c > $3 AND d > NOW() - $4",
+            "SELECT * FROM testdata WHERE a >= DATE '2022-03-01' AND b < TIMESTAMP '2022-03-01 10:30:00.250' AND c > CAST('2022-03-01T10:30:00Z' AS TIMESTAMPTZ) AND d > NOW() - INTERVAL '1 day'",
+            vec![
+                BindValue::Date(NaiveDate::from_ymd(2022, 3, 1)),
+                BindValue::Timestamp(NaiveDate::from_ymd(2022, 3, 1).and_hms_milli(10, 30, 0, 250)),
+                BindValue::TimestampTz(DateTime::from_utc(
+                    NaiveDate::from_ymd(2022, 3, 1).and_hms(10, 30, 0),
+                    Utc,
+                )),
+                BindValue::Interval("1 day".to_string()),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_cast_interval() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT CAST($1 AS interval)",
+            "SELECT CAST('1 day' AS INTERVAL)",
+            vec![BindValue::String("1 day".to_string())],
+        )?;
+
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA > NOW() - CAST($1 AS interval)",
+            "SELECT * FROM testdata WHERE fieldA > NOW() - CAST('2 hours' AS INTERVAL)",
+            vec![BindValue::String("2 hours".to_string())],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_cast_custom_type() -> Result<(), CubeError> {
+        test_binder(
+            r#"SELECT CAST($1 AS myschema.mytype), $2::"MySchema"."MyType""#,
+            r#"SELECT CAST('a' AS myschema.mytype), CAST('b' AS "MySchema"."MyType")"#,
+            vec![
+                BindValue::String("a".to_string()),
+                BindValue::String("b".to_string()),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_extract() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT EXTRACT(YEAR FROM CAST($1 AS TIMESTAMP)), EXTRACT(SECOND FROM $2)",
+            "SELECT EXTRACT(YEAR FROM CAST('2022-03-01 10:00:00' AS TIMESTAMP)), EXTRACT(SECOND FROM '2022-03-01 10:00:05')",
+            vec![
+                BindValue::String("2022-03-01 10:00:00".to_string()),
+                BindValue::String("2022-03-01 10:00:05".to_string()),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_cast_chain() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = $1::text::int",
+            "SELECT * FROM testdata WHERE fieldA = CAST(CAST('5' AS TEXT) AS INT)",
+            vec![BindValue::String("5".to_string())],
+        )?;
+
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = ($1::text)::int",
+            "SELECT * FROM testdata WHERE fieldA = CAST((CAST('5' AS TEXT)) AS INT)",
+            vec![BindValue::String("5".to_string())],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_trait_object() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = $1",
+        )
+        .unwrap();
+
+        let mut binder: Box<dyn Binder> = Box::new(StatementBinder::new(vec![BindValue::String(
+            "test".to_string(),
+        )]));
+        let mut stmt = stmts[0].clone();
+        binder.bind(&mut stmt)?;
+
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT * FROM testdata WHERE fieldA = 'test'"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_missing_value() {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = $1 AND fieldB = $2",
+        )
+        .unwrap();
+
+        let mut binder = StatementBinder::new(vec![BindValue::Int64(1)]);
+        let mut stmt = stmts[0].clone();
+        let err = binder.bind(&mut stmt).unwrap_err();
+
+        assert_eq!(err.message, "Unable to find value for parameter $2");
+    }
+
+    #[test]
+    fn test_binder_window_spec() -> Result<(), CubeError> {
+        test_binder(
+            r#"
+                SELECT SUM(amount) OVER (PARTITION BY COALESCE(region, $1) ORDER BY ts)
+                FROM testdata
+            "#,
+            "SELECT SUM(amount) OVER (PARTITION BY COALESCE(region, 'n/a') ORDER BY ts) FROM testdata",
+            vec![BindValue::String("n/a".to_string())],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_window_functions() -> Result<(), CubeError> {
+        let split = |input: &str| -> Result<String, CubeError> {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+            match &stmts[0] {
+                ast::Statement::Query(query) => Ok(split_window_functions(query)?.to_string()),
+                _ => panic!("{} must be a query", input),
+            }
+        };
+
+        assert_eq!(
+            split(
+                "SELECT customer_gender, COUNT(*) AS cnt, ROW_NUMBER() OVER (ORDER BY COUNT(*) DESC) AS rn FROM KibanaSampleDataEcommerce WHERE customer_gender IS NOT NULL GROUP BY 1 ORDER BY rn LIMIT 10"
+            )?,
+            "SELECT customer_gender AS customer_gender, __window_member_2 AS cnt, ROW_NUMBER() OVER (ORDER BY __window_member_2 DESC) AS rn FROM (SELECT customer_gender, COUNT(*) AS __window_member_2 FROM KibanaSampleDataEcommerce WHERE customer_gender IS NOT NULL GROUP BY customer_gender) AS KibanaSampleDataEcommerce ORDER BY rn LIMIT 10"
+        );
 
-    fn test_binder(input: &str, output: &str, values: Vec<BindValue>) -> Result<(), CubeError> {
-        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+        // Members of window specifications are selected by the subquery, but not by the query
+        assert_eq!(
+            split(
+                "SELECT k.customer_gender, LAG(k.taxful_total_price, 1) OVER (PARTITION BY k.customer_gender ORDER BY k.order_date) FROM KibanaSampleDataEcommerce AS k"
+            )?,
+            "SELECT customer_gender AS customer_gender, LAG(taxful_total_price, 1) OVER (PARTITION BY customer_gender ORDER BY order_date) AS \"LAG(k.taxful_total_price, 1) OVER (PARTITION BY k.customer_gender ORDER BY k.order_date)\" FROM (SELECT k.customer_gender AS customer_gender, k.taxful_total_price AS taxful_total_price, k.order_date AS order_date FROM KibanaSampleDataEcommerce AS k) AS k"
+        );
+
+        assert!(
+            split("SELECT *, ROW_NUMBER() OVER () FROM KibanaSampleDataEcommerce").is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_ordered_aggregates() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT customer_gender, percentile_cont(0.5, __order_by(taxful_total_price, 'asc nulls last')) AS m FROM KibanaSampleDataEcommerce WHERE customer_gender IS NOT NULL GROUP BY 1",
+        )
+        .unwrap();
+        let query = match &stmts[0] {
+            ast::Statement::Query(query) => split_ordered_aggregates(query)?,
+            _ => panic!("Statement must be a query"),
+        };
+
+        // Values are fetched by the subquery, they are aggregated by the original grouping
+        assert_eq!(
+            query.to_string(),
+            "SELECT customer_gender AS customer_gender, percentile_cont(0.5, __order_by(taxful_total_price, 'asc nulls last')) AS m FROM (SELECT customer_gender, taxful_total_price FROM KibanaSampleDataEcommerce WHERE customer_gender IS NOT NULL GROUP BY customer_gender, taxful_total_price) AS KibanaSampleDataEcommerce GROUP BY customer_gender"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_regexp_substrings() -> Result<(), CubeError> {
+        let mut stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT SUBSTRING(name FROM 'a(b+)'), SUBSTRING(name, '[0-9]+'), SUBSTRING(name FROM 2 FOR 3) FROM t",
+        )
+        .unwrap();
+        rewrite_regexp_substrings(&mut stmts[0])?;
+        assert_eq!(
+            stmts[0].to_string(),
+            "SELECT regexp_substring(name, 'a(b+)'), regexp_substring(name, '[0-9]+'), SUBSTRING(name FROM 2 FOR 3) FROM t"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_json_casts() -> Result<(), CubeError> {
+        let mut stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT '{}'::jsonb, CAST(data AS JSON), CAST(1 AS TEXT) FROM t",
+        )
+        .unwrap();
+        rewrite_json_casts(&mut stmts[0])?;
+        assert_eq!(
+            stmts[0].to_string(),
+            "SELECT __jsonb(CAST('{}' AS TEXT)), __json(CAST(data AS TEXT)), CAST(1 AS TEXT) FROM t"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_filters() -> Result<(), CubeError> {
+        let parse = |input: &str| {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, input).unwrap();
+            match &stmts[0] {
+                ast::Statement::Query(query) => query.as_ref().clone(),
+                _ => panic!("Statement must be a query"),
+            }
+        };
+        let push_down =
+            |input: &str| push_down_aggregate_filters(&parse(input)).map(|query| query.to_string());
+
+        assert_eq!(
+            push_down("SELECT status, COUNT(*), SUM(amount) FROM orders GROUP BY 1"),
+            None
+        );
+        assert_eq!(
+            push_down("SELECT status, __filter(COUNT(*), paid = true) AS c, __filter(SUM(amount), paid = true) FROM orders WHERE amount > 1 OR amount IS NULL GROUP BY 1"),
+            Some("SELECT status, COUNT(*) AS c, SUM(amount) FROM orders WHERE (amount > 1 OR amount IS NULL) AND (paid = true) GROUP BY 1".to_string())
+        );
+        assert_eq!(
+            push_down("SELECT status, __filter(COUNT(*), paid = true) AS c FROM orders GROUP BY 1 ORDER BY __filter(COUNT(*), paid = true) DESC"),
+            Some("SELECT status, COUNT(*) AS c FROM orders WHERE paid = true GROUP BY 1 ORDER BY COUNT(*) DESC".to_string())
+        );
+        // Other aggregates need rows which don't match the condition
+        assert_eq!(
+            push_down("SELECT __filter(COUNT(*), paid = true), __filter(COUNT(*), paid = false) FROM orders"),
+            None
+        );
+        assert_eq!(
+            push_down("SELECT __filter(COUNT(*), paid = true), SUM(amount) FROM orders"),
+            None
+        );
+        match &parse("SELECT __filter(COUNT(*), paid = true) + 1 FROM orders").body {
+            ast::SetExpr::Select(select) => assert!(has_aggregate_filters(select)),
+            _ => panic!("Query must be a select"),
+        };
+
+        let mut stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT __filter(COUNT(*), n > 1), __filter(string_agg(s, ', '), n > 1) FROM t",
+        )
+        .unwrap();
+        rewrite_aggregate_filters(&mut stmts[0])?;
+        assert_eq!(
+            stmts[0].to_string(),
+            "SELECT COUNT(CASE WHEN n > 1 THEN 1 END), string_agg(CASE WHEN n > 1 THEN s END, ', ') FROM t"
+        );
+
+        let mut stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT __filter(array_agg(s), n > 1) FROM t",
+        )
+        .unwrap();
+        assert!(rewrite_aggregate_filters(&mut stmts[0]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_decimals() -> Result<(), CubeError> {
+        let rewrite = |input: &str| -> Result<String, CubeError> {
+            let mut stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+            rewrite_decimals(&mut stmts[0], &|name| {
+                if name.to_string().eq_ignore_ascii_case("orders") {
+                    vec![("amount".to_string(), 10, 2)]
+                } else {
+                    vec![]
+                }
+            })?;
+
+            Ok(stmts[0].to_string())
+        };
+
+        assert_eq!(
+            rewrite("SELECT amount * 2, amount + 0.5 AS total FROM orders WHERE amount > 10")?,
+            "SELECT numeric_mul(amount, 2), numeric_add(amount, numeric_cast_1_1('0.5')) AS total FROM orders WHERE numeric_gt(amount, 10)"
+        );
+        // Aggregates of columns of tables are kept for Cube
+        assert_eq!(
+            rewrite("SELECT SUM(amount), AVG(amount * 2) FROM orders")?,
+            "SELECT SUM(amount), numeric_cast_38_16(numeric_avg(numeric_mul(amount, 2))) FROM orders"
+        );
+        assert_eq!(
+            rewrite("SELECT t.total / 3 FROM (SELECT SUM(amount) AS total FROM orders) AS t")?,
+            "SELECT numeric_div(t.total, 3) FROM (SELECT SUM(amount) AS total FROM orders) AS t"
+        );
+        assert_eq!(
+            rewrite("WITH t AS (SELECT amount AS a FROM orders) SELECT MAX(a), -a FROM t")?,
+            "WITH t AS (SELECT amount AS a FROM orders) SELECT numeric_cast_10_2(numeric_max(a)), numeric_sub(0, a) FROM t"
+        );
+        assert_eq!(
+            rewrite("SELECT CAST('1.25' AS NUMERIC(10, 2)) * price FROM products")?,
+            "SELECT numeric_mul(numeric_cast_10_2('1.25'), price) FROM products"
+        );
+        assert_eq!(
+            rewrite("SELECT price * 2 FROM products")?,
+            "SELECT price * 2 FROM products"
+        );
+        assert!(rewrite("SELECT CAST(1 AS NUMERIC(50, 2))").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_timestamptz() -> Result<(), CubeError> {
+        let rewrite = |input: &str| -> Result<String, CubeError> {
+            let mut stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+            rewrite_timestamptz(&mut stmts[0])?;
+
+            Ok(stmts[0].to_string())
+        };
+
+        assert_eq!(
+            rewrite("SELECT date_trunc('day', CAST(ts AS TIMESTAMPTZ)), EXTRACT(HOUR FROM '2022-03-01 10:30:00+00'::timestamptz) FROM t")?,
+            "SELECT timestamptz_trunc('day', to_timestamptz(ts)), timestamptz_part('hour', to_timestamptz('2022-03-01 10:30:00+00')) FROM t"
+        );
+        assert_eq!(
+            rewrite(
+                "SELECT CAST(date_trunc('month', CAST(ts AS TIMESTAMPTZ)) AS TIMESTAMP) FROM t"
+            )?,
+            "SELECT timestamptz_local(timestamptz_trunc('month', to_timestamptz(ts))) FROM t"
+        );
+        // date_trunc of timestamps without time zone is kept for Cube
+        assert_eq!(
+            rewrite("SELECT date_trunc('day', order_date), EXTRACT(YEAR FROM order_date) FROM t")?,
+            "SELECT date_trunc('day', order_date), timestamp_part('year', order_date) FROM t"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_intervals() -> Result<(), CubeError> {
+        let rewrite = |input: &str| -> Result<String, CubeError> {
+            let mut stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+            rewrite_intervals(&mut stmts[0])?;
+
+            Ok(stmts[0].to_string())
+        };
+
+        assert_eq!(
+            rewrite("SELECT * FROM t WHERE ts >= now() - INTERVAL '30 days'")?,
+            "SELECT * FROM t WHERE ts >= date_sub(now(), INTERVAL '30 day')"
+        );
+        assert_eq!(
+            rewrite("SELECT ts - INTERVAL '1 mon -2 days 01:30:00', INTERVAL '1' HOUR + ts FROM t")?,
+            "SELECT date_sub(date_add(date_sub(ts, INTERVAL '1 month'), INTERVAL '2 day'), INTERVAL '1 hour 30 minute'), date_add(ts, INTERVAL '1 hour') FROM t"
+        );
+        assert_eq!(
+            rewrite("SELECT date_add(ts, CAST('2 hours' AS INTERVAL)) FROM t")?,
+            "SELECT date_add(ts, INTERVAL '2 hour') FROM t"
+        );
+        assert!(rewrite("SELECT ts - INTERVAL '30 dayz' FROM t").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_ordered_aggregates() -> Result<(), CubeError> {
+        let rewrite = |input: &str| -> Result<String, CubeError> {
+            let mut stmts = Parser::parse_sql(&PostgreSqlDialect {}, &input).unwrap();
+            rewrite_ordered_aggregates(&mut stmts[0])?;
+
+            Ok(stmts[0].to_string())
+        };
+
+        assert_eq!(
+            rewrite("SELECT string_agg(DISTINCT name, ', ', __order_by(name, 'desc nulls first')), listagg(name), array_agg(id) FROM t")?,
+            "SELECT __string_agg_distinct(name, ', ', name, 'desc nulls first'), string_agg(name, ''), array_agg(id) FROM t"
+        );
+        assert_eq!(
+            rewrite("SELECT percentile_disc(0.9, __order_by(x, 'desc nulls first')), median(y) FROM t")?,
+            "SELECT percentile_disc(0.9, x, 'desc nulls first'), percentile_cont(0.5, y, 'asc nulls last') FROM t"
+        );
+        assert!(rewrite("SELECT string_agg(name) FROM t").is_err());
+        assert!(rewrite("SELECT percentile_cont(0.5) FROM t").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expression_subqueries() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT (SELECT MAX(a) FROM t) AS m FROM t WHERE a IN (SELECT a FROM s WHERE b IN (SELECT b FROM r)) AND NOT EXISTS (SELECT 1 FROM s)",
+        )
+        .unwrap();
+        let mut stmt = stmts[0].clone();
+
+        let subqueries = expression_subqueries(&stmt)?;
+        assert_eq!(
+            subqueries
+                .iter()
+                .map(|(kind, query)| (*kind, query.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                (SubqueryKind::Scalar, "SELECT MAX(a) FROM t".to_string()),
+                (
+                    SubqueryKind::In,
+                    "SELECT a FROM s WHERE b IN (SELECT b FROM r)".to_string()
+                ),
+                (SubqueryKind::Exists, "SELECT 1 FROM s".to_string()),
+            ]
+        );
+
+        let number = |n: &str| ast::Expr::Value(ast::Value::Number(n.to_string(), false));
+        replace_expression_subqueries(
+            &mut stmt,
+            vec![
+                Some(vec![number("5")]),
+                Some(vec![number("1"), number("2")]),
+                None,
+            ],
+        )?;
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT 5 AS m FROM t WHERE a IN (1, 2) AND NOT EXISTS (SELECT 1 FROM s)"
+        );
+
+        // Empty results
+        let mut stmt = stmts[0].clone();
+        replace_expression_subqueries(&mut stmt, vec![Some(vec![]), Some(vec![]), Some(vec![])])?;
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT NULL AS m FROM t WHERE false AND NOT false"
+        );
+
+        let mut stmt = stmts[0].clone();
+        assert!(replace_expression_subqueries(
+            &mut stmt,
+            vec![Some(vec![number("1"), number("2")]), None, None],
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_referenced_columns() -> Result<(), CubeError> {
+        let stmt = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT a, \"B\" FROM t WHERE EXISTS (SELECT 1 FROM s WHERE s.c = T.d)",
+        )
+        .unwrap()
+        .remove(0);
+
+        let column = |qualifier: Option<&str>, name: &str| ColumnReference {
+            qualifier: qualifier.map(|q| q.to_string()),
+            name: name.to_string(),
+        };
+        assert_eq!(
+            referenced_columns(&stmt)?,
+            vec![
+                column(None, "a"),
+                column(None, "B"),
+                column(Some("s"), "c"),
+                column(Some("t"), "d"),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_long_values() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = $1 OR fieldA = $2",
+        )
+        .unwrap();
+
+        let long = "x".repeat(1024);
+        let mut binder = StatementBinder::new(vec![
+            BindValue::String(long.clone()),
+            BindValue::String("short".to_string()),
+        ]);
+        let mut stmt = stmts[0].clone();
+        binder.bind(&mut stmt)?;
+
+        assert_eq!(
+            stmt.to_string(),
+            format!(
+                "SELECT * FROM testdata WHERE fieldA = '{}' OR fieldA = 'short'",
+                long
+            )
+        );
+
+        Ok(())
+    }
+
+    #[derive(Debug, Default)]
+    struct StringCollector {
+        strings: Vec<*const u8>,
+    }
+
+    impl<'ast> Visitor<'ast> for StringCollector {
+        fn visit_value(&mut self, value: &mut ast::Value) -> Result<(), CubeError> {
+            if let ast::Value::SingleQuotedString(v) = value {
+                self.strings.push(v.as_ptr());
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_binder_moves_values() -> Result<(), CubeError> {
+        let mut stmt = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = $1 OR fieldB = $2 OR fieldC = $2",
+        )
+        .unwrap()[0]
+            .clone();
+
+        let (a, b) = ("a".repeat(64), "b".repeat(64));
+        let (a_ptr, b_ptr) = (a.as_ptr(), b.as_ptr());
+        StatementBinder::new(vec![BindValue::String(a), BindValue::String(b)]).bind(&mut stmt)?;
+
+        // Buffers of the values end up in the statement, only the repeated $2 is cloned once
+        let mut collector = StringCollector::default();
+        collector.visit_statement(&mut stmt)?;
+        assert_eq!(collector.strings.len(), 3);
+        assert_eq!(collector.strings[0], a_ptr);
+        assert_ne!(collector.strings[1], b_ptr);
+        assert_eq!(collector.strings[2], b_ptr);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_placeholder_numbers() -> Result<(), CubeError> {
+        // Values are bound by the number of the placeholder, not by its position
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldB = $2 AND fieldA = $1",
+            "SELECT * FROM testdata WHERE fieldB = 'b' AND fieldA = 'a'",
+            vec![
+                BindValue::String("a".to_string()),
+                BindValue::String("b".to_string()),
+            ],
+        )?;
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = $1 OR fieldB = $1 OR fieldC = $2",
+            "SELECT * FROM testdata WHERE fieldA = 'a' OR fieldB = 'a' OR fieldC = 2",
+            vec![BindValue::String("a".to_string()), BindValue::Int64(2)],
+        )?;
+
+        // Values of repeated placeholders are used, they are not reported by strict values
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, "SELECT $2, $1, $2").unwrap();
+        let mut binder = StatementBinder::new(ints(2)).with_options(BinderOptions {
+            strict_values: true,
+            ..BinderOptions::default()
+        });
+        let mut stmt = stmts[0].clone();
+        binder.bind(&mut stmt)?;
+        assert_eq!(stmt.to_string(), "SELECT 2, 1, 2");
+
+        Ok(())
+    }
+
+    #[bench]
+    fn bench_binder_strings(b: &mut Bencher) {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA IN ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .unwrap();
+        let value = "x".repeat(4096);
+
+        b.iter(|| {
+            let mut binder = StatementBinder::new(
+                (0..8)
+                    .map(|_| BindValue::String(value.clone()))
+                    .collect::<Vec<_>>(),
+            );
+            let mut stmt = stmts[0].clone();
+            binder.bind(&mut stmt).unwrap();
+
+            stmt
+        });
+    }
+
+    #[test]
+    fn test_binder_insert() -> Result<(), CubeError> {
+        test_binder(
+            "INSERT INTO testdata (fieldA, fieldB) VALUES ($1, $2)",
+            "INSERT INTO testdata (fieldA, fieldB) VALUES ('test', 1)",
+            vec![BindValue::String("test".to_string()), BindValue::Int64(1)],
+        )?;
+
+        test_binder(
+            "INSERT INTO testdata VALUES ($1), ($2)",
+            "INSERT INTO testdata VALUES ('test1'), ('test2')",
+            vec![
+                BindValue::String("test1".to_string()),
+                BindValue::String("test2".to_string()),
+            ],
+        )?;
+
+        // Expressions in a row
+        test_binder(
+            "INSERT INTO testdata (fieldA, fieldB) VALUES (($1 * $2), $3)",
+            "INSERT INTO testdata (fieldA, fieldB) VALUES ((6 * 7), 'test')",
+            vec![
+                BindValue::Int64(6),
+                BindValue::Int64(7),
+                BindValue::String("test".to_string()),
+            ],
+        )?;
+
+        // Nothing to bind
+        test_binder(
+            "INSERT INTO testdata (fieldA) VALUES (DEFAULT)",
+            "INSERT INTO testdata (fieldA) VALUES (DEFAULT)",
+            vec![],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit_capper() -> Result<(), CubeError> {
+        let cap = |input: &str, values: Vec<BindValue>| -> Result<String, CubeError> {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, input).unwrap();
+            let mut stmt = stmts[0].clone();
+
+            let mut binders: Vec<Box<dyn Binder>> = vec![
+                Box::new(StatementBinder::new(values)),
+                Box::new(LimitCapper::new(1000)),
+            ];
+            for binder in binders.iter_mut() {
+                binder.bind(&mut stmt)?;
+            }
+
+            Ok(stmt.to_string())
+        };
+
+        assert_eq!(
+            cap("SELECT * FROM testdata LIMIT 50000", vec![])?,
+            "SELECT * FROM testdata LIMIT 1000"
+        );
+        assert_eq!(
+            cap("SELECT * FROM testdata LIMIT 10", vec![])?,
+            "SELECT * FROM testdata LIMIT 10"
+        );
+        assert_eq!(
+            cap(
+                "SELECT * FROM testdata LIMIT $1 OFFSET $2",
+                vec![BindValue::Int64(100000), BindValue::Int64(20)]
+            )?,
+            "SELECT * FROM testdata LIMIT 1000 OFFSET 20"
+        );
+        assert_eq!(
+            cap(
+                "SELECT * FROM (SELECT * FROM testdata LIMIT $1) LIMIT 5",
+                vec![BindValue::Int64(2000)]
+            )?,
+            "SELECT * FROM (SELECT * FROM testdata LIMIT 1000) LIMIT 5"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_locking_clause_is_not_dropped() {
+        // Once sqlparser learns locking clauses, replace this check with a binding test
+        // asserting that the lock survives
+        assert!(Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE id = $1 FOR UPDATE OF testdata NOWAIT",
+        )
+        .is_err());
+    }
+
+    // Forms which are not parsed by the pinned sqlparser, the error names the unexpected token
+    fn parse_error(dialect: &dyn Dialect, sql: &str) -> String {
+        Parser::parse_sql(dialect, sql).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_binder_row_values_are_rejected() {
+        // Once sqlparser parses tuples, the BinaryOp arm binds both sides, replace this check
+        // with a binding test
+        let err = parse_error(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE (fieldA, fieldB) = ($1, $2)",
+        );
+        assert!(err.contains("Expected ), found: ,"), "{}", err);
+    }
+
+    #[test]
+    fn test_binder_at_local_is_rejected() {
+        // Timezone conversions are not modeled, `AT` ends the expression
+        let err = parse_error(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE ts AT LOCAL > $1",
+        );
+        assert!(err.contains("end of statement, found: AT"), "{}", err);
+    }
+
+    #[test]
+    fn test_binder_qualify_is_rejected() {
+        let err = parse_error(
+            &PostgreSqlDialect {},
+            "SELECT fieldA FROM testdata AS t QUALIFY ROW_NUMBER() OVER (ORDER BY fieldB) = $1",
+        );
+        assert!(err.contains("end of statement, found: QUALIFY"), "{}", err);
+    }
+
+    #[test]
+    fn test_binder_named_window_is_rejected() {
+        // Neither references to named windows nor the WINDOW clause are parsed, placeholders
+        // in window definitions can be bound only in inline OVER (...) specifications
+        let err = parse_error(
+            &PostgreSqlDialect {},
+            "SELECT SUM(amount) OVER w FROM testdata",
+        );
+        assert!(err.contains("Expected (, found: w"), "{}", err);
+
+        let err = parse_error(
+            &PostgreSqlDialect {},
+            "SELECT SUM(amount) OVER (ORDER BY ts) FROM testdata AS t WINDOW w AS (PARTITION BY COALESCE(region, $1))",
+        );
+        assert!(err.contains("end of statement, found: WINDOW"), "{}", err);
+    }
+
+    #[test]
+    fn test_binder_insert_overriding_is_rejected() {
+        let err = parse_error(
+            &PostgreSqlDialect {},
+            "INSERT INTO testdata OVERRIDING SYSTEM VALUE VALUES ($1)",
+        );
+        assert!(err.contains("found: OVERRIDING"), "{}", err);
+    }
+
+    #[test]
+    fn test_binder_insert_default_values_is_rejected() {
+        // The source of INSERT must be a query, this form can't be bound even without values
+        let err = parse_error(&PostgreSqlDialect {}, "INSERT INTO testdata DEFAULT VALUES");
+        assert!(err.contains("found: DEFAULT"), "{}", err);
+    }
+
+    #[test]
+    fn test_binder_mysql_convert_using_is_rejected() {
+        let err = parse_error(
+            &MySqlDialectWithBackTicks {},
+            "SELECT CONVERT(? USING utf8mb4) FROM `testdata`",
+        );
+        assert!(err.contains("Expected ), found: USING"), "{}", err);
+    }
+
+    #[test]
+    fn test_binder_rewritten_operators() -> Result<(), CubeError> {
+        let bind = |sql: &str, values: Vec<BindValue>| -> Result<String, CubeError> {
+            let mut stmt = parse_sql_to_statement(&sql.to_string(), DatabaseProtocol::PostgreSQL)?;
+            StatementBinder::new(values).bind(&mut stmt)?;
+
+            Ok(stmt.to_string())
+        };
+
+        // `->` and `->>` are rewritten into functions, keys are their arguments
+        assert_eq!(
+            bind(
+                "SELECT data -> $1 ->> $2 FROM testdata",
+                vec![BindValue::String("a".to_string()), BindValue::Int64(0)],
+            )?,
+            "SELECT json_extract_path_text(json_extract_path(data, 'a'), 0) FROM testdata"
+        );
+        // Fields unknown to the pinned sqlparser are date_part() fields
+        assert_eq!(
+            bind(
+                "SELECT EXTRACT(EPOCH FROM $1) FROM testdata",
+                vec![BindValue::String("2022-01-01".to_string())],
+            )?,
+            "SELECT date_part('epoch', '2022-01-01') FROM testdata"
+        );
+
+        // JSON path operators are not rewritten, replace this check with a binding test for
+        // `data #> $1` (text[] parameter) after the upgrade
+        assert!(bind("SELECT data #> $1 FROM testdata", vec![BindValue::Null]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_overlaps_is_rejected() {
+        // Neither row values nor OVERLAPS are parsed by the pinned sqlparser, replace this check
+        // with a binding test for the second period after the upgrade
+        assert!(Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE (start1, end1) OVERLAPS ($1, $2)",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_placeholder_report() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = $1 AND fieldB = $3 OR fieldC = $1",
+        )
+        .unwrap();
+
+        assert_eq!(
+            placeholder_report(&stmts[0])?,
+            PlaceholderReport {
+                max_index: 3,
+                distinct: vec![1, 3].into_iter().collect(),
+                gaps: vec![2],
+            }
+        );
+
+        let stmts = Parser::parse_sql(&PostgreSqlDialect {}, "SELECT * FROM testdata").unwrap();
+        assert_eq!(
+            placeholder_report(&stmts[0])?,
+            PlaceholderReport {
+                max_index: 0,
+                distinct: BTreeSet::new(),
+                gaps: vec![],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_statements_with_param_offsets() -> Result<(), CubeError> {
+        let stmts = split_statements_with_param_offsets(
+            "SELECT * FROM testdata WHERE fieldA = $1; SELECT * FROM testdata WHERE fieldB = $2",
+            &PostgreSqlDialect {},
+        )?;
+
+        assert_eq!(
+            stmts
+                .iter()
+                .map(|(stmt, range)| (stmt.to_string(), range.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("SELECT * FROM testdata WHERE fieldA = $1".to_string(), 0..1),
+                ("SELECT * FROM testdata WHERE fieldB = $2".to_string(), 1..2),
+            ]
+        );
+
+        // Positional placeholders are numbered across statements
+        let stmts = split_statements_with_param_offsets(
+            "SELECT ?, ?; SELECT 1; SELECT ?",
+            &MySqlDialectWithBackTicks {},
+        )?;
+
+        assert_eq!(
+            stmts.into_iter().map(|(_, range)| range).collect::<Vec<_>>(),
+            vec![0..2, 2..2, 2..3]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_type_mismatch() {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = $1 AND fieldB = $2",
+        )
+        .unwrap();
+
+        let mut binder = StatementBinder::with_types(
+            vec![
+                BindValue::String("test".to_string()),
+                BindValue::String("1".to_string()),
+            ],
+            vec![Some(PgTypeId::Text), Some(PgTypeId::Int4)],
+        );
+        let mut stmt = stmts[0].clone();
+        let err = binder.bind(&mut stmt).unwrap_err();
+
+        assert_eq!(err.message, "parameter $2: expected int4, got String");
+    }
+
+    #[test]
+    fn test_binder_strict_types() -> Result<(), CubeError> {
+        let bind = |values: Vec<BindValue>, types: Vec<PgTypeId>| -> Result<String, CubeError> {
+            let stmts = Parser::parse_sql(
+                &PostgreSqlDialect {},
+                "SELECT * FROM testdata WHERE fieldA = $1 AND fieldB = $2",
+            )
+            .unwrap();
+            let mut stmt = stmts[0].clone();
+
+            StatementBinder::with_types(values, types.into_iter().map(Some).collect())
+                .with_options(BinderOptions {
+                    strict_types: true,
+                    ..BinderOptions::default()
+                })
+                .bind(&mut stmt)?;
+
+            Ok(stmt.to_string())
+        };
+
+        assert_eq!(
+            bind(
+                vec![BindValue::String("test".to_string()), BindValue::Float64(1.5)],
+                vec![PgTypeId::Text, PgTypeId::Float8],
+            )?,
+            "SELECT * FROM testdata WHERE fieldA = 'test' AND fieldB = 1.5"
+        );
+
+        assert_eq!(
+            bind(
+                vec![
+                    BindValue::String("test".to_string()),
+                    BindValue::String("2022-01-01".to_string()),
+                ],
+                vec![PgTypeId::Text, PgTypeId::Timestamp],
+            )
+            .unwrap_err()
+            .message,
+            "parameter $2: String requires implicit coercion to timestamp, which is not allowed in strict mode"
+        );
+
+        assert_eq!(
+            bind(
+                vec![BindValue::Int64(1), BindValue::Float64(1.5)],
+                vec![PgTypeId::Float8, PgTypeId::Float8],
+            )
+            .unwrap_err()
+            .message,
+            "parameter $1: Int64 requires implicit coercion to float8, which is not allowed in strict mode"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_with_types() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = $1 AND fieldB = $2 AND fieldC = $3",
+        )
+        .unwrap();
+
+        let mut binder = StatementBinder::with_types(
+            vec![
+                BindValue::String("test".to_string()),
+                BindValue::Int64(5),
+                BindValue::Bool(true),
+            ],
+            vec![Some(PgTypeId::Varchar), Some(PgTypeId::Numeric), None],
+        );
+        let mut stmt = stmts[0].clone();
+        binder.bind(&mut stmt)?;
+
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT * FROM testdata WHERE fieldA = 'test' AND fieldB = 5 AND fieldC = true"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "extended-statements")]
+    #[test]
+    fn test_binder_extended_statements() -> Result<(), CubeError> {
+        test_binder(
+            "EXPLAIN SELECT * FROM testdata WHERE fieldA = $1",
+            "EXPLAIN SELECT * FROM testdata WHERE fieldA = 'test'",
+            vec![BindValue::String("test".to_string())],
+        )?;
 
-        let mut binder = StatementBinder::new(values);
-        let mut input = stmts[0].clone();
-        binder.bind(&mut input);
+        test_binder(
+            "UPDATE testdata SET fieldA = $1 WHERE fieldB = $2",
+            "UPDATE testdata SET fieldA = 'test' WHERE fieldB = 1",
+            vec![BindValue::String("test".to_string()), BindValue::Int64(1)],
+        )?;
 
-        assert_eq!(input.to_string(), output);
+        test_binder(
+            "DELETE FROM testdata WHERE fieldB = $1",
+            "DELETE FROM testdata WHERE fieldB = 1",
+            vec![BindValue::Int64(1)],
+        )?;
 
         Ok(())
     }
 
     #[test]
-    fn test_binder_named() -> Result<(), CubeError> {
-        test_binder(
-            "SELECT ?",
-            "SELECT 'test'",
-            vec![BindValue::String("test".to_string())],
+    fn test_bind_and_validate() -> Result<(), CubeError> {
+        let tricky = "it's a \\ \"test\" -- with $1 and ; inside";
+        let stmt = bind_and_validate(
+            "SELECT * FROM testdata WHERE fieldA = $1 AND fieldB = $2",
+            &PostgreSqlDialect {},
+            vec![BindValue::String(tricky.to_string()), BindValue::Int64(1)],
         )?;
 
-        // binary op
+        match &stmt {
+            ast::Statement::Query(query) => match &query.body {
+                ast::SetExpr::Select(select) => match &select.selection {
+                    Some(ast::Expr::BinaryOp { left, .. }) => match left.as_ref() {
+                        ast::Expr::BinaryOp { right, .. } => assert_eq!(
+                            right.as_ref(),
+                            &ast::Expr::Value(ast::Value::SingleQuotedString(tricky.to_string()))
+                        ),
+                        e => panic!("unexpected expr: {:?}", e),
+                    },
+                    e => panic!("unexpected selection: {:?}", e),
+                },
+                b => panic!("unexpected body: {:?}", b),
+            },
+            s => panic!("unexpected statement: {:?}", s),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_group_by() -> Result<(), CubeError> {
         test_binder(
             r#"
-                SELECT *
+                SELECT COALESCE(fieldA, $1), COUNT(*)
                 FROM testdata
-                WHERE fieldA = $1 AND fieldB = $2 OR (fieldC = $3 AND fieldD = $4)
+                WHERE fieldC = $2
+                GROUP BY COALESCE(fieldA, $3), COALESCE(fieldB, $4)
             "#,
-            "SELECT * FROM testdata WHERE fieldA = 'test' AND fieldB = 1 OR (fieldC = 2 AND fieldD = 2)",
+            "SELECT COALESCE(fieldA, 'a'), COUNT(*) FROM testdata WHERE fieldC = 'c' GROUP BY COALESCE(fieldA, 'a'), COALESCE(fieldB, 'b')",
             vec![
-                BindValue::String("test".to_string()),
-                BindValue::Int64(1),
-                BindValue::UInt64(2),
-                BindValue::Float64(2.0),
-                BindValue::Bool(true),
+                BindValue::String("a".to_string()),
+                BindValue::String("c".to_string()),
+                BindValue::String("a".to_string()),
+                BindValue::String("b".to_string()),
             ],
         )?;
 
-        // IN
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_having() -> Result<(), CubeError> {
         test_binder(
             r#"
-                SELECT *
+                SELECT region, SUM(amount) OVER (PARTITION BY region ORDER BY ts)
                 FROM testdata
-                WHERE fieldA IN ($1, $2)
+                WHERE fieldA = $1
+                GROUP BY region
+                HAVING SUM(amount) > $2
             "#,
-            "SELECT * FROM testdata WHERE fieldA IN ('test1', 'test2')",
+            "SELECT region, SUM(amount) OVER (PARTITION BY region ORDER BY ts) FROM testdata WHERE fieldA = 'test' GROUP BY region HAVING SUM(amount) > 10",
+            vec![BindValue::String("test".to_string()), BindValue::Int64(10)],
+        )?;
+
+        // GROUPING SETS are rewritten into function calls by parse_sql_to_statement
+        let mut stmt = parse_sql_to_statement(
+            &"SELECT region FROM testdata GROUP BY GROUPING SETS ((region), ()) HAVING SUM(amount) > $1"
+                .to_string(),
+            DatabaseProtocol::PostgreSQL,
+        )
+        .unwrap();
+        StatementBinder::new(vec![BindValue::Int64(10)]).bind(&mut stmt)?;
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT region FROM testdata GROUP BY __grouping_sets(__grouping_set(region), __grouping_set()) HAVING SUM(amount) > 10"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_any_some() -> Result<(), CubeError> {
+        // The pinned sqlparser parses ANY/SOME as regular function calls
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = SOME($1)",
+            "SELECT * FROM testdata WHERE fieldA = SOME('{a,b}')",
+            vec![BindValue::String("{a,b}".to_string())],
+        )?;
+
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = ANY($1) AND fieldB = $2",
+            "SELECT * FROM testdata WHERE fieldA = ANY('{1,2}') AND fieldB = 3",
             vec![
-                BindValue::String("test1".to_string()),
-                BindValue::String("test2".to_string()),
+                BindValue::String("{1,2}".to_string()),
+                BindValue::Int64(3),
             ],
         )?;
 
-        // BETWEEN
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_array() -> Result<(), CubeError> {
         test_binder(
-            r#"
-                SELECT *
-                FROM testdata
-                WHERE fieldA BETWEEN $1 AND $2
-            "#,
-            "SELECT * FROM testdata WHERE fieldA BETWEEN 'test1' AND 'test2'",
+            "SELECT * FROM testdata WHERE fieldA = ANY($1)",
+            r#"SELECT * FROM testdata WHERE fieldA = ANY(make_array('a', 'b"c'))"#,
+            vec![BindValue::Array(vec![
+                BindValue::String("a".to_string()),
+                BindValue::String("b\"c".to_string()),
+            ])],
+        )?;
+
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = ANY($1)",
+            "SELECT * FROM testdata WHERE fieldA = ANY(make_array(make_array(1, 2), make_array(3, 4)))",
+            vec![BindValue::Array(vec![
+                BindValue::Array(vec![BindValue::Int64(1), BindValue::Int64(2)]),
+                BindValue::Array(vec![BindValue::Int64(3), BindValue::Int64(4)]),
+            ])],
+        )?;
+
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = ANY($1)",
+            "SELECT * FROM testdata WHERE fieldA = ANY(make_array(DATE '2022-01-01', NULL))",
+            vec![BindValue::Array(vec![
+                BindValue::Date(NaiveDate::from_ymd(2022, 1, 1)),
+                BindValue::Null,
+            ])],
+        )?;
+
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA = ANY($1)",
+            "SELECT * FROM testdata WHERE fieldA = ANY('{}')",
+            vec![BindValue::Array(vec![])],
+        )?;
+
+        let err = BindValue::Array(vec![BindValue::Int64(1), BindValue::String("a".to_string())])
+            .to_ast_expr()
+            .unwrap_err();
+        assert_eq!(
+            err.message,
+            "array elements must be of the same type, got Int64 and String"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nulls_ordering_rewriter() -> Result<(), CubeError> {
+        let rewrite = |input: &str| -> Result<String, CubeError> {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, input).unwrap();
+            let mut stmt = stmts[0].clone();
+            NullsOrderingRewriter::new().bind(&mut stmt)?;
+
+            Ok(stmt.to_string())
+        };
+
+        assert_eq!(
+            rewrite("SELECT * FROM testdata ORDER BY fieldA DESC NULLS LAST, fieldB")?,
+            "SELECT * FROM testdata ORDER BY CASE WHEN fieldA IS NULL THEN 1 ELSE 0 END, fieldA DESC, fieldB"
+        );
+        assert_eq!(
+            rewrite("SELECT * FROM testdata ORDER BY fieldA NULLS FIRST")?,
+            "SELECT * FROM testdata ORDER BY CASE WHEN fieldA IS NULL THEN 0 ELSE 1 END, fieldA"
+        );
+        assert_eq!(
+            rewrite("SELECT * FROM testdata ORDER BY fieldA ASC")?,
+            "SELECT * FROM testdata ORDER BY fieldA ASC"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_retarget_placeholders() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = $2 AND fieldB = $1 OR fieldC = $2",
+        )
+        .unwrap();
+        let mut stmt = stmts[0].clone();
+
+        let mapping = retarget_placeholders(&mut stmt, PlaceholderSyntax::QuestionMark)?;
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT * FROM testdata WHERE fieldA = ? AND fieldB = ? OR fieldC = ?"
+        );
+        assert_eq!(mapping, vec![1, 0, 1]);
+
+        let mapping = retarget_placeholders(&mut stmt, PlaceholderSyntax::Dollar)?;
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT * FROM testdata WHERE fieldA = $1 AND fieldB = $2 OR fieldC = $3"
+        );
+        assert_eq!(mapping, vec![0, 1, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_statement() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM testdata WHERE fieldA = 'a' AND fieldB = $1 AND fieldC IS NULL LIMIT 10",
+        )
+        .unwrap();
+
+        assert_eq!(
+            normalize_statement(&stmts[0])?,
+            "SELECT * FROM testdata WHERE fieldA = $2 AND fieldB = $1 AND fieldC IS NULL LIMIT $3"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_mysql_convert() -> Result<(), CubeError> {
+        let stmts = Parser::parse_sql(
+            &MySqlDialectWithBackTicks {},
+            "SELECT CONVERT(?, CHAR) FROM `testdata` WHERE `fieldA` = CONVERT(?, SIGNED)",
+        )
+        .unwrap();
+
+        let mut binder = StatementBinder::new(vec![
+            BindValue::String("test".to_string()),
+            BindValue::String("1".to_string()),
+        ]);
+        let mut stmt = stmts[0].clone();
+        binder.bind(&mut stmt)?;
+
+        assert_eq!(
+            stmt.to_string(),
+            "SELECT CONVERT('test', CHAR) FROM `testdata` WHERE `fieldA` = CONVERT('1', SIGNED)"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_trim() -> Result<(), CubeError> {
+        for spec in ["LEADING", "TRAILING", "BOTH"].iter() {
+            test_binder(
+                &format!("SELECT TRIM({} $1 FROM fieldA) FROM testdata", spec),
+                &format!("SELECT TRIM({} 'x' FROM fieldA) FROM testdata", spec),
+                vec![BindValue::String("x".to_string())],
+            )?;
+        }
+
+        test_binder(
+            "SELECT TRIM(LEADING ? FROM ?) FROM testdata",
+            "SELECT TRIM(LEADING 'x' FROM 'xa') FROM testdata",
             vec![
-                BindValue::String("test1".to_string()),
-                BindValue::String("test2".to_string()),
+                BindValue::String("x".to_string()),
+                BindValue::String("xa".to_string()),
             ],
         )?;
+        test_binder(
+            "SELECT TRIM($1) FROM testdata",
+            "SELECT TRIM(' a ') FROM testdata",
+            vec![BindValue::String(" a ".to_string())],
+        )
+    }
 
+    #[test]
+    fn test_binder_is_null() -> Result<(), CubeError> {
         test_binder(
-            r#"
-                SELECT *
-                FROM testdata
-                WHERE fieldA = $1
-                UNION ALL
-                SELECT *
-                FROM testdata
-                WHERE fieldA = $2
-            "#,
-            "SELECT * FROM testdata WHERE fieldA = 'test1' UNION ALL SELECT * FROM testdata WHERE fieldA = 'test2'",
+            "SELECT * FROM testdata WHERE $1 IS NULL OR fieldA = $2",
+            "SELECT * FROM testdata WHERE 'a' IS NULL OR fieldA = 2",
+            vec![BindValue::String("a".to_string()), BindValue::Int64(2)],
+        )?;
+        test_binder(
+            "SELECT * FROM testdata WHERE ? IS NOT NULL AND fieldA = ?",
+            "SELECT * FROM testdata WHERE NULL IS NOT NULL AND fieldA = 2",
+            vec![BindValue::Null, BindValue::Int64(2)],
+        )?;
+        test_binder(
+            "SELECT * FROM testdata WHERE fieldA IS DISTINCT FROM $1 OR fieldB IS NOT DISTINCT FROM $2",
+            "SELECT * FROM testdata WHERE fieldA IS DISTINCT FROM 2 OR fieldB IS NOT DISTINCT FROM 'a'",
+            vec![BindValue::Int64(2), BindValue::String("a".to_string())],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_literals_are_idempotent() -> Result<(), CubeError> {
+        let queries = vec![
+            "SELECT 1, -2, 3.50, 'str', true, NULL FROM testdata",
+            "SELECT * FROM testdata WHERE fieldA IN (1, 2.0, '3') AND fieldB BETWEEN -1 AND 1",
+            "SELECT CAST('1 day' AS INTERVAL), COALESCE(fieldA, 0.000) FROM testdata LIMIT 10 OFFSET 5",
+            "SELECT * FROM (SELECT 'it''s' AS a) UNION ALL SELECT 0.1",
+            "INSERT INTO testdata VALUES (1, 'a'), (-1.5, 'b')",
+        ];
+
+        for query in queries {
+            let stmts = Parser::parse_sql(&PostgreSqlDialect {}, query).unwrap();
+            let original = stmts[0].to_string();
+
+            let mut stmt = stmts[0].clone();
+            StatementBinder::new(vec![]).bind(&mut stmt)?;
+            assert_eq!(stmt.to_string(), original);
+
+            StatementBinder::new(vec![]).bind(&mut stmt)?;
+            assert_eq!(stmt.to_string(), original);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_narrow_integers() -> Result<(), CubeError> {
+        let bind = |values: Vec<BindValue>, narrow_integers: bool| -> Result<String, CubeError> {
+            let stmts = Parser::parse_sql(
+                &PostgreSqlDialect {},
+                "SELECT * FROM testdata WHERE fieldA = $1 AND fieldB = $2",
+            )
+            .unwrap();
+            let mut stmt = stmts[0].clone();
+
+            StatementBinder::with_types(values, vec![Some(PgTypeId::Int4), Some(PgTypeId::Int2)])
+                .with_options(BinderOptions {
+                    narrow_integers,
+                    ..BinderOptions::default()
+                })
+                .bind(&mut stmt)?;
+
+            Ok(stmt.to_string())
+        };
+
+        assert_eq!(
+            bind(vec![BindValue::Int64(5), BindValue::Int64(7)], true)?,
+            "SELECT * FROM testdata WHERE fieldA = CAST(5 AS INT) AND fieldB = CAST(7 AS SMALLINT)"
+        );
+        assert_eq!(
+            bind(vec![BindValue::Int64(5_000_000_000), BindValue::Int64(70_000)], true)?,
+            "SELECT * FROM testdata WHERE fieldA = 5000000000 AND fieldB = 70000"
+        );
+        assert_eq!(
+            bind(vec![BindValue::Int64(5), BindValue::Int64(7)], false)?,
+            "SELECT * FROM testdata WHERE fieldA = 5 AND fieldB = 7"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parameter_metadata() -> Result<(), CubeError> {
+        use datafusion::{arrow::datatypes::DataType, logical_plan::DFField};
+
+        let schema = DFSchema::new(vec![
+            DFField::new(Some("testdata"), "name", DataType::Utf8, true),
+            DFField::new(Some("testdata"), "amount", DataType::Int32, false),
+        ])
+        .unwrap();
+
+        let stmts = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT name FROM testdata WHERE name = $1 AND testdata.amount > $2 LIMIT $3",
+        )
+        .unwrap();
+
+        assert_eq!(
+            parameter_metadata(&stmts[0], Some(&schema))?,
             vec![
-                BindValue::String(
-                    "test1".to_string(),
-                ),
-                BindValue::String(
-                    "test2".to_string(),
-                ),
+                ParamMeta {
+                    oid: PgTypeId::Text.to_oid(),
+                    nullable: true,
+                    clause: PlaceholderClause::Where,
+                },
+                ParamMeta {
+                    oid: PgTypeId::Int4.to_oid(),
+                    nullable: false,
+                    clause: PlaceholderClause::Where,
+                },
+                ParamMeta {
+                    oid: PgTypeId::Int8.to_oid(),
+                    nullable: false,
+                    clause: PlaceholderClause::Limit,
+                },
             ]
+        );
+
+        // Without schema, only the context is known
+        let metadata = parameter_metadata(&stmts[0], None)?;
+        assert_eq!(metadata[0].oid, 0);
+        assert_eq!(metadata[0].clause, PlaceholderClause::Where);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_string_agg_delimiter() -> Result<(), CubeError> {
+        test_binder(
+            "SELECT string_agg(name, $1) FROM testdata",
+            "SELECT string_agg(name, ', ') FROM testdata",
+            vec![BindValue::String(", ".to_string())],
         )?;
 
         test_binder(
-            r#"
-                SELECT * FROM (
-                    SELECT *
-                    FROM testdata
-                    WHERE fieldA = $1
-                )
-            "#,
-            "SELECT * FROM (SELECT * FROM testdata WHERE fieldA = 'test1')",
-            vec![BindValue::String("test1".to_string())],
+            "SELECT LISTAGG(name, $1) WITHIN GROUP (ORDER BY COALESCE(name, $2)) FROM testdata",
+            "SELECT LISTAGG(name, ', ') WITHIN GROUP (ORDER BY COALESCE(name, '')) FROM testdata",
+            vec![
+                BindValue::String(", ".to_string()),
+                BindValue::String("".to_string()),
+            ],
         )?;
 
         Ok(())
     }
+
+    /// Whether `walk_expr` visits nested expressions of the variant. The match is exhaustive on
+    /// purpose (no `_` arm), so a new variant in sqlparser is a compile error here.
+    fn expr_binding_coverage(expr: &ast::Expr) -> bool {
+        match expr {
+            ast::Expr::Value(_)
+            | ast::Expr::Identifier(_)
+            | ast::Expr::Nested(_)
+            | ast::Expr::Cast { .. }
+            | ast::Expr::TryCast { .. }
+            | ast::Expr::Extract { .. }
+            | ast::Expr::Between { .. }
+            | ast::Expr::BinaryOp { .. }
+            | ast::Expr::InList { .. }
+            | ast::Expr::InSubquery { .. }
+            | ast::Expr::Subquery(_)
+            | ast::Expr::Exists(_)
+            | ast::Expr::UnaryOp { .. }
+            | ast::Expr::Case { .. }
+            | ast::Expr::IsNull(_)
+            | ast::Expr::IsNotNull(_)
+            | ast::Expr::IsDistinctFrom(_, _)
+            | ast::Expr::IsNotDistinctFrom(_, _)
+            | ast::Expr::Trim { .. }
+            | ast::Expr::Substring { .. }
+            | ast::Expr::Function(_)
+            | ast::Expr::ListAgg(_) => true,
+            // No nested expressions
+            ast::Expr::Wildcard
+            | ast::Expr::QualifiedWildcard(_)
+            | ast::Expr::CompoundIdentifier(_)
+            | ast::Expr::TypedString { .. } => false,
+            // Not bindable yet
+            ast::Expr::Collate { .. }
+            | ast::Expr::MapAccess { .. } => false,
+        }
+    }
+
+    #[test]
+    fn test_binder_expr_coverage() -> Result<(), CubeError> {
+        let cases = vec![
+            ("$1", true),
+            ("($1)", true),
+            ("CAST($1 AS INT)", true),
+            ("fieldA BETWEEN $1 AND 2", true),
+            ("fieldA = $1", true),
+            ("fieldA IN ($1)", true),
+            ("COALESCE(fieldA, $1)", true),
+            ("fieldA IN (SELECT $1)", true),
+            ("EXISTS (SELECT $1)", true),
+            ("testdata.fieldA", false),
+            ("$1 IS NULL", true),
+            ("$1 IS NOT NULL", true),
+            ("fieldA IS DISTINCT FROM $1", true),
+            ("$1 IS NOT DISTINCT FROM fieldA", true),
+            ("TRIM(BOTH $1 FROM fieldA)", true),
+            ("-$1", true),
+            ("CASE WHEN fieldA THEN $1 END", true),
+            ("SUBSTRING(fieldA FROM $1 FOR 2)", true),
+        ];
+
+        for (expr, covered) in cases {
+            let sql = format!("SELECT * FROM testdata WHERE {}", expr);
+            let mut stmt = Parser::parse_sql(&PostgreSqlDialect {}, &sql).unwrap()[0].clone();
+
+            match &stmt {
+                ast::Statement::Query(query) => match &query.body {
+                    ast::SetExpr::Select(select) => assert_eq!(
+                        expr_binding_coverage(select.selection.as_ref().unwrap()),
+                        covered,
+                        "{}",
+                        expr
+                    ),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+
+            // Coverage must match the binder: covered placeholders are replaced
+            let mut binder = StatementBinder::new(vec![BindValue::Int64(1)]);
+            binder.bind(&mut stmt)?;
+            if expr.contains('$') {
+                assert_eq!(!stmt.to_string().contains('$'), covered, "{}", expr);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binder_iterative_deep_expr() {
+        // Building and dropping the AST is recursive, only binding runs on a small stack
+        std::thread::Builder::new()
+            .stack_size(256 * 1024 * 1024)
+            .spawn(|| {
+                // Every level is a Nested and a BinaryOp, 50,000 expressions deep
+                let depth = 25_000;
+
+                let placeholder =
+                    || Box::new(ast::Expr::Value(ast::Value::Placeholder("?".to_string())));
+                let mut expr = *placeholder();
+                for i in 0..depth {
+                    expr = ast::Expr::Nested(Box::new(ast::Expr::BinaryOp {
+                        left: placeholder(),
+                        op: if i % 2 == 0 {
+                            ast::BinaryOperator::Or
+                        } else {
+                            ast::BinaryOperator::And
+                        },
+                        right: Box::new(expr),
+                    }));
+                }
+
+                let mut stmt = Parser::parse_sql(&PostgreSqlDialect {}, "SELECT * FROM testdata")
+                    .unwrap()[0]
+                    .clone();
+                match &mut stmt {
+                    ast::Statement::Query(query) => match &mut query.body {
+                        ast::SetExpr::Select(select) => select.selection = Some(expr),
+                        _ => unreachable!(),
+                    },
+                    _ => unreachable!(),
+                };
+
+                let stmt = std::thread::Builder::new()
+                    .stack_size(512 * 1024)
+                    .spawn(move || {
+                        let mut binder = StatementBinder::new(
+                            (0..=depth).map(|i| BindValue::Int64(i as i64)).collect(),
+                        )
+                        .with_options(BinderOptions {
+                            iterative: true,
+                            ..BinderOptions::default()
+                        });
+                        binder.bind(&mut stmt).map(|_| stmt)
+                    })
+                    .unwrap()
+                    .join()
+                    .unwrap()
+                    .unwrap();
+
+                let mut expr = match &stmt {
+                    ast::Statement::Query(query) => match &query.body {
+                        ast::SetExpr::Select(select) => select.selection.as_ref().unwrap(),
+                        _ => unreachable!(),
+                    },
+                    _ => unreachable!(),
+                };
+
+                // Values are bound in the textual order, from the outermost placeholder
+                let mut expected = 0;
+                loop {
+                    match expr {
+                        ast::Expr::Nested(nested) => match nested.as_ref() {
+                            ast::Expr::BinaryOp { left, right, .. } => {
+                                assert_eq!(left.to_string(), expected.to_string());
+                                expected += 1;
+                                expr = right;
+                            }
+                            _ => unreachable!(),
+                        },
+                        last => {
+                            assert_eq!(last.to_string(), expected.to_string());
+                            break;
+                        }
+                    }
+                }
+                assert_eq!(expected, depth);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    fn bind_error(
+        input: &str,
+        values: Vec<BindValue>,
+        types: Vec<Option<PgTypeId>>,
+        options: BinderOptions,
+    ) -> CubeError {
+        let mut stmt = Parser::parse_sql(&PostgreSqlDialect {}, input).unwrap()[0].clone();
+
+        let err = StatementBinder::with_types(values, types)
+            .with_options(options)
+            .bind(&mut stmt)
+            .unwrap_err();
+        assert!(matches!(err.cause, crate::CubeErrorCauseType::User), "{:?}", err);
+
+        err
+    }
+
+    fn ints(n: i64) -> Vec<BindValue> {
+        (1..=n).map(BindValue::Int64).collect()
+    }
+
+    #[test]
+    fn test_binder_error_too_few_values() {
+        let err = bind_error("SELECT $1, $2", ints(1), vec![], BinderOptions::default());
+        assert_eq!(err.message, "Unable to find value for parameter $2");
+    }
+
+    #[test]
+    fn test_binder_error_too_many_values() {
+        let strict = BinderOptions {
+            strict_values: true,
+            ..BinderOptions::default()
+        };
+
+        let err = bind_error("SELECT $1", ints(3), vec![], strict);
+        assert_eq!(
+            err.message,
+            "2 of 3 supplied values are not used in the statement"
+        );
+    }
+
+    #[test]
+    fn test_binder_error_gap() {
+        let strict = BinderOptions {
+            strict_values: true,
+            ..BinderOptions::default()
+        };
+
+        let err = bind_error("SELECT $1, $3", ints(2), vec![], strict);
+        assert_eq!(err.message, "parameter $2 is not used in the statement");
+    }
+
+    #[test]
+    fn test_binder_error_malformed_placeholder() {
+        let err = bind_error("SELECT $0", ints(1), vec![], BinderOptions::default());
+        assert_eq!(err.message, "Malformed placeholder: $0");
+
+        // Not produced by the tokenizer, but can come from rewritten statements
+        let mut stmt = Parser::parse_sql(&PostgreSqlDialect {}, "SELECT $1").unwrap()[0].clone();
+        retarget(&mut stmt, "$abc");
+        let err = StatementBinder::new(ints(1)).bind(&mut stmt).unwrap_err();
+        assert_eq!(err.message, "Malformed placeholder: $abc");
+    }
+
+    fn retarget(stmt: &mut ast::Statement, placeholder: &str) {
+        match stmt {
+            ast::Statement::Query(query) => match &mut query.body {
+                ast::SetExpr::Select(select) => {
+                    select.projection = vec![ast::SelectItem::UnnamedExpr(ast::Expr::Value(
+                        ast::Value::Placeholder(placeholder.to_string()),
+                    ))]
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_binder_error_max_depth() {
+        let options = BinderOptions {
+            max_depth: Some(3),
+            ..BinderOptions::default()
+        };
+
+        let err = bind_error("SELECT (((($1))))", ints(1), vec![], options);
+        assert_eq!(err.message, "Expression nesting exceeds the limit of 3");
+
+        // Subqueries are walked recursively by the iterative traversal, the parser is recursive too
+        let err = std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(|| {
+                let iterative = BinderOptions {
+                    iterative: true,
+                    ..BinderOptions::default()
+                };
+                let sql = format!(
+                    "SELECT {}$1{}",
+                    "(SELECT ".repeat(MAX_ITERATIVE_DEPTH + 1),
+                    ")".repeat(MAX_ITERATIVE_DEPTH + 1)
+                );
+
+                bind_error(&sql, ints(1), vec![], iterative)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(
+            err.message,
+            format!(
+                "Expression nesting exceeds the limit of {}",
+                MAX_ITERATIVE_DEPTH
+            )
+        );
+    }
+
+    #[test]
+    fn test_binder_iterative_deep_functions() {
+        // Parsing, printing and dropping are recursive, only binding runs on a small stack
+        std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(|| {
+                // Every other level is CASE WHEN $N = 1 THEN ... END, 4 times the depth limit
+                let depth = 4 * MAX_ITERATIVE_DEPTH;
+                let mut sql = "$1".to_string();
+                for i in 0..depth {
+                    sql = if i % 2 == 0 {
+                        format!("ABS({})", sql)
+                    } else {
+                        format!("CASE WHEN ${} = 1 THEN {} END", i / 2 + 2, sql)
+                    };
+                }
+
+                let stmt = Parser::parse_sql(&PostgreSqlDialect {}, &format!("SELECT {}", sql))
+                    .unwrap()[0]
+                    .clone();
+                let stmt = std::thread::Builder::new()
+                    .stack_size(512 * 1024)
+                    .spawn(move || {
+                        let mut stmt = stmt;
+                        let mut binder = StatementBinder::new(
+                            (0..=depth / 2).map(|_| BindValue::Int64(1)).collect(),
+                        )
+                        .with_options(BinderOptions {
+                            iterative: true,
+                            strict_values: true,
+                            ..BinderOptions::default()
+                        });
+                        binder.bind(&mut stmt).map(|_| stmt)
+                    })
+                    .unwrap()
+                    .join()
+                    .unwrap()
+                    .unwrap();
+
+                assert!(!stmt.to_string().contains('$'));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_binder_error_max_placeholders() {
+        let options = BinderOptions {
+            max_placeholders: Some(2),
+            ..BinderOptions::default()
+        };
+
+        let err = bind_error("SELECT $1, $2, $3", ints(3), vec![], options);
+        assert_eq!(err.message, "Statement has more than 2 placeholders");
+    }
+
+    #[test]
+    fn test_binder_error_type_mismatch() {
+        let err = bind_error(
+            "SELECT $1",
+            vec![BindValue::Bool(true)],
+            vec![Some(PgTypeId::Int8)],
+            BinderOptions::default(),
+        );
+        assert_eq!(err.message, "parameter $1: expected int8, got Bool");
+    }
+
+    #[test]
+    fn test_binder_error_oversized_string() {
+        let options = BinderOptions {
+            max_string_length: Some(4),
+            ..BinderOptions::default()
+        };
+
+        let err = bind_error(
+            "SELECT $1",
+            vec![BindValue::String("12345".to_string())],
+            vec![],
+            options,
+        );
+        assert_eq!(
+            err.message,
+            "parameter $1: string of 5 bytes exceeds the limit of 4"
+        );
+    }
 }
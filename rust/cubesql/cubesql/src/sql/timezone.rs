@@ -0,0 +1,161 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::{Tz, TZ_VARIANTS};
+
+/// Zone of TimeZone: IANA names are matched case-insensitively, offsets in hours
+/// (`SET TIME ZONE -5`) are `Etc/GMT` zones, which have the inverted sign as in POSIX
+pub fn parse_time_zone(value: &str) -> Option<Tz> {
+    let value = value.trim();
+    let name = match value.parse::<i32>() {
+        Ok(0) => "Etc/GMT".to_string(),
+        Ok(hours) if (-12..=14).contains(&hours) => format!("Etc/GMT{:+}", -hours),
+        Ok(_) => return None,
+        Err(_) => value.to_string(),
+    };
+
+    TZ_VARIANTS
+        .iter()
+        .find(|tz| tz.name().eq_ignore_ascii_case(&name))
+        .copied()
+}
+
+/// Local time of a timestamptz, which is UTC nanoseconds
+pub fn local_time(unix_nano: i64, tz: &Tz) -> DateTime<Tz> {
+    tz.timestamp_nanos(unix_nano)
+}
+
+/// UTC nanoseconds of a local time. As in PostgreSQL, ambiguous times (the clock is turned back)
+/// are in standard time, which is the later one, and skipped times are shifted by the offset
+/// before the transition.
+pub fn local_to_unix_nano(local: &NaiveDateTime, tz: &Tz) -> i64 {
+    match tz.from_local_datetime(local) {
+        LocalResult::Single(v) | LocalResult::Ambiguous(_, v) => v.timestamp_nanos(),
+        LocalResult::None => {
+            let before = tz
+                .offset_from_utc_datetime(&(*local - Duration::days(1)))
+                .fix();
+            (*local - Duration::seconds(before.local_minus_utc() as i64)).timestamp_nanos()
+        }
+    }
+}
+
+/// Text of a timestamptz in the zone, as PostgreSQL writes it: `2022-03-01 05:30:00-05`,
+/// offsets with minutes are `+05:30`
+pub fn format_timestamptz(unix_nano: i64, tz: &Tz) -> String {
+    let local = local_time(unix_nano, tz);
+    let offset = local.offset().fix().local_minus_utc();
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+
+    let mut out = format!(
+        "{}{}{:02}",
+        local.format("%Y-%m-%d %H:%M:%S%.f"),
+        sign,
+        offset / 3600
+    );
+    if offset % 3600 != 0 {
+        out.push_str(&format!(":{:02}", offset % 3600 / 60));
+    }
+
+    out
+}
+
+/// Parses the text of a timestamptz into UTC nanoseconds, values without an offset are in
+/// the zone
+pub fn parse_timestamptz(text: &str, tz: &Tz) -> Option<i64> {
+    let text = text.trim();
+    // Postgres writes offsets without minutes (`+03`)
+    let with_offset = ["%Y-%m-%d %H:%M:%S%.f%#z", "%Y-%m-%dT%H:%M:%S%.f%#z"]
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(text, format).ok());
+    if let Some(value) = with_offset {
+        return Some(value.timestamp_nanos());
+    }
+
+    let (text, utc) = match text.strip_suffix(|c| c == 'Z' || c == 'z') {
+        Some(text) => (text, true),
+        None => (text, false),
+    };
+    let local = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_hms(0, 0, 0))
+        })?;
+
+    if utc {
+        Some(local.timestamp_nanos())
+    } else {
+        Some(local_to_unix_nano(&local, tz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_zone() {
+        assert_eq!(
+            parse_time_zone("america/new_york"),
+            Some(Tz::America__New_York)
+        );
+        assert_eq!(parse_time_zone("UTC"), Some(Tz::UTC));
+        assert_eq!(parse_time_zone("-5"), Some(Tz::Etc__GMTPlus5));
+        assert_eq!(parse_time_zone("3"), Some(Tz::Etc__GMTMinus3));
+        assert_eq!(parse_time_zone("15"), None);
+        assert_eq!(parse_time_zone("Mars/Olympus_Mons"), None);
+    }
+
+    #[test]
+    fn test_timestamptz_text() {
+        let utc = |text: &str| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+                .unwrap()
+                .timestamp_nanos()
+        };
+        let new_york = Tz::America__New_York;
+
+        assert_eq!(
+            format_timestamptz(utc("2022-03-01 10:30:00"), &new_york),
+            "2022-03-01 05:30:00-05"
+        );
+        assert_eq!(
+            format_timestamptz(utc("2022-07-01 10:30:00") + 250_000_000, &new_york),
+            "2022-07-01 06:30:00.250-04"
+        );
+        assert_eq!(
+            format_timestamptz(utc("2022-03-01 10:30:00"), &Tz::Asia__Kolkata),
+            "2022-03-01 16:00:00+05:30"
+        );
+
+        assert_eq!(
+            parse_timestamptz("2022-03-01 05:30:00", &new_york),
+            Some(utc("2022-03-01 10:30:00"))
+        );
+        assert_eq!(
+            parse_timestamptz("2022-03-01 05:30:00+03", &new_york),
+            Some(utc("2022-03-01 02:30:00"))
+        );
+        assert_eq!(
+            parse_timestamptz("2022-03-01T05:30:00Z", &new_york),
+            Some(utc("2022-03-01 05:30:00"))
+        );
+        assert_eq!(
+            parse_timestamptz("2022-03-01", &new_york),
+            Some(utc("2022-03-01 05:00:00"))
+        );
+        assert_eq!(parse_timestamptz("yesterday", &new_york), None);
+
+        // 02:30 is skipped and 01:30 is repeated by DST transitions
+        assert_eq!(
+            parse_timestamptz("2022-03-13 02:30:00", &new_york),
+            Some(utc("2022-03-13 07:30:00"))
+        );
+        assert_eq!(
+            parse_timestamptz("2022-11-06 01:30:00", &new_york),
+            Some(utc("2022-11-06 06:30:00"))
+        );
+    }
+}
@@ -13,6 +13,13 @@ pub enum ColumnType {
     Int64,
     Blob,
     Timestamp,
+    /// Timestamp with time zone, it's rendered in TimeZone of the session
+    Timestamptz,
+    Interval,
+    /// NUMERIC with the precision and the scale
+    Decimal(usize, usize),
+    /// Array of elements of the type
+    List(&'static ColumnType),
 }
 
 impl ColumnType {
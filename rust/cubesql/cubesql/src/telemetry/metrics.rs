@@ -0,0 +1,260 @@
+use std::{collections::BTreeMap, fmt::Write, sync::RwLock as RwLockSync, time::Duration};
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+/// Upper bounds of histogram buckets in seconds
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// Internals of the SQL API in the Prometheus text format, they are served by `MetricsServer`
+pub struct Metrics {
+    pub queries: Counter,
+    pub pg_messages: Counter,
+    pub errors: Counter,
+    pub rewrite_duration: Histogram,
+    pub cube_api_duration: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            queries: Counter::new(
+                "cubesql_queries_total",
+                "Statements received from clients",
+                "protocol",
+            ),
+            pg_messages: Counter::new(
+                "cubesql_pg_messages_total",
+                "Messages of the PostgreSQL protocol received from clients",
+                "message",
+            ),
+            errors: Counter::new(
+                "cubesql_errors_total",
+                "Errors sent to PostgreSQL clients",
+                "sqlstate",
+            ),
+            rewrite_duration: Histogram::new(
+                "cubesql_rewrite_duration_seconds",
+                "Time of rewriting of logical plans into Cube queries",
+                "protocol",
+            ),
+            cube_api_duration: Histogram::new(
+                "cubesql_cube_api_duration_seconds",
+                "Latency of requests to the Cube API",
+                "method",
+            ),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.queries.render(&mut out);
+        self.pg_messages.render(&mut out);
+        self.errors.render(&mut out);
+        self.rewrite_duration.render(&mut out);
+        self.cube_api_duration.render(&mut out);
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Label values are quoted, backslashes, quotes and line feeds are escaped
+fn label_pair(label: &str, value: &str) -> String {
+    let value = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+
+    format!("{}=\"{}\"", label, value)
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, typ: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, typ).unwrap();
+}
+
+/// Gauge which is computed for every scrape, e.g. the number of sessions
+pub fn write_gauge(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: Vec<(String, u64)>,
+) {
+    write_header(out, name, help, "gauge");
+    for (value, gauge) in values {
+        writeln!(out, "{}{{{}}} {}", name, label_pair(label, &value), gauge).unwrap();
+    }
+}
+
+/// Monotonic counter by values of a label
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: RwLockSync<BTreeMap<String, u64>>,
+}
+
+impl Counter {
+    pub fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            label,
+            values: RwLockSync::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, value: &str) {
+        let mut guard = self
+            .values
+            .write()
+            .expect("failed to unlock counter for writting");
+
+        *guard.entry(value.to_string()).or_insert(0) += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        let guard = self
+            .values
+            .read()
+            .expect("failed to unlock counter for reading");
+
+        write_header(out, self.name, self.help, "counter");
+        for (value, count) in guard.iter() {
+            writeln!(
+                out,
+                "{}{{{}}} {}",
+                self.name,
+                label_pair(self.label, value),
+                count
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct HistogramValue {
+    /// Observations per bucket, they are not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Histogram of durations by values of a label
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: RwLockSync<BTreeMap<String, HistogramValue>>,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            label,
+            values: RwLockSync::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, value: &str, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+
+        let mut guard = self
+            .values
+            .write()
+            .expect("failed to unlock histogram for writting");
+
+        let histogram = guard
+            .entry(value.to_string())
+            .or_insert_with(|| HistogramValue {
+                buckets: vec![0; DURATION_BUCKETS.len()],
+                ..HistogramValue::default()
+            });
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|le| seconds <= *le) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        let guard = self
+            .values
+            .read()
+            .expect("failed to unlock histogram for reading");
+
+        write_header(out, self.name, self.help, "histogram");
+        for (value, histogram) in guard.iter() {
+            let label = label_pair(self.label, value);
+
+            let mut cumulative = 0;
+            for (le, count) in DURATION_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"{}\"}} {}",
+                    self.name, label, le, cumulative
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "{}_bucket{{{},le=\"+Inf\"}} {}",
+                self.name, label, histogram.count
+            )
+            .unwrap();
+            writeln!(out, "{}_sum{{{}}} {}", self.name, label, histogram.sum).unwrap();
+            writeln!(out, "{}_count{{{}}} {}", self.name, label, histogram.count).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let counter = Counter::new("test_total", "Test counter", "code");
+        counter.inc("42\"P01");
+        counter.inc("42\"P01");
+        counter.inc("0A000");
+
+        let mut out = String::new();
+        counter.render(&mut out);
+        assert_eq!(
+            out,
+            "# HELP test_total Test counter\n\
+            # TYPE test_total counter\n\
+            test_total{code=\"0A000\"} 1\n\
+            test_total{code=\"42\\\"P01\"} 2\n"
+        );
+
+        let histogram = Histogram::new("test_seconds", "Test histogram", "method");
+        histogram.observe("load", Duration::from_millis(500));
+        histogram.observe("load", Duration::from_secs(120));
+
+        let mut out = String::new();
+        histogram.render(&mut out);
+        let lines = out.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"test_seconds_bucket{method=\"load\",le=\"0.25\"} 0"));
+        assert!(lines.contains(&"test_seconds_bucket{method=\"load\",le=\"0.5\"} 1"));
+        assert!(lines.contains(&"test_seconds_bucket{method=\"load\",le=\"60\"} 1"));
+        assert!(lines.contains(&"test_seconds_bucket{method=\"load\",le=\"+Inf\"} 2"));
+        assert!(lines.contains(&"test_seconds_sum{method=\"load\"} 120.5"));
+        assert!(lines.contains(&"test_seconds_count{method=\"load\"} 2"));
+    }
+}
@@ -0,0 +1,237 @@
+use std::{io::Error, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use log::{debug, error};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+    sync::{watch, RwLock},
+    time::timeout,
+};
+
+use crate::{
+    config::processing_loop::ProcessingLoop,
+    sql::{session::DatabaseProtocol, SessionManager},
+    CubeError,
+};
+
+use super::metrics::{write_gauge, METRICS};
+
+/// Requests with bigger headers are rejected
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Connections which don't send the request headers in time are closed
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP endpoint for Prometheus, metrics are served by `GET /metrics`
+pub struct MetricsServer {
+    address: String,
+    close_socket_rx: RwLock<watch::Receiver<bool>>,
+    close_socket_tx: watch::Sender<bool>,
+    // reference
+    session_manager: Arc<SessionManager>,
+}
+
+crate::di_service!(MetricsServer, []);
+
+#[async_trait]
+impl ProcessingLoop for MetricsServer {
+    async fn processing_loop(&self) -> Result<(), CubeError> {
+        let listener = TcpListener::bind(self.address.clone()).await?;
+
+        println!(
+            "📊 Cube SQL metrics are served on http://{}/metrics",
+            self.address
+        );
+
+        loop {
+            let mut stop_receiver = self.close_socket_rx.write().await;
+            let (mut socket, _) = tokio::select! {
+                res = stop_receiver.changed() => {
+                    if res.is_err() || *stop_receiver.borrow() {
+                        return Ok(());
+                    } else {
+                        continue;
+                    }
+                }
+                accept_res = listener.accept() => {
+                    match accept_res {
+                        Ok(res) => res,
+                        Err(err) => {
+                            error!("Network error: {}", err);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let session_manager = self.session_manager.clone();
+            tokio::spawn(async move {
+                let metrics = || render_metrics(&session_manager);
+                if let Err(e) = serve_request(&mut socket, REQUEST_TIMEOUT, metrics).await {
+                    debug!("Error during serving metrics: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn stop_processing(&self) -> Result<(), CubeError> {
+        self.close_socket_tx.send(true)?;
+        Ok(())
+    }
+}
+
+impl MetricsServer {
+    pub fn new(address: String, session_manager: Arc<SessionManager>) -> Arc<Self> {
+        let (close_socket_tx, close_socket_rx) = watch::channel(false);
+        Arc::new(Self {
+            address,
+            close_socket_rx: RwLock::new(close_socket_rx),
+            close_socket_tx,
+            session_manager,
+        })
+    }
+}
+
+fn render_metrics(session_manager: &SessionManager) -> String {
+    let mut out = METRICS.render();
+    write_gauge(
+        &mut out,
+        "cubesql_sessions",
+        "Open sessions, including sessions which are not authenticated yet",
+        "protocol",
+        [DatabaseProtocol::MySQL, DatabaseProtocol::PostgreSQL]
+            .iter()
+            .map(|protocol| {
+                let count = session_manager.count_sessions(protocol) as u64;
+                (protocol.as_str().to_string(), count)
+            })
+            .collect(),
+    );
+
+    out
+}
+
+/// Responds to a single HTTP/1.x request, the connection is closed after the response
+async fn serve_request<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    read_timeout: Duration,
+    metrics: impl FnOnce() -> String,
+) -> Result<(), Error> {
+    let request = match timeout(read_timeout, read_head(socket)).await {
+        Ok(Ok(Some(request))) => request,
+        // The connection is closed before the end of the headers
+        Ok(Ok(None)) => return Ok(()),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => return write_response(socket, "408 Request Timeout", "").await,
+    };
+    if request.len() > MAX_REQUEST_SIZE {
+        return write_response(socket, "431 Request Header Fields Too Large", "").await;
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or("").split(' ');
+    let method = request_line.next().unwrap_or("");
+    let path = request_line.next().unwrap_or("");
+    let path = path.split('?').next().unwrap_or("");
+
+    match (method, path) {
+        ("GET", "/metrics") => write_response(socket, "200 OK", &metrics()).await,
+        ("GET", _) => write_response(socket, "404 Not Found", "").await,
+        _ => write_response(socket, "405 Method Not Allowed", "").await,
+    }
+}
+
+/// Reads the request line and headers, until there is an empty line or more than
+/// `MAX_REQUEST_SIZE` bytes. None if the connection is closed before
+async fn read_head<S: AsyncRead + Unpin>(socket: &mut S) -> Result<Option<Vec<u8>>, Error> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while request.len() <= MAX_REQUEST_SIZE
+        && !request.windows(4).any(|window| window == b"\r\n\r\n")
+    {
+        let read = socket.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    Ok(Some(request))
+}
+
+async fn write_response<S: AsyncWrite + Unpin>(
+    socket: &mut S,
+    status: &str,
+    body: &str,
+) -> Result<(), Error> {
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+        Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\
+        \r\n\
+        {}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn request(request: &str) -> Result<String, Error> {
+        let (mut client, mut server) = tokio::io::duplex(MAX_REQUEST_SIZE * 2);
+        client.write_all(request.as_bytes()).await?;
+        serve_request(&mut server, REQUEST_TIMEOUT, || "metric 1\n".to_string()).await?;
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await?;
+
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn test_serve_request() -> Result<(), Error> {
+        let response = request("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Length: 9\r\n"));
+        assert!(response.ends_with("\r\n\r\nmetric 1\n"));
+
+        let response = request("GET /?a=1 HTTP/1.1\r\n\r\n").await?;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let response = request("POST /metrics HTTP/1.1\r\n\r\n").await?;
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+
+        let response = request(&format!(
+            "GET /{} HTTP/1.1\r\n\r\n",
+            "a".repeat(MAX_REQUEST_SIZE)
+        ))
+        .await?;
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_serve_request_timeout() -> Result<(), Error> {
+        let (mut client, mut server) = tokio::io::duplex(MAX_REQUEST_SIZE);
+        // The headers are never finished, the client keeps the connection open
+        client.write_all(b"GET /metrics HTTP/1.1\r\n").await?;
+        serve_request(&mut server, Duration::from_millis(10), || {
+            "metric 1\n".to_string()
+        })
+        .await?;
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await?;
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+
+        Ok(())
+    }
+}
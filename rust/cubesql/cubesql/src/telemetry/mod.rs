@@ -1,3 +1,8 @@
+pub mod metrics;
+pub mod metrics_server;
+pub mod query_log;
+pub mod tracing;
+
 use crate::CubeError;
 use chrono::{SecondsFormat, Utc};
 use core::mem;
@@ -0,0 +1,384 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    mem,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+use datafusion::logical_plan::LogicalPlan;
+use log::error;
+use serde::Serialize;
+use tokio::sync::{watch, Notify, RwLock};
+
+use crate::{
+    compile::engine::df::scan::CubeScanNode,
+    config::{processing_loop::ProcessingLoop, QueryLogConfig},
+    CubeError,
+};
+
+lazy_static! {
+    pub static ref QUERY_LOG: QueryLog = QueryLog::new();
+}
+
+/// Records are dropped when the destination is slower than sessions
+const MAX_QUEUE_SIZE: usize = 8192;
+
+/// How the statement was executed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pushdown {
+    /// The statement was answered by the SQL API (SET, SHOW, meta queries)
+    Meta,
+    /// The statement was executed by DataFusion without the Cube API (system tables)
+    DataFusion,
+    /// The statement was pushed down to the Cube API
+    Cube,
+}
+
+impl Pushdown {
+    pub fn of_plan(plan: &LogicalPlan) -> Self {
+        if has_cube_scan(plan) {
+            Pushdown::Cube
+        } else {
+            Pushdown::DataFusion
+        }
+    }
+}
+
+fn has_cube_scan(plan: &LogicalPlan) -> bool {
+    if let LogicalPlan::Extension { node } = plan {
+        if node.as_any().downcast_ref::<CubeScanNode>().is_some() {
+            return true;
+        }
+    }
+
+    plan.inputs().into_iter().any(has_cube_scan)
+}
+
+/// Record of an executed or failed statement, it's written as a line of JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryLogRecord {
+    pub timestamp: String,
+    pub protocol: &'static str,
+    pub connection_id: u32,
+    pub user: Option<String>,
+    pub sql: String,
+    /// Literals are replaced by parameters as in pg_stat_statements
+    pub normalized_sql: Option<String>,
+    pub duration_ms: f64,
+    pub rows: u64,
+    /// It's not known for statements which failed before planning
+    pub pushdown: Option<Pushdown>,
+    /// `x-request-id` of requests to the Cube API
+    pub cube_request_id: Option<String>,
+    pub error: Option<String>,
+}
+
+impl QueryLogRecord {
+    pub fn new(
+        protocol: &'static str,
+        connection_id: u32,
+        user: Option<String>,
+        sql: String,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            protocol,
+            connection_id,
+            user,
+            sql,
+            normalized_sql: None,
+            duration_ms: duration.as_nanos() as f64 / 1_000_000.0,
+            rows: 0,
+            pushdown: None,
+            cube_request_id: None,
+            error: None,
+        }
+    }
+}
+
+/// Queue of records, statements are not logged until `QueryLogWriter` is started
+pub struct QueryLog {
+    enabled: AtomicBool,
+    lines: Mutex<Vec<String>>,
+    notify: Notify,
+}
+
+impl QueryLog {
+    fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            lines: Mutex::new(vec![]),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Queues the record of the statement, successful statements faster than `min_duration`
+    /// are skipped as by slow_query_log_min_duration. Failed statements are always logged.
+    pub fn log(&self, record: QueryLogRecord, min_duration: Duration) {
+        if !self.is_enabled() || !should_log(&record, min_duration) {
+            return;
+        }
+
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Unable to serialize the query log record: {}", e);
+                return;
+            }
+        };
+
+        let mut lines = self
+            .lines
+            .lock()
+            .expect("failed to unlock query log for queueing");
+        if lines.len() < MAX_QUEUE_SIZE {
+            lines.push(line);
+        }
+        self.notify.notify_one();
+    }
+
+    fn take(&self) -> Vec<String> {
+        let mut lines = self
+            .lines
+            .lock()
+            .expect("failed to unlock query log for writing");
+
+        mem::take(&mut *lines)
+    }
+}
+
+fn should_log(record: &QueryLogRecord, min_duration: Duration) -> bool {
+    record.error.is_some() || record.duration_ms >= min_duration.as_secs_f64() * 1000.0
+}
+
+/// Destination of the query log
+enum QueryLogSink {
+    Stderr,
+    File(RotatingFile),
+}
+
+impl QueryLogSink {
+    fn write(&mut self, lines: &[String]) -> io::Result<()> {
+        match self {
+            QueryLogSink::Stderr => {
+                let stderr = io::stderr();
+                let mut stderr = stderr.lock();
+                for line in lines {
+                    writeln!(stderr, "{}", line)?;
+                }
+
+                stderr.flush()
+            }
+            QueryLogSink::File(file) => file.write(lines),
+        }
+    }
+}
+
+/// File, which is renamed to `<path>.1` when it reaches `max_size` bytes. Previous files are
+/// shifted, at most `max_files` rotated files are kept.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn new(path: PathBuf, max_size: u64, max_files: usize) -> Self {
+        Self {
+            path,
+            max_size,
+            max_files,
+            file: None,
+            size: 0,
+        }
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(index + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+
+        Ok(())
+    }
+
+    fn write(&mut self, lines: &[String]) -> io::Result<()> {
+        for line in lines {
+            let len = line.len() as u64 + 1;
+            if self.file.is_none() {
+                self.open()?;
+            }
+            if self.size > 0 && self.size + len > self.max_size {
+                self.rotate()?;
+                self.open()?;
+            }
+
+            if let Some(file) = &mut self.file {
+                writeln!(file, "{}", line)?;
+                self.size += len;
+            }
+        }
+
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes records of the query log to stderr or to a file
+pub struct QueryLogWriter {
+    config: QueryLogConfig,
+    close_rx: RwLock<watch::Receiver<bool>>,
+    close_tx: watch::Sender<bool>,
+}
+
+crate::di_service!(QueryLogWriter, []);
+
+impl QueryLogWriter {
+    pub fn new(config: QueryLogConfig) -> Arc<Self> {
+        let (close_tx, close_rx) = watch::channel(false);
+        Arc::new(Self {
+            config,
+            close_rx: RwLock::new(close_rx),
+            close_tx,
+        })
+    }
+
+    fn write(&self, sink: &mut QueryLogSink) {
+        let lines = QUERY_LOG.take();
+        if lines.is_empty() {
+            return;
+        }
+
+        if let Err(e) = sink.write(&lines) {
+            error!("Unable to write {} query log records: {}", lines.len(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl ProcessingLoop for QueryLogWriter {
+    async fn processing_loop(&self) -> Result<(), CubeError> {
+        let mut sink = match &self.config.path {
+            Some(path) => QueryLogSink::File(RotatingFile::new(
+                PathBuf::from(path),
+                self.config.max_size,
+                self.config.max_files,
+            )),
+            None => QueryLogSink::Stderr,
+        };
+        QUERY_LOG.enabled.store(true, Ordering::Relaxed);
+
+        let mut close_rx = self.close_rx.write().await;
+        loop {
+            tokio::select! {
+                res = close_rx.changed() => {
+                    if res.is_err() || *close_rx.borrow() {
+                        QUERY_LOG.enabled.store(false, Ordering::Relaxed);
+                        self.write(&mut sink);
+
+                        return Ok(());
+                    }
+                }
+                _ = QUERY_LOG.notify.notified() => {}
+            }
+
+            self.write(&mut sink);
+        }
+    }
+
+    async fn stop_processing(&self) -> Result<(), CubeError> {
+        self.close_tx.send(true)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_log_record() -> Result<(), CubeError> {
+        let mut record = QueryLogRecord::new(
+            "postgres",
+            1,
+            Some("ovr".to_string()),
+            "SELECT 1".to_string(),
+            Duration::from_millis(50),
+        );
+        assert!(should_log(&record, Duration::from_millis(50)));
+        assert!(!should_log(&record, Duration::from_millis(51)));
+
+        record.error = Some("canceling statement due to user request".to_string());
+        assert!(should_log(&record, Duration::from_secs(1)));
+
+        record.pushdown = Some(Pushdown::DataFusion);
+        let value = serde_json::to_value(&record)?;
+        assert_eq!(value["duration_ms"], serde_json::json!(50.0));
+        assert_eq!(value["pushdown"], serde_json::json!("datafusion"));
+        assert_eq!(value["cube_request_id"], serde_json::Value::Null);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotating_file() -> Result<(), CubeError> {
+        let dir = std::env::temp_dir().join(format!("cubesql-query-log-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("queries.log");
+
+        let mut file = RotatingFile::new(path.clone(), 10, 2);
+        let lines = (0..4).map(|i| format!("line {}", i)).collect::<Vec<_>>();
+        file.write(&lines)?;
+
+        assert_eq!(fs::read_to_string(&path)?, "line 3\n");
+        assert_eq!(fs::read_to_string(file.rotated_path(1))?, "line 2\n");
+        assert_eq!(fs::read_to_string(file.rotated_path(2))?, "line 1\n");
+        assert!(!file.rotated_path(3).exists());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
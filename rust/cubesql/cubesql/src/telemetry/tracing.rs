@@ -0,0 +1,354 @@
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use tokio::sync::{watch, Notify, RwLock};
+
+use crate::{config::processing_loop::ProcessingLoop, CubeError};
+
+lazy_static! {
+    pub static ref TRACER: Tracer = Tracer::new();
+}
+
+/// Finished spans are exported with this interval or when there are `EXPORT_BATCH_SIZE` of them
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+const EXPORT_BATCH_SIZE: usize = 512;
+/// Spans are dropped when the collector is slower than sessions
+const MAX_QUEUE_SIZE: usize = 8192;
+
+/// Identifiers of a span, which are propagated to the Cube API by the W3C `traceparent` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+impl SpanContext {
+    fn root() -> Self {
+        Self {
+            trace_id: rand::random::<u128>().max(1),
+            span_id: rand::random::<u64>().max(1),
+        }
+    }
+
+    fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: rand::random::<u64>().max(1),
+        }
+    }
+
+    /// `x-request-id` of requests to the Cube API, requests of a statement share its trace
+    pub fn request_id(&self) -> String {
+        format!("{:032x}", self.trace_id)
+    }
+
+    /// Value of the `traceparent` header, spans are always sampled
+    pub fn traceparent(&self) -> String {
+        format!("00-{:032x}-{:016x}-01", self.trace_id, self.span_id)
+    }
+}
+
+/// https://opentelemetry.io/docs/reference/specification/trace/api/#spankind
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanKind {
+    Internal = 1,
+    /// Statements received from clients
+    Server = 2,
+    /// Requests to the Cube API
+    Client = 3,
+}
+
+#[derive(Debug)]
+struct FinishedSpan {
+    context: SpanContext,
+    parent_span_id: Option<u64>,
+    name: &'static str,
+    kind: SpanKind,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+    error: Option<String>,
+}
+
+/// Span of work, it's finished and queued for export when it's dropped
+#[derive(Debug)]
+pub struct Span {
+    context: SpanContext,
+    parent_span_id: Option<u64>,
+    name: &'static str,
+    kind: SpanKind,
+    start: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+    error: Option<String>,
+}
+
+impl Span {
+    /// Starts a span, it's the root of a new trace if there is no `parent`
+    pub fn start(name: &'static str, kind: SpanKind, parent: Option<SpanContext>) -> Self {
+        Self {
+            context: match &parent {
+                Some(parent) => parent.child(),
+                None => SpanContext::root(),
+            },
+            parent_span_id: parent.map(|parent| parent.span_id),
+            name,
+            kind,
+            start: SystemTime::now(),
+            attributes: vec![],
+            error: None,
+        }
+    }
+
+    /// Starts an internal span under this span
+    pub fn child(&self, name: &'static str) -> Self {
+        Self::start(name, SpanKind::Internal, Some(self.context))
+    }
+
+    pub fn context(&self) -> SpanContext {
+        self.context
+    }
+
+    pub fn set_attribute(&mut self, key: &'static str, value: impl ToString) {
+        self.attributes.push((key, value.to_string()));
+    }
+
+    /// Marks the span as failed
+    pub fn set_error(&mut self, message: impl ToString) {
+        self.error = Some(message.to_string());
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if !TRACER.is_enabled() {
+            return;
+        }
+
+        TRACER.queue(FinishedSpan {
+            context: self.context,
+            parent_span_id: self.parent_span_id,
+            name: self.name,
+            kind: self.kind,
+            start: self.start,
+            end: SystemTime::now(),
+            attributes: mem::take(&mut self.attributes),
+            error: self.error.take(),
+        });
+    }
+}
+
+/// Queue of finished spans, spans are not collected until `SpanExporter` is started
+pub struct Tracer {
+    enabled: AtomicBool,
+    spans: Mutex<Vec<FinishedSpan>>,
+    notify: Notify,
+}
+
+impl Tracer {
+    fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            spans: Mutex::new(vec![]),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn queue(&self, span: FinishedSpan) {
+        let mut spans = self
+            .spans
+            .lock()
+            .expect("failed to unlock spans for queueing");
+
+        if spans.len() >= MAX_QUEUE_SIZE {
+            return;
+        }
+        spans.push(span);
+
+        if spans.len() >= EXPORT_BATCH_SIZE {
+            self.notify.notify_one();
+        }
+    }
+
+    fn take(&self) -> Vec<FinishedSpan> {
+        let mut spans = self
+            .spans
+            .lock()
+            .expect("failed to unlock spans for exporting");
+
+        mem::take(&mut *spans)
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0)
+        .to_string()
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// Request of the OTLP/HTTP JSON protocol
+fn export_request(spans: &[FinishedSpan]) -> Value {
+    let spans = spans
+        .iter()
+        .map(|span| {
+            let mut value = json!({
+                "traceId": format!("{:032x}", span.context.trace_id),
+                "spanId": format!("{:016x}", span.context.span_id),
+                "name": span.name,
+                "kind": span.kind as u8,
+                "startTimeUnixNano": unix_nanos(span.start),
+                "endTimeUnixNano": unix_nanos(span.end),
+                "attributes": span
+                    .attributes
+                    .iter()
+                    .map(|(key, value)| attribute(key, value))
+                    .collect::<Vec<_>>(),
+            });
+            if let Some(parent_span_id) = span.parent_span_id {
+                value["parentSpanId"] = json!(format!("{:016x}", parent_span_id));
+            }
+            if let Some(error) = &span.error {
+                value["status"] = json!({ "code": 2, "message": error });
+            }
+
+            value
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [attribute("service.name", "cubesql")] },
+            "scopeSpans": [{ "scope": { "name": "cubesql" }, "spans": spans }],
+        }]
+    })
+}
+
+/// Sends spans to an OpenTelemetry collector by OTLP/HTTP
+pub struct SpanExporter {
+    endpoint: String,
+    close_rx: RwLock<watch::Receiver<bool>>,
+    close_tx: watch::Sender<bool>,
+}
+
+crate::di_service!(SpanExporter, []);
+
+impl SpanExporter {
+    /// `endpoint` is the base URL of the collector, e.g. `http://localhost:4318`
+    pub fn new(endpoint: String) -> Arc<Self> {
+        let (close_tx, close_rx) = watch::channel(false);
+        Arc::new(Self {
+            endpoint,
+            close_rx: RwLock::new(close_rx),
+            close_tx,
+        })
+    }
+
+    async fn export(&self, client: &reqwest::Client) {
+        let spans = TRACER.take();
+        if spans.is_empty() {
+            return;
+        }
+
+        let url = format!("{}/v1/traces", self.endpoint.trim_end_matches('/'));
+        let response = client
+            .post(&url)
+            .json(&export_request(&spans))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match response {
+            Ok(_) => debug!("Exported {} spans to {}", spans.len(), url),
+            Err(e) => warn!("Unable to export {} spans to {}: {}", spans.len(), url, e),
+        }
+    }
+}
+
+#[async_trait]
+impl ProcessingLoop for SpanExporter {
+    async fn processing_loop(&self) -> Result<(), CubeError> {
+        let client = reqwest::ClientBuilder::new().use_rustls_tls().build()?;
+        TRACER.enabled.store(true, Ordering::Relaxed);
+
+        let mut close_rx = self.close_rx.write().await;
+        loop {
+            tokio::select! {
+                res = close_rx.changed() => {
+                    if res.is_err() || *close_rx.borrow() {
+                        TRACER.enabled.store(false, Ordering::Relaxed);
+                        // Spans of the last interval are flushed
+                        self.export(&client).await;
+
+                        return Ok(());
+                    }
+                }
+                _ = tokio::time::sleep(EXPORT_INTERVAL) => {}
+                _ = TRACER.notify.notified() => {}
+            }
+
+            self.export(&client).await;
+        }
+    }
+
+    async fn stop_processing(&self) -> Result<(), CubeError> {
+        self.close_tx.send(true)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_request() {
+        let root = Span::start("query", SpanKind::Server, None);
+        let mut child = root.child("parse");
+        child.set_attribute("db.statement", "SELECT 1");
+        child.set_error("Unable to parse");
+
+        assert_eq!(root.context().trace_id, child.context().trace_id);
+        assert_ne!(root.context().span_id, child.context().span_id);
+        assert_eq!(child.context().traceparent().len(), 55);
+
+        let finished = FinishedSpan {
+            context: child.context,
+            parent_span_id: child.parent_span_id,
+            name: child.name,
+            kind: child.kind,
+            start: UNIX_EPOCH + Duration::from_millis(1),
+            end: UNIX_EPOCH + Duration::from_millis(2),
+            attributes: child.attributes.clone(),
+            error: child.error.clone(),
+        };
+        let request = export_request(&[finished]);
+        let span = &request["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(
+            span["parentSpanId"],
+            json!(format!("{:016x}", root.context().span_id))
+        );
+        assert_eq!(span["kind"], json!(1));
+        assert_eq!(span["startTimeUnixNano"], json!("1000000"));
+        assert_eq!(
+            span["attributes"][0],
+            json!({ "key": "db.statement", "value": { "stringValue": "SELECT 1" } })
+        );
+        assert_eq!(span["status"]["code"], json!(2));
+    }
+}
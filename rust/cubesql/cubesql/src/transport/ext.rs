@@ -2,6 +2,44 @@ use cubeclient::models::{V1CubeMeta, V1CubeMetaDimension, V1CubeMetaMeasure, V1C
 
 use crate::sql::ColumnType;
 
+/// Precision and scale of decimals without parameters (`decimal`, `NUMERIC`)
+pub const DEFAULT_DECIMAL_PRECISION: usize = 38;
+pub const DEFAULT_DECIMAL_SCALE: usize = 10;
+const MAX_DECIMAL_PRECISION: usize = 38;
+
+/// Members of the `decimal(p, s)` type (or `numeric`) are NUMERIC, the scale defaults to 0
+/// when only the precision is defined, and both default to 38 and 10 without parameters
+pub fn decimal_column_type(member_type: &str) -> Option<ColumnType> {
+    let member_type = member_type.trim().to_lowercase();
+    let params = member_type
+        .strip_prefix("decimal")
+        .or_else(|| member_type.strip_prefix("numeric"))?
+        .trim();
+    if params.is_empty() {
+        return Some(ColumnType::Decimal(
+            DEFAULT_DECIMAL_PRECISION,
+            DEFAULT_DECIMAL_SCALE,
+        ));
+    }
+
+    let params = params
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .split(',')
+        .map(|param| param.trim().parse::<usize>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let (precision, scale) = match params.as_slice() {
+        [precision] => (*precision, 0),
+        [precision, scale] => (*precision, *scale),
+        _ => return None,
+    };
+    if precision == 0 || precision > MAX_DECIMAL_PRECISION || scale > precision {
+        return None;
+    }
+
+    Some(ColumnType::Decimal(precision, scale))
+}
+
 pub trait V1CubeMetaMeasureExt {
     fn get_real_name(&self) -> String;
 
@@ -35,7 +73,7 @@ impl V1CubeMetaMeasureExt for V1CubeMetaMeasure {
     fn get_sql_type(&self) -> ColumnType {
         let from_type = match &self._type.to_lowercase().as_str() {
             &"number" => ColumnType::Double,
-            _ => ColumnType::String,
+            typ => decimal_column_type(typ).unwrap_or(ColumnType::String),
         };
 
         match &self.agg_type {
@@ -43,6 +81,8 @@ impl V1CubeMetaMeasureExt for V1CubeMetaMeasure {
                 "count" => ColumnType::Int64,
                 "countDistinct" => ColumnType::Int64,
                 "countDistinctApprox" => ColumnType::Int64,
+                // Aggregations of decimals keep the type of the measure
+                _ if matches!(from_type, ColumnType::Decimal(_, _)) => from_type,
                 "sum" => ColumnType::Double,
                 "avg" => ColumnType::Double,
                 "min" => ColumnType::Double,
@@ -98,7 +138,7 @@ impl V1CubeMetaDimensionExt for V1CubeMetaDimension {
             "time" => ColumnType::Timestamp,
             "number" => ColumnType::Double,
             "boolean" => ColumnType::Int8,
-            _ => ColumnType::String,
+            typ => decimal_column_type(typ).unwrap_or(ColumnType::String),
         }
     }
 }
@@ -231,6 +271,7 @@ impl V1CubeMetaExt for V1CubeMeta {
                 "boolean" => MemberType::Boolean,
                 "string" => MemberType::String,
                 "time" => MemberType::Time,
+                x if decimal_column_type(x).is_some() => MemberType::Number,
                 x => panic!("Unexpected dimension type: {}", x),
             });
         }
@@ -3,6 +3,8 @@ use cubeclient::apis::{
     configuration::Configuration as ClientConfiguration, default_api as cube_api,
 };
 use cubeclient::models::{V1LoadRequest, V1LoadRequestQuery, V1LoadResponse};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest_middleware::ClientBuilder;
 
 use std::fmt::Debug;
 use std::sync::Arc;
@@ -10,18 +12,31 @@ use std::time::Duration;
 use tokio::sync::RwLock as RwLockAsync;
 use tokio::time::Instant;
 
-use crate::{compile::MetaContext, sql::AuthContext, CubeError};
+use crate::{
+    compile::MetaContext,
+    sql::AuthContext,
+    telemetry::{
+        metrics::METRICS,
+        tracing::{Span, SpanContext, SpanKind},
+    },
+    CubeError,
+};
 
 #[async_trait]
 pub trait TransportService: Send + Sync + Debug {
-    // Load meta information about cubes
-    async fn meta(&self, ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError>;
+    // Load meta information about cubes, requests are traced under `span`
+    async fn meta(
+        &self,
+        ctx: Arc<AuthContext>,
+        span: Option<SpanContext>,
+    ) -> Result<Arc<MetaContext>, CubeError>;
 
     // Execute load query
     async fn load(
         &self,
         query: V1LoadRequestQuery,
         ctx: Arc<AuthContext>,
+        span: Option<SpanContext>,
     ) -> Result<V1LoadResponse, CubeError>;
 }
 
@@ -38,6 +53,8 @@ pub struct HttpTransport {
     /// because currently we dont persist DF in the SessionState
     /// and it causes a lot of HTTP requests which slow down BI connections
     cache: RwLockAsync<Option<MetaCacheBucket>>,
+    /// Connections (and TLS sessions) to the Cube API are pooled by the client
+    client: reqwest::Client,
 }
 
 const CACHE_LIFETIME_DURATION: Duration = Duration::from_secs(5);
@@ -46,15 +63,37 @@ impl HttpTransport {
     pub fn new() -> Self {
         Self {
             cache: RwLockAsync::new(None),
+            client: reqwest::Client::new(),
         }
     }
 
-    fn get_client_config_for_ctx(&self, ctx: Arc<AuthContext>) -> ClientConfiguration {
-        let mut cube_config = ClientConfiguration::default();
+    /// The context of the request span is sent by the `traceparent` header, so spans of
+    /// the Cube API are stitched to traces of statements. The trace is also the request id
+    /// for logs of the Cube API. Headers are set per request, the client is shared.
+    fn get_client_config_for_ctx(
+        &self,
+        ctx: Arc<AuthContext>,
+        span: &Span,
+    ) -> Result<ClientConfiguration, CubeError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            HeaderValue::from_str(&span.context().traceparent())
+                .map_err(|e| CubeError::internal(e.to_string()))?,
+        );
+        headers.insert(
+            "x-request-id",
+            HeaderValue::from_str(&span.context().request_id())
+                .map_err(|e| CubeError::internal(e.to_string()))?,
+        );
+
+        let mut cube_config =
+            ClientConfiguration::new(ClientBuilder::new(self.client.clone()).build());
+        cube_config.headers = headers;
         cube_config.bearer_access_token = Some(ctx.access_token.clone());
         cube_config.base_path = ctx.base_path.clone();
 
-        cube_config
+        Ok(cube_config)
     }
 }
 
@@ -62,7 +101,11 @@ crate::di_service!(HttpTransport, [TransportService]);
 
 #[async_trait]
 impl TransportService for HttpTransport {
-    async fn meta(&self, ctx: Arc<AuthContext>) -> Result<Arc<MetaContext>, CubeError> {
+    async fn meta(
+        &self,
+        ctx: Arc<AuthContext>,
+        span: Option<SpanContext>,
+    ) -> Result<Arc<MetaContext>, CubeError> {
         {
             let store = self.cache.read().await;
             if let Some(cache_bucket) = &*store {
@@ -72,7 +115,17 @@ impl TransportService for HttpTransport {
             };
         }
 
-        let response = cube_api::meta_v1(&self.get_client_config_for_ctx(ctx)).await?;
+        let mut span = Span::start("cube_api.meta", SpanKind::Client, span);
+        span.set_attribute("http.url", format!("{}/v1/meta", ctx.base_path));
+        let config = self.get_client_config_for_ctx(ctx, &span)?;
+
+        let start = Instant::now();
+        let response = cube_api::meta_v1(&config).await.map_err(CubeError::from);
+        METRICS.cube_api_duration.observe("meta", start.elapsed());
+        if let Err(e) = &response {
+            span.set_error(&e.message);
+        }
+        let response = response?;
 
         let mut store = self.cache.write().await;
         if let Some(cache_bucket) = &*store {
@@ -95,13 +148,25 @@ impl TransportService for HttpTransport {
         &self,
         query: V1LoadRequestQuery,
         ctx: Arc<AuthContext>,
+        span: Option<SpanContext>,
     ) -> Result<V1LoadResponse, CubeError> {
+        let mut span = Span::start("cube_api.load", SpanKind::Client, span);
+        span.set_attribute("http.url", format!("{}/v1/load", ctx.base_path));
+        let config = self.get_client_config_for_ctx(ctx, &span)?;
+
         let request = V1LoadRequest {
             query: Some(query),
             query_type: Some("multi".to_string()),
         };
-        let response =
-            cube_api::load_v1(&self.get_client_config_for_ctx(ctx), Some(request)).await?;
+        let start = Instant::now();
+        let response = cube_api::load_v1(&config, Some(request))
+            .await
+            .map_err(CubeError::from);
+        METRICS.cube_api_duration.observe("load", start.elapsed());
+        if let Err(e) = &response {
+            span.set_error(&e.message);
+        }
+        let response = response?;
 
         Ok(response)
     }